@@ -1,9 +1,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use lopdf::{Document, Object};
+use lopdf::{Document, Object, ObjectId};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use tauri::Manager;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::{Emitter, Manager, State};
 use base64::{Engine as _, engine::general_purpose};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,6 +17,9 @@ struct PdfPage {
     height: f64,
     rotation: i32,
     thumbnail: String,
+    error: Option<String>,
+    // Features the renderer skipped or approximated while drawing the thumbnail
+    warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,164 +29,21398 @@ struct PdfInfo {
     pages: Vec<PdfPage>,
 }
 
+// Maximum number of undo snapshots kept per document
+const MAX_HISTORY: usize = 50;
+
+// Page attributes that may be inherited from ancestor /Pages nodes
+const INHERITABLE_PAGE_KEYS: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+// Catalog entries copied as-is when save_pdf rebuilds the document. The structure tree
+// keeps its alt text; /Pg references to dropped pages become null.
+const CATALOG_KEYS_KEPT_ON_SAVE: [&[u8]; 8] = [
+    b"ViewerPreferences",
+    b"PageMode",
+    b"PageLayout",
+    b"Lang",
+    b"MarkInfo",
+    b"StructTreeRoot",
+    b"OutputIntents",
+    b"Metadata",
+];
+
+// Error returned by edits to a document locked with set_readonly
+const READ_ONLY: &str = "ReadOnly";
+
+// Rough per-object overhead used when estimating memory usage
+const OBJECT_OVERHEAD_BYTES: u64 = 64;
+
+struct OpenDocument {
+    doc: Document,
+    undo_stack: Vec<Document>,
+    redo_stack: Vec<Document>,
+    // Generated thumbnails by page number, invalidated whenever the document changes
+    thumbnails: HashMap<usize, PageThumbnail>,
+    // Rejects every edit (including undo/redo) until cleared
+    readonly: bool,
+    // Changes whenever the thumbnails are invalidated, so work done on a snapshot can
+    // tell if it is stale
+    revision: u64,
+}
+
+// Revisions are unique across documents, so a reopened or reverted document never
+// matches a snapshot taken before
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+// Numbers temp files, so concurrent saves to one destination never share a temp file
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Clone)]
+struct PageThumbnail {
+    data: String,
+    error: Option<String>,
+    warnings: Vec<String>,
+}
+
+impl OpenDocument {
+    fn new(doc: Document) -> Self {
+        OpenDocument {
+            doc,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            thumbnails: HashMap::new(),
+            readonly: false,
+            revision: next_revision(),
+        }
+    }
+
+    // Record that `doc`, or a setting its thumbnails depend on, changed
+    fn invalidate(&mut self) {
+        self.thumbnails.clear();
+        self.revision = next_revision();
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheStats {
+    open_documents: usize,
+    estimated_bytes: u64,
+    thumbnail_cache_bytes: u64,
+}
+
+// Fallback page size (A4) for pages without a resolvable MediaBox
+const DEFAULT_PAGE_SIZE: (f64, f64) = (595.0, 842.0);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum PageSizePreset {
+    A4,
+    A5,
+    Letter,
+    Legal,
+}
+
+impl PageSizePreset {
+    fn dimensions(self) -> (f64, f64) {
+        match self {
+            PageSizePreset::A4 => (595.0, 842.0),
+            PageSizePreset::A5 => (420.0, 595.0),
+            PageSizePreset::Letter => (612.0, 792.0),
+            PageSizePreset::Legal => (612.0, 1008.0),
+        }
+    }
+}
+
+struct Settings {
+    render_timeout_ms: u64,
+    default_page_size: (f64, f64),
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            render_timeout_ms: DEFAULT_RENDER_TIMEOUT_MS,
+            default_page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+// Documents opened by the UI, keyed by their path on disk
+#[derive(Default)]
+struct AppState {
+    documents: Mutex<HashMap<String, OpenDocument>>,
+    settings: Mutex<Settings>,
+    jobs: Mutex<Jobs>,
+}
+
+fn open_document<'a>(
+    documents: &'a mut HashMap<String, OpenDocument>,
+    path: &str,
+) -> Result<&'a mut OpenDocument, String> {
+    match documents.entry(path.to_string()) {
+        Entry::Occupied(entry) => Ok(entry.into_mut()),
+        Entry::Vacant(entry) => {
+            let doc = Document::load(path).map_err(|e| e.to_string())?;
+            Ok(entry.insert(OpenDocument::new(doc)))
+        }
+    }
+}
+
+// Run a read-only operation against the cached document, loading it on first use
+fn with_document<T>(
+    state: &AppState,
+    path: &str,
+    f: impl FnOnce(&Document) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let open = open_document(&mut documents, path)?;
+    f(&open.doc)
+}
+
+// Run a mutating operation against the cached document as a single undoable step.
+// If the operation fails the document is restored to its previous state.
+fn edit_document<T>(
+    state: &AppState,
+    path: &str,
+    f: impl FnOnce(&mut Document) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let open = open_document(&mut documents, path)?;
+    if open.readonly {
+        return Err(READ_ONLY.to_string());
+    }
+    let snapshot = open.doc.clone();
+
+    match f(&mut open.doc) {
+        Ok(value) => {
+            open.undo_stack.push(snapshot);
+            if open.undo_stack.len() > MAX_HISTORY {
+                open.undo_stack.remove(0);
+            }
+            open.redo_stack.clear();
+            open.invalidate();
+            Ok(value)
+        }
+        Err(e) => {
+            open.doc = snapshot;
+            Err(e)
+        }
+    }
+}
+
+fn page_id(doc: &Document, page_num: usize) -> Result<ObjectId, String> {
+    doc.get_pages()
+        .get(&(page_num as u32))
+        .copied()
+        .ok_or_else(|| format!("Page {} not found", page_num))
+}
+
+// Copy attributes inherited from ancestor /Pages nodes onto the page itself,
+// so the page keeps its appearance when it is moved to a different parent
+fn materialize_inherited_attributes(doc: &mut Document, page_id: ObjectId) -> Result<(), String> {
+    let mut inherited = Vec::new();
+    {
+        let page = doc.get_dictionary(page_id).map_err(|e| e.to_string())?;
+        let mut missing: Vec<&[u8]> = INHERITABLE_PAGE_KEYS
+            .iter()
+            .copied()
+            .filter(|key| !page.has(key))
+            .collect();
+        let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+        let mut depth = 0;
+
+        while let Some(parent_id) = parent {
+            if missing.is_empty() || depth > 64 {
+                break;
+            }
+            let node = doc.get_dictionary(parent_id).map_err(|e| e.to_string())?;
+            missing.retain(|key| match node.get(key) {
+                Ok(value) => {
+                    inherited.push((key.to_vec(), value.clone()));
+                    false
+                }
+                Err(_) => true,
+            });
+            parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+            depth += 1;
+        }
+    }
+
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    for (key, value) in inherited {
+        page.set(key, value);
+    }
+    Ok(())
+}
+
+// Replace the page tree with a flat list of the given pages under the root /Pages node
+fn apply_page_order(doc: &mut Document, order: &[ObjectId]) -> Result<(), String> {
+    let pages_id = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .map_err(|e| e.to_string())?;
+
+    for &page_id in order {
+        materialize_inherited_attributes(doc, page_id)?;
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        page.set("Parent", Object::Reference(pages_id));
+    }
+
+    let pages = doc.get_dictionary_mut(pages_id).map_err(|e| e.to_string())?;
+    pages.set(
+        "Kids",
+        Object::Array(order.iter().map(|&id| Object::Reference(id)).collect()),
+    );
+    pages.set("Count", Object::Integer(order.len() as i64));
+    Ok(())
+}
+
 #[tauri::command]
-async fn load_pdf(path: String) -> Result<PdfInfo, String> {
-    let doc = Document::load(&path).map_err(|e| e.to_string())?;
+async fn load_pdf(state: State<'_, AppState>, path: String) -> Result<PdfInfo, String> {
+    let (timeout_ms, default_size) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.render_timeout_ms, settings.default_page_size)
+    };
+
+    // Render from a snapshot so other commands are not blocked while the pages render
+    let (doc, mut thumbnails, revision) = {
+        let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+        let open = open_document(&mut documents, &path)?;
+        (open.doc.clone(), open.thumbnails.clone(), open.revision)
+    };
+    let info = build_pdf_info(&doc, &path, &mut thumbnails, timeout_ms, default_size)?;
+
+    // Thumbnails of a document edited in the meantime are already out of date
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    if let Some(open) = documents.get_mut(&path).filter(|open| open.revision == revision) {
+        open.thumbnails.extend(thumbnails);
+    }
+    Ok(info)
+}
+
+#[tauri::command]
+async fn get_page_thumbnail(state: State<'_, AppState>, path: String, page_num: usize) -> Result<PdfPage, String> {
+    let (timeout_ms, default_size) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.render_timeout_ms, settings.default_page_size)
+    };
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let open = open_document(&mut documents, &path)?;
+    let doc = &open.doc;
+    let page_id = page_id(doc, page_num)?;
+    let (width, height) = get_page_dimensions(doc, page_num, default_size)?;
+    let thumbnail = open
+        .thumbnails
+        .entry(page_num)
+        .or_insert_with(|| render_thumbnail(doc, page_num, timeout_ms, default_size))
+        .clone();
+
+    Ok(PdfPage {
+        page_number: page_num,
+        width,
+        height,
+        rotation: page_rotation(doc, page_id),
+        thumbnail: thumbnail.data,
+        error: thumbnail.error,
+        warnings: thumbnail.warnings,
+    })
+}
+
+// Set the size used for pages that have no resolvable MediaBox, either from a named
+// preset or from explicit dimensions in points
+#[tauri::command]
+async fn set_default_page_size(
+    state: State<'_, AppState>,
+    width: Option<f64>,
+    height: Option<f64>,
+    preset: Option<PageSizePreset>,
+) -> Result<(), String> {
+    let size = match (preset, width, height) {
+        (Some(preset), _, _) => preset.dimensions(),
+        (None, Some(width), Some(height)) if width > 0.0 && height > 0.0 => (width, height),
+        _ => return Err("Provide a preset or a positive width and height".to_string()),
+    };
+    state.settings.lock().map_err(|e| e.to_string())?.default_page_size = size;
+
+    // Thumbnails of pages without a MediaBox were drawn at the old size
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    for open in documents.values_mut() {
+        open.invalidate();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_render_timeout(state: State<'_, AppState>, timeout_ms: u64) -> Result<(), String> {
+    let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.render_timeout_ms = timeout_ms;
+    Ok(())
+}
+
+fn build_pdf_info(
+    doc: &Document,
+    path: &str,
+    thumbnails: &mut HashMap<usize, PageThumbnail>,
+    timeout_ms: u64,
+    default_size: (f64, f64),
+) -> Result<PdfInfo, String> {
     let page_count = doc.get_pages().len();
     let mut pages = Vec::new();
 
-    for (i, _page_id) in doc.get_pages().iter().enumerate() {
+    for (i, (_, &page_id)) in doc.get_pages().iter().enumerate() {
         let page_number = i + 1;
         
         // Get page dimensions
-        let (width, height) = get_page_dimensions(&doc, page_number)?;
+        let (width, height) = get_page_dimensions(doc, page_number, default_size)?;
         
-        // Generate thumbnail (simplified - just placeholder for now)
-        let thumbnail = generate_thumbnail_placeholder(page_number);
+        // Render the thumbnail, falling back to the placeholder if it fails or times out
+        let thumbnail = thumbnails
+            .entry(page_number)
+            .or_insert_with(|| render_thumbnail(doc, page_number, timeout_ms, default_size))
+            .clone();
         
         pages.push(PdfPage {
             page_number,
             width,
             height,
-            rotation: 0,
-            thumbnail,
+            rotation: page_rotation(doc, page_id),
+            thumbnail: thumbnail.data,
+            error: thumbnail.error,
+            warnings: thumbnail.warnings,
         });
     }
 
     Ok(PdfInfo {
-        path,
+        path: path.to_string(),
         page_count,
         pages,
     })
 }
 
-fn get_page_dimensions(doc: &Document, page_num: usize) -> Result<(f64, f64), String> {
+// Physical page size in points: the visible box scaled by the page's /UserUnit
+fn get_page_dimensions(doc: &Document, page_num: usize, default_size: (f64, f64)) -> Result<(f64, f64), String> {
+    let unit = page_user_unit(doc, page_id(doc, page_num)?);
+    let (width, height) = page_size_in_units(doc, page_num, default_size)?;
+    Ok((width * unit, height * unit))
+}
+
+// Page size in the page's own user space units, as new content for it is measured
+fn page_size_in_units(doc: &Document, page_num: usize, default_size: (f64, f64)) -> Result<(f64, f64), String> {
     let pages = doc.get_pages();
     let page_id = pages.get(&(page_num as u32)).ok_or("Page not found")?;
-    let page = doc.get_object(*page_id).map_err(|e| e.to_string())?;
-    
-    if let Object::Dictionary(dict) = page {
-        if let Ok(Object::Array(media_box)) = dict.get(b"MediaBox") {
-            if media_box.len() >= 4 {
-                let width = media_box[2].as_i64().unwrap_or(595) as f64 - media_box[0].as_i64().unwrap_or(0) as f64;
-                let height = media_box[3].as_i64().unwrap_or(842) as f64 - media_box[1].as_i64().unwrap_or(0) as f64;
-                return Ok((width, height));
-            }
-        }
+
+    match page_visible_box(doc, *page_id) {
+        Some([x0, y0, x1, y1]) => Ok((x1 - x0, y1 - y0)),
+        None => Ok(default_size),
     }
-    
-    Ok((595.0, 842.0)) // Default A4 size
 }
 
-fn generate_thumbnail_placeholder(page_num: usize) -> String {
-    // Simple placeholder - in production, you'd generate actual thumbnails
+// Size of one user space unit in points (/UserUnit, PDF 1.6); 1 when absent. It is not
+// inheritable.
+fn page_user_unit(doc: &Document, page_id: ObjectId) -> f64 {
+    doc.get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"UserUnit").and_then(Object::as_float).ok())
+        .map(|unit| unit as f64)
+        .filter(|&unit| unit > 0.0)
+        .unwrap_or(1.0)
+}
+
+// Placeholder shaped like the page as displayed: width and height are the unrotated
+// page size, and a quarter-turn rotation swaps them
+fn generate_thumbnail_placeholder(
+    page_num: usize,
+    width: f64,
+    height: f64,
+    rotation: i32,
+    default_size: (f64, f64),
+) -> String {
+    let (width, height) = if rotation == 90 || rotation == 270 { (height, width) } else { (width, height) };
+    let (width, height) = if width > 0.0 && height > 0.0 { (width, height) } else { default_size };
+
+    // Longest side is 141 units, matching the original portrait placeholder
+    let scale = 141.0 / width.max(height);
+    let (w, h) = ((width * scale).round().max(1.0), (height * scale).round().max(1.0));
     let svg_content = format!(
-        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"141\" viewBox=\"0 0 100 141\">\
-         <rect width=\"100\" height=\"141\" fill=\"#f0f0f0\" stroke=\"#cccccc\"/>\
-         <text x=\"50\" y=\"70\" text-anchor=\"middle\" font-family=\"Arial\" font-size=\"24\" fill=\"#666666\">{}</text>\
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"#f0f0f0\" stroke=\"#cccccc\"/>\
+         <text x=\"{x}\" y=\"{y}\" text-anchor=\"middle\" dominant-baseline=\"middle\" font-family=\"Arial\" font-size=\"24\" fill=\"#666666\">{page_num}</text>\
          </svg>",
-        page_num
+        x = w / 2.0,
+        y = h / 2.0,
     );
     format!("data:image/svg+xml;base64,{}", general_purpose::STANDARD.encode(svg_content))
 }
 
-#[tauri::command]
-async fn save_pdf(
-    path: String,
-    output_path: String,
-    page_order: Vec<usize>,
-    rotations: BTreeMap<usize, i32>,
-    deleted_pages: Vec<usize>,
-) -> Result<(), String> {
-    let doc = Document::load(&path).map_err(|e| e.to_string())?;
-    let mut new_doc = Document::with_version("1.5");
-    
-    // Copy metadata
-    if let Ok(info) = doc.trailer.get(b"Info") {
-        new_doc.trailer.set("Info", info.clone());
+// Default time budget for rendering a single page, in milliseconds
+const DEFAULT_RENDER_TIMEOUT_MS: u64 = 5000;
+
+// Longest edge of generated thumbnails, in pixels
+const THUMBNAIL_SIZE: f64 = 200.0;
+
+const RENDER_TIMEOUT: &str = "render_timeout";
+
+// Look up an inheritable page entry (e.g. /MediaBox or /Rotate), following /Parent links
+fn inherited_page_entry<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..64 {
+        if let Ok(value) = node.get(key) {
+            return resolve_object(doc, value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
     }
-    
-    // Process pages in the specified order
-    for &page_num in &page_order {
-        if deleted_pages.contains(&page_num) {
-            continue;
+    None
+}
+
+fn rect_from_object(doc: &Document, object: &Object) -> Option<[f64; 4]> {
+    let values: Vec<f64> = object
+        .as_array()
+        .ok()?
+        .iter()
+        .filter_map(|value| resolve_object(doc, value))
+        .filter_map(|value| value.as_float().ok())
+        .map(|value| value as f64)
+        .collect();
+    if values.len() < 4 {
+        return None;
+    }
+    // Normalize so the rectangle is [llx lly urx ury]
+    Some([
+        values[0].min(values[2]),
+        values[1].min(values[3]),
+        values[0].max(values[2]),
+        values[1].max(values[3]),
+    ])
+}
+
+// The page's visible area: its /CropBox, falling back to the /MediaBox
+fn page_visible_box(doc: &Document, page_id: ObjectId) -> Option<[f64; 4]> {
+    inherited_page_entry(doc, page_id, b"CropBox")
+        .and_then(|object| rect_from_object(doc, object))
+        .or_else(|| inherited_page_entry(doc, page_id, b"MediaBox").and_then(|object| rect_from_object(doc, object)))
+}
+
+// The visible box, or the configured default page size at the origin when there is none
+fn page_box_or_default(doc: &Document, page_id: ObjectId, default_size: (f64, f64)) -> [f64; 4] {
+    page_visible_box(doc, page_id).unwrap_or([0.0, 0.0, default_size.0, default_size.1])
+}
+
+fn normalize_rotation(degrees: i64) -> i32 {
+    degrees.rem_euclid(360) as i32 / 90 * 90
+}
+
+fn page_rotation(doc: &Document, page_id: ObjectId) -> i32 {
+    inherited_page_entry(doc, page_id, b"Rotate")
+        .and_then(|rotate| rotate.as_i64().ok())
+        .map_or(0, normalize_rotation)
+}
+
+#[derive(Clone)]
+struct PaintState {
+    ctm: Matrix,
+    fill: [u8; 3],
+    stroke: [u8; 3],
+    fill_components: usize,
+    stroke_components: usize,
+    fill_alpha: f64,
+    stroke_alpha: f64,
+    line_width: f64,
+    text: TextState,
+    render_mode: i64,
+}
+
+impl PaintState {
+    fn new(ctm: Matrix) -> Self {
+        PaintState {
+            ctm,
+            fill: [0, 0, 0],
+            stroke: [0, 0, 0],
+            fill_components: 1,
+            stroke_components: 1,
+            fill_alpha: 1.0,
+            stroke_alpha: 1.0,
+            line_width: 1.0,
+            text: TextState {
+                ctm,
+                char_spacing: 0.0,
+                word_spacing: 0.0,
+                horizontal_scale: 1.0,
+                leading: 0.0,
+                rise: 0.0,
+                font: Vec::new(),
+                font_size: 0.0,
+            },
+            render_mode: 0,
         }
-        
-        let pages = doc.get_pages();
-        if let Some(&page_id) = pages.get(&(page_num as u32)) {
-            // Clone the page
-            let page = doc.get_object(page_id).map_err(|e| e.to_string())?;
-            let mut page_dict = if let Object::Dictionary(dict) = page {
-                dict.clone()
-            } else {
-                continue;
+    }
+}
+
+struct Rasterizer<'a> {
+    doc: &'a Document,
+    image: image::RgbImage,
+    deadline: Option<std::time::Instant>,
+    images: HashMap<ObjectId, Option<image::RgbImage>>,
+    // Distinct messages about content that was skipped or approximated
+    warnings: Vec<String>,
+}
+
+fn color_from_components(values: &[f64]) -> [u8; 3] {
+    let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match values.len() {
+        1 => [channel(values[0]); 3],
+        3 => [channel(values[0]), channel(values[1]), channel(values[2])],
+        4 => cmyk_to_rgb(channel(values[0]), channel(values[1]), channel(values[2]), channel(values[3])),
+        _ => [0, 0, 0],
+    }
+}
+
+fn color_space_components(doc: &Document, resources: Option<&lopdf::Dictionary>, space: &Object) -> usize {
+    let direct = resolve_color_space(doc, space).components();
+    if direct > 0 {
+        return direct;
+    }
+    // Named color spaces are looked up in the resource dictionary
+    let name = match space.as_name() {
+        Ok(name) => name,
+        Err(_) => return 0,
+    };
+    resources
+        .and_then(|r| resource_category(doc, r, b"ColorSpace"))
+        .and_then(|spaces| spaces.get(name).ok())
+        .map_or(0, |space| resolve_color_space(doc, space).components())
+}
+
+fn blend_pixel(image: &mut image::RgbImage, x: u32, y: u32, color: [u8; 3], alpha: f64) {
+    let pixel = image.get_pixel_mut(x, y);
+    if alpha >= 1.0 {
+        *pixel = image::Rgb(color);
+        return;
+    }
+    for (existing, &target) in pixel.0.iter_mut().zip(color.iter()) {
+        *existing = (*existing as f64 + (target as f64 - *existing as f64) * alpha).round() as u8;
+    }
+}
+
+impl<'a> Rasterizer<'a> {
+    fn warn(&mut self, message: String) {
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    fn check_deadline(&self) -> Result<(), String> {
+        match self.deadline {
+            Some(deadline) if std::time::Instant::now() > deadline => Err(RENDER_TIMEOUT.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    // Scanline polygon fill of device-space subpaths
+    fn fill_polygons(&mut self, polygons: &[Vec<(f64, f64)>], color: [u8; 3], alpha: f64, even_odd: bool) -> Result<(), String> {
+        let (width, height) = (self.image.width() as f64, self.image.height() as f64);
+        let points = polygons.iter().flatten();
+        let min_y = points.clone().map(|p| p.1).fold(f64::INFINITY, f64::min).max(0.0);
+        let max_y = points.map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).min(height);
+        if min_y >= max_y || alpha <= 0.0 {
+            return Ok(());
+        }
+
+        let mut row = min_y.floor() as u32;
+        while (row as f64) < max_y {
+            if row.is_multiple_of(64) {
+                self.check_deadline()?;
+            }
+            let scan_y = row as f64 + 0.5;
+            let mut crossings: Vec<(f64, i32)> = Vec::new();
+            for polygon in polygons {
+                for i in 0..polygon.len() {
+                    let (x0, y0) = polygon[i];
+                    let (x1, y1) = polygon[(i + 1) % polygon.len()];
+                    if (y0 <= scan_y && y1 > scan_y) || (y1 <= scan_y && y0 > scan_y) {
+                        let x = x0 + (scan_y - y0) / (y1 - y0) * (x1 - x0);
+                        crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                    }
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                let inside = if even_odd { winding % 2 != 0 } else { winding != 0 };
+                if inside {
+                    let start = pair[0].0.round().max(0.0) as u32;
+                    let end = pair[1].0.round().min(width) as u32;
+                    for x in start..end {
+                        blend_pixel(&mut self.image, x, row, color, alpha);
+                    }
+                }
+            }
+            row += 1;
+        }
+        Ok(())
+    }
+
+    fn stroke_polylines(&mut self, polylines: &[Vec<(f64, f64)>], closed: &[bool], width: f64, color: [u8; 3], alpha: f64) -> Result<(), String> {
+        let half = (width / 2.0).max(0.5);
+        for (polyline, &is_closed) in polylines.iter().zip(closed) {
+            let mut segments: Vec<((f64, f64), (f64, f64))> = polyline.windows(2).map(|w| (w[0], w[1])).collect();
+            if is_closed && polyline.len() > 2 {
+                segments.push((polyline[polyline.len() - 1], polyline[0]));
+            }
+            for ((x0, y0), (x1, y1)) in segments {
+                let length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+                let (nx, ny) = if length > 0.0 {
+                    (-(y1 - y0) / length * half, (x1 - x0) / length * half)
+                } else {
+                    (half, 0.0)
+                };
+                let quad = vec![(x0 + nx, y0 + ny), (x1 + nx, y1 + ny), (x1 - nx, y1 - ny), (x0 - nx, y0 - ny)];
+                self.fill_polygons(&[quad], color, alpha, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_image(&mut self, id: ObjectId, device: &Matrix, fill: [u8; 3], alpha: f64) -> Result<(), String> {
+        let doc = self.doc;
+        if !self.images.contains_key(&id) {
+            let decoded = match doc.get_object(id) {
+                Ok(Object::Stream(stream)) => decode_image_xobject(doc, stream)
+                    .map_err(|e| self.warn(format!("image {} {} not drawn: {}", id.0, id.1, e)))
+                    .ok(),
+                _ => None,
             };
-            
-            // Apply rotation if needed
-            if let Some(&rotation) = rotations.get(&page_num) {
-                if rotation != 0 {
-                    page_dict.set("Rotate", Object::Integer(rotation as i64));
+            self.images.insert(id, decoded);
+        }
+        let is_mask = matches!(doc.get_object(id), Ok(Object::Stream(s)) if s.dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false));
+        let source = match self.images.get(&id) {
+            Some(Some(source)) => source,
+            _ => return Ok(()),
+        };
+
+        let det = device[0] * device[3] - device[1] * device[2];
+        if det.abs() < 1e-9 {
+            return Ok(());
+        }
+        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(u, v)| transform_point(device, u, v));
+        let (width, height) = (self.image.width() as f64, self.image.height() as f64);
+        let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min).max(0.0).floor() as u32;
+        let max_x = corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max).min(width).ceil() as u32;
+        let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min).max(0.0).floor() as u32;
+        let max_y = corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max).min(height).ceil() as u32;
+
+        for y in min_y..max_y {
+            if y % 64 == 0 {
+                self.check_deadline()?;
+            }
+            for x in min_x..max_x {
+                // Map the pixel centre back into the unit image square
+                let (px, py) = (x as f64 + 0.5 - device[4], y as f64 + 0.5 - device[5]);
+                let u = (px * device[3] - py * device[2]) / det;
+                let v = (py * device[0] - px * device[1]) / det;
+                if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                    continue;
+                }
+                let sx = ((u * source.width() as f64) as u32).min(source.width().saturating_sub(1));
+                let sy = (((1.0 - v) * source.height() as f64) as u32).min(source.height().saturating_sub(1));
+                let sample = source.get_pixel(sx, sy).0;
+                if is_mask {
+                    if sample[0] > 127 {
+                        blend_pixel(&mut self.image, x, y, fill, alpha);
+                    }
+                } else {
+                    blend_pixel(&mut self.image, x, y, sample, alpha);
                 }
             }
-            
-            // Add page to new document
-            let new_page_id = new_doc.new_object_id();
-            new_doc.objects.insert(new_page_id, Object::Dictionary(page_dict));
-            
-            // Update page tree - simplified for now
-            // In production, you'd properly build the page tree structure
         }
+        Ok(())
+    }
+
+    fn run(&mut self, content: &[u8], resources: Option<&lopdf::Dictionary>, base: Matrix, depth: usize) -> Result<(), String> {
+        if depth > 8 {
+            self.warn("form XObjects nested too deeply".to_string());
+            return Ok(());
+        }
+        let doc = self.doc;
+        let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+        let fonts = load_fonts(doc, resources);
+        let mut state = PaintState::new(base);
+        let mut stack: Vec<PaintState> = Vec::new();
+        let mut subpaths: Vec<Vec<(f64, f64)>> = Vec::new();
+        let mut closed: Vec<bool> = Vec::new();
+        let mut current = (0.0, 0.0);
+        let mut tm = IDENTITY;
+        let mut tlm = IDENTITY;
+
+        for (index, operation) in content.operations.iter().enumerate() {
+            if index % 256 == 0 {
+                self.check_deadline()?;
+            }
+            let n = operand_numbers(&operation.operands);
+            let op = operation.operator.as_str();
+            match op {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(previous) = stack.pop() {
+                        state = previous;
+                    }
+                }
+                "cm" if n.len() == 6 => state.ctm = multiply(&[n[0], n[1], n[2], n[3], n[4], n[5]], &state.ctm),
+                "w" => state.line_width = n.first().copied().unwrap_or(1.0),
+                "gs" => {
+                    let ext = operation
+                        .operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| {
+                            resources
+                                .and_then(|r| resource_category(doc, r, b"ExtGState"))
+                                .and_then(|states| states.get(name).ok())
+                        })
+                        .and_then(|ext| resolve_object(doc, ext))
+                        .and_then(|ext| ext.as_dict().ok());
+                    if let Some(ext) = ext {
+                        if let Ok(alpha) = ext.get(b"ca").and_then(Object::as_float) {
+                            state.fill_alpha = alpha as f64;
+                        }
+                        if let Ok(alpha) = ext.get(b"CA").and_then(Object::as_float) {
+                            state.stroke_alpha = alpha as f64;
+                        }
+                        if let Ok(width) = ext.get(b"LW").and_then(Object::as_float) {
+                            state.line_width = width as f64;
+                        }
+                        if ext.get(b"SMask").and_then(Object::as_name).map_or(ext.has(b"SMask"), |name| name != b"None") {
+                            self.warn("unsupported soft mask".to_string());
+                        }
+                        if ext.get(b"BM").and_then(Object::as_name).is_ok_and(|mode| mode != b"Normal" && mode != b"Compatible") {
+                            self.warn("unsupported blend mode".to_string());
+                        }
+                    }
+                }
+                "g" | "rg" | "k" => {
+                    state.fill = color_from_components(&n);
+                    state.fill_components = n.len();
+                }
+                "G" | "RG" | "K" => {
+                    state.stroke = color_from_components(&n);
+                    state.stroke_components = n.len();
+                }
+                "cs" | "CS" => {
+                    let components = operation
+                        .operands
+                        .first()
+                        .map_or(0, |space| color_space_components(doc, resources, space));
+                    if op == "cs" {
+                        state.fill_components = components;
+                        state.fill = [0, 0, 0];
+                    } else {
+                        state.stroke_components = components;
+                        state.stroke = [0, 0, 0];
+                    }
+                }
+                "sc" | "scn" => {
+                    if n.len() == state.fill_components {
+                        state.fill = color_from_components(&n);
+                    } else {
+                        // Patterns and special color spaces are approximated with mid gray
+                        state.fill = [128, 128, 128];
+                        self.warn("approximated pattern or special color space".to_string());
+                    }
+                }
+                "SC" | "SCN" => {
+                    if n.len() == state.stroke_components {
+                        state.stroke = color_from_components(&n);
+                    } else {
+                        state.stroke = [128, 128, 128];
+                        self.warn("approximated pattern or special color space".to_string());
+                    }
+                }
+                "m" if n.len() == 2 => {
+                    current = transform_point(&state.ctm, n[0], n[1]);
+                    subpaths.push(vec![current]);
+                    closed.push(false);
+                }
+                "l" if n.len() == 2 => {
+                    current = transform_point(&state.ctm, n[0], n[1]);
+                    match subpaths.last_mut() {
+                        Some(path) => path.push(current),
+                        None => {
+                            subpaths.push(vec![current]);
+                            closed.push(false);
+                        }
+                    }
+                }
+                "c" | "v" | "y" => {
+                    let (p1, p2, p3) = match (op, n.len()) {
+                        ("c", 6) => ((n[0], n[1]), (n[2], n[3]), (n[4], n[5])),
+                        ("v", 4) => ((f64::NAN, f64::NAN), (n[0], n[1]), (n[2], n[3])),
+                        ("y", 4) => ((n[0], n[1]), (n[2], n[3]), (n[2], n[3])),
+                        _ => continue,
+                    };
+                    let start = current;
+                    let c1 = if p1.0.is_nan() { start } else { transform_point(&state.ctm, p1.0, p1.1) };
+                    let c2 = transform_point(&state.ctm, p2.0, p2.1);
+                    let end = transform_point(&state.ctm, p3.0, p3.1);
+                    if subpaths.is_empty() {
+                        subpaths.push(vec![start]);
+                        closed.push(false);
+                    }
+                    let path = subpaths.last_mut().unwrap();
+                    for step in 1..=12 {
+                        let t = step as f64 / 12.0;
+                        let mt = 1.0 - t;
+                        let x = mt * mt * mt * start.0 + 3.0 * mt * mt * t * c1.0 + 3.0 * mt * t * t * c2.0 + t * t * t * end.0;
+                        let y = mt * mt * mt * start.1 + 3.0 * mt * mt * t * c1.1 + 3.0 * mt * t * t * c2.1 + t * t * t * end.1;
+                        path.push((x, y));
+                    }
+                    current = end;
+                }
+                "re" if n.len() == 4 => {
+                    let corners = [(n[0], n[1]), (n[0] + n[2], n[1]), (n[0] + n[2], n[1] + n[3]), (n[0], n[1] + n[3])];
+                    subpaths.push(corners.iter().map(|&(x, y)| transform_point(&state.ctm, x, y)).collect());
+                    closed.push(true);
+                    current = transform_point(&state.ctm, n[0], n[1]);
+                }
+                "h" => {
+                    if let Some(last) = closed.last_mut() {
+                        *last = true;
+                    }
+                }
+                "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "S" | "s" | "n" => {
+                    let fill = matches!(op, "f" | "F" | "f*" | "B" | "B*" | "b" | "b*");
+                    let stroke = matches!(op, "B" | "B*" | "b" | "b*" | "S" | "s");
+                    if matches!(op, "b" | "b*" | "s") {
+                        if let Some(last) = closed.last_mut() {
+                            *last = true;
+                        }
+                    }
+                    if fill {
+                        let even_odd = op.ends_with('*');
+                        self.fill_polygons(&subpaths, state.fill, state.fill_alpha, even_odd)?;
+                    }
+                    if stroke {
+                        let scale = (state.ctm[0] * state.ctm[3] - state.ctm[1] * state.ctm[2]).abs().sqrt();
+                        self.stroke_polylines(&subpaths, &closed, state.line_width * scale, state.stroke, state.stroke_alpha)?;
+                    }
+                    subpaths.clear();
+                    closed.clear();
+                }
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    if let Some(name) = operation.operands.first().and_then(|name| name.as_name().ok()) {
+                        state.text.font = name.to_vec();
+                        match fonts.get(name) {
+                            Some(font) if !font.embedded => self.warn(format!("substituted font {}", font.base_font)),
+                            Some(_) => {}
+                            None => self.warn(format!("missing font resource /{}", String::from_utf8_lossy(name))),
+                        }
+                    }
+                    state.text.font_size = n.first().copied().unwrap_or(0.0);
+                }
+                "Tc" => state.text.char_spacing = n.first().copied().unwrap_or(0.0),
+                "Tw" => state.text.word_spacing = n.first().copied().unwrap_or(0.0),
+                "Tz" => state.text.horizontal_scale = n.first().copied().unwrap_or(100.0) / 100.0,
+                "TL" => state.text.leading = n.first().copied().unwrap_or(0.0),
+                "Ts" => state.text.rise = n.first().copied().unwrap_or(0.0),
+                "Tr" => state.render_mode = n.first().copied().unwrap_or(0.0) as i64,
+                "Td" | "TD" if n.len() == 2 => {
+                    if op == "TD" {
+                        state.text.leading = -n[1];
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, n[0], n[1]], &tlm);
+                    tm = tlm;
+                }
+                "Tm" if n.len() == 6 => {
+                    tlm = [n[0], n[1], n[2], n[3], n[4], n[5]];
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.text.leading], &tlm);
+                    tm = tlm;
+                }
+                "Tj" | "'" | "\"" | "TJ" => {
+                    if op == "'" || op == "\"" {
+                        tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.text.leading], &tlm);
+                        tm = tlm;
+                    }
+                    let font = match fonts.get(&state.text.font) {
+                        Some(font) => font,
+                        None => continue,
+                    };
+                    let size = state.text.font_size;
+                    let scale = state.text.horizontal_scale;
+                    let mut glyphs: Vec<(f64, f64)> = Vec::new();
+                    let mut advance = 0.0;
+                    let mut show = |bytes: &[u8], advance: &mut f64| {
+                        for code in font.codes(bytes) {
+                            let width = font.width(code) * size;
+                            let is_space = font.decode(code).trim().is_empty();
+                            if !is_space {
+                                glyphs.push((*advance, width));
+                            }
+                            let mut glyph = width + state.text.char_spacing;
+                            if !font.two_byte && code == 32 {
+                                glyph += state.text.word_spacing;
+                            }
+                            *advance += glyph * scale;
+                        }
+                    };
+                    for operand in &operation.operands {
+                        match operand {
+                            Object::String(bytes, _) => show(bytes, &mut advance),
+                            Object::Array(items) => {
+                                for item in items {
+                                    match item {
+                                        Object::String(bytes, _) => show(bytes, &mut advance),
+                                        other => {
+                                            if let Ok(adjust) = other.as_float() {
+                                                advance -= adjust as f64 / 1000.0 * size * scale;
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // Invisible text (mode 3, e.g. OCR layers) and clipping-only modes paint nothing
+                    if !matches!(state.render_mode, 3 | 7) {
+                        let device = multiply(&tm, &state.ctm);
+                        let rise = state.text.rise;
+                        // Glyphs are drawn as solid boxes covering the x-height band
+                        let boxes: Vec<Vec<(f64, f64)>> = glyphs
+                            .iter()
+                            .map(|&(x, width)| {
+                                let (x0, x1) = (x + width * 0.1 * scale, x + width * 0.9 * scale);
+                                let (y0, y1) = (rise, rise + size * 0.55);
+                                vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+                                    .into_iter()
+                                    .map(|(px, py)| transform_point(&device, px, py))
+                                    .collect()
+                            })
+                            .collect();
+                        let color = if matches!(state.render_mode, 1 | 5) { state.stroke } else { state.fill };
+                        self.fill_polygons(&boxes, color, state.fill_alpha, false)?;
+                    }
+                    tm = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+                }
+                "Do" => {
+                    let name = match operation.operands.first().and_then(|name| name.as_name().ok()) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let reference = resources
+                        .and_then(|r| resource_category(doc, r, b"XObject"))
+                        .and_then(|xobjects| xobjects.get(name).ok());
+                    let (id, xobject) = match reference.and_then(|r| doc.dereference(r).ok()) {
+                        Some((Some(id), Object::Stream(xobject))) => (id, xobject),
+                        _ => continue,
+                    };
+                    match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+                        Ok(b"Image") => self.draw_image(id, &state.ctm, state.fill, state.fill_alpha)?,
+                        Ok(b"Form") => {
+                            let matrix = xobject
+                                .dict
+                                .get(b"Matrix")
+                                .ok()
+                                .and_then(|m| m.as_array().ok())
+                                .map(|m| operand_numbers(m))
+                                .filter(|m| m.len() == 6)
+                                .map_or(IDENTITY, |m| [m[0], m[1], m[2], m[3], m[4], m[5]]);
+                            let form_resources = xobject
+                                .dict
+                                .get(b"Resources")
+                                .ok()
+                                .and_then(|r| resolve_object(doc, r))
+                                .and_then(|r| r.as_dict().ok())
+                                .or(resources);
+                            let form_content = xobject.decompressed_content().unwrap_or_else(|_| xobject.content.clone());
+                            self.run(&form_content, form_resources, multiply(&matrix, &state.ctm), depth + 1)?;
+                        }
+                        _ => {}
+                    }
+                }
+                "sh" => self.warn("unsupported shading".to_string()),
+                "BI" => self.warn("unsupported inline image".to_string()),
+                _ => {}
+            }
+        }
+        Ok(())
     }
-    
-    // Save the new document
-    new_doc.save(output_path).map_err(|e| e.to_string())?;
-    
-    Ok(())
 }
 
-#[tauri::command]
-async fn merge_pdfs(paths: Vec<String>, output_path: String) -> Result<(), String> {
-    if paths.is_empty() {
-        return Err("No PDFs to merge".to_string());
+// Matrix mapping page space onto a pixel grid at the given scale, honouring the
+// visible box origin and the page's /Rotate. Returns the matrix and the pixel size.
+fn page_device_matrix(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    default_size: (f64, f64),
+) -> (Matrix, u32, u32) {
+    device_matrix(doc, page_id, scale, page_rotation(doc, page_id), default_size)
+}
+
+fn device_matrix(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    rotation: i32,
+    default_size: (f64, f64),
+) -> (Matrix, u32, u32) {
+    let [x0, y0, x1, y1] = page_box_or_default(doc, page_id, default_size);
+    let (w, h) = (x1 - x0, y1 - y0);
+    let (rotate, display_w, display_h) = match rotation {
+        90 => ([0.0, -1.0, 1.0, 0.0, 0.0, w], h, w),
+        180 => ([-1.0, 0.0, 0.0, -1.0, w, h], w, h),
+        270 => ([0.0, 1.0, -1.0, 0.0, h, 0.0], h, w),
+        _ => (IDENTITY, w, h),
+    };
+    let translate = [1.0, 0.0, 0.0, 1.0, -x0, -y0];
+    let to_pixels = [scale, 0.0, 0.0, -scale, 0.0, display_h * scale];
+    let matrix = multiply(&multiply(&translate, &rotate), &to_pixels);
+    let width = (display_w * scale).round().max(1.0) as u32;
+    let height = (display_h * scale).round().max(1.0) as u32;
+    (matrix, width, height)
+}
+
+// Rasterize a page onto a white canvas, as a viewer would display it (/Rotate applied).
+//
+// This is an approximate, dependency-free renderer: paths, fills, strokes and images are
+// drawn, but clipping, shadings, blend modes and soft masks are ignored, and text is drawn
+// as solid glyph boxes rather than real outlines. It is good enough for thumbnails and
+// for coverage or layout analysis, not for print-quality output.
+fn render_page(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    deadline: Option<std::time::Instant>,
+    default_size: (f64, f64),
+) -> Result<image::RgbImage, String> {
+    render_page_oriented(doc, page_id, scale, deadline, page_rotation(doc, page_id), default_size)
+}
+
+// Rasterize a page in its own coordinate space, ignoring /Rotate. Useful when the
+// result is drawn back into the page.
+fn render_page_unrotated(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    default_size: (f64, f64),
+) -> Result<image::RgbImage, String> {
+    render_page_oriented(doc, page_id, scale, None, 0, default_size)
+}
+
+fn render_page_oriented(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    deadline: Option<std::time::Instant>,
+    rotation: i32,
+    default_size: (f64, f64),
+) -> Result<image::RgbImage, String> {
+    render_page_with_warnings(doc, page_id, scale, deadline, rotation, default_size).map(|(image, _)| image)
+}
+
+// Like render_page_oriented, also returning what the renderer skipped or approximated
+fn render_page_with_warnings(
+    doc: &Document,
+    page_id: ObjectId,
+    scale: f64,
+    deadline: Option<std::time::Instant>,
+    rotation: i32,
+    default_size: (f64, f64),
+) -> Result<(image::RgbImage, Vec<String>), String> {
+    let (matrix, width, height) = device_matrix(doc, page_id, scale, rotation, default_size);
+    let mut rasterizer = Rasterizer {
+        doc,
+        image: image::RgbImage::from_pixel(width, height, image::Rgb([255, 255, 255])),
+        deadline,
+        images: HashMap::new(),
+        warnings: Vec::new(),
+    };
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    rasterizer.run(&content, page_resources(doc, page_id), matrix, 0)?;
+    Ok((rasterizer.image, rasterizer.warnings))
+}
+
+fn encode_png_data_url(image: &image::RgbImage) -> Result<String, String> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(bytes.into_inner())
+    ))
+}
+
+// Embedded /Thumb images larger than this on either side are ignored
+const MAX_EMBEDDED_THUMBNAIL_SIZE: u32 = 512;
+
+// Decode a page's embedded /Thumb image, if it has a usable one
+fn embedded_thumbnail(doc: &Document, page_id: ObjectId) -> Option<image::RgbImage> {
+    let thumb = doc.get_dictionary(page_id).ok()?.get(b"Thumb").ok()?;
+    let thumb = resolve_object(doc, thumb)?.as_stream().ok()?;
+    let image = decode_image_xobject(doc, thumb).ok()?;
+    if image.width() == 0
+        || image.height() == 0
+        || image.width() > MAX_EMBEDDED_THUMBNAIL_SIZE
+        || image.height() > MAX_EMBEDDED_THUMBNAIL_SIZE
+    {
+        return None;
     }
-    
-    let mut merged_doc = Document::load(&paths[0]).map_err(|e| e.to_string())?;
-    
-    for path in paths.iter().skip(1) {
-        let doc = Document::load(path).map_err(|e| e.to_string())?;
-        
-        // Merge pages from doc into merged_doc
-        // This is a simplified version - proper implementation would handle resources, etc.
-        for (_, page_id) in doc.get_pages() {
-            if let Ok(page) = doc.get_object(page_id) {
-                let new_page_id = merged_doc.new_object_id();
-                merged_doc.objects.insert(new_page_id, page.clone());
+    Some(image)
+}
+
+// Render a page thumbnail within the time budget, falling back to the placeholder.
+// A thumbnail embedded in the PDF is used instead of rendering when present.
+fn render_thumbnail(doc: &Document, page_num: usize, timeout_ms: u64, default_size: (f64, f64)) -> PageThumbnail {
+    let embedded = page_id(doc, page_num)
+        .ok()
+        .and_then(|page_id| embedded_thumbnail(doc, page_id))
+        .and_then(|image| encode_png_data_url(&image).ok());
+    if let Some(data) = embedded {
+        return PageThumbnail { data, error: None, warnings: Vec::new() };
+    }
+
+    let result = page_id(doc, page_num).and_then(|page_id| {
+        let (_, width, height) = page_device_matrix(doc, page_id, 1.0, default_size);
+        let scale = THUMBNAIL_SIZE / width.max(height) as f64;
+        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+        render_page_with_warnings(doc, page_id, scale, Some(deadline), page_rotation(doc, page_id), default_size)
+    });
+
+    match result.and_then(|(image, warnings)| Ok((encode_png_data_url(&image)?, warnings))) {
+        Ok((data, warnings)) => PageThumbnail { data, error: None, warnings },
+        Err(error) => {
+            let (width, height) = get_page_dimensions(doc, page_num, default_size).unwrap_or(default_size);
+            let rotation = page_id(doc, page_num).map(|id| page_rotation(doc, id)).unwrap_or(0);
+            PageThumbnail {
+                data: generate_thumbnail_placeholder(page_num, width, height, rotation, default_size),
+                error: Some(error),
+                warnings: Vec::new(),
             }
         }
     }
-    
-    merged_doc.save(output_path).map_err(|e| e.to_string())?;
-    
-    Ok(())
 }
 
+#[tauri::command]
+async fn swap_pages(state: State<'_, AppState>, path: String, a: usize, b: usize) -> Result<(), String> {
+    // Swapping a page with itself changes nothing, so it leaves no undo entry
+    if a == b {
+        return with_document(&state, &path, |doc| page_id(doc, a).map(|_| ()));
+    }
 
+    edit_document(&state, &path, |doc| {
+        page_id(doc, a)?;
+        page_id(doc, b)?;
+        let mut order: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        order.swap(a - 1, b - 1);
+        apply_page_order(doc, &order)
+    })
+}
 
-fn main() {
-    tauri::Builder::default()
-        .setup(|app| {
-            #[cfg(debug_assertions)]
-            {
-                let window = app.get_webview_window("main").unwrap();
-                window.open_devtools();
-            }
-            Ok(())
-        })
+// Operator appended by decode_content_strict to detect where lopdf stopped parsing
+const CONTENT_END_CHECK: &str = "EndOfContentCheck";
+
+// Decode content operators, failing on input lopdf can't tokenize all the way through.
+// Content::decode stops at the first bad token and returns what it parsed so far.
+fn decode_content_strict(bytes: &[u8]) -> Result<lopdf::content::Content, String> {
+    let mut checked = bytes.to_vec();
+    checked.extend_from_slice(format!("\n{}", CONTENT_END_CHECK).as_bytes());
+    let mut content = lopdf::content::Content::decode(&checked).map_err(|e| e.to_string())?;
+    match content.operations.pop() {
+        Some(last) if last.operator == CONTENT_END_CHECK && last.operands.is_empty() => Ok(content),
+        Some(last) if last.operator == CONTENT_END_CHECK => Err("operands at the end have no operator".to_string()),
+        _ => Err(format!("stops parsing after {} operators", content.operations.len())),
+    }
+}
+
+#[tauri::command]
+async fn replace_page_content(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    content_operators: String,
+) -> Result<(), String> {
+    // Make sure the operators tokenize before touching the document
+    let content = decode_content_strict(content_operators.as_bytes())
+        .map_err(|e| format!("Invalid content stream: {}", e))?;
+    let bytes = content.encode().map_err(|e| e.to_string())?;
+
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+
+        // Resources are left untouched so names used by the new content still resolve
+        set_page_content(doc, page_id, bytes)
+    })
+}
+
+#[tauri::command]
+async fn undo(state: State<'_, AppState>, path: String) -> Result<bool, String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let open = documents.get_mut(&path).ok_or("Document is not open")?;
+    if open.readonly {
+        return Err(READ_ONLY.to_string());
+    }
+
+    match open.undo_stack.pop() {
+        Some(previous) => {
+            let current = std::mem::replace(&mut open.doc, previous);
+            open.redo_stack.push(current);
+            open.invalidate();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[tauri::command]
+async fn redo(state: State<'_, AppState>, path: String) -> Result<bool, String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let open = documents.get_mut(&path).ok_or("Document is not open")?;
+    if open.readonly {
+        return Err(READ_ONLY.to_string());
+    }
+
+    match open.redo_stack.pop() {
+        Some(next) => {
+            let current = std::mem::replace(&mut open.doc, next);
+            open.undo_stack.push(current);
+            open.invalidate();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn estimate_document_bytes(doc: &Document) -> u64 {
+    doc.objects
+        .values()
+        .map(|object| match object {
+            Object::Stream(stream) => OBJECT_OVERHEAD_BYTES + stream.content.len() as u64,
+            _ => OBJECT_OVERHEAD_BYTES,
+        })
+        .sum()
+}
+
+#[tauri::command]
+async fn get_cache_stats(state: State<'_, AppState>) -> Result<CacheStats, String> {
+    let documents = state.documents.lock().map_err(|e| e.to_string())?;
+    let mut estimated_bytes = 0;
+    let mut thumbnail_cache_bytes = 0;
+
+    for open in documents.values() {
+        // Undo/redo snapshots are full copies, so they count as well
+        estimated_bytes += estimate_document_bytes(&open.doc);
+        for snapshot in open.undo_stack.iter().chain(open.redo_stack.iter()) {
+            estimated_bytes += estimate_document_bytes(snapshot);
+        }
+        thumbnail_cache_bytes += open
+            .thumbnails
+            .values()
+            .map(|thumbnail| thumbnail.data.len() as u64)
+            .sum::<u64>();
+    }
+
+    Ok(CacheStats {
+        open_documents: documents.len(),
+        estimated_bytes: estimated_bytes + thumbnail_cache_bytes,
+        thumbnail_cache_bytes,
+    })
+}
+
+#[tauri::command]
+async fn unload_all(state: State<'_, AppState>) -> Result<(), String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    documents.clear();
+    Ok(())
+}
+
+// Write the document with the given page order, rotations and deletions. With
+// `consolidate_fonts`, returns what font consolidation subsetted and what it left whole.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn save_pdf(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    page_order: Vec<usize>,
+    rotations: BTreeMap<usize, i32>,
+    deleted_pages: Vec<usize>,
+    consolidate_content: Option<bool>,
+    canonical: Option<bool>,
+    consolidate_fonts: Option<bool>,
+) -> Result<Option<FontConsolidation>, String> {
+    // Start from the cached document so in-memory edits are written out
+    let mut doc = snapshot_document(&state, &path)?;
+    let source_pages = doc.get_pages();
+
+    // Collect the pages to keep, in output order
+    let mut kept: Vec<(usize, ObjectId)> = Vec::new();
+    for &page_num in &page_order {
+        if deleted_pages.contains(&page_num) {
+            continue;
+        }
+        if let Some(&page_id) = source_pages.get(&(page_num as u32)) {
+            if !kept.iter().any(|&(_, id)| id == page_id) {
+                kept.push((page_num, page_id));
+            }
+        }
+    }
+    let mut new_doc = build_document_from_pages(&mut doc, &kept, &rotations)?;
+    if consolidate_content.unwrap_or(false) {
+        let mut replaced = Vec::new();
+        for page_id in new_doc.get_pages().into_values() {
+            replaced.extend(consolidate_page_contents(&mut new_doc, page_id)?);
+        }
+        remove_unreferenced(&mut new_doc, &replaced);
+    }
+    let fonts = match consolidate_fonts {
+        Some(true) => Some(consolidate_document_fonts(&mut new_doc)?),
+        _ => None,
+    };
+    if canonical.unwrap_or(false) {
+        canonicalize_document(&mut new_doc)?;
+    }
+
+    // Save the new document
+    save_document(&mut new_doc, &output_path)?;
+
+    Ok(fonts)
+}
+
+// Join a page's content streams into a single compressed stream. Streams are separated
+// by a newline, as viewers treat them, so the page renders identically. Returns the
+// objects the page no longer points at, which may still be shared with other pages.
+fn consolidate_page_contents(doc: &mut Document, page_id: ObjectId) -> Result<Vec<ObjectId>, String> {
+    let refs = page_content_refs(doc, page_id);
+    if refs.len() < 2 {
+        return Ok(Vec::new());
+    }
+    let mut replaced: Vec<ObjectId> = refs.iter().filter_map(|content| content.as_reference().ok()).collect();
+    if let Ok(Object::Reference(array_id)) = doc.get_dictionary(page_id).and_then(|page| page.get(b"Contents")) {
+        replaced.push(*array_id);
+    }
+
+    let mut joined = Vec::new();
+    for content in &refs {
+        if let Some(Object::Stream(stream)) = resolve_object(doc, content) {
+            let bytes = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+            joined.extend_from_slice(&bytes);
+            joined.push(b'\n');
+        }
+    }
+    set_page_content(doc, page_id, joined)?;
+    Ok(replaced)
+}
+
+// Remove the given objects unless something in the document still references them.
+// Repeats so candidates only kept alive by other removed candidates go too.
+fn remove_unreferenced(doc: &mut Document, candidates: &[ObjectId]) {
+    loop {
+        let mut referenced = Vec::new();
+        for object in doc.objects.values() {
+            gather_references(object, &mut referenced);
+        }
+        let referenced: std::collections::HashSet<ObjectId> = referenced.into_iter().collect();
+        let unused: Vec<ObjectId> = candidates
+            .iter()
+            .copied()
+            .filter(|id| doc.objects.contains_key(id) && !referenced.contains(id))
+            .collect();
+        if unused.is_empty() {
+            return;
+        }
+        for id in unused {
+            doc.objects.remove(&id);
+        }
+    }
+}
+
+// Rewrite a document into a reproducible form: only objects reachable from the trailer,
+// numbered in depth-first order from Root then Info, dictionary keys sorted and no
+// object streams. A missing /ID is derived from the resulting bytes instead of the
+// clock, so the same logical content always saves to the same file.
+fn canonicalize_document(doc: &mut Document) -> Result<(), String> {
+    if doc.is_encrypted() {
+        // Encrypted strings and streams are keyed by object number
+        return Err("Canonical save is not supported for encrypted documents".to_string());
+    }
+    let mut trailer = lopdf::Dictionary::new();
+    for key in [&b"Root"[..], b"Info", b"ID"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            trailer.set(key.to_vec(), value.clone());
+        }
+    }
+
+    let mut roots = Vec::new();
+    for key in [&b"Root"[..], b"Info"] {
+        if let Ok(value) = trailer.get(key) {
+            gather_references(value, &mut roots);
+        }
+    }
+    for object in doc.objects.values_mut() {
+        sort_dictionary_keys(object);
+    }
+    let stop = std::collections::HashSet::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut order = Vec::new();
+    for root in roots {
+        collect_reachable(doc, root, &stop, &mut visited, &mut order);
+    }
+
+    let renumbered: HashMap<ObjectId, ObjectId> = order
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| (id, (index as u32 + 1, 0)))
+        .collect();
+    let mut objects = BTreeMap::new();
+    for id in &order {
+        if let Some(mut object) = doc.objects.remove(id) {
+            remap_references(&mut object, &renumbered);
+            objects.insert(renumbered[id], object);
+        }
+    }
+    for (_, value) in trailer.iter_mut() {
+        remap_references(value, &renumbered);
+    }
+    doc.objects = objects;
+    doc.max_id = doc.objects.len() as u32;
+    doc.trailer = trailer;
+
+    if !doc.trailer.has(b"ID") {
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).map_err(|e| e.to_string())?;
+        let id = Object::String(md5(&bytes).to_vec(), lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", Object::Array(vec![id.clone(), id]));
+    }
+    Ok(())
+}
+
+fn sort_dictionary_keys(object: &mut Object) {
+    match object {
+        Object::Array(items) => items.iter_mut().for_each(sort_dictionary_keys),
+        Object::Dictionary(dict) => *dict = sorted_dictionary(dict),
+        Object::Stream(stream) => stream.dict = sorted_dictionary(&stream.dict),
+        _ => {}
+    }
+}
+
+fn sorted_dictionary(dict: &lopdf::Dictionary) -> lopdf::Dictionary {
+    let mut entries: Vec<(&Vec<u8>, &Object)> = dict.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let mut sorted = lopdf::Dictionary::new();
+    for (key, value) in entries {
+        let mut value = value.clone();
+        sort_dictionary_keys(&mut value);
+        sorted.set(key.clone(), value);
+    }
+    sorted
+}
+
+// Build a standalone document holding the given (page number, page) pairs in order,
+// copying everything they reference. Rotations are keyed by source page number.
+fn build_document_from_pages(
+    doc: &mut Document,
+    kept: &[(usize, ObjectId)],
+    rotations: &BTreeMap<usize, i32>,
+) -> Result<Document, String> {
+    let mut new_doc = Document::with_version("1.5");
+    let source_pages = doc.get_pages();
+    let kept_ids: Vec<ObjectId> = kept.iter().map(|&(_, id)| id).collect();
+
+    let pages_id = new_doc.new_object_id();
+    let mut copier = ObjectCopier::new();
+
+    // Dropped pages and the old page tree must never be pulled in through references
+    for (&id, object) in doc.objects.iter() {
+        let is_dropped_page = source_pages.values().any(|&page| page == id) && !kept_ids.contains(&id);
+        if is_dropped_page || is_pages_node(object) {
+            copier.exclude(id);
+        }
+    }
+
+    // Reserve IDs for kept pages so annotation /P entries and link destinations resolve
+    for &page_id in &kept_ids {
+        copier.reserve(&mut new_doc, page_id);
+    }
+
+    // Copy metadata
+    if let Ok(info) = doc.trailer.get(b"Info") {
+        let info = copier.copy_object(doc, &mut new_doc, info);
+        new_doc.trailer.set("Info", info);
+    }
+    // Keep the document's identity so the output is recognized as the same file
+    if let Ok(id) = doc.trailer.get(b"ID") {
+        let id = copier.copy_object(doc, &mut new_doc, id);
+        new_doc.trailer.set("ID", id);
+    }
+
+    for &(page_num, page_id) in kept {
+        materialize_inherited_attributes(doc, page_id)?;
+        let mut page_dict = doc.get_dictionary(page_id).map_err(|e| e.to_string())?.clone();
+        page_dict.remove(b"Parent");
+
+        // Drop internal links whose target page is not part of the output
+        if let Ok(annots) = page_dict.get(b"Annots") {
+            let annots = match doc.dereference(annots) {
+                Ok((_, Object::Array(items))) => items.clone(),
+                _ => Vec::new(),
+            };
+            let retained: Vec<Object> = annots
+                .into_iter()
+                .filter(|annot| match link_target_page(doc, annot) {
+                    Some(target) => kept_ids.contains(&target),
+                    None => true,
+                })
+                .collect();
+            page_dict.set("Annots", Object::Array(retained));
+        }
+
+        let mut new_page = copier.copy_dictionary(doc, &mut new_doc, &page_dict);
+        new_page.set("Parent", Object::Reference(pages_id));
+
+        // Apply rotation if needed
+        if let Some(&rotation) = rotations.get(&page_num) {
+            if rotation != 0 {
+                new_page.set("Rotate", Object::Integer(rotation as i64));
+            }
+        }
+
+        let new_page_id = copier.mapped(page_id).ok_or("Page was not reserved")?;
+        new_doc.objects.insert(new_page_id, Object::Dictionary(new_page));
+    }
+
+    // Build the page tree and catalog
+    let kids: Vec<Object> = kept_ids
+        .iter()
+        .filter_map(|&id| copier.mapped(id))
+        .map(Object::Reference)
+        .collect();
+    let mut pages = lopdf::Dictionary::new();
+    pages.set("Type", Object::Name(b"Pages".to_vec()));
+    pages.set("Count", Object::Integer(kids.len() as i64));
+    pages.set("Kids", Object::Array(kids));
+    new_doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+    let mut catalog = lopdf::Dictionary::new();
+    catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+    catalog.set("Pages", Object::Reference(pages_id));
+
+    // Carry over document-level settings that don't depend on the page tree
+    if let Ok(source_catalog) = doc.catalog() {
+        for &key in CATALOG_KEYS_KEPT_ON_SAVE.iter() {
+            if let Ok(value) = source_catalog.get(key) {
+                let value = copier.copy_object(doc, &mut new_doc, value);
+                catalog.set(key.to_vec(), value);
+            }
+        }
+    }
+    let catalog_id = new_doc.add_object(catalog);
+    new_doc.trailer.set("Root", Object::Reference(catalog_id));
+
+    Ok(new_doc)
+}
+
+// Write a file via a sibling temp file that is renamed over the destination only once
+// it is complete, so a failed or interrupted save never truncates the existing file
+fn write_atomically(
+    output_path: &str,
+    write: impl FnOnce(&mut std::fs::File) -> Result<(), String>,
+) -> Result<(), String> {
+    let target = std::path::Path::new(output_path);
+    let file_name = target.file_name().ok_or("Invalid output path")?;
+    let temp_path = target.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = std::fs::File::create(&temp_path)
+        .map_err(|e| e.to_string())
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all().map_err(|e| e.to_string())
+        })
+        .and_then(|_| std::fs::rename(&temp_path, target).map_err(|e| e.to_string()));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn save_document(doc: &mut Document, output_path: &str) -> Result<(), String> {
+    ensure_document_id(doc, output_path);
+    write_document(doc, output_path)
+}
+
+// Like save_document, but writes the trailer exactly as it is, so a removed /ID stays removed
+fn write_document(doc: &mut Document, output_path: &str) -> Result<(), String> {
+    write_atomically(output_path, |file| {
+        let mut writer = std::io::BufWriter::new(file);
+        doc.save_to(&mut writer).map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())
+    })
+}
+
+// A fresh 16-byte file identifier, from the output location, time and document size
+fn new_document_id(doc: &Document, seed: &str) -> Object {
+    let seed = format!("{}{:?}{}", seed, std::time::SystemTime::now(), doc.objects.len());
+    Object::String(md5(seed.as_bytes()).to_vec(), lopdf::StringFormat::Hexadecimal)
+}
+
+// Give the trailer a two-element /ID when it has none; the permanent and changing
+// halves start out identical
+fn ensure_document_id(doc: &mut Document, seed: &str) {
+    let valid = doc
+        .trailer
+        .get(b"ID")
+        .and_then(Object::as_array)
+        .is_ok_and(|ids| ids.len() == 2 && ids.iter().all(|id| matches!(id, Object::String(..))));
+    if !valid {
+        let id = new_document_id(doc, seed);
+        doc.trailer.set("ID", Object::Array(vec![id.clone(), id]));
+    }
+}
+
+// Deep-copies objects from a source document into a target document,
+// assigning fresh IDs and remapping references as it goes
+struct ObjectCopier {
+    id_map: HashMap<ObjectId, ObjectId>,
+    excluded: std::collections::HashSet<ObjectId>,
+}
+
+impl ObjectCopier {
+    fn new() -> Self {
+        ObjectCopier {
+            id_map: HashMap::new(),
+            excluded: std::collections::HashSet::new(),
+        }
+    }
+
+    // References to excluded objects are replaced with null
+    fn exclude(&mut self, id: ObjectId) {
+        self.excluded.insert(id);
+    }
+
+    // Allocate the target ID up front; the caller inserts the object itself
+    fn reserve(&mut self, target: &mut Document, id: ObjectId) -> ObjectId {
+        self.excluded.remove(&id);
+        *self.id_map.entry(id).or_insert_with(|| target.new_object_id())
+    }
+
+    fn mapped(&self, id: ObjectId) -> Option<ObjectId> {
+        self.id_map.get(&id).copied()
+    }
+
+    fn copy_reference(&mut self, source: &Document, target: &mut Document, id: ObjectId) -> Option<ObjectId> {
+        if self.excluded.contains(&id) {
+            return None;
+        }
+        if let Some(&new_id) = self.id_map.get(&id) {
+            return Some(new_id);
+        }
+
+        let new_id = target.new_object_id();
+        self.id_map.insert(id, new_id);
+        let object = source.get_object(id).cloned().unwrap_or(Object::Null);
+        let copied = self.copy_object(source, target, &object);
+        target.objects.insert(new_id, copied);
+        Some(new_id)
+    }
+
+    fn copy_object(&mut self, source: &Document, target: &mut Document, object: &Object) -> Object {
+        match object {
+            Object::Reference(id) => match self.copy_reference(source, target, *id) {
+                Some(new_id) => Object::Reference(new_id),
+                None => Object::Null,
+            },
+            Object::Array(items) => Object::Array(
+                items
+                    .iter()
+                    .map(|item| self.copy_object(source, target, item))
+                    .collect(),
+            ),
+            Object::Dictionary(dict) => Object::Dictionary(self.copy_dictionary(source, target, dict)),
+            Object::Stream(stream) => {
+                let mut copy = stream.clone();
+                copy.dict = self.copy_dictionary(source, target, &stream.dict);
+                Object::Stream(copy)
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn copy_dictionary(
+        &mut self,
+        source: &Document,
+        target: &mut Document,
+        dict: &lopdf::Dictionary,
+    ) -> lopdf::Dictionary {
+        let mut copy = lopdf::Dictionary::new();
+        for (key, value) in dict.iter() {
+            copy.set(key.clone(), self.copy_object(source, target, value));
+        }
+        copy
+    }
+}
+
+fn is_pages_node(object: &Object) -> bool {
+    match object {
+        Object::Dictionary(dict) => matches!(dict.get(b"Type"), Ok(Object::Name(name)) if name == b"Pages"),
+        _ => false,
+    }
+}
+
+// Resolve the page targeted by an internal link annotation (/Dest or a /GoTo action)
+fn link_target_page(doc: &Document, annot: &Object) -> Option<ObjectId> {
+    let (_, annot) = doc.dereference(annot).ok()?;
+    let annot = annot.as_dict().ok()?;
+
+    let dest = match annot.get(b"Dest") {
+        Ok(dest) => dest,
+        Err(_) => {
+            let (_, action) = doc.dereference(annot.get(b"A").ok()?).ok()?;
+            let action = action.as_dict().ok()?;
+            if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+                return None;
+            }
+            action.get(b"D").ok()?
+        }
+    };
+
+    match doc.dereference(dest).ok()?.1 {
+        Object::Array(items) => items.first()?.as_reference().ok(),
+        _ => None,
+    }
+}
+
+// Interactive form being assembled from all merge inputs
+#[derive(Default)]
+struct MergedForm {
+    fields: Vec<Object>,
+    names: std::collections::HashSet<String>,
+    default_resources: lopdf::Dictionary,
+    default_appearance: Option<Object>,
+    need_appearances: bool,
+}
+
+impl MergedForm {
+    fn into_dictionary(self) -> lopdf::Dictionary {
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("Fields", Object::Array(self.fields));
+        if !self.default_resources.is_empty() {
+            dict.set("DR", Object::Dictionary(self.default_resources));
+        }
+        if let Some(appearance) = self.default_appearance {
+            dict.set("DA", appearance);
+        }
+        if self.need_appearances {
+            dict.set("NeedAppearances", Object::Boolean(true));
+        }
+        dict
+    }
+
+    // First free name of the form `name`, `name_2`, `name_3`, ...
+    fn unique_name(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut suffix = 2;
+        while self.names.contains(&candidate) {
+            candidate = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        self.names.insert(candidate.clone());
+        candidate
+    }
+}
+
+// Add an input's AcroForm fields and default resources to the merged form. Must run
+// after the input's pages are copied so widget annotations map to the same objects.
+fn merge_acro_form(
+    source: &Document,
+    target: &mut Document,
+    copier: &mut ObjectCopier,
+    form: &mut MergedForm,
+) -> Result<(), String> {
+    let acro_form = match source
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(source, form))
+        .and_then(|form| form.as_dict().ok())
+    {
+        Some(acro_form) => acro_form,
+        None => return Ok(()),
+    };
+
+    let fields = match acro_form.get(b"Fields").ok().and_then(|f| resolve_object(source, f)) {
+        Some(Object::Array(fields)) => fields.clone(),
+        _ => Vec::new(),
+    };
+    for field in &fields {
+        let copied = copier.copy_object(source, target, field);
+        let field_id = match copied {
+            Object::Reference(id) => id,
+            _ => continue,
+        };
+
+        // Only top-level names can collide; children are qualified by their parent
+        let name = target
+            .get_dictionary(field_id)
+            .ok()
+            .and_then(|dict| dict.get(b"T").ok())
+            .and_then(|name| name.as_str().ok())
+            .map(decode_pdf_string);
+        if let Some(name) = name {
+            let unique = form.unique_name(&name);
+            if unique != name {
+                let dict = target.get_dictionary_mut(field_id).map_err(|e| e.to_string())?;
+                dict.set("T", encode_pdf_string(&unique));
+            }
+        }
+        form.fields.push(Object::Reference(field_id));
+    }
+
+    // Union default resources per category (Font, XObject, ...); earlier inputs win on clashes
+    if let Ok(resources) = acro_form.get(b"DR") {
+        if let Object::Dictionary(resources) = copier.copy_object(source, target, resources) {
+            for (category, entries) in resources.iter() {
+                let entries = match resolve_object(target, entries) {
+                    Some(Object::Dictionary(entries)) => entries.clone(),
+                    _ => {
+                        if !form.default_resources.has(category) {
+                            form.default_resources.set(category.clone(), entries.clone());
+                        }
+                        continue;
+                    }
+                };
+                let mut merged = match form.default_resources.get(category) {
+                    Ok(Object::Dictionary(existing)) => existing.clone(),
+                    _ => lopdf::Dictionary::new(),
+                };
+                for (key, value) in entries.iter() {
+                    if !merged.has(key) {
+                        merged.set(key.clone(), value.clone());
+                    }
+                }
+                form.default_resources.set(category.clone(), Object::Dictionary(merged));
+            }
+        }
+    }
+
+    if form.default_appearance.is_none() {
+        form.default_appearance = acro_form.get(b"DA").ok().cloned();
+    }
+    if let Ok(Object::Boolean(true)) = acro_form.get(b"NeedAppearances") {
+        form.need_appearances = true;
+    }
+    Ok(())
+}
+
+// Pages processed between two progress events for a single input
+const MERGE_PROGRESS_INTERVAL: usize = 25;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MergeProgress {
+    current_file: usize,
+    files_total: usize,
+    pages_done: usize,
+    done: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeparatorSpec {
+    blank: bool,
+    // Text centered on the separator page; a labeled separator is inserted even when
+    // `blank` is false
+    label: Option<String>,
+}
+
+// A page with an optional line of Helvetica text centered on it
+fn create_label_page(doc: &mut Document, width: f64, height: f64, label: Option<&str>) -> Result<ObjectId, String> {
+    let page_id = create_blank_page(doc, width, height);
+    if let Some(label) = label.filter(|label| !label.trim().is_empty()) {
+        let operations = centered_text_line("F1", label, 24.0, width / 2.0, height / 2.0, width * 0.8);
+        let content = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", Object::Reference(font_id));
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("Font", Object::Dictionary(fonts));
+        doc.get_dictionary_mut(page_id)
+            .map_err(|e| e.to_string())?
+            .set("Resources", Object::Dictionary(resources));
+        set_page_content(doc, page_id, content)?;
+    }
+    Ok(page_id)
+}
+
+// With `unify_size` every incoming page is fitted and centered onto that size;
+// otherwise each page keeps its own size. A `separator` page goes between consecutive
+// inputs, sized like the page before it. Emits `merge_progress` events to the frontend
+// while the inputs are copied.
+#[tauri::command]
+async fn merge_pdfs<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    paths: Vec<String>,
+    output_path: String,
+    unify_size: Option<PageSizePreset>,
+    separator: Option<SeparatorSpec>,
+) -> Result<(), String> {
+    if paths.is_empty() {
+        return Err("No PDFs to merge".to_string());
+    }
+    let default_size = app.state::<AppState>().settings.lock().map_err(|e| e.to_string())?.default_page_size;
+
+    let files_total = paths.len();
+    let report = |current_file: usize, pages_done: usize, done: bool| {
+        let progress = MergeProgress { current_file, files_total, pages_done, done };
+        // Progress is best effort; a closed window must not fail the merge
+        let _ = app.emit("merge_progress", progress);
+    };
+
+    let mut merged_doc = Document::with_version("1.5");
+    let pages_id = merged_doc.new_object_id();
+    let mut kids: Vec<Object> = Vec::new();
+    let mut form = MergedForm::default();
+
+    for (index, path) in paths.iter().enumerate() {
+        let current_file = index + 1;
+        let mut doc = Document::load(path).map_err(|e| e.to_string())?;
+
+        let wants_separator = separator.as_ref().is_some_and(|spec| spec.blank || spec.label.is_some());
+        if let (true, Some(spec), Some(&Object::Reference(previous))) = (wants_separator, &separator, kids.last()) {
+            let (width, height) = match unify_size {
+                Some(target) => target.dimensions(),
+                None => page_visible_box(&merged_doc, previous)
+                    .map_or(default_size, |[x0, y0, x1, y1]| (x1 - x0, y1 - y0)),
+            };
+            let separator_id = create_label_page(&mut merged_doc, width, height, spec.label.as_deref())?;
+            merged_doc
+                .get_dictionary_mut(separator_id)
+                .map_err(|e| e.to_string())?
+                .set("Parent", Object::Reference(pages_id));
+            kids.push(Object::Reference(separator_id));
+        }
+        let mut copier = ObjectCopier::new();
+
+        // Each input's page tree is replaced by the merged one
+        for (&id, object) in doc.objects.iter() {
+            if is_pages_node(object) {
+                copier.exclude(id);
+            }
+        }
+        let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        for &page_id in &page_ids {
+            copier.reserve(&mut merged_doc, page_id);
+        }
+
+        for (page_index, &page_id) in page_ids.iter().enumerate() {
+            materialize_inherited_attributes(&mut doc, page_id)?;
+            let mut page_dict = doc.get_dictionary(page_id).map_err(|e| e.to_string())?.clone();
+            page_dict.remove(b"Parent");
+
+            let mut new_page = copier.copy_dictionary(&doc, &mut merged_doc, &page_dict);
+            new_page.set("Parent", Object::Reference(pages_id));
+            let new_page_id = copier.mapped(page_id).ok_or("Page was not reserved")?;
+            merged_doc.objects.insert(new_page_id, Object::Dictionary(new_page));
+            if let Some(target) = unify_size {
+                resize_page(&mut merged_doc, new_page_id, target, ResizeMode::Fit, false)?;
+            }
+            kids.push(Object::Reference(new_page_id));
+
+            if (page_index + 1) % MERGE_PROGRESS_INTERVAL == 0 {
+                report(current_file, kids.len(), false);
+            }
+        }
+
+        merge_acro_form(&doc, &mut merged_doc, &mut copier, &mut form)?;
+        report(current_file, kids.len(), false);
+    }
+
+    let mut pages = lopdf::Dictionary::new();
+    pages.set("Type", Object::Name(b"Pages".to_vec()));
+    pages.set("Count", Object::Integer(kids.len() as i64));
+    pages.set("Kids", Object::Array(kids));
+    merged_doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+    let mut catalog = lopdf::Dictionary::new();
+    catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+    catalog.set("Pages", Object::Reference(pages_id));
+    if !form.fields.is_empty() {
+        let acro_form = merged_doc.add_object(form.into_dictionary());
+        catalog.set("AcroForm", Object::Reference(acro_form));
+    }
+    let catalog_id = merged_doc.add_object(catalog);
+    merged_doc.trailer.set("Root", Object::Reference(catalog_id));
+
+    let pages_done = merged_doc.get_pages().len();
+    save_document(&mut merged_doc, &output_path)?;
+    report(files_total, pages_done, true);
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AccessibilityInfo {
+    is_tagged: bool,
+    has_lang: bool,
+    has_title: bool,
+    images_missing_alt: Vec<usize>,
+}
+
+// A structure element from the document's /StructTreeRoot
+struct StructElement {
+    id: Option<ObjectId>,
+    role: Vec<u8>,
+    page: Option<ObjectId>,
+    alt: Option<String>,
+}
+
+// Decode a PDF text string (UTF-16BE with BOM, otherwise treated as Latin-1)
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+// Encode a text string for storage in the PDF, using UTF-16BE only when needed
+fn encode_pdf_string(text: &str) -> Object {
+    if text.chars().all(|c| (c as u32) < 0x80) {
+        Object::string_literal(text)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+        Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+    }
+}
+
+fn info_dictionary(doc: &Document) -> Option<&lopdf::Dictionary> {
+    let info = doc.trailer.get(b"Info").ok()?;
+    doc.dereference(info).ok()?.1.as_dict().ok()
+}
+
+fn info_string(doc: &Document, key: &[u8]) -> Option<String> {
+    let value = info_dictionary(doc)?.get(key).ok()?;
+    let (_, value) = doc.dereference(value).ok()?;
+    value.as_str().ok().map(decode_pdf_string)
+}
+
+// Look up a page's resource dictionary, following /Parent inheritance
+fn page_resources(doc: &Document, page_id: ObjectId) -> Option<&lopdf::Dictionary> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..64 {
+        if let Ok(resources) = node.get(b"Resources") {
+            return doc.dereference(resources).ok()?.1.as_dict().ok();
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+// Look up a resource category such as /XObject or /Font within a resource dictionary
+fn resource_category<'a>(
+    doc: &'a Document,
+    resources: &'a lopdf::Dictionary,
+    category: &[u8],
+) -> Option<&'a lopdf::Dictionary> {
+    let entry = resources.get(category).ok()?;
+    doc.dereference(entry).ok()?.1.as_dict().ok()
+}
+
+fn is_image_xobject(object: &Object) -> bool {
+    match object {
+        Object::Stream(stream) => {
+            matches!(stream.dict.get(b"Subtype"), Ok(Object::Name(name)) if name == b"Image")
+        }
+        _ => false,
+    }
+}
+
+// Image XObjects painted by a page, in the order the content stream first draws them
+fn page_images(doc: &Document, page_id: ObjectId) -> Result<Vec<(Vec<u8>, ObjectId)>, String> {
+    let xobjects = match page_resources(doc, page_id).and_then(|r| resource_category(doc, r, b"XObject")) {
+        Some(xobjects) => xobjects,
+        None => return Ok(Vec::new()),
+    };
+
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let content = lopdf::content::Content::decode(&content).map_err(|e| e.to_string())?;
+    let mut images: Vec<(Vec<u8>, ObjectId)> = Vec::new();
+
+    for operation in &content.operations {
+        if operation.operator != "Do" {
+            continue;
+        }
+        let name = match operation.operands.first().and_then(|o| o.as_name().ok()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if images.iter().any(|(existing, _)| existing == name) {
+            continue;
+        }
+        if let Ok(id) = xobjects.get(name).and_then(Object::as_reference) {
+            if doc.get_object(id).map(is_image_xobject).unwrap_or(false) {
+                images.push((name.to_vec(), id));
+            }
+        }
+    }
+
+    Ok(images)
+}
+
+fn struct_tree_root(doc: &Document) -> Option<&lopdf::Dictionary> {
+    let root = doc.catalog().ok()?.get(b"StructTreeRoot").ok()?;
+    doc.dereference(root).ok()?.1.as_dict().ok()
+}
+
+// Walk the structure tree depth-first, returning elements in document order
+fn collect_struct_elements(doc: &Document) -> Vec<StructElement> {
+    let mut elements = Vec::new();
+    if let Some(root) = struct_tree_root(doc) {
+        if let Ok(kids) = root.get(b"K") {
+            collect_struct_kids(doc, kids, None, &mut elements, 0);
+        }
+    }
+    elements
+}
+
+fn collect_struct_kids(
+    doc: &Document,
+    kids: &Object,
+    page: Option<ObjectId>,
+    elements: &mut Vec<StructElement>,
+    depth: usize,
+) {
+    if depth > 256 {
+        return;
+    }
+    let (id, kids) = match doc.dereference(kids) {
+        Ok(resolved) => resolved,
+        Err(_) => return,
+    };
+
+    match kids {
+        Object::Array(items) => {
+            for item in items {
+                collect_struct_kids(doc, item, page, elements, depth + 1);
+            }
+        }
+        Object::Dictionary(dict) => {
+            // Marked-content and object references are leaves, not elements
+            let role = match dict.get(b"S").and_then(Object::as_name) {
+                Ok(role) => role.to_vec(),
+                Err(_) => return,
+            };
+            let page = dict.get(b"Pg").and_then(Object::as_reference).ok().or(page);
+            let alt = dict
+                .get(b"Alt")
+                .ok()
+                .and_then(|alt| doc.dereference(alt).ok())
+                .and_then(|(_, alt)| alt.as_str().ok())
+                .map(decode_pdf_string);
+
+            elements.push(StructElement { id, role, page, alt });
+            if let Ok(children) = dict.get(b"K") {
+                collect_struct_kids(doc, children, page, elements, depth + 1);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[tauri::command]
+async fn get_accessibility_info(state: State<'_, AppState>, path: String) -> Result<AccessibilityInfo, String> {
+    with_document(&state, &path, |doc| {
+        let catalog = doc.catalog().map_err(|e| e.to_string())?;
+
+        let is_tagged = catalog
+            .get(b"MarkInfo")
+            .ok()
+            .and_then(|mark_info| doc.dereference(mark_info).ok())
+            .and_then(|(_, mark_info)| mark_info.as_dict().ok())
+            .and_then(|mark_info| mark_info.get(b"Marked").and_then(Object::as_bool).ok())
+            .unwrap_or(false);
+        let has_lang = catalog.has(b"Lang");
+        let has_title = info_string(doc, b"Title").map_or(false, |title| !title.trim().is_empty());
+
+        // A page is missing alt text when it paints more images than it has described figures
+        let figures = collect_struct_elements(doc);
+        let mut images_missing_alt = Vec::new();
+        for (page_number, page_id) in doc.get_pages() {
+            let image_count = page_images(doc, page_id)?.len();
+            if image_count == 0 {
+                continue;
+            }
+            let described = figures
+                .iter()
+                .filter(|element| element.role == b"Figure" && element.page == Some(page_id))
+                .filter(|element| element.alt.as_deref().map_or(false, |alt| !alt.is_empty()))
+                .count();
+            if described < image_count {
+                images_missing_alt.push(page_number as usize);
+            }
+        }
+
+        Ok(AccessibilityInfo {
+            is_tagged,
+            has_lang,
+            has_title,
+            images_missing_alt,
+        })
+    })
+}
+
+// Append a child to a structure node's /K entry, converting a single kid into an array
+fn append_struct_kid(doc: &mut Document, parent_id: ObjectId, kid: Object) -> Result<(), String> {
+    let existing = doc
+        .get_dictionary(parent_id)
+        .map_err(|e| e.to_string())?
+        .get(b"K")
+        .ok()
+        .cloned();
+
+    let kids = match existing {
+        Some(Object::Reference(id)) if matches!(doc.get_object(id), Ok(Object::Array(_))) => {
+            if let Ok(Object::Array(items)) = doc.get_object_mut(id) {
+                items.push(kid);
+            }
+            return Ok(());
+        }
+        Some(Object::Array(mut items)) => {
+            items.push(kid);
+            items
+        }
+        Some(Object::Null) | None => vec![kid],
+        Some(single) => vec![single, kid],
+    };
+
+    let parent = doc.get_dictionary_mut(parent_id).map_err(|e| e.to_string())?;
+    parent.set("K", Object::Array(kids));
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_image_alt(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    image_index: usize,
+    alt: String,
+) -> Result<Option<String>, String> {
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let image_count = page_images(doc, page_id)?.len();
+        if image_index >= image_count {
+            return Err(format!(
+                "Image {} not found on page {} ({} images)",
+                image_index, page_num, image_count
+            ));
+        }
+
+        // Figures on the page are matched to images by their order of appearance
+        let figures: Vec<ObjectId> = collect_struct_elements(doc)
+            .into_iter()
+            .filter(|element| element.role == b"Figure" && element.page == Some(page_id))
+            .filter_map(|element| element.id)
+            .collect();
+
+        if let Some(&figure_id) = figures.get(image_index) {
+            let figure = doc.get_dictionary_mut(figure_id).map_err(|e| e.to_string())?;
+            figure.set("Alt", encode_pdf_string(&alt));
+            return Ok(None);
+        }
+
+        let mut warning = None;
+        let root_id = match doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"StructTreeRoot").ok())
+            .and_then(|root| root.as_reference().ok())
+        {
+            Some(root_id) => root_id,
+            None => {
+                let mut root = lopdf::Dictionary::new();
+                root.set("Type", Object::Name(b"StructTreeRoot".to_vec()));
+                root.set("K", Object::Array(Vec::new()));
+                let root_id = doc.add_object(root);
+                doc.catalog_mut()
+                    .map_err(|e| e.to_string())?
+                    .set("StructTreeRoot", Object::Reference(root_id));
+                warning = Some(
+                    "Document is not tagged; created a minimal structure tree. \
+                     Page content is not marked, so assistive technology support is limited."
+                        .to_string(),
+                );
+                root_id
+            }
+        };
+
+        // Earlier images without a figure get an empty placeholder so indices line up
+        for index in figures.len()..=image_index {
+            let mut figure = lopdf::Dictionary::new();
+            figure.set("Type", Object::Name(b"StructElem".to_vec()));
+            figure.set("S", Object::Name(b"Figure".to_vec()));
+            figure.set("P", Object::Reference(root_id));
+            figure.set("Pg", Object::Reference(page_id));
+            if index == image_index {
+                figure.set("Alt", encode_pdf_string(&alt));
+            }
+            let figure_id = doc.add_object(figure);
+            append_struct_kid(doc, root_id, Object::Reference(figure_id))?;
+        }
+
+        Ok(warning)
+    })
+}
+
+// Collect every object reachable from `start`, without crossing into the `stop` set
+fn collect_reachable(
+    doc: &Document,
+    start: ObjectId,
+    stop: &std::collections::HashSet<ObjectId>,
+    visited: &mut std::collections::HashSet<ObjectId>,
+    out: &mut Vec<ObjectId>,
+) {
+    let mut pending = vec![start];
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let object = match doc.get_object(id) {
+            Ok(object) => object,
+            Err(_) => continue,
+        };
+        out.push(id);
+
+        let mut references = Vec::new();
+        gather_references(object, &mut references);
+        for reference in references.into_iter().rev() {
+            if !stop.contains(&reference) && !visited.contains(&reference) {
+                pending.push(reference);
+            }
+        }
+    }
+}
+
+fn gather_references(object: &Object, out: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => out.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| gather_references(item, out)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| gather_references(value, out)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| gather_references(value, out)),
+        _ => {}
+    }
+}
+
+fn remap_references(object: &mut Object, map: &HashMap<ObjectId, ObjectId>) {
+    match object {
+        Object::Reference(id) => {
+            if let Some(&new_id) = map.get(id) {
+                *id = new_id;
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| remap_references(item, map)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| remap_references(value, map)),
+        Object::Stream(stream) => stream
+            .dict
+            .iter_mut()
+            .for_each(|(_, value)| remap_references(value, map)),
+        _ => {}
+    }
+}
+
+// Parse the classic cross-reference table lopdf writes, returning object offsets and the xref offset
+fn parse_xref_offsets(bytes: &[u8]) -> Option<(HashMap<u32, usize>, usize)> {
+    let tail_start = bytes.len().saturating_sub(64);
+    let tail = String::from_utf8_lossy(&bytes[tail_start..]);
+    let startxref = tail.rfind("startxref")?;
+    let xref_offset: usize = tail[startxref + 9..].split_whitespace().next()?.parse().ok()?;
+
+    let table = String::from_utf8_lossy(&bytes[xref_offset..]);
+    let mut lines = table.lines();
+    if lines.next()?.trim() != "xref" {
+        return None;
+    }
+
+    let mut offsets = HashMap::new();
+    while let Some(line) = lines.next() {
+        let mut fields = line.split_whitespace();
+        let (first, count) = match (fields.next(), fields.next()) {
+            (Some("trailer"), _) | (None, _) => break,
+            (Some(first), Some(count)) => (first.parse::<u32>().ok()?, count.parse::<u32>().ok()?),
+            _ => return None,
+        };
+        for number in first..first + count {
+            let entry = lines.next()?;
+            let mut entry_fields = entry.split_whitespace();
+            let offset: usize = entry_fields.next()?.parse().ok()?;
+            entry_fields.next();
+            if entry_fields.next() == Some("n") {
+                offsets.insert(number, offset);
+            }
+        }
+    }
+
+    Some((offsets, xref_offset))
+}
+
+// lopdf leaves linearization dictionaries out when saving, so object 1 is inserted
+// right after the header and the cross-reference table rewritten around it
+fn splice_linearization_dictionary(bytes: &[u8], lin: &lopdf::Dictionary) -> Option<Vec<u8>> {
+    let header_end = bytes.iter().position(|&byte| byte == b'\n')? + 1;
+    let (offsets, xref_offset) = parse_xref_offsets(bytes)?;
+    let trailer_start = find_bytes(bytes, b"trailer", xref_offset)?;
+    let trailer_end = find_bytes(bytes, b"startxref", trailer_start)?;
+
+    let mut object = b"1 0 obj\n".to_vec();
+    write_pdf_object(&mut object, &Object::Dictionary(lin.clone()));
+    object.extend_from_slice(b"\nendobj\n");
+    let shift = object.len();
+
+    let mut out = bytes[..header_end].to_vec();
+    out.extend_from_slice(&object);
+    out.extend_from_slice(&bytes[header_end..xref_offset]);
+    let new_xref_offset = out.len();
+    let size = offsets.keys().max().copied().unwrap_or(1).max(1) + 1;
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", size).as_bytes());
+    for number in 1..size {
+        let entry = match offsets.get(&number) {
+            _ if number == 1 => format!("{:010} 00000 n \n", header_end),
+            Some(offset) => format!("{:010} 00000 n \n", offset + shift),
+            None => "0000000000 00000 f \n".to_string(),
+        };
+        out.extend_from_slice(entry.as_bytes());
+    }
+    out.extend_from_slice(&bytes[trailer_start..trailer_end]);
+    out.extend_from_slice(format!("startxref\n{}\n%%EOF", new_xref_offset).as_bytes());
+    Some(out)
+}
+
+// Packs values MSB-first into a byte buffer, as used by the linearization hint tables
+struct BitWriter {
+    bytes: Vec<u8>,
+    used_bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            used_bits: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, bits: u32) {
+        for bit in (0..bits).rev() {
+            if self.used_bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if (value >> bit) & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.used_bits % 8);
+            }
+            self.used_bits += 1;
+        }
+    }
+
+    // Pad to the next byte boundary
+    fn align(&mut self) {
+        self.used_bits = self.bytes.len() as u32 * 8;
+    }
+}
+
+fn bits_needed(value: u64) -> u32 {
+    64 - value.leading_zeros()
+}
+
+#[derive(Default, PartialEq)]
+struct LinearizationParams {
+    file_length: usize,
+    hint_offset: usize,
+    hint_length: usize,
+    first_page_end: usize,
+    xref_offset: usize,
+    hint_data: Vec<u8>,
+    shared_table_offset: usize,
+}
+
+// Build the page offset and shared object hint tables from the object layout of the last pass.
+// Shared objects are attributed to the first page that uses them, so the shared table is empty.
+fn build_hint_tables(page_groups: &[(usize, usize, u32)], first_page_object_offset: usize) -> (Vec<u8>, usize) {
+    let counts: Vec<u64> = page_groups.iter().map(|&(_, _, count)| count as u64).collect();
+    let lengths: Vec<u64> = page_groups
+        .iter()
+        .map(|&(start, end, _)| end.saturating_sub(start) as u64)
+        .collect();
+    let least_count = counts.iter().copied().min().unwrap_or(0);
+    let least_length = lengths.iter().copied().min().unwrap_or(0);
+    let count_bits = bits_needed(counts.iter().map(|c| c - least_count).max().unwrap_or(0));
+    let length_bits = bits_needed(lengths.iter().map(|l| l - least_length).max().unwrap_or(0));
+
+    let mut writer = BitWriter::new();
+    writer.write(least_count, 32);
+    writer.write(first_page_object_offset as u64, 32);
+    writer.write(count_bits as u64, 16);
+    writer.write(least_length, 32);
+    writer.write(length_bits as u64, 16);
+    // Content stream offsets/lengths and shared references are not tracked
+    writer.write(0, 32);
+    writer.write(0, 16);
+    writer.write(0, 32);
+    writer.write(0, 16);
+    writer.write(0, 16);
+    writer.write(0, 16);
+    writer.write(0, 16);
+    writer.write(1, 16);
+
+    for count in &counts {
+        writer.write(count - least_count, count_bits);
+    }
+    for length in &lengths {
+        writer.write(length - least_length, length_bits);
+    }
+    writer.align();
+
+    let shared_table_offset = writer.bytes.len();
+    writer.write(0, 32);
+    writer.write(0, 32);
+    writer.write(0, 32);
+    writer.write(0, 32);
+    writer.write(0, 16);
+    writer.write(0, 32);
+    writer.write(0, 16);
+
+    (writer.bytes, shared_table_offset)
+}
+
+// Write a linearized ("fast web view") copy of the document.
+//
+// Objects are renumbered so the linearization dictionary comes first, followed by the
+// hint stream, the catalog and everything page 1 needs, then each later page's objects.
+// Limitations: a single cross-reference table is written at the end of the file (no
+// first-page xref section), objects shared between pages are stored with the first page
+// that uses them, and the hint tables omit content stream and shared object details.
+// Viewers that reject these simplifications still open the file normally.
+#[tauri::command]
+async fn linearize(state: State<'_, AppState>, path: String, output_path: String) -> Result<(), String> {
+    let doc = snapshot_document(&state, &path)?;
+    if doc.is_encrypted() {
+        // Encrypted strings and streams are keyed by object number, so renumbering would corrupt them
+        return Err("Document is encrypted".to_string());
+    }
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if pages.is_empty() {
+        return Err("Document has no pages".to_string());
+    }
+    let catalog_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| e.to_string())?;
+
+    // Group objects by the first page that needs them; the catalog leads page 1's group
+    let mut stop: std::collections::HashSet<ObjectId> = pages.iter().copied().collect();
+    for (&id, object) in doc.objects.iter() {
+        if is_pages_node(object) {
+            stop.insert(id);
+        }
+    }
+    let mut visited = std::collections::HashSet::new();
+    let mut groups: Vec<Vec<ObjectId>> = Vec::new();
+    for (index, &page) in pages.iter().enumerate() {
+        let mut group = Vec::new();
+        if index == 0 {
+            visited.insert(catalog_id);
+            group.push(catalog_id);
+            for (&id, object) in doc.objects.iter() {
+                if is_pages_node(object) {
+                    visited.insert(id);
+                    group.push(id);
+                }
+            }
+        }
+        let mut page_stop = stop.clone();
+        page_stop.remove(&page);
+        collect_reachable(&doc, page, &page_stop, &mut visited, &mut group);
+        groups.push(group);
+    }
+    let mut remainder: Vec<ObjectId> = Vec::new();
+    for &id in doc.objects.keys() {
+        if !visited.contains(&id) {
+            remainder.push(id);
+        }
+    }
+
+    // Object 1 is the linearization dictionary and object 2 the hint stream
+    let mut map = HashMap::new();
+    let mut next = 3;
+    for &id in groups.iter().flatten().chain(remainder.iter()) {
+        map.insert(id, (next, 0));
+        next += 1;
+    }
+
+    let mut new_doc = Document::with_version(doc.version.as_str());
+    // New documents default to an xref stream, which parse_xref_offsets cannot read back
+    new_doc.reference_table.cross_reference_type = lopdf::xref::XrefType::CrossReferenceTable;
+    for (&id, object) in doc.objects.iter() {
+        let mut object = object.clone();
+        remap_references(&mut object, &map);
+        new_doc.objects.insert(map[&id], object);
+    }
+    new_doc.max_id = next - 1;
+    for key in [&b"Root"[..], &b"Info"[..], &b"ID"[..]] {
+        if let Ok(value) = doc.trailer.get(key) {
+            let mut value = value.clone();
+            remap_references(&mut value, &map);
+            new_doc.trailer.set(key.to_vec(), value);
+        }
+    }
+
+    let first_page_number = map[&pages[0]].0;
+    let group_firsts: Vec<u32> = groups.iter().map(|group| map[&group[0]].0).collect();
+    let group_sizes: Vec<u32> = groups.iter().map(|group| group.len() as u32).collect();
+    let remainder_first = remainder.first().map(|id| map[id].0);
+
+    let mut params = LinearizationParams::default();
+    for _ in 0..10 {
+        let mut lin = lopdf::Dictionary::new();
+        lin.set("Linearized", Object::Integer(1));
+        lin.set("L", Object::Integer(params.file_length as i64));
+        lin.set(
+            "H",
+            Object::Array(vec![
+                Object::Integer(params.hint_offset as i64),
+                Object::Integer(params.hint_length as i64),
+            ]),
+        );
+        lin.set("O", Object::Integer(first_page_number as i64));
+        lin.set("E", Object::Integer(params.first_page_end as i64));
+        lin.set("N", Object::Integer(pages.len() as i64));
+        lin.set("T", Object::Integer(params.xref_offset as i64));
+
+        let mut hint_dict = lopdf::Dictionary::new();
+        hint_dict.set("S", Object::Integer(params.shared_table_offset as i64));
+        new_doc
+            .objects
+            .insert((2, 0), Object::Stream(lopdf::Stream::new(hint_dict, params.hint_data.clone())));
+
+        let mut written = Vec::new();
+        new_doc.save_to(&mut written).map_err(|e| e.to_string())?;
+        let bytes = splice_linearization_dictionary(&written, &lin)
+            .ok_or("Could not read back the written cross-reference table")?;
+        let (offsets, xref_offset) =
+            parse_xref_offsets(&bytes).ok_or("Could not read back the written cross-reference table")?;
+
+        // Each page group runs until the next group (or the remainder / xref) starts
+        let mut page_groups = Vec::new();
+        for (index, &first) in group_firsts.iter().enumerate() {
+            let start = offsets.get(&first).copied().unwrap_or(0);
+            let end = group_firsts
+                .get(index + 1)
+                .copied()
+                .or(remainder_first)
+                .and_then(|number| offsets.get(&number).copied())
+                .unwrap_or(xref_offset);
+            page_groups.push((start, end, group_sizes[index]));
+        }
+
+        let hint_offset = offsets.get(&2).copied().unwrap_or(0);
+        let first_object_after_hint = offsets.get(&3).copied().unwrap_or(xref_offset);
+        let (hint_data, shared_table_offset) =
+            build_hint_tables(&page_groups, offsets.get(&first_page_number).copied().unwrap_or(0));
+
+        let computed = LinearizationParams {
+            file_length: bytes.len(),
+            hint_offset,
+            hint_length: first_object_after_hint - hint_offset,
+            first_page_end: page_groups[0].1,
+            xref_offset,
+            hint_data,
+            shared_table_offset,
+        };
+
+        if computed == params {
+            return write_atomically(&output_path, |file| file.write_all(&bytes).map_err(|e| e.to_string()));
+        }
+        params = computed;
+    }
+
+    Err("Linearization layout did not stabilize".to_string())
+}
+
+// Replace a page's /Contents with a single deflated stream
+fn set_page_content(doc: &mut Document, page_id: ObjectId, bytes: Vec<u8>) -> Result<(), String> {
+    let mut stream = lopdf::Stream::new(lopdf::Dictionary::new(), bytes);
+    stream.compress().map_err(|e| e.to_string())?;
+    let content_id = doc.add_object(stream);
+
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    page.set("Contents", Object::Reference(content_id));
+    Ok(())
+}
+
+// Fonts whose string bytes map one-to-one onto Latin-1 style characters
+fn is_simple_encoded_font(doc: &Document, font: &lopdf::Dictionary) -> bool {
+    let subtype = font.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+    if subtype == b"Type0" || subtype == b"Type3" {
+        return false;
+    }
+    match font.get(b"Encoding").ok().and_then(|e| doc.dereference(e).ok()) {
+        None => true,
+        Some((_, Object::Name(name))) => {
+            matches!(name.as_slice(), b"WinAnsiEncoding" | b"StandardEncoding" | b"MacRomanEncoding")
+        }
+        // Custom /Differences may remap the characters we would be matching
+        Some((_, Object::Dictionary(encoding))) => !encoding.has(b"Differences"),
+        Some(_) => false,
+    }
+}
+
+fn encode_latin1(text: &str) -> Option<Vec<u8>> {
+    text.chars()
+        .map(|c| if (c as u32) <= 0xFF { Some(c as u8) } else { None })
+        .collect()
+}
+
+fn replace_bytes(haystack: &[u8], needle: &[u8], replacement: &[u8]) -> (Vec<u8>, usize) {
+    let mut result = Vec::with_capacity(haystack.len());
+    let mut count = 0;
+    let mut i = 0;
+    while i < haystack.len() {
+        if !needle.is_empty() && haystack[i..].starts_with(needle) {
+            result.extend_from_slice(replacement);
+            i += needle.len();
+            count += 1;
+        } else {
+            result.push(haystack[i]);
+            i += 1;
+        }
+    }
+    (result, count)
+}
+
+fn count_bytes(haystack: &[u8], needle: &[u8]) -> usize {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return 0;
+    }
+    haystack.windows(needle.len()).filter(|window| *window == needle).count()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextReplacement {
+    replaced: usize,
+    // Matches left alone: split across TJ elements or in fonts with complex encodings
+    skipped: usize,
+}
+
+// Replace text inside individual Tj/TJ/'/" string operands, returning how many matches
+// were replaced and how many were skipped.
+//
+// Only fonts with a simple single-byte encoding (no Type0/CID fonts, no custom
+// /Differences) are edited, and a match must sit inside one string operand. Matches
+// that span TJ elements or live in complex fonts are left alone and counted as skipped.
+#[tauri::command]
+async fn replace_text(
+    state: State<'_, AppState>,
+    path: String,
+    find: String,
+    replace: String,
+    pages: Option<Vec<usize>>,
+) -> Result<TextReplacement, String> {
+    if find.is_empty() {
+        return Err("Search text must not be empty".to_string());
+    }
+    let find_bytes = encode_latin1(&find).ok_or("Search text contains characters outside Latin-1")?;
+    let replace_bytes_encoded =
+        encode_latin1(&replace).ok_or("Replacement text contains characters outside Latin-1")?;
+
+    edit_document(&state, &path, |doc| {
+        let targets: Vec<(u32, ObjectId)> = doc
+            .get_pages()
+            .into_iter()
+            .filter(|(number, _)| pages.as_ref().map_or(true, |p| p.contains(&(*number as usize))))
+            .collect();
+        let mut replaced = 0;
+        let mut skipped = 0;
+
+        for (_, page_id) in targets {
+            let fonts = page_resources(doc, page_id).and_then(|r| resource_category(doc, r, b"Font"));
+            let simple_fonts: Vec<Vec<u8>> = fonts
+                .map(|fonts| {
+                    fonts
+                        .iter()
+                        .filter(|(_, font)| {
+                            doc.dereference(font)
+                                .ok()
+                                .and_then(|(_, font)| font.as_dict().ok())
+                                .map_or(false, |font| is_simple_encoded_font(doc, font))
+                        })
+                        .map(|(name, _)| name.clone())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let raw = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+            let mut content = lopdf::content::Content::decode(&raw).map_err(|e| e.to_string())?;
+            let mut current_font_simple = false;
+            // The font is part of the graphics state, so q/Q save and restore it
+            let mut font_stack = Vec::new();
+            let mut page_replaced = 0;
+
+            for operation in content.operations.iter_mut() {
+                match operation.operator.as_str() {
+                    "q" => font_stack.push(current_font_simple),
+                    "Q" => current_font_simple = font_stack.pop().unwrap_or(false),
+                    "Tf" => {
+                        current_font_simple = operation
+                            .operands
+                            .first()
+                            .and_then(|name| name.as_name().ok())
+                            .map_or(false, |name| simple_fonts.iter().any(|font| font == name));
+                    }
+                    "Tj" | "'" | "\"" | "TJ" => {
+                        for operand in operation.operands.iter_mut() {
+                            match operand {
+                                Object::String(bytes, _) => {
+                                    if current_font_simple {
+                                        let (updated, count) =
+                                            replace_bytes(bytes, &find_bytes, &replace_bytes_encoded);
+                                        *bytes = updated;
+                                        page_replaced += count;
+                                    } else {
+                                        skipped += count_bytes(bytes, &find_bytes);
+                                    }
+                                }
+                                Object::Array(items) => {
+                                    let joined: Vec<u8> = items
+                                        .iter()
+                                        .filter_map(|item| item.as_str().ok())
+                                        .flatten()
+                                        .copied()
+                                        .collect();
+                                    let mut inside = 0;
+                                    for item in items.iter_mut() {
+                                        if let Object::String(bytes, _) = item {
+                                            if current_font_simple {
+                                                let (updated, count) =
+                                                    replace_bytes(bytes, &find_bytes, &replace_bytes_encoded);
+                                                *bytes = updated;
+                                                inside += count;
+                                            } else {
+                                                inside += count_bytes(bytes, &find_bytes);
+                                            }
+                                        }
+                                    }
+                                    // Anything found only in the joined run spans elements
+                                    let spanning = count_bytes(&joined, &find_bytes).saturating_sub(inside);
+                                    skipped += spanning;
+                                    if current_font_simple {
+                                        page_replaced += inside;
+                                    } else {
+                                        skipped += inside;
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if page_replaced > 0 {
+                let bytes = content.encode().map_err(|e| e.to_string())?;
+                set_page_content(doc, page_id, bytes)?;
+                replaced += page_replaced;
+            }
+        }
+
+        Ok(TextReplacement { replaced, skipped })
+    })
+}
+
+fn annotation_subtype(doc: &Document, annot: &Object) -> Option<Vec<u8>> {
+    let (_, annot) = doc.dereference(annot).ok()?;
+    annot
+        .as_dict()
+        .ok()?
+        .get(b"Subtype")
+        .and_then(Object::as_name)
+        .ok()
+        .map(|name| name.to_vec())
+}
+
+fn page_annotations(doc: &Document, page_id: ObjectId) -> Vec<Object> {
+    doc.get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .and_then(|annots| doc.dereference(annots).ok())
+        .and_then(|(_, annots)| annots.as_array().ok())
+        .cloned()
+        .unwrap_or_default()
+}
+
+// Remove annotations from every page, optionally only those of the given subtypes.
+// Widget annotations belong to form fields and are only removed when listed explicitly.
+#[tauri::command]
+async fn remove_all_annotations(
+    state: State<'_, AppState>,
+    path: String,
+    subtypes: Option<Vec<String>>,
+) -> Result<usize, String> {
+    edit_document(&state, &path, |doc| {
+        let should_remove = |subtype: &Option<Vec<u8>>| match (&subtypes, subtype) {
+            (Some(filter), Some(subtype)) => filter.iter().any(|s| s.as_bytes() == subtype.as_slice()),
+            (Some(_), None) => false,
+            (None, Some(subtype)) => subtype != b"Widget",
+            (None, None) => true,
+        };
+
+        let mut removed_ids = Vec::new();
+        let mut removed = 0;
+        for page_id in doc.get_pages().into_values() {
+            let annots = page_annotations(doc, page_id);
+            if annots.is_empty() {
+                continue;
+            }
+
+            let mut retained = Vec::new();
+            for annot in annots {
+                if should_remove(&annotation_subtype(doc, &annot)) {
+                    if let Object::Reference(id) = annot {
+                        removed_ids.push(id);
+                        // Popups hang off the annotation they belong to
+                        if let Ok(popup) = doc
+                            .get_dictionary(id)
+                            .and_then(|dict| dict.get(b"Popup"))
+                            .and_then(Object::as_reference)
+                        {
+                            removed_ids.push(popup);
+                        }
+                    }
+                    removed += 1;
+                } else {
+                    retained.push(annot);
+                }
+            }
+
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            if retained.is_empty() {
+                page.remove(b"Annots");
+            } else {
+                page.set("Annots", Object::Array(retained));
+            }
+        }
+
+        // Popups whose parent stays are kept on the page, so only drop unreferenced ones
+        let still_listed: Vec<ObjectId> = doc
+            .get_pages()
+            .into_values()
+            .flat_map(|page_id| page_annotations(doc, page_id))
+            .filter_map(|annot| annot.as_reference().ok())
+            .collect();
+        removed_ids.retain(|id| !still_listed.contains(id));
+
+        // Removed widgets must not stay listed as form fields
+        if !removed_ids.is_empty() {
+            prune_form_fields(doc, &removed_ids)?;
+        }
+
+        for id in removed_ids {
+            doc.objects.remove(&id);
+        }
+        // Appearance streams and fields that only the removed annotations used
+        doc.prune_objects();
+        Ok(removed)
+    })
+}
+
+// Drop removed widgets from the AcroForm field tree, whether /AcroForm is a reference or
+// inline in the catalog
+fn prune_form_fields(doc: &mut Document, removed: &[ObjectId]) -> Result<(), String> {
+    let acroform_id = match doc.catalog().and_then(|catalog| catalog.get(b"AcroForm")) {
+        Ok(Object::Reference(id)) => Some(*id),
+        Ok(_) => None,
+        Err(_) => return Ok(()),
+    };
+    let fields = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| resolve_object(doc, fields))
+        .and_then(|fields| fields.as_array().ok())
+        .cloned();
+    let fields = match fields {
+        Some(fields) => prune_field_kids(doc, fields, removed, 0),
+        None => return Ok(()),
+    };
+
+    let acroform = match acroform_id {
+        Some(id) => doc.get_dictionary_mut(id),
+        None => doc.catalog_mut().and_then(|catalog| catalog.get_mut(b"AcroForm")).and_then(Object::as_dict_mut),
+    };
+    acroform.map_err(|e| e.to_string())?.set("Fields", Object::Array(fields));
+    Ok(())
+}
+
+// Fields whose widgets were all removed go as well, so no field is left without a widget
+fn prune_field_kids(doc: &mut Document, fields: Vec<Object>, removed: &[ObjectId], depth: usize) -> Vec<Object> {
+    let mut kept = Vec::new();
+    for field in fields {
+        let id = match field {
+            Object::Reference(id) => id,
+            other => {
+                kept.push(other);
+                continue;
+            }
+        };
+        if removed.contains(&id) {
+            continue;
+        }
+        let kids = doc
+            .get_dictionary(id)
+            .and_then(|field| field.get(b"Kids"))
+            .and_then(Object::as_array)
+            .cloned();
+        if let Ok(kids) = kids {
+            if !kids.is_empty() && depth < 64 {
+                let kids = prune_field_kids(doc, kids, removed, depth + 1);
+                if kids.is_empty() {
+                    continue;
+                }
+                if let Ok(field) = doc.get_dictionary_mut(id) {
+                    field.set("Kids", Object::Array(kids));
+                }
+            }
+        }
+        kept.push(field);
+    }
+    kept
+}
+
+enum ColorSpaceKind {
+    Gray,
+    Rgb,
+    Cmyk,
+    Indexed { base: Box<ColorSpaceKind>, lookup: Vec<u8> },
+    Unknown,
+}
+
+impl ColorSpaceKind {
+    fn components(&self) -> usize {
+        match self {
+            ColorSpaceKind::Gray | ColorSpaceKind::Indexed { .. } => 1,
+            ColorSpaceKind::Rgb => 3,
+            ColorSpaceKind::Cmyk => 4,
+            ColorSpaceKind::Unknown => 0,
+        }
+    }
+}
+
+fn resolve_color_space(doc: &Document, color_space: &Object) -> ColorSpaceKind {
+    let color_space = match doc.dereference(color_space) {
+        Ok((_, object)) => object,
+        Err(_) => return ColorSpaceKind::Unknown,
+    };
+
+    match color_space {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" | b"G" => ColorSpaceKind::Gray,
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => ColorSpaceKind::Rgb,
+            b"DeviceCMYK" | b"CMYK" => ColorSpaceKind::Cmyk,
+            _ => ColorSpaceKind::Unknown,
+        },
+        Object::Array(items) => {
+            let family = items.first().and_then(|f| f.as_name().ok()).unwrap_or(b"");
+            match family {
+                b"ICCBased" => {
+                    let components = items
+                        .get(1)
+                        .and_then(|profile| doc.dereference(profile).ok())
+                        .and_then(|(_, profile)| profile.as_stream().ok())
+                        .and_then(|profile| profile.dict.get(b"N").and_then(Object::as_i64).ok());
+                    match components {
+                        Some(1) => ColorSpaceKind::Gray,
+                        Some(3) => ColorSpaceKind::Rgb,
+                        Some(4) => ColorSpaceKind::Cmyk,
+                        _ => ColorSpaceKind::Unknown,
+                    }
+                }
+                b"CalGray" => ColorSpaceKind::Gray,
+                b"CalRGB" | b"Lab" => ColorSpaceKind::Rgb,
+                b"Indexed" | b"I" => {
+                    let base = items
+                        .get(1)
+                        .map(|base| resolve_color_space(doc, base))
+                        .unwrap_or(ColorSpaceKind::Unknown);
+                    let lookup = items
+                        .get(3)
+                        .and_then(|lookup| doc.dereference(lookup).ok())
+                        .and_then(|(_, lookup)| match lookup {
+                            Object::String(bytes, _) => Some(bytes.clone()),
+                            Object::Stream(stream) => stream
+                                .decompressed_content()
+                                .ok()
+                                .or_else(|| Some(stream.content.clone())),
+                            _ => None,
+                        })
+                        .unwrap_or_default();
+                    ColorSpaceKind::Indexed {
+                        base: Box::new(base),
+                        lookup,
+                    }
+                }
+                _ => ColorSpaceKind::Unknown,
+            }
+        }
+        _ => ColorSpaceKind::Unknown,
+    }
+}
+
+fn cmyk_to_rgb(c: u8, m: u8, y: u8, k: u8) -> [u8; 3] {
+    let k = 255 - k as u32;
+    [
+        ((255 - c as u32) * k / 255) as u8,
+        ((255 - m as u32) * k / 255) as u8,
+        ((255 - y as u32) * k / 255) as u8,
+    ]
+}
+
+// A stream's data with its Flate/LZW filters undone. lopdf refuses to decompress
+// streams with /Subtype /Image, so images are decoded from a copy without it.
+fn decoded_stream_data(stream: &lopdf::Stream) -> lopdf::Result<Vec<u8>> {
+    if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(&b"Image"[..]) {
+        return stream.decompressed_content();
+    }
+    let mut plain = stream.clone();
+    plain.dict.remove(b"Subtype");
+    plain.decompressed_content()
+}
+
+fn stream_filters(stream: &lopdf::Stream) -> Vec<Vec<u8>> {
+    match stream.dict.get(b"Filter") {
+        Ok(Object::Name(name)) => vec![name.clone()],
+        Ok(Object::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_name().ok())
+            .map(|name| name.to_vec())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Decode an image XObject into RGB pixels, applying its /Decode ranges.
+// Supports 8-bit gray/RGB/CMYK/indexed samples (plus 1-bit gray) with Flate or no
+// compression, and DCT (JPEG) images. Other encodings return an error.
+fn decode_image_xobject(doc: &Document, stream: &lopdf::Stream) -> Result<image::RgbImage, String> {
+    let filters = stream_filters(stream);
+    // /Decode ranges per component; a reversed range ([1 0]) inverts that component
+    let decode: Vec<(f64, f64)> = stream
+        .dict
+        .get(b"Decode")
+        .ok()
+        .and_then(|decode| resolve_object(doc, decode))
+        .and_then(|decode| decode.as_array().ok())
+        .map(|decode| operand_numbers(decode).chunks_exact(2).map(|r| (r[0], r[1])).collect())
+        .unwrap_or_default();
+    let inverted = !decode.is_empty() && decode.iter().all(|&(low, high)| low > high);
+
+    if filters.iter().any(|f| f == b"DCTDecode" || f == b"DCT") {
+        let image = image::load_from_memory_with_format(&stream.content, image::ImageFormat::Jpeg)
+            .map_err(|e| e.to_string())?;
+        let mut image = image.to_rgb8();
+        if inverted {
+            image::imageops::invert(&mut image);
+        }
+        return Ok(image);
+    }
+    if filters
+        .iter()
+        .any(|f| !matches!(f.as_slice(), b"FlateDecode" | b"Fl" | b"LZWDecode" | b"LZW"))
+    {
+        return Err("Unsupported image filter".to_string());
+    }
+
+    let width = stream.dict.get(b"Width").and_then(Object::as_i64).map_err(|e| e.to_string())? as u32;
+    let height = stream.dict.get(b"Height").and_then(Object::as_i64).map_err(|e| e.to_string())? as u32;
+    let is_mask = stream.dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+    let bits = if is_mask {
+        1
+    } else {
+        stream.dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8)
+    };
+    let color_space = if is_mask {
+        ColorSpaceKind::Gray
+    } else {
+        stream
+            .dict
+            .get(b"ColorSpace")
+            .map(|cs| resolve_color_space(doc, cs))
+            .unwrap_or(ColorSpaceKind::Unknown)
+    };
+    let data = if filters.is_empty() {
+        stream.content.clone()
+    } else {
+        decoded_stream_data(stream).map_err(|e| e.to_string())?
+    };
+
+    let mut image = image::RgbImage::new(width, height);
+    match (bits, &color_space) {
+        (1, ColorSpaceKind::Gray) => {
+            let row_bytes = (width as usize).div_ceil(8);
+            for y in 0..height {
+                for x in 0..width {
+                    let byte = data.get(y as usize * row_bytes + x as usize / 8).copied().unwrap_or(0);
+                    let set = ((byte >> (7 - (x % 8))) & 1 == 1) != inverted;
+                    // Stencil masks paint where the bit is 0 (by default)
+                    let value = if set != is_mask { 255 } else { 0 };
+                    image.put_pixel(x, y, image::Rgb([value, value, value]));
+                }
+            }
+        }
+        (8, _) => {
+            let components = color_space.components();
+            // Indexed /Decode ranges remap palette indices, which callers don't use
+            let is_indexed = matches!(color_space, ColorSpaceKind::Indexed { .. });
+            if components == 0 {
+                return Err("Unsupported image color space".to_string());
+            }
+            for y in 0..height {
+                for x in 0..width {
+                    let offset = (y as usize * width as usize + x as usize) * components;
+                    let raw = |i: usize| data.get(offset + i).copied().unwrap_or(0);
+                    let sample = |i: usize| match decode.get(i) {
+                        Some(&(low, high)) if !is_indexed => {
+                            ((low + raw(i) as f64 / 255.0 * (high - low)) * 255.0).round().clamp(0.0, 255.0) as u8
+                        }
+                        _ => raw(i),
+                    };
+                    let rgb = match &color_space {
+                        ColorSpaceKind::Gray => [sample(0); 3],
+                        ColorSpaceKind::Rgb => [sample(0), sample(1), sample(2)],
+                        ColorSpaceKind::Cmyk => cmyk_to_rgb(sample(0), sample(1), sample(2), sample(3)),
+                        ColorSpaceKind::Indexed { base, lookup } => {
+                            let n = base.components().max(1);
+                            let index = sample(0) as usize * n;
+                            let entry = |i: usize| lookup.get(index + i).copied().unwrap_or(0);
+                            match base.as_ref() {
+                                ColorSpaceKind::Gray => [entry(0); 3],
+                                ColorSpaceKind::Cmyk => cmyk_to_rgb(entry(0), entry(1), entry(2), entry(3)),
+                                _ => [entry(0), entry(1), entry(2)],
+                            }
+                        }
+                        ColorSpaceKind::Unknown => [0, 0, 0],
+                    };
+                    image.put_pixel(x, y, image::Rgb(rgb));
+                }
+            }
+        }
+        _ => return Err(format!("Unsupported bits per component: {}", bits)),
+    }
+
+    Ok(image)
+}
+
+// Build a Flate-compressed DeviceRGB image XObject, keeping the original's extra entries
+fn rgb_image_stream(original: &lopdf::Dictionary, image: &image::RgbImage) -> Result<lopdf::Stream, String> {
+    let mut stream = lopdf::Stream::new(rgb_image_dict(original, image), image.as_raw().clone());
+    stream.compress().map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// Same as rgb_image_stream, but JPEG-encoded at the given quality
+fn jpeg_image_stream(original: &lopdf::Dictionary, image: &image::RgbImage, quality: u8) -> Result<lopdf::Stream, String> {
+    let mut bytes = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality)
+        .encode_image(image)
+        .map_err(|e| e.to_string())?;
+
+    let mut dict = rgb_image_dict(original, image);
+    dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+    let mut stream = lopdf::Stream::new(dict, bytes);
+    stream.allows_compression = false;
+    Ok(stream)
+}
+
+fn rgb_image_dict(original: &lopdf::Dictionary, image: &image::RgbImage) -> lopdf::Dictionary {
+    let mut dict = original.clone();
+    for key in [&b"Filter"[..], &b"DecodeParms"[..], &b"Decode"[..], &b"Length"[..], &b"ImageMask"[..]] {
+        dict.remove(key);
+    }
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Image".to_vec()));
+    dict.set("Width", Object::Integer(image.width() as i64));
+    dict.set("Height", Object::Integer(image.height() as i64));
+    dict.set("ColorSpace", Object::Name(b"DeviceRGB".to_vec()));
+    dict.set("BitsPerComponent", Object::Integer(8));
+    dict
+}
+
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+// Quality used when re-encoding images as JPEG, clamped to 1-100
+fn clamp_jpeg_quality(quality: Option<u8>) -> u8 {
+    quality.unwrap_or(DEFAULT_JPEG_QUALITY).clamp(1, 100)
+}
+
+fn is_jpeg_stream(stream: &lopdf::Stream) -> bool {
+    stream_filters(stream).iter().any(|filter| filter == b"DCTDecode")
+}
+
+// Output filter for re-encoded images. Both are read by every viewer. JPEG 2000
+// (JPXDecode) is not offered, as no encoder is bundled.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum ImageCodec {
+    // DCTDecode, lossy at the given quality
+    Jpeg,
+    // FlateDecode over the raw pixels
+    FlateLossless,
+}
+
+fn encode_image_stream(
+    original: &lopdf::Dictionary,
+    image: &image::RgbImage,
+    codec: ImageCodec,
+    quality: u8,
+) -> Result<lopdf::Stream, String> {
+    match codec {
+        ImageCodec::Jpeg => jpeg_image_stream(original, image, quality),
+        ImageCodec::FlateLossless => rgb_image_stream(original, image),
+    }
+}
+
+// Re-encode a decoded image with the given codec. Without one, JPEG sources stay JPEG
+// and everything else is lossless.
+fn reencode_image(
+    original: &lopdf::Stream,
+    image: &image::RgbImage,
+    quality: u8,
+    codec: Option<ImageCodec>,
+) -> Result<lopdf::Stream, String> {
+    match codec {
+        Some(codec) => encode_image_stream(&original.dict, image, codec, quality),
+        None if is_jpeg_stream(original) => jpeg_image_stream(&original.dict, image, quality),
+        None => rgb_image_stream(&original.dict, image),
+    }
+}
+
+// Clone of the document as the editor currently sees it (cached edits included)
+fn snapshot_document(state: &AppState, path: &str) -> Result<Document, String> {
+    with_document(state, path, |doc| Ok(doc.clone()))
+}
+
+// Names in a resource dictionary's /ColorSpace category that resolve to CMYK
+fn cmyk_color_space_names(doc: &Document, resources: Option<&lopdf::Dictionary>) -> Vec<Vec<u8>> {
+    resources
+        .and_then(|resources| resource_category(doc, resources, b"ColorSpace"))
+        .map(|spaces| {
+            spaces
+                .iter()
+                .filter(|(_, space)| matches!(resolve_color_space(doc, space), ColorSpaceKind::Cmyk))
+                .map(|(name, _)| name.clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Rewrite k/K, and cs/CS selecting a CMYK space with the sc/scn colors that follow, to RGB
+fn convert_cmyk_operators(content: &mut lopdf::content::Content, cmyk_spaces: &[Vec<u8>]) -> usize {
+    let mut converted = 0;
+    // Whether the fill and stroke color spaces are CMYK; part of the graphics state
+    let mut cmyk = (false, false);
+    let mut stack = Vec::new();
+    for operation in content.operations.iter_mut() {
+        let operator = match operation.operator.as_str() {
+            "q" => {
+                stack.push(cmyk);
+                continue;
+            }
+            "Q" => {
+                cmyk = stack.pop().unwrap_or((false, false));
+                continue;
+            }
+            "cs" | "CS" => {
+                let is_cmyk = operation.operands.first().and_then(|name| name.as_name().ok()).is_some_and(|name| {
+                    name == b"DeviceCMYK" || cmyk_spaces.iter().any(|space| space.as_slice() == name)
+                });
+                if operation.operator == "cs" {
+                    cmyk.0 = is_cmyk;
+                } else {
+                    cmyk.1 = is_cmyk;
+                }
+                if is_cmyk {
+                    // The initial CMYK color is black, as is DeviceRGB's
+                    operation.operands = vec![Object::Name(b"DeviceRGB".to_vec())];
+                    converted += 1;
+                }
+                continue;
+            }
+            "g" | "rg" => {
+                cmyk.0 = false;
+                continue;
+            }
+            "G" | "RG" => {
+                cmyk.1 = false;
+                continue;
+            }
+            "k" => {
+                cmyk.0 = false;
+                "rg"
+            }
+            "K" => {
+                cmyk.1 = false;
+                "RG"
+            }
+            "sc" | "scn" if cmyk.0 => "sc",
+            "SC" | "SCN" if cmyk.1 => "SC",
+            _ => continue,
+        };
+        let values: Vec<f32> = operation
+            .operands
+            .iter()
+            .filter_map(|operand| operand.as_float().ok())
+            .collect();
+        if values.len() != 4 {
+            continue;
+        }
+        let k = 1.0 - values[3];
+        operation.operator = operator.to_string();
+        operation.operands = vec![
+            Object::Real((1.0 - values[0]) * k),
+            Object::Real((1.0 - values[1]) * k),
+            Object::Real((1.0 - values[2]) * k),
+        ];
+        converted += 1;
+    }
+    converted
+}
+
+#[tauri::command]
+async fn convert_to_rgb(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    jpeg_quality: Option<u8>,
+    codec: Option<ImageCodec>,
+) -> Result<(), String> {
+    let quality = clamp_jpeg_quality(jpeg_quality);
+    let mut doc = snapshot_document(&state, &path)?;
+
+    // Convert CMYK images to RGB
+    let image_ids: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| is_image_xobject(object))
+        .map(|(&id, _)| id)
+        .collect();
+    for id in image_ids {
+        let stream = match doc.get_object(id) {
+            Ok(Object::Stream(stream)) => stream,
+            _ => continue,
+        };
+        let is_cmyk = stream
+            .dict
+            .get(b"ColorSpace")
+            .map(|cs| matches!(resolve_color_space(&doc, cs), ColorSpaceKind::Cmyk))
+            .unwrap_or(false);
+        if !is_cmyk {
+            continue;
+        }
+        let image = decode_image_xobject(&doc, stream)?;
+        let converted = reencode_image(stream, &image, quality, codec)?;
+        doc.objects.insert(id, Object::Stream(converted));
+    }
+
+    // Convert CMYK color operators on every page
+    for page_id in doc.get_pages().into_values() {
+        let cmyk_spaces = cmyk_color_space_names(&doc, page_resources(&doc, page_id));
+        let raw = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+        let mut content = lopdf::content::Content::decode(&raw).map_err(|e| e.to_string())?;
+        if convert_cmyk_operators(&mut content, &cmyk_spaces) > 0 {
+            let bytes = content.encode().map_err(|e| e.to_string())?;
+            set_page_content(&mut doc, page_id, bytes)?;
+        }
+    }
+
+    // Form XObjects draw with their own content streams
+    let forms: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| match object {
+            Object::Stream(stream) => stream.dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Form"),
+            _ => false,
+        })
+        .map(|(&id, _)| id)
+        .collect();
+    for form_id in forms {
+        let (raw, cmyk_spaces) = match doc.get_object(form_id) {
+            Ok(Object::Stream(form)) => {
+                let resources =
+                    form.dict.get(b"Resources").ok().and_then(|r| resolve_object(&doc, r)).and_then(|r| r.as_dict().ok());
+                (form.decompressed_content().unwrap_or_else(|_| form.content.clone()), cmyk_color_space_names(&doc, resources))
+            }
+            _ => continue,
+        };
+        let mut content = lopdf::content::Content::decode(&raw).map_err(|e| e.to_string())?;
+        if convert_cmyk_operators(&mut content, &cmyk_spaces) == 0 {
+            continue;
+        }
+        let bytes = content.encode().map_err(|e| e.to_string())?;
+        if let Ok(Object::Stream(form)) = doc.get_object_mut(form_id) {
+            form.dict.remove(b"Filter");
+            form.dict.remove(b"DecodeParms");
+            form.set_content(bytes);
+            let _ = form.compress();
+        }
+    }
+
+    // Output intents would tell viewers to treat the result as CMYK
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.remove(b"OutputIntents");
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PageTextStats {
+    page_number: usize,
+    char_count: usize,
+    word_count: usize,
+    language: Option<String>,
+}
+
+// TJ adjustment (thousandths of an em) beyond which a backward kern reads as a word gap
+const TJ_WORD_GAP: f32 = 200.0;
+
+// Fraction of the font size between two runs on a line that separates words; closer
+// runs are parts of one word kerned or positioned glyph by glyph
+const WORD_GAP_RATIO: f64 = 0.15;
+
+// Page text rebuilt from positioned runs: lines top to bottom, runs left to right, with a
+// space only where the runs are visibly apart. Falls back to lopdf's extraction when no
+// runs could be decoded (for example fonts missing from the resources).
+fn extract_page_text(doc: &Document, page_num: usize) -> Result<String, String> {
+    let spans = page_text_spans(doc, page_id(doc, page_num)?)?;
+    if spans.is_empty() {
+        return doc.extract_text(&[page_num as u32]).map_err(|e| e.to_string());
+    }
+    let mut text = String::new();
+    for mut line in group_into_lines(spans) {
+        line.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+        let mut end: Option<f64> = None;
+        for span in line {
+            if let Some(end) = end {
+                let gap = span.x - end;
+                let separated = text.ends_with(char::is_whitespace) || span.text.starts_with(char::is_whitespace);
+                if gap > span.font_size * WORD_GAP_RATIO && !separated {
+                    text.push(' ');
+                }
+            }
+            end = Some(end.map_or(span.x + span.width, |end: f64| end.max(span.x + span.width)));
+            text.push_str(&span.text);
+        }
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+#[cfg(feature = "language-detection")]
+fn detect_language(text: &str) -> Option<String> {
+    let info = whatlang::detect(text)?;
+    if !info.is_reliable() {
+        return None;
+    }
+    // Report ISO 639-1 codes for the common languages, ISO 639-3 otherwise
+    let code = match info.lang().code() {
+        "eng" => "en",
+        "fra" => "fr",
+        "deu" => "de",
+        "spa" => "es",
+        "ita" => "it",
+        "por" => "pt",
+        "nld" => "nl",
+        "rus" => "ru",
+        "pol" => "pl",
+        "swe" => "sv",
+        "jpn" => "ja",
+        "cmn" => "zh",
+        "kor" => "ko",
+        "ara" => "ar",
+        other => other,
+    };
+    Some(code.to_string())
+}
+
+#[cfg(not(feature = "language-detection"))]
+fn detect_language(_text: &str) -> Option<String> {
+    None
+}
+
+#[tauri::command]
+async fn analyze_text(state: State<'_, AppState>, path: String) -> Result<Vec<PageTextStats>, String> {
+    with_document(&state, &path, |doc| {
+        let mut stats = Vec::new();
+        for page_number in doc.get_pages().into_keys() {
+            let text = extract_page_text(doc, page_number as usize)?;
+            let char_count = text.chars().filter(|c| !c.is_whitespace()).count();
+            let word_count = text.split_whitespace().count();
+            let language = if word_count > 0 { detect_language(&text) } else { None };
+
+            stats.push(PageTextStats {
+                page_number: page_number as usize,
+                char_count,
+                word_count,
+                language,
+            });
+        }
+        Ok(stats)
+    })
+}
+
+// Create an empty page object; the caller links it into the page tree
+fn create_blank_page(doc: &mut Document, width: f64, height: f64) -> ObjectId {
+    let content_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), Vec::new()));
+    let mut page = lopdf::Dictionary::new();
+    page.set("Type", Object::Name(b"Page".to_vec()));
+    page.set(
+        "MediaBox",
+        Object::Array(vec![
+            Object::Integer(0),
+            Object::Integer(0),
+            Object::Real(width as f32),
+            Object::Real(height as f32),
+        ]),
+    );
+    page.set("Resources", Object::Dictionary(lopdf::Dictionary::new()));
+    page.set("Contents", Object::Reference(content_id));
+    doc.add_object(page)
+}
+
+// Order pages for printing both sides on a single-sided printer: all odd pages first,
+// then the even pages (reversed when the stack is flipped face up). An odd page count
+// gets a trailing blank so the last sheet has a back.
+#[tauri::command]
+async fn reorder_for_simplex_duplex(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    reinsert_reversed: bool,
+) -> Result<(), String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if pages.is_empty() {
+        return Err("Document has no pages".to_string());
+    }
+
+    let mut odd: Vec<ObjectId> = pages.iter().copied().step_by(2).collect();
+    let mut even: Vec<ObjectId> = pages.iter().copied().skip(1).step_by(2).collect();
+    if pages.len() % 2 == 1 {
+        let (width, height) = page_size_in_units(&doc, pages.len(), default_size)?;
+        even.push(create_blank_page(&mut doc, width, height));
+    }
+    if reinsert_reversed {
+        even.reverse();
+    }
+
+    odd.extend(even);
+    apply_page_order(&mut doc, &odd)?;
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TextSpan {
+    text: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    font_size: f64,
+    font_name: String,
+}
+
+type Matrix = [f64; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// Multiply two PDF matrices (row-vector convention, so `a` is applied first)
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+fn transform_point(m: &Matrix, x: f64, y: f64) -> (f64, f64) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+fn operand_numbers(operands: &[Object]) -> Vec<f64> {
+    operands
+        .iter()
+        .filter_map(|operand| operand.as_float().ok())
+        .map(|value| value as f64)
+        .collect()
+}
+
+// What the text tracker needs to know about a font
+struct FontInfo {
+    two_byte: bool,
+    first_char: u32,
+    widths: Vec<f64>,
+    cid_widths: HashMap<u32, f64>,
+    default_width: f64,
+    to_unicode: HashMap<u32, String>,
+    embedded: bool,
+    base_font: String,
+}
+
+impl FontInfo {
+    fn width(&self, code: u32) -> f64 {
+        if self.two_byte {
+            return self.cid_widths.get(&code).copied().unwrap_or(self.default_width);
+        }
+        code.checked_sub(self.first_char)
+            .and_then(|index| self.widths.get(index as usize))
+            .copied()
+            .unwrap_or(self.default_width)
+    }
+
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_byte {
+            bytes
+                .chunks(2)
+                .map(|pair| ((pair[0] as u32) << 8) | pair.get(1).copied().unwrap_or(0) as u32)
+                .collect()
+        } else {
+            bytes.iter().map(|&b| b as u32).collect()
+        }
+    }
+
+    fn decode(&self, code: u32) -> String {
+        if let Some(text) = self.to_unicode.get(&code) {
+            return text.clone();
+        }
+        if self.two_byte {
+            return char::from_u32(code).map(String::from).unwrap_or_default();
+        }
+        (code as u8 as char).to_string()
+    }
+}
+
+fn parse_hex_code(token: &str) -> Option<u32> {
+    let hex = token.trim_start_matches('<').trim_end_matches('>');
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn parse_hex_unicode(token: &str) -> String {
+    let hex = token.trim_start_matches('<').trim_end_matches('>');
+    let units: Vec<u16> = (0..hex.len() / 4)
+        .filter_map(|i| u16::from_str_radix(&hex[i * 4..i * 4 + 4], 16).ok())
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+// Parse the bfchar/bfrange sections of a /ToUnicode CMap
+fn parse_to_unicode(cmap: &[u8]) -> HashMap<u32, String> {
+    let text = String::from_utf8_lossy(cmap);
+    let tokens: Vec<String> = text
+        .replace('<', " <")
+        .replace('>', "> ")
+        .replace('[', " [ ")
+        .replace(']', " ] ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    let mut map = HashMap::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "beginbfchar" => {
+                i += 1;
+                while i + 1 < tokens.len() && tokens[i] != "endbfchar" {
+                    if let Some(code) = parse_hex_code(&tokens[i]) {
+                        map.insert(code, parse_hex_unicode(&tokens[i + 1]));
+                    }
+                    i += 2;
+                }
+            }
+            "beginbfrange" => {
+                i += 1;
+                while i + 2 < tokens.len() && tokens[i] != "endbfrange" {
+                    let low = parse_hex_code(&tokens[i]);
+                    let high = parse_hex_code(&tokens[i + 1]);
+                    if tokens[i + 2] == "[" {
+                        let mut j = i + 3;
+                        let mut code = low.unwrap_or(0);
+                        while j < tokens.len() && tokens[j] != "]" {
+                            map.insert(code, parse_hex_unicode(&tokens[j]));
+                            code += 1;
+                            j += 1;
+                        }
+                        i = j + 1;
+                    } else {
+                        if let (Some(low), Some(high)) = (low, high) {
+                            let start: Vec<u16> = parse_hex_unicode(&tokens[i + 2]).encode_utf16().collect();
+                            for (offset, code) in (low..=high.min(low + 0xFFFF)).enumerate() {
+                                let mut units = start.clone();
+                                if let Some(last) = units.last_mut() {
+                                    *last = last.wrapping_add(offset as u16);
+                                }
+                                map.insert(code, String::from_utf16_lossy(&units));
+                            }
+                        }
+                        i += 3;
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    map
+}
+
+fn resolve_object<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    doc.dereference(object).ok().map(|(_, object)| object)
+}
+
+fn load_font_info(doc: &Document, font: &lopdf::Dictionary) -> FontInfo {
+    let subtype = font.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+    let base_font = font
+        .get(b"BaseFont")
+        .and_then(Object::as_name)
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .unwrap_or_default();
+
+    let to_unicode = font
+        .get(b"ToUnicode")
+        .ok()
+        .and_then(|object| resolve_object(doc, object))
+        .and_then(|cmap| cmap.as_stream().ok())
+        .and_then(|cmap| cmap.decompressed_content().ok().or_else(|| Some(cmap.content.clone())))
+        .map(|cmap| parse_to_unicode(&cmap))
+        .unwrap_or_default();
+
+    let descriptor_of = |font: &lopdf::Dictionary| {
+        font.get(b"FontDescriptor")
+            .ok()
+            .and_then(|object| resolve_object(doc, object))
+            .and_then(|descriptor| descriptor.as_dict().ok())
+            .cloned()
+    };
+    let has_font_file = |descriptor: &Option<lopdf::Dictionary>| {
+        descriptor.as_ref().is_some_and(|descriptor| {
+            descriptor.has(b"FontFile") || descriptor.has(b"FontFile2") || descriptor.has(b"FontFile3")
+        })
+    };
+
+    if subtype == b"Type0" {
+        let descendant = font
+            .get(b"DescendantFonts")
+            .ok()
+            .and_then(|object| resolve_object(doc, object))
+            .and_then(|fonts| fonts.as_array().ok())
+            .and_then(|fonts| fonts.first())
+            .and_then(|object| resolve_object(doc, object))
+            .and_then(|font| font.as_dict().ok());
+        let default_width = descendant
+            .and_then(|d| d.get(b"DW").and_then(Object::as_float).ok())
+            .map_or(1000.0, |w| w as f64)
+            / 1000.0;
+
+        // /W is a mix of `c [w1 w2 ...]` and `c_first c_last w` entries
+        let mut cid_widths = HashMap::new();
+        if let Some(Object::Array(entries)) = descendant.and_then(|d| d.get(b"W").ok()).and_then(|object| resolve_object(doc, object)) {
+            let mut i = 0;
+            while i < entries.len() {
+                let first = entries[i].as_i64().unwrap_or(0) as u32;
+                match entries.get(i + 1).and_then(|object| resolve_object(doc, object)) {
+                    Some(Object::Array(widths)) => {
+                        for (offset, width) in widths.iter().enumerate() {
+                            if let Ok(width) = width.as_float() {
+                                cid_widths.insert(first + offset as u32, width as f64 / 1000.0);
+                            }
+                        }
+                        i += 2;
+                    }
+                    Some(last) => {
+                        let last = last.as_i64().unwrap_or(0) as u32;
+                        let width = entries.get(i + 2).and_then(|w| w.as_float().ok()).unwrap_or(1000.0);
+                        for code in first..=last.min(first + 0xFFFF) {
+                            cid_widths.insert(code, width as f64 / 1000.0);
+                        }
+                        i += 3;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        return FontInfo {
+            two_byte: true,
+            first_char: 0,
+            widths: Vec::new(),
+            cid_widths,
+            default_width,
+            to_unicode,
+            embedded: has_font_file(&descendant.and_then(descriptor_of)),
+            base_font,
+        };
+    }
+
+    let first_char = font.get(b"FirstChar").and_then(Object::as_i64).unwrap_or(0) as u32;
+    let widths = font
+        .get(b"Widths")
+        .ok()
+        .and_then(|object| resolve_object(doc, object))
+        .and_then(|widths| widths.as_array().ok())
+        .map(|widths| {
+            widths
+                .iter()
+                .map(|w| resolve_object(doc, w).and_then(|w| w.as_float().ok()).unwrap_or(0.0) as f64 / 1000.0)
+                .collect()
+        })
+        .unwrap_or_default();
+    let descriptor = descriptor_of(font);
+    let default_width = descriptor
+        .as_ref()
+        .and_then(|d| d.get(b"MissingWidth").and_then(Object::as_float).ok())
+        .filter(|&w| w > 0.0)
+        .map_or(0.5, |w| w as f64 / 1000.0);
+
+    FontInfo {
+        two_byte: false,
+        first_char,
+        widths,
+        cid_widths: HashMap::new(),
+        default_width,
+        to_unicode,
+        embedded: has_font_file(&descriptor) || subtype == b"Type3",
+        base_font,
+    }
+}
+
+fn load_fonts(doc: &Document, resources: Option<&lopdf::Dictionary>) -> HashMap<Vec<u8>, FontInfo> {
+    let mut fonts = HashMap::new();
+    if let Some(font_dict) = resources.and_then(|r| resource_category(doc, r, b"Font")) {
+        for (name, font) in font_dict.iter() {
+            if let Ok((_, Object::Dictionary(font))) = doc.dereference(font) {
+                fonts.insert(name.clone(), load_font_info(doc, font));
+            }
+        }
+    }
+    fonts
+}
+
+#[derive(Clone)]
+struct TextState {
+    ctm: Matrix,
+    char_spacing: f64,
+    word_spacing: f64,
+    horizontal_scale: f64,
+    leading: f64,
+    rise: f64,
+    font: Vec<u8>,
+    font_size: f64,
+}
+
+// Walk a content stream, tracking the text and graphics matrices, and collect one span
+// per string shown. Form XObjects are followed with their own resources.
+fn collect_text_spans(
+    doc: &Document,
+    content: &[u8],
+    resources: Option<&lopdf::Dictionary>,
+    base: Matrix,
+    spans: &mut Vec<TextSpan>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > 8 {
+        return Ok(());
+    }
+    let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+    let fonts = load_fonts(doc, resources);
+    let mut state = TextState {
+        ctm: base,
+        char_spacing: 0.0,
+        word_spacing: 0.0,
+        horizontal_scale: 1.0,
+        leading: 0.0,
+        rise: 0.0,
+        font: Vec::new(),
+        font_size: 0.0,
+    };
+    let mut stack: Vec<TextState> = Vec::new();
+    let mut tm = IDENTITY;
+    let mut tlm = IDENTITY;
+
+    for operation in &content.operations {
+        let numbers = operand_numbers(&operation.operands);
+        match operation.operator.as_str() {
+            "q" => stack.push(state.clone()),
+            "Q" => {
+                if let Some(previous) = stack.pop() {
+                    state = previous;
+                }
+            }
+            "cm" if numbers.len() == 6 => {
+                let m = [numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]];
+                state.ctm = multiply(&m, &state.ctm);
+            }
+            "BT" => {
+                tm = IDENTITY;
+                tlm = IDENTITY;
+            }
+            "Tf" => {
+                if let Some(name) = operation.operands.first().and_then(|n| n.as_name().ok()) {
+                    state.font = name.to_vec();
+                }
+                state.font_size = numbers.first().copied().unwrap_or(0.0);
+            }
+            "Tc" => state.char_spacing = numbers.first().copied().unwrap_or(0.0),
+            "Tw" => state.word_spacing = numbers.first().copied().unwrap_or(0.0),
+            "Tz" => state.horizontal_scale = numbers.first().copied().unwrap_or(100.0) / 100.0,
+            "TL" => state.leading = numbers.first().copied().unwrap_or(0.0),
+            "Ts" => state.rise = numbers.first().copied().unwrap_or(0.0),
+            "Td" | "TD" if numbers.len() == 2 => {
+                if operation.operator == "TD" {
+                    state.leading = -numbers[1];
+                }
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, numbers[0], numbers[1]], &tlm);
+                tm = tlm;
+            }
+            "Tm" if numbers.len() == 6 => {
+                tlm = [numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]];
+                tm = tlm;
+            }
+            "T*" => {
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                tm = tlm;
+            }
+            "Tj" | "'" | "\"" | "TJ" => {
+                if operation.operator == "'" || operation.operator == "\"" {
+                    if operation.operator == "\"" && numbers.len() >= 2 {
+                        state.word_spacing = numbers[0];
+                        state.char_spacing = numbers[1];
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                    tm = tlm;
+                }
+                let font = match fonts.get(&state.font) {
+                    Some(font) => font,
+                    None => continue,
+                };
+
+                let start = multiply(&tm, &state.ctm);
+                let mut text = String::new();
+                let mut advance = 0.0;
+                let show = |bytes: &[u8], text: &mut String, advance: &mut f64| {
+                    for code in font.codes(bytes) {
+                        text.push_str(&font.decode(code));
+                        let mut glyph = font.width(code) * state.font_size + state.char_spacing;
+                        if !font.two_byte && code == 32 {
+                            glyph += state.word_spacing;
+                        }
+                        *advance += glyph * state.horizontal_scale;
+                    }
+                };
+
+                for operand in &operation.operands {
+                    match operand {
+                        Object::String(bytes, _) => show(bytes, &mut text, &mut advance),
+                        Object::Array(items) => {
+                            for item in items {
+                                match item {
+                                    Object::String(bytes, _) => show(bytes, &mut text, &mut advance),
+                                    other => {
+                                        if let Ok(adjust) = other.as_float() {
+                                            advance -= adjust as f64 / 1000.0 * state.font_size * state.horizontal_scale;
+                                            // Word gaps in justified text are often positioning
+                                            // rather than space characters
+                                            if adjust < -TJ_WORD_GAP && !text.is_empty() && !text.ends_with(char::is_whitespace) {
+                                                text.push(' ');
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                tm = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let (x0, y0) = transform_point(&start, 0.0, state.rise);
+                let (x1, y1) = transform_point(&start, advance, state.rise);
+                let vertical_scale = (start[2] * start[2] + start[3] * start[3]).sqrt();
+                spans.push(TextSpan {
+                    text,
+                    x: x0,
+                    y: y0,
+                    width: ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt(),
+                    height: state.font_size.abs() * vertical_scale,
+                    font_size: state.font_size.abs() * vertical_scale,
+                    font_name: if font.base_font.is_empty() {
+                        String::from_utf8_lossy(&state.font).to_string()
+                    } else {
+                        font.base_font.clone()
+                    },
+                });
+            }
+            "Do" => {
+                let name = match operation.operands.first().and_then(|n| n.as_name().ok()) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let form = resources
+                    .and_then(|r| resource_category(doc, r, b"XObject"))
+                    .and_then(|xobjects| xobjects.get(name).ok())
+                    .and_then(|xobject| doc.dereference(xobject).ok())
+                    .and_then(|(_, xobject)| xobject.as_stream().ok())
+                    .filter(|xobject| {
+                        matches!(xobject.dict.get(b"Subtype"), Ok(Object::Name(subtype)) if subtype == b"Form")
+                    });
+                if let Some(form) = form {
+                    let matrix = form
+                        .dict
+                        .get(b"Matrix")
+                        .ok()
+                        .and_then(|m| m.as_array().ok())
+                        .map(|m| operand_numbers(m))
+                        .filter(|m| m.len() == 6)
+                        .map_or(IDENTITY, |m| [m[0], m[1], m[2], m[3], m[4], m[5]]);
+                    let form_resources = form
+                        .dict
+                        .get(b"Resources")
+                        .ok()
+                        .and_then(|r| doc.dereference(r).ok())
+                        .and_then(|(_, r)| r.as_dict().ok())
+                        .or(resources);
+                    let form_content = form.decompressed_content().unwrap_or_else(|_| form.content.clone());
+                    collect_text_spans(
+                        doc,
+                        &form_content,
+                        form_resources,
+                        multiply(&matrix, &state.ctm),
+                        spans,
+                        depth + 1,
+                    )?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Positioned text spans of a page in content stream order
+fn page_text_spans(doc: &Document, page_id: ObjectId) -> Result<Vec<TextSpan>, String> {
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let mut spans = Vec::new();
+    collect_text_spans(doc, &content, page_resources(doc, page_id), IDENTITY, &mut spans, 0)?;
+    Ok(spans)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Lines from top to bottom, made of spans sharing a baseline within half a line height.
+// Spans within a line are left in content stream order.
+fn group_into_lines(mut spans: Vec<TextSpan>) -> Vec<Vec<TextSpan>> {
+    spans.sort_by(|a, b| b.y.partial_cmp(&a.y).unwrap_or(std::cmp::Ordering::Equal));
+    let mut lines: Vec<Vec<TextSpan>> = Vec::new();
+    for span in spans {
+        let tolerance = span.height.max(1.0) * 0.5;
+        match lines.last_mut() {
+            Some(line) if (line[0].y - span.y).abs() <= tolerance => line.push(span),
+            _ => lines.push(vec![span]),
+        }
+    }
+    lines
+}
+
+// Extract a table from a page into CSV.
+//
+// Heuristic: spans are grouped into rows by baseline, and columns are the horizontal
+// ranges covered by text across all rows. Works best for simple ruled/gridded tables
+// with one text run per cell; merged cells and wrapped cell text are not recognized.
+#[tauri::command]
+async fn extract_table(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    region: Option<[f64; 4]>,
+    output_path: String,
+) -> Result<(), String> {
+    let spans = with_document(&state, &path, |doc| page_text_spans(doc, page_id(doc, page_num)?))?;
+    let spans: Vec<TextSpan> = spans
+        .into_iter()
+        .filter(|span| match region {
+            Some([x0, y0, x1, y1]) => {
+                let (cx, cy) = (span.x + span.width / 2.0, span.y + span.height / 3.0);
+                cx >= x0.min(x1) && cx <= x0.max(x1) && cy >= y0.min(y1) && cy <= y0.max(y1)
+            }
+            None => true,
+        })
+        .collect();
+    if spans.is_empty() {
+        return Err("No text found in the table region".to_string());
+    }
+
+    let mut rows = group_into_lines(spans);
+
+    // Columns: merge the horizontal extents of all spans into disjoint ranges
+    let mut extents: Vec<(f64, f64)> = rows.iter().flatten().map(|s| (s.x, s.x + s.width)).collect();
+    extents.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut columns: Vec<(f64, f64)> = Vec::new();
+    for (start, end) in extents {
+        match columns.last_mut() {
+            Some(column) if start <= column.1 => column.1 = column.1.max(end),
+            _ => columns.push((start, end)),
+        }
+    }
+
+    let mut csv = String::new();
+    for row in &mut rows {
+        row.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|&(start, end)| {
+                let text: Vec<&str> = row
+                    .iter()
+                    .filter(|span| {
+                        let center = span.x + span.width / 2.0;
+                        center >= start && center <= end
+                    })
+                    .map(|span| span.text.trim())
+                    .collect();
+                csv_field(&text.join(" "))
+            })
+            .collect();
+        csv.push_str(&cells.join(","));
+        csv.push('\n');
+    }
+
+    write_atomically(&output_path, |file| file.write_all(csv.as_bytes()).map_err(|e| e.to_string()))
+}
+
+#[tauri::command]
+async fn embed_thumbnails(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    size: u32,
+    force: bool,
+) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    if size == 0 {
+        return Err("Thumbnail size must be greater than zero".to_string());
+    }
+    let mut doc = snapshot_document(&state, &path)?;
+
+    for page_id in doc.get_pages().into_values() {
+        let has_thumb = doc.get_dictionary(page_id).map_or(false, |page| page.has(b"Thumb"));
+        if has_thumb && !force {
+            continue;
+        }
+
+        let (_, width, height) = page_device_matrix(&doc, page_id, 1.0, default_size);
+        let scale = size as f64 / width.max(height) as f64;
+        let image = render_page(&doc, page_id, scale, None, default_size)?;
+        let thumb = rgb_image_stream(&lopdf::Dictionary::new(), &image)?;
+        let thumb_id = doc.add_object(thumb);
+
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        page.set("Thumb", Object::Reference(thumb_id));
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+// Page count straight from the file, without caching the document or rendering anything
+#[tauri::command]
+async fn get_page_count(path: String) -> Result<usize, String> {
+    let doc = Document::load(&path).map_err(|e| e.to_string())?;
+    if doc.is_encrypted() {
+        return Err("Document is encrypted".to_string());
+    }
+    Ok(doc.get_pages().len())
+}
+
+// Register an object in a page's resources under a fresh name and return that name.
+// The page gets its own copy of the resource dictionary so shared resources are not affected.
+fn add_page_resource(
+    doc: &mut Document,
+    page_id: ObjectId,
+    category: &str,
+    prefix: &str,
+    value: Object,
+) -> Result<Vec<u8>, String> {
+    materialize_inherited_attributes(doc, page_id)?;
+    let mut resources = page_resources(doc, page_id).cloned().unwrap_or_default();
+    let mut entries = resource_category(doc, &resources, category.as_bytes())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut index = 1;
+    let name = loop {
+        let candidate = format!("{}{}", prefix, index).into_bytes();
+        if !entries.has(&candidate) {
+            break candidate;
+        }
+        index += 1;
+    };
+    entries.set(name.clone(), value);
+    resources.set(category, Object::Dictionary(entries));
+
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    page.set("Resources", Object::Dictionary(resources));
+    Ok(name)
+}
+
+fn page_content_refs(doc: &Document, page_id: ObjectId) -> Vec<Object> {
+    match doc
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"Contents").ok())
+    {
+        Some(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(items)) => items.clone(),
+            _ => vec![Object::Reference(*id)],
+        },
+        Some(Object::Array(items)) => items.clone(),
+        _ => Vec::new(),
+    }
+}
+
+// Draw extra operators on top of a page. The original content is wrapped in q/Q so
+// any graphics state it leaves behind does not leak into the overlay.
+fn append_page_overlay(doc: &mut Document, page_id: ObjectId, operators: &[u8]) -> Result<(), String> {
+    let mut contents = page_content_refs(doc, page_id);
+    let open_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), b"q\n".to_vec()));
+    let mut overlay = b"Q\n".to_vec();
+    overlay.extend_from_slice(operators);
+    let mut overlay = lopdf::Stream::new(lopdf::Dictionary::new(), overlay);
+    overlay.compress().map_err(|e| e.to_string())?;
+    let overlay_id = doc.add_object(overlay);
+
+    contents.insert(0, Object::Reference(open_id));
+    contents.push(Object::Reference(overlay_id));
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    page.set("Contents", Object::Array(contents));
+    Ok(())
+}
+
+// Embed an image file as an image XObject, with a soft mask when it has transparency
+fn embed_image_file(doc: &mut Document, image_path: &str) -> Result<(ObjectId, u32, u32), String> {
+    let image = image::open(image_path).map_err(|e| e.to_string())?;
+    let rgba = image.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let rgb = image::DynamicImage::ImageRgba8(rgba.clone()).to_rgb8();
+    let mut stream = rgb_image_stream(&lopdf::Dictionary::new(), &rgb)?;
+
+    if rgba.pixels().any(|pixel| pixel.0[3] < 255) {
+        let alpha: Vec<u8> = rgba.pixels().map(|pixel| pixel.0[3]).collect();
+        let mut mask_dict = lopdf::Dictionary::new();
+        mask_dict.set("Type", Object::Name(b"XObject".to_vec()));
+        mask_dict.set("Subtype", Object::Name(b"Image".to_vec()));
+        mask_dict.set("Width", Object::Integer(width as i64));
+        mask_dict.set("Height", Object::Integer(height as i64));
+        mask_dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+        mask_dict.set("BitsPerComponent", Object::Integer(8));
+        let mut mask = lopdf::Stream::new(mask_dict, alpha);
+        mask.compress().map_err(|e| e.to_string())?;
+        let mask_id = doc.add_object(mask);
+        stream.dict.set("SMask", Object::Reference(mask_id));
+    }
+
+    Ok((doc.add_object(stream), width, height))
+}
+
+fn opacity_state(opacity: f64) -> lopdf::Dictionary {
+    let mut state = lopdf::Dictionary::new();
+    state.set("Type", Object::Name(b"ExtGState".to_vec()));
+    state.set("ca", Object::Real(opacity as f32));
+    state.set("CA", Object::Real(opacity as f32));
+    state
+}
+
+// Draw an image centered on each target page. `scale` is the size relative to the page
+// (1.0 fits the page) and the image keeps its aspect ratio.
+#[tauri::command]
+async fn add_image_watermark(
+    state: State<'_, AppState>,
+    path: String,
+    image_path: String,
+    opacity: f64,
+    scale: f64,
+    pages: Option<Vec<usize>>,
+) -> Result<(), String> {
+    if scale <= 0.0 {
+        return Err("Scale must be greater than zero".to_string());
+    }
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+
+    edit_document(&state, &path, |doc| {
+        apply_image_watermark(doc, &image_path, opacity, scale, &pages, default_size)
+    })
+}
+
+fn apply_image_watermark(
+    doc: &mut Document,
+    image_path: &str,
+    opacity: f64,
+    scale: f64,
+    pages: &Option<Vec<usize>>,
+    default_size: (f64, f64),
+) -> Result<(), String> {
+    let (image_id, image_width, image_height) = embed_image_file(doc, image_path)?;
+    let state_id = doc.add_object(opacity_state(opacity.clamp(0.0, 1.0)));
+
+    for (page_number, page_id) in doc.get_pages() {
+        if let Some(pages) = pages {
+            if !pages.contains(&(page_number as usize)) {
+                continue;
+            }
+        }
+        let [x0, y0, x1, y1] = page_visible_box(doc, page_id)
+            .unwrap_or([0.0, 0.0, default_size.0, default_size.1]);
+        let (page_width, page_height) = (x1 - x0, y1 - y0);
+        let factor = (page_width * scale / image_width as f64).min(page_height * scale / image_height as f64);
+        let (width, height) = (image_width as f64 * factor, image_height as f64 * factor);
+        let x = x0 + (page_width - width) / 2.0;
+        let y = y0 + (page_height - height) / 2.0;
+
+        let image_name = add_page_resource(doc, page_id, "XObject", "WmImg", Object::Reference(image_id))?;
+        let state_name = add_page_resource(doc, page_id, "ExtGState", "WmGs", Object::Reference(state_id))?;
+        let operators = format!(
+            "q /{} gs {:.4} 0 0 {:.4} {:.4} {:.4} cm /{} Do Q\n",
+            String::from_utf8_lossy(&state_name),
+            width,
+            height,
+            x,
+            y,
+            String::from_utf8_lossy(&image_name)
+        );
+        append_page_overlay(doc, page_id, operators.as_bytes())?;
+    }
+    Ok(())
+}
+
+// Discard all in-memory edits and reload the document from disk. Unlike unloading, the
+// document stays open; its undo/redo history and cached thumbnails are cleared.
+#[tauri::command]
+async fn revert(state: State<'_, AppState>, path: String) -> Result<(), String> {
+    let doc = Document::load(&path).map_err(|e| e.to_string())?;
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    if documents.get(&path).is_some_and(|open| open.readonly) {
+        return Err(READ_ONLY.to_string());
+    }
+    documents.insert(path, OpenDocument::new(doc));
+    Ok(())
+}
+
+// Variants carry the PDF names, which all start with Duplex
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Duplex {
+    Simplex,
+    DuplexFlipShortEdge,
+    DuplexFlipLongEdge,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ViewerPreferences {
+    hide_toolbar: bool,
+    hide_menubar: bool,
+    fit_window: bool,
+    center_window: bool,
+    display_doc_title: bool,
+    duplex: Option<Duplex>,
+}
+
+// Resolve the catalog's /ViewerPreferences dictionary, if any
+fn viewer_preferences_dict(doc: &Document) -> Option<&lopdf::Dictionary> {
+    let prefs = doc.catalog().ok()?.get(b"ViewerPreferences").ok()?;
+    resolve_object(doc, prefs)?.as_dict().ok()
+}
+
+// Update entries of the catalog's /ViewerPreferences, keeping any entries we don't manage
+fn update_viewer_preferences(
+    doc: &mut Document,
+    update: impl FnOnce(&mut lopdf::Dictionary),
+) -> Result<(), String> {
+    let mut prefs = viewer_preferences_dict(doc).cloned().unwrap_or_default();
+    update(&mut prefs);
+    doc.catalog_mut()
+        .map_err(|e| e.to_string())?
+        .set("ViewerPreferences", Object::Dictionary(prefs));
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_viewer_preferences(state: State<'_, AppState>, path: String) -> Result<ViewerPreferences, String> {
+    with_document(&state, &path, |doc| {
+        let prefs = match viewer_preferences_dict(doc) {
+            Some(prefs) => prefs,
+            None => return Ok(ViewerPreferences::default()),
+        };
+        let flag = |key: &[u8]| prefs.get(key).and_then(Object::as_bool).unwrap_or(false);
+        let duplex = match prefs.get(b"Duplex").and_then(Object::as_name) {
+            Ok(b"Simplex") => Some(Duplex::Simplex),
+            Ok(b"DuplexFlipShortEdge") => Some(Duplex::DuplexFlipShortEdge),
+            Ok(b"DuplexFlipLongEdge") => Some(Duplex::DuplexFlipLongEdge),
+            _ => None,
+        };
+
+        Ok(ViewerPreferences {
+            hide_toolbar: flag(b"HideToolbar"),
+            hide_menubar: flag(b"HideMenubar"),
+            fit_window: flag(b"FitWindow"),
+            center_window: flag(b"CenterWindow"),
+            display_doc_title: flag(b"DisplayDocTitle"),
+            duplex,
+        })
+    })
+}
+
+#[tauri::command]
+async fn set_viewer_preferences(
+    state: State<'_, AppState>,
+    path: String,
+    prefs: ViewerPreferences,
+) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        update_viewer_preferences(doc, |dict| {
+            dict.set("HideToolbar", Object::Boolean(prefs.hide_toolbar));
+            dict.set("HideMenubar", Object::Boolean(prefs.hide_menubar));
+            dict.set("FitWindow", Object::Boolean(prefs.fit_window));
+            dict.set("CenterWindow", Object::Boolean(prefs.center_window));
+            dict.set("DisplayDocTitle", Object::Boolean(prefs.display_doc_title));
+            match prefs.duplex {
+                Some(duplex) => {
+                    let name = match duplex {
+                        Duplex::Simplex => "Simplex",
+                        Duplex::DuplexFlipShortEdge => "DuplexFlipShortEdge",
+                        Duplex::DuplexFlipLongEdge => "DuplexFlipLongEdge",
+                    };
+                    dict.set("Duplex", Object::Name(name.as_bytes().to_vec()));
+                }
+                None => {
+                    dict.remove(b"Duplex");
+                }
+            }
+        })
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum TransitionStyle {
+    Split,
+    Blinds,
+    Box,
+    Wipe,
+    Dissolve,
+    Glitter,
+    Replace,
+    Fly,
+    Push,
+    Cover,
+    Uncover,
+    Fade,
+}
+
+impl TransitionStyle {
+    fn pdf_name(self) -> &'static str {
+        match self {
+            TransitionStyle::Split => "Split",
+            TransitionStyle::Blinds => "Blinds",
+            TransitionStyle::Box => "Box",
+            TransitionStyle::Wipe => "Wipe",
+            TransitionStyle::Dissolve => "Dissolve",
+            TransitionStyle::Glitter => "Glitter",
+            TransitionStyle::Replace => "R",
+            TransitionStyle::Fly => "Fly",
+            TransitionStyle::Push => "Push",
+            TransitionStyle::Cover => "Cover",
+            TransitionStyle::Uncover => "Uncover",
+            TransitionStyle::Fade => "Fade",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Transition {
+    style: TransitionStyle,
+    // Transition duration in seconds
+    duration: f64,
+    // How long the page is shown before advancing, in seconds
+    display_duration: Option<f64>,
+}
+
+// Pages selected by an optional list of 1-based page numbers (all pages when `None`)
+fn select_pages(doc: &Document, pages: &Option<Vec<usize>>) -> Result<Vec<(usize, ObjectId)>, String> {
+    let all = doc.get_pages();
+    match pages {
+        None => Ok(all.into_iter().map(|(number, id)| (number as usize, id)).collect()),
+        Some(numbers) => numbers
+            .iter()
+            .map(|&number| page_id(doc, number).map(|id| (number, id)))
+            .collect(),
+    }
+}
+
+#[tauri::command]
+async fn set_page_transition(
+    state: State<'_, AppState>,
+    path: String,
+    pages: Option<Vec<usize>>,
+    transition: Transition,
+) -> Result<(), String> {
+    if transition.duration < 0.0 || transition.display_duration.map_or(false, |d| d < 0.0) {
+        return Err("Durations must not be negative".to_string());
+    }
+
+    edit_document(&state, &path, |doc| {
+        for (_, page_id) in select_pages(doc, &pages)? {
+            let mut trans = lopdf::Dictionary::new();
+            trans.set("Type", Object::Name(b"Trans".to_vec()));
+            trans.set("S", Object::Name(transition.style.pdf_name().as_bytes().to_vec()));
+            trans.set("D", Object::Real(transition.duration as f32));
+
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Trans", Object::Dictionary(trans));
+            if let Some(display_duration) = transition.display_duration {
+                page.set("Dur", Object::Real(display_duration as f32));
+            }
+        }
+        Ok(())
+    })
+}
+
+// Resolution used when page content has to be rasterized
+const RASTER_DPI: f64 = 150.0;
+
+fn has_transparency_group(dict: &lopdf::Dictionary, doc: &Document) -> bool {
+    dict.get(b"Group")
+        .ok()
+        .and_then(|group| resolve_object(doc, group))
+        .and_then(|group| group.as_dict().ok())
+        .is_some_and(|group| matches!(group.get(b"S"), Ok(Object::Name(name)) if name == b"Transparency"))
+}
+
+fn ext_gstate_is_transparent(doc: &Document, state: &lopdf::Dictionary) -> bool {
+    let alpha_below_one = |key: &[u8]| state.get(key).and_then(Object::as_float).is_ok_and(|a| a < 1.0);
+    let soft_mask = state
+        .get(b"SMask")
+        .ok()
+        .and_then(|mask| resolve_object(doc, mask))
+        .is_some_and(|mask| !matches!(mask, Object::Name(name) if name == b"None"));
+    let blend = state
+        .get(b"BM")
+        .ok()
+        .and_then(|mode| resolve_object(doc, mode))
+        .is_some_and(|mode| match mode {
+            Object::Name(name) => name != b"Normal" && name != b"Compatible",
+            _ => true,
+        });
+    alpha_below_one(b"ca") || alpha_below_one(b"CA") || soft_mask || blend
+}
+
+// Whether the content drawn through a resource dictionary uses transparency
+fn resources_use_transparency(doc: &Document, resources: &lopdf::Dictionary, depth: usize) -> bool {
+    if depth > 8 {
+        return false;
+    }
+    if let Some(states) = resource_category(doc, resources, b"ExtGState") {
+        for (_, state) in states.iter() {
+            if let Some(Object::Dictionary(state)) = resolve_object(doc, state) {
+                if ext_gstate_is_transparent(doc, state) {
+                    return true;
+                }
+            }
+        }
+    }
+    if let Some(xobjects) = resource_category(doc, resources, b"XObject") {
+        for (_, xobject) in xobjects.iter() {
+            if let Some(Object::Stream(xobject)) = resolve_object(doc, xobject) {
+                let is_form = matches!(xobject.dict.get(b"Subtype"), Ok(Object::Name(name)) if name == b"Form");
+                if is_form && has_transparency_group(&xobject.dict, doc) {
+                    return true;
+                }
+                let nested = xobject
+                    .dict
+                    .get(b"Resources")
+                    .ok()
+                    .and_then(|r| resolve_object(doc, r))
+                    .and_then(|r| r.as_dict().ok());
+                if is_form && nested.is_some_and(|r| resources_use_transparency(doc, r, depth + 1)) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+// Composite an image with its soft mask against white, producing an opaque image
+fn flatten_soft_mask(doc: &Document, stream: &lopdf::Stream) -> Option<image::RgbImage> {
+    let mask = stream.dict.get(b"SMask").ok().and_then(|m| resolve_object(doc, m))?.as_stream().ok()?;
+    let base = decode_image_xobject(doc, stream).ok()?;
+    let mask = decode_image_xobject(doc, mask).ok()?;
+    let mask = image::imageops::resize(&mask, base.width(), base.height(), image::imageops::FilterType::Triangle);
+
+    let mut flattened = base.clone();
+    for (x, y, pixel) in flattened.enumerate_pixels_mut() {
+        let alpha = mask.get_pixel(x, y).0[0] as f64 / 255.0;
+        for channel in 0..3 {
+            pixel.0[channel] = (pixel.0[channel] as f64 * alpha + 255.0 * (1.0 - alpha)).round() as u8;
+        }
+    }
+    Some(flattened)
+}
+
+// Replace a page's content with a single full-page image of its rendering, encoded
+// with `codec`
+fn rasterize_page_in_place(
+    doc: &mut Document,
+    page_id: ObjectId,
+    dpi: f64,
+    codec: ImageCodec,
+    quality: u8,
+    default_size: (f64, f64),
+) -> Result<(), String> {
+    materialize_inherited_attributes(doc, page_id)?;
+    let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+    let image = render_page_unrotated(doc, page_id, dpi / 72.0, default_size)?;
+    let image_id = doc.add_object(encode_image_stream(&lopdf::Dictionary::new(), &image, codec, quality)?);
+
+    let mut xobjects = lopdf::Dictionary::new();
+    xobjects.set("Raster", Object::Reference(image_id));
+    let mut resources = lopdf::Dictionary::new();
+    resources.set("XObject", Object::Dictionary(xobjects));
+
+    let operators = format!("q {:.4} 0 0 {:.4} {:.4} {:.4} cm /Raster Do Q\n", x1 - x0, y1 - y0, x0, y0);
+    set_page_content(doc, page_id, operators.into_bytes())?;
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    page.set("Resources", Object::Dictionary(resources));
+    Ok(())
+}
+
+// Remove transparency so the output prints on devices that don't support it.
+//
+// Soft-masked images are composited against white and kept as images, so surrounding
+// vector and text content is untouched. Pages that use other transparency (alpha in
+// ExtGStates, soft masks, blend modes or transparency groups on form XObjects) are
+// rasterized as a whole, which loses selectable text on those pages. `codec` sets the
+// filter of both; without it, flattened images keep their kind and rasters are JPEG.
+#[tauri::command]
+async fn flatten_transparency(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    jpeg_quality: Option<u8>,
+    codec: Option<ImageCodec>,
+) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let quality = clamp_jpeg_quality(jpeg_quality);
+    let mut doc = snapshot_document(&state, &path)?;
+
+    let masked_images: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| match object {
+            Object::Stream(stream) => is_image_xobject(object) && stream.dict.has(b"SMask"),
+            _ => false,
+        })
+        .map(|(&id, _)| id)
+        .collect();
+    for id in masked_images {
+        let flattened = match doc.get_object(id) {
+            Ok(Object::Stream(stream)) => flatten_soft_mask(&doc, stream).map(|image| (stream.clone(), image)),
+            _ => None,
+        };
+        if let Some((original, image)) = flattened {
+            let mut stream = reencode_image(&original, &image, quality, codec)?;
+            stream.dict.remove(b"SMask");
+            doc.objects.insert(id, Object::Stream(stream));
+        }
+    }
+
+    let raster_codec = codec.unwrap_or(ImageCodec::Jpeg);
+    for page_id in doc.get_pages().into_values() {
+        let transparent = page_resources(&doc, page_id).map_or(false, |r| resources_use_transparency(&doc, r, 0));
+        if transparent {
+            rasterize_page_in_place(&mut doc, page_id, RASTER_DPI, raster_codec, quality, default_size)?;
+        }
+        if let Ok(page) = doc.get_dictionary_mut(page_id) {
+            page.remove(b"Group");
+        }
+    }
+
+    // Any remaining transparency group entries on forms are now meaningless
+    for object in doc.objects.values_mut() {
+        if let Object::Stream(stream) = object {
+            if stream.dict.get(b"Group").is_ok() {
+                stream.dict.remove(b"Group");
+            }
+        }
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+// Maximum number of kids per node when building a fresh page tree
+const PAGE_TREE_FANOUT: usize = 32;
+
+fn is_page_object(object: &Object) -> bool {
+    match object {
+        Object::Dictionary(dict) => matches!(dict.get(b"Type"), Ok(Object::Name(name)) if name == b"Page"),
+        _ => false,
+    }
+}
+
+// Pages reachable from the catalog's page tree, in traversal order. Tolerates cycles,
+// dangling references and wrong /Count values.
+fn traverse_page_tree(doc: &Document) -> Vec<ObjectId> {
+    let mut order = Vec::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut stack: Vec<ObjectId> = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .into_iter()
+        .collect();
+
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let object = match doc.get_object(id) {
+            Ok(object) => object,
+            Err(_) => continue,
+        };
+        if is_page_object(object) {
+            order.push(id);
+            continue;
+        }
+        if let Ok(kids) = object.as_dict().and_then(|node| node.get(b"Kids")) {
+            if let Some(Object::Array(kids)) = resolve_object(doc, kids) {
+                stack.extend(kids.iter().rev().filter_map(|kid| kid.as_reference().ok()));
+            }
+        }
+    }
+    order
+}
+
+// Build a balanced page tree over the given pages, reusing `root_id` for the top node
+fn build_page_tree(doc: &mut Document, root_id: ObjectId, pages: &[ObjectId]) -> Result<(), String> {
+    let mut level: Vec<(ObjectId, usize)> = pages.iter().map(|&id| (id, 1)).collect();
+
+    loop {
+        let is_root = level.len() <= PAGE_TREE_FANOUT;
+        let groups: Vec<Vec<(ObjectId, usize)>> = if is_root {
+            vec![level.clone()]
+        } else {
+            level.chunks(PAGE_TREE_FANOUT).map(|chunk| chunk.to_vec()).collect()
+        };
+
+        let mut next_level = Vec::new();
+        for group in groups {
+            let node_id = if is_root { root_id } else { doc.new_object_id() };
+            let count: usize = group.iter().map(|&(_, count)| count).sum();
+            for &(kid, _) in &group {
+                let kid = doc.get_dictionary_mut(kid).map_err(|e| e.to_string())?;
+                kid.set("Parent", Object::Reference(node_id));
+            }
+
+            let mut node = lopdf::Dictionary::new();
+            node.set("Type", Object::Name(b"Pages".to_vec()));
+            node.set("Kids", Object::Array(group.iter().map(|&(id, _)| Object::Reference(id)).collect()));
+            node.set("Count", Object::Integer(count as i64));
+            doc.objects.insert(node_id, Object::Dictionary(node));
+            next_level.push((node_id, count));
+        }
+
+        if is_root {
+            return Ok(());
+        }
+        level = next_level;
+    }
+}
+
+// Rebuild the page tree of a damaged file from the page objects it contains.
+// Pages reachable from the existing tree keep their order; orphaned pages follow
+// in object number order.
+#[tauri::command]
+async fn rebuild_page_tree(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    // Loaded directly rather than through the cache: the file may be too broken to edit normally
+    let mut doc = Document::load(&path).map_err(|e| e.to_string())?;
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+
+    let mut pages = traverse_page_tree(&doc);
+    let mut orphans: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|&(id, object)| is_page_object(object) && !pages.contains(id))
+        .map(|(&id, _)| id)
+        .collect();
+    orphans.sort();
+    pages.extend(orphans);
+
+    if pages.is_empty() {
+        return Err("No page objects found".to_string());
+    }
+
+    for &page_id in &pages {
+        // A broken parent chain just means there is nothing left to inherit
+        materialize_inherited_attributes(&mut doc, page_id).ok();
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        if !page.has(b"MediaBox") {
+            let (width, height) = default_size;
+            page.set(
+                "MediaBox",
+                Object::Array(vec![Object::Integer(0), Object::Integer(0), Object::Real(width as f32), Object::Real(height as f32)]),
+            );
+        }
+    }
+
+    // Drop the old intermediate nodes; the new tree replaces them
+    let root_id = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .ok();
+    let old_nodes: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| is_pages_node(object))
+        .map(|(&id, _)| id)
+        .collect();
+    for id in old_nodes {
+        doc.objects.remove(&id);
+    }
+
+    let root_id = root_id.unwrap_or_else(|| doc.new_object_id());
+    build_page_tree(&mut doc, root_id, &pages)?;
+    doc.catalog_mut()
+        .map_err(|e| e.to_string())?
+        .set("Pages", Object::Reference(root_id));
+
+    save_document(&mut doc, &output_path)?;
+    Ok(pages.len())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum PageClass {
+    TextNative,
+    Scanned,
+    Mixed,
+    Empty,
+}
+
+// Fraction of the page that must be covered by images for it to count as a scan
+const SCANNED_IMAGE_COVERAGE: f64 = 0.8;
+
+// Samples per side when estimating how much of a page images cover
+const COVERAGE_GRID: usize = 64;
+
+// Page-space bounding boxes of every image drawn by a content stream, following forms
+fn collect_image_placements(
+    doc: &Document,
+    content: &[u8],
+    resources: Option<&lopdf::Dictionary>,
+    base: Matrix,
+    placements: &mut Vec<[f64; 4]>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > 8 {
+        return Ok(());
+    }
+    let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+    let xobjects = resources.and_then(|r| resource_category(doc, r, b"XObject"));
+    let mut ctm = base;
+    let mut stack: Vec<Matrix> = Vec::new();
+
+    for operation in &content.operations {
+        let numbers = operand_numbers(&operation.operands);
+        match operation.operator.as_str() {
+            "q" => stack.push(ctm),
+            "Q" => {
+                if let Some(previous) = stack.pop() {
+                    ctm = previous;
+                }
+            }
+            "cm" if numbers.len() == 6 => {
+                let m = [numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]];
+                ctm = multiply(&m, &ctm);
+            }
+            "Do" => {
+                let name = match operation.operands.first().and_then(|o| o.as_name().ok()) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let xobject = match xobjects
+                    .and_then(|x| x.get(name).ok())
+                    .and_then(|x| resolve_object(doc, x))
+                {
+                    Some(Object::Stream(stream)) => stream,
+                    _ => continue,
+                };
+                match xobject.dict.get(b"Subtype") {
+                    Ok(Object::Name(subtype)) if subtype == b"Image" => {
+                        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+                            .map(|(x, y)| transform_point(&ctm, x, y));
+                        let xs = corners.map(|(x, _)| x);
+                        let ys = corners.map(|(_, y)| y);
+                        placements.push([
+                            xs.iter().cloned().fold(f64::INFINITY, f64::min),
+                            ys.iter().cloned().fold(f64::INFINITY, f64::min),
+                            xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                            ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                        ]);
+                    }
+                    Ok(Object::Name(subtype)) if subtype == b"Form" => {
+                        let matrix = xobject
+                            .dict
+                            .get(b"Matrix")
+                            .ok()
+                            .and_then(|m| resolve_object(doc, m))
+                            .and_then(|m| m.as_array().ok())
+                            .map(|m| operand_numbers(m))
+                            .filter(|m| m.len() == 6)
+                            .map(|m| [m[0], m[1], m[2], m[3], m[4], m[5]])
+                            .unwrap_or(IDENTITY);
+                        let form_resources = xobject
+                            .dict
+                            .get(b"Resources")
+                            .ok()
+                            .and_then(|r| resolve_object(doc, r))
+                            .and_then(|r| r.as_dict().ok())
+                            .or(resources);
+                        let form_content = xobject.decompressed_content().unwrap_or_else(|_| xobject.content.clone());
+                        collect_image_placements(
+                            doc,
+                            &form_content,
+                            form_resources,
+                            multiply(&matrix, &ctm),
+                            placements,
+                            depth + 1,
+                        )?;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Fraction of the page's visible box covered by at least one image
+fn page_image_coverage(doc: &Document, page_id: ObjectId, default_size: (f64, f64)) -> Result<f64, String> {
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let mut placements = Vec::new();
+    collect_image_placements(doc, &content, page_resources(doc, page_id), IDENTITY, &mut placements, 0)?;
+    if placements.is_empty() {
+        return Ok(0.0);
+    }
+
+    let [x0, y0, x1, y1] = page_box_or_default(doc, page_id, default_size);
+    let mut covered = 0;
+    for row in 0..COVERAGE_GRID {
+        for column in 0..COVERAGE_GRID {
+            let x = x0 + (x1 - x0) * (column as f64 + 0.5) / COVERAGE_GRID as f64;
+            let y = y0 + (y1 - y0) * (row as f64 + 0.5) / COVERAGE_GRID as f64;
+            if placements.iter().any(|&[left, bottom, right, top]| x >= left && x <= right && y >= bottom && y <= top) {
+                covered += 1;
+            }
+        }
+    }
+    Ok(covered as f64 / (COVERAGE_GRID * COVERAGE_GRID) as f64)
+}
+
+// Whether a page's content stream paints anything at all
+fn page_has_marks(doc: &Document, page_id: ObjectId) -> Result<bool, String> {
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let content = lopdf::content::Content::decode(&content).map_err(|e| e.to_string())?;
+    Ok(content.operations.iter().any(|operation| {
+        matches!(
+            operation.operator.as_str(),
+            "S" | "s" | "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "sh" | "Do" | "Tj" | "TJ" | "'" | "\"" | "BI"
+        )
+    }))
+}
+
+// Classify each page as digital text, a scan, a mix of both, or blank, to choose between
+// text extraction and OCR. Pages with vector drawings but no text count as TextNative.
+#[tauri::command]
+async fn classify_pages(state: State<'_, AppState>, path: String) -> Result<Vec<PageClass>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    with_document(&state, &path, |doc| {
+        let mut classes = Vec::new();
+        for page_id in doc.get_pages().into_values() {
+            let has_text = page_text_spans(doc, page_id)?
+                .iter()
+                .any(|span| !span.text.trim().is_empty());
+            let scanned = page_image_coverage(doc, page_id, default_size)? >= SCANNED_IMAGE_COVERAGE;
+
+            let class = match (has_text, scanned) {
+                (false, true) => PageClass::Scanned,
+                (true, true) => PageClass::Mixed,
+                (true, false) => PageClass::TextNative,
+                (false, false) if page_has_marks(doc, page_id)? => PageClass::TextNative,
+                (false, false) => PageClass::Empty,
+            };
+            classes.push(class);
+        }
+        Ok(classes)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GcReport {
+    removed: usize,
+    before: usize,
+    after: usize,
+}
+
+// Drop objects that can't be reached from the trailer and renumber the rest densely.
+// Unlike optimization passes, stream data is written out untouched.
+#[tauri::command]
+async fn garbage_collect(state: State<'_, AppState>, path: String, output_path: String) -> Result<GcReport, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    if doc.is_encrypted() {
+        // Encrypted strings and streams are keyed by object number, so renumbering would corrupt them
+        return Err("Document is encrypted".to_string());
+    }
+    let before = doc.objects.len();
+
+    let mut roots = Vec::new();
+    for (key, value) in doc.trailer.iter() {
+        if key != b"Prev" && key != b"XRefStm" {
+            gather_references(value, &mut roots);
+        }
+    }
+    let stop = std::collections::HashSet::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut reachable = Vec::new();
+    for root in roots {
+        collect_reachable(&doc, root, &stop, &mut visited, &mut reachable);
+    }
+    reachable.sort();
+
+    let renumbered: HashMap<ObjectId, ObjectId> = reachable
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| (id, (index as u32 + 1, 0)))
+        .collect();
+    let mut objects = BTreeMap::new();
+    for id in &reachable {
+        if let Some(mut object) = doc.objects.remove(id) {
+            remap_references(&mut object, &renumbered);
+            objects.insert(renumbered[id], object);
+        }
+    }
+    doc.objects = objects;
+    doc.max_id = doc.objects.len() as u32;
+    for (_, value) in doc.trailer.iter_mut() {
+        remap_references(value, &renumbered);
+    }
+
+    let after = doc.objects.len();
+    save_document(&mut doc, &output_path)?;
+    Ok(GcReport {
+        removed: before - after,
+        before,
+        after,
+    })
+}
+
+// Positioned text spans in page coordinates (origin bottom-left), in reading order:
+// top to bottom, then left to right within a line
+#[tauri::command]
+async fn get_text_layout(state: State<'_, AppState>, path: String, page_num: usize) -> Result<Vec<TextSpan>, String> {
+    let spans = with_document(&state, &path, |doc| page_text_spans(doc, page_id(doc, page_num)?))?;
+    let spans: Vec<TextSpan> = spans.into_iter().filter(|span| !span.text.trim().is_empty()).collect();
+
+    let mut layout = Vec::with_capacity(spans.len());
+    for mut line in group_into_lines(spans) {
+        line.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+        layout.extend(line);
+    }
+    Ok(layout)
+}
+
+// A single page as a standalone PDF, base64-encoded, built entirely in memory
+#[tauri::command]
+async fn extract_page_bytes(state: State<'_, AppState>, path: String, page_num: usize) -> Result<String, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let page_id = match doc.get_pages().get(&(page_num as u32)) {
+        Some(&page_id) => page_id,
+        None => return Err("PageOutOfRange".to_string()),
+    };
+
+    let mut page_doc = build_document_from_pages(&mut doc, &[(page_num, page_id)], &BTreeMap::new())?;
+    let mut bytes = Vec::new();
+    page_doc.save_to(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(general_purpose::STANDARD.encode(bytes))
+}
+
+// Set a text entry in the document information dictionary, creating it if needed
+fn set_info_string(doc: &mut Document, key: &str, value: &str) -> Result<(), String> {
+    let info_id = match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => {
+            let id = doc.add_object(lopdf::Dictionary::new());
+            doc.trailer.set("Info", Object::Reference(id));
+            id
+        }
+    };
+    let info = doc.get_dictionary_mut(info_id).map_err(|e| e.to_string())?;
+    info.set(key, encode_pdf_string(value));
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Replace the contents of an XMP element, if the packet has one. Returns whether it did.
+fn set_xmp_element(doc: &mut Document, element: &str, inner: &str) -> Result<bool, String> {
+    let metadata_id = match doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Metadata"))
+        .and_then(Object::as_reference)
+    {
+        Ok(id) => id,
+        Err(_) => return Ok(false),
+    };
+    let stream = match doc.get_object_mut(metadata_id) {
+        Ok(Object::Stream(stream)) => stream,
+        _ => return Ok(false),
+    };
+
+    let packet = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+    let packet = String::from_utf8_lossy(&packet).into_owned();
+    let (open, close) = (format!("<{}>", element), format!("</{}>", element));
+    let (start, end) = match (packet.find(&open), packet.find(&close)) {
+        (Some(start), Some(end)) if end > start => (start + open.len(), end),
+        _ => return Ok(false),
+    };
+
+    let updated = format!("{}{}{}", &packet[..start], inner, &packet[end..]);
+    stream.dict.remove(b"Filter");
+    stream.dict.remove(b"DecodeParms");
+    stream.set_content(updated.into_bytes());
+    // XMP stays uncompressed so other tools can find it
+    stream.allows_compression = false;
+    Ok(true)
+}
+
+// Set the title and ask viewers to show it in the window bar instead of the file name
+#[tauri::command]
+async fn set_display_title(state: State<'_, AppState>, path: String, title: String) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        set_info_string(doc, "Title", &title)?;
+        let alt = format!(
+            "<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>",
+            escape_xml(&title)
+        );
+        set_xmp_element(doc, "dc:title", &alt)?;
+        update_viewer_preferences(doc, |prefs| prefs.set("DisplayDocTitle", Object::Boolean(true)))
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MetadataDiff {
+    field: String,
+    value_a: Option<String>,
+    value_b: Option<String>,
+}
+
+// Every /Info entry as text; non-string values use their PDF syntax
+fn info_entries(doc: &Document) -> BTreeMap<String, String> {
+    let mut entries = BTreeMap::new();
+    if let Some(info) = info_dictionary(doc) {
+        for (key, value) in info.iter() {
+            let text = match resolve_object(doc, value) {
+                Some(Object::String(bytes, _)) => decode_pdf_string(bytes),
+                Some(Object::Name(name)) => format!("/{}", String::from_utf8_lossy(name)),
+                Some(Object::Integer(number)) => number.to_string(),
+                Some(Object::Real(number)) => number.to_string(),
+                Some(Object::Boolean(flag)) => flag.to_string(),
+                _ => continue,
+            };
+            entries.insert(String::from_utf8_lossy(key).into_owned(), text);
+        }
+    }
+    entries
+}
+
+// /Info fields whose values differ between two documents, including fields only one has
+#[tauri::command]
+async fn diff_metadata(state: State<'_, AppState>, path_a: String, path_b: String) -> Result<Vec<MetadataDiff>, String> {
+    let entries_a = with_document(&state, &path_a, |doc| Ok(info_entries(doc)))?;
+    let entries_b = with_document(&state, &path_b, |doc| Ok(info_entries(doc)))?;
+
+    let fields: std::collections::BTreeSet<&String> = entries_a.keys().chain(entries_b.keys()).collect();
+    Ok(fields
+        .into_iter()
+        .filter(|field| entries_a.get(*field) != entries_b.get(*field))
+        .map(|field| MetadataDiff {
+            field: field.clone(),
+            value_a: entries_a.get(field).cloned(),
+            value_b: entries_b.get(field).cloned(),
+        })
+        .collect())
+}
+
+// Human-readable PDF syntax for an object, with stream data summarized by length
+fn format_object(object: &Object, indent: usize) -> String {
+    let pad = "  ".repeat(indent);
+    match object {
+        Object::Null => "null".to_string(),
+        Object::Boolean(flag) => flag.to_string(),
+        Object::Integer(number) => number.to_string(),
+        Object::Real(number) => number.to_string(),
+        Object::Name(name) => format!("/{}", String::from_utf8_lossy(name)),
+        Object::String(bytes, _) => format!("({})", decode_pdf_string(bytes)),
+        Object::Reference((number, generation)) => format!("{} {} R", number, generation),
+        Object::Array(items) => {
+            let items: Vec<String> = items.iter().map(|item| format_object(item, indent)).collect();
+            format!("[{}]", items.join(" "))
+        }
+        Object::Dictionary(dict) => format_dictionary(dict, indent),
+        Object::Stream(stream) => {
+            let filters: Vec<String> = stream_filters(stream)
+                .iter()
+                .map(|filter| String::from_utf8_lossy(filter).into_owned())
+                .collect();
+            let filters = if filters.is_empty() { String::new() } else { format!(", {}", filters.join(", ")) };
+            format!(
+                "{}\n{}stream ({} bytes{})\n{}endstream",
+                format_dictionary(&stream.dict, indent),
+                pad,
+                stream.content.len(),
+                filters,
+                pad
+            )
+        }
+    }
+}
+
+fn format_dictionary(dict: &lopdf::Dictionary, indent: usize) -> String {
+    if dict.is_empty() {
+        return "<< >>".to_string();
+    }
+    let pad = "  ".repeat(indent + 1);
+    let mut text = String::from("<<\n");
+    for (key, value) in dict.iter() {
+        text.push_str(&format!(
+            "{}/{} {}\n",
+            pad,
+            String::from_utf8_lossy(key),
+            format_object(value, indent + 1)
+        ));
+    }
+    text.push_str(&"  ".repeat(indent));
+    text.push_str(">>");
+    text
+}
+
+#[tauri::command]
+async fn dump_object(state: State<'_, AppState>, path: String, object_id: (u32, u16)) -> Result<String, String> {
+    with_document(&state, &path, |doc| {
+        let object = doc
+            .get_object(object_id)
+            .map_err(|_| format!("Object {} {} R not found", object_id.0, object_id.1))?;
+        Ok(format!("{} {} obj\n{}\nendobj", object_id.0, object_id.1, format_object(object, 0)))
+    })
+}
+
+#[tauri::command]
+async fn dump_trailer(state: State<'_, AppState>, path: String) -> Result<String, String> {
+    with_document(&state, &path, |doc| Ok(format_dictionary(&doc.trailer, 0)))
+}
+
+#[tauri::command]
+async fn dump_catalog(state: State<'_, AppState>, path: String) -> Result<String, String> {
+    with_document(&state, &path, |doc| {
+        let catalog = doc.catalog().map_err(|e| e.to_string())?;
+        Ok(format_dictionary(catalog, 0))
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum ResizeMode {
+    // Scale uniformly and center, leaving margins on one axis
+    Fit,
+    // Scale each axis independently to fill the page
+    Stretch,
+}
+
+// Wrap a page's existing content in operators placed before and after it
+fn wrap_page_content(doc: &mut Document, page_id: ObjectId, before: &[u8], after: &[u8]) -> Result<(), String> {
+    let mut contents = page_content_refs(doc, page_id);
+    let before_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), before.to_vec()));
+    let after_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), after.to_vec()));
+    contents.insert(0, Object::Reference(before_id));
+    contents.push(Object::Reference(after_id));
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    page.set("Contents", Object::Array(contents));
+    Ok(())
+}
+
+// Move a page's annotation rectangles along with content transformed by `matrix`
+fn transform_annotation_rects(doc: &mut Document, page_id: ObjectId, matrix: &Matrix) {
+    let annots: Vec<ObjectId> = page_annotations(doc, page_id)
+        .iter()
+        .filter_map(|annot| annot.as_reference().ok())
+        .collect();
+    for annot_id in annots {
+        let rect = match doc.get_dictionary(annot_id).ok().and_then(|a| a.get(b"Rect").ok()) {
+            Some(rect) => rect_from_object(doc, rect),
+            None => None,
+        };
+        if let (Some([x0, y0, x1, y1]), Ok(annot)) = (rect, doc.get_dictionary_mut(annot_id)) {
+            let (ax, ay) = transform_point(matrix, x0, y0);
+            let (bx, by) = transform_point(matrix, x1, y1);
+            annot.set(
+                "Rect",
+                Object::Array(
+                    [ax.min(bx), ay.min(by), ax.max(bx), ay.max(by)]
+                        .iter()
+                        .map(|&v| Object::Real(v as f32))
+                        .collect(),
+                ),
+            );
+        }
+    }
+}
+
+// Scale a page's content onto a preset size. With `match_orientation` landscape pages
+// get the target in landscape too. Inherited attributes must already be materialized.
+fn resize_page(
+    doc: &mut Document,
+    page_id: ObjectId,
+    target: PageSizePreset,
+    mode: ResizeMode,
+    match_orientation: bool,
+) -> Result<(), String> {
+    let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+    let (width, height) = (x1 - x0, y1 - y0);
+    if width <= 0.0 || height <= 0.0 {
+        return Ok(());
+    }
+
+    let (mut target_w, mut target_h) = target.dimensions();
+    if match_orientation && (width > height) != (target_w > target_h) {
+        std::mem::swap(&mut target_w, &mut target_h);
+    }
+
+    let matrix: Matrix = match mode {
+        ResizeMode::Fit => {
+            let scale = (target_w / width).min(target_h / height);
+            [
+                scale,
+                0.0,
+                0.0,
+                scale,
+                (target_w - width * scale) / 2.0 - x0 * scale,
+                (target_h - height * scale) / 2.0 - y0 * scale,
+            ]
+        }
+        ResizeMode::Stretch => {
+            let (sx, sy) = (target_w / width, target_h / height);
+            [sx, 0.0, 0.0, sy, -x0 * sx, -y0 * sy]
+        }
+    };
+
+    let cm = format!(
+        "q {:.6} 0 0 {:.6} {:.4} {:.4} cm\n",
+        matrix[0], matrix[3], matrix[4], matrix[5]
+    );
+    wrap_page_content(doc, page_id, cm.as_bytes(), b"\nQ\n")?;
+    transform_annotation_rects(doc, page_id, &matrix);
+
+    let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+    let size = Object::Array(vec![
+        Object::Integer(0),
+        Object::Integer(0),
+        Object::Real(target_w as f32),
+        Object::Real(target_h as f32),
+    ]);
+    page.set("MediaBox", size.clone());
+    page.set("CropBox", size);
+    // The old print boxes no longer describe the page
+    for key in [&b"BleedBox"[..], &b"TrimBox"[..], &b"ArtBox"[..]] {
+        page.remove(key);
+    }
+    Ok(())
+}
+
+// Resize every page to a preset size. With `match_orientation` (the default) landscape
+// pages get the target in landscape too.
+#[tauri::command]
+async fn unify_page_size(
+    state: State<'_, AppState>,
+    path: String,
+    target: PageSizePreset,
+    mode: ResizeMode,
+    match_orientation: Option<bool>,
+) -> Result<(), String> {
+    let match_orientation = match_orientation.unwrap_or(true);
+    edit_document(&state, &path, |doc| {
+        for page_id in doc.get_pages().into_values() {
+            materialize_inherited_attributes(doc, page_id)?;
+            resize_page(doc, page_id, target, mode, match_orientation)?;
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FontIssue {
+    page: usize,
+    font: String,
+    problem: String,
+}
+
+// Character codes shown with each font resource in a page's content stream
+fn used_font_codes(
+    doc: &Document,
+    page_id: ObjectId,
+    fonts: &HashMap<Vec<u8>, FontInfo>,
+) -> Result<HashMap<Vec<u8>, std::collections::BTreeSet<u32>>, String> {
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    font_codes_in_content(&content, fonts)
+}
+
+fn font_codes_in_content(
+    content: &[u8],
+    fonts: &HashMap<Vec<u8>, FontInfo>,
+) -> Result<HashMap<Vec<u8>, std::collections::BTreeSet<u32>>, String> {
+    let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+    let mut used: HashMap<Vec<u8>, std::collections::BTreeSet<u32>> = HashMap::new();
+    let mut current: Option<Vec<u8>> = None;
+
+    for operation in &content.operations {
+        let strings: Vec<&[u8]> = match operation.operator.as_str() {
+            "Tf" => {
+                current = operation.operands.first().and_then(|o| o.as_name().ok()).map(|n| n.to_vec());
+                continue;
+            }
+            "Tj" | "'" | "\"" => operation
+                .operands
+                .iter()
+                .filter_map(|o| o.as_str().ok())
+                .collect(),
+            "TJ" => match operation.operands.first() {
+                Some(Object::Array(items)) => items.iter().filter_map(|o| o.as_str().ok()).collect(),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let (name, font) = match current.as_ref().and_then(|name| fonts.get(name).map(|font| (name, font))) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let codes = used.entry(name.clone()).or_default();
+        for bytes in strings {
+            codes.extend(font.codes(bytes));
+        }
+    }
+    Ok(used)
+}
+
+// Subset fonts carry a six-letter tag, e.g. ABCDEF+Helvetica
+fn is_subset_font_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 7 && bytes[6] == b'+' && bytes[..6].iter().all(u8::is_ascii_uppercase)
+}
+
+// Glyph count from the 'maxp' table of a TrueType program
+fn truetype_glyph_count(program: &[u8]) -> Option<u32> {
+    let read_u16 = |at: usize| program.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32);
+    let read_u32 = |at: usize| program.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let table_count = read_u16(4)? as usize;
+    for index in 0..table_count {
+        let record = 12 + index * 16;
+        if program.get(record..record + 4)? == b"maxp" {
+            return read_u16(read_u32(record + 8)? + 4);
+        }
+    }
+    None
+}
+
+// Codes a CID font uses that map to glyphs missing from its embedded TrueType program,
+// or None when the program can't be inspected
+fn missing_cid_glyphs(
+    doc: &Document,
+    font: &lopdf::Dictionary,
+    codes: &std::collections::BTreeSet<u32>,
+) -> Option<Vec<u32>> {
+    let descendant = font
+        .get(b"DescendantFonts")
+        .ok()
+        .and_then(|fonts| resolve_object(doc, fonts))
+        .and_then(|fonts| fonts.as_array().ok())
+        .and_then(|fonts| fonts.first())
+        .and_then(|font| resolve_object(doc, font))
+        .and_then(|font| font.as_dict().ok())?;
+    let program = descendant
+        .get(b"FontDescriptor")
+        .ok()
+        .and_then(|d| resolve_object(doc, d))
+        .and_then(|d| d.as_dict().ok())
+        .and_then(|d| d.get(b"FontFile2").ok())
+        .and_then(|file| resolve_object(doc, file))
+        .and_then(|file| file.as_stream().ok())?;
+    let program = program.decompressed_content().unwrap_or_else(|_| program.content.clone());
+    let glyph_count = truetype_glyph_count(&program)?;
+
+    // Without a CIDToGIDMap stream, CIDs are glyph indices (Identity)
+    let gid_map = descendant
+        .get(b"CIDToGIDMap")
+        .ok()
+        .and_then(|map| resolve_object(doc, map))
+        .and_then(|map| map.as_stream().ok())
+        .map(|map| map.decompressed_content().unwrap_or_else(|_| map.content.clone()));
+    let glyph_for = |cid: u32| match &gid_map {
+        Some(map) => map
+            .get(cid as usize * 2..cid as usize * 2 + 2)
+            .map_or(0, |b| u16::from_be_bytes([b[0], b[1]]) as u32),
+        None => cid,
+    };
+
+    Some(
+        codes
+            .iter()
+            .copied()
+            .filter(|&cid| {
+                let glyph = glyph_for(cid);
+                glyph == 0 || glyph >= glyph_count
+            })
+            .collect(),
+    )
+}
+
+fn format_codes(codes: &[u32]) -> String {
+    let mut listed: Vec<String> = codes.iter().take(10).map(|code| format!("{:#06x}", code)).collect();
+    if codes.len() > 10 {
+        listed.push(format!("and {} more", codes.len() - 10));
+    }
+    listed.join(", ")
+}
+
+// Fonts a viewer will have to substitute, and subset fonts whose embedded program
+// lacks glyphs the text uses. Simple subset fonts are checked against their /Widths,
+// since a code the subsetter dropped is normally left without a width.
+#[tauri::command]
+async fn check_font_coverage(state: State<'_, AppState>, path: String) -> Result<Vec<FontIssue>, String> {
+    with_document(&state, &path, |doc| {
+        let mut issues = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            let resources = page_resources(doc, page_id);
+            let fonts = load_fonts(doc, resources);
+            let used = used_font_codes(doc, page_id, &fonts)?;
+            let font_dicts = resources.and_then(|r| resource_category(doc, r, b"Font"));
+
+            let mut names: Vec<&Vec<u8>> = used.keys().collect();
+            names.sort();
+            for name in names {
+                let (info, codes) = (&fonts[name], &used[name]);
+                let font_name = if info.base_font.is_empty() {
+                    String::from_utf8_lossy(name).into_owned()
+                } else {
+                    info.base_font.clone()
+                };
+                let mut report = |problem: String| {
+                    issues.push(FontIssue {
+                        page: page_num as usize,
+                        font: font_name.clone(),
+                        problem,
+                    })
+                };
+
+                if !info.embedded {
+                    report("Font is not embedded; viewers will substitute a system font".to_string());
+                    continue;
+                }
+                if !is_subset_font_name(&info.base_font) {
+                    continue;
+                }
+
+                let missing: Vec<u32> = if info.two_byte {
+                    let font = font_dicts
+                        .and_then(|fonts| fonts.get(name).ok())
+                        .and_then(|font| resolve_object(doc, font))
+                        .and_then(|font| font.as_dict().ok());
+                    match font.and_then(|font| missing_cid_glyphs(doc, font, codes)) {
+                        Some(missing) => missing,
+                        None => continue,
+                    }
+                } else {
+                    codes
+                        .iter()
+                        .copied()
+                        .filter(|&code| {
+                            let index = code.checked_sub(info.first_char).map(|i| i as usize);
+                            index.and_then(|i| info.widths.get(i)).map_or(true, |&width| width == 0.0)
+                        })
+                        .collect()
+                };
+                if !missing.is_empty() {
+                    report(format!("Subset font is missing glyphs for codes {}", format_codes(&missing)));
+                }
+            }
+        }
+        Ok(issues)
+    })
+}
+
+// Helvetica advance widths for ASCII 32..=126, in thousandths of an em
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667,
+    556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556,
+    556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722,
+    500, 500, 500, 334, 260, 334, 584,
+];
+
+// Width of WinAnsi-encoded text set in Helvetica at the given size
+fn helvetica_text_width(bytes: &[u8], font_size: f64) -> f64 {
+    let units: u32 = bytes
+        .iter()
+        .map(|&b| match b {
+            32..=126 => HELVETICA_WIDTHS[(b - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f64 / 1000.0 * font_size
+}
+
+// Latin-1 bytes for a standard font, with characters it can't show replaced by '?'
+fn encode_standard_font_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if (c as u32) <= 0xFF { c as u8 } else { b'?' })
+        .collect()
+}
+
+fn standard_font(base_font: &str) -> lopdf::Dictionary {
+    let mut font = lopdf::Dictionary::new();
+    font.set("Type", Object::Name(b"Font".to_vec()));
+    font.set("Subtype", Object::Name(b"Type1".to_vec()));
+    font.set("BaseFont", Object::Name(base_font.as_bytes().to_vec()));
+    font.set("Encoding", Object::Name(b"WinAnsiEncoding".to_vec()));
+    font
+}
+
+// Operators drawing one horizontally centered line of Helvetica text. The size shrinks
+// if needed so the line fits within `max_width`.
+fn centered_text_line(
+    font: &str,
+    text: &str,
+    font_size: f64,
+    center_x: f64,
+    baseline: f64,
+    max_width: f64,
+) -> Vec<lopdf::content::Operation> {
+    use lopdf::content::Operation;
+
+    let bytes = encode_standard_font_text(text);
+    let natural = helvetica_text_width(&bytes, font_size);
+    let size = if natural > max_width { font_size * max_width / natural } else { font_size };
+    let x = center_x - helvetica_text_width(&bytes, size) / 2.0;
+    vec![
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(font.as_bytes().to_vec()), Object::Real(size as f32)]),
+        Operation::new("Td", vec![Object::Real(x as f32), Object::Real(baseline as f32)]),
+        Operation::new("Tj", vec![Object::String(bytes, lopdf::StringFormat::Literal)]),
+        Operation::new("ET", vec![]),
+    ]
+}
+
+// The page size used by the most pages, preferring the earliest on ties
+fn dominant_page_size(doc: &Document, default_size: (f64, f64)) -> (f64, f64) {
+    let sizes: Vec<(f64, f64)> = (1..=doc.get_pages().len())
+        .map(|page_num| page_size_in_units(doc, page_num, default_size).unwrap_or(default_size))
+        .collect();
+    most_common_size(&sizes).unwrap_or(default_size)
+}
+
+// Sizes within a point of each other count as the same paper size
+fn same_page_size(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() < 1.0 && (a.1 - b.1).abs() < 1.0
+}
+
+fn most_common_size(sizes: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let mut counts: Vec<((f64, f64), usize)> = Vec::new();
+    for &size in sizes {
+        match counts.iter_mut().find(|(known, _)| same_page_size(*known, size)) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((size, 1)),
+        }
+    }
+    counts
+        .iter()
+        .fold(None, |best: Option<&((f64, f64), usize)>, entry| match best {
+            Some(best) if best.1 >= entry.1 => Some(best),
+            _ => Some(entry),
+        })
+        .map(|&(size, _)| size)
+}
+
+// Insert a title page, sized like most of the document's pages, at the front or back
+#[tauri::command]
+async fn add_cover_page(
+    state: State<'_, AppState>,
+    path: String,
+    title: String,
+    subtitle: Option<String>,
+    at_start: bool,
+) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    edit_document(&state, &path, |doc| {
+        let (width, height) = dominant_page_size(doc, default_size);
+        let max_width = width * 0.8;
+
+        let mut operations = centered_text_line("F1", &title, 36.0, width / 2.0, height * 0.6, max_width);
+        if let Some(subtitle) = subtitle.as_deref().filter(|s| !s.trim().is_empty()) {
+            operations.extend(centered_text_line("F1", subtitle, 18.0, width / 2.0, height * 0.6 - 48.0, max_width));
+        }
+        let content = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+
+        let cover_id = create_blank_page(doc, width, height);
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", Object::Reference(font_id));
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("Font", Object::Dictionary(fonts));
+        doc.get_dictionary_mut(cover_id)
+            .map_err(|e| e.to_string())?
+            .set("Resources", Object::Dictionary(resources));
+        set_page_content(doc, cover_id, content)?;
+
+        let mut order: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        if at_start {
+            order.insert(0, cover_id);
+        } else {
+            order.push(cover_id);
+        }
+        apply_page_order(doc, &order)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutputIntent {
+    subtype: String,
+    output_condition_identifier: Option<String>,
+    info: Option<String>,
+    components: Option<i64>,
+    profile_size: Option<usize>,
+}
+
+// Number of color components from an ICC profile header's data color space
+fn icc_components(profile: &[u8]) -> Result<i64, String> {
+    match profile.get(16..20) {
+        Some(b"GRAY") => Ok(1),
+        Some(b"RGB ") => Ok(3),
+        Some(b"CMYK") => Ok(4),
+        Some(other) => Err(format!("Unsupported ICC color space '{}'", String::from_utf8_lossy(other).trim())),
+        None => Err("Not an ICC profile".to_string()),
+    }
+}
+
+// The first output intent in the catalog, if any
+#[tauri::command]
+async fn get_output_intent(state: State<'_, AppState>, path: String) -> Result<Option<OutputIntent>, String> {
+    with_document(&state, &path, |doc| {
+        let intent = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"OutputIntents").ok())
+            .and_then(|intents| resolve_object(doc, intents))
+            .and_then(|intents| intents.as_array().ok())
+            .and_then(|intents| intents.first())
+            .and_then(|intent| resolve_object(doc, intent))
+            .and_then(|intent| intent.as_dict().ok());
+        let intent = match intent {
+            Some(intent) => intent,
+            None => return Ok(None),
+        };
+
+        let text = |key: &[u8]| {
+            intent
+                .get(key)
+                .ok()
+                .and_then(|value| resolve_object(doc, value))
+                .and_then(|value| value.as_str().ok())
+                .map(decode_pdf_string)
+        };
+        let profile = intent
+            .get(b"DestOutputProfile")
+            .ok()
+            .and_then(|profile| resolve_object(doc, profile))
+            .and_then(|profile| profile.as_stream().ok());
+
+        Ok(Some(OutputIntent {
+            subtype: intent
+                .get(b"S")
+                .and_then(Object::as_name)
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .unwrap_or_default(),
+            output_condition_identifier: text(b"OutputConditionIdentifier"),
+            info: text(b"Info"),
+            components: profile.and_then(|p| p.dict.get(b"N").and_then(Object::as_i64).ok()),
+            profile_size: profile.map(|p| p.decompressed_content().map_or(p.content.len(), |bytes| bytes.len())),
+        }))
+    })
+}
+
+// Embed an ICC profile as the document's output intent, replacing any existing ones.
+// `subtype` is the intent's /S, e.g. GTS_PDFX or GTS_PDFA1.
+#[tauri::command]
+async fn set_output_intent(
+    state: State<'_, AppState>,
+    path: String,
+    icc_path: String,
+    subtype: String,
+) -> Result<(), String> {
+    let profile = std::fs::read(&icc_path).map_err(|e| e.to_string())?;
+    let components = icc_components(&profile)?;
+    let identifier = std::path::Path::new(&icc_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Custom".to_string());
+    let subtype = subtype.trim_start_matches('/').to_string();
+
+    edit_document(&state, &path, |doc| {
+        let mut profile_dict = lopdf::Dictionary::new();
+        profile_dict.set("N", Object::Integer(components));
+        let mut stream = lopdf::Stream::new(profile_dict, profile.clone());
+        stream.compress().map_err(|e| e.to_string())?;
+        let profile_id = doc.add_object(stream);
+
+        let mut intent = lopdf::Dictionary::new();
+        intent.set("Type", Object::Name(b"OutputIntent".to_vec()));
+        intent.set("S", Object::Name(subtype.as_bytes().to_vec()));
+        intent.set("OutputConditionIdentifier", encode_pdf_string(&identifier));
+        intent.set("Info", encode_pdf_string(&identifier));
+        intent.set("DestOutputProfile", Object::Reference(profile_id));
+        let intent_id = doc.add_object(intent);
+
+        doc.catalog_mut()
+            .map_err(|e| e.to_string())?
+            .set("OutputIntents", Object::Array(vec![Object::Reference(intent_id)]));
+        Ok(())
+    })
+}
+
+// Lock or unlock a cached document. While locked, edit commands fail with `ReadOnly`;
+// reading, exporting and saving still work.
+#[tauri::command]
+async fn set_readonly(state: State<'_, AppState>, path: String, readonly: bool) -> Result<(), String> {
+    let mut documents = state.documents.lock().map_err(|e| e.to_string())?;
+    open_document(&mut documents, &path)?.readonly = readonly;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PdfAInfo {
+    part: u32,
+    conformance: Option<String>,
+    // Part and conformance together, e.g. "2B"
+    level: String,
+    // Obvious problems that contradict the claim
+    issues: Vec<String>,
+}
+
+// The catalog's XMP metadata packet as text
+fn xmp_packet(doc: &Document) -> Option<String> {
+    let metadata = doc.catalog().ok()?.get(b"Metadata").ok()?;
+    let stream = resolve_object(doc, metadata)?.as_stream().ok()?;
+    let bytes = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// Value of an XMP property written either as an element or as an attribute
+fn xmp_property(packet: &str, name: &str) -> Option<String> {
+    let open = format!("<{}>", name);
+    if let Some(start) = packet.find(&open).map(|i| i + open.len()) {
+        let end = packet[start..].find('<')? + start;
+        return Some(packet[start..end].trim().to_string());
+    }
+    for quote in ['"', '\''] {
+        let attribute = format!("{}={}", name, quote);
+        if let Some(start) = packet.find(&attribute).map(|i| i + attribute.len()) {
+            let end = packet[start..].find(quote)? + start;
+            return Some(packet[start..end].trim().to_string());
+        }
+    }
+    None
+}
+
+// The PDF/A level a file claims in its XMP metadata, or None if it makes no claim.
+// This only checks the claim plus a few cheap structural requirements (embedded fonts,
+// an output intent, no encryption); it is not a conformance validator.
+#[tauri::command]
+async fn get_pdfa_info(state: State<'_, AppState>, path: String) -> Result<Option<PdfAInfo>, String> {
+    with_document(&state, &path, |doc| {
+        let packet = match xmp_packet(doc) {
+            Some(packet) => packet,
+            None => return Ok(None),
+        };
+        let part = match xmp_property(&packet, "pdfaid:part").and_then(|part| part.parse::<u32>().ok()) {
+            Some(part) => part,
+            None => return Ok(None),
+        };
+        let conformance = xmp_property(&packet, "pdfaid:conformance").map(|c| c.to_uppercase());
+
+        let mut issues = Vec::new();
+        if doc.is_encrypted() {
+            issues.push("Document is encrypted".to_string());
+        }
+        let has_output_intent = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"OutputIntents").ok())
+            .and_then(|intents| resolve_object(doc, intents))
+            .and_then(|intents| intents.as_array().ok())
+            .map_or(false, |intents| !intents.is_empty());
+        if !has_output_intent {
+            issues.push("No output intent".to_string());
+        }
+
+        let mut unembedded: Vec<String> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| matches!(dict.get(b"Type"), Ok(Object::Name(name)) if name == b"Font"))
+            .filter(|dict| !matches!(dict.get(b"Subtype"), Ok(Object::Name(name)) if name == b"CIDFontType0" || name == b"CIDFontType2"))
+            .map(|dict| load_font_info(doc, dict))
+            .filter(|font| !font.embedded)
+            .map(|font| font.base_font)
+            .collect();
+        unembedded.sort();
+        unembedded.dedup();
+        for font in unembedded {
+            issues.push(format!("Font {} is not embedded", font));
+        }
+
+        let level = format!("{}{}", part, conformance.clone().unwrap_or_default());
+        Ok(Some(PdfAInfo {
+            part,
+            conformance,
+            level,
+            issues,
+        }))
+    })
+}
+
+fn fold_case(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+// Start offsets (in chars) of non-overlapping matches of `query` in `text`
+fn find_char_matches(text: &[char], query: &[char]) -> Vec<usize> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while !query.is_empty() && start + query.len() <= text.len() {
+        if text[start..start + query.len()] == *query {
+            matches.push(start);
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+// Redaction boxes run from a quarter of the font size below the baseline to just above
+// the font size, covering descenders and accents
+const REDACTION_DESCENT: f64 = 0.25;
+const REDACTION_ASCENT: f64 = 0.95;
+
+type Quad = [(f64, f64); 4];
+
+fn transform_quad(m: &Matrix, quad: &Quad) -> Quad {
+    quad.map(|(x, y)| transform_point(m, x, y))
+}
+
+// Where a text-showing operator drew its glyphs, and the text state it used
+struct ShowOperation {
+    index: usize,
+    font: Vec<u8>,
+    font_size: f64,
+    char_spacing: f64,
+    word_spacing: f64,
+    rise: f64,
+    // Text matrix times CTM at the start of the operator
+    start: Matrix,
+}
+
+// One glyph of a text object; `element` is the string's position among the operator's
+// shown strings and `code` the glyph's position in that string
+struct ShownGlyph {
+    show: usize,
+    element: usize,
+    code: usize,
+    // Advance along the baseline before and after the glyph, in text space
+    from: f64,
+    to: f64,
+}
+
+// Text shown so far in the current BT ... ET object
+#[derive(Default)]
+struct TextObject {
+    glyphs: Vec<ShownGlyph>,
+    // Folded characters, each owned by a glyph or a gap between runs
+    chars: Vec<char>,
+    owners: Vec<Option<usize>>,
+    // End of the last glyph in base space, and the size of its font
+    end: Option<((f64, f64), f64)>,
+}
+
+// A content stream after redaction: the new bytes if anything was removed, quads
+// covering the removed glyphs in the stream's base space, and the number of matches
+struct RedactedContent {
+    content: Option<Vec<u8>>,
+    boxes: Vec<Quad>,
+    matches: usize,
+}
+
+// Mark every match in a finished text object for removal, and box the removed glyphs,
+// one quad per contiguous run within a single operator
+fn close_text_object(
+    object: &mut TextObject,
+    shows: &[ShowOperation],
+    query: &[char],
+    removed: &mut std::collections::HashSet<(usize, usize, usize)>,
+    boxes: &mut Vec<Quad>,
+) -> usize {
+    let object = std::mem::take(object);
+    let matches = find_char_matches(&object.chars, query);
+    for &start in &matches {
+        let mut glyphs: Vec<usize> = object.owners[start..start + query.len()].iter().flatten().copied().collect();
+        glyphs.dedup();
+
+        let mut run: Option<(usize, f64, f64)> = None;
+        for index in glyphs {
+            let glyph = &object.glyphs[index];
+            removed.insert((shows[glyph.show].index, glyph.element, glyph.code));
+            run = match run {
+                Some((show, from, _)) if show == glyph.show => Some((show, from, glyph.to)),
+                previous => {
+                    if let Some(previous) = previous {
+                        boxes.push(run_quad(&shows[previous.0], previous.1, previous.2));
+                    }
+                    Some((glyph.show, glyph.from, glyph.to))
+                }
+            };
+        }
+        if let Some((show, from, to)) = run {
+            boxes.push(run_quad(&shows[show], from, to));
+        }
+    }
+    matches.len()
+}
+
+fn run_quad(show: &ShowOperation, from: f64, to: f64) -> Quad {
+    let bottom = show.rise - show.font_size.abs() * REDACTION_DESCENT;
+    let top = show.rise + show.font_size.abs() * REDACTION_ASCENT;
+    transform_quad(&show.start, &[(from, bottom), (to, bottom), (to, top), (from, top)])
+}
+
+// The TJ array a text-showing operator becomes once some of its glyphs are removed.
+// Removed glyphs turn into adjustments of the same advance, so the text after them
+// keeps its position.
+fn redacted_show_elements(
+    operation: &lopdf::content::Operation,
+    show: &ShowOperation,
+    font: &FontInfo,
+    removed: &std::collections::HashSet<(usize, usize, usize)>,
+) -> Vec<Object> {
+    let shown: Vec<Object> = match operation.operator.as_str() {
+        "TJ" => match operation.operands.first() {
+            Some(Object::Array(items)) => items.clone(),
+            _ => Vec::new(),
+        },
+        _ => operation.operands.last().cloned().into_iter().collect(),
+    };
+    let encode = |code: u32, out: &mut Vec<u8>| {
+        if font.two_byte {
+            out.extend_from_slice(&[(code >> 8) as u8, code as u8]);
+        } else {
+            out.push(code as u8);
+        }
+    };
+
+    let mut elements = Vec::new();
+    let mut gap = 0.0;
+    for (element, item) in shown.into_iter().enumerate() {
+        let bytes = match item {
+            Object::String(bytes, _) => bytes,
+            other => {
+                elements.push(other);
+                continue;
+            }
+        };
+        let mut kept = Vec::new();
+        for (position, code) in font.codes(&bytes).into_iter().enumerate() {
+            if removed.contains(&(show.index, element, position)) {
+                if !kept.is_empty() {
+                    elements.push(Object::String(std::mem::take(&mut kept), lopdf::StringFormat::Hexadecimal));
+                }
+                // Character and word spacing are part of the glyph's advance too
+                let mut spacing = show.char_spacing;
+                if !font.two_byte && code == 32 {
+                    spacing += show.word_spacing;
+                }
+                gap += font.width(code) + if show.font_size != 0.0 { spacing / show.font_size } else { 0.0 };
+            } else {
+                if gap != 0.0 {
+                    elements.push(Object::Real((-gap * 1000.0) as f32));
+                    gap = 0.0;
+                }
+                encode(code, &mut kept);
+            }
+        }
+        if !kept.is_empty() {
+            elements.push(Object::String(kept, lopdf::StringFormat::Hexadecimal));
+        }
+    }
+    if gap != 0.0 {
+        elements.push(Object::Real((-gap * 1000.0) as f32));
+    }
+    elements
+}
+
+// Remove matches of `query` from a content stream and the form XObjects it draws.
+// Matches are searched in the text of each BT ... ET object as a whole, so they may
+// span strings, TJ elements and text-showing operators. Forms are rewritten in place
+// once; `forms` remembers their boxes (in the space they are drawn in) and match
+// counts for later uses.
+fn redact_content(
+    doc: &mut Document,
+    content: &[u8],
+    resources: Option<&lopdf::Dictionary>,
+    query: &[char],
+    case_sensitive: bool,
+    forms: &mut HashMap<ObjectId, (Vec<Quad>, usize)>,
+    depth: usize,
+) -> Result<RedactedContent, String> {
+    use lopdf::content::Operation;
+
+    let mut result = RedactedContent { content: None, boxes: Vec::new(), matches: 0 };
+    if depth > 8 {
+        return Ok(result);
+    }
+    let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+    let fonts = load_fonts(doc, resources);
+    let mut state = TextState {
+        ctm: IDENTITY,
+        char_spacing: 0.0,
+        word_spacing: 0.0,
+        horizontal_scale: 1.0,
+        leading: 0.0,
+        rise: 0.0,
+        font: Vec::new(),
+        font_size: 0.0,
+    };
+    let mut stack: Vec<TextState> = Vec::new();
+    let mut tm = IDENTITY;
+    let mut tlm = IDENTITY;
+    let mut shows: Vec<ShowOperation> = Vec::new();
+    let mut object = TextObject::default();
+    let mut removed = std::collections::HashSet::new();
+
+    for (index, operation) in content.operations.iter().enumerate() {
+        let numbers = operand_numbers(&operation.operands);
+        match operation.operator.as_str() {
+            "q" => stack.push(state.clone()),
+            "Q" => {
+                if let Some(previous) = stack.pop() {
+                    state = previous;
+                }
+            }
+            "cm" if numbers.len() == 6 => {
+                let m = [numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]];
+                state.ctm = multiply(&m, &state.ctm);
+            }
+            "BT" => {
+                result.matches += close_text_object(&mut object, &shows, query, &mut removed, &mut result.boxes);
+                tm = IDENTITY;
+                tlm = IDENTITY;
+            }
+            "ET" => {
+                result.matches += close_text_object(&mut object, &shows, query, &mut removed, &mut result.boxes);
+            }
+            "Tf" => {
+                if let Some(name) = operation.operands.first().and_then(|n| n.as_name().ok()) {
+                    state.font = name.to_vec();
+                }
+                state.font_size = numbers.first().copied().unwrap_or(0.0);
+            }
+            "Tc" => state.char_spacing = numbers.first().copied().unwrap_or(0.0),
+            "Tw" => state.word_spacing = numbers.first().copied().unwrap_or(0.0),
+            "Tz" => state.horizontal_scale = numbers.first().copied().unwrap_or(100.0) / 100.0,
+            "TL" => state.leading = numbers.first().copied().unwrap_or(0.0),
+            "Ts" => state.rise = numbers.first().copied().unwrap_or(0.0),
+            "Td" | "TD" if numbers.len() == 2 => {
+                if operation.operator == "TD" {
+                    state.leading = -numbers[1];
+                }
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, numbers[0], numbers[1]], &tlm);
+                tm = tlm;
+            }
+            "Tm" if numbers.len() == 6 => {
+                tlm = [numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5]];
+                tm = tlm;
+            }
+            "T*" => {
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                tm = tlm;
+            }
+            "Tj" | "'" | "\"" | "TJ" => {
+                if operation.operator == "'" || operation.operator == "\"" {
+                    if operation.operator == "\"" && numbers.len() >= 2 {
+                        state.word_spacing = numbers[0];
+                        state.char_spacing = numbers[1];
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                    tm = tlm;
+                }
+                let font = match fonts.get(&state.font) {
+                    Some(font) => font,
+                    None => continue,
+                };
+
+                let start = multiply(&tm, &state.ctm);
+                let show = shows.len();
+                shows.push(ShowOperation {
+                    index,
+                    font: state.font.clone(),
+                    font_size: state.font_size,
+                    char_spacing: state.char_spacing,
+                    word_spacing: state.word_spacing,
+                    rise: state.rise,
+                    start,
+                });
+                // Runs that start visibly apart from the previous one read as separate words
+                let origin = transform_point(&start, 0.0, state.rise);
+                if let Some(((x, y), size)) = object.end {
+                    let gap = ((origin.0 - x).powi(2) + (origin.1 - y).powi(2)).sqrt();
+                    if gap > size.abs() * WORD_GAP_RATIO && object.chars.last().is_some_and(|c| !c.is_whitespace()) {
+                        object.chars.push(' ');
+                        object.owners.push(None);
+                    }
+                }
+
+                let shown: Vec<&Object> = match operation.operator.as_str() {
+                    "TJ" => match operation.operands.first() {
+                        Some(Object::Array(items)) => items.iter().collect(),
+                        _ => Vec::new(),
+                    },
+                    _ => operation.operands.last().into_iter().collect(),
+                };
+                let mut advance = 0.0;
+                for (element, item) in shown.into_iter().enumerate() {
+                    let bytes = match item {
+                        Object::String(bytes, _) => bytes,
+                        other => {
+                            if let Ok(adjust) = other.as_float() {
+                                advance -= adjust as f64 / 1000.0 * state.font_size * state.horizontal_scale;
+                                if adjust < -TJ_WORD_GAP && object.chars.last().is_some_and(|c| !c.is_whitespace()) {
+                                    object.chars.push(' ');
+                                    object.owners.push(None);
+                                }
+                            }
+                            continue;
+                        }
+                    };
+                    for (position, code) in font.codes(bytes).into_iter().enumerate() {
+                        let mut glyph = font.width(code) * state.font_size + state.char_spacing;
+                        if !font.two_byte && code == 32 {
+                            glyph += state.word_spacing;
+                        }
+                        let from = advance;
+                        advance += glyph * state.horizontal_scale;
+                        for c in font.decode(code).chars() {
+                            object.chars.push(fold_case(c, case_sensitive));
+                            object.owners.push(Some(object.glyphs.len()));
+                        }
+                        object.glyphs.push(ShownGlyph { show, element, code: position, from, to: advance });
+                    }
+                }
+                object.end = Some((transform_point(&start, advance, state.rise), state.font_size));
+                tm = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+            }
+            "Do" => {
+                let form_id = operation
+                    .operands
+                    .first()
+                    .and_then(|name| name.as_name().ok())
+                    .and_then(|name| resources.and_then(|r| resource_category(doc, r, b"XObject"))?.get(name).ok())
+                    .and_then(|xobject| xobject.as_reference().ok());
+                let form_id = match form_id {
+                    Some(id) => id,
+                    None => continue,
+                };
+                if !forms.contains_key(&form_id) {
+                    let form = match doc.get_object(form_id).and_then(Object::as_stream) {
+                        Ok(form) if matches!(form.dict.get(b"Subtype"), Ok(Object::Name(subtype)) if subtype == b"Form") => {
+                            form.clone()
+                        }
+                        _ => continue,
+                    };
+                    let matrix = form
+                        .dict
+                        .get(b"Matrix")
+                        .ok()
+                        .and_then(|m| m.as_array().ok())
+                        .map(|m| operand_numbers(m))
+                        .filter(|m| m.len() == 6)
+                        .map_or(IDENTITY, |m| [m[0], m[1], m[2], m[3], m[4], m[5]]);
+                    let form_resources = form
+                        .dict
+                        .get(b"Resources")
+                        .ok()
+                        .and_then(|r| resolve_object(doc, r))
+                        .and_then(|r| r.as_dict().ok())
+                        .or(resources)
+                        .cloned();
+                    let form_content = form.decompressed_content().unwrap_or_else(|_| form.content.clone());
+                    // Claimed before recursing so a form that draws itself stops here
+                    forms.insert(form_id, (Vec::new(), 0));
+                    let redacted =
+                        redact_content(doc, &form_content, form_resources.as_ref(), query, case_sensitive, forms, depth + 1)?;
+                    if let Some(bytes) = redacted.content {
+                        if let Ok(Object::Stream(form)) = doc.get_object_mut(form_id) {
+                            form.dict.remove(b"Filter");
+                            form.dict.remove(b"DecodeParms");
+                            form.set_content(bytes);
+                            let _ = form.compress();
+                        }
+                    }
+                    let boxes = redacted.boxes.iter().map(|quad| transform_quad(&matrix, quad)).collect();
+                    forms.insert(form_id, (boxes, redacted.matches));
+                }
+                let (boxes, matches) = &forms[&form_id];
+                result.boxes.extend(boxes.iter().map(|quad| transform_quad(&state.ctm, quad)));
+                result.matches += matches;
+            }
+            _ => {}
+        }
+    }
+    result.matches += close_text_object(&mut object, &shows, query, &mut removed, &mut result.boxes);
+
+    if removed.is_empty() {
+        return Ok(result);
+    }
+    let rewritten: HashMap<usize, &ShowOperation> = shows
+        .iter()
+        .filter(|show| removed.iter().any(|&(index, _, _)| index == show.index))
+        .map(|show| (show.index, show))
+        .collect();
+    let mut operations = Vec::with_capacity(content.operations.len());
+    for (index, operation) in content.operations.into_iter().enumerate() {
+        let show = match rewritten.get(&index) {
+            Some(show) => show,
+            None => {
+                operations.push(operation);
+                continue;
+            }
+        };
+        let elements = redacted_show_elements(&operation, show, &fonts[&show.font], &removed);
+        match operation.operator.as_str() {
+            "'" => operations.push(Operation::new("T*", vec![])),
+            "\"" => {
+                let spacing = &operation.operands;
+                if spacing.len() >= 3 {
+                    operations.push(Operation::new("Tw", vec![spacing[0].clone()]));
+                    operations.push(Operation::new("Tc", vec![spacing[1].clone()]));
+                }
+                operations.push(Operation::new("T*", vec![]));
+            }
+            _ => {}
+        }
+        operations.push(Operation::new("TJ", vec![Object::Array(elements)]));
+    }
+    result.content = Some(lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?);
+    Ok(result)
+}
+
+// Redact every occurrence of a term: the glyphs are removed from the content streams,
+// including form XObjects, and each removed run is covered with a black box. Returns
+// the number of matches removed. Fails if a match can still be extracted afterwards,
+// for example one split across text objects, so no box is left over readable text.
+#[tauri::command]
+async fn redact_text_matches(
+    state: State<'_, AppState>,
+    path: String,
+    query: String,
+    case_sensitive: bool,
+    output_path: String,
+) -> Result<usize, String> {
+    let query: Vec<char> = query.chars().map(|c| fold_case(c, case_sensitive)).collect();
+    if query.is_empty() {
+        return Err("Search text must not be empty".to_string());
+    }
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut forms = HashMap::new();
+    let mut total = 0;
+
+    for (page_num, page_id) in doc.get_pages() {
+        let raw = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+        let resources = page_resources(&doc, page_id).cloned();
+        let redacted = redact_content(&mut doc, &raw, resources.as_ref(), &query, case_sensitive, &mut forms, 0)?;
+        if let Some(bytes) = redacted.content {
+            set_page_content(&mut doc, page_id, bytes)?;
+        }
+        if !redacted.boxes.is_empty() {
+            let mut boxes = String::from("q 0 g\n");
+            for [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] in &redacted.boxes {
+                boxes.push_str(&format!(
+                    "{:.3} {:.3} m {:.3} {:.3} l {:.3} {:.3} l {:.3} {:.3} l h f\n",
+                    x0, y0, x1, y1, x2, y2, x3, y3
+                ));
+            }
+            boxes.push_str("Q\n");
+            append_page_overlay(&mut doc, page_id, boxes.as_bytes())?;
+        }
+
+        let text: Vec<char> = extract_page_text(&doc, page_num as usize)?
+            .chars()
+            .map(|c| fold_case(c, case_sensitive))
+            .collect();
+        let remaining = find_char_matches(&text, &query).len();
+        if remaining > 0 {
+            return Err(format!("{} match(es) on page {} could not be removed", remaining, page_num));
+        }
+        total += redacted.matches;
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(total)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum PageMode {
+    UseNone,
+    UseOutlines,
+    UseThumbs,
+    FullScreen,
+}
+
+impl PageMode {
+    fn pdf_name(self) -> &'static str {
+        match self {
+            PageMode::UseNone => "UseNone",
+            PageMode::UseOutlines => "UseOutlines",
+            PageMode::UseThumbs => "UseThumbs",
+            PageMode::FullScreen => "FullScreen",
+        }
+    }
+}
+
+// Which panel (bookmarks, thumbnails, none) viewers show when the document opens
+#[tauri::command]
+async fn set_page_mode(state: State<'_, AppState>, path: String, mode: PageMode) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        doc.catalog_mut()
+            .map_err(|e| e.to_string())?
+            .set("PageMode", Object::Name(mode.pdf_name().as_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum PageLayout {
+    SinglePage,
+    OneColumn,
+    TwoColumnLeft,
+    TwoColumnRight,
+    TwoPageLeft,
+    TwoPageRight,
+}
+
+impl PageLayout {
+    const ALL: [PageLayout; 6] = [
+        PageLayout::SinglePage,
+        PageLayout::OneColumn,
+        PageLayout::TwoColumnLeft,
+        PageLayout::TwoColumnRight,
+        PageLayout::TwoPageLeft,
+        PageLayout::TwoPageRight,
+    ];
+
+    fn pdf_name(self) -> &'static str {
+        match self {
+            PageLayout::SinglePage => "SinglePage",
+            PageLayout::OneColumn => "OneColumn",
+            PageLayout::TwoColumnLeft => "TwoColumnLeft",
+            PageLayout::TwoColumnRight => "TwoColumnRight",
+            PageLayout::TwoPageLeft => "TwoPageLeft",
+            PageLayout::TwoPageRight => "TwoPageRight",
+        }
+    }
+}
+
+// How viewers arrange pages when the document opens: one at a time, a continuous
+// column, or two side by side with odd pages on the left or right
+#[tauri::command]
+async fn set_page_layout(state: State<'_, AppState>, path: String, layout: PageLayout) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        doc.catalog_mut()
+            .map_err(|e| e.to_string())?
+            .set("PageLayout", Object::Name(layout.pdf_name().as_bytes().to_vec()));
+        Ok(())
+    })
+}
+
+// The catalog's /PageLayout, or None when unset or not a known layout
+#[tauri::command]
+async fn get_page_layout(state: State<'_, AppState>, path: String) -> Result<Option<PageLayout>, String> {
+    with_document(&state, &path, |doc| {
+        let name = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"PageLayout").ok())
+            .and_then(|layout| resolve_object(doc, layout))
+            .and_then(|layout| layout.as_name().ok());
+        Ok(name.and_then(|name| PageLayout::ALL.into_iter().find(|layout| layout.pdf_name().as_bytes() == name)))
+    })
+}
+
+// Look up a key in a name tree (/Names leaves, /Kids intermediate nodes)
+fn name_tree_lookup<'a>(
+    doc: &'a Document,
+    node: &'a lopdf::Dictionary,
+    key: &[u8],
+    depth: usize,
+) -> Option<&'a Object> {
+    if depth > 32 {
+        return None;
+    }
+    if let Some(Object::Array(names)) = node.get(b"Names").ok().and_then(|n| resolve_object(doc, n)) {
+        for pair in names.chunks(2) {
+            if let [Object::String(name, _), value] = pair {
+                if name.as_slice() == key {
+                    return resolve_object(doc, value);
+                }
+            }
+        }
+    }
+    if let Some(Object::Array(kids)) = node.get(b"Kids").ok().and_then(|k| resolve_object(doc, k)) {
+        for kid in kids {
+            if let Some(Object::Dictionary(kid)) = resolve_object(doc, kid) {
+                if let Some(found) = name_tree_lookup(doc, kid, key, depth + 1) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+    None
+}
+
+// Resolve a destination (explicit array, or a name looked up in /Dests) to its array form
+fn resolve_destination(doc: &Document, dest: &Object) -> Option<Vec<Object>> {
+    let catalog = doc.catalog().ok()?;
+    let found = match resolve_object(doc, dest)? {
+        Object::Array(items) => return Some(items.clone()),
+        Object::Name(name) => {
+            let dests = catalog.get(b"Dests").ok().and_then(|d| resolve_object(doc, d))?.as_dict().ok()?;
+            resolve_object(doc, dests.get(name).ok()?)?
+        }
+        Object::String(name, _) => {
+            let names = catalog.get(b"Names").ok().and_then(|n| resolve_object(doc, n))?.as_dict().ok()?;
+            let tree = names.get(b"Dests").ok().and_then(|d| resolve_object(doc, d))?.as_dict().ok()?;
+            name_tree_lookup(doc, tree, name, 0)?
+        }
+        _ => return None,
+    };
+    named_destination_array(doc, found)
+}
+
+// Named destinations may be wrapped in a dictionary with a /D entry
+fn named_destination_array(doc: &Document, value: &Object) -> Option<Vec<Object>> {
+    match resolve_object(doc, value)? {
+        Object::Array(items) => Some(items.clone()),
+        Object::Dictionary(dict) => match dict.get(b"D").ok().and_then(|d| resolve_object(doc, d)) {
+            Some(Object::Array(items)) => Some(items.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// An outline entry with its nesting depth and resolved destination
+struct OutlineEntry {
+    title: String,
+    depth: usize,
+    dest: Vec<Object>,
+}
+
+fn collect_outline_entries(doc: &Document) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    let first = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|outlines| resolve_object(doc, outlines))
+        .and_then(|outlines| outlines.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").and_then(Object::as_reference).ok());
+
+    let mut visited = std::collections::HashSet::new();
+    let mut stack: Vec<(ObjectId, usize)> = first.map(|id| (id, 0)).into_iter().collect();
+    while let Some((id, depth)) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let item = match doc.get_dictionary(id) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        // Push the sibling first so the children are visited before it
+        if let Ok(next) = item.get(b"Next").and_then(Object::as_reference) {
+            stack.push((next, depth));
+        }
+        if let Ok(child) = item.get(b"First").and_then(Object::as_reference) {
+            stack.push((child, depth + 1));
+        }
+
+        let title = item
+            .get(b"Title")
+            .ok()
+            .and_then(|t| resolve_object(doc, t))
+            .and_then(|t| t.as_str().ok())
+            .map(decode_pdf_string)
+            .unwrap_or_default();
+        let dest = match item.get(b"Dest") {
+            Ok(dest) => resolve_destination(doc, dest),
+            Err(_) => item
+                .get(b"A")
+                .ok()
+                .and_then(|a| resolve_object(doc, a))
+                .and_then(|a| a.as_dict().ok())
+                .filter(|a| matches!(a.get(b"S"), Ok(Object::Name(s)) if s == b"GoTo"))
+                .and_then(|a| a.get(b"D").ok())
+                .and_then(|d| resolve_destination(doc, d)),
+        };
+        if let Some(dest) = dest {
+            entries.push(OutlineEntry { title, depth, dest });
+        }
+    }
+    entries
+}
+
+fn roman_numeral(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+        (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut text = String::new();
+    for &(value, numeral) in NUMERALS.iter() {
+        while number >= value {
+            text.push_str(numeral);
+            number -= value;
+        }
+    }
+    text
+}
+
+// Letters counting as a, b, ..., z, aa, bb, ... as page labels do
+fn alphabetic_label(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let letter = (b'a' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat((number - 1) / 26 + 1)
+}
+
+// Page label entries from the /PageLabels number tree, sorted by starting page index
+fn page_label_ranges(doc: &Document) -> Vec<(usize, lopdf::Dictionary)> {
+    fn walk(doc: &Document, node: &lopdf::Dictionary, out: &mut Vec<(usize, lopdf::Dictionary)>, depth: usize) {
+        if depth > 32 {
+            return;
+        }
+        if let Some(Object::Array(nums)) = node.get(b"Nums").ok().and_then(|n| resolve_object(doc, n)) {
+            for pair in nums.chunks(2) {
+                if let [index, label] = pair {
+                    if let (Ok(index), Some(Object::Dictionary(label))) = (index.as_i64(), resolve_object(doc, label)) {
+                        out.push((index.max(0) as usize, label.clone()));
+                    }
+                }
+            }
+        }
+        if let Some(Object::Array(kids)) = node.get(b"Kids").ok().and_then(|k| resolve_object(doc, k)) {
+            for kid in kids {
+                if let Some(Object::Dictionary(kid)) = resolve_object(doc, kid) {
+                    walk(doc, kid, out, depth + 1);
+                }
+            }
+        }
+    }
+
+    let mut ranges = Vec::new();
+    if let Some(Object::Dictionary(tree)) = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"PageLabels").ok())
+        .and_then(|labels| resolve_object(doc, labels))
+    {
+        walk(doc, tree, &mut ranges, 0);
+    }
+    ranges.sort_by_key(|&(index, _)| index);
+    ranges
+}
+
+// Label of a page by zero-based index, or None when the document has no page labels
+fn page_label(doc: &Document, index: usize) -> Option<String> {
+    let ranges = page_label_ranges(doc);
+    let (start, label) = ranges.iter().rev().find(|(start, _)| *start <= index)?;
+    let prefix = label
+        .get(b"P")
+        .ok()
+        .and_then(|p| resolve_object(doc, p))
+        .and_then(|p| p.as_str().ok())
+        .map(decode_pdf_string)
+        .unwrap_or_default();
+    let first = label.get(b"St").and_then(Object::as_i64).unwrap_or(1).max(1) as usize;
+    let number = first + index - start;
+    let numbering = match label.get(b"S").and_then(Object::as_name) {
+        Ok(b"D") => number.to_string(),
+        Ok(b"r") => roman_numeral(number),
+        Ok(b"R") => roman_numeral(number).to_uppercase(),
+        Ok(b"a") => alphabetic_label(number),
+        Ok(b"A") => alphabetic_label(number).to_uppercase(),
+        _ => String::new(),
+    };
+    Some(format!("{}{}", prefix, numbering))
+}
+
+const TOC_MARGIN: f64 = 72.0;
+const TOC_FONT_SIZE: f64 = 12.0;
+const TOC_LINE_HEIGHT: f64 = 18.0;
+const TOC_INDENT: f64 = 18.0;
+
+// Insert printed table of contents pages built from the outline. Each line links to its
+// entry's destination; page numbers use page labels when the document defines them.
+#[tauri::command]
+async fn generate_toc_page(state: State<'_, AppState>, path: String, at_start: bool) -> Result<usize, String> {
+    use lopdf::content::Operation;
+
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    edit_document(&state, &path, |doc| {
+        let entries = collect_outline_entries(doc);
+        if entries.is_empty() {
+            return Err("Document has no outline entries with destinations".to_string());
+        }
+
+        let (width, height) = dominant_page_size(doc, default_size);
+        let heading_space = 48.0;
+        let lines_per_page = (((height - 2.0 * TOC_MARGIN - heading_space) / TOC_LINE_HEIGHT).floor() as usize).max(1);
+        let toc_pages = entries.len().div_ceil(lines_per_page);
+
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let has_labels = !page_label_ranges(doc).is_empty();
+        let number_for = |target: Option<ObjectId>| -> String {
+            let index = match target.and_then(|id| pages.iter().position(|&page| page == id)) {
+                Some(index) => index,
+                None => return String::new(),
+            };
+            // Position in the finished document, after the TOC pages are inserted
+            let index = index + if at_start { toc_pages } else { 0 };
+            if has_labels {
+                page_label(doc, index).unwrap_or_default()
+            } else {
+                (index + 1).to_string()
+            }
+        };
+        let lines: Vec<(String, String, usize, Vec<Object>)> = entries
+            .iter()
+            .map(|entry| {
+                let target = entry.dest.first().and_then(|page| page.as_reference().ok());
+                (entry.title.clone(), number_for(target), entry.depth, entry.dest.clone())
+            })
+            .collect();
+
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let mut new_pages = Vec::new();
+        for chunk in lines.chunks(lines_per_page) {
+            let page_id = create_blank_page(doc, width, height);
+            let mut operations = if new_pages.is_empty() {
+                centered_text_line("F1", "Contents", 18.0, width / 2.0, height - TOC_MARGIN, width - 2.0 * TOC_MARGIN)
+            } else {
+                Vec::new()
+            };
+            let mut annots = Vec::new();
+
+            for (row, (title, number, depth, dest)) in chunk.iter().enumerate() {
+                let baseline = height - TOC_MARGIN - heading_space - row as f64 * TOC_LINE_HEIGHT;
+                let left = TOC_MARGIN + TOC_INDENT * (*depth).min(6) as f64;
+                let right = width - TOC_MARGIN;
+                let number_bytes = encode_standard_font_text(number);
+                let number_width = helvetica_text_width(&number_bytes, TOC_FONT_SIZE);
+
+                // Truncate long titles, then fill the gap with dot leaders
+                let mut title_bytes = encode_standard_font_text(title);
+                let available = right - left - number_width - helvetica_text_width(b"  ", TOC_FONT_SIZE);
+                while !title_bytes.is_empty() && helvetica_text_width(&title_bytes, TOC_FONT_SIZE) > available {
+                    title_bytes.pop();
+                }
+                let dot = helvetica_text_width(b".", TOC_FONT_SIZE);
+                let gap = available - helvetica_text_width(&title_bytes, TOC_FONT_SIZE);
+                let mut line = title_bytes;
+                line.push(b' ');
+                line.extend(std::iter::repeat(b'.').take((gap / dot).max(0.0) as usize));
+
+                operations.push(Operation::new("BT", vec![]));
+                operations.push(Operation::new(
+                    "Tf",
+                    vec![Object::Name(b"F1".to_vec()), Object::Real(TOC_FONT_SIZE as f32)],
+                ));
+                operations.push(Operation::new("Td", vec![Object::Real(left as f32), Object::Real(baseline as f32)]));
+                operations.push(Operation::new("Tj", vec![Object::String(line, lopdf::StringFormat::Literal)]));
+                operations.push(Operation::new("ET", vec![]));
+                operations.push(Operation::new("BT", vec![]));
+                operations.push(Operation::new(
+                    "Tf",
+                    vec![Object::Name(b"F1".to_vec()), Object::Real(TOC_FONT_SIZE as f32)],
+                ));
+                operations.push(Operation::new(
+                    "Td",
+                    vec![Object::Real((right - number_width) as f32), Object::Real(baseline as f32)],
+                ));
+                operations.push(Operation::new("Tj", vec![Object::String(number_bytes, lopdf::StringFormat::Literal)]));
+                operations.push(Operation::new("ET", vec![]));
+
+                let mut link = lopdf::Dictionary::new();
+                link.set("Type", Object::Name(b"Annot".to_vec()));
+                link.set("Subtype", Object::Name(b"Link".to_vec()));
+                link.set(
+                    "Rect",
+                    Object::Array(vec![
+                        Object::Real(left as f32),
+                        Object::Real((baseline - 4.0) as f32),
+                        Object::Real(right as f32),
+                        Object::Real((baseline + TOC_FONT_SIZE) as f32),
+                    ]),
+                );
+                link.set("Border", Object::Array(vec![Object::Integer(0), Object::Integer(0), Object::Integer(0)]));
+                link.set("Dest", Object::Array(dest.clone()));
+                link.set("P", Object::Reference(page_id));
+                annots.push(Object::Reference(doc.add_object(link)));
+            }
+
+            let content = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+            set_page_content(doc, page_id, content)?;
+            let mut fonts = lopdf::Dictionary::new();
+            fonts.set("F1", Object::Reference(font_id));
+            let mut resources = lopdf::Dictionary::new();
+            resources.set("Font", Object::Dictionary(fonts));
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Resources", Object::Dictionary(resources));
+            page.set("Annots", Object::Array(annots));
+            new_pages.push(page_id);
+        }
+
+        let mut order = if at_start { new_pages.clone() } else { Vec::new() };
+        order.extend(pages.iter().copied());
+        if !at_start {
+            order.extend(new_pages.iter().copied());
+        }
+        apply_page_order(doc, &order)?;
+        Ok(new_pages.len())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StripMetadataReport {
+    // Names of the /Info entries that were removed
+    info_fields: Vec<String>,
+    xmp_streams: usize,
+    removed_id: bool,
+}
+
+// Remove identifying metadata before sharing: /Info entries (optionally keeping
+// Producer), every XMP /Metadata stream, and optionally the trailer /ID.
+// Active content such as JavaScript is left alone.
+#[tauri::command]
+async fn strip_metadata(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    keep_producer: Option<bool>,
+    remove_id: Option<bool>,
+) -> Result<StripMetadataReport, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let keep_producer = keep_producer.unwrap_or(false);
+    let mut report = StripMetadataReport {
+        info_fields: Vec::new(),
+        xmp_streams: 0,
+        removed_id: false,
+    };
+
+    let info_id = doc.trailer.get(b"Info").and_then(Object::as_reference).ok();
+    let info = info_dictionary(&doc).cloned().unwrap_or_default();
+    let mut kept = lopdf::Dictionary::new();
+    for (key, value) in info.iter() {
+        if keep_producer && key == b"Producer" {
+            kept.set(key.clone(), value.clone());
+        } else {
+            report.info_fields.push(String::from_utf8_lossy(key).into_owned());
+        }
+    }
+    if let Some(id) = info_id {
+        doc.objects.remove(&id);
+    }
+    if kept.is_empty() {
+        doc.trailer.remove(b"Info");
+    } else {
+        let id = doc.add_object(kept);
+        doc.trailer.set("Info", Object::Reference(id));
+    }
+
+    // XMP can be attached to the catalog, pages, images and fonts
+    let mut xmp_ids = Vec::new();
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        if let Ok(metadata) = dict.get(b"Metadata") {
+            if let Ok(id) = metadata.as_reference() {
+                xmp_ids.push(id);
+            }
+            dict.remove(b"Metadata");
+            report.xmp_streams += 1;
+        }
+    }
+    for id in xmp_ids {
+        doc.objects.remove(&id);
+    }
+
+    if remove_id.unwrap_or(false) {
+        report.removed_id = doc.trailer.remove(b"ID").is_some();
+        write_document(&mut doc, &output_path)?;
+    } else {
+        save_document(&mut doc, &output_path)?;
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum LabelStyle {
+    Decimal,
+    UpperRoman,
+    LowerRoman,
+    UpperAlpha,
+    LowerAlpha,
+}
+
+impl LabelStyle {
+    fn pdf_name(self) -> &'static [u8] {
+        match self {
+            LabelStyle::Decimal => b"D",
+            LabelStyle::UpperRoman => b"R",
+            LabelStyle::LowerRoman => b"r",
+            LabelStyle::UpperAlpha => b"A",
+            LabelStyle::LowerAlpha => b"a",
+        }
+    }
+}
+
+fn label_range(style: LabelStyle, start: usize) -> lopdf::Dictionary {
+    let mut range = lopdf::Dictionary::new();
+    range.set("S", Object::Name(style.pdf_name().to_vec()));
+    if start != 1 {
+        range.set("St", Object::Integer(start as i64));
+    }
+    range
+}
+
+// Label of every page, e.g. ["i", "ii", "1", "2"]. Without page labels this is the
+// physical page number.
+#[tauri::command]
+async fn get_page_labels(state: State<'_, AppState>, path: String) -> Result<Vec<String>, String> {
+    with_document(&state, &path, |doc| {
+        let count = doc.get_pages().len();
+        Ok((0..count)
+            .map(|index| page_label(doc, index).unwrap_or_else(|| (index + 1).to_string()))
+            .collect())
+    })
+}
+
+// Number pages from `start_number` beginning at `physical_page` (1-based), replacing any
+// existing labels. Pages before it are numbered as lowercase roman front matter.
+#[tauri::command]
+async fn set_start_page_number(
+    state: State<'_, AppState>,
+    path: String,
+    physical_page: usize,
+    start_number: usize,
+    style: LabelStyle,
+) -> Result<(), String> {
+    if start_number == 0 {
+        return Err("Page numbers start at 1".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        page_id(doc, physical_page)?;
+
+        let mut nums = Vec::new();
+        if physical_page > 1 {
+            nums.push(Object::Integer(0));
+            nums.push(Object::Dictionary(label_range(LabelStyle::LowerRoman, 1)));
+        }
+        nums.push(Object::Integer(physical_page as i64 - 1));
+        nums.push(Object::Dictionary(label_range(style, start_number)));
+
+        let mut tree = lopdf::Dictionary::new();
+        tree.set("Nums", Object::Array(nums));
+        doc.catalog_mut()
+            .map_err(|e| e.to_string())?
+            .set("PageLabels", Object::Dictionary(tree));
+        Ok(())
+    })
+}
+
+// Longest side, in pixels, of the quick renders used for page analysis
+const ANALYSIS_RENDER_SIZE: f64 = 300.0;
+
+// Render a page small enough for cheap pixel statistics
+fn render_for_analysis(doc: &Document, page_id: ObjectId, default_size: (f64, f64)) -> Result<image::RgbImage, String> {
+    let (_, width, height) = page_device_matrix(doc, page_id, 1.0, default_size);
+    let scale = ANALYSIS_RENDER_SIZE / width.max(height) as f64;
+    render_page(doc, page_id, scale, None, default_size)
+}
+
+fn luminance(pixel: &image::Rgb<u8>) -> f64 {
+    let [r, g, b] = pixel.0;
+    0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+}
+
+// Fraction of pixels that must be dark for a page to count as a negative
+const INVERTED_DARK_FRACTION: f64 = 0.6;
+
+// Pages whose rendering is mostly dark with some light marks, as a negative scan looks.
+// Only pages that draw images are considered, since those are what invert_pages changes.
+#[tauri::command]
+async fn detect_inverted_pages(state: State<'_, AppState>, path: String) -> Result<Vec<usize>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    with_document(&state, &path, |doc| {
+        let mut inverted = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            if page_images(doc, page_id)?.is_empty() {
+                continue;
+            }
+            let image = render_for_analysis(doc, page_id, default_size)?;
+            let total = (image.width() * image.height()).max(1) as f64;
+            let dark = image.pixels().filter(|p| luminance(p) < 96.0).count() as f64 / total;
+            let light = image.pixels().filter(|p| luminance(p) > 160.0).count() as f64 / total;
+            if dark >= INVERTED_DARK_FRACTION && light > 0.005 {
+                inverted.push(page_num as usize);
+            }
+        }
+        Ok(inverted)
+    })
+}
+
+// A color-inverted copy of an image XObject, or None for stencil masks. Device and ICC
+// spaces flip their /Decode ranges, so the pixel data is untouched; indexed and unknown
+// spaces are decoded and re-encoded.
+fn invert_image(doc: &Document, image_id: ObjectId) -> Result<Option<lopdf::Stream>, String> {
+    let stream = match doc.get_object(image_id) {
+        Ok(Object::Stream(stream)) => stream,
+        _ => return Ok(None),
+    };
+    if matches!(stream.dict.get(b"ImageMask"), Ok(Object::Boolean(true))) {
+        return Ok(None);
+    }
+    let kind = stream
+        .dict
+        .get(b"ColorSpace")
+        .map(|space| resolve_color_space(doc, space))
+        .unwrap_or(ColorSpaceKind::Unknown);
+
+    let replacement = match kind {
+        ColorSpaceKind::Gray | ColorSpaceKind::Rgb | ColorSpaceKind::Cmyk => {
+            let current = stream
+                .dict
+                .get(b"Decode")
+                .ok()
+                .and_then(|decode| resolve_object(doc, decode))
+                .and_then(|decode| decode.as_array().ok())
+                .map(|decode| operand_numbers(decode))
+                .filter(|decode| decode.len() == kind.components() * 2)
+                .unwrap_or_else(|| [0.0, 1.0].repeat(kind.components()));
+            let flipped: Vec<Object> = current
+                .chunks(2)
+                .flat_map(|range| [Object::Real(range[1] as f32), Object::Real(range[0] as f32)])
+                .collect();
+            let mut updated = stream.clone();
+            updated.dict.set("Decode", Object::Array(flipped));
+            updated
+        }
+        _ => {
+            let mut image = decode_image_xobject(doc, stream)?;
+            image::imageops::invert(&mut image);
+            reencode_image(stream, &image, DEFAULT_JPEG_QUALITY, None)?
+        }
+    };
+    Ok(Some(replacement))
+}
+
+// Invert the images drawn on the given pages, e.g. those found by detect_inverted_pages.
+// The listed pages are pointed at inverted copies, so pages and forms outside the list
+// that share an image keep the original. Returns how many images were inverted; an
+// image shared by several listed pages is only inverted once.
+#[tauri::command]
+async fn invert_pages(state: State<'_, AppState>, path: String, pages: Vec<usize>) -> Result<usize, String> {
+    edit_document(&state, &path, |doc| {
+        let mut copies: HashMap<ObjectId, ObjectId> = HashMap::new();
+        for &page_num in &pages {
+            let page_id = page_id(doc, page_num)?;
+            let mut replaced = Vec::new();
+            for (name, image_id) in page_images(doc, page_id)? {
+                let copy_id = match copies.get(&image_id) {
+                    Some(&copy_id) => copy_id,
+                    None => match invert_image(doc, image_id)? {
+                        Some(copy) => {
+                            let copy_id = doc.add_object(copy);
+                            copies.insert(image_id, copy_id);
+                            copy_id
+                        }
+                        None => continue,
+                    },
+                };
+                replaced.push((name, copy_id));
+            }
+            if replaced.is_empty() {
+                continue;
+            }
+
+            // Give the page its own resource dictionaries before repointing the names
+            materialize_inherited_attributes(doc, page_id)?;
+            let mut resources = page_resources(doc, page_id).cloned().unwrap_or_default();
+            let mut xobjects = resource_category(doc, &resources, b"XObject").cloned().unwrap_or_default();
+            for (name, copy_id) in replaced {
+                xobjects.set(name, Object::Reference(copy_id));
+            }
+            resources.set("XObject", Object::Dictionary(xobjects));
+            doc.get_dictionary_mut(page_id)
+                .map_err(|e| e.to_string())?
+                .set("Resources", Object::Dictionary(resources));
+        }
+
+        let originals: Vec<ObjectId> = copies.keys().copied().collect();
+        remove_unreferenced(doc, &originals);
+        Ok(copies.len())
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct AnnotationData {
+    subtype: String,
+    page: usize,
+    rect: [f64; 4],
+    contents: Option<String>,
+    color: Option<Vec<f64>>,
+    author: Option<String>,
+    timestamp: Option<String>,
+    quad_points: Option<Vec<f64>>,
+}
+
+fn annotation_data(doc: &Document, page: usize, annot: &lopdf::Dictionary) -> Option<AnnotationData> {
+    let text = |key: &[u8]| {
+        annot
+            .get(key)
+            .ok()
+            .and_then(|value| resolve_object(doc, value))
+            .and_then(|value| value.as_str().ok())
+            .map(decode_pdf_string)
+    };
+    let numbers = |key: &[u8]| {
+        annot
+            .get(key)
+            .ok()
+            .and_then(|value| resolve_object(doc, value))
+            .and_then(|value| value.as_array().ok())
+            .map(|value| operand_numbers(value))
+    };
+    let rect = numbers(b"Rect").filter(|rect| rect.len() == 4)?;
+
+    Some(AnnotationData {
+        subtype: String::from_utf8_lossy(annot.get(b"Subtype").and_then(Object::as_name).ok()?).into_owned(),
+        page,
+        rect: [rect[0], rect[1], rect[2], rect[3]],
+        contents: text(b"Contents"),
+        color: numbers(b"C"),
+        author: text(b"T"),
+        timestamp: text(b"M"),
+        quad_points: numbers(b"QuadPoints"),
+    })
+}
+
+fn number_array(values: &[f64]) -> Object {
+    Object::Array(values.iter().map(|&v| Object::Real(v as f32)).collect())
+}
+
+// Write every markup annotation to a JSON file. Widgets (form fields) and popups
+// (owned by their parent annotation) are not exported.
+#[tauri::command]
+async fn export_annotations(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    let annotations = with_document(&state, &path, |doc| {
+        let mut annotations = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            for annot in page_annotations(doc, page_id) {
+                let annot = match resolve_object(doc, &annot).and_then(|a| a.as_dict().ok()) {
+                    Some(annot) => annot,
+                    None => continue,
+                };
+                if let Some(data) = annotation_data(doc, page_num as usize, annot) {
+                    if data.subtype != "Widget" && data.subtype != "Popup" {
+                        annotations.push(data);
+                    }
+                }
+            }
+        }
+        Ok(annotations)
+    })?;
+
+    let json = serde_json::to_string_pretty(&annotations).map_err(|e| e.to_string())?;
+    write_atomically(&output_path, |file| file.write_all(json.as_bytes()).map_err(|e| e.to_string()))?;
+    Ok(annotations.len())
+}
+
+// Recreate annotations from a file written by export_annotations
+#[tauri::command]
+async fn import_annotations(state: State<'_, AppState>, path: String, json_path: String) -> Result<usize, String> {
+    let json = std::fs::read_to_string(&json_path).map_err(|e| e.to_string())?;
+    let annotations: Vec<AnnotationData> = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    edit_document(&state, &path, |doc| {
+        for data in &annotations {
+            let page_id = page_id(doc, data.page)?;
+            let mut annot = lopdf::Dictionary::new();
+            annot.set("Type", Object::Name(b"Annot".to_vec()));
+            annot.set("Subtype", Object::Name(data.subtype.as_bytes().to_vec()));
+            annot.set("Rect", number_array(&data.rect));
+            annot.set("P", Object::Reference(page_id));
+            if let Some(contents) = &data.contents {
+                annot.set("Contents", encode_pdf_string(contents));
+            }
+            if let Some(color) = &data.color {
+                annot.set("C", number_array(color));
+            }
+            if let Some(author) = &data.author {
+                annot.set("T", encode_pdf_string(author));
+            }
+            if let Some(timestamp) = &data.timestamp {
+                annot.set("M", encode_pdf_string(timestamp));
+            }
+            if let Some(quad_points) = &data.quad_points {
+                annot.set("QuadPoints", number_array(quad_points));
+            }
+            let annot_id = doc.add_object(annot);
+
+            let mut annots = page_annotations(doc, page_id);
+            annots.push(Object::Reference(annot_id));
+            doc.get_dictionary_mut(page_id)
+                .map_err(|e| e.to_string())?
+                .set("Annots", Object::Array(annots));
+        }
+        Ok(annotations.len())
+    })
+}
+
+// Turn pages by `delta` degrees (a multiple of 90; positive is clockwise) on top of
+// their current /Rotate, like a rotate left/right button
+#[tauri::command]
+async fn rotate_pages_relative(
+    state: State<'_, AppState>,
+    path: String,
+    pages: Vec<usize>,
+    delta: i32,
+) -> Result<(), String> {
+    if delta % 90 != 0 {
+        return Err("Rotation must be a multiple of 90 degrees".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let mut targets: Vec<ObjectId> = Vec::new();
+        for &page_num in &pages {
+            let id = page_id(doc, page_num)?;
+            if !targets.contains(&id) {
+                targets.push(id);
+            }
+        }
+        for page_id in targets {
+            let rotation = normalize_rotation(page_rotation(doc, page_id) as i64 + delta as i64);
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Rotate", Object::Integer(rotation as i64));
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChannelCoverage {
+    cyan: f64,
+    magenta: f64,
+    yellow: f64,
+    black: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PageInk {
+    page_number: usize,
+    coverage: f64,
+    channels: Option<ChannelCoverage>,
+}
+
+// Pixels with every channel at or above this count as blank paper
+const PAPER_WHITE: u8 = 250;
+
+// Share of an image's pixels darker than blank paper
+fn inked_fraction(image: &image::RgbImage) -> f64 {
+    let total = (image.width() * image.height()).max(1) as f64;
+    image.pixels().filter(|p| p.0.iter().any(|&c| c < PAPER_WHITE)).count() as f64 / total
+}
+
+// Share of each page covered by non-white content, from a small rasterization. With
+// `per_channel`, also the average CMYK ink per channel (0-1) using a naive RGB to CMYK
+// conversion, as a rough estimate rather than a color-managed separation.
+#[tauri::command]
+async fn ink_coverage(state: State<'_, AppState>, path: String, per_channel: Option<bool>) -> Result<Vec<PageInk>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let per_channel = per_channel.unwrap_or(false);
+    with_document(&state, &path, |doc| {
+        let mut pages = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            let image = render_for_analysis(doc, page_id, default_size)?;
+            let total = (image.width() * image.height()).max(1) as f64;
+
+            let channels = per_channel.then(|| {
+                let mut sums = [0.0; 4];
+                for pixel in image.pixels() {
+                    let [r, g, b] = pixel.0.map(|c| c as f64 / 255.0);
+                    let k = 1.0 - r.max(g).max(b);
+                    if k < 1.0 {
+                        sums[0] += (1.0 - r - k) / (1.0 - k);
+                        sums[1] += (1.0 - g - k) / (1.0 - k);
+                        sums[2] += (1.0 - b - k) / (1.0 - k);
+                    }
+                    sums[3] += k;
+                }
+                ChannelCoverage {
+                    cyan: sums[0] / total,
+                    magenta: sums[1] / total,
+                    yellow: sums[2] / total,
+                    black: sums[3] / total,
+                }
+            });
+
+            pages.push(PageInk {
+                page_number: page_num as usize,
+                coverage: inked_fraction(&image),
+                channels,
+            });
+        }
+        Ok(pages)
+    })
+}
+
+// Text at or below this size (in points, after scaling) counts as small for overprinting
+const SMALL_TEXT_SIZE: f64 = 12.0;
+
+fn overprint_state(enabled: bool) -> lopdf::Dictionary {
+    let mut state = lopdf::Dictionary::new();
+    state.set("Type", Object::Name(b"ExtGState".to_vec()));
+    state.set("OP", Object::Boolean(enabled));
+    state.set("op", Object::Boolean(enabled));
+    state.set("OPM", Object::Integer(if enabled { 1 } else { 0 }));
+    state
+}
+
+// Whether fill color operands describe pure black (0 g, or 0 0 0 1 k)
+fn is_black_fill(operator: &str, numbers: &[f64]) -> bool {
+    match operator {
+        "g" => numbers.first().is_some_and(|&gray| gray == 0.0),
+        "k" => numbers.len() == 4 && numbers[..3].iter().all(|&v| v == 0.0) && numbers[3] == 1.0,
+        _ => false,
+    }
+}
+
+// Make small black text overprint so it can't show gaps from misregistration.
+//
+// "Small" means an effective size (Tf size scaled by the text and graphics matrices)
+// of SMALL_TEXT_SIZE points or less, filled with DeviceGray 0 (the initial fill) or
+// DeviceCMYK 0 0 0 1. Overprint is switched on just for those show operators and
+// switched off after them, which also resets any overprint state the page itself had
+// set at that point. Only the page's own content stream is changed, not form XObjects.
+#[tauri::command]
+async fn set_overprint_black(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    use lopdf::content::Operation;
+
+    let mut doc = snapshot_document(&state, &path)?;
+    let on_id = doc.add_object(overprint_state(true));
+    let off_id = doc.add_object(overprint_state(false));
+    let mut affected = 0;
+
+    for page_id in doc.get_pages().into_values() {
+        let raw = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+        let content = lopdf::content::Content::decode(&raw).map_err(|e| e.to_string())?;
+
+        // (black fill, ctm scale) per graphics state level; the initial fill is DeviceGray 0
+        let mut graphics = (true, 1.0);
+        let mut stack = Vec::new();
+        let mut text_scale = 1.0;
+        let mut font_size = 0.0;
+        let mut marks = Vec::new();
+
+        for (index, operation) in content.operations.iter().enumerate() {
+            let numbers = operand_numbers(&operation.operands);
+            match operation.operator.as_str() {
+                "q" => stack.push(graphics),
+                "Q" => graphics = stack.pop().unwrap_or(graphics),
+                "cm" if numbers.len() == 6 => graphics.1 *= (numbers[2] * numbers[2] + numbers[3] * numbers[3]).sqrt(),
+                "g" | "k" => graphics.0 = is_black_fill(&operation.operator, &numbers),
+                "rg" | "sc" | "scn" | "cs" => graphics.0 = false,
+                "BT" => text_scale = 1.0,
+                "Tm" if numbers.len() == 6 => text_scale = (numbers[2] * numbers[2] + numbers[3] * numbers[3]).sqrt(),
+                "Tf" => font_size = numbers.first().copied().unwrap_or(0.0),
+                "Tj" | "TJ" | "'" | "\"" => {
+                    let size = (font_size * text_scale * graphics.1).abs();
+                    if graphics.0 && size > 0.0 && size <= SMALL_TEXT_SIZE {
+                        marks.push(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if marks.is_empty() {
+            continue;
+        }
+
+        let on = add_page_resource(&mut doc, page_id, "ExtGState", "OPon", Object::Reference(on_id))?;
+        let off = add_page_resource(&mut doc, page_id, "ExtGState", "OPoff", Object::Reference(off_id))?;
+        let mut operations = Vec::with_capacity(content.operations.len() + marks.len() * 2);
+        for (index, operation) in content.operations.into_iter().enumerate() {
+            let marked = marks.binary_search(&index).is_ok();
+            if marked {
+                operations.push(Operation::new("gs", vec![Object::Name(on.clone())]));
+            }
+            operations.push(operation);
+            if marked {
+                operations.push(Operation::new("gs", vec![Object::Name(off.clone())]));
+            }
+        }
+        let bytes = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+        set_page_content(&mut doc, page_id, bytes)?;
+        affected += 1;
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(affected)
+}
+
+// Longest side, in pixels, of the renders used to find content bounds
+const AUTO_CROP_RENDER_SIZE: f64 = 800.0;
+
+// Crop each page to the bounding box of its visible (non-white) content plus `margin`
+// points, never extending past the current visible box. Blank pages are left as they are.
+#[tauri::command]
+async fn auto_crop(state: State<'_, AppState>, path: String, margin: f64) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    edit_document(&state, &path, |doc| {
+        for page_id in doc.get_pages().into_values() {
+            let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+            let scale = AUTO_CROP_RENDER_SIZE / (x1 - x0).max(y1 - y0).max(1.0);
+            let image = render_page_unrotated(doc, page_id, scale, default_size)?;
+
+            let mut bounds: Option<(u32, u32, u32, u32)> = None;
+            for (x, y, pixel) in image.enumerate_pixels() {
+                if pixel.0.iter().all(|&c| c >= PAPER_WHITE) {
+                    continue;
+                }
+                bounds = Some(match bounds {
+                    Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x), bottom.max(y)),
+                    None => (x, y, x, y),
+                });
+            }
+            let (left, top, right, bottom) = match bounds {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+
+            // Pixel rows run top-down while page space runs bottom-up
+            let height = image.height() as f64;
+            let crop = [
+                (x0 + left as f64 / scale - margin).max(x0),
+                (y0 + (height - bottom as f64 - 1.0) / scale - margin).max(y0),
+                (x0 + (right as f64 + 1.0) / scale + margin).min(x1),
+                (y0 + (height - top as f64) / scale + margin).min(y1),
+            ];
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("CropBox", Object::Array(crop.iter().map(|&v| Object::Real(v as f32)).collect()));
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Capabilities {
+    // Page rasterization (thumbnails, analysis, flattening) via the built-in renderer
+    can_render: bool,
+    can_ocr: bool,
+    can_encrypt: bool,
+    has_qr: bool,
+    language_detection: bool,
+}
+
+// Whether the renderer compiled into this build works, by rasterizing a tiny blank page
+fn renderer_available() -> bool {
+    let mut doc = Document::with_version("1.7");
+    let page_id = create_blank_page(&mut doc, 8.0, 8.0);
+    render_page(&doc, page_id, 1.0, None, DEFAULT_PAGE_SIZE).is_ok_and(|image| image.width() > 0)
+}
+
+// What this build can do, so the frontend can hide unavailable actions
+#[tauri::command]
+async fn get_capabilities() -> Result<Capabilities, String> {
+    Ok(Capabilities {
+        can_render: renderer_available(),
+        // No OCR engine or QR encoder is compiled in yet
+        can_ocr: false,
+        // Owner-password restrictions through set_permissions_only
+        can_encrypt: true,
+        has_qr: false,
+        language_detection: cfg!(feature = "language-detection"),
+    })
+}
+
+// Corrections smaller than this (in degrees) are not worth resampling for
+const MIN_DESKEW_ANGLE: f64 = 0.2;
+
+// Longest side, in pixels, of the renders used to estimate skew
+const DESKEW_RENDER_SIZE: f64 = 1000.0;
+
+// Estimate the counter-clockwise skew of a page's text lines, in degrees, by finding
+// the angle whose horizontal projection profile of dark pixels is sharpest
+fn estimate_skew(image: &image::RgbImage, max_angle: f64) -> Option<f64> {
+    let dark: Vec<(f64, f64)> = image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| luminance(pixel) < 128.0)
+        .map(|(x, y, _)| (x as f64, y as f64))
+        .collect();
+    if dark.len() < 100 {
+        return None;
+    }
+
+    let rows = image.height() as f64;
+    let width = image.width() as f64;
+    let steps = (max_angle / 0.1).round() as i64;
+    let mut best: Option<(f64, f64)> = None;
+    for step in -steps..=steps {
+        let angle = step as f64 * 0.1;
+        let slope = angle.to_radians().tan();
+        // Rows are shifted so lines at this angle fall into a single bin
+        let offset = (width * slope.abs()).ceil();
+        let mut profile = vec![0u32; (rows + 2.0 * offset) as usize + 1];
+        for &(x, y) in &dark {
+            let bin = (y + x * slope + offset).round();
+            if bin >= 0.0 && (bin as usize) < profile.len() {
+                profile[bin as usize] += 1;
+            }
+        }
+        let score: f64 = profile.iter().map(|&count| (count as f64).powi(2)).sum();
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((angle, score));
+        }
+    }
+    best.map(|(angle, _)| angle)
+}
+
+// Straighten scanned pages (those mostly covered by images) whose skew is at most
+// `max_angle` degrees, by rotating the page content about its center. Returns the
+// correction applied to each page, in degrees counter-clockwise.
+#[tauri::command]
+async fn deskew(state: State<'_, AppState>, path: String, max_angle: f64) -> Result<BTreeMap<usize, f64>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let max_angle = max_angle.abs().min(45.0);
+    edit_document(&state, &path, |doc| {
+        let mut corrections = BTreeMap::new();
+        for (page_num, page_id) in doc.get_pages() {
+            if page_image_coverage(doc, page_id, default_size)? < SCANNED_IMAGE_COVERAGE {
+                continue;
+            }
+            let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+            let scale = DESKEW_RENDER_SIZE / (x1 - x0).max(y1 - y0).max(1.0);
+            let image = render_page_unrotated(doc, page_id, scale, default_size)?;
+            let skew = match estimate_skew(&image, max_angle) {
+                Some(skew) if skew.abs() >= MIN_DESKEW_ANGLE => skew,
+                _ => continue,
+            };
+
+            let correction = -skew;
+            let (sin, cos) = correction.to_radians().sin_cos();
+            let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+            let cm = format!(
+                "q {:.6} {:.6} {:.6} {:.6} {:.4} {:.4} cm\n",
+                cos,
+                sin,
+                -sin,
+                cos,
+                cx - cx * cos + cy * sin,
+                cy - cx * sin - cy * cos
+            );
+            wrap_page_content(doc, page_id, cm.as_bytes(), b"\nQ\n")?;
+            corrections.insert(page_num as usize, correction);
+        }
+        Ok(corrections)
+    })
+}
+
+// Basic BCP-47 shape: a 2-8 letter primary tag followed by 1-8 character
+// alphanumeric subtags, e.g. "en", "fr-CA", "zh-Hant-TW"
+fn is_language_tag(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let primary = parts.next().unwrap_or("");
+    let primary_ok = (2..=8).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic());
+    primary_ok && parts.all(|part| (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+#[tauri::command]
+async fn set_document_language(state: State<'_, AppState>, path: String, lang: String) -> Result<(), String> {
+    let lang = lang.trim().to_string();
+    if !is_language_tag(&lang) {
+        return Err(format!("'{}' is not a valid language tag", lang));
+    }
+    edit_document(&state, &path, |doc| {
+        doc.catalog_mut()
+            .map_err(|e| e.to_string())?
+            .set("Lang", encode_pdf_string(&lang));
+        Ok(())
+    })
+}
+
+#[tauri::command]
+async fn get_document_language(state: State<'_, AppState>, path: String) -> Result<Option<String>, String> {
+    with_document(&state, &path, |doc| {
+        Ok(doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"Lang").ok())
+            .and_then(|lang| resolve_object(doc, lang))
+            .and_then(|lang| lang.as_str().ok())
+            .map(decode_pdf_string))
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PageVerification {
+    page_number: usize,
+    source_page: usize,
+    dimensions_match: bool,
+    // 1.0 when both renderings are identical
+    similarity: f64,
+    text_match: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VerifyReport {
+    passed: bool,
+    input_page_count: usize,
+    output_page_count: usize,
+    pages: Vec<PageVerification>,
+    regressions: Vec<String>,
+}
+
+// Pages rendering less alike than this are reported as regressions
+const MIN_RENDER_SIMILARITY: f64 = 0.98;
+
+// Page size differences below this many points are rounding, not regressions
+const DIMENSION_TOLERANCE: f64 = 0.5;
+
+// Mean luminance agreement between two renderings, after scaling `b` to the size of `a`
+fn render_similarity(a: &image::RgbImage, b: &image::RgbImage) -> f64 {
+    let b = if a.dimensions() == b.dimensions() {
+        b.clone()
+    } else {
+        image::imageops::resize(b, a.width(), a.height(), image::imageops::FilterType::Triangle)
+    };
+    let total = (a.width() * a.height()).max(1) as f64;
+    let difference: f64 = a
+        .pixels()
+        .zip(b.pixels())
+        .map(|(pa, pb)| (luminance(pa) - luminance(pb)).abs())
+        .sum();
+    1.0 - difference / (255.0 * total)
+}
+
+// Compare a saved file against the open document it was saved from. `page_order` maps
+// each output page to its 1-based source page, as passed to save_pdf; without it pages
+// are compared one to one.
+#[tauri::command]
+async fn verify_save(
+    state: State<'_, AppState>,
+    input_path: String,
+    output_path: String,
+    page_order: Option<Vec<usize>>,
+) -> Result<VerifyReport, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let output = Document::load(&output_path).map_err(|e| e.to_string())?;
+    with_document(&state, &input_path, |input| {
+        let input_pages = input.get_pages();
+        let output_pages = output.get_pages();
+        let order = page_order.unwrap_or_else(|| (1..=input_pages.len()).collect());
+        let mut regressions = Vec::new();
+        if order.len() != output_pages.len() {
+            regressions.push(format!(
+                "expected {} pages, output has {}",
+                order.len(),
+                output_pages.len()
+            ));
+        }
+
+        let mut pages = Vec::new();
+        for (&page_number, &output_id) in &output_pages {
+            let source_page = match order.get(page_number as usize - 1) {
+                Some(&source) => source,
+                None => continue,
+            };
+            let input_id = match input_pages.get(&(source_page as u32)) {
+                Some(&id) => id,
+                None => {
+                    regressions.push(format!("page {}: source page {} does not exist", page_number, source_page));
+                    continue;
+                }
+            };
+
+            let size = |doc: &Document, id: ObjectId| {
+                let visible = page_box_or_default(doc, id, default_size);
+                let rotated = page_rotation(doc, id) % 180 != 0;
+                let (w, h) = (visible[2] - visible[0], visible[3] - visible[1]);
+                if rotated { (h, w) } else { (w, h) }
+            };
+            let (input_w, input_h) = size(input, input_id);
+            let (output_w, output_h) = size(&output, output_id);
+            let dimensions_match =
+                (input_w - output_w).abs() <= DIMENSION_TOLERANCE && (input_h - output_h).abs() <= DIMENSION_TOLERANCE;
+            if !dimensions_match {
+                regressions.push(format!(
+                    "page {}: size changed from {:.1}x{:.1} to {:.1}x{:.1}",
+                    page_number, input_w, input_h, output_w, output_h
+                ));
+            }
+
+            let input_render = render_for_analysis(input, input_id, default_size);
+            let output_render = render_for_analysis(&output, output_id, default_size);
+            let similarity = match (input_render, output_render) {
+                (Ok(a), Ok(b)) => render_similarity(&a, &b),
+                (Ok(_), Err(e)) => {
+                    regressions.push(format!("page {}: output does not render: {}", page_number, e));
+                    0.0
+                }
+                // Nothing to compare if the source itself cannot be rendered
+                (Err(_), _) => 1.0,
+            };
+            if similarity < MIN_RENDER_SIMILARITY {
+                regressions.push(format!("page {}: rendering differs ({:.1}% similar)", page_number, similarity * 100.0));
+            }
+
+            let normalize = |text: String| text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let input_text = extract_page_text(input, source_page).map(normalize).unwrap_or_default();
+            let output_text = extract_page_text(&output, page_number as usize).map(normalize).unwrap_or_default();
+            let text_match = input_text == output_text;
+            if !text_match {
+                regressions.push(format!("page {}: extracted text differs", page_number));
+            }
+
+            pages.push(PageVerification {
+                page_number: page_number as usize,
+                source_page,
+                dimensions_match,
+                similarity,
+                text_match,
+            });
+        }
+
+        Ok(VerifyReport {
+            passed: regressions.is_empty(),
+            input_page_count: input_pages.len(),
+            output_page_count: output_pages.len(),
+            pages,
+            regressions,
+        })
+    })
+}
+
+// TrueType programs substituted for the base-14 fonts. They are metric-compatible and
+// are expected under fonts/ in the app's bundled resources. Symbol and ZapfDingbats
+// have no substitute and are left as they are.
+const STANDARD_FONT_FILES: [(&str, &str); 12] = [
+    ("Helvetica", "LiberationSans-Regular.ttf"),
+    ("Helvetica-Bold", "LiberationSans-Bold.ttf"),
+    ("Helvetica-Oblique", "LiberationSans-Italic.ttf"),
+    ("Helvetica-BoldOblique", "LiberationSans-BoldItalic.ttf"),
+    ("Times-Roman", "LiberationSerif-Regular.ttf"),
+    ("Times-Bold", "LiberationSerif-Bold.ttf"),
+    ("Times-Italic", "LiberationSerif-Italic.ttf"),
+    ("Times-BoldItalic", "LiberationSerif-BoldItalic.ttf"),
+    ("Courier", "LiberationMono-Regular.ttf"),
+    ("Courier-Bold", "LiberationMono-Bold.ttf"),
+    ("Courier-Oblique", "LiberationMono-Italic.ttf"),
+    ("Courier-BoldOblique", "LiberationMono-BoldItalic.ttf"),
+];
+
+// Unicode values of WinAnsiEncoding codes 0x80..=0x9F (0 where undefined); the rest of
+// the encoding matches Latin-1
+#[cfg(any(test, feature = "bundled-fonts"))]
+const WIN_ANSI_HIGH: [u32; 32] = [
+    0x20AC, 0, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017D, 0,
+    0, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0, 0x017E,
+    0x0178,
+];
+
+#[cfg(any(test, feature = "bundled-fonts"))]
+fn truetype_table<'a>(program: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let read_u16 = |at: usize| program.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize);
+    let read_u32 = |at: usize| program.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let table_count = read_u16(4)?;
+    for index in 0..table_count {
+        let record = 12 + index * 16;
+        if program.get(record..record + 4)? == tag {
+            let (offset, length) = (read_u32(record + 8)?, read_u32(record + 12)?);
+            return program.get(offset..offset + length);
+        }
+    }
+    None
+}
+
+// Glyph index for a Unicode value from a (3,1) or (0,x) format 4 cmap subtable
+#[cfg(any(test, feature = "bundled-fonts"))]
+fn truetype_glyph(cmap: &[u8], code: u32) -> Option<u16> {
+    let read_u16 = |at: usize| cmap.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let read_u32 = |at: usize| cmap.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let subtable = (0..read_u16(2)? as usize).find_map(|index| {
+        let record = 4 + index * 8;
+        let platform = read_u16(record)?;
+        let encoding = read_u16(record + 2)?;
+        let offset = read_u32(record + 4)?;
+        let unicode = platform == 0 || (platform == 3 && encoding == 1);
+        (unicode && read_u16(offset) == Some(4)).then_some(offset)
+    })?;
+    if code > 0xFFFF {
+        return None;
+    }
+    let code = code as u16;
+    let segments = read_u16(subtable + 6)? as usize / 2;
+    let ends = subtable + 14;
+    let starts = ends + segments * 2 + 2;
+    let deltas = starts + segments * 2;
+    let range_offsets = deltas + segments * 2;
+    for segment in 0..segments {
+        if read_u16(ends + segment * 2)? < code {
+            continue;
+        }
+        let start = read_u16(starts + segment * 2)?;
+        if start > code {
+            return None;
+        }
+        let delta = read_u16(deltas + segment * 2)?;
+        let range_at = range_offsets + segment * 2;
+        let range_offset = read_u16(range_at)? as usize;
+        if range_offset == 0 {
+            return Some(code.wrapping_add(delta));
+        }
+        let glyph = read_u16(range_at + range_offset + (code - start) as usize * 2)?;
+        return Some(if glyph == 0 { 0 } else { glyph.wrapping_add(delta) });
+    }
+    None
+}
+
+// Font dictionary entries for an embedded TrueType program, with widths for WinAnsi
+// codes 32..=255. `font_name` is written as BaseFont and FontName, e.g. with a subset
+// tag; the flags come from the standard font name `base_font`.
+#[cfg(any(test, feature = "bundled-fonts"))]
+fn embedded_truetype_font(
+    doc: &mut Document,
+    base_font: &str,
+    font_name: &str,
+    program: Vec<u8>,
+) -> Result<lopdf::Dictionary, String> {
+    let table = |tag: &[u8; 4]| {
+        truetype_table(&program, tag).ok_or_else(|| format!("Font program has no {} table", String::from_utf8_lossy(tag)))
+    };
+    let read_u16 = |data: &[u8], at: usize| data.get(at..at + 2).map_or(0, |b| u16::from_be_bytes([b[0], b[1]]));
+    let read_i16 = |data: &[u8], at: usize| read_u16(data, at) as i16 as f64;
+
+    let (head, hhea, hmtx, cmap) = (table(b"head")?, table(b"hhea")?, table(b"hmtx")?, table(b"cmap")?);
+    let units = read_u16(head, 18).max(1) as f64;
+    let scale = |value: f64| (value * 1000.0 / units).round() as i64;
+    let metrics_count = read_u16(hhea, 34).max(1) as usize;
+    let advance = |glyph: u16| read_u16(hmtx, (glyph as usize).min(metrics_count - 1) * 4) as f64;
+
+    let widths: Vec<Object> = (32u32..=255)
+        .map(|code| {
+            let unicode = if (0x80..0xA0).contains(&code) { WIN_ANSI_HIGH[code as usize - 0x80] } else { code };
+            let glyph = truetype_glyph(cmap, unicode).unwrap_or(0);
+            Object::Integer(scale(advance(glyph)))
+        })
+        .collect();
+
+    let ascent = read_i16(hhea, 4);
+    let descent = read_i16(hhea, 6);
+    // sCapHeight exists from OS/2 version 2 on
+    let cap_height = truetype_table(&program, b"OS/2")
+        .filter(|os2| read_u16(os2, 0) >= 2)
+        .map_or(ascent, |os2| read_i16(os2, 88));
+    let italic_angle = truetype_table(&program, b"post").map_or(0.0, |post| read_i16(post, 4));
+
+    let mut flags = 32; // nonsymbolic
+    if base_font.starts_with("Courier") {
+        flags |= 1;
+    }
+    if base_font.starts_with("Times") {
+        flags |= 2;
+    }
+    if base_font.contains("Italic") || base_font.contains("Oblique") {
+        flags |= 64;
+    }
+
+    let mut file_dict = lopdf::Dictionary::new();
+    file_dict.set("Length1", Object::Integer(program.len() as i64));
+    let mut file = lopdf::Stream::new(file_dict, program.clone());
+    let _ = file.compress();
+    let file_id = doc.add_object(file);
+
+    let mut descriptor = lopdf::Dictionary::new();
+    descriptor.set("Type", Object::Name(b"FontDescriptor".to_vec()));
+    descriptor.set("FontName", Object::Name(font_name.as_bytes().to_vec()));
+    descriptor.set("Flags", Object::Integer(flags));
+    descriptor.set(
+        "FontBBox",
+        Object::Array([36, 38, 40, 42].iter().map(|&at| Object::Integer(scale(read_i16(head, at)))).collect()),
+    );
+    descriptor.set("ItalicAngle", Object::Integer(italic_angle as i64));
+    descriptor.set("Ascent", Object::Integer(scale(ascent)));
+    descriptor.set("Descent", Object::Integer(scale(descent)));
+    descriptor.set("CapHeight", Object::Integer(scale(cap_height)));
+    descriptor.set("StemV", Object::Integer(if flags & 1 != 0 || base_font.contains("Bold") { 120 } else { 80 }));
+    descriptor.set("FontFile2", Object::Reference(file_id));
+    let descriptor_id = doc.add_object(descriptor);
+
+    let mut font = lopdf::Dictionary::new();
+    font.set("Subtype", Object::Name(b"TrueType".to_vec()));
+    font.set("BaseFont", Object::Name(font_name.as_bytes().to_vec()));
+    font.set("FirstChar", Object::Integer(32));
+    font.set("LastChar", Object::Integer(255));
+    font.set("Widths", Object::Array(widths));
+    font.set("FontDescriptor", Object::Reference(descriptor_id));
+    Ok(font)
+}
+
+// Replace references to the base-14 fonts with embedded TrueType equivalents read from
+// `font_dir`. Returns the number of font dictionaries changed. Fonts with custom
+// encodings are skipped. Each program is cut down to the glyphs the content draws and
+// named with a subset tag; fonts in the AcroForm's default resources, which viewers may
+// use for any text, and documents whose text can't all be attributed get the full font.
+#[cfg(any(test, feature = "bundled-fonts"))]
+fn embed_bundled_fonts(doc: &mut Document, font_dir: &std::path::Path) -> Result<usize, String> {
+    let candidates: Vec<(ObjectId, String)> = doc
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let font = object.as_dict().ok()?;
+            if font.get(b"Type").and_then(Object::as_name).ok()? != b"Font"
+                || font.get(b"Subtype").and_then(Object::as_name).ok()? != b"Type1"
+                || font.has(b"FontDescriptor")
+            {
+                return None;
+            }
+            let encoding_ok = match font.get(b"Encoding") {
+                Err(_) => true,
+                Ok(encoding) => encoding.as_name().is_ok_and(|name| name == b"WinAnsiEncoding"),
+            };
+            let base_font = String::from_utf8_lossy(font.get(b"BaseFont").and_then(Object::as_name).ok()?).into_owned();
+            (encoding_ok && STANDARD_FONT_FILES.iter().any(|(name, _)| *name == base_font)).then_some((id, base_font))
+        })
+        .collect();
+
+    // Character codes drawn with each standard font, or None where it must stay whole
+    let mut used: HashMap<String, Option<std::collections::BTreeSet<u32>>> = HashMap::new();
+    let form_fonts: Vec<ObjectId> = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"DR").ok())
+        .and_then(|dr| resolve_object(doc, dr))
+        .and_then(|dr| dr.as_dict().ok())
+        .and_then(|dr| resource_category(doc, dr, b"Font"))
+        .map(|fonts| fonts.iter().filter_map(|(_, font)| font.as_reference().ok()).collect())
+        .unwrap_or_default();
+    match text_content_streams(doc) {
+        Some(streams) => {
+            for (content, resources) in &streams {
+                let fonts = load_fonts(doc, *resources);
+                let codes = font_codes_in_content(content, &fonts)?;
+                let font_dicts = resources.and_then(|r| resource_category(doc, r, b"Font"));
+                for (name, font) in font_dicts.into_iter().flat_map(|fonts| fonts.iter()) {
+                    let font_id = match font.as_reference() {
+                        Ok(id) => id,
+                        Err(_) => continue,
+                    };
+                    if let Some((_, base_font)) = candidates.iter().find(|(id, _)| *id == font_id) {
+                        if let Some(entry) = used.entry(base_font.clone()).or_insert_with(|| Some(Default::default())) {
+                            entry.extend(codes.get(name).into_iter().flatten());
+                        }
+                    }
+                }
+            }
+            for (font_id, base_font) in &candidates {
+                if form_fonts.contains(font_id) {
+                    used.insert(base_font.clone(), None);
+                }
+            }
+        }
+        None => used.extend(candidates.iter().map(|(_, base_font)| (base_font.clone(), None))),
+    }
+
+    let mut embedded: HashMap<String, lopdf::Dictionary> = HashMap::new();
+    for (font_id, base_font) in &candidates {
+        let replacement = match embedded.get(base_font) {
+            Some(replacement) => replacement.clone(),
+            None => {
+                let file_name = STANDARD_FONT_FILES.iter().find(|(name, _)| name == base_font).map(|(_, file)| *file);
+                let file_path = font_dir.join(file_name.ok_or("Unknown standard font")?);
+                let program = std::fs::read(&file_path)
+                    .map_err(|e| format!("Font {} is not bundled ({}): {}", base_font, file_path.display(), e))?;
+
+                // A font no content draws with keeps just .notdef
+                let subset = used.get(base_font).cloned().unwrap_or_else(|| Some(Default::default())).and_then(|codes| {
+                    let cmap = truetype_table(&program, b"cmap")?;
+                    let glyphs = codes
+                        .iter()
+                        .map(|&code| if (0x80..0xA0).contains(&code) { WIN_ANSI_HIGH[code as usize - 0x80] } else { code })
+                        .filter_map(|unicode| truetype_glyph(cmap, unicode))
+                        .map(u32::from)
+                        .collect();
+                    subset_truetype_glyphs(&program, &glyphs).filter(|subset| subset.len() < program.len())
+                });
+                let replacement = match subset {
+                    Some(subset) => {
+                        let font_name = format!("{}+{}", subset_tag(&subset), base_font);
+                        embedded_truetype_font(doc, base_font, &font_name, subset)?
+                    }
+                    None => embedded_truetype_font(doc, base_font, base_font, program)?,
+                };
+                embedded.insert(base_font.clone(), replacement.clone());
+                replacement
+            }
+        };
+        let font = doc.get_dictionary_mut(*font_id).map_err(|e| e.to_string())?;
+        for (key, value) in replacement.iter() {
+            font.set(key.clone(), value.clone());
+        }
+        // Without an explicit encoding a standard font uses StandardEncoding, which
+        // agrees with WinAnsi for letters and digits
+        font.set("Encoding", Object::Name(b"WinAnsiEncoding".to_vec()));
+    }
+    Ok(candidates.len())
+}
+
+// Embed the base-14 fonts a document uses from the fonts bundled with the app. Only built
+// with the bundled-fonts feature, since the fonts are not part of the repository.
+#[cfg(feature = "bundled-fonts")]
+#[tauri::command]
+async fn embed_standard_fonts<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+) -> Result<usize, String> {
+    let font_dir = app.path().resource_dir().map_err(|e| e.to_string())?.join("fonts");
+    let mut doc = snapshot_document(&state, &path)?;
+    let embedded = embed_bundled_fonts(&mut doc, &font_dir)?;
+    save_document(&mut doc, &output_path)?;
+    Ok(embedded)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DestPosition {
+    // Destination type, e.g. XYZ, Fit, FitH
+    fit: String,
+    left: Option<f64>,
+    top: Option<f64>,
+    zoom: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NamedDest {
+    name: String,
+    page_number: Option<usize>,
+    position: Option<DestPosition>,
+}
+
+// Every name/value pair of a name tree, in tree order
+fn name_tree_entries<'a>(
+    doc: &'a Document,
+    node: &'a lopdf::Dictionary,
+    depth: usize,
+    entries: &mut Vec<(Vec<u8>, &'a Object)>,
+) {
+    if depth > 32 {
+        return;
+    }
+    if let Some(Object::Array(names)) = node.get(b"Names").ok().and_then(|n| resolve_object(doc, n)) {
+        for pair in names.chunks(2) {
+            if let [Object::String(name, _), value] = pair {
+                entries.push((name.clone(), value));
+            }
+        }
+    }
+    if let Some(Object::Array(kids)) = node.get(b"Kids").ok().and_then(|k| resolve_object(doc, k)) {
+        for kid in kids {
+            if let Some(Object::Dictionary(kid)) = resolve_object(doc, kid) {
+                name_tree_entries(doc, kid, depth + 1, entries);
+            }
+        }
+    }
+}
+
+fn destination_position(dest: &[Object]) -> Option<DestPosition> {
+    let fit = String::from_utf8_lossy(dest.get(1)?.as_name().ok()?).into_owned();
+    let number = |index: usize| dest.get(index).and_then(|value| value.as_float().ok()).map(|value| value as f64);
+    let (left, top, zoom) = match fit.as_str() {
+        "XYZ" => (number(2), number(3), number(4).filter(|&zoom| zoom != 0.0)),
+        "FitH" | "FitBH" => (None, number(2), None),
+        "FitV" | "FitBV" => (number(2), None, None),
+        "FitR" => (number(2), number(5), None),
+        _ => (None, None, None),
+    };
+    Some(DestPosition { fit, left, top, zoom })
+}
+
+// Named destinations from the catalog's /Names /Dests tree and the older /Dests
+// dictionary, sorted by name. Destinations pointing at a missing page have no page number.
+fn named_destinations(doc: &Document) -> Result<Vec<NamedDest>, String> {
+    let catalog = doc.catalog().map_err(|e| e.to_string())?;
+    let mut entries: Vec<(Vec<u8>, &Object)> = Vec::new();
+    let tree = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(|names| resolve_object(doc, names))
+        .and_then(|names| names.as_dict().ok())
+        .and_then(|names| names.get(b"Dests").ok())
+        .and_then(|dests| resolve_object(doc, dests))
+        .and_then(|dests| dests.as_dict().ok());
+    if let Some(tree) = tree {
+        name_tree_entries(doc, tree, 0, &mut entries);
+    }
+    let dests = catalog
+        .get(b"Dests")
+        .ok()
+        .and_then(|dests| resolve_object(doc, dests))
+        .and_then(|dests| dests.as_dict().ok());
+    if let Some(dests) = dests {
+        entries.extend(dests.iter().map(|(name, value)| (name.clone(), value)));
+    }
+
+    let page_numbers: HashMap<ObjectId, usize> =
+        doc.get_pages().into_iter().map(|(number, id)| (id, number as usize)).collect();
+    let mut destinations: Vec<NamedDest> = entries
+        .into_iter()
+        .map(|(name, value)| {
+            let dest = named_destination_array(doc, value).unwrap_or_default();
+            let page_number = dest
+                .first()
+                .and_then(|page| page.as_reference().ok())
+                .and_then(|page| page_numbers.get(&page).copied());
+            NamedDest {
+                name: decode_pdf_string(&name),
+                page_number,
+                position: destination_position(&dest),
+            }
+        })
+        .collect();
+    destinations.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(destinations)
+}
+
+#[tauri::command]
+async fn get_named_destinations(state: State<'_, AppState>, path: String) -> Result<Vec<NamedDest>, String> {
+    with_document(&state, &path, named_destinations)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Permissions {
+    print: bool,
+    modify: bool,
+    copy: bool,
+    annotate: bool,
+    fill_forms: bool,
+    extract_for_accessibility: bool,
+    assemble: bool,
+    print_high_quality: bool,
+}
+
+impl Permissions {
+    // Reserved bits 7-8 and 13-32 are set, bits 1-2 are clear
+    fn to_flags(self) -> i32 {
+        let mut flags: u32 = 0xFFFF_F0C0;
+        let bits = [
+            (self.print, 3),
+            (self.modify, 4),
+            (self.copy, 5),
+            (self.annotate, 6),
+            (self.fill_forms, 9),
+            (self.extract_for_accessibility, 10),
+            (self.assemble, 11),
+            (self.print_high_quality, 12),
+        ];
+        for (allowed, bit) in bits {
+            if allowed {
+                flags |= 1 << (bit - 1);
+            }
+        }
+        flags as i32
+    }
+
+    fn from_flags(flags: i64) -> Self {
+        let bit = |position: u32| flags & (1 << (position - 1)) != 0;
+        Permissions {
+            print: bit(3),
+            modify: bit(4),
+            copy: bit(5),
+            annotate: bit(6),
+            fill_forms: bit(9),
+            extract_for_accessibility: bit(10),
+            assemble: bit(11),
+            print_high_quality: bit(12),
+        }
+    }
+}
+
+// Password padding string from the standard security handler
+const PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xBF, 0x4E, 0x5E, 0x4E, 0x75, 0x8A, 0x41, 0x64, 0x00, 0x4E, 0x56, 0xFF, 0xFA, 0x01, 0x08, 0x2E, 0x2E, 0x00,
+    0xB6, 0xD0, 0x68, 0x3E, 0x80, 0x2F, 0x0C, 0xA9, 0xFE, 0x64, 0x53, 0x69, 0x7A,
+];
+
+// Length of the RC4 file key in bytes (128-bit, revision 3)
+const ENCRYPTION_KEY_LENGTH: usize = 16;
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants: Vec<u32> = (0..64).map(|i| (((i + 1) as f64).sin().abs() * 4_294_967_296.0) as u32).collect();
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for chunk in message.chunks(64) {
+        let words: Vec<u32> = chunk.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(constants[i]).wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[(i / 16) * 4 + i % 4]));
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 16];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
+
+// RC4 is symmetric, so this both encrypts and decrypts
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut s: Vec<u8> = (0..=255).collect();
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+        s.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|&byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(s[i as usize]);
+            s.swap(i as usize, j as usize);
+            byte ^ s[s[i as usize].wrapping_add(s[j as usize]) as usize]
+        })
+        .collect()
+}
+
+// Revision 3 applies RC4 twenty times, with the key XORed with the round number
+fn rc4_rounds(key: &[u8], data: &[u8]) -> Vec<u8> {
+    (0..20u8).fold(data.to_vec(), |data, round| {
+        let round_key: Vec<u8> = key.iter().map(|b| b ^ round).collect();
+        rc4(&round_key, &data)
+    })
+}
+
+fn padded_password(password: &[u8]) -> Vec<u8> {
+    password.iter().chain(PASSWORD_PADDING.iter()).take(32).copied().collect()
+}
+
+// MD5 applied once, then fifty more times over the first `length` bytes (revision 3)
+fn md5_iterated(data: &[u8], length: usize) -> Vec<u8> {
+    let mut digest = md5(data).to_vec();
+    for _ in 0..50 {
+        digest = md5(&digest[..length]).to_vec();
+    }
+    digest.truncate(length);
+    digest
+}
+
+// File encryption key derived from a user password by the standard security handler
+// (revisions 2 to 4)
+fn rc4_file_key(
+    password: &[u8],
+    owner_value: &[u8],
+    flags: i32,
+    file_id: &[u8],
+    length: usize,
+    revision: i64,
+    encrypt_metadata: bool,
+) -> Vec<u8> {
+    let mut input = padded_password(password);
+    input.extend_from_slice(owner_value);
+    input.extend_from_slice(&flags.to_le_bytes());
+    input.extend_from_slice(file_id);
+    if revision >= 4 && !encrypt_metadata {
+        input.extend_from_slice(&[0xFF; 4]);
+    }
+    if revision >= 3 {
+        md5_iterated(&input, length)
+    } else {
+        md5(&input)[..length.min(16)].to_vec()
+    }
+}
+
+// The /U value expected for a file key; from revision 3 on only the first 16 bytes are significant
+fn user_password_value(key: &[u8], file_id: &[u8], revision: i64) -> Vec<u8> {
+    if revision < 3 {
+        return rc4(key, &PASSWORD_PADDING);
+    }
+    let mut input = PASSWORD_PADDING.to_vec();
+    input.extend_from_slice(file_id);
+    let mut value = rc4_rounds(key, &md5(&input));
+    value.resize(32, 0);
+    value
+}
+
+fn rc4_encrypt_object(object: &mut Object, key: &[u8]) {
+    match object {
+        Object::String(bytes, _) => *bytes = rc4(key, bytes),
+        Object::Array(items) => items.iter_mut().for_each(|item| rc4_encrypt_object(item, key)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| rc4_encrypt_object(value, key)),
+        Object::Stream(stream) => {
+            stream.dict.iter_mut().for_each(|(_, value)| rc4_encrypt_object(value, key));
+            let content = rc4(key, &stream.content);
+            stream.set_content(content);
+        }
+        _ => {}
+    }
+}
+
+// Restrict a document with an owner password and permission flags. The user password
+// is empty, so any viewer opens it without prompting but should enforce `perms`; this
+// uses 128-bit RC4 (revision 3), which discourages edits rather than protecting content.
+#[tauri::command]
+async fn set_permissions_only(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    owner_password: String,
+    perms: Permissions,
+) -> Result<(), String> {
+    if owner_password.is_empty() {
+        return Err("Owner password must not be empty".to_string());
+    }
+    let mut doc = snapshot_document(&state, &path)?;
+    if doc.is_encrypted() {
+        return Err("Document is encrypted".to_string());
+    }
+
+    ensure_document_id(&mut doc, &output_path);
+    let file_id = match doc.trailer.get(b"ID").and_then(Object::as_array).ok().and_then(|ids| ids.first()) {
+        Some(Object::String(id, _)) => id.clone(),
+        _ => return Err("Document has no usable /ID".to_string()),
+    };
+    let flags = perms.to_flags();
+
+    let owner_key = md5_iterated(&padded_password(owner_password.as_bytes()), ENCRYPTION_KEY_LENGTH);
+    let owner_value = rc4_rounds(&owner_key, &PASSWORD_PADDING);
+
+    let key = rc4_file_key(b"", &owner_value, flags, &file_id, ENCRYPTION_KEY_LENGTH, 3, true);
+    let user_value = user_password_value(&key, &file_id, 3);
+
+    for (&(number, generation), object) in doc.objects.iter_mut() {
+        let type_name = match object {
+            Object::Dictionary(dict) => dict.get(b"Type").and_then(Object::as_name).ok().map(<[u8]>::to_vec),
+            Object::Stream(stream) => stream.dict.get(b"Type").and_then(Object::as_name).ok().map(<[u8]>::to_vec),
+            _ => None,
+        };
+        // Cross-reference and object streams are never encrypted
+        if matches!(type_name.as_deref(), Some(b"XRef" | b"ObjStm")) {
+            continue;
+        }
+        // Nor is a signature's /Contents, which holds the signed digest
+        let signature = match (type_name.as_deref(), &mut *object) {
+            (Some(b"Sig" | b"DocTimeStamp"), Object::Dictionary(dict)) => dict.remove(b"Contents"),
+            _ => None,
+        };
+
+        let mut object_key = key.clone();
+        object_key.extend_from_slice(&number.to_le_bytes()[..3]);
+        object_key.extend_from_slice(&generation.to_le_bytes());
+        let object_key = md5(&object_key);
+        rc4_encrypt_object(object, &object_key[..(ENCRYPTION_KEY_LENGTH + 5).min(16)]);
+        if let (Some(contents), Object::Dictionary(dict)) = (signature, object) {
+            dict.set("Contents", contents);
+        }
+    }
+
+    let mut encrypt = lopdf::Dictionary::new();
+    encrypt.set("Filter", Object::Name(b"Standard".to_vec()));
+    encrypt.set("V", Object::Integer(2));
+    encrypt.set("R", Object::Integer(3));
+    encrypt.set("Length", Object::Integer(ENCRYPTION_KEY_LENGTH as i64 * 8));
+    encrypt.set("O", Object::String(owner_value, lopdf::StringFormat::Hexadecimal));
+    encrypt.set("U", Object::String(user_value, lopdf::StringFormat::Hexadecimal));
+    encrypt.set("P", Object::Integer(flags as i64));
+    // The encryption dictionary itself must stay in the clear, so it is added afterwards
+    let encrypt_id = doc.add_object(encrypt);
+    doc.trailer.set("Encrypt", Object::Reference(encrypt_id));
+    // 128-bit keys (revision 3) need PDF 1.4
+    if doc.version.as_str() < "1.4" {
+        doc.version = "1.4".to_string();
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+// Permissions granted by the document's security handler; unencrypted documents allow everything
+#[tauri::command]
+async fn get_permissions(state: State<'_, AppState>, path: String) -> Result<Permissions, String> {
+    with_document(&state, &path, |doc| {
+        let flags = doc
+            .trailer
+            .get(b"Encrypt")
+            .ok()
+            .and_then(|encrypt| resolve_object(doc, encrypt))
+            .and_then(|encrypt| encrypt.as_dict().ok())
+            .and_then(|encrypt| encrypt.get(b"P").and_then(Object::as_i64).ok());
+        Ok(Permissions::from_flags(flags.unwrap_or(-1)))
+    })
+}
+
+// Color operator for an annotation color array (/C or /IC), by component count
+fn annotation_color_operation(values: &[f64], stroke: bool) -> Option<lopdf::content::Operation> {
+    let operator = match (values.len(), stroke) {
+        (1, false) => "g",
+        (1, true) => "G",
+        (3, false) => "rg",
+        (3, true) => "RG",
+        (4, false) => "k",
+        (4, true) => "K",
+        _ => return None,
+    };
+    let operands = values.iter().map(|&v| Object::Real(v as f32)).collect();
+    Some(lopdf::content::Operation::new(operator, operands))
+}
+
+// Font size and fill color from a default appearance string such as "/Helv 10 Tf 1 0 0 rg"
+fn parse_default_appearance(appearance: &[u8]) -> (f64, Vec<f64>) {
+    let mut size = 12.0;
+    let mut color = vec![0.0];
+    let mut numbers: Vec<f64> = Vec::new();
+    for token in String::from_utf8_lossy(appearance).split_whitespace() {
+        match token {
+            "Tf" => size = numbers.last().copied().filter(|&s| s > 0.0).unwrap_or(size),
+            "g" | "rg" | "k" => {
+                let count = match token {
+                    "g" => 1,
+                    "rg" => 3,
+                    _ => 4,
+                };
+                if numbers.len() >= count {
+                    color = numbers[numbers.len() - count..].to_vec();
+                }
+            }
+            _ => {
+                if let Ok(number) = token.parse::<f64>() {
+                    numbers.push(number);
+                    continue;
+                }
+            }
+        }
+        numbers.clear();
+    }
+    (size, color)
+}
+
+// Greedy word wrap of Helvetica text to a maximum line width
+fn wrap_helvetica_text(text: &str, font_size: f64, max_width: f64) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if !line.is_empty() && helvetica_text_width(&encode_standard_font_text(&candidate), font_size) > max_width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// Content and resources for an annotation's normal appearance, drawn in page space
+// over its Rect, or None for subtypes without a generator
+fn annotation_appearance(
+    doc: &Document,
+    annot: &lopdf::Dictionary,
+    rect: [f64; 4],
+) -> Option<(Vec<lopdf::content::Operation>, lopdf::Dictionary)> {
+    use lopdf::content::Operation;
+    let numbers = |key: &[u8]| {
+        annot
+            .get(key)
+            .ok()
+            .and_then(|value| resolve_object(doc, value))
+            .and_then(|value| value.as_array().ok())
+            .map(|values| operand_numbers(values))
+            .unwrap_or_default()
+    };
+    let color = numbers(b"C");
+    let border_width = annot
+        .get(b"BS")
+        .ok()
+        .and_then(|bs| resolve_object(doc, bs))
+        .and_then(|bs| bs.as_dict().ok())
+        .and_then(|bs| bs.get(b"W").and_then(Object::as_float).ok())
+        .map(|w| w as f64)
+        .or_else(|| numbers(b"Border").get(2).copied())
+        .unwrap_or(1.0);
+    let opacity = annot.get(b"CA").and_then(Object::as_float).map_or(1.0, |ca| ca as f64);
+    let [x0, y0, x1, y1] = rect;
+    let mut resources = lopdf::Dictionary::new();
+    let mut ops = Vec::new();
+
+    let set_state = |state: lopdf::Dictionary, ops: &mut Vec<Operation>, resources: &mut lopdf::Dictionary| {
+        let mut states = lopdf::Dictionary::new();
+        states.set("GS0", Object::Dictionary(state));
+        resources.set("ExtGState", Object::Dictionary(states));
+        ops.push(Operation::new("gs", vec![Object::Name(b"GS0".to_vec())]));
+    };
+
+    match annot.get(b"Subtype").and_then(Object::as_name).ok()? {
+        b"Highlight" => {
+            let mut state = opacity_state(opacity);
+            state.set("BM", Object::Name(b"Multiply".to_vec()));
+            set_state(state, &mut ops, &mut resources);
+            ops.extend(annotation_color_operation(&color, false));
+            let mut quads = numbers(b"QuadPoints");
+            if quads.len() < 8 {
+                quads = vec![x0, y1, x1, y1, x0, y0, x1, y0];
+            }
+            for quad in quads.chunks_exact(8) {
+                // Corners are ordered upper-left, upper-right, lower-left, lower-right
+                let points = [(quad[0], quad[1]), (quad[2], quad[3]), (quad[6], quad[7]), (quad[4], quad[5])];
+                for (index, (x, y)) in points.iter().enumerate() {
+                    let operator = if index == 0 { "m" } else { "l" };
+                    ops.push(Operation::new(operator, vec![Object::Real(*x as f32), Object::Real(*y as f32)]));
+                }
+                ops.push(Operation::new("h", vec![]));
+            }
+            ops.push(Operation::new("f", vec![]));
+        }
+        b"Square" | b"Circle" | b"Link" => {
+            let subtype = annot.get(b"Subtype").and_then(Object::as_name).ok();
+            let is_link = subtype == Some(&b"Link"[..]);
+            let interior = if is_link { Vec::new() } else { numbers(b"IC") };
+            // Links without a color or border are invisible hot spots
+            if (border_width <= 0.0 && interior.is_empty()) || (is_link && color.is_empty()) {
+                return Some((ops, resources));
+            }
+            if opacity < 1.0 {
+                set_state(opacity_state(opacity), &mut ops, &mut resources);
+            }
+            ops.extend(annotation_color_operation(&color, true));
+            ops.extend(annotation_color_operation(&interior, false));
+            ops.push(Operation::new("w", vec![Object::Real(border_width as f32)]));
+            let inset = border_width / 2.0;
+            if subtype == Some(&b"Circle"[..]) {
+                ops.extend(ellipse_path(x0 + inset, y0 + inset, x1 - inset, y1 - inset));
+            } else {
+                ops.push(Operation::new(
+                    "re",
+                    [x0 + inset, y0 + inset, x1 - x0 - border_width, y1 - y0 - border_width]
+                        .iter()
+                        .map(|&v| Object::Real(v as f32))
+                        .collect(),
+                ));
+            }
+            let paint = match (interior.is_empty(), border_width > 0.0 && !color.is_empty()) {
+                (true, true) => "S",
+                (false, true) => "B",
+                (false, false) => "f",
+                (true, false) => "n",
+            };
+            ops.push(Operation::new(paint, vec![]));
+        }
+        b"FreeText" => {
+            let appearance = annot.get(b"DA").and_then(Object::as_str).unwrap_or(b"");
+            let (size, text_color) = parse_default_appearance(appearance);
+            let contents = annot
+                .get(b"Contents")
+                .and_then(Object::as_str)
+                .map(decode_pdf_string)
+                .unwrap_or_default();
+
+            let mut fonts = lopdf::Dictionary::new();
+            fonts.set("Helv", Object::Dictionary(standard_font("Helvetica")));
+            resources.set("Font", Object::Dictionary(fonts));
+            if opacity < 1.0 {
+                set_state(opacity_state(opacity), &mut ops, &mut resources);
+            }
+            if border_width > 0.0 && !color.is_empty() {
+                ops.extend(annotation_color_operation(&color, true));
+                ops.push(Operation::new("w", vec![Object::Real(border_width as f32)]));
+                let inset = border_width / 2.0;
+                ops.push(Operation::new(
+                    "re",
+                    [x0 + inset, y0 + inset, x1 - x0 - border_width, y1 - y0 - border_width]
+                        .iter()
+                        .map(|&v| Object::Real(v as f32))
+                        .collect(),
+                ));
+                ops.push(Operation::new("S", vec![]));
+            }
+
+            let padding = border_width.max(0.0) + 2.0;
+            let leading = size * 1.2;
+            ops.push(Operation::new("BT", vec![]));
+            ops.extend(annotation_color_operation(&text_color, false));
+            ops.push(Operation::new("Tf", vec![Object::Name(b"Helv".to_vec()), Object::Real(size as f32)]));
+            ops.push(Operation::new("TL", vec![Object::Real(leading as f32)]));
+            ops.push(Operation::new(
+                "Td",
+                vec![Object::Real((x0 + padding) as f32), Object::Real((y1 - padding - size) as f32)],
+            ));
+            for (index, line) in wrap_helvetica_text(&contents, size, x1 - x0 - 2.0 * padding).iter().enumerate() {
+                if index > 0 {
+                    ops.push(Operation::new("T*", vec![]));
+                }
+                ops.push(Operation::new(
+                    "Tj",
+                    vec![Object::String(encode_standard_font_text(line), lopdf::StringFormat::Literal)],
+                ));
+            }
+            ops.push(Operation::new("ET", vec![]));
+        }
+        b"Line" => {
+            let points = numbers(b"L");
+            if points.len() < 4 || border_width <= 0.0 || color.is_empty() {
+                return Some((ops, resources));
+            }
+            let (start, end) = ((points[0], points[1]), (points[2], points[3]));
+            if opacity < 1.0 {
+                set_state(opacity_state(opacity), &mut ops, &mut resources);
+            }
+            ops.extend(annotation_color_operation(&color, true));
+            ops.extend(annotation_color_operation(&color, false));
+            ops.push(Operation::new("w", vec![Object::Real(border_width as f32)]));
+            ops.push(Operation::new("J", vec![Object::Integer(1)]));
+            let point = |operator: &str, (x, y): (f64, f64)| {
+                Operation::new(operator, vec![Object::Real(x as f32), Object::Real(y as f32)])
+            };
+            ops.push(point("m", start));
+            ops.push(point("l", end));
+            ops.push(Operation::new("S", vec![]));
+
+            // Arrowheads from /LE [start end], pointing away from the line
+            let endings = annot
+                .get(b"LE")
+                .ok()
+                .and_then(|endings| resolve_object(doc, endings))
+                .and_then(|endings| endings.as_array().ok())
+                .cloned()
+                .unwrap_or_default();
+            for (index, (tip, from)) in [(start, end), (end, start)].into_iter().enumerate() {
+                let style = endings.get(index).and_then(|e| e.as_name().ok()).unwrap_or(&b"None"[..]);
+                if style != b"OpenArrow" && style != b"ClosedArrow" {
+                    continue;
+                }
+                let [left, right] = arrowhead_points(from, tip, border_width);
+                ops.push(point("m", left));
+                ops.push(point("l", tip));
+                ops.push(point("l", right));
+                ops.push(Operation::new(if style == b"ClosedArrow" { "b" } else { "S" }, vec![]));
+            }
+        }
+        _ => return None,
+    }
+    Some((ops, resources))
+}
+
+// A closed ellipse path inscribed in a rectangle, as four Bezier quarters
+fn ellipse_path(x0: f64, y0: f64, x1: f64, y1: f64) -> Vec<lopdf::content::Operation> {
+    use lopdf::content::Operation;
+    // Control point distance that best approximates a quarter circle
+    const KAPPA: f64 = 0.552_284_75;
+    let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+    let (rx, ry) = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let numbers = |values: &[f64]| -> Vec<Object> { values.iter().map(|&v| Object::Real(v as f32)).collect() };
+    vec![
+        Operation::new("m", numbers(&[cx + rx, cy])),
+        Operation::new("c", numbers(&[cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry])),
+        Operation::new("c", numbers(&[cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy])),
+        Operation::new("c", numbers(&[cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry])),
+        Operation::new("c", numbers(&[cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy])),
+        Operation::new("h", vec![]),
+    ]
+}
+
+// The two back corners of an arrowhead at `tip` on a line coming from `from`
+fn arrowhead_points(from: (f64, f64), tip: (f64, f64), line_width: f64) -> [(f64, f64); 2] {
+    let length = (line_width * 4.0).max(6.0);
+    let angle = (tip.1 - from.1).atan2(tip.0 - from.0);
+    let spread = std::f64::consts::PI / 7.0;
+    let corner = |turn: f64| {
+        let direction = angle + std::f64::consts::PI + turn;
+        (tip.0 + length * direction.cos(), tip.1 + length * direction.sin())
+    };
+    [corner(spread), corner(-spread)]
+}
+
+// An /AP dictionary whose normal appearance is a form drawing `ops` in page space over
+// the annotation's Rect
+fn appearance_dictionary(
+    doc: &mut Document,
+    ops: Vec<lopdf::content::Operation>,
+    resources: lopdf::Dictionary,
+    rect: [f64; 4],
+) -> Result<lopdf::Dictionary, String> {
+    let content = lopdf::content::Content { operations: ops }.encode().map_err(|e| e.to_string())?;
+    let mut form = lopdf::Dictionary::new();
+    form.set("Type", Object::Name(b"XObject".to_vec()));
+    form.set("Subtype", Object::Name(b"Form".to_vec()));
+    form.set("BBox", number_array(&rect));
+    form.set("Resources", Object::Dictionary(resources));
+    let form_id = doc.add_object(lopdf::Stream::new(form, content));
+
+    let mut ap = lopdf::Dictionary::new();
+    ap.set("N", Object::Reference(form_id));
+    Ok(ap)
+}
+
+// Build /AP /N appearance streams for Highlight, Square, Circle, Line, FreeText and Link
+// annotations that have none, from their geometry, colors and contents. Returns the
+// number generated.
+#[tauri::command]
+async fn regenerate_appearances(
+    state: State<'_, AppState>,
+    path: String,
+    pages: Option<Vec<usize>>,
+) -> Result<usize, String> {
+    edit_document(&state, &path, |doc| {
+        let mut generated = 0;
+        for (_, page_id) in select_pages(doc, &pages)? {
+            for annot in page_annotations(doc, page_id) {
+                let annot_id = match annot.as_reference() {
+                    Ok(id) => id,
+                    Err(_) => continue,
+                };
+                let appearance = {
+                    let dict = match doc.get_dictionary(annot_id) {
+                        Ok(dict) if !dict.has(b"AP") => dict,
+                        _ => continue,
+                    };
+                    let rect = match dict.get(b"Rect").ok().and_then(|rect| rect_from_object(doc, rect)) {
+                        Some(rect) => rect,
+                        None => continue,
+                    };
+                    annotation_appearance(doc, dict, rect).map(|(ops, resources)| (ops, resources, rect))
+                };
+                let (ops, resources, rect) = match appearance {
+                    Some(appearance) => appearance,
+                    None => continue,
+                };
+
+                let ap = appearance_dictionary(doc, ops, resources, rect)?;
+                let dict = doc.get_dictionary_mut(annot_id).map_err(|e| e.to_string())?;
+                dict.set("AP", Object::Dictionary(ap));
+                generated += 1;
+            }
+        }
+        Ok(generated)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DedupReport {
+    objects_removed: usize,
+    bytes_saved: u64,
+}
+
+// Image streams, font programs and the font dictionaries built on them: the objects
+// merged documents tend to duplicate. Images are left out unless `images` is set.
+fn dedup_candidates(doc: &Document, images: bool) -> Vec<ObjectId> {
+    let mut font_files = std::collections::HashSet::new();
+    for object in doc.objects.values() {
+        if let Ok(dict) = object.as_dict() {
+            if dict.get(b"Type").and_then(Object::as_name).ok() == Some(&b"FontDescriptor"[..]) {
+                for key in [&b"FontFile"[..], &b"FontFile2"[..], &b"FontFile3"[..]] {
+                    if let Ok(id) = dict.get(key).and_then(Object::as_reference) {
+                        font_files.insert(id);
+                    }
+                }
+            }
+        }
+    }
+    doc.objects
+        .iter()
+        .filter(|(id, object)| match object {
+            Object::Stream(_) => font_files.contains(id) || (images && is_image_xobject(object)),
+            Object::Dictionary(dict) => matches!(
+                dict.get(b"Type").and_then(Object::as_name),
+                Ok(b"Font") | Ok(b"FontDescriptor")
+            ),
+            _ => false,
+        })
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+// Collapse byte-identical images, font programs and font dictionaries into one shared
+// object each. Merging repeats until nothing changes, since fonts only compare equal
+// once the programs they point to have been merged.
+#[tauri::command]
+async fn dedup_resources(state: State<'_, AppState>, path: String, output_path: String) -> Result<DedupReport, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let report = merge_duplicate_objects(&mut doc, true);
+    save_document(&mut doc, &output_path)?;
+    Ok(report)
+}
+
+fn merge_duplicate_objects(doc: &mut Document, images: bool) -> DedupReport {
+    use std::hash::{Hash, Hasher};
+    let mut report = DedupReport { objects_removed: 0, bytes_saved: 0 };
+
+    loop {
+        let mut candidates = dedup_candidates(doc, images);
+        candidates.sort();
+        let mut buckets: HashMap<u64, Vec<(ObjectId, String)>> = HashMap::new();
+        let mut duplicates: HashMap<ObjectId, ObjectId> = HashMap::new();
+
+        for id in candidates {
+            let (dict, content): (&lopdf::Dictionary, &[u8]) = match doc.objects.get(&id) {
+                Some(Object::Stream(stream)) => (&stream.dict, &stream.content),
+                Some(Object::Dictionary(dict)) => (dict, &[]),
+                _ => continue,
+            };
+            let key = format_dictionary(dict, 0);
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            key.hash(&mut hasher);
+            content.hash(&mut hasher);
+            let bucket = buckets.entry(hasher.finish()).or_default();
+
+            // Hash collisions are ruled out by comparing the bytes themselves
+            let original = bucket.iter().find(|(other, other_key)| {
+                *other_key == key
+                    && match doc.objects.get(other) {
+                        Some(Object::Stream(stream)) => stream.content == content,
+                        _ => content.is_empty(),
+                    }
+            });
+            match original {
+                Some(&(original, _)) => {
+                    duplicates.insert(id, original);
+                }
+                None => bucket.push((id, key)),
+            }
+        }
+
+        if duplicates.is_empty() {
+            break;
+        }
+        for object in doc.objects.values_mut() {
+            remap_references(object, &duplicates);
+        }
+        for (_, value) in doc.trailer.iter_mut() {
+            remap_references(value, &duplicates);
+        }
+        for id in duplicates.keys() {
+            if let Some(object) = doc.objects.remove(id) {
+                report.objects_removed += 1;
+                if let Object::Stream(stream) = object {
+                    report.bytes_saved += stream.content.len() as u64;
+                }
+            }
+        }
+    }
+    report
+}
+
+// Crop marks stop this far short of the trim box, relative to the bleed
+const CROP_MARK_GAP: f64 = 0.25;
+
+// Operators for a registration target (circle and crosshair) of radius `r` at (x, y)
+fn registration_target(x: f64, y: f64, r: f64) -> String {
+    // Control point distance approximating a quarter circle with a cubic Bezier
+    let k = r * 0.5523;
+    format!(
+        "{x0:.3} {y:.3} m {x0:.3} {yk1:.3} {xk0:.3} {y1:.3} {x:.3} {y1:.3} c \
+         {xk1:.3} {y1:.3} {x1:.3} {yk1:.3} {x1:.3} {y:.3} c \
+         {x1:.3} {yk0:.3} {xk1:.3} {y0:.3} {x:.3} {y0:.3} c \
+         {xk0:.3} {y0:.3} {x0:.3} {yk0:.3} {x0:.3} {y:.3} c S\n\
+         {x0:.3} {y:.3} m {x1:.3} {y:.3} l {x:.3} {y0:.3} m {x:.3} {y1:.3} l S\n",
+        x = x,
+        y = y,
+        x0 = x - r,
+        x1 = x + r,
+        y0 = y - r,
+        y1 = y + r,
+        xk0 = x - k,
+        xk1 = x + k,
+        yk0 = y - k,
+        yk1 = y + k,
+    )
+}
+
+// Grow every page by `bleed` on each side and draw crop marks, registration targets and
+// optionally CMYK color bars in that margin. The TrimBox keeps the original page size and
+// the BleedBox covers the enlarged page. Marks use all four inks, as registration color does.
+#[tauri::command]
+async fn add_printer_marks(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    bleed: f64,
+    color_bars: Option<bool>,
+) -> Result<(), String> {
+    if bleed.is_nan() || bleed <= 0.0 {
+        return Err("Bleed must be positive".to_string());
+    }
+    let mut doc = snapshot_document(&state, &path)?;
+
+    for page_id in doc.get_pages().into_values() {
+        materialize_inherited_attributes(&mut doc, page_id)?;
+        let [x0, y0, x1, y1] = page_visible_box(&doc, page_id).ok_or("Page has no MediaBox")?;
+        let (left, bottom, right, top) = (x0 - bleed, y0 - bleed, x1 + bleed, y1 + bleed);
+
+        let gap = bleed * CROP_MARK_GAP;
+        let mut marks = String::from("q 0.25 w 1 1 1 1 K\n");
+        for (x, y, dx, dy) in [(x0, y0, -1.0, -1.0), (x1, y0, 1.0, -1.0), (x0, y1, -1.0, 1.0), (x1, y1, 1.0, 1.0)] {
+            // One horizontal and one vertical mark per corner, aligned with the trim edges
+            let (hx0, hx1) = (x + dx * gap, x + dx * bleed);
+            let (vy0, vy1) = (y + dy * gap, y + dy * bleed);
+            marks.push_str(&format!(
+                "{:.3} {:.3} m {:.3} {:.3} l {:.3} {:.3} m {:.3} {:.3} l S\n",
+                hx0, y, hx1, y, x, vy0, x, vy1
+            ));
+        }
+
+        let radius = (bleed - gap) / 2.0 * 0.8;
+        let middle = gap + (bleed - gap) / 2.0;
+        let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+        for (x, y) in [(cx, y0 - middle), (cx, y1 + middle), (x0 - middle, cy), (x1 + middle, cy)] {
+            marks.push_str(&registration_target(x, y, radius));
+        }
+
+        if color_bars.unwrap_or(false) {
+            let size = bleed - gap;
+            let swatches = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+            // Placed in the top margin, left of the registration target
+            let mut x = x0 + size;
+            for tint in [1.0, 0.5] {
+                for swatch in swatches {
+                    if x + size > cx - radius * 2.0 {
+                        break;
+                    }
+                    marks.push_str(&format!(
+                        "{:.2} {:.2} {:.2} {:.2} k {:.3} {:.3} {:.3} {:.3} re f\n",
+                        swatch[0] * tint,
+                        swatch[1] * tint,
+                        swatch[2] * tint,
+                        swatch[3] * tint,
+                        x,
+                        y1 + gap,
+                        size,
+                        size
+                    ));
+                    x += size;
+                }
+            }
+        }
+        marks.push_str("Q\n");
+        append_page_overlay(&mut doc, page_id, marks.as_bytes())?;
+
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        let media = number_array(&[left, bottom, right, top]);
+        page.set("MediaBox", media.clone());
+        page.set("BleedBox", media);
+        page.set("TrimBox", number_array(&[x0, y0, x1, y1]));
+        page.remove(b"CropBox");
+        page.remove(b"ArtBox");
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(())
+}
+
+// Pages at least this much wider than tall (as displayed) are treated as two-page spreads
+const SPREAD_ASPECT_RATIO: f64 = 1.2;
+
+// Restrict a page dictionary to one half of a spread
+fn set_spread_half(page: &mut lopdf::Dictionary, half: [f64; 4], annots: Vec<Object>) {
+    page.set("MediaBox", number_array(&half));
+    page.set("CropBox", number_array(&half));
+    // Print boxes and the embedded thumbnail describe the whole spread
+    for key in [&b"BleedBox"[..], &b"TrimBox"[..], &b"ArtBox"[..], &b"Thumb"[..]] {
+        page.remove(key);
+    }
+    if annots.is_empty() {
+        page.remove(b"Annots");
+    } else {
+        page.set("Annots", Object::Array(annots));
+    }
+}
+
+// Split each landscape spread into its left and right halves, as two pages sharing the
+// original content. `gutter_ratio` is the fraction of the spread's width dropped from the
+// middle (0 splits exactly down the center). Returns the number of spreads split.
+#[tauri::command]
+async fn split_spreads(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    gutter_ratio: f64,
+) -> Result<usize, String> {
+    if !(0.0..0.5).contains(&gutter_ratio) {
+        return Err("Gutter ratio must be at least 0 and below 0.5".to_string());
+    }
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut order = Vec::new();
+    let mut split = 0;
+
+    for page_id in doc.get_pages().into_values() {
+        order.push(page_id);
+        materialize_inherited_attributes(&mut doc, page_id)?;
+        let [x0, y0, x1, y1] = match page_visible_box(&doc, page_id) {
+            Some(visible) => visible,
+            None => continue,
+        };
+        let rotation = page_rotation(&doc, page_id);
+        let sideways = rotation % 180 != 0;
+        let (display_w, display_h) = if sideways { (y1 - y0, x1 - x0) } else { (x1 - x0, y1 - y0) };
+        if display_h <= 0.0 || display_w / display_h < SPREAD_ASPECT_RATIO {
+            continue;
+        }
+
+        // The displayed left half is the low end of the page's x axis when upright, of
+        // its y axis when turned clockwise, and the high end otherwise
+        let (low, high) = if sideways { (y0, y1) } else { (x0, x1) };
+        let gutter = (high - low) * gutter_ratio;
+        let middle = (low + high) / 2.0;
+        let mut halves = [(low, middle - gutter / 2.0), (middle + gutter / 2.0, high)];
+        if rotation == 180 || rotation == 270 {
+            halves.reverse();
+        }
+        let half_box = |(a, b): (f64, f64)| if sideways { [x0, a, x1, b] } else { [a, y0, b, y1] };
+        let (first, second) = (half_box(halves[0]), half_box(halves[1]));
+
+        // Each annotation stays on the half containing its center
+        let mut first_annots = Vec::new();
+        let mut second_annots = Vec::new();
+        for annot in page_annotations(&doc, page_id) {
+            let center = annot
+                .as_reference()
+                .ok()
+                .and_then(|id| doc.get_dictionary(id).ok())
+                .and_then(|dict| dict.get(b"Rect").ok())
+                .and_then(|rect| rect_from_object(&doc, rect))
+                .map(|[ax0, ay0, ax1, ay1]| ((ax0 + ax1) / 2.0, (ay0 + ay1) / 2.0));
+            let in_second = center.is_some_and(|(cx, cy)| {
+                let [bx0, by0, bx1, by1] = second;
+                (bx0..=bx1).contains(&cx) && (by0..=by1).contains(&cy)
+            });
+            if in_second {
+                second_annots.push(annot);
+            } else {
+                first_annots.push(annot);
+            }
+        }
+
+        let mut second_page = doc.get_dictionary(page_id).map_err(|e| e.to_string())?.clone();
+        set_spread_half(&mut second_page, second, second_annots.clone());
+        let second_id = doc.add_object(second_page);
+        for annot in second_annots.iter().filter_map(|annot| annot.as_reference().ok()) {
+            if let Ok(dict) = doc.get_dictionary_mut(annot) {
+                if dict.has(b"P") {
+                    dict.set("P", Object::Reference(second_id));
+                }
+            }
+        }
+
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        set_spread_half(page, first, first_annots);
+
+        order.push(second_id);
+        split += 1;
+    }
+
+    apply_page_order(&mut doc, &order)?;
+    save_document(&mut doc, &output_path)?;
+    Ok(split)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ObjectSize {
+    object_id: (u32, u16),
+    kind: String,
+    bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocStats {
+    objects: usize,
+    dictionaries: usize,
+    streams: usize,
+    images: usize,
+    fonts: usize,
+    // Stream data as stored in the file, and after undoing its filters where lopdf can
+    stream_bytes_encoded: u64,
+    stream_bytes_decoded: u64,
+    largest: Vec<ObjectSize>,
+}
+
+// Number of objects listed in DocStats::largest
+const LARGEST_OBJECTS_REPORTED: usize = 10;
+
+// Short description of what an object is, from its /Type and /Subtype
+fn object_kind(object: &Object) -> String {
+    let dict = match object {
+        Object::Dictionary(dict) => dict,
+        Object::Stream(stream) => &stream.dict,
+        Object::Array(_) => return "Array".to_string(),
+        _ => return "Value".to_string(),
+    };
+    let name = |key: &[u8]| {
+        dict.get(key)
+            .and_then(Object::as_name)
+            .ok()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+    };
+    match (name(b"Type"), name(b"Subtype")) {
+        (Some(kind), Some(subtype)) => format!("{}/{}", kind, subtype),
+        (Some(kind), None) => kind,
+        (None, Some(subtype)) => subtype,
+        (None, None) if dict.has(b"Length1") || dict.has(b"Length2") => "FontFile".to_string(),
+        (None, None) if matches!(object, Object::Stream(_)) => "Stream".to_string(),
+        (None, None) => "Dictionary".to_string(),
+    }
+}
+
+#[tauri::command]
+async fn get_document_stats(state: State<'_, AppState>, path: String) -> Result<DocStats, String> {
+    with_document(&state, &path, |doc| {
+        let mut stats = DocStats {
+            objects: doc.objects.len(),
+            dictionaries: 0,
+            streams: 0,
+            images: 0,
+            fonts: 0,
+            stream_bytes_encoded: 0,
+            stream_bytes_decoded: 0,
+            largest: Vec::new(),
+        };
+        let mut sizes = Vec::new();
+
+        for (&(number, generation), object) in &doc.objects {
+            let bytes = match object {
+                Object::Stream(stream) => {
+                    stats.streams += 1;
+                    let encoded = stream.content.len() as u64;
+                    stats.stream_bytes_encoded += encoded;
+                    stats.stream_bytes_decoded +=
+                        decoded_stream_data(stream).map_or(encoded, |content| content.len() as u64);
+                    if is_image_xobject(object) {
+                        stats.images += 1;
+                    }
+                    format_dictionary(&stream.dict, 0).len() as u64 + encoded
+                }
+                Object::Dictionary(dict) => {
+                    stats.dictionaries += 1;
+                    if dict.get(b"Type").and_then(Object::as_name).ok() == Some(&b"Font"[..]) {
+                        stats.fonts += 1;
+                    }
+                    format_dictionary(dict, 0).len() as u64
+                }
+                other => format_object(other, 0).len() as u64,
+            };
+            sizes.push(((number, generation), bytes));
+        }
+
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        stats.largest = sizes
+            .into_iter()
+            .take(LARGEST_OBJECTS_REPORTED)
+            .map(|(object_id, bytes)| ObjectSize {
+                object_id,
+                kind: object_kind(&doc.objects[&object_id]),
+                bytes,
+            })
+            .collect();
+        Ok(stats)
+    })
+}
+
+// Set /Rotate to 0 on the given pages (all when None) without touching their content.
+// An explicit 0 is written rather than removing the key, which could re-expose a
+// rotation inherited from the page tree.
+#[tauri::command]
+async fn clear_rotation(state: State<'_, AppState>, path: String, pages: Option<Vec<usize>>) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        for (_, page_id) in select_pages(doc, &pages)? {
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Rotate", Object::Integer(0));
+        }
+        Ok(())
+    })
+}
+
+// Reorder the cached document's pages in one undoable step. `order` lists the current
+// 1-based page numbers in their new order and must be a permutation of all pages.
+#[tauri::command]
+async fn set_page_order(state: State<'_, AppState>, path: String, order: Vec<usize>) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        if order.len() != pages.len() {
+            return Err(format!(
+                "Page order has {} entries but the document has {} pages",
+                order.len(),
+                pages.len()
+            ));
+        }
+        let mut seen = vec![false; pages.len()];
+        for &page_num in &order {
+            if page_num == 0 || page_num > pages.len() {
+                return Err(format!("Page {} is out of range", page_num));
+            }
+            if std::mem::replace(&mut seen[page_num - 1], true) {
+                return Err(format!("Page {} appears more than once", page_num));
+            }
+        }
+
+        let reordered: Vec<ObjectId> = order.iter().map(|&page_num| pages[page_num - 1]).collect();
+        apply_page_order(doc, &reordered)
+    })
+}
+
+// Converts content streams into SVG elements in a y-down coordinate space
+struct SvgWriter<'a> {
+    doc: &'a Document,
+    body: String,
+    // Embedded image data URLs, None for images that could not be decoded
+    images: HashMap<ObjectId, Option<String>>,
+}
+
+fn svg_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn svg_matrix(m: &Matrix) -> String {
+    format!("matrix({:.4} {:.4} {:.4} {:.4} {:.3} {:.3})", m[0], m[1], m[2], m[3], m[4], m[5])
+}
+
+// CSS font family and style for a PDF font name
+fn svg_font_style(base_font: &str) -> String {
+    let family = if base_font.contains("Times") || base_font.contains("Serif") {
+        "serif"
+    } else if base_font.contains("Courier") || base_font.contains("Mono") {
+        "monospace"
+    } else {
+        "sans-serif"
+    };
+    let mut style = format!("font-family=\"{}\"", family);
+    if base_font.contains("Bold") {
+        style.push_str(" font-weight=\"bold\"");
+    }
+    if base_font.contains("Italic") || base_font.contains("Oblique") {
+        style.push_str(" font-style=\"italic\"");
+    }
+    style
+}
+
+impl<'a> SvgWriter<'a> {
+    fn image(&mut self, id: ObjectId, device: &Matrix) {
+        let doc = self.doc;
+        let data = self
+            .images
+            .entry(id)
+            .or_insert_with(|| match doc.get_object(id) {
+                Ok(Object::Stream(stream)) => decode_image_xobject(doc, stream)
+                    .and_then(|image| encode_png_data_url(&image))
+                    .ok(),
+                _ => None,
+            })
+            .clone();
+        if let Some(data) = data {
+            // Image rows run top to bottom, while the unit square's v axis points up
+            let matrix = multiply(&[1.0, 0.0, 0.0, -1.0, 0.0, 1.0], device);
+            self.body.push_str(&format!(
+                "<image width=\"1\" height=\"1\" preserveAspectRatio=\"none\" transform=\"{}\" href=\"{}\"/>\n",
+                svg_matrix(&matrix),
+                data
+            ));
+        }
+    }
+
+    fn run(
+        &mut self,
+        content: &[u8],
+        resources: Option<&lopdf::Dictionary>,
+        base: Matrix,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > 8 {
+            return Ok(());
+        }
+        let doc = self.doc;
+        let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+        let fonts = load_fonts(doc, resources);
+        let mut state = PaintState::new(base);
+        let mut stack: Vec<PaintState> = Vec::new();
+        let mut path = String::new();
+        // Last path point in device space, needed by "v" curves
+        let mut current = (0.0, 0.0);
+        let mut tm = IDENTITY;
+        let mut tlm = IDENTITY;
+
+        for operation in &content.operations {
+            let n = operand_numbers(&operation.operands);
+            let op = operation.operator.as_str();
+            let point = |state: &PaintState, x: f64, y: f64| {
+                let (px, py) = transform_point(&state.ctm, x, y);
+                format!("{:.3} {:.3}", px, py)
+            };
+            let last = |state: &PaintState, x: f64, y: f64| transform_point(&state.ctm, x, y);
+            match op {
+                "q" => stack.push(state.clone()),
+                "Q" => {
+                    if let Some(previous) = stack.pop() {
+                        state = previous;
+                    }
+                }
+                "cm" if n.len() == 6 => state.ctm = multiply(&[n[0], n[1], n[2], n[3], n[4], n[5]], &state.ctm),
+                "w" => state.line_width = n.first().copied().unwrap_or(1.0),
+                "gs" => {
+                    let ext = operation
+                        .operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| {
+                            resources
+                                .and_then(|r| resource_category(doc, r, b"ExtGState"))
+                                .and_then(|states| states.get(name).ok())
+                        })
+                        .and_then(|ext| resolve_object(doc, ext))
+                        .and_then(|ext| ext.as_dict().ok());
+                    if let Some(ext) = ext {
+                        if let Ok(alpha) = ext.get(b"ca").and_then(Object::as_float) {
+                            state.fill_alpha = alpha as f64;
+                        }
+                        if let Ok(alpha) = ext.get(b"CA").and_then(Object::as_float) {
+                            state.stroke_alpha = alpha as f64;
+                        }
+                        if let Ok(width) = ext.get(b"LW").and_then(Object::as_float) {
+                            state.line_width = width as f64;
+                        }
+                    }
+                }
+                "g" | "rg" | "k" => {
+                    state.fill = color_from_components(&n);
+                    state.fill_components = n.len();
+                }
+                "G" | "RG" | "K" => {
+                    state.stroke = color_from_components(&n);
+                    state.stroke_components = n.len();
+                }
+                "cs" | "CS" => {
+                    let components = operation
+                        .operands
+                        .first()
+                        .map_or(0, |space| color_space_components(doc, resources, space));
+                    if op == "cs" {
+                        state.fill_components = components;
+                        state.fill = [0, 0, 0];
+                    } else {
+                        state.stroke_components = components;
+                        state.stroke = [0, 0, 0];
+                    }
+                }
+                "sc" | "scn" => {
+                    // Patterns and special color spaces are approximated with mid gray
+                    state.fill = if n.len() == state.fill_components {
+                        color_from_components(&n)
+                    } else {
+                        [128, 128, 128]
+                    };
+                }
+                "SC" | "SCN" => {
+                    state.stroke = if n.len() == state.stroke_components {
+                        color_from_components(&n)
+                    } else {
+                        [128, 128, 128]
+                    };
+                }
+                "m" if n.len() == 2 => {
+                    path.push_str(&format!("M{} ", point(&state, n[0], n[1])));
+                    current = last(&state, n[0], n[1]);
+                }
+                "l" if n.len() == 2 => {
+                    path.push_str(&format!("L{} ", point(&state, n[0], n[1])));
+                    current = last(&state, n[0], n[1]);
+                }
+                "c" if n.len() == 6 => {
+                    path.push_str(&format!(
+                        "C{} {} {} ",
+                        point(&state, n[0], n[1]),
+                        point(&state, n[2], n[3]),
+                        point(&state, n[4], n[5])
+                    ));
+                    current = last(&state, n[4], n[5]);
+                }
+                "v" if n.len() == 4 => {
+                    // The current point doubles as the first control point
+                    path.push_str(&format!(
+                        "C{:.3} {:.3} {} {} ",
+                        current.0,
+                        current.1,
+                        point(&state, n[0], n[1]),
+                        point(&state, n[2], n[3])
+                    ));
+                    current = last(&state, n[2], n[3]);
+                }
+                "y" if n.len() == 4 => {
+                    path.push_str(&format!(
+                        "C{} {} {} ",
+                        point(&state, n[0], n[1]),
+                        point(&state, n[2], n[3]),
+                        point(&state, n[2], n[3])
+                    ));
+                    current = last(&state, n[2], n[3]);
+                }
+                "re" if n.len() == 4 => {
+                    path.push_str(&format!(
+                        "M{} L{} L{} L{} Z ",
+                        point(&state, n[0], n[1]),
+                        point(&state, n[0] + n[2], n[1]),
+                        point(&state, n[0] + n[2], n[1] + n[3]),
+                        point(&state, n[0], n[1] + n[3])
+                    ));
+                    current = last(&state, n[0], n[1]);
+                }
+                "h" => path.push_str("Z "),
+                "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" | "S" | "s" | "n" => {
+                    if matches!(op, "b" | "b*" | "s") {
+                        path.push_str("Z ");
+                    }
+                    let fill = matches!(op, "f" | "F" | "f*" | "B" | "B*" | "b" | "b*");
+                    let stroke = matches!(op, "B" | "B*" | "b" | "b*" | "S" | "s");
+                    if (fill || stroke) && !path.is_empty() {
+                        let mut element = format!("<path d=\"{}\"", path.trim_end());
+                        if fill {
+                            element.push_str(&format!(" fill=\"{}\"", svg_color(state.fill)));
+                            if state.fill_alpha < 1.0 {
+                                element.push_str(&format!(" fill-opacity=\"{:.3}\"", state.fill_alpha));
+                            }
+                            if op.ends_with('*') {
+                                element.push_str(" fill-rule=\"evenodd\"");
+                            }
+                        } else {
+                            element.push_str(" fill=\"none\"");
+                        }
+                        if stroke {
+                            let scale = (state.ctm[0] * state.ctm[3] - state.ctm[1] * state.ctm[2]).abs().sqrt();
+                            element.push_str(&format!(
+                                " stroke=\"{}\" stroke-width=\"{:.3}\"",
+                                svg_color(state.stroke),
+                                (state.line_width * scale).max(0.1)
+                            ));
+                            if state.stroke_alpha < 1.0 {
+                                element.push_str(&format!(" stroke-opacity=\"{:.3}\"", state.stroke_alpha));
+                            }
+                        }
+                        element.push_str("/>\n");
+                        self.body.push_str(&element);
+                    }
+                    path.clear();
+                }
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tf" => {
+                    if let Some(name) = operation.operands.first().and_then(|name| name.as_name().ok()) {
+                        state.text.font = name.to_vec();
+                    }
+                    state.text.font_size = n.first().copied().unwrap_or(0.0);
+                }
+                "Tc" => state.text.char_spacing = n.first().copied().unwrap_or(0.0),
+                "Tw" => state.text.word_spacing = n.first().copied().unwrap_or(0.0),
+                "Tz" => state.text.horizontal_scale = n.first().copied().unwrap_or(100.0) / 100.0,
+                "TL" => state.text.leading = n.first().copied().unwrap_or(0.0),
+                "Ts" => state.text.rise = n.first().copied().unwrap_or(0.0),
+                "Tr" => state.render_mode = n.first().copied().unwrap_or(0.0) as i64,
+                "Td" | "TD" if n.len() == 2 => {
+                    if op == "TD" {
+                        state.text.leading = -n[1];
+                    }
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, n[0], n[1]], &tlm);
+                    tm = tlm;
+                }
+                "Tm" if n.len() == 6 => {
+                    tlm = [n[0], n[1], n[2], n[3], n[4], n[5]];
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.text.leading], &tlm);
+                    tm = tlm;
+                }
+                "Tj" | "'" | "\"" | "TJ" => {
+                    if op == "'" || op == "\"" {
+                        tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.text.leading], &tlm);
+                        tm = tlm;
+                    }
+                    let font = match fonts.get(&state.text.font) {
+                        Some(font) => font,
+                        None => continue,
+                    };
+                    let size = state.text.font_size;
+                    let scale = state.text.horizontal_scale;
+                    let mut text = String::new();
+                    let mut advance = 0.0;
+                    let mut show = |bytes: &[u8], advance: &mut f64| {
+                        for code in font.codes(bytes) {
+                            text.push_str(&font.decode(code));
+                            let mut glyph = font.width(code) * size + state.text.char_spacing;
+                            if !font.two_byte && code == 32 {
+                                glyph += state.text.word_spacing;
+                            }
+                            *advance += glyph * scale;
+                        }
+                    };
+                    for operand in &operation.operands {
+                        match operand {
+                            Object::String(bytes, _) => show(bytes, &mut advance),
+                            Object::Array(items) => {
+                                for item in items {
+                                    match item {
+                                        Object::String(bytes, _) => show(bytes, &mut advance),
+                                        other => {
+                                            if let Ok(adjust) = other.as_float() {
+                                                advance -= adjust as f64 / 1000.0 * size * scale;
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    // Invisible text (mode 3, e.g. OCR layers) and clipping-only modes paint nothing
+                    if !matches!(state.render_mode, 3 | 7) && !text.trim().is_empty() && size != 0.0 {
+                        // Glyphs are drawn upright in the flipped device space
+                        let text_space =
+                            multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, state.text.rise], &multiply(&tm, &state.ctm));
+                        let matrix = multiply(&[1.0, 0.0, 0.0, -1.0, 0.0, 0.0], &text_space);
+                        let color = if matches!(state.render_mode, 1 | 5) { state.stroke } else { state.fill };
+                        self.body.push_str(&format!(
+                            "<text transform=\"{}\" font-size=\"{:.3}\" {} fill=\"{}\" textLength=\"{:.3}\" lengthAdjust=\"spacingAndGlyphs\" xml:space=\"preserve\">{}</text>\n",
+                            svg_matrix(&matrix),
+                            size,
+                            svg_font_style(&font.base_font),
+                            svg_color(color),
+                            advance.abs().max(0.001),
+                            escape_xml(&text)
+                        ));
+                    }
+                    tm = multiply(&[1.0, 0.0, 0.0, 1.0, advance, 0.0], &tm);
+                }
+                "Do" => {
+                    let name = match operation.operands.first().and_then(|name| name.as_name().ok()) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let reference = resources
+                        .and_then(|r| resource_category(doc, r, b"XObject"))
+                        .and_then(|xobjects| xobjects.get(name).ok());
+                    let (id, xobject) = match reference.and_then(|r| doc.dereference(r).ok()) {
+                        Some((Some(id), Object::Stream(xobject))) => (id, xobject),
+                        _ => continue,
+                    };
+                    match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+                        Ok(b"Image") => self.image(id, &state.ctm),
+                        Ok(b"Form") => {
+                            let matrix = xobject
+                                .dict
+                                .get(b"Matrix")
+                                .ok()
+                                .and_then(|m| m.as_array().ok())
+                                .map(|m| operand_numbers(m))
+                                .filter(|m| m.len() == 6)
+                                .map_or(IDENTITY, |m| [m[0], m[1], m[2], m[3], m[4], m[5]]);
+                            let form_resources = xobject
+                                .dict
+                                .get(b"Resources")
+                                .ok()
+                                .and_then(|r| resolve_object(doc, r))
+                                .and_then(|r| r.as_dict().ok())
+                                .or(resources);
+                            let form_content = xobject.decompressed_content().unwrap_or_else(|_| xobject.content.clone());
+                            self.run(&form_content, form_resources, multiply(&matrix, &state.ctm), depth + 1)?;
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+// Write a page as a standalone SVG sized to the page in points, as displayed (/Rotate
+// applied). Paths, solid colors and opacity are kept and images are embedded as PNG.
+// Approximations: clipping paths, shadings, blend modes and soft masks are ignored,
+// patterns become gray, and text is emitted as <text> in a generic family matching the
+// font's style (glyph outlines are not converted), stretched to the PDF advance width.
+#[tauri::command]
+async fn export_page_svg(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    output_path: String,
+) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let svg = with_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+        let rotation = page_rotation(doc, page_id);
+        let (width, height) = if rotation % 180 == 0 { (x1 - x0, y1 - y0) } else { (y1 - y0, x1 - x0) };
+        let (matrix, _, _) = device_matrix(doc, page_id, 1.0, rotation, default_size);
+
+        let mut writer = SvgWriter {
+            doc,
+            body: String::new(),
+            images: HashMap::new(),
+        };
+        let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+        writer.run(&content, page_resources(doc, page_id), matrix, 0)?;
+
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+             <rect width=\"{w}\" height=\"{h}\" fill=\"#ffffff\"/>\n{body}</svg>\n",
+            w = width,
+            h = height,
+            body = writer.body
+        ))
+    })?;
+
+    write_atomically(&output_path, |file| file.write_all(svg.as_bytes()).map_err(|e| e.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecurityFinding {
+    severity: Severity,
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecurityAudit {
+    encrypted: bool,
+    // Highest severity first
+    findings: Vec<SecurityFinding>,
+}
+
+// Whether the standard security handler accepts an empty user password, or None when
+// that can't be checked (AES-256 handlers, missing entries)
+fn opens_without_password(doc: &Document, encrypt: &lopdf::Dictionary, revision: i64) -> Option<bool> {
+    if !(2..=4).contains(&revision) {
+        return None;
+    }
+    let bytes = |key: &[u8]| encrypt.get(key).and_then(Object::as_str).ok();
+    let (owner_value, user_value) = (bytes(b"O")?, bytes(b"U")?);
+    let flags = encrypt.get(b"P").and_then(Object::as_i64).ok()? as i32;
+    let file_id = match doc.trailer.get(b"ID").and_then(Object::as_array).ok()?.first()? {
+        Object::String(id, _) => id.clone(),
+        _ => return None,
+    };
+    let length = if revision == 2 {
+        5
+    } else {
+        encrypt.get(b"Length").and_then(Object::as_i64).unwrap_or(40) as usize / 8
+    };
+    let encrypt_metadata = encrypt.get(b"EncryptMetadata").and_then(Object::as_bool).unwrap_or(true);
+    let key = rc4_file_key(b"", owner_value, flags, &file_id, length, revision, encrypt_metadata);
+    let expected = user_password_value(&key, &file_id, revision);
+    let significant = if revision == 2 { 32 } else { 16 };
+    Some(user_value.get(..significant) == expected.get(..significant))
+}
+
+// Review how a document is protected: weak ciphers, empty user passwords that make
+// permission flags advisory only, and unencrypted metadata
+#[tauri::command]
+async fn audit_security(state: State<'_, AppState>, path: String) -> Result<SecurityAudit, String> {
+    with_document(&state, &path, |doc| {
+        let mut findings = Vec::new();
+        let mut report = |severity: Severity, message: &str| {
+            findings.push(SecurityFinding { severity, message: message.to_string() })
+        };
+
+        let encrypt = doc
+            .trailer
+            .get(b"Encrypt")
+            .ok()
+            .and_then(|encrypt| resolve_object(doc, encrypt))
+            .and_then(|encrypt| encrypt.as_dict().ok());
+        let encrypt = match encrypt {
+            Some(encrypt) => encrypt,
+            None => {
+                report(Severity::Medium, "No protection: the document is not encrypted");
+                return Ok(SecurityAudit { encrypted: false, findings });
+            }
+        };
+
+        let filter = encrypt.get(b"Filter").and_then(Object::as_name).unwrap_or(b"");
+        if filter != b"Standard" {
+            report(
+                Severity::Info,
+                &format!("Uses the {} security handler, which is not audited", String::from_utf8_lossy(filter)),
+            );
+            return Ok(SecurityAudit { encrypted: true, findings });
+        }
+
+        let number = |key: &[u8]| encrypt.get(key).and_then(Object::as_i64).ok();
+        let version = number(b"V").unwrap_or(0);
+        let revision = number(b"R").unwrap_or(0);
+        let key_bits = number(b"Length").unwrap_or(40);
+        // Version 4 names its cipher in the default crypt filter
+        let crypt_method = encrypt
+            .get(b"CF")
+            .ok()
+            .and_then(|filters| resolve_object(doc, filters))
+            .and_then(|filters| filters.as_dict().ok())
+            .and_then(|filters| {
+                let name = encrypt.get(b"StmF").and_then(Object::as_name).unwrap_or(b"StdCF");
+                filters.get(name).ok()
+            })
+            .and_then(|filter| resolve_object(doc, filter))
+            .and_then(|filter| filter.as_dict().ok())
+            .and_then(|filter| filter.get(b"CFM").and_then(Object::as_name).ok());
+
+        match (version, crypt_method) {
+            (1, _) => report(Severity::High, "RC4 with a 40-bit key can be brute-forced in minutes"),
+            (2 | 3, _) if key_bits <= 40 => report(Severity::High, "RC4 with a 40-bit key can be brute-forced in minutes"),
+            (2 | 3, _) | (4, Some(b"V2")) => report(Severity::Medium, "RC4 encryption is considered broken"),
+            (4, Some(b"AESV2")) => report(Severity::Low, "AES-128 with MD5-based key derivation is outdated"),
+            (5, _) if revision == 5 => {
+                report(Severity::Medium, "AES-256 revision 5 has a known password validation weakness")
+            }
+            (5, _) => {}
+            _ => report(Severity::Info, "Unrecognized encryption settings"),
+        }
+
+        let flags = number(b"P").unwrap_or(-1);
+        // Bits 3-6 and 9-12 grant the individual permissions
+        let restricted = flags & 0xF3C != 0xF3C;
+        match opens_without_password(doc, encrypt, revision) {
+            Some(true) if restricted => report(
+                Severity::High,
+                "Permissions are advisory only: the document opens without a password, so any tool can remove the restrictions",
+            ),
+            Some(true) => report(Severity::Medium, "The document opens without a password"),
+            Some(false) => {}
+            None => report(Severity::Info, "Could not check whether the user password is empty"),
+        }
+
+        if matches!(encrypt.get(b"EncryptMetadata").and_then(Object::as_bool), Ok(false)) {
+            report(Severity::Low, "Document metadata is stored unencrypted");
+        }
+
+        findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+        Ok(SecurityAudit { encrypted: true, findings })
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum WatermarkContent {
+    // Gray Helvetica text set diagonally across the page center
+    Text { text: String, font_size: f64 },
+    // An image file centered on the page, `scale` relative to the page size
+    Image { image_path: String, scale: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WatermarkSpec {
+    content: WatermarkContent,
+    opacity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BatchOutput {
+    input_path: String,
+    output_path: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BatchProgress {
+    current: usize,
+    total: usize,
+    input_path: String,
+}
+
+fn apply_text_watermark(
+    doc: &mut Document,
+    text: &str,
+    font_size: f64,
+    opacity: f64,
+    default_size: (f64, f64),
+) -> Result<(), String> {
+    if font_size <= 0.0 {
+        return Err("Font size must be greater than zero".to_string());
+    }
+    let font_id = doc.add_object(standard_font("Helvetica"));
+    let state_id = doc.add_object(opacity_state(opacity.clamp(0.0, 1.0)));
+    let bytes = encode_standard_font_text(text);
+    let text_width = helvetica_text_width(&bytes, font_size);
+
+    for page_id in doc.get_pages().into_values() {
+        let [x0, y0, x1, y1] = page_visible_box(doc, page_id).unwrap_or([0.0, 0.0, default_size.0, default_size.1]);
+        let (cx, cy) = ((x0 + x1) / 2.0, (y0 + y1) / 2.0);
+        let angle = (y1 - y0).atan2(x1 - x0);
+        let (sin, cos) = angle.sin_cos();
+
+        let font_name = add_page_resource(doc, page_id, "Font", "WmFont", Object::Reference(font_id))?;
+        let state_name = add_page_resource(doc, page_id, "ExtGState", "WmGs", Object::Reference(state_id))?;
+        let content = lopdf::content::Content {
+            operations: vec![
+                lopdf::content::Operation::new("q", vec![]),
+                lopdf::content::Operation::new("gs", vec![Object::Name(state_name)]),
+                lopdf::content::Operation::new("g", vec![Object::Real(0.5)]),
+                lopdf::content::Operation::new("BT", vec![]),
+                lopdf::content::Operation::new("Tf", vec![Object::Name(font_name), Object::Real(font_size as f32)]),
+                lopdf::content::Operation::new(
+                    "Tm",
+                    // Rotate along the page diagonal about the center, then center the text on it
+                    [cos, sin, -sin, cos, cx - cos * text_width / 2.0, cy - sin * text_width / 2.0]
+                        .iter()
+                        .map(|&v| Object::Real(v as f32))
+                        .collect(),
+                ),
+                lopdf::content::Operation::new("Tj", vec![Object::String(bytes.clone(), lopdf::StringFormat::Literal)]),
+                lopdf::content::Operation::new("ET", vec![]),
+                lopdf::content::Operation::new("Q", vec![]),
+            ],
+        };
+        let operators = content.encode().map_err(|e| e.to_string())?;
+        append_page_overlay(doc, page_id, &operators)?;
+    }
+    Ok(())
+}
+
+fn watermark_file(
+    input_path: &str,
+    output_path: &str,
+    watermark: &WatermarkSpec,
+    default_size: (f64, f64),
+) -> Result<(), String> {
+    if let (Ok(input), Ok(output)) = (std::fs::canonicalize(input_path), std::fs::canonicalize(output_path)) {
+        if input == output {
+            return Err("Output would overwrite the input file".to_string());
+        }
+    }
+    let mut doc = Document::load(input_path).map_err(|e| e.to_string())?;
+    match &watermark.content {
+        WatermarkContent::Text { text, font_size } => {
+            apply_text_watermark(&mut doc, text, *font_size, watermark.opacity, default_size)?
+        }
+        WatermarkContent::Image { image_path, scale } => {
+            if *scale <= 0.0 {
+                return Err("Scale must be greater than zero".to_string());
+            }
+            apply_image_watermark(&mut doc, image_path, watermark.opacity, *scale, &None, default_size)?
+        }
+    }
+    save_document(&mut doc, output_path)
+}
+
+// Watermark each input file and write it to `output_dir` under its own file name. A file
+// that fails is reported in its entry and the rest of the batch continues. Emits
+// `batch_progress` before each file.
+#[tauri::command]
+async fn batch_watermark<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+    input_paths: Vec<String>,
+    output_dir: String,
+    watermark: WatermarkSpec,
+) -> Result<Vec<BatchOutput>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let output_dir = std::path::PathBuf::from(output_dir);
+    if !output_dir.is_dir() {
+        return Err(format!("{} is not a directory", output_dir.display()));
+    }
+
+    let total = input_paths.len();
+    let mut used_names = std::collections::HashSet::new();
+    let mut results = Vec::new();
+    for (index, input_path) in input_paths.into_iter().enumerate() {
+        let progress = BatchProgress { current: index + 1, total, input_path: input_path.clone() };
+        let _ = app.emit("batch_progress", progress);
+
+        // Inputs sharing a file name get a numeric suffix instead of overwriting each other
+        let source = std::path::Path::new(&input_path);
+        let stem = source.file_stem().map_or("output".into(), |stem| stem.to_string_lossy());
+        let mut name = format!("{}.pdf", stem);
+        let mut suffix = 2;
+        while !used_names.insert(name.clone()) {
+            name = format!("{}-{}.pdf", stem, suffix);
+            suffix += 1;
+        }
+        let output_path = output_dir.join(&name).to_string_lossy().into_owned();
+
+        results.push(match watermark_file(&input_path, &output_path, &watermark, default_size) {
+            Ok(()) => BatchOutput { input_path, output_path: Some(output_path), error: None },
+            Err(error) => BatchOutput { input_path, output_path: None, error: Some(error) },
+        });
+    }
+    Ok(results)
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+// The trailer's /ID pair as hex strings, or None when the document has no valid /ID
+#[tauri::command]
+async fn get_document_id(state: State<'_, AppState>, path: String) -> Result<Option<(String, String)>, String> {
+    with_document(&state, &path, |doc| {
+        let ids = match doc.trailer.get(b"ID").and_then(Object::as_array) {
+            Ok(ids) => ids,
+            Err(_) => return Ok(None),
+        };
+        match ids.as_slice() {
+            [Object::String(permanent, _), Object::String(changing, _)] => {
+                Ok(Some((hex_string(permanent), hex_string(changing))))
+            }
+            _ => Ok(None),
+        }
+    })
+}
+
+// Replace both halves of the /ID with a freshly generated identifier
+#[tauri::command]
+async fn regenerate_document_id(state: State<'_, AppState>, path: String) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        let id = new_document_id(doc, &path);
+        doc.trailer.set("ID", Object::Array(vec![id.clone(), id]));
+        Ok(())
+    })
+}
+
+fn sha_padding(data: &[u8]) -> Vec<u8> {
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+    message
+}
+
+fn sha1(data: &[u8]) -> Vec<u8> {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    for chunk in sha_padding(data).chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5A82_7999),
+                1 => (b ^ c ^ d, 0x6ED9_EBA1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in h.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    h.iter().flat_map(|value| value.to_be_bytes()).collect()
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98,
+        0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786,
+        0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8,
+        0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+        0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819,
+        0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a,
+        0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    for chunk in sha_padding(data).chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for (&k, &word) in K.iter().zip(w.iter()) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = hh.wrapping_add(s1).wrapping_add(choice).wrapping_add(k).wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, add) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    h.iter().flat_map(|value| value.to_be_bytes()).collect()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SignatureStatus {
+    field_name: String,
+    signer: Option<String>,
+    signed_at: Option<String>,
+    covers_whole_file: bool,
+    digest_valid: bool,
+    // Why the digest could not be checked, e.g. an unsupported algorithm
+    error: Option<String>,
+}
+
+// DER object identifiers as encoded after the 0x06 tag and length
+const OID_MESSAGE_DIGEST: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x04];
+const OID_SHA1: &[u8] = &[0x2B, 0x0E, 0x03, 0x02, 0x1A];
+const OID_SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+// Position just past the first DER encoding of an object identifier
+fn find_der_oid(der: &[u8], oid: &[u8]) -> Option<usize> {
+    let mut encoded = vec![0x06, oid.len() as u8];
+    encoded.extend_from_slice(oid);
+    der.windows(encoded.len())
+        .position(|window| window == encoded.as_slice())
+        .map(|at| at + encoded.len())
+}
+
+// Tag, content start and content length of the DER element at `at`
+fn der_element(der: &[u8], at: usize) -> Option<(u8, usize, usize)> {
+    let tag = *der.get(at)?;
+    let first = *der.get(at + 1)? as usize;
+    if first < 0x80 {
+        return Some((tag, at + 2, first));
+    }
+    let count = first & 0x7F;
+    if count == 0 || count > 4 {
+        return None;
+    }
+    let length = der.get(at + 2..at + 2 + count)?.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+    Some((tag, at + 2 + count, length))
+}
+
+// The messageDigest signed attribute of a CMS SignedData blob
+fn cms_message_digest(der: &[u8]) -> Option<&[u8]> {
+    let at = find_der_oid(der, OID_MESSAGE_DIGEST)?;
+    let (set_tag, set_start, _) = der_element(der, at)?;
+    let (octet_tag, start, length) = der_element(der, set_start)?;
+    if set_tag != 0x31 || octet_tag != 0x04 {
+        return None;
+    }
+    der.get(start..start + length)
+}
+
+// Signature fields of the interactive form with their signature dictionaries
+fn signature_fields(doc: &Document) -> Vec<(String, &lopdf::Dictionary)> {
+    let fields = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| resolve_object(doc, fields))
+        .and_then(|fields| fields.as_array().ok());
+
+    let mut found = Vec::new();
+    let mut pending: Vec<(&Object, String, bool)> =
+        fields.map(|fields| fields.iter().map(|f| (f, String::new(), false)).collect()).unwrap_or_default();
+    let mut visited = std::collections::HashSet::new();
+    while let Some((field, parent_name, parent_is_sig)) = pending.pop() {
+        if let Ok(id) = field.as_reference() {
+            if !visited.insert(id) {
+                continue;
+            }
+        }
+        let dict = match resolve_object(doc, field).and_then(|f| f.as_dict().ok()) {
+            Some(dict) => dict,
+            None => continue,
+        };
+        let partial = dict.get(b"T").and_then(Object::as_str).map(decode_pdf_string).ok();
+        let name = match (parent_name.is_empty(), partial) {
+            (_, None) => parent_name,
+            (true, Some(partial)) => partial,
+            (false, Some(partial)) => format!("{}.{}", parent_name, partial),
+        };
+        // /FT is inheritable, so kids of a signature field are signature fields too
+        let is_sig = match dict.get(b"FT").and_then(Object::as_name) {
+            Ok(kind) => kind == b"Sig",
+            Err(_) => parent_is_sig,
+        };
+        if is_sig {
+            let value = dict.get(b"V").ok().and_then(|v| resolve_object(doc, v)).and_then(|v| v.as_dict().ok());
+            if let Some(value) = value {
+                found.push((name.clone(), value));
+            }
+        }
+        if let Ok(Object::Array(kids)) = dict.get(b"Kids") {
+            pending.extend(kids.iter().map(|kid| (kid, name.clone(), is_sig)));
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    found
+}
+
+// Check each signature's byte-range digest against the file on disk. Only document
+// integrity is verified: the digest the signer committed to (the CMS messageDigest
+// attribute) must match the signed bytes. The cryptographic signature over that digest
+// and the certificate chain are not checked.
+#[tauri::command]
+async fn verify_signatures(path: String) -> Result<Vec<SignatureStatus>, String> {
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let doc = Document::load_mem(&bytes).map_err(|e| e.to_string())?;
+
+    let mut statuses = Vec::new();
+    for (field_name, signature) in signature_fields(&doc) {
+        let text = |key: &[u8]| signature.get(key).and_then(Object::as_str).map(decode_pdf_string).ok();
+        let mut status = SignatureStatus {
+            field_name,
+            signer: text(b"Name"),
+            signed_at: text(b"M"),
+            covers_whole_file: false,
+            digest_valid: false,
+            error: None,
+        };
+
+        let range: Vec<usize> = signature
+            .get(b"ByteRange")
+            .and_then(Object::as_array)
+            .map(|range| range.iter().filter_map(|v| v.as_i64().ok()).map(|v| v.max(0) as usize).collect())
+            .unwrap_or_default();
+        let contents = signature.get(b"Contents").and_then(Object::as_str).unwrap_or(b"");
+        let signed: Option<Vec<u8>> = range
+            .chunks(2)
+            .map(|pair| match pair {
+                [start, length] => bytes.get(*start..start.checked_add(*length)?).map(|part| part.to_vec()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|parts| parts.concat());
+
+        let signed = match (range.len(), signed) {
+            (4, Some(signed)) => signed,
+            _ => {
+                status.error = Some("Invalid /ByteRange".to_string());
+                statuses.push(status);
+                continue;
+            }
+        };
+        // The gap between the two ranges must be exactly the /Contents hex string
+        status.covers_whole_file = range[0] == 0
+            && range[1] <= range[2]
+            && range[2] - range[1] <= contents.len() * 2 + 2
+            && range[2] + range[3] == bytes.len();
+
+        let sub_filter = signature.get(b"SubFilter").and_then(Object::as_name).unwrap_or(b"");
+        if !matches!(sub_filter, b"adbe.pkcs7.detached" | b"ETSI.CAdES.detached") {
+            status.error = Some(format!("Unsupported signature format {}", String::from_utf8_lossy(sub_filter)));
+            statuses.push(status);
+            continue;
+        }
+        // The first hash identifier in SignedData is its digestAlgorithms entry
+        let hash = [(OID_SHA256, sha256 as fn(&[u8]) -> Vec<u8>), (OID_SHA1, sha1)]
+            .into_iter()
+            .filter_map(|(oid, hash)| find_der_oid(contents, oid).map(|at| (at, hash)))
+            .min_by_key(|(at, _)| *at);
+        let digest = match hash {
+            Some((_, hash)) => hash(&signed),
+            None => {
+                status.error = Some("Unsupported digest algorithm".to_string());
+                statuses.push(status);
+                continue;
+            }
+        };
+        match cms_message_digest(contents) {
+            Some(expected) => status.digest_valid = expected == digest.as_slice(),
+            None => status.error = Some("Signature has no messageDigest attribute".to_string()),
+        }
+        statuses.push(status);
+    }
+    Ok(statuses)
+}
+
+// Object ID of the catalog's /AcroForm, creating an empty form or moving a direct one
+// into its own object as needed
+fn acro_form_id(doc: &mut Document) -> Result<ObjectId, String> {
+    let existing = doc.catalog().map_err(|e| e.to_string())?.get(b"AcroForm").ok().cloned();
+    let form = match existing {
+        Some(Object::Reference(id)) => return Ok(id),
+        Some(Object::Dictionary(form)) => form,
+        _ => {
+            let mut form = lopdf::Dictionary::new();
+            form.set("Fields", Object::Array(Vec::new()));
+            form
+        }
+    };
+    let form_id = doc.add_object(form);
+    let root = doc.trailer.get(b"Root").and_then(Object::as_reference).map_err(|e| e.to_string())?;
+    doc.get_dictionary_mut(root)
+        .map_err(|e| e.to_string())?
+        .set("AcroForm", Object::Reference(form_id));
+    Ok(form_id)
+}
+
+// Add an unsigned signature field with a visible stamp: the signer's name and optional
+// reason, beside an optional signature image. /V is left empty so the field can be
+// signed later. Returns the new field's name.
+#[tauri::command]
+async fn add_signature_appearance(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    rect: [f64; 4],
+    name: String,
+    reason: Option<String>,
+    image_path: Option<String>,
+) -> Result<String, String> {
+    let [x0, y0, x1, y1] = rect;
+    let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+    if width <= 0.0 || height <= 0.0 {
+        return Err("Signature rectangle must have a positive size".to_string());
+    }
+    let rect = [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)];
+
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let form_id = acro_form_id(doc)?;
+
+        let mut resources = lopdf::Dictionary::new();
+        let mut content = String::new();
+        // The image takes the left part of the stamp, keeping its aspect ratio
+        let mut text_left = 0.0;
+        if let Some(image_path) = &image_path {
+            let (image_id, image_width, image_height) = embed_image_file(doc, image_path)?;
+            let area = width * 0.4;
+            let factor = (area / image_width as f64).min(height / image_height as f64);
+            let (w, h) = (image_width as f64 * factor, image_height as f64 * factor);
+            content.push_str(&format!(
+                "q {:.4} 0 0 {:.4} {:.4} {:.4} cm /Img Do Q\n",
+                w,
+                h,
+                (area - w) / 2.0,
+                (height - h) / 2.0
+            ));
+            let mut xobjects = lopdf::Dictionary::new();
+            xobjects.set("Img", Object::Reference(image_id));
+            resources.set("XObject", Object::Dictionary(xobjects));
+            text_left = area;
+        }
+
+        let mut lines = vec![format!("Signed by: {}", name)];
+        if let Some(reason) = reason.as_deref().filter(|reason| !reason.trim().is_empty()) {
+            lines.push(format!("Reason: {}", reason));
+        }
+        let font_size = (height / (lines.len() as f64 * 1.4)).min(12.0);
+        let text_width = width - text_left;
+        let center_x = text_left + text_width / 2.0;
+        let mut operations = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let baseline = height / 2.0 + (lines.len() as f64 / 2.0 - index as f64 - 0.8) * font_size * 1.4;
+            operations.extend(centered_text_line("F1", line, font_size, center_x, baseline, text_width * 0.9));
+        }
+        let text = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+        content.push_str(&String::from_utf8_lossy(&text));
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", Object::Reference(font_id));
+        resources.set("Font", Object::Dictionary(fonts));
+
+        let mut appearance = lopdf::Dictionary::new();
+        appearance.set("Type", Object::Name(b"XObject".to_vec()));
+        appearance.set("Subtype", Object::Name(b"Form".to_vec()));
+        appearance.set("BBox", number_array(&[0.0, 0.0, width, height]));
+        appearance.set("Resources", Object::Dictionary(resources));
+        let appearance_id = doc.add_object(lopdf::Stream::new(appearance, content.into_bytes()));
+
+        let existing: std::collections::HashSet<String> = doc
+            .get_dictionary(form_id)
+            .ok()
+            .and_then(|form| form.get(b"Fields").ok())
+            .and_then(|fields| resolve_object(doc, fields))
+            .and_then(|fields| fields.as_array().ok())
+            .map(|fields| {
+                fields
+                    .iter()
+                    .filter_map(|field| resolve_object(doc, field)?.as_dict().ok()?.get(b"T").ok()?.as_str().ok())
+                    .map(decode_pdf_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let field_name = (1..).map(|n| format!("Signature{}", n)).find(|n| !existing.contains(n)).unwrap_or_default();
+
+        let mut ap = lopdf::Dictionary::new();
+        ap.set("N", Object::Reference(appearance_id));
+        let mut widget = lopdf::Dictionary::new();
+        widget.set("Type", Object::Name(b"Annot".to_vec()));
+        widget.set("Subtype", Object::Name(b"Widget".to_vec()));
+        widget.set("FT", Object::Name(b"Sig".to_vec()));
+        widget.set("T", encode_pdf_string(&field_name));
+        widget.set("Rect", number_array(&rect));
+        // Printed, and locked against accidental moves
+        widget.set("F", Object::Integer(4 | 128));
+        widget.set("P", Object::Reference(page_id));
+        widget.set("AP", Object::Dictionary(ap));
+        let widget_id = doc.add_object(widget);
+
+        let mut annots = page_annotations(doc, page_id);
+        annots.push(Object::Reference(widget_id));
+        doc.get_dictionary_mut(page_id)
+            .map_err(|e| e.to_string())?
+            .set("Annots", Object::Array(annots));
+
+        let mut fields = doc
+            .get_dictionary(form_id)
+            .ok()
+            .and_then(|form| form.get(b"Fields").ok())
+            .and_then(|fields| resolve_object(doc, fields))
+            .and_then(|fields| fields.as_array().ok())
+            .cloned()
+            .unwrap_or_default();
+        fields.push(Object::Reference(widget_id));
+        let form = doc.get_dictionary_mut(form_id).map_err(|e| e.to_string())?;
+        form.set("Fields", Object::Array(fields));
+        // SignaturesExist
+        let flags = form.get(b"SigFlags").and_then(Object::as_i64).unwrap_or(0);
+        form.set("SigFlags", Object::Integer(flags | 1));
+        Ok(field_name)
+    })
+}
+
+// Remove every page whose extracted text contains `query` and return the removed 1-based
+// page numbers. Whitespace runs compare equal, so a phrase split across text spans still
+// matches. Refuses to remove all pages.
+#[tauri::command]
+async fn delete_pages_matching(
+    state: State<'_, AppState>,
+    path: String,
+    query: String,
+    case_sensitive: bool,
+) -> Result<Vec<usize>, String> {
+    let normalize = |text: &str| -> String {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .map(|c| fold_case(c, case_sensitive))
+            .collect()
+    };
+    let needle = normalize(&query);
+    if needle.is_empty() {
+        return Err("Query must not be empty".to_string());
+    }
+
+    edit_document(&state, &path, |doc| {
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            let spans = page_text_spans(doc, page_id)?;
+            let text: Vec<&str> = spans.iter().map(|span| span.text.as_str()).collect();
+            if normalize(&text.join(" ")).contains(&needle) {
+                removed.push(page_num as usize);
+            } else {
+                kept.push(page_id);
+            }
+        }
+        if kept.is_empty() {
+            return Err("Every page matches; refusing to delete all pages".to_string());
+        }
+        if !removed.is_empty() {
+            apply_page_order(doc, &kept)?;
+        }
+        Ok(removed)
+    })
+}
+
+// Serialize an object for hashing: references are replaced by what they point to, dictionary
+// keys are sorted and stream data is decoded, so the result doesn't depend on object
+// numbering or on how the file was written. A reference back into an object being
+// serialized is written as a marker.
+fn canonical_object_bytes(doc: &Document, object: &Object, out: &mut Vec<u8>, stack: &mut Vec<ObjectId>) {
+    let dictionary = |dict: &lopdf::Dictionary, out: &mut Vec<u8>, stack: &mut Vec<ObjectId>| {
+        let mut entries: Vec<(&Vec<u8>, &Object)> = dict
+            .iter()
+            // Parent links lead back into the page tree, and lengths depend on filters
+            .filter(|(key, _)| !matches!(key.as_slice(), b"Parent" | b"Length" | b"Filter" | b"DecodeParms"))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        out.push(b'<');
+        for (key, value) in entries {
+            out.push(b'/');
+            out.extend_from_slice(key);
+            out.push(b' ');
+            canonical_object_bytes(doc, value, out, stack);
+        }
+        out.push(b'>');
+    };
+    match object {
+        Object::Reference(id) => {
+            if stack.contains(id) || stack.len() > 32 {
+                out.extend_from_slice(b"@cycle");
+                return;
+            }
+            if let Ok(target) = doc.get_object(*id) {
+                stack.push(*id);
+                canonical_object_bytes(doc, target, out, stack);
+                stack.pop();
+            }
+        }
+        Object::Dictionary(dict) => dictionary(dict, out, stack),
+        Object::Stream(stream) => {
+            dictionary(&stream.dict, out, stack);
+            let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+            out.extend_from_slice(format!("stream{}:", content.len()).as_bytes());
+            out.extend_from_slice(&content);
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for item in items {
+                canonical_object_bytes(doc, item, out, stack);
+                out.push(b' ');
+            }
+            out.push(b']');
+        }
+        Object::String(bytes, _) => {
+            out.extend_from_slice(format!("({}:", bytes.len()).as_bytes());
+            out.extend_from_slice(bytes);
+            out.push(b')');
+        }
+        Object::Name(name) => {
+            out.push(b'/');
+            out.extend_from_slice(name);
+        }
+        Object::Integer(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => out.extend_from_slice(format!("{:.4}", value).as_bytes()),
+        Object::Boolean(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Null => out.extend_from_slice(b"null"),
+    }
+}
+
+// A SHA-256 hex digest per page over its tokenized content stream, its resources (with
+// everything they reference) and its visible box and rotation. Unchanged pages hash the
+// same across saves, renumbering and recompression.
+#[tauri::command]
+async fn page_hashes(state: State<'_, AppState>, path: String) -> Result<Vec<String>, String> {
+    with_document(&state, &path, |doc| {
+        let mut hashes = Vec::new();
+        for page_id in doc.get_pages().into_values() {
+            let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+            // Re-encoding the parsed operations drops whitespace and comment differences
+            let mut bytes = lopdf::content::Content::decode(&content)
+                .and_then(|content| content.encode())
+                .unwrap_or(content);
+
+            let mut stack = vec![page_id];
+            if let Some(resources) = page_resources(doc, page_id) {
+                canonical_object_bytes(doc, &Object::Dictionary(resources.clone()), &mut bytes, &mut stack);
+            }
+            let visible = page_visible_box(doc, page_id).unwrap_or_default();
+            bytes.extend_from_slice(format!("{:?}{}", visible, page_rotation(doc, page_id)).as_bytes());
+            hashes.push(hex_string(&sha256(&bytes)));
+        }
+        Ok(hashes)
+    })
+}
+
+// Object ID of the form field with the given fully qualified name
+fn form_field_id(doc: &Document, field_name: &str) -> Option<ObjectId> {
+    let fields = doc
+        .catalog()
+        .ok()?
+        .get(b"AcroForm")
+        .ok()
+        .and_then(|form| resolve_object(doc, form))?
+        .as_dict()
+        .ok()?
+        .get(b"Fields")
+        .ok()
+        .and_then(|fields| resolve_object(doc, fields))?
+        .as_array()
+        .ok()?;
+
+    let mut pending: Vec<(ObjectId, String)> =
+        fields.iter().filter_map(|f| f.as_reference().ok()).map(|id| (id, String::new())).collect();
+    let mut visited = std::collections::HashSet::new();
+    while let Some((id, parent_name)) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let dict = match doc.get_dictionary(id) {
+            Ok(dict) => dict,
+            Err(_) => continue,
+        };
+        // Widgets without /T are part of their parent field, not fields of their own
+        let name = match dict.get(b"T").and_then(Object::as_str).map(decode_pdf_string) {
+            Ok(partial) if parent_name.is_empty() => partial,
+            Ok(partial) => format!("{}.{}", parent_name, partial),
+            Err(_) => continue,
+        };
+        if name == field_name {
+            return Some(id);
+        }
+        if let Ok(Object::Array(kids)) = dict.get(b"Kids") {
+            pending.extend(kids.iter().filter_map(|kid| kid.as_reference().ok()).map(|kid| (kid, name.clone())));
+        }
+    }
+    None
+}
+
+// Set a field's default appearance (font, size and fill color used for its value) and
+// make sure the font is in the form's default resources. `font` is either a resource
+// name already in /DR or one of the standard 14 base fonts. Existing appearance streams
+// are kept, so /NeedAppearances asks viewers to redraw values with the new settings.
+#[tauri::command]
+async fn set_field_default_appearance(
+    state: State<'_, AppState>,
+    path: String,
+    field_name: String,
+    font: String,
+    size: f64,
+    color: [f64; 3],
+) -> Result<(), String> {
+    if !size.is_finite() || size < 0.0 {
+        return Err("Font size must be zero (auto) or positive".to_string());
+    }
+    if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+        return Err("Color components must be between 0 and 1".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let field_id = form_field_id(doc, &field_name).ok_or_else(|| format!("Form field '{}' not found", field_name))?;
+        let form_id = acro_form_id(doc)?;
+
+        let mut resources = doc
+            .get_dictionary(form_id)
+            .map_err(|e| e.to_string())?
+            .get(b"DR")
+            .ok()
+            .and_then(|dr| resolve_object(doc, dr))
+            .and_then(|dr| dr.as_dict().ok())
+            .cloned()
+            .unwrap_or_default();
+        let mut fonts = resources
+            .get(b"Font")
+            .ok()
+            .and_then(|fonts| resolve_object(doc, fonts))
+            .and_then(|fonts| fonts.as_dict().ok())
+            .cloned()
+            .unwrap_or_default();
+
+        let resource_name = if fonts.has(font.as_bytes()) {
+            font.clone()
+        } else {
+            let is_standard = STANDARD_FONT_FILES.iter().any(|(name, _)| *name == font)
+                || font == "Symbol"
+                || font == "ZapfDingbats";
+            if !is_standard {
+                return Err(format!("'{}' is neither a form font resource nor a standard font", font));
+            }
+            // The abbreviations viewers conventionally use for form fonts
+            let name = match font.as_str() {
+                "Helvetica" => "Helv".to_string(),
+                "Times-Roman" => "TiRo".to_string(),
+                "Courier" => "Cour".to_string(),
+                "ZapfDingbats" => "ZaDb".to_string(),
+                other => other.replace('-', ""),
+            };
+            let existing = fonts
+                .get(name.as_bytes())
+                .ok()
+                .and_then(|f| resolve_object(doc, f))
+                .and_then(|f| f.as_dict().ok())
+                .and_then(|f| f.get(b"BaseFont").and_then(Object::as_name).ok())
+                .map(|base| base == font.as_bytes());
+            if existing != Some(true) {
+                let font_id = doc.add_object(standard_font(&font));
+                fonts.set(name.as_str(), Object::Reference(font_id));
+            }
+            name
+        };
+        resources.set("Font", Object::Dictionary(fonts));
+
+        let appearance = format!("/{} {} Tf {} {} {} rg", resource_name, size, color[0], color[1], color[2]);
+        let form = doc.get_dictionary_mut(form_id).map_err(|e| e.to_string())?;
+        form.set("DR", Object::Dictionary(resources));
+        form.set("NeedAppearances", Object::Boolean(true));
+
+        // Widget kids can carry their own /DA, which would override the field's
+        let widgets: Vec<ObjectId> = match doc.get_dictionary(field_id).map_err(|e| e.to_string())?.get(b"Kids") {
+            Ok(Object::Array(kids)) => kids.iter().filter_map(|kid| kid.as_reference().ok()).collect(),
+            _ => Vec::new(),
+        };
+        for widget in widgets {
+            if let Ok(widget) = doc.get_dictionary_mut(widget) {
+                if !widget.has(b"T") {
+                    widget.remove(b"DA");
+                }
+            }
+        }
+        doc.get_dictionary_mut(field_id)
+            .map_err(|e| e.to_string())?
+            .set("DA", Object::string_literal(appearance));
+        Ok(())
+    })
+}
+
+// A page's content stream as text, one operation per line, in the syntax
+// replace_page_content accepts. Array /Contents are concatenated. The whole stream is
+// returned with no size cap; content that doesn't tokenize is returned as decoded bytes.
+#[tauri::command]
+async fn get_content_stream(state: State<'_, AppState>, path: String, page_num: usize) -> Result<String, String> {
+    with_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        // Streams of an array are separated by whitespace, so `Q` at the end of one can't
+        // run into `BT` at the start of the next
+        let parts: Vec<Vec<u8>> = doc
+            .get_page_contents(page_id)
+            .into_iter()
+            .filter_map(|id| doc.get_object(id).and_then(Object::as_stream).ok())
+            .map(|stream| stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()))
+            .collect();
+        let raw = parts.join(&b'\n');
+        let bytes = lopdf::content::Content::decode(&raw)
+            .and_then(|content| content.encode())
+            .unwrap_or(raw);
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    })
+}
+
+// Page rotation comes in two kinds:
+// - display rotation (/Rotate) only tells viewers how to turn the page on screen and in
+//   print; the content, its coordinates and the page's boxes are unchanged
+// - baked rotation transforms the content itself so the page is upright with /Rotate 0,
+//   swapping width and height for quarter turns
+// The rotation map passed to save_pdf and rotate_pages_relative are display rotation.
+
+// Set the display rotation of one page. Any multiple of 90 is accepted and normalized
+// into 0, 90, 180 or 270 (-90 becomes 270, 450 becomes 90).
+#[tauri::command]
+async fn set_display_rotation(state: State<'_, AppState>, path: String, page: usize, deg: i64) -> Result<(), String> {
+    if deg % 90 != 0 {
+        return Err("Rotation must be a multiple of 90 degrees".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page)?;
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        page.set("Rotate", Object::Integer(normalize_rotation(deg) as i64));
+        Ok(())
+    })
+}
+
+// Bake the display rotation of the given pages (all when None) into their content, so
+// they look the same but have /Rotate 0 and the rotated dimensions. Annotation
+// rectangles move with the content; their appearances are not turned.
+#[tauri::command]
+async fn bake_rotation(state: State<'_, AppState>, path: String, pages: Option<Vec<usize>>) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        for (_, page_id) in select_pages(doc, &pages)? {
+            let rotation = page_rotation(doc, page_id);
+            if rotation == 0 {
+                continue;
+            }
+            materialize_inherited_attributes(doc, page_id)?;
+            let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+            let (w, h) = (x1 - x0, y1 - y0);
+            // The same turn the renderer applies for /Rotate, in page units
+            let (rotate, display_w, display_h) = match rotation {
+                90 => ([0.0, -1.0, 1.0, 0.0, 0.0, w], h, w),
+                180 => ([-1.0, 0.0, 0.0, -1.0, w, h], w, h),
+                _ => ([0.0, 1.0, -1.0, 0.0, h, 0.0], h, w),
+            };
+            let matrix = multiply(&[1.0, 0.0, 0.0, 1.0, -x0, -y0], &rotate);
+
+            let cm = format!(
+                "q {} {} {} {} {:.4} {:.4} cm\n",
+                matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5]
+            );
+            wrap_page_content(doc, page_id, cm.as_bytes(), b"\nQ\n")?;
+            transform_annotation_rects(doc, page_id, &matrix);
+
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            let size = Object::Array(vec![
+                Object::Integer(0),
+                Object::Integer(0),
+                Object::Real(display_w as f32),
+                Object::Real(display_h as f32),
+            ]);
+            page.set("MediaBox", size.clone());
+            page.set("CropBox", size);
+            for key in [&b"BleedBox"[..], &b"TrimBox"[..], &b"ArtBox"[..]] {
+                page.remove(key);
+            }
+            page.set("Rotate", Object::Integer(0));
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RepairedImage {
+    object_id: (u32, u16),
+    problem: String,
+    color_space: String,
+}
+
+// The number of color components that accounts for an image's decoded data, or None
+// when the data can't be sized (for example unsupported filters)
+fn infer_image_components(stream: &lopdf::Stream) -> Option<usize> {
+    let dict = &stream.dict;
+    let filters: Vec<Vec<u8>> = match dict.get(b"Filter") {
+        Ok(Object::Name(name)) => vec![name.clone()],
+        Ok(Object::Array(items)) => items.iter().filter_map(|f| f.as_name().ok()).map(<[u8]>::to_vec).collect(),
+        _ => Vec::new(),
+    };
+    if filters.iter().any(|f| f == b"DCTDecode" || f == b"DCT") {
+        let jpeg = image::load_from_memory_with_format(&stream.content, image::ImageFormat::Jpeg).ok()?;
+        return Some(if jpeg.color().has_color() { 3 } else { 1 });
+    }
+    let width = dict.get(b"Width").and_then(Object::as_i64).ok()?.max(0) as usize;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok()?.max(0) as usize;
+    let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8).max(1) as usize;
+    let data = decoded_stream_data(stream).ok().or_else(|| filters.is_empty().then(|| stream.content.clone()))?;
+    let expected = |components: usize| (width * components * bits).div_ceil(8) * height;
+    [1, 3, 4]
+        .into_iter()
+        .find(|&c| data.len() == expected(c))
+        .or_else(|| [4, 3, 1].into_iter().find(|&c| data.len() >= expected(c)))
+}
+
+// What's wrong with an image's /ColorSpace, or None when it resolves to something the
+// image can be drawn with
+fn color_space_problem(doc: &Document, dict: &lopdf::Dictionary) -> Option<String> {
+    let space = match dict.get(b"ColorSpace") {
+        Ok(space) => space,
+        Err(_) => return Some("missing color space".to_string()),
+    };
+    let space = match space {
+        Object::Reference(id) => match doc.get_object(*id) {
+            Ok(space) => space,
+            Err(_) => return Some(format!("color space object {} {} R does not exist", id.0, id.1)),
+        },
+        space => space,
+    };
+    let family = match space {
+        Object::Name(name) => name.as_slice(),
+        Object::Array(items) => items.first().and_then(|f| f.as_name().ok()).unwrap_or(b""),
+        _ => return Some("color space is not a name or array".to_string()),
+    };
+    match family {
+        b"Separation" | b"DeviceN" | b"Lab" | b"CalGray" | b"CalRGB" => None,
+        b"ICCBased" => match resolve_color_space(doc, space) {
+            ColorSpaceKind::Unknown => Some("ICC profile is missing or has no valid /N".to_string()),
+            _ => None,
+        },
+        b"Indexed" | b"I" => match resolve_color_space(doc, space) {
+            ColorSpaceKind::Indexed { base, lookup } => {
+                let high = space
+                    .as_array()
+                    .ok()
+                    .and_then(|items| items.get(2))
+                    .and_then(|h| h.as_i64().ok())
+                    .unwrap_or(-1);
+                if base.components() == 0 {
+                    Some("indexed color space has an unusable base".to_string())
+                } else if high < 0 || lookup.len() < (high as usize + 1) * base.components() {
+                    Some("indexed color space lookup table is truncated".to_string())
+                } else {
+                    None
+                }
+            }
+            _ => Some("malformed indexed color space".to_string()),
+        },
+        _ => match resolve_color_space(doc, space) {
+            ColorSpaceKind::Unknown => Some(format!("unknown color space {}", String::from_utf8_lossy(family))),
+            _ => None,
+        },
+    }
+}
+
+// Replace unresolvable or malformed image color spaces with a device space matching
+// the data: Gray, RGB or CMYK by component count. Indexed images keep their indices;
+// a truncated lookup table is padded with black, and an unusable palette is replaced
+// by a gray ramp across the index range. Image masks and JPEG 2000 images (which carry
+// their own color space) are left alone.
+#[tauri::command]
+async fn repair_image_colorspaces(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+) -> Result<Vec<RepairedImage>, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut repaired = Vec::new();
+
+    let images: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| is_image_xobject(object))
+        .map(|(&id, _)| id)
+        .collect();
+    for id in images {
+        let stream = match doc.get_object(id).and_then(Object::as_stream) {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let dict = &stream.dict;
+        let is_mask = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+        let is_jpx = match dict.get(b"Filter") {
+            Ok(Object::Name(name)) => name == b"JPXDecode",
+            Ok(Object::Array(items)) => items.iter().any(|f| f.as_name().ok() == Some(&b"JPXDecode"[..])),
+            _ => false,
+        };
+        if is_mask || is_jpx {
+            continue;
+        }
+        let problem = match color_space_problem(&doc, dict) {
+            Some(problem) => problem,
+            None => continue,
+        };
+
+        let space = dict.get(b"ColorSpace").ok().and_then(|s| resolve_object(&doc, s)).cloned();
+        let indexed = space
+            .as_ref()
+            .and_then(|s| s.as_array().ok())
+            .filter(|items| matches!(items.first().and_then(|f| f.as_name().ok()), Some(b"Indexed") | Some(b"I")));
+        let bits = dict.get(b"BitsPerComponent").and_then(Object::as_i64).unwrap_or(8).clamp(1, 16);
+
+        let (replacement, decode, description) = match (indexed, space.as_ref().map(|s| resolve_color_space(&doc, s))) {
+            (Some(items), Some(ColorSpaceKind::Indexed { base, mut lookup })) if base.components() > 0 => {
+                let high = items.get(2).and_then(|h| h.as_i64().ok()).unwrap_or(0).max(0);
+                lookup.resize((high as usize + 1) * base.components(), 0);
+                let fixed = vec![
+                    items[0].clone(),
+                    items[1].clone(),
+                    Object::Integer(high),
+                    Object::String(lookup, lopdf::StringFormat::Hexadecimal),
+                ];
+                (Object::Array(fixed), None, "Indexed (lookup padded)".to_string())
+            }
+            (Some(items), _) => {
+                let high = items.get(2).and_then(|h| h.as_i64().ok()).filter(|&h| h > 0).unwrap_or((1 << bits) - 1);
+                // Stretch the index range over black to white
+                let decode = vec![Object::Integer(0), Object::Real((((1i64 << bits) - 1) as f64 / high as f64) as f32)];
+                (Object::Name(b"DeviceGray".to_vec()), Some(decode), "DeviceGray (index ramp)".to_string())
+            }
+            _ => {
+                let name: &[u8] = match infer_image_components(stream) {
+                    Some(1) => b"DeviceGray",
+                    Some(4) => b"DeviceCMYK",
+                    _ => b"DeviceRGB",
+                };
+                (Object::Name(name.to_vec()), None, String::from_utf8_lossy(name).into_owned())
+            }
+        };
+
+        if let Ok(Object::Stream(stream)) = doc.get_object_mut(id) {
+            stream.dict.set("ColorSpace", replacement);
+            if let Some(decode) = decode {
+                stream.dict.set("Decode", Object::Array(decode));
+            } else if indexed.is_none() {
+                // A Decode array sized for the old color space would no longer fit
+                stream.dict.remove(b"Decode");
+            }
+        }
+        repaired.push(RepairedImage { object_id: id, problem, color_space: description });
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(repaired)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SizeResult {
+    bytes: u64,
+    // Resolution and JPEG quality images were resampled to; None when lossless
+    // optimization alone was enough
+    max_dpi: Option<u32>,
+    jpeg_quality: Option<u8>,
+    images_resampled: usize,
+}
+
+// Downsampling steps tried in turn by save_under_size. The last one is the limit below
+// which scanned text stops being readable.
+const SIZE_REDUCTION_STEPS: [(u32, u8); 5] = [(200, 85), (150, 75), (120, 65), (96, 55), (72, 45)];
+
+// The largest size, in points, at which each image XObject is drawn directly by a page
+fn image_display_sizes(doc: &Document) -> HashMap<ObjectId, (f64, f64)> {
+    let mut sizes: HashMap<ObjectId, (f64, f64)> = HashMap::new();
+    for page_id in doc.get_pages().into_values() {
+        let xobjects = match page_resources(doc, page_id).and_then(|r| resource_category(doc, r, b"XObject")) {
+            Some(xobjects) => xobjects,
+            None => continue,
+        };
+        let content = match doc
+            .get_page_content(page_id)
+            .ok()
+            .and_then(|raw| lopdf::content::Content::decode(&raw).ok())
+        {
+            Some(content) => content,
+            None => continue,
+        };
+        let mut ctm = IDENTITY;
+        let mut stack = Vec::new();
+        for operation in &content.operations {
+            match operation.operator.as_str() {
+                "q" => stack.push(ctm),
+                "Q" => ctm = stack.pop().unwrap_or(IDENTITY),
+                "cm" => {
+                    let m = operand_numbers(&operation.operands);
+                    if m.len() == 6 {
+                        ctm = multiply(&[m[0], m[1], m[2], m[3], m[4], m[5]], &ctm);
+                    }
+                }
+                "Do" => {
+                    let id = operation
+                        .operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| xobjects.get(name).and_then(Object::as_reference).ok());
+                    if let Some(id) = id.filter(|&id| doc.get_object(id).is_ok_and(is_image_xobject)) {
+                        // Images fill the unit square, so the CTM's axes are the drawn size
+                        let width = ctm[0].hypot(ctm[1]);
+                        let height = ctm[2].hypot(ctm[3]);
+                        let size = sizes.entry(id).or_insert((0.0, 0.0));
+                        *size = (size.0.max(width), size.1.max(height));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    sizes
+}
+
+// Resample color and gray images drawn above `max_dpi` down to it and re-encode every
+// decodable image with `codec` (`quality` applies to JPEG), keeping the result only
+// where it is smaller. Images not drawn directly by a page (in forms or patterns) are
+// sized against the largest page. Returns how many images were replaced.
+fn downsample_images(doc: &mut Document, max_dpi: u32, quality: u8, codec: ImageCodec) -> usize {
+    let sizes = image_display_sizes(doc);
+    let largest_page = doc
+        .get_pages()
+        .into_values()
+        .filter_map(|page_id| page_visible_box(doc, page_id))
+        .fold((0.0f64, 0.0f64), |size, [x0, y0, x1, y1]| (size.0.max(x1 - x0), size.1.max(y1 - y0)));
+
+    let image_ids: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| is_image_xobject(object))
+        .map(|(&id, _)| id)
+        .collect();
+    // Soft masks are referenced by other images, not drawn as pixels of their own
+    let soft_masks: Vec<ObjectId> = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_stream().ok()?.dict.get(b"SMask").and_then(Object::as_reference).ok())
+        .collect();
+    let mut replaced = 0;
+    for id in image_ids {
+        let stream = match doc.get_object(id) {
+            Ok(Object::Stream(stream)) => stream,
+            _ => continue,
+        };
+        if soft_masks.contains(&id) || stream.dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false) {
+            continue;
+        }
+        let image = match decode_image_xobject(doc, stream) {
+            Ok(image) => image,
+            Err(_) => continue,
+        };
+
+        let (points_w, points_h) = sizes.get(&id).copied().unwrap_or(largest_page);
+        let scale = |pixels: u32, points: f64| {
+            let limit = (points / 72.0 * max_dpi as f64).ceil().max(1.0);
+            if points > 0.0 && (pixels as f64) > limit {
+                limit as u32
+            } else {
+                pixels
+            }
+        };
+        let (width, height) = (scale(image.width(), points_w), scale(image.height(), points_h));
+        let resampled = if (width, height) != image.dimensions() {
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+        } else {
+            image
+        };
+
+        let encoded = match encode_image_stream(&stream.dict, &resampled, codec, quality) {
+            Ok(encoded) => encoded,
+            Err(_) => continue,
+        };
+        if encoded.content.len() < stream.content.len() {
+            doc.objects.insert(id, Object::Stream(encoded));
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+fn serialized_size(doc: &mut Document) -> Result<u64, String> {
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.len() as u64)
+}
+
+// Save a copy no larger than `max_bytes`. Lossless clean-up (unused objects dropped,
+// streams compressed) comes first; if that isn't enough, images are resampled and
+// re-encoded through SIZE_REDUCTION_STEPS, each step starting from the original images.
+// Fails without writing when even the last step is too large.
+#[tauri::command]
+async fn save_under_size(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    max_bytes: u64,
+    codec: Option<ImageCodec>,
+) -> Result<SizeResult, String> {
+    let codec = codec.unwrap_or(ImageCodec::Jpeg);
+    let mut doc = snapshot_document(&state, &path)?;
+    doc.prune_objects();
+    doc.compress();
+    ensure_document_id(&mut doc, &output_path);
+
+    let mut size = serialized_size(&mut doc)?;
+    if size <= max_bytes {
+        save_document(&mut doc, &output_path)?;
+        return Ok(SizeResult { bytes: size, max_dpi: None, jpeg_quality: None, images_resampled: 0 });
+    }
+
+    for (max_dpi, quality) in SIZE_REDUCTION_STEPS {
+        let mut attempt = doc.clone();
+        let images_resampled = downsample_images(&mut attempt, max_dpi, quality, codec);
+        size = serialized_size(&mut attempt)?;
+        if size <= max_bytes {
+            save_document(&mut attempt, &output_path)?;
+            return Ok(SizeResult {
+                bytes: size,
+                max_dpi: Some(max_dpi),
+                jpeg_quality: Some(quality),
+                images_resampled,
+            });
+        }
+    }
+    Err(format!(
+        "Can't get below {} bytes without making the document unreadable; the smallest result was {} bytes",
+        max_bytes, size
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResourceUse {
+    object_id: (u32, u16),
+    // "font" or "image"
+    kind: String,
+    // The font's /BaseFont, or the image's pixel size
+    description: String,
+    pages: Vec<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResourceUsage {
+    resources: Vec<ResourceUse>,
+    // Fonts and images no page uses (a subset of `resources`, which lists them with no pages)
+    orphans: Vec<(u32, u16)>,
+}
+
+// Font and image objects reachable from a resource dictionary, following form XObjects,
+// patterns and Type 3 fonts into their own resources
+fn used_resource_objects(
+    doc: &Document,
+    resources: &lopdf::Dictionary,
+    used: &mut Vec<ObjectId>,
+    visited: &mut std::collections::HashSet<ObjectId>,
+) {
+    let mut nested: Vec<&lopdf::Dictionary> = Vec::new();
+    for category in [&b"Font"[..], &b"XObject"[..], &b"Pattern"[..]] {
+        let entries = match resource_category(doc, resources, category) {
+            Some(entries) => entries,
+            None => continue,
+        };
+        for (_, entry) in entries.iter() {
+            let id = match entry.as_reference() {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            if !visited.insert(id) {
+                continue;
+            }
+            let object = match doc.get_object(id) {
+                Ok(object) => object,
+                Err(_) => continue,
+            };
+            if category == b"Font" || is_image_xobject(object) {
+                used.push(id);
+            }
+            let dict = match object {
+                Object::Stream(stream) => &stream.dict,
+                Object::Dictionary(dict) => dict,
+                _ => continue,
+            };
+            let inner = dict
+                .get(b"Resources")
+                .ok()
+                .and_then(|r| resolve_object(doc, r))
+                .and_then(|r| r.as_dict().ok());
+            nested.extend(inner);
+        }
+    }
+    for inner in nested {
+        used_resource_objects(doc, inner, used, visited);
+    }
+}
+
+// Which pages use each font and image, including through form XObjects and patterns,
+// and which of them no page uses at all
+#[tauri::command]
+async fn resource_usage(state: State<'_, AppState>, path: String) -> Result<ResourceUsage, String> {
+    with_document(&state, &path, |doc| {
+        let mut pages_by_object: BTreeMap<ObjectId, Vec<usize>> = BTreeMap::new();
+        for (&id, object) in doc.objects.iter() {
+            let is_font = object
+                .as_dict()
+                .is_ok_and(|dict| dict.get(b"Type").and_then(Object::as_name).ok() == Some(&b"Font"[..]));
+            if is_font || is_image_xobject(object) {
+                pages_by_object.insert(id, Vec::new());
+            }
+        }
+
+        for (page_num, page_id) in doc.get_pages() {
+            let mut used = Vec::new();
+            if let Some(resources) = page_resources(doc, page_id) {
+                used_resource_objects(doc, resources, &mut used, &mut std::collections::HashSet::new());
+            }
+            for id in used {
+                pages_by_object.entry(id).or_default().push(page_num as usize);
+            }
+        }
+
+        let resources: Vec<ResourceUse> = pages_by_object
+            .into_iter()
+            .map(|(id, pages)| {
+                let object = doc.get_object(id).unwrap_or(&Object::Null);
+                let (kind, description) = if is_image_xobject(object) {
+                    let dict = object.as_stream().map(|s| &s.dict).ok();
+                    let dimension = |key: &[u8]| dict.and_then(|d| d.get(key).and_then(Object::as_i64).ok()).unwrap_or(0);
+                    ("image", format!("{}x{}", dimension(b"Width"), dimension(b"Height")))
+                } else {
+                    let base_font = object
+                        .as_dict()
+                        .ok()
+                        .and_then(|dict| dict.get(b"BaseFont").and_then(Object::as_name).ok())
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .unwrap_or_default();
+                    ("font", base_font)
+                };
+                ResourceUse { object_id: id, kind: kind.to_string(), description, pages }
+            })
+            .collect();
+        let orphans = resources.iter().filter(|r| r.pages.is_empty()).map(|r| r.object_id).collect();
+        Ok(ResourceUsage { resources, orphans })
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum Anchor {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Anchor {
+    // Position as fractions of the slack across and down the displayed page
+    fn fractions(self) -> (f64, f64) {
+        match self {
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+}
+
+const ASPECT_TOLERANCE: f64 = 0.001;
+
+// Crop every page to the largest rectangle with the given width:height ratio (16:9 is
+// 1.777...) that fits in its visible area, placed by `anchor`. Ratio and anchor are
+// taken as the page is displayed, so rotated pages are cropped the way they appear.
+#[tauri::command]
+async fn crop_to_aspect(state: State<'_, AppState>, path: String, ratio: f64, anchor: Anchor) -> Result<(), String> {
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Err("Aspect ratio must be positive".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        for page_id in doc.get_pages().into_values() {
+            let [x0, y0, x1, y1] = match page_visible_box(doc, page_id) {
+                Some(visible) => visible,
+                None => continue,
+            };
+            let (w, h) = (x1 - x0, y1 - y0);
+            if w <= 0.0 || h <= 0.0 {
+                continue;
+            }
+            // Map the displayed ratio and anchor into unrotated page space, where the
+            // vertical fraction counts up from the bottom
+            let rotation = page_rotation(doc, page_id);
+            let (across, down) = anchor.fractions();
+            let (page_ratio, fx, fy) = match rotation {
+                90 => (1.0 / ratio, down, across),
+                180 => (ratio, 1.0 - across, down),
+                270 => (1.0 / ratio, 1.0 - down, 1.0 - across),
+                _ => (ratio, across, 1.0 - down),
+            };
+            if ((w / h) / page_ratio - 1.0).abs() <= ASPECT_TOLERANCE {
+                continue;
+            }
+            let (crop_w, crop_h) = if w / h > page_ratio { (h * page_ratio, h) } else { (w, w / page_ratio) };
+            let left = x0 + fx * (w - crop_w);
+            let bottom = y0 + fy * (h - crop_h);
+
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set(
+                "CropBox",
+                Object::Array(
+                    [left, bottom, left + crop_w, bottom + crop_h]
+                        .iter()
+                        .map(|&v| Object::Real(v as f32))
+                        .collect(),
+                ),
+            );
+        }
+        Ok(())
+    })
+}
+
+// Field flags (/Ff) for text, button and choice fields
+const FIELD_FLAG_MULTILINE: i64 = 1 << 12;
+const FIELD_FLAG_PASSWORD: i64 = 1 << 13;
+const FIELD_FLAG_FILE_SELECT: i64 = 1 << 20;
+const FIELD_FLAG_COMB: i64 = 1 << 24;
+const FIELD_FLAG_PUSHBUTTON: i64 = 1 << 16;
+const FIELD_FLAG_COMBO: i64 = 1 << 17;
+
+// Fields inherit /FT, /Ff, /V and /DA through /Parent the same way pages inherit attributes
+fn inherited_field_entry<'a>(doc: &'a Document, field_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    inherited_page_entry(doc, field_id, key)
+}
+
+// Widget annotations of a field: the field itself when field and widget are merged,
+// otherwise its kids that aren't fields of their own
+fn field_widgets(doc: &Document, field_id: ObjectId) -> Vec<ObjectId> {
+    let dict = match doc.get_dictionary(field_id) {
+        Ok(dict) => dict,
+        Err(_) => return Vec::new(),
+    };
+    if dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(&b"Widget"[..]) {
+        return vec![field_id];
+    }
+    match dict.get(b"Kids") {
+        Ok(Object::Array(kids)) => kids
+            .iter()
+            .filter_map(|kid| kid.as_reference().ok())
+            .filter(|&kid| doc.get_dictionary(kid).is_ok_and(|kid| !kid.has(b"T")))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Turn a single-line text field into a comb of `max_len` equal cells and redraw its
+// widgets with the current value spread one character per cell
+#[tauri::command]
+async fn set_field_comb(state: State<'_, AppState>, path: String, field_name: String, max_len: u32) -> Result<(), String> {
+    use lopdf::content::Operation;
+    if max_len == 0 {
+        return Err("A comb field needs at least one cell".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let field_id = form_field_id(doc, &field_name).ok_or_else(|| format!("Form field '{}' not found", field_name))?;
+        let field_type = inherited_field_entry(doc, field_id, b"FT").and_then(|t| t.as_name().ok());
+        if field_type != Some(&b"Tx"[..]) {
+            return Err(format!("'{}' is not a text field", field_name));
+        }
+        let flags = inherited_field_entry(doc, field_id, b"Ff").and_then(|f| f.as_i64().ok()).unwrap_or(0);
+        if flags & (FIELD_FLAG_MULTILINE | FIELD_FLAG_PASSWORD | FIELD_FLAG_FILE_SELECT) != 0 {
+            return Err(format!("'{}' is a multiline, password or file field and can't be a comb", field_name));
+        }
+
+        let value: Vec<char> = inherited_field_entry(doc, field_id, b"V")
+            .and_then(|v| v.as_str().ok())
+            .map(decode_pdf_string)
+            .unwrap_or_default()
+            .chars()
+            .take(max_len as usize)
+            .collect();
+        let form_appearance = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"AcroForm").ok())
+            .and_then(|form| resolve_object(doc, form))
+            .and_then(|form| form.as_dict().ok())
+            .and_then(|form| form.get(b"DA").and_then(Object::as_str).ok());
+        let appearance = inherited_field_entry(doc, field_id, b"DA").and_then(|da| da.as_str().ok()).or(form_appearance);
+        let (da_size, color) = parse_default_appearance(appearance.unwrap_or(b""));
+        // parse_default_appearance falls back to 12; an explicit 0 means fit to the cell
+        let auto_size = appearance.is_some_and(|da| String::from_utf8_lossy(da).contains(" 0 Tf"));
+
+        let field = doc.get_dictionary_mut(field_id).map_err(|e| e.to_string())?;
+        field.set("Ff", Object::Integer(flags | FIELD_FLAG_COMB));
+        field.set("MaxLen", Object::Integer(max_len as i64));
+
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        for widget_id in field_widgets(doc, field_id) {
+            let rect = doc
+                .get_dictionary(widget_id)
+                .ok()
+                .and_then(|widget| widget.get(b"Rect").ok())
+                .and_then(|rect| rect_from_object(doc, rect));
+            let [x0, y0, x1, y1] = match rect {
+                Some(rect) => rect,
+                None => continue,
+            };
+            let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+            let cell = width / max_len as f64;
+            let size = if auto_size { (height * 0.7).min(cell * 1.4) } else { da_size };
+
+            let mut operations = vec![
+                Operation::new("BMC", vec![Object::Name(b"Tx".to_vec())]),
+                Operation::new("q", vec![]),
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec![Object::Name(b"Helv".to_vec()), Object::Real(size as f32)]),
+            ];
+            operations.extend(annotation_color_operation(&color, false));
+            let baseline = (height - size * 0.7) / 2.0;
+            for (index, c) in value.iter().enumerate() {
+                let glyph = encode_standard_font_text(&c.to_string());
+                let left = index as f64 * cell + (cell - helvetica_text_width(&glyph, size)) / 2.0;
+                let position = [1.0, 0.0, 0.0, 1.0, left, baseline];
+                operations.push(Operation::new("Tm", position.iter().map(|&v| Object::Real(v as f32)).collect()));
+                operations.push(Operation::new("Tj", vec![Object::string_literal(glyph)]));
+            }
+            operations.extend(["ET", "Q", "EMC"].map(|operator| Operation::new(operator, vec![])));
+            let content = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+
+            let mut fonts = lopdf::Dictionary::new();
+            fonts.set("Helv", Object::Reference(font_id));
+            let mut resources = lopdf::Dictionary::new();
+            resources.set("Font", Object::Dictionary(fonts));
+            let mut form = lopdf::Dictionary::new();
+            form.set("Type", Object::Name(b"XObject".to_vec()));
+            form.set("Subtype", Object::Name(b"Form".to_vec()));
+            form.set("BBox", number_array(&[0.0, 0.0, width, height]));
+            form.set("Resources", Object::Dictionary(resources));
+            let appearance_id = doc.add_object(lopdf::Stream::new(form, content));
+
+            let mut ap = lopdf::Dictionary::new();
+            ap.set("N", Object::Reference(appearance_id));
+            doc.get_dictionary_mut(widget_id)
+                .map_err(|e| e.to_string())?
+                .set("AP", Object::Dictionary(ap));
+        }
+        Ok(())
+    })
+}
+
+// Wrap a page's content and resources in a Form XObject drawn upright (its /Rotate
+// applied) with the visible area's lower-left corner at the origin. Returns the form
+// and its displayed width and height.
+fn page_as_form(doc: &mut Document, page_id: ObjectId) -> Result<(ObjectId, f64, f64), String> {
+    materialize_inherited_attributes(doc, page_id)?;
+    let [x0, y0, x1, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+    let (w, h) = (x1 - x0, y1 - y0);
+    let (rotate, display_w, display_h) = match page_rotation(doc, page_id) {
+        90 => ([0.0, -1.0, 1.0, 0.0, 0.0, w], h, w),
+        180 => ([-1.0, 0.0, 0.0, -1.0, w, h], w, h),
+        270 => ([0.0, 1.0, -1.0, 0.0, h, 0.0], h, w),
+        _ => (IDENTITY, w, h),
+    };
+    let matrix = multiply(&[1.0, 0.0, 0.0, 1.0, -x0, -y0], &rotate);
+
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let resources = page_resources(doc, page_id).cloned().unwrap_or_default();
+    let mut form = lopdf::Dictionary::new();
+    form.set("Type", Object::Name(b"XObject".to_vec()));
+    form.set("Subtype", Object::Name(b"Form".to_vec()));
+    form.set("BBox", number_array(&[x0, y0, x1, y1]));
+    form.set("Matrix", number_array(&matrix));
+    form.set("Resources", Object::Dictionary(resources));
+    let mut stream = lopdf::Stream::new(form, content);
+    stream.compress().map_err(|e| e.to_string())?;
+    Ok((doc.add_object(stream), display_w, display_h))
+}
+
+// Put each consecutive pair of pages next to each other at full size, on a sheet as
+// wide as both and as tall as the taller (the shorter is centered vertically). An odd
+// last page gets a sheet of its own. Annotations aren't carried onto the sheets.
+// Returns the number of sheets.
+#[tauri::command]
+async fn combine_side_by_side(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if pages.is_empty() {
+        return Err("Document has no pages".to_string());
+    }
+
+    let mut sheets = Vec::new();
+    for pair in pages.chunks(2) {
+        let mut forms = Vec::new();
+        for &page_id in pair {
+            forms.push(page_as_form(&mut doc, page_id)?);
+        }
+        let width: f64 = forms.iter().map(|&(_, w, _)| w).sum();
+        let height = forms.iter().map(|&(_, _, h)| h).fold(0.0, f64::max);
+
+        let sheet_id = create_blank_page(&mut doc, width, height);
+        let mut xobjects = lopdf::Dictionary::new();
+        let mut content = String::new();
+        let mut left = 0.0;
+        for (index, &(form_id, w, h)) in forms.iter().enumerate() {
+            let name = format!("P{}", index);
+            xobjects.set(name.as_str(), Object::Reference(form_id));
+            content.push_str(&format!("q 1 0 0 1 {:.4} {:.4} cm /{} Do Q\n", left, (height - h) / 2.0, name));
+            left += w;
+        }
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("XObject", Object::Dictionary(xobjects));
+        doc.get_dictionary_mut(sheet_id)
+            .map_err(|e| e.to_string())?
+            .set("Resources", Object::Dictionary(resources));
+        set_page_content(&mut doc, sheet_id, content.into_bytes())?;
+        sheets.push(sheet_id);
+    }
+
+    apply_page_order(&mut doc, &sheets)?;
+    doc.prune_objects();
+    save_document(&mut doc, &output_path)?;
+    Ok(sheets.len())
+}
+
+// Add or replace an entry of one of the catalog's /Names trees (e.g. Dests). The tree is
+// rewritten as a single sorted leaf, which viewers accept for any size.
+fn set_name_tree_entry(doc: &mut Document, tree_key: &[u8], name: &[u8], value: Object) -> Result<(), String> {
+    let names_object = doc.catalog().map_err(|e| e.to_string())?.get(b"Names").ok().cloned();
+    let mut names = names_object
+        .as_ref()
+        .and_then(|names| resolve_object(doc, names))
+        .and_then(|names| names.as_dict().ok())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut entries: Vec<(Vec<u8>, Object)> = Vec::new();
+    if let Some(tree) = names.get(tree_key).ok().and_then(|t| resolve_object(doc, t)).and_then(|t| t.as_dict().ok()) {
+        let mut existing = Vec::new();
+        name_tree_entries(doc, tree, 0, &mut existing);
+        entries = existing.into_iter().map(|(key, value)| (key, value.clone())).collect();
+    }
+    entries.retain(|(key, _)| key != name);
+    entries.push((name.to_vec(), value));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut leaf = lopdf::Dictionary::new();
+    leaf.set(
+        "Names",
+        Object::Array(
+            entries
+                .into_iter()
+                .flat_map(|(key, value)| [Object::String(key, lopdf::StringFormat::Literal), value])
+                .collect(),
+        ),
+    );
+    let leaf_id = doc.add_object(leaf);
+    names.set(tree_key.to_vec(), Object::Reference(leaf_id));
+
+    if let Some(Object::Reference(id)) = names_object {
+        doc.objects.insert(id, Object::Dictionary(names));
+    } else {
+        doc.catalog_mut().map_err(|e| e.to_string())?.set("Names", Object::Dictionary(names));
+    }
+    Ok(())
+}
+
+// Store a named view: the top of `page_num` at `zoom` (1.0 is 100%; 0 keeps the viewer's
+// zoom), as a named destination the UI and other viewers can jump to. An existing
+// destination with the same name is replaced.
+#[tauri::command]
+async fn add_named_view(
+    state: State<'_, AppState>,
+    path: String,
+    name: String,
+    page_num: usize,
+    zoom: f64,
+) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("View name must not be empty".to_string());
+    }
+    if !zoom.is_finite() || zoom < 0.0 {
+        return Err("Zoom must be zero or positive".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let [x0, _, _, y1] = page_visible_box(doc, page_id).ok_or("Page has no MediaBox")?;
+        let destination = Object::Array(vec![
+            Object::Reference(page_id),
+            Object::Name(b"XYZ".to_vec()),
+            Object::Real(x0 as f32),
+            Object::Real(y1 as f32),
+            Object::Real(zoom as f32),
+        ]);
+        let key = match encode_pdf_string(&name) {
+            Object::String(bytes, _) => bytes,
+            _ => name.clone().into_bytes(),
+        };
+        // The older catalog /Dests dictionary would shadow the new entry in some viewers
+        let dests = doc.catalog().ok().and_then(|catalog| catalog.get(b"Dests").ok()).cloned();
+        let dests = match dests {
+            Some(Object::Reference(id)) => doc.get_dictionary_mut(id).ok(),
+            Some(Object::Dictionary(_)) => doc
+                .catalog_mut()
+                .ok()
+                .and_then(|catalog| catalog.get_mut(b"Dests").ok())
+                .and_then(|dests| dests.as_dict_mut().ok()),
+            _ => None,
+        };
+        if let Some(dests) = dests {
+            dests.remove(name.as_bytes());
+        }
+        set_name_tree_entry(doc, b"Dests", &key, destination)
+    })
+}
+
+// Named destinations that lead to a page, for the UI to offer as views
+#[tauri::command]
+async fn list_named_views(state: State<'_, AppState>, path: String) -> Result<Vec<NamedDest>, String> {
+    with_document(&state, &path, |doc| {
+        let mut views = named_destinations(doc)?;
+        views.retain(|view| view.page_number.is_some());
+        Ok(views)
+    })
+}
+
+// Resolve one named view to its page and position
+#[tauri::command]
+async fn goto_named_view(state: State<'_, AppState>, path: String, name: String) -> Result<NamedDest, String> {
+    with_document(&state, &path, |doc| {
+        let view = named_destinations(doc)?
+            .into_iter()
+            .find(|view| view.name == name)
+            .ok_or_else(|| format!("Named view '{}' not found", name))?;
+        if view.page_number.is_none() {
+            return Err(format!("Named view '{}' points to a page that no longer exists", name));
+        }
+        Ok(view)
+    })
+}
+
+// Pixel scale make_diff_pdf compares pages at, and the size of the cells it highlights
+const DIFF_RENDER_SCALE: f64 = 1.5;
+const DIFF_CELL_SIZE: u32 = 6;
+// Mean luminance difference within a cell that counts as a change
+const DIFF_CELL_THRESHOLD: f64 = 12.0;
+
+fn invert_matrix(m: &Matrix) -> Option<Matrix> {
+    let det = m[0] * m[3] - m[1] * m[2];
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let (a, b, c, d) = (m[3] / det, -m[1] / det, -m[2] / det, m[0] / det);
+    Some([a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)])
+}
+
+// Pixel rectangles [x0, y0, x1, y1] of the cells where two renders differ, with runs of
+// changed cells on a row merged
+fn changed_regions(a: &image::RgbImage, b: &image::RgbImage) -> Vec<[u32; 4]> {
+    let a = if a.dimensions() == b.dimensions() {
+        a.clone()
+    } else {
+        image::imageops::resize(a, b.width(), b.height(), image::imageops::FilterType::Triangle)
+    };
+    let mut regions = Vec::new();
+    for cell_y in (0..b.height()).step_by(DIFF_CELL_SIZE as usize) {
+        let mut run: Option<u32> = None;
+        let bottom = (cell_y + DIFF_CELL_SIZE).min(b.height());
+        for cell_x in (0..b.width()).step_by(DIFF_CELL_SIZE as usize) {
+            let right = (cell_x + DIFF_CELL_SIZE).min(b.width());
+            let mut difference = 0.0;
+            for y in cell_y..bottom {
+                for x in cell_x..right {
+                    difference += (luminance(a.get_pixel(x, y)) - luminance(b.get_pixel(x, y))).abs();
+                }
+            }
+            let changed = difference / ((right - cell_x) * (bottom - cell_y)) as f64 > DIFF_CELL_THRESHOLD;
+            match (changed, run) {
+                (true, None) => run = Some(cell_x),
+                (false, Some(start)) => {
+                    regions.push([start, cell_y, cell_x, bottom]);
+                    run = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = run {
+            regions.push([start, cell_y, b.width(), bottom]);
+        }
+    }
+    regions
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DiffPdfSummary {
+    // Pages of B (1-based) with highlighted changes
+    changed_pages: Vec<usize>,
+    // Pages only B has, framed in the output
+    added_pages: Vec<usize>,
+    // Pages only A has, represented by labelled placeholder pages at the end
+    removed_pages: Vec<usize>,
+}
+
+// Write a copy of document B with translucent red boxes over the areas that render
+// differently from the same page of A. Pages B has beyond A's length are framed in red;
+// pages only A has are listed on placeholder pages appended at the end.
+#[tauri::command]
+async fn make_diff_pdf(
+    state: State<'_, AppState>,
+    path_a: String,
+    path_b: String,
+    output_path: String,
+) -> Result<DiffPdfSummary, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let renders_a = with_document(&state, &path_a, |doc| {
+        doc.get_pages()
+            .into_values()
+            .map(|page_id| render_page(doc, page_id, DIFF_RENDER_SCALE, None, default_size))
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+    let mut doc = snapshot_document(&state, &path_b)?;
+    let pages_b = doc.get_pages();
+    let mut summary = DiffPdfSummary { changed_pages: Vec::new(), added_pages: Vec::new(), removed_pages: Vec::new() };
+
+    for (&page_num, &page_id) in &pages_b {
+        let [x0, y0, x1, y1] = page_box_or_default(&doc, page_id, default_size);
+        let render_a = match renders_a.get(page_num as usize - 1) {
+            Some(render) => render,
+            None => {
+                let gs = add_page_resource(&mut doc, page_id, "ExtGState", "GS", Object::Dictionary(opacity_state(0.8)))?;
+                let frame = format!(
+                    "q /{} gs 1 0 0 RG 6 w {:.4} {:.4} {:.4} {:.4} re S Q\n",
+                    String::from_utf8_lossy(&gs),
+                    x0 + 3.0,
+                    y0 + 3.0,
+                    x1 - x0 - 6.0,
+                    y1 - y0 - 6.0
+                );
+                append_page_overlay(&mut doc, page_id, frame.as_bytes())?;
+                summary.added_pages.push(page_num as usize);
+                continue;
+            }
+        };
+        let render_b = render_page(&doc, page_id, DIFF_RENDER_SCALE, None, default_size)?;
+        let regions = changed_regions(render_a, &render_b);
+        if regions.is_empty() {
+            continue;
+        }
+
+        // Pixel rectangles back into page space, through the inverse of the render transform
+        let (device, _, _) = page_device_matrix(&doc, page_id, DIFF_RENDER_SCALE, default_size);
+        let to_page = invert_matrix(&device).ok_or("Page has a degenerate size")?;
+        let mut overlay = String::new();
+        for [px0, py0, px1, py1] in regions {
+            let (ax, ay) = transform_point(&to_page, px0 as f64, py0 as f64);
+            let (bx, by) = transform_point(&to_page, px1 as f64, py1 as f64);
+            overlay.push_str(&format!(
+                "{:.4} {:.4} {:.4} {:.4} re\n",
+                ax.min(bx),
+                ay.min(by),
+                (bx - ax).abs(),
+                (by - ay).abs()
+            ));
+        }
+        let gs = add_page_resource(&mut doc, page_id, "ExtGState", "GS", Object::Dictionary(opacity_state(0.35)))?;
+        let overlay = format!("q /{} gs 1 0 0 rg\n{}f Q\n", String::from_utf8_lossy(&gs), overlay);
+        append_page_overlay(&mut doc, page_id, overlay.as_bytes())?;
+        summary.changed_pages.push(page_num as usize);
+    }
+
+    if renders_a.len() > pages_b.len() {
+        let mut order: Vec<ObjectId> = pages_b.values().copied().collect();
+        for page_num in pages_b.len() + 1..=renders_a.len() {
+            let label = format!("Page {} of the original was removed", page_num);
+            order.push(create_label_page(&mut doc, default_size.0, default_size.1, Some(&label))?);
+            summary.removed_pages.push(page_num);
+        }
+        apply_page_order(&mut doc, &order)?;
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(summary)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+const MAX_BATCH_IMAGE_DPI: u32 = 600;
+
+fn encode_image_file(image: &image::RgbImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    match format {
+        ImageFormat::Png => image
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .map_err(|e| e.to_string())?,
+        ImageFormat::Jpeg => image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, DEFAULT_JPEG_QUALITY)
+            .encode_image(image)
+            .map_err(|e| e.to_string())?,
+    }
+    Ok(bytes.into_inner())
+}
+
+// Render every page of one file into `output_dir` as {stem}_p{n}.{ext}
+fn file_to_images(
+    input_path: &str,
+    output_dir: &std::path::Path,
+    stem: &str,
+    dpi: u32,
+    format: ImageFormat,
+    default_size: (f64, f64),
+    outputs: &mut Vec<String>,
+) -> Result<(), String> {
+    let doc = Document::load(input_path).map_err(|e| e.to_string())?;
+    for (page_num, page_id) in doc.get_pages() {
+        let image = render_page(&doc, page_id, dpi as f64 / 72.0, None, default_size)?;
+        let bytes = encode_image_file(&image, format)?;
+        let output_path = output_dir
+            .join(format!("{}_p{}.{}", stem, page_num, format.extension()))
+            .to_string_lossy()
+            .into_owned();
+        write_atomically(&output_path, |file| file.write_all(&bytes).map_err(|e| e.to_string()))?;
+        outputs.push(output_path);
+    }
+    Ok(())
+}
+
+// Rasterize every page of every input into `output_dir`, returning the images written.
+// A file that fails is reported through a "batch_file_error" event and skipped; its
+// pages written before the failure are still returned.
+#[tauri::command]
+async fn batch_to_images<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    input_paths: Vec<String>,
+    output_dir: String,
+    dpi: u32,
+    format: ImageFormat,
+) -> Result<Vec<String>, String> {
+    files_to_images(&app, input_paths, &output_dir, dpi, format, &|progress| {
+        let _ = app.emit("batch_progress", progress);
+    })
+}
+
+fn files_to_images<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    input_paths: Vec<String>,
+    output_dir: &str,
+    dpi: u32,
+    format: ImageFormat,
+    on_progress: &dyn Fn(BatchProgress),
+) -> Result<Vec<String>, String> {
+    if dpi == 0 || dpi > MAX_BATCH_IMAGE_DPI {
+        return Err(format!("DPI must be between 1 and {}", MAX_BATCH_IMAGE_DPI));
+    }
+    let output_dir = std::path::PathBuf::from(output_dir);
+    if !output_dir.is_dir() {
+        return Err(format!("{} is not a directory", output_dir.display()));
+    }
+
+    let default_size = app.state::<AppState>().settings.lock().map_err(|e| e.to_string())?.default_page_size;
+
+    let total = input_paths.len();
+    let mut used_stems = std::collections::HashSet::new();
+    let mut outputs = Vec::new();
+    for (index, input_path) in input_paths.into_iter().enumerate() {
+        on_progress(BatchProgress { current: index + 1, total, input_path: input_path.clone() });
+
+        // Inputs sharing a file name get a numeric suffix instead of overwriting each other
+        let source = std::path::Path::new(&input_path);
+        let base = source.file_stem().map_or("output".into(), |stem| stem.to_string_lossy()).into_owned();
+        let mut stem = base.clone();
+        let mut suffix = 2;
+        while !used_stems.insert(stem.clone()) {
+            stem = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+
+        if let Err(error) = file_to_images(&input_path, &output_dir, &stem, dpi, format, default_size, &mut outputs) {
+            let failure = BatchOutput { input_path, output_path: None, error: Some(error) };
+            let _ = app.emit("batch_file_error", failure);
+        }
+    }
+    Ok(outputs)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum ShapeKind {
+    Rect,
+    Ellipse,
+}
+
+// Add a markup annotation to a page with a generated normal appearance
+fn add_drawn_annotation(doc: &mut Document, page_id: ObjectId, mut annot: lopdf::Dictionary, rect: [f64; 4]) -> Result<(), String> {
+    annot.set("Type", Object::Name(b"Annot".to_vec()));
+    annot.set("Rect", number_array(&rect));
+    annot.set("P", Object::Reference(page_id));
+    // Printed, like markup drawn on paper
+    annot.set("F", Object::Integer(4));
+    if let Some((ops, resources)) = annotation_appearance(doc, &annot, rect) {
+        let ap = appearance_dictionary(doc, ops, resources, rect)?;
+        annot.set("AP", Object::Dictionary(ap));
+    }
+    let annot_id = doc.add_object(annot);
+
+    let mut annots = page_annotations(doc, page_id);
+    annots.push(Object::Reference(annot_id));
+    doc.get_dictionary_mut(page_id)
+        .map_err(|e| e.to_string())?
+        .set("Annots", Object::Array(annots));
+    Ok(())
+}
+
+fn border_style(width: f64) -> Object {
+    let mut style = lopdf::Dictionary::new();
+    style.set("W", Object::Real(width as f32));
+    style.set("S", Object::Name(b"S".to_vec()));
+    Object::Dictionary(style)
+}
+
+fn check_color(color: &[f64]) -> Result<(), String> {
+    if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+        return Err("Color components must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+// Draw a line from `start` to `end` (page space) as a Line annotation, optionally with
+// an arrowhead at the end
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn add_line_annotation(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    start: [f64; 2],
+    end: [f64; 2],
+    color: [f64; 3],
+    width: f64,
+    arrow: bool,
+) -> Result<(), String> {
+    check_color(&color)?;
+    if !width.is_finite() || width <= 0.0 {
+        return Err("Line width must be positive".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let mut annot = lopdf::Dictionary::new();
+        annot.set("Subtype", Object::Name(b"Line".to_vec()));
+        annot.set("L", number_array(&[start[0], start[1], end[0], end[1]]));
+        annot.set("C", number_array(&color));
+        annot.set("BS", border_style(width));
+        let ending: &[u8] = if arrow { b"OpenArrow" } else { b"None" };
+        annot.set("LE", Object::Array(vec![Object::Name(b"None".to_vec()), Object::Name(ending.to_vec())]));
+
+        // Room for the stroke and the arrowhead around the line's bounds
+        let margin = width + if arrow { (width * 4.0).max(6.0) } else { 0.0 };
+        let rect = [
+            start[0].min(end[0]) - margin,
+            start[1].min(end[1]) - margin,
+            start[0].max(end[0]) + margin,
+            start[1].max(end[1]) + margin,
+        ];
+        add_drawn_annotation(doc, page_id, annot, rect)
+    })
+}
+
+// Draw a rectangle or ellipse over `rect` (page space) as a Square or Circle annotation,
+// outlined in `color` and filled with `fill` when given
+#[tauri::command]
+async fn add_shape_annotation(
+    state: State<'_, AppState>,
+    path: String,
+    page_num: usize,
+    shape: ShapeKind,
+    rect: [f64; 4],
+    color: [f64; 3],
+    fill: Option<[f64; 3]>,
+) -> Result<(), String> {
+    check_color(&color)?;
+    if let Some(fill) = &fill {
+        check_color(fill)?;
+    }
+    let [x0, y0, x1, y1] = rect;
+    let rect = [x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)];
+    if rect[2] - rect[0] <= 0.0 || rect[3] - rect[1] <= 0.0 {
+        return Err("Shape rectangle must have a positive width and height".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let subtype: &[u8] = match shape {
+            ShapeKind::Rect => b"Square",
+            ShapeKind::Ellipse => b"Circle",
+        };
+        let mut annot = lopdf::Dictionary::new();
+        annot.set("Subtype", Object::Name(subtype.to_vec()));
+        annot.set("C", number_array(&color));
+        annot.set("BS", border_style(1.0));
+        if let Some(fill) = fill {
+            annot.set("IC", number_array(&fill));
+        }
+        add_drawn_annotation(doc, page_id, annot, rect)
+    })
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| position + from)
+}
+
+// The integer following the last `keyword` in `bytes[start..end]`
+fn integer_after(bytes: &[u8], keyword: &[u8], start: usize, end: usize) -> Option<usize> {
+    let section = bytes.get(start..end.min(bytes.len()))?;
+    let at = section.windows(keyword.len()).rposition(|window| window == keyword)? + keyword.len();
+    let digits: Vec<u8> = section[at..]
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace())
+        .take_while(|b| b.is_ascii_digit())
+        .copied()
+        .collect();
+    String::from_utf8(digits).ok()?.parse().ok()
+}
+
+// Offsets of the file's cross-reference sections, newest first, following /Prev links
+// from the final startxref. A linearized file's first-page section counts as one.
+fn xref_chain(bytes: &[u8]) -> Vec<usize> {
+    let mut chain = Vec::new();
+    let mut next = integer_after(bytes, b"startxref", 0, bytes.len());
+    while let Some(offset) = next.filter(|&offset| offset < bytes.len() && !chain.contains(&offset)) {
+        chain.push(offset);
+        // The section's trailer (or xref stream dictionary) ends before the next startxref
+        let end = find_bytes(bytes, b"startxref", offset).unwrap_or(bytes.len());
+        next = integer_after(bytes, b"/Prev", offset, end);
+    }
+    chain
+}
+
+// Serialize one object in PDF syntax for an incremental section
+fn write_pdf_object(out: &mut Vec<u8>, object: &Object) {
+    let write_dict = |out: &mut Vec<u8>, dict: &lopdf::Dictionary| {
+        out.extend_from_slice(b"<<");
+        for (key, value) in dict.iter() {
+            write_pdf_object(out, &Object::Name(key.clone()));
+            out.push(b' ');
+            write_pdf_object(out, value);
+        }
+        out.extend_from_slice(b">>");
+    };
+    match object {
+        Object::Null => out.extend_from_slice(b"null"),
+        Object::Boolean(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Integer(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => out.extend_from_slice(value.to_string().as_bytes()),
+        Object::Name(name) => {
+            out.push(b'/');
+            for &byte in name {
+                let regular = byte > b' ' && byte < 0x7F && !b"#()<>[]{}/%".contains(&byte);
+                if regular {
+                    out.push(byte);
+                } else {
+                    out.extend_from_slice(format!("#{:02X}", byte).as_bytes());
+                }
+            }
+        }
+        Object::String(bytes, lopdf::StringFormat::Hexadecimal) => {
+            out.push(b'<');
+            out.extend_from_slice(hex_string(bytes).as_bytes());
+            out.push(b'>');
+        }
+        Object::String(bytes, _) => {
+            out.push(b'(');
+            for &byte in bytes {
+                match byte {
+                    b'(' | b')' | b'\\' => out.extend_from_slice(&[b'\\', byte]),
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    _ => out.push(byte),
+                }
+            }
+            out.push(b')');
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(b' ');
+                }
+                write_pdf_object(out, item);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dict(out, dict),
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", Object::Integer(stream.content.len() as i64));
+            write_dict(out, &dict);
+            out.extend_from_slice(b"\nstream\n");
+            out.extend_from_slice(&stream.content);
+            out.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((number, generation)) => {
+            out.extend_from_slice(format!("{} {} R", number, generation).as_bytes());
+        }
+    }
+}
+
+// Save the cached document's changes as a new revision appended to the file on disk,
+// leaving every earlier byte (and so every earlier revision and signature) untouched.
+// Objects that differ from the file's current state are rewritten, removed ones are
+// marked free. Page order and rotation passed to save_pdf aren't applied here; make
+// those edits in the document first. Returns the number of objects written.
+#[tauri::command]
+async fn save_incremental(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    let original = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let base = Document::load_mem(&original).map_err(|e| e.to_string())?;
+    if base.trailer.has(b"Encrypt") {
+        return Err("Incremental saves of encrypted documents are not supported".to_string());
+    }
+    let previous_xref = *xref_chain(&original).first().ok_or("Could not find the file's cross-reference section")?;
+    let doc = snapshot_document(&state, &path)?;
+
+    let serialized = |object: &Object| {
+        let mut bytes = Vec::new();
+        write_pdf_object(&mut bytes, object);
+        bytes
+    };
+    let changed: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(id, object)| !base.objects.get(id).is_some_and(|old| serialized(old) == serialized(object)))
+        .map(|(&id, _)| id)
+        .collect();
+    let removed: Vec<ObjectId> = base.objects.keys().filter(|id| !doc.objects.contains_key(id)).copied().collect();
+
+    // A file whose newest section is an xref stream gets one too, as readers of such
+    // files (PDF 1.5 object streams) may not accept a classic table after it
+    let stream_section = !original[previous_xref..].starts_with(b"xref");
+    let mut out = original;
+    if !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    // Object number to (in use, offset, generation); free entries keep the next generation
+    let mut entries: BTreeMap<u32, (bool, usize, u16)> = BTreeMap::new();
+    for &id in &changed {
+        entries.insert(id.0, (true, out.len(), id.1));
+        out.extend_from_slice(format!("{} {} obj\n", id.0, id.1).as_bytes());
+        write_pdf_object(&mut out, &doc.objects[&id]);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    for id in removed {
+        entries.insert(id.0, (false, 0, id.1.saturating_add(1)));
+    }
+
+    let mut trailer = doc.trailer.clone();
+    // Entries that belonged to the previous section or to an xref stream dictionary
+    let section_keys: [&[u8]; 8] = [b"Prev", b"XRefStm", b"Type", b"W", b"Index", b"Length", b"Filter", b"DecodeParms"];
+    for key in section_keys {
+        trailer.remove(key);
+    }
+    let mut size = doc.max_id.max(base.max_id) + 1;
+    trailer.set("Prev", Object::Integer(previous_xref as i64));
+
+    let xref_offset = out.len();
+    if stream_section {
+        // The stream is an object of its own and lists itself
+        let stream_number = size;
+        size += 1;
+        entries.insert(stream_number, (true, xref_offset, 0));
+        let numbers: Vec<u32> = entries.keys().copied().collect();
+        let runs: Vec<&[u32]> = numbers.chunk_by(|a, b| b == &(a + 1)).collect();
+        let offset_width = (usize::BITS - xref_offset.leading_zeros()).div_ceil(8).max(1) as usize;
+        let mut data = Vec::new();
+        for &(in_use, offset, generation) in entries.values() {
+            data.push(in_use as u8);
+            let offset = offset.to_be_bytes();
+            data.extend_from_slice(&offset[offset.len() - offset_width..]);
+            data.extend_from_slice(&generation.to_be_bytes());
+        }
+        trailer.set("Type", Object::Name(b"XRef".to_vec()));
+        trailer.set("Size", Object::Integer(size as i64));
+        trailer.set("W", Object::Array(vec![1.into(), (offset_width as i64).into(), 2.into()]));
+        trailer.set(
+            "Index",
+            Object::Array(runs.iter().flat_map(|run| [(run[0] as i64).into(), (run.len() as i64).into()]).collect()),
+        );
+        let mut stream = lopdf::Stream::new(trailer, data);
+        stream.compress().map_err(|e| e.to_string())?;
+        out.extend_from_slice(format!("{} 0 obj\n", stream_number).as_bytes());
+        write_pdf_object(&mut out, &Object::Stream(stream));
+        out.extend_from_slice(b"\nendobj\n");
+    } else {
+        out.extend_from_slice(b"xref\n");
+        let numbers: Vec<u32> = entries.keys().copied().collect();
+        for run in numbers.chunk_by(|a, b| b == &(a + 1)) {
+            out.extend_from_slice(format!("{} {}\n", run[0], run.len()).as_bytes());
+            for number in run {
+                let (in_use, offset, generation) = entries[number];
+                let kind = if in_use { 'n' } else { 'f' };
+                out.extend_from_slice(format!("{:010} {:05} {}\r\n", offset, generation, kind).as_bytes());
+            }
+        }
+        trailer.set("Size", Object::Integer(size as i64));
+        out.extend_from_slice(b"trailer\n");
+        write_pdf_object(&mut out, &Object::Dictionary(trailer));
+    }
+    out.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", xref_offset).as_bytes());
+
+    write_atomically(&output_path, |file| file.write_all(&out).map_err(|e| e.to_string()))?;
+    Ok(changed.len())
+}
+
+// Number of revisions in the file on disk: the original plus one per incremental update
+#[tauri::command]
+async fn get_revision_count(path: String) -> Result<usize, String> {
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let chain = xref_chain(&bytes);
+    if chain.is_empty() {
+        return Err("Could not find the file's cross-reference section".to_string());
+    }
+    // A linearized file's first-page section is part of its first revision
+    let linearized = find_bytes(&bytes[..bytes.len().min(1024)], b"/Linearized", 0).is_some();
+    Ok(if linearized && chain.len() > 1 { chain.len() - 1 } else { chain.len() })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UriRef {
+    uri: String,
+    pages: Vec<usize>,
+}
+
+// Every distinct URI opened by a link annotation's URI action (including chained /Next
+// actions), with the pages linking to it, in order of first appearance. Relative URIs
+// are resolved against the catalog's /URI /Base when present.
+#[tauri::command]
+async fn list_external_uris(state: State<'_, AppState>, path: String) -> Result<Vec<UriRef>, String> {
+    with_document(&state, &path, |doc| {
+        let base = doc
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"URI").ok())
+            .and_then(|uri| resolve_object(doc, uri))
+            .and_then(|uri| uri.as_dict().ok())
+            .and_then(|uri| uri.get(b"Base").and_then(Object::as_str).ok())
+            .map(|base| String::from_utf8_lossy(base).into_owned());
+
+        let mut uris: Vec<UriRef> = Vec::new();
+        for (page_num, page_id) in doc.get_pages() {
+            for annot in page_annotations(doc, page_id) {
+                let mut action = resolve_object(doc, &annot)
+                    .and_then(|annot| annot.as_dict().ok())
+                    .and_then(|annot| annot.get(b"A").ok());
+                let mut visited = 0;
+                while let Some(dict) = action.and_then(|a| resolve_object(doc, a)).and_then(|a| a.as_dict().ok()) {
+                    visited += 1;
+                    if visited > 16 {
+                        break;
+                    }
+                    let is_uri = dict.get(b"S").and_then(Object::as_name).ok() == Some(&b"URI"[..]);
+                    if let (true, Ok(uri)) = (is_uri, dict.get(b"URI").and_then(Object::as_str)) {
+                        let mut uri = String::from_utf8_lossy(uri).trim().to_string();
+                        if let Some(base) = base.as_deref().filter(|_| !uri.contains(':')) {
+                            uri = format!("{}{}", base, uri);
+                        }
+                        let page_num = page_num as usize;
+                        match uris.iter_mut().find(|entry| entry.uri == uri) {
+                            Some(entry) if !entry.pages.contains(&page_num) => entry.pages.push(page_num),
+                            Some(_) => {}
+                            None => uris.push(UriRef { uri, pages: vec![page_num] }),
+                        }
+                    }
+                    // /Next is a single action or an array of them; follow the first
+                    action = match dict.get(b"Next") {
+                        Ok(Object::Array(next)) => next.first(),
+                        Ok(next) => Some(next),
+                        Err(_) => None,
+                    };
+                }
+            }
+        }
+        Ok(uris)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PageBoxes {
+    media_box: Option<[f64; 4]>,
+    crop_box: Option<[f64; 4]>,
+    bleed_box: Option<[f64; 4]>,
+    trim_box: Option<[f64; 4]>,
+    art_box: Option<[f64; 4]>,
+    // Points per user space unit; the boxes are in user space units
+    user_unit: f64,
+}
+
+// The page's boxes as written (MediaBox and CropBox may be inherited), without falling
+// back from one box to another
+#[tauri::command]
+async fn get_page_boxes(state: State<'_, AppState>, path: String, page_num: usize) -> Result<PageBoxes, String> {
+    with_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let page_box = |key: &[u8]| inherited_page_entry(doc, page_id, key).and_then(|rect| rect_from_object(doc, rect));
+        Ok(PageBoxes {
+            media_box: page_box(b"MediaBox"),
+            crop_box: page_box(b"CropBox"),
+            bleed_box: page_box(b"BleedBox"),
+            trim_box: page_box(b"TrimBox"),
+            art_box: page_box(b"ArtBox"),
+            user_unit: page_user_unit(doc, page_id),
+        })
+    })
+}
+
+// Set a page's /UserUnit, the size of one user space unit in points, so pages larger
+// than the 200 inch limit keep true dimensions. 1 removes the entry. Content and boxes
+// are unchanged; only the physical size they represent scales.
+#[tauri::command]
+async fn set_user_unit(state: State<'_, AppState>, path: String, page_num: usize, unit: f64) -> Result<(), String> {
+    if !unit.is_finite() || unit <= 0.0 {
+        return Err("User unit must be positive".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, page_num)?;
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        if unit == 1.0 {
+            page.remove(b"UserUnit");
+        } else {
+            page.set("UserUnit", Object::Real(unit as f32));
+        }
+        // UserUnit was introduced in PDF 1.6
+        if doc.version.as_str() < "1.6" {
+            doc.version = "1.6".to_string();
+        }
+        Ok(())
+    })
+}
+
+// Annotation flag bits (/F)
+const ANNOTATION_FLAG_HIDDEN: i64 = 1 << 1;
+
+// The normal appearance stream of an annotation, picking the /AS state when /N holds
+// several
+fn normal_appearance(doc: &Document, annot: &lopdf::Dictionary) -> Option<ObjectId> {
+    let normal = annot
+        .get(b"AP")
+        .ok()
+        .and_then(|ap| resolve_object(doc, ap))
+        .and_then(|ap| ap.as_dict().ok())?
+        .get(b"N")
+        .ok()?;
+    let normal = match doc.get_object(normal.as_reference().ok()?) {
+        Ok(Object::Stream(_)) => normal,
+        Ok(Object::Dictionary(states)) => {
+            let state = annot.get(b"AS").and_then(Object::as_name).ok()?;
+            states.get(state).ok()?
+        }
+        _ => return None,
+    };
+    let id = normal.as_reference().ok()?;
+    doc.get_object(id).and_then(Object::as_stream).ok().map(|_| id)
+}
+
+// The matrix that draws an appearance form over an annotation's Rect: the form's BBox,
+// transformed by its /Matrix, is scaled and moved onto the rectangle
+fn appearance_matrix(doc: &Document, form: &lopdf::Stream, rect: [f64; 4]) -> Option<Matrix> {
+    let [bx0, by0, bx1, by1] = form.dict.get(b"BBox").ok().and_then(|b| rect_from_object(doc, b))?;
+    let matrix = form
+        .dict
+        .get(b"Matrix")
+        .ok()
+        .and_then(|m| m.as_array().ok())
+        .map(|m| operand_numbers(m))
+        .filter(|m| m.len() == 6)
+        .map_or(IDENTITY, |m| [m[0], m[1], m[2], m[3], m[4], m[5]]);
+    let corners = [(bx0, by0), (bx1, by0), (bx0, by1), (bx1, by1)].map(|(x, y)| transform_point(&matrix, x, y));
+    let min_x = corners.iter().map(|c| c.0).fold(f64::INFINITY, f64::min);
+    let max_x = corners.iter().map(|c| c.0).fold(f64::NEG_INFINITY, f64::max);
+    let min_y = corners.iter().map(|c| c.1).fold(f64::INFINITY, f64::min);
+    let max_y = corners.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max);
+    if max_x - min_x <= 0.0 || max_y - min_y <= 0.0 {
+        return None;
+    }
+    let [x0, y0, x1, y1] = rect;
+    let (sx, sy) = ((x1 - x0) / (max_x - min_x), (y1 - y0) / (max_y - min_y));
+    Some([sx, 0.0, 0.0, sy, x0 - min_x * sx, y0 - min_y * sy])
+}
+
+// Draw annotations' normal appearances into the page content and remove them, so markup
+// can no longer be moved or deleted. `subtypes` limits which are flattened (all markup
+// when None); form widgets are only flattened when listed, and links are kept unless
+// `keep_links` is false. Hidden annotations are dropped without drawing; annotations
+// without an appearance are kept. Returns the number flattened.
+#[tauri::command]
+async fn flatten_annotations(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+    subtypes: Option<Vec<String>>,
+    keep_links: Option<bool>,
+) -> Result<usize, String> {
+    let keep_links = keep_links.unwrap_or(true);
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut flattened = 0;
+
+    for page_id in doc.get_pages().into_values() {
+        let mut kept = Vec::new();
+        let mut removed: Vec<ObjectId> = Vec::new();
+        let mut placements: Vec<(ObjectId, Matrix)> = Vec::new();
+
+        for annot in page_annotations(&doc, page_id) {
+            let annot_id = annot.as_reference().ok();
+            let dict = match resolve_object(&doc, &annot).and_then(|a| a.as_dict().ok()) {
+                Some(dict) => dict,
+                None => continue,
+            };
+            let subtype = String::from_utf8_lossy(dict.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"")).into_owned();
+            let selected = match &subtypes {
+                Some(subtypes) => subtypes.iter().any(|s| s == &subtype),
+                None => subtype != "Widget" && subtype != "Popup" && !(keep_links && subtype == "Link"),
+            };
+            if !selected {
+                kept.push(annot);
+                continue;
+            }
+
+            let hidden = dict.get(b"F").and_then(Object::as_i64).unwrap_or(0) & ANNOTATION_FLAG_HIDDEN != 0;
+            let placement = normal_appearance(&doc, dict).and_then(|form_id| {
+                let form = doc.get_object(form_id).and_then(Object::as_stream).ok()?;
+                let rect = dict.get(b"Rect").ok().and_then(|r| rect_from_object(&doc, r))?;
+                Some((form_id, appearance_matrix(&doc, form, rect)?))
+            });
+            match (hidden, placement) {
+                (true, _) => {}
+                (false, Some(placement)) => placements.push(placement),
+                (false, None) => {
+                    kept.push(annot);
+                    continue;
+                }
+            }
+            removed.extend(annot_id);
+            // The popup window of a flattened annotation goes with it
+            if let Ok(popup) = dict.get(b"Popup").and_then(Object::as_reference) {
+                removed.push(popup);
+            }
+            flattened += 1;
+        }
+
+        if removed.is_empty() {
+            continue;
+        }
+        kept.retain(|annot| !annot.as_reference().is_ok_and(|id| removed.contains(&id)));
+        let mut overlay = String::new();
+        for (form_id, m) in placements {
+            // Appearance streams may omit /Type and /Subtype, which Do needs
+            if let Ok(Object::Stream(form)) = doc.get_object_mut(form_id) {
+                form.dict.set("Type", Object::Name(b"XObject".to_vec()));
+                form.dict.set("Subtype", Object::Name(b"Form".to_vec()));
+            }
+            let name = add_page_resource(&mut doc, page_id, "XObject", "Annot", Object::Reference(form_id))?;
+            overlay.push_str(&format!(
+                "q {:.6} {:.6} {:.6} {:.6} {:.4} {:.4} cm /{} Do Q\n",
+                m[0],
+                m[1],
+                m[2],
+                m[3],
+                m[4],
+                m[5],
+                String::from_utf8_lossy(&name)
+            ));
+        }
+        if !overlay.is_empty() {
+            append_page_overlay(&mut doc, page_id, overlay.as_bytes())?;
+        }
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        if kept.is_empty() {
+            page.remove(b"Annots");
+        } else {
+            page.set("Annots", Object::Array(kept));
+        }
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(flattened)
+}
+
+// Height of the strip above each half of a comparison sheet that names its source
+const COMPARE_LABEL_HEIGHT: f64 = 24.0;
+
+// Lay two documents out for review: page N of A on the left and page N of B on the
+// right of each double-width sheet, at full size, under a strip naming the source file.
+// When one document is shorter its side is left blank. Returns the number of sheets.
+#[tauri::command]
+async fn side_by_side_compare(
+    state: State<'_, AppState>,
+    path_a: String,
+    path_b: String,
+    output_path: String,
+) -> Result<usize, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    let mut sources = [snapshot_document(&state, &path_a)?, snapshot_document(&state, &path_b)?];
+    let mut out = Document::with_version("1.5");
+    let pages_id = out.new_object_id();
+    let mut pages = lopdf::Dictionary::new();
+    pages.set("Type", Object::Name(b"Pages".to_vec()));
+    pages.set("Kids", Object::Array(Vec::new()));
+    pages.set("Count", Object::Integer(0));
+    out.objects.insert(pages_id, Object::Dictionary(pages));
+    let mut catalog = lopdf::Dictionary::new();
+    catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+    catalog.set("Pages", Object::Reference(pages_id));
+    let catalog_id = out.add_object(catalog);
+    out.trailer.set("Root", Object::Reference(catalog_id));
+
+    // Each page becomes a form in its own document, then the forms are copied over
+    let mut forms: [Vec<(ObjectId, f64, f64)>; 2] = [Vec::new(), Vec::new()];
+    for (source, forms) in sources.iter_mut().zip(forms.iter_mut()) {
+        let mut copier = ObjectCopier::new();
+        let page_ids: Vec<ObjectId> = source.get_pages().into_values().collect();
+        for &page_id in &page_ids {
+            copier.exclude(page_id);
+        }
+        for page_id in page_ids {
+            let (form_id, width, height) = page_as_form(source, page_id)?;
+            let copied = copier.copy_reference(source, &mut out, form_id).ok_or("Could not copy page content")?;
+            forms.push((copied, width, height));
+        }
+    }
+    let labels = [&path_a, &path_b].map(|path| {
+        std::path::Path::new(path)
+            .file_name()
+            .map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned())
+    });
+
+    let count = forms[0].len().max(forms[1].len());
+    if count == 0 {
+        return Err("Both documents are empty".to_string());
+    }
+    let font_id = out.add_object(standard_font("Helvetica"));
+    let mut sheets = Vec::new();
+    for index in 0..count {
+        let sides = [forms[0].get(index).copied(), forms[1].get(index).copied()];
+        // A missing side takes the size of the page it is compared with
+        let size = |side: Option<(ObjectId, f64, f64)>, other: Option<(ObjectId, f64, f64)>| {
+            side.or(other).map_or(default_size, |(_, w, h)| (w, h))
+        };
+        let (left_w, left_h) = size(sides[0], sides[1]);
+        let (right_w, right_h) = size(sides[1], sides[0]);
+        let height = left_h.max(right_h);
+        let sheet_id = create_blank_page(&mut out, left_w + right_w, height + COMPARE_LABEL_HEIGHT);
+
+        let mut xobjects = lopdf::Dictionary::new();
+        let mut content = String::new();
+        let mut operations = Vec::new();
+        let placements = [(sides[0], 0.0, left_w), (sides[1], left_w, right_w)];
+        for (side, (form, x, width)) in placements.into_iter().enumerate() {
+            let label = match form {
+                Some((form_id, _, form_h)) => {
+                    let name = format!("P{}", side);
+                    xobjects.set(name.as_str(), Object::Reference(form_id));
+                    content.push_str(&format!("q 1 0 0 1 {:.4} {:.4} cm /{} Do Q\n", x, height - form_h, name));
+                    labels[side].clone()
+                }
+                None => format!("{} (no page {})", labels[side], index + 1),
+            };
+            let baseline = height + (COMPARE_LABEL_HEIGHT - 10.0) / 2.0;
+            operations.extend(centered_text_line("F1", &label, 10.0, x + width / 2.0, baseline, width * 0.9));
+        }
+        // A rule between the two halves
+        let top = height + COMPARE_LABEL_HEIGHT;
+        content.push_str(&format!("q 0.5 G 0.5 w {:.4} 0 m {:.4} {:.4} l S Q\n", left_w, left_w, top));
+        let text = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+        content.push_str(&String::from_utf8_lossy(&text));
+
+        let mut fonts = lopdf::Dictionary::new();
+        fonts.set("F1", Object::Reference(font_id));
+        let mut resources = lopdf::Dictionary::new();
+        resources.set("XObject", Object::Dictionary(xobjects));
+        resources.set("Font", Object::Dictionary(fonts));
+        out.get_dictionary_mut(sheet_id)
+            .map_err(|e| e.to_string())?
+            .set("Resources", Object::Dictionary(resources));
+        set_page_content(&mut out, sheet_id, content.into_bytes())?;
+        sheets.push(sheet_id);
+    }
+
+    apply_page_order(&mut out, &sheets)?;
+    save_document(&mut out, &output_path)?;
+    Ok(sheets.len())
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum PageOrientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviatingPage {
+    page_number: usize,
+    width: f64,
+    height: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DominantSize {
+    width: f64,
+    height: f64,
+    orientation: PageOrientation,
+    // Pages whose size differs from the dominant one by more than a point
+    deviations: Vec<DeviatingPage>,
+}
+
+// The most common physical page size (visible box, with inheritance and UserUnit
+// applied) and the pages that differ from it. Sizes are unrotated, as the resizing
+// commands measure them.
+#[tauri::command]
+async fn get_dominant_page_size(state: State<'_, AppState>, path: String) -> Result<DominantSize, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    with_document(&state, &path, |doc| {
+        let sizes = (1..=doc.get_pages().len())
+            .map(|page_num| get_page_dimensions(doc, page_num, default_size))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (width, height) = most_common_size(&sizes).unwrap_or(default_size);
+
+        let orientation = if (width - height).abs() < 1.0 {
+            PageOrientation::Square
+        } else if width > height {
+            PageOrientation::Landscape
+        } else {
+            PageOrientation::Portrait
+        };
+        let deviations = sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, &size)| !same_page_size(size, (width, height)))
+            .map(|(index, &(width, height))| DeviatingPage { page_number: index + 1, width, height })
+            .collect();
+
+        Ok(DominantSize { width, height, orientation, deviations })
+    })
+}
+
+// Fade whole pages by wrapping their content in an ExtGState with the given fill and
+// stroke alpha. Content that sets its own alpha through a later gs replaces this value
+// rather than multiplying with it.
+#[tauri::command]
+async fn set_page_opacity(
+    state: State<'_, AppState>,
+    path: String,
+    pages: Option<Vec<usize>>,
+    opacity: f64,
+) -> Result<(), String> {
+    let opacity = opacity.clamp(0.0, 1.0);
+    edit_document(&state, &path, |doc| {
+        let targets = select_pages(doc, &pages)?;
+        let state_id = doc.add_object(opacity_state(opacity));
+        for (_, page_id) in targets {
+            let name = add_page_resource(doc, page_id, "ExtGState", "Fade", Object::Reference(state_id))?;
+            let before = format!("q /{} gs\n", String::from_utf8_lossy(&name));
+            wrap_page_content(doc, page_id, before.as_bytes(), b"\nQ\n")?;
+        }
+        Ok(())
+    })
+}
+
+const STRUCTURE_VALUE_LIMIT: usize = 120;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StructureNode {
+    object_id: (u32, u16),
+    kind: String,
+    page_number: Option<usize>,
+    count: Option<i64>,
+    // Inheritable attributes set on this node itself, summarized
+    attributes: BTreeMap<String, String>,
+    kids: Vec<StructureNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocumentStructure {
+    page_count: usize,
+    tree_depth: usize,
+    catalog: BTreeMap<String, String>,
+    has_acro_form: bool,
+    page_tree: Option<StructureNode>,
+}
+
+// Short single-line form of a value: containers are described rather than expanded
+fn summarize_object(object: &Object) -> String {
+    let text = match object {
+        Object::Dictionary(dict) => format!("<< {} entries >>", dict.len()),
+        Object::Stream(stream) => format!("stream ({} bytes)", stream.content.len()),
+        other => format_object(other, 0),
+    };
+    if text.chars().count() > STRUCTURE_VALUE_LIMIT {
+        let cut: String = text.chars().take(STRUCTURE_VALUE_LIMIT).collect();
+        format!("{}...", cut)
+    } else {
+        text
+    }
+}
+
+fn structure_node(
+    doc: &Document,
+    node_id: ObjectId,
+    depth: usize,
+    next_page: &mut usize,
+    max_depth: &mut usize,
+    visited: &mut std::collections::HashSet<ObjectId>,
+) -> Option<StructureNode> {
+    if !visited.insert(node_id) {
+        return None;
+    }
+    let node = doc.get_dictionary(node_id).ok()?;
+    *max_depth = (*max_depth).max(depth);
+    let kind = node
+        .get(b"Type")
+        .and_then(Object::as_name)
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .unwrap_or_else(|_| if node.has(b"Kids") { "Pages" } else { "Page" }.to_string());
+
+    let attributes = [&b"Resources"[..], b"MediaBox", b"CropBox", b"Rotate"]
+        .iter()
+        .filter_map(|key| {
+            node.get(key)
+                .ok()
+                .map(|value| (String::from_utf8_lossy(key).into_owned(), summarize_object(value)))
+        })
+        .collect();
+
+    let kids: Vec<StructureNode> = match node.get(b"Kids").ok().and_then(|kids| resolve_object(doc, kids)) {
+        Some(Object::Array(kids)) => kids
+            .iter()
+            .filter_map(|kid| kid.as_reference().ok())
+            .filter_map(|kid| structure_node(doc, kid, depth + 1, next_page, max_depth, visited))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let page_number = if kind == "Page" {
+        *next_page += 1;
+        Some(*next_page)
+    } else {
+        None
+    };
+
+    Some(StructureNode {
+        object_id: node_id,
+        kind,
+        page_number,
+        count: node.get(b"Count").and_then(Object::as_i64).ok(),
+        attributes,
+        kids,
+    })
+}
+
+// JSON outline of the document's shape for bug reports: the page tree with counts and
+// inherited attributes, the catalog entries and whether there is a form. Stream data
+// is never included. Depth counts the root Pages node as 1.
+#[tauri::command]
+async fn export_structure(state: State<'_, AppState>, path: String) -> Result<String, String> {
+    with_document(&state, &path, |doc| {
+        let catalog = doc.catalog().map_err(|e| e.to_string())?;
+        let catalog_entries = catalog
+            .iter()
+            .map(|(key, value)| (String::from_utf8_lossy(key).into_owned(), summarize_object(value)))
+            .collect();
+
+        let mut next_page = 0;
+        let mut tree_depth = 0;
+        let mut visited = std::collections::HashSet::new();
+        let page_tree = catalog
+            .get(b"Pages")
+            .and_then(Object::as_reference)
+            .ok()
+            .and_then(|root| structure_node(doc, root, 1, &mut next_page, &mut tree_depth, &mut visited));
+
+        let structure = DocumentStructure {
+            page_count: doc.get_pages().len(),
+            tree_depth,
+            catalog: catalog_entries,
+            has_acro_form: catalog.has(b"AcroForm"),
+            page_tree,
+        };
+        serde_json::to_string_pretty(&structure).map_err(|e| e.to_string())
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum BackgroundSpec {
+    // A solid RGB fill, components 0-1
+    Color { color: [f64; 3] },
+    // An image file scaled to cover the page, cropped to its visible box
+    Image { image_path: String },
+}
+
+// Draw a background behind the existing content of each target page. The drawing is
+// prepended in its own q/Q so the original content streams are untouched.
+#[tauri::command]
+async fn add_page_background(
+    state: State<'_, AppState>,
+    path: String,
+    pages: Option<Vec<usize>>,
+    background: BackgroundSpec,
+) -> Result<(), String> {
+    if let BackgroundSpec::Color { color } = &background {
+        check_color(color)?;
+    }
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+
+    edit_document(&state, &path, |doc| {
+        let targets = select_pages(doc, &pages)?;
+        let image = match &background {
+            BackgroundSpec::Image { image_path } => Some(embed_image_file(doc, image_path)?),
+            BackgroundSpec::Color { .. } => None,
+        };
+
+        for (_, page_id) in targets {
+            let [x0, y0, x1, y1] = page_visible_box(doc, page_id)
+                .unwrap_or([0.0, 0.0, default_size.0, default_size.1]);
+            let (page_width, page_height) = (x1 - x0, y1 - y0);
+            let mut operators = format!("q {:.4} {:.4} {:.4} {:.4} re ", x0, y0, page_width, page_height);
+
+            match &background {
+                BackgroundSpec::Color { color } => {
+                    operators.push_str(&format!("{:.4} {:.4} {:.4} rg f Q\n", color[0], color[1], color[2]));
+                }
+                BackgroundSpec::Image { .. } => {
+                    let (image_id, image_width, image_height) = image.ok_or("Background image was not embedded")?;
+                    let factor = (page_width / image_width as f64).max(page_height / image_height as f64);
+                    let (width, height) = (image_width as f64 * factor, image_height as f64 * factor);
+                    let name = add_page_resource(doc, page_id, "XObject", "Bg", Object::Reference(image_id))?;
+                    operators.push_str(&format!(
+                        "W n {:.4} 0 0 {:.4} {:.4} {:.4} cm /{} Do Q\n",
+                        width,
+                        height,
+                        x0 + (page_width - width) / 2.0,
+                        y0 + (page_height - height) / 2.0,
+                        String::from_utf8_lossy(&name)
+                    ));
+                }
+            }
+
+            let mut contents = page_content_refs(doc, page_id);
+            let background_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), operators.into_bytes()));
+            contents.insert(0, Object::Reference(background_id));
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Contents", Object::Array(contents));
+        }
+        Ok(())
+    })
+}
+
+// Catalog, page tree, xref table and trailer of each part
+const PART_OVERHEAD_BYTES: u64 = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SplitWarning {
+    page_number: usize,
+    estimated_bytes: u64,
+    max_bytes: u64,
+}
+
+// Rough serialized size of one object: stream data plus its dictionary, or the
+// object's syntax
+fn estimated_object_size(object: &Object) -> u64 {
+    match object {
+        Object::Stream(stream) => (stream.content.len() + format_dictionary(&stream.dict, 0).len() + 40) as u64,
+        other => (format_object(other, 0).len() + 20) as u64,
+    }
+}
+
+// Split a document into consecutive page ranges whose files stay under `max_bytes`,
+// written to `output_dir` as {stem}_part{n}.pdf. Each page is charged for the objects
+// it reaches that the current part doesn't already hold, so shared fonts and images
+// count once per part. A page over the cap on its own becomes a part by itself and
+// is reported through a "split_warning" event.
+#[tauri::command]
+async fn split_by_size<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+    path: String,
+    max_bytes: u64,
+    output_dir: String,
+) -> Result<Vec<String>, String> {
+    if max_bytes <= PART_OVERHEAD_BYTES {
+        return Err(format!("Size cap must be larger than {} bytes", PART_OVERHEAD_BYTES));
+    }
+    let output_dir = std::path::PathBuf::from(output_dir);
+    if !output_dir.is_dir() {
+        return Err(format!("{} is not a directory", output_dir.display()));
+    }
+    let stem = std::path::Path::new(&path)
+        .file_stem()
+        .map_or("output".into(), |stem| stem.to_string_lossy())
+        .into_owned();
+
+    let mut doc = snapshot_document(&state, &path)?;
+    let pages: Vec<(usize, ObjectId)> = doc.get_pages().into_iter().map(|(n, id)| (n as usize, id)).collect();
+    for &(_, page_id) in &pages {
+        materialize_inherited_attributes(&mut doc, page_id)?;
+    }
+    let mut stop: std::collections::HashSet<ObjectId> = pages.iter().map(|&(_, id)| id).collect();
+    for (&id, object) in doc.objects.iter() {
+        if is_pages_node(object) {
+            stop.insert(id);
+        }
+    }
+
+    let mut parts: Vec<Vec<(usize, ObjectId)>> = Vec::new();
+    let mut current: Vec<(usize, ObjectId)> = Vec::new();
+    let mut current_objects = std::collections::HashSet::new();
+    let mut current_size = PART_OVERHEAD_BYTES;
+    for &(page_num, page_id) in &pages {
+        let mut page_stop = stop.clone();
+        page_stop.remove(&page_id);
+        let mut reachable = Vec::new();
+        collect_reachable(&doc, page_id, &page_stop, &mut std::collections::HashSet::new(), &mut reachable);
+
+        let cost = |held: &std::collections::HashSet<ObjectId>| -> u64 {
+            reachable
+                .iter()
+                .filter(|id| !held.contains(*id))
+                .filter_map(|id| doc.objects.get(id))
+                .map(estimated_object_size)
+                .sum()
+        };
+        let mut added = cost(&current_objects);
+        if !current.is_empty() && current_size + added > max_bytes {
+            parts.push(std::mem::take(&mut current));
+            current_objects.clear();
+            current_size = PART_OVERHEAD_BYTES;
+            added = cost(&current_objects);
+        }
+        if current.is_empty() && current_size + added > max_bytes {
+            let warning = SplitWarning { page_number: page_num, estimated_bytes: current_size + added, max_bytes };
+            let _ = app.emit("split_warning", warning);
+        }
+        current.push((page_num, page_id));
+        current_objects.extend(reachable);
+        current_size += added;
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    let mut outputs = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        let mut part_doc = build_document_from_pages(&mut doc, part, &BTreeMap::new())?;
+        let output_path = output_dir
+            .join(format!("{}_part{}.pdf", stem, index + 1))
+            .to_string_lossy()
+            .into_owned();
+        save_document(&mut part_doc, &output_path)?;
+        outputs.push(output_path);
+    }
+    Ok(outputs)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LayerInfo {
+    name: String,
+    visible_by_default: bool,
+    // Pages whose content, XObjects or annotations are marked with this layer
+    pages: Vec<usize>,
+}
+
+fn oc_properties(doc: &Document) -> Option<&lopdf::Dictionary> {
+    let properties = doc.catalog().ok()?.get(b"OCProperties").ok()?;
+    resolve_object(doc, properties)?.as_dict().ok()
+}
+
+fn reference_list(doc: &Document, dict: &lopdf::Dictionary, key: &[u8]) -> Vec<ObjectId> {
+    match dict.get(key).ok().and_then(|value| resolve_object(doc, value)) {
+        Some(Object::Array(items)) => items.iter().filter_map(|item| item.as_reference().ok()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Every optional content group with its name and its state under the default
+// configuration (/D): /BaseState decides, and /ON or /OFF lists override it
+fn layer_states(doc: &Document) -> Vec<(ObjectId, String, bool)> {
+    let properties = match oc_properties(doc) {
+        Some(properties) => properties,
+        None => return Vec::new(),
+    };
+    let config = properties
+        .get(b"D")
+        .ok()
+        .and_then(|config| resolve_object(doc, config))
+        .and_then(|config| config.as_dict().ok());
+    let base_on = config
+        .and_then(|config| config.get(b"BaseState").and_then(Object::as_name).ok())
+        .is_none_or(|state| state != b"OFF");
+    let on = config.map(|config| reference_list(doc, config, b"ON")).unwrap_or_default();
+    let off = config.map(|config| reference_list(doc, config, b"OFF")).unwrap_or_default();
+
+    reference_list(doc, properties, b"OCGs")
+        .into_iter()
+        .map(|id| {
+            let name = doc
+                .get_dictionary(id)
+                .ok()
+                .and_then(|group| group.get(b"Name").ok())
+                .and_then(|name| match name {
+                    Object::String(bytes, _) => Some(decode_pdf_string(bytes)),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let visible = if base_on { !off.contains(&id) } else { on.contains(&id) };
+            (id, name, visible)
+        })
+        .collect()
+}
+
+// The groups an /OC value refers to: the group itself, or the /OCGs of a membership
+// dictionary (OCMD)
+fn optional_content_groups(doc: &Document, oc: &Object) -> Vec<ObjectId> {
+    let (id, dict) = match doc.dereference(oc) {
+        Ok((Some(id), Object::Dictionary(dict))) => (id, dict),
+        _ => return Vec::new(),
+    };
+    if dict.get(b"Type").and_then(Object::as_name).is_ok_and(|kind| kind == b"OCMD") {
+        match dict.get(b"OCGs") {
+            Ok(Object::Reference(group)) => vec![*group],
+            _ => reference_list(doc, dict, b"OCGs"),
+        }
+    } else {
+        vec![id]
+    }
+}
+
+// Groups used by a resource dictionary: marked-content /Properties and XObjects with
+// /OC, including those inside nested forms
+fn resource_layers(
+    doc: &Document,
+    resources: &lopdf::Dictionary,
+    layers: &mut std::collections::HashSet<ObjectId>,
+    visited: &mut std::collections::HashSet<ObjectId>,
+) {
+    if let Some(properties) = resource_category(doc, resources, b"Properties") {
+        for (_, value) in properties.iter() {
+            layers.extend(optional_content_groups(doc, value));
+        }
+    }
+    if let Some(xobjects) = resource_category(doc, resources, b"XObject") {
+        for (_, value) in xobjects.iter() {
+            let (id, stream) = match doc.dereference(value) {
+                Ok((Some(id), Object::Stream(stream))) => (id, stream),
+                _ => continue,
+            };
+            if !visited.insert(id) {
+                continue;
+            }
+            if let Ok(oc) = stream.dict.get(b"OC") {
+                layers.extend(optional_content_groups(doc, oc));
+            }
+            let inner = stream.dict.get(b"Resources").ok().and_then(|inner| resolve_object(doc, inner));
+            if let Some(Object::Dictionary(inner)) = inner {
+                resource_layers(doc, inner, layers, visited);
+            }
+        }
+    }
+}
+
+fn page_layers(doc: &Document, page_id: ObjectId) -> std::collections::HashSet<ObjectId> {
+    let mut layers = std::collections::HashSet::new();
+    if let Some(resources) = page_resources(doc, page_id) {
+        resource_layers(doc, resources, &mut layers, &mut std::collections::HashSet::new());
+    }
+    for annot in page_annotations(doc, page_id) {
+        let annot = resolve_object(doc, &annot).and_then(|annot| annot.as_dict().ok());
+        if let Some(oc) = annot.and_then(|annot| annot.get(b"OC").ok()) {
+            layers.extend(optional_content_groups(doc, oc));
+        }
+    }
+    layers
+}
+
+#[tauri::command]
+async fn list_layers(state: State<'_, AppState>, path: String) -> Result<Vec<LayerInfo>, String> {
+    with_document(&state, &path, |doc| {
+        let page_layers: Vec<(usize, std::collections::HashSet<ObjectId>)> = doc
+            .get_pages()
+            .into_iter()
+            .map(|(page_num, page_id)| (page_num as usize, page_layers(doc, page_id)))
+            .collect();
+        Ok(layer_states(doc)
+            .into_iter()
+            .map(|(id, name, visible_by_default)| LayerInfo {
+                name,
+                visible_by_default,
+                pages: page_layers
+                    .iter()
+                    .filter(|(_, layers)| layers.contains(&id))
+                    .map(|&(page_num, _)| page_num)
+                    .collect(),
+            })
+            .collect())
+    })
+}
+
+// Change whether the named layer (every group with that name) is shown by default.
+// The group is moved between the default configuration's /ON and /OFF lists.
+#[tauri::command]
+async fn set_layer_visibility(state: State<'_, AppState>, path: String, name: String, visible: bool) -> Result<(), String> {
+    edit_document(&state, &path, |doc| {
+        let groups: Vec<ObjectId> = layer_states(doc)
+            .into_iter()
+            .filter(|(_, layer, _)| *layer == name)
+            .map(|(id, _, _)| id)
+            .collect();
+        if groups.is_empty() {
+            return Err(format!("Layer '{}' not found", name));
+        }
+
+        let catalog = doc.catalog().map_err(|e| e.to_string())?;
+        let properties_ref = catalog.get(b"OCProperties").and_then(Object::as_reference).ok();
+        let mut properties = oc_properties(doc).cloned().ok_or("Document has no optional content")?;
+        let config_ref = properties.get(b"D").and_then(Object::as_reference).ok();
+        let mut config = properties
+            .get(b"D")
+            .ok()
+            .and_then(|config| resolve_object(doc, config))
+            .and_then(|config| config.as_dict().ok())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut on = reference_list(doc, &config, b"ON");
+        let mut off = reference_list(doc, &config, b"OFF");
+        on.retain(|id| !groups.contains(id));
+        off.retain(|id| !groups.contains(id));
+        if visible { on.extend(&groups) } else { off.extend(&groups) }
+        let to_array = |ids: Vec<ObjectId>| Object::Array(ids.into_iter().map(Object::Reference).collect());
+        config.set("ON", to_array(on));
+        config.set("OFF", to_array(off));
+
+        match config_ref {
+            Some(config_id) => {
+                doc.objects.insert(config_id, Object::Dictionary(config));
+            }
+            None => {
+                properties.set("D", Object::Dictionary(config));
+                match properties_ref {
+                    Some(properties_id) => {
+                        doc.objects.insert(properties_id, Object::Dictionary(properties));
+                    }
+                    None => {
+                        let catalog = doc.catalog_mut().map_err(|e| e.to_string())?;
+                        catalog.set("OCProperties", Object::Dictionary(properties));
+                    }
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// Whether content marked with an /OC value shows when the given groups are hidden.
+// Membership dictionaries follow their /P policy (AnyOn by default); /VE visibility
+// expressions are not evaluated and fall back to the policy.
+fn optional_content_visible(doc: &Document, oc: &Object, hidden: &std::collections::HashSet<ObjectId>) -> bool {
+    let dict = match doc.dereference(oc) {
+        Ok((_, Object::Dictionary(dict))) => dict,
+        _ => return true,
+    };
+    let is_membership = dict.get(b"Type").and_then(Object::as_name).is_ok_and(|kind| kind == b"OCMD");
+    if !is_membership {
+        return match doc.dereference(oc) {
+            Ok((Some(id), _)) => !hidden.contains(&id),
+            _ => true,
+        };
+    }
+    let states: Vec<bool> = optional_content_groups(doc, oc).iter().map(|id| !hidden.contains(id)).collect();
+    if states.is_empty() {
+        return true;
+    }
+    match dict.get(b"P").and_then(Object::as_name).unwrap_or(b"AnyOn") {
+        b"AllOn" => states.iter().all(|&on| on),
+        b"AnyOff" => states.iter().any(|&on| !on),
+        b"AllOff" => states.iter().all(|&on| !on),
+        _ => states.iter().any(|&on| on),
+    }
+}
+
+// Drop marked-content sections (BDC /OC ... EMC) and XObject draws that belong to
+// hidden groups, keeping everything else as is
+fn strip_hidden_content(
+    doc: &Document,
+    content: &[u8],
+    resources: Option<&lopdf::Dictionary>,
+    hidden: &std::collections::HashSet<ObjectId>,
+) -> Result<Vec<u8>, String> {
+    let content = lopdf::content::Content::decode(content).map_err(|e| e.to_string())?;
+    let properties = resources.and_then(|resources| resource_category(doc, resources, b"Properties"));
+    let xobjects = resources.and_then(|resources| resource_category(doc, resources, b"XObject"));
+
+    let mut sections: Vec<bool> = Vec::new();
+    let mut kept = Vec::new();
+    for operation in content.operations {
+        let inside_hidden = sections.last().copied().unwrap_or(false);
+        match operation.operator.as_str() {
+            "BDC" => {
+                let is_layer = operation.operands.first().and_then(|tag| tag.as_name().ok()) == Some(&b"OC"[..]);
+                let marked = match operation.operands.get(1) {
+                    Some(Object::Name(name)) => properties.and_then(|properties| properties.get(name).ok()),
+                    other => other,
+                };
+                let hides = is_layer && marked.is_some_and(|oc| !optional_content_visible(doc, oc, hidden));
+                sections.push(inside_hidden || hides);
+                if inside_hidden || hides {
+                    continue;
+                }
+            }
+            "BMC" => {
+                sections.push(inside_hidden);
+                if inside_hidden {
+                    continue;
+                }
+            }
+            "EMC" => {
+                let closes_hidden = sections.pop().unwrap_or(false);
+                if closes_hidden {
+                    continue;
+                }
+            }
+            _ if inside_hidden => continue,
+            "Do" => {
+                let xobject = operation
+                    .operands
+                    .first()
+                    .and_then(|name| name.as_name().ok())
+                    .and_then(|name| xobjects.and_then(|xobjects| xobjects.get(name).ok()))
+                    .and_then(|xobject| resolve_object(doc, xobject));
+                if let Some(Object::Stream(stream)) = xobject {
+                    if stream.dict.get(b"OC").is_ok_and(|oc| !optional_content_visible(doc, oc, hidden)) {
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        kept.push(operation);
+    }
+    lopdf::content::Content { operations: kept }.encode().map_err(|e| e.to_string())
+}
+
+// Bake layers into plain content as a viewer shows them with the default
+// configuration: content, XObjects and annotations of hidden groups are removed, and
+// /OCProperties and the remaining /OC entries are dropped.
+#[tauri::command]
+async fn flatten_layers(state: State<'_, AppState>, path: String, output_path: String) -> Result<(), String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let hidden: std::collections::HashSet<ObjectId> = layer_states(&doc)
+        .into_iter()
+        .filter(|(_, _, visible)| !visible)
+        .map(|(id, _, _)| id)
+        .collect();
+
+    for page_id in doc.get_pages().into_values() {
+        let raw = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+        let content = strip_hidden_content(&doc, &raw, page_resources(&doc, page_id), &hidden)?;
+        set_page_content(&mut doc, page_id, content)?;
+
+        let annots: Vec<Object> = page_annotations(&doc, page_id)
+            .into_iter()
+            .filter(|annot| {
+                let oc = resolve_object(&doc, annot)
+                    .and_then(|annot| annot.as_dict().ok())
+                    .and_then(|annot| annot.get(b"OC").ok());
+                !oc.is_some_and(|oc| !optional_content_visible(&doc, oc, &hidden))
+            })
+            .collect();
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        if page.has(b"Annots") {
+            page.set("Annots", Object::Array(annots));
+        }
+    }
+
+    // Form XObjects carry their own marked content
+    let forms: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| match object {
+            Object::Stream(stream) => stream.dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Form"),
+            _ => false,
+        })
+        .map(|(&id, _)| id)
+        .collect();
+    for form_id in forms {
+        let (raw, resources) = match doc.get_object(form_id) {
+            Ok(Object::Stream(form)) => (
+                form.decompressed_content().unwrap_or_else(|_| form.content.clone()),
+                form.dict.get(b"Resources").ok().and_then(|r| resolve_object(&doc, r)).and_then(|r| r.as_dict().ok()),
+            ),
+            _ => continue,
+        };
+        let content = strip_hidden_content(&doc, &raw, resources, &hidden)?;
+        if let Ok(Object::Stream(form)) = doc.get_object_mut(form_id) {
+            form.dict.remove(b"Filter");
+            form.dict.remove(b"DecodeParms");
+            form.set_content(content);
+            let _ = form.compress();
+        }
+    }
+
+    for object in doc.objects.values_mut() {
+        match object {
+            Object::Dictionary(dict) => {
+                dict.remove(b"OC");
+            }
+            Object::Stream(stream) => {
+                stream.dict.remove(b"OC");
+            }
+            _ => {}
+        }
+    }
+    doc.catalog_mut().map_err(|e| e.to_string())?.remove(b"OCProperties");
+    doc.prune_objects();
+    save_document(&mut doc, &output_path)
+}
+
+// Add /Type entries that malformed files leave out, inferred from where each object is
+// used: the catalog, page tree nodes (by /Kids) and leaves, and fonts, XObjects and
+// annotations referenced from pages. Returns how many entries were added.
+#[tauri::command]
+async fn fix_missing_types(state: State<'_, AppState>, path: String, output_path: String) -> Result<usize, String> {
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut missing: Vec<(ObjectId, &'static str)> = Vec::new();
+    let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference).map_err(|e| e.to_string())?;
+    missing.push((catalog_id, "Catalog"));
+
+    // The page tree, walked by /Kids so nodes without /Type are still found: anything
+    // with /Kids is an intermediate node and any other node a page
+    let mut pending: Vec<ObjectId> = doc
+        .get_dictionary(catalog_id)
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+        .into_iter()
+        .collect();
+    let mut visited = std::collections::HashSet::new();
+    let mut pages = Vec::new();
+    while let Some(node_id) = pending.pop() {
+        if !visited.insert(node_id) {
+            continue;
+        }
+        let node = match doc.get_dictionary(node_id) {
+            Ok(node) => node,
+            Err(_) => continue,
+        };
+        match node.get(b"Kids").ok().and_then(|kids| resolve_object(&doc, kids)) {
+            Some(Object::Array(kids)) => {
+                missing.push((node_id, "Pages"));
+                pending.extend(kids.iter().filter_map(|kid| kid.as_reference().ok()));
+            }
+            _ => {
+                missing.push((node_id, "Page"));
+                pages.push(node_id);
+            }
+        }
+    }
+    let outlines = doc
+        .get_dictionary(catalog_id)
+        .and_then(|catalog| catalog.get(b"Outlines"))
+        .and_then(Object::as_reference);
+    if let Ok(outlines) = outlines {
+        missing.push((outlines, "Outlines"));
+    }
+
+    for &page_id in &pages {
+        if let Some(resources) = page_resources(&doc, page_id) {
+            let categories = [(&b"Font"[..], "Font"), (&b"XObject"[..], "XObject"), (&b"ExtGState"[..], "ExtGState")];
+            for (category, kind) in categories {
+                if let Some(entries) = resource_category(&doc, resources, category) {
+                    let ids = entries.iter().filter_map(|(_, value)| value.as_reference().ok());
+                    missing.extend(ids.map(|id| (id, kind)));
+                }
+            }
+        }
+        for annot in page_annotations(&doc, page_id) {
+            if let Ok(annot_id) = annot.as_reference() {
+                missing.push((annot_id, "Annot"));
+            }
+        }
+    }
+
+    let mut fixed = 0;
+    for (id, kind) in missing {
+        let dict = match doc.get_object_mut(id) {
+            Ok(Object::Dictionary(dict)) => dict,
+            Ok(Object::Stream(stream)) => &mut stream.dict,
+            _ => continue,
+        };
+        if !dict.has(b"Type") {
+            dict.set("Type", Object::Name(kind.as_bytes().to_vec()));
+            fixed += 1;
+        }
+    }
+
+    save_document(&mut doc, &output_path)?;
+    Ok(fixed)
+}
+
+// Longest side, in pixels, of the preview stored by set_document_preview
+const PREVIEW_SIZE: u32 = 256;
+
+// Store a preview of the document as page 1's /Thumb, the standard thumbnail that file
+// managers and viewers pick up. The image comes from `image_path` when given and from
+// a rendering of page 1 otherwise, scaled so its longest side is PREVIEW_SIZE.
+#[tauri::command]
+async fn set_document_preview(state: State<'_, AppState>, path: String, image_path: Option<String>) -> Result<(), String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    edit_document(&state, &path, |doc| {
+        let page_id = page_id(doc, 1)?;
+        let image = match &image_path {
+            Some(image_path) => {
+                let image = image::open(image_path).map_err(|e| e.to_string())?.to_rgb8();
+                let scale = PREVIEW_SIZE as f64 / image.width().max(image.height()) as f64;
+                let width = ((image.width() as f64 * scale).round() as u32).max(1);
+                let height = ((image.height() as f64 * scale).round() as u32).max(1);
+                image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+            }
+            None => {
+                let (_, width, height) = page_device_matrix(doc, page_id, 1.0, default_size);
+                render_page(doc, page_id, PREVIEW_SIZE as f64 / width.max(height) as f64, None, default_size)?
+            }
+        };
+
+        let thumb_id = doc.add_object(rgb_image_stream(&lopdf::Dictionary::new(), &image)?);
+        let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+        page.set("Thumb", Object::Reference(thumb_id));
+        Ok(())
+    })
+}
+
+// Set how many seconds each listed page stays up before a viewer in presentation mode
+// advances (/Dur), keyed by page number
+#[tauri::command]
+async fn set_page_durations(state: State<'_, AppState>, path: String, durations: BTreeMap<usize, f64>) -> Result<(), String> {
+    if durations.values().any(|&seconds| seconds < 0.0) {
+        return Err("Durations must not be negative".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        for (&page_num, &seconds) in &durations {
+            let page_id = page_id(doc, page_num)?;
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            page.set("Dur", Object::Real(seconds as f32));
+        }
+        Ok(())
+    })
+}
+
+// Display durations of the pages that have one
+#[tauri::command]
+async fn get_page_durations(state: State<'_, AppState>, path: String) -> Result<BTreeMap<usize, f64>, String> {
+    with_document(&state, &path, |doc| {
+        Ok(doc
+            .get_pages()
+            .into_iter()
+            .filter_map(|(page_num, page_id)| {
+                let seconds = doc.get_dictionary(page_id).ok()?.get(b"Dur").and_then(Object::as_float).ok()?;
+                Some((page_num as usize, seconds as f64))
+            })
+            .collect())
+    })
+}
+
+// How long a finished job's status stays available for polling
+const JOB_RETENTION: std::time::Duration = std::time::Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum JobState {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JobStatus {
+    state: JobState,
+    // Fraction done, 0-1
+    progress: f64,
+    result_path: Option<String>,
+    error: Option<String>,
+}
+
+struct Job {
+    status: JobStatus,
+    finished_at: Option<std::time::Instant>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: HashMap<u64, Job>,
+}
+
+// Long operations that can run in the background, each taking its command's arguments.
+// Most write a new file or directory and leave the cached documents alone; Deskew and
+// AutoCrop edit the cached document like their commands do and report its path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum JobRequest {
+    SaveUnderSize { path: String, output_path: String, max_bytes: u64, codec: Option<ImageCodec> },
+    MakeDiffPdf { path_a: String, path_b: String, output_path: String },
+    SideBySideCompare { path_a: String, path_b: String, output_path: String },
+    BatchToImages { input_paths: Vec<String>, output_dir: String, dpi: u32, format: ImageFormat },
+    FlattenTransparency { path: String, output_path: String, jpeg_quality: Option<u8>, codec: Option<ImageCodec> },
+    ConvertToRgb { path: String, output_path: String, jpeg_quality: Option<u8>, codec: Option<ImageCodec> },
+    EmbedThumbnails { path: String, output_path: String, size: u32, force: bool },
+    Deskew { path: String, max_angle: f64 },
+    AutoCrop { path: String, margin: f64 },
+}
+
+fn update_job<R: tauri::Runtime>(app: &tauri::AppHandle<R>, job_id: u64, update: impl FnOnce(&mut Job)) {
+    if let Ok(mut jobs) = app.state::<AppState>().jobs.lock() {
+        if let Some(job) = jobs.jobs.get_mut(&job_id) {
+            update(job);
+        }
+    }
+}
+
+async fn run_job<R: tauri::Runtime>(app: &tauri::AppHandle<R>, job_id: u64, request: JobRequest) -> Result<String, String> {
+    let state = app.state::<AppState>();
+    match request {
+        JobRequest::SaveUnderSize { path, output_path, max_bytes, codec } => {
+            save_under_size(state, path, output_path.clone(), max_bytes, codec).await?;
+            Ok(output_path)
+        }
+        JobRequest::MakeDiffPdf { path_a, path_b, output_path } => {
+            make_diff_pdf(state, path_a, path_b, output_path.clone()).await?;
+            Ok(output_path)
+        }
+        JobRequest::SideBySideCompare { path_a, path_b, output_path } => {
+            side_by_side_compare(state, path_a, path_b, output_path.clone()).await?;
+            Ok(output_path)
+        }
+        JobRequest::BatchToImages { input_paths, output_dir, dpi, format } => {
+            files_to_images(app, input_paths, &output_dir, dpi, format, &|progress| {
+                let done = (progress.current - 1) as f64 / progress.total as f64;
+                update_job(app, job_id, |job| job.status.progress = done);
+            })?;
+            Ok(output_dir)
+        }
+        JobRequest::FlattenTransparency { path, output_path, jpeg_quality, codec } => {
+            flatten_transparency(state, path, output_path.clone(), jpeg_quality, codec).await?;
+            Ok(output_path)
+        }
+        JobRequest::ConvertToRgb { path, output_path, jpeg_quality, codec } => {
+            convert_to_rgb(state, path, output_path.clone(), jpeg_quality, codec).await?;
+            Ok(output_path)
+        }
+        JobRequest::EmbedThumbnails { path, output_path, size, force } => {
+            embed_thumbnails(state, path, output_path.clone(), size, force).await?;
+            Ok(output_path)
+        }
+        JobRequest::Deskew { path, max_angle } => {
+            deskew(state, path.clone(), max_angle).await?;
+            Ok(path)
+        }
+        JobRequest::AutoCrop { path, margin } => {
+            auto_crop(state, path.clone(), margin).await?;
+            Ok(path)
+        }
+    }
+}
+
+// Start a long operation in the background and return its id for get_job_status. The
+// work is CPU-bound, so it runs on a blocking thread rather than an async worker.
+// Finished jobs older than JOB_RETENTION are dropped whenever a new one starts.
+#[tauri::command]
+async fn start_job<R: tauri::Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AppState>,
+    request: JobRequest,
+) -> Result<u64, String> {
+    let job_id = {
+        let mut jobs = state.jobs.lock().map_err(|e| e.to_string())?;
+        jobs.jobs
+            .retain(|_, job| !job.finished_at.is_some_and(|finished| finished.elapsed() > JOB_RETENTION));
+        jobs.next_id += 1;
+        let job_id = jobs.next_id;
+        let status = JobStatus { state: JobState::Running, progress: 0.0, result_path: None, error: None };
+        jobs.jobs.insert(job_id, Job { status, finished_at: None });
+        job_id
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = tauri::async_runtime::block_on(run_job(&app, job_id, request));
+        update_job(&app, job_id, |job| {
+            job.finished_at = Some(std::time::Instant::now());
+            match result {
+                Ok(result_path) => {
+                    job.status.state = JobState::Completed;
+                    job.status.progress = 1.0;
+                    job.status.result_path = Some(result_path);
+                }
+                Err(error) => {
+                    job.status.state = JobState::Failed;
+                    job.status.error = Some(error);
+                }
+            }
+        });
+    });
+    Ok(job_id)
+}
+
+#[tauri::command]
+async fn get_job_status(state: State<'_, AppState>, job_id: u64) -> Result<JobStatus, String> {
+    let jobs = state.jobs.lock().map_err(|e| e.to_string())?;
+    jobs.jobs
+        .get(&job_id)
+        .map(|job| job.status.clone())
+        .ok_or_else(|| format!("Job {} not found", job_id))
+}
+
+// Move each target page's MediaBox origin to (0, 0). Content is translated by the old
+// origin and the other page boxes and annotation rectangles move with it, so the page
+// looks the same. Returns how many pages were shifted.
+#[tauri::command]
+async fn normalize_origin(state: State<'_, AppState>, path: String, pages: Option<Vec<usize>>) -> Result<usize, String> {
+    edit_document(&state, &path, |doc| {
+        let mut shifted = 0;
+        for (_, page_id) in select_pages(doc, &pages)? {
+            materialize_inherited_attributes(doc, page_id)?;
+            let media_box = doc
+                .get_dictionary(page_id)
+                .ok()
+                .and_then(|page| page.get(b"MediaBox").ok())
+                .and_then(|media_box| rect_from_object(doc, media_box))
+                .ok_or("Page has no MediaBox")?;
+            let (dx, dy) = (-media_box[0], -media_box[1]);
+            if dx == 0.0 && dy == 0.0 {
+                continue;
+            }
+
+            let matrix = [1.0, 0.0, 0.0, 1.0, dx, dy];
+            let cm = format!("q 1 0 0 1 {:.4} {:.4} cm\n", dx, dy);
+            wrap_page_content(doc, page_id, cm.as_bytes(), b"\nQ\n")?;
+            transform_annotation_rects(doc, page_id, &matrix);
+
+            let boxes: Vec<(&[u8], [f64; 4])> = [&b"MediaBox"[..], b"CropBox", b"BleedBox", b"TrimBox", b"ArtBox"]
+                .into_iter()
+                .filter_map(|key| {
+                    let page = doc.get_dictionary(page_id).ok()?;
+                    Some((key, rect_from_object(doc, page.get(key).ok()?)?))
+                })
+                .collect();
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            for (key, [x0, y0, x1, y1]) in boxes {
+                let moved = [x0 + dx, y0 + dy, x1 + dx, y1 + dy];
+                page.set(key, Object::Array(moved.iter().map(|&v| Object::Real(v as f32)).collect()));
+            }
+            shifted += 1;
+        }
+        Ok(shifted)
+    })
+}
+
+// Action types that run code, start programs or send and load data
+const ACTIVE_ACTION_TYPES: [&str; 4] = ["JavaScript", "Launch", "SubmitForm", "ImportData"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActiveContentItem {
+    // An action type from ACTIVE_ACTION_TYPES, "OpenAction" or "EmbeddedFile"
+    kind: String,
+    // Where it was found, e.g. "page 3 Link annotation /A"
+    location: String,
+    page_number: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ActiveContentReport {
+    javascript_names: Vec<String>,
+    has_open_action: bool,
+    embedded_files: Vec<String>,
+    pages_with_active_content: Vec<usize>,
+    items: Vec<ActiveContentItem>,
+}
+
+// Active action types in an action and the actions chained after it through /Next
+fn active_actions(doc: &Document, action: &Object, depth: usize, kinds: &mut Vec<String>) {
+    if depth > 32 {
+        return;
+    }
+    let action = match resolve_object(doc, action) {
+        Some(Object::Dictionary(action)) => action,
+        Some(Object::Array(actions)) => {
+            actions.iter().for_each(|action| active_actions(doc, action, depth + 1, kinds));
+            return;
+        }
+        _ => return,
+    };
+    if let Ok(kind) = action.get(b"S").and_then(Object::as_name) {
+        let kind = String::from_utf8_lossy(kind).into_owned();
+        if ACTIVE_ACTION_TYPES.contains(&kind.as_str()) {
+            kinds.push(kind);
+        }
+    }
+    if let Ok(next) = action.get(b"Next") {
+        active_actions(doc, next, depth + 1, kinds);
+    }
+}
+
+// Record the active actions of a dictionary's /A action and its /AA trigger actions
+fn record_actions(
+    doc: &Document,
+    dict: &lopdf::Dictionary,
+    location: &str,
+    page_number: Option<usize>,
+    items: &mut Vec<ActiveContentItem>,
+) {
+    let mut triggers: Vec<(String, &Object)> = Vec::new();
+    if let Ok(action) = dict.get(b"A") {
+        triggers.push(("/A".to_string(), action));
+    }
+    if let Some(Object::Dictionary(additional)) = dict.get(b"AA").ok().and_then(|aa| resolve_object(doc, aa)) {
+        for (trigger, action) in additional.iter() {
+            triggers.push((format!("/AA /{}", String::from_utf8_lossy(trigger)), action));
+        }
+    }
+    for (trigger, action) in triggers {
+        let mut kinds = Vec::new();
+        active_actions(doc, action, 0, &mut kinds);
+        for kind in kinds {
+            items.push(ActiveContentItem { kind, location: format!("{} {}", location, trigger), page_number });
+        }
+    }
+}
+
+fn catalog_name_tree<'a>(doc: &'a Document, key: &[u8]) -> Vec<(Vec<u8>, &'a Object)> {
+    let mut entries = Vec::new();
+    let tree = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Names").ok())
+        .and_then(|names| resolve_object(doc, names))
+        .and_then(|names| names.as_dict().ok())
+        .and_then(|names| names.get(key).ok())
+        .and_then(|tree| resolve_object(doc, tree))
+        .and_then(|tree| tree.as_dict().ok());
+    if let Some(tree) = tree {
+        name_tree_entries(doc, tree, 0, &mut entries);
+    }
+    entries
+}
+
+// Map the document's JavaScript, auto-run and data-moving actions and embedded files,
+// with where each one lives, for review before sanitizing
+#[tauri::command]
+async fn scan_active_content(state: State<'_, AppState>, path: String) -> Result<ActiveContentReport, String> {
+    with_document(&state, &path, |doc| {
+        let catalog = doc.catalog().map_err(|e| e.to_string())?;
+        let mut items = Vec::new();
+
+        let scripts = catalog_name_tree(doc, b"JavaScript");
+        let javascript_names: Vec<String> = scripts.iter().map(|(name, _)| decode_pdf_string(name)).collect();
+        for (name, action) in &scripts {
+            let mut kinds = Vec::new();
+            active_actions(doc, action, 0, &mut kinds);
+            let location = format!("document script '{}'", decode_pdf_string(name));
+            for kind in kinds {
+                items.push(ActiveContentItem { kind, location: location.clone(), page_number: None });
+            }
+        }
+
+        let open_action = catalog.get(b"OpenAction").ok();
+        if let Some(action) = open_action {
+            items.push(ActiveContentItem {
+                kind: "OpenAction".to_string(),
+                location: "catalog /OpenAction".to_string(),
+                page_number: None,
+            });
+            let mut kinds = Vec::new();
+            active_actions(doc, action, 0, &mut kinds);
+            items.extend(kinds.into_iter().map(|kind| ActiveContentItem {
+                kind,
+                location: "catalog /OpenAction".to_string(),
+                page_number: None,
+            }));
+        }
+        record_actions(doc, catalog, "catalog", None, &mut items);
+
+        let embedded = catalog_name_tree(doc, b"EmbeddedFiles");
+        let embedded_files: Vec<String> = embedded.iter().map(|(name, _)| decode_pdf_string(name)).collect();
+        for name in &embedded_files {
+            items.push(ActiveContentItem {
+                kind: "EmbeddedFile".to_string(),
+                location: format!("document attachment '{}'", name),
+                page_number: None,
+            });
+        }
+
+        for (page_num, page_id) in doc.get_pages() {
+            let page_num = page_num as usize;
+            if let Ok(page) = doc.get_dictionary(page_id) {
+                // Pages have no /A, so only their open/close triggers are found here
+                record_actions(doc, page, &format!("page {}", page_num), Some(page_num), &mut items);
+            }
+            for annot in page_annotations(doc, page_id) {
+                let annot = match resolve_object(doc, &annot) {
+                    Some(Object::Dictionary(annot)) => annot,
+                    _ => continue,
+                };
+                let subtype = annot.get(b"Subtype").and_then(Object::as_name).unwrap_or(&b"Annot"[..]);
+                let location = format!("page {} {} annotation", page_num, String::from_utf8_lossy(subtype));
+                record_actions(doc, annot, &location, Some(page_num), &mut items);
+                if subtype == b"FileAttachment" {
+                    let kind = "EmbeddedFile".to_string();
+                    items.push(ActiveContentItem { kind, location, page_number: Some(page_num) });
+                }
+            }
+        }
+
+        let mut pages_with_active_content: Vec<usize> = items.iter().filter_map(|item| item.page_number).collect();
+        pages_with_active_content.dedup();
+        Ok(ActiveContentReport {
+            javascript_names,
+            has_open_action: open_action.is_some(),
+            embedded_files,
+            pages_with_active_content,
+            items,
+        })
+    })
+}
+
+// Tables of a TrueType program as (tag, data), in directory order
+fn truetype_tables(program: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let read_u32 = |at: usize| program.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    let table_count = u16::from_be_bytes([*program.get(4)?, *program.get(5)?]) as usize;
+    (0..table_count)
+        .map(|index| {
+            let record = 12 + index * 16;
+            let tag: [u8; 4] = program.get(record..record + 4)?.try_into().ok()?;
+            let (offset, length) = (read_u32(record + 8)?, read_u32(record + 12)?);
+            Some((tag, program.get(offset..offset + length)?))
+        })
+        .collect()
+}
+
+fn truetype_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+// Glyph indices a composite glyph is built from
+fn composite_components(glyph: &[u8]) -> Vec<u32> {
+    let mut components = Vec::new();
+    if glyph.len() < 10 || i16::from_be_bytes([glyph[0], glyph[1]]) >= 0 {
+        return components;
+    }
+    let mut at = 10;
+    while let Some(header) = glyph.get(at..at + 4) {
+        let flags = u16::from_be_bytes([header[0], header[1]]);
+        components.push(u16::from_be_bytes([header[2], header[3]]) as u32);
+        at += 4 + if flags & 0x0001 != 0 { 4 } else { 2 };
+        at += match flags {
+            f if f & 0x0008 != 0 => 2,
+            f if f & 0x0040 != 0 => 4,
+            f if f & 0x0080 != 0 => 8,
+            _ => 0,
+        };
+        if flags & 0x0020 == 0 {
+            break;
+        }
+    }
+    components
+}
+
+// Empty every glyph outside `keep` (plus .notdef and the parts of kept composites).
+// Glyph indices stay the same, so cmaps, widths and CID mappings remain valid; only
+// the 'glyf' and 'loca' tables change. None when the program can't be parsed.
+fn subset_truetype_glyphs(program: &[u8], keep: &std::collections::BTreeSet<u32>) -> Option<Vec<u8>> {
+    let tables = truetype_tables(program)?;
+    let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).map(|(_, data)| *data);
+    let (head, loca, glyf) = (table(b"head")?, table(b"loca")?, table(b"glyf")?);
+    let long_offsets = i16::from_be_bytes([*head.get(50)?, *head.get(51)?]) != 0;
+    let glyph_count = truetype_glyph_count(program)? as usize;
+
+    let offsets: Vec<usize> = (0..=glyph_count)
+        .map(|index| {
+            if long_offsets {
+                loca.get(index * 4..index * 4 + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            } else {
+                loca.get(index * 2..index * 2 + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize * 2)
+            }
+        })
+        .collect::<Option<_>>()?;
+    let glyph = |index: usize| glyf.get(offsets[index]..offsets[index + 1].max(offsets[index]));
+
+    let mut kept: std::collections::BTreeSet<u32> = keep.iter().copied().filter(|&g| (g as usize) < glyph_count).collect();
+    kept.insert(0);
+    let mut pending: Vec<u32> = kept.iter().copied().collect();
+    while let Some(index) = pending.pop() {
+        for component in composite_components(glyph(index as usize)?) {
+            if (component as usize) < glyph_count && kept.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_offsets = vec![0usize];
+    for index in 0..glyph_count {
+        if kept.contains(&(index as u32)) {
+            new_glyf.extend_from_slice(glyph(index)?);
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+        new_offsets.push(new_glyf.len());
+    }
+    let new_loca: Vec<u8> = new_offsets
+        .iter()
+        .flat_map(|&offset| {
+            if long_offsets {
+                (offset as u32).to_be_bytes().to_vec()
+            } else {
+                ((offset / 2) as u16).to_be_bytes().to_vec()
+            }
+        })
+        .collect();
+
+    // Rebuild the file with the same tables, zeroing head.checkSumAdjustment until the end
+    let mut head = head.to_vec();
+    head.get_mut(8..12)?.copy_from_slice(&[0; 4]);
+    let tables: Vec<([u8; 4], Vec<u8>)> = tables
+        .iter()
+        .map(|(tag, data)| {
+            let data = match tag {
+                b"glyf" => new_glyf.clone(),
+                b"loca" => new_loca.clone(),
+                b"head" => head.clone(),
+                _ => data.to_vec(),
+            };
+            (*tag, data)
+        })
+        .collect();
+    let mut out = program.get(..12)?.to_vec();
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, data) in &tables {
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&truetype_checksum(data).to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().div_ceil(4) * 4;
+    }
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(out.len());
+        }
+        out.extend_from_slice(data);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(truetype_checksum(&out));
+    let head_offset = head_offset?;
+    out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    Some(out)
+}
+
+// The embedded TrueType program of a Type 0 font whose codes are glyph indices
+// (Identity-H/V with an identity or stream CIDToGIDMap), with the CID to glyph map.
+// Other fonts can't be checked for which glyphs they draw.
+fn subsettable_font_program(doc: &Document, font: &lopdf::Dictionary) -> Option<(ObjectId, Option<Vec<u8>>)> {
+    let encoding = font.get(b"Encoding").and_then(Object::as_name).ok()?;
+    if font.get(b"Subtype").and_then(Object::as_name).ok()? != b"Type0"
+        || (encoding != b"Identity-H" && encoding != b"Identity-V")
+    {
+        return None;
+    }
+    let descendant = font
+        .get(b"DescendantFonts")
+        .ok()
+        .and_then(|fonts| resolve_object(doc, fonts))
+        .and_then(|fonts| fonts.as_array().ok())
+        .and_then(|fonts| fonts.first())
+        .and_then(|font| resolve_object(doc, font))
+        .and_then(|font| font.as_dict().ok())?;
+    let program = font_descriptor(doc, font)?.get(b"FontFile2").and_then(Object::as_reference).ok()?;
+    let gid_map = match descendant.get(b"CIDToGIDMap").ok().and_then(|map| resolve_object(doc, map)) {
+        Some(Object::Stream(map)) => Some(map.decompressed_content().unwrap_or_else(|_| map.content.clone())),
+        Some(Object::Name(name)) if name == b"Identity" => None,
+        None => None,
+        _ => return None,
+    };
+    Some((program, gid_map))
+}
+
+// Content streams that can draw text, with the resources they resolve fonts against:
+// pages, form XObjects (including annotation appearances) and tiling patterns. None
+// when some content depends on resources that can't be attributed (forms without
+// their own /Resources, Type 3 fonts with resources), since fonts used there can't be
+// accounted for.
+fn text_content_streams(doc: &Document) -> Option<Vec<(Vec<u8>, Option<&lopdf::Dictionary>)>> {
+    let mut streams = Vec::new();
+    for page_id in doc.get_pages().into_values() {
+        streams.push((doc.get_page_content(page_id).ok()?, page_resources(doc, page_id)));
+    }
+    for object in doc.objects.values() {
+        match object {
+            Object::Stream(stream) => {
+                let is_form = stream.dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Form");
+                let is_pattern = stream.dict.get(b"PatternType").and_then(Object::as_i64).is_ok_and(|t| t == 1);
+                if !is_form && !is_pattern {
+                    continue;
+                }
+                let resources = stream
+                    .dict
+                    .get(b"Resources")
+                    .ok()
+                    .and_then(|r| resolve_object(doc, r))
+                    .and_then(|r| r.as_dict().ok())?;
+                let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                streams.push((content, Some(resources)));
+            }
+            Object::Dictionary(dict) => {
+                let is_type3 = dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Type3");
+                if is_type3 && dict.has(b"Resources") {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(streams)
+}
+
+// A font's descriptor; for Type 0 fonts, the one of its descendant CID font
+fn font_descriptor<'a>(doc: &'a Document, font: &'a lopdf::Dictionary) -> Option<&'a lopdf::Dictionary> {
+    let font = font
+        .get(b"DescendantFonts")
+        .ok()
+        .and_then(|fonts| resolve_object(doc, fonts))
+        .and_then(|fonts| fonts.as_array().ok())
+        .and_then(|fonts| fonts.first())
+        .and_then(|font| resolve_object(doc, font))
+        .and_then(|font| font.as_dict().ok())
+        .unwrap_or(font);
+    font.get(b"FontDescriptor")
+        .ok()
+        .and_then(|descriptor| resolve_object(doc, descriptor))
+        .and_then(|descriptor| descriptor.as_dict().ok())
+}
+
+// Programs of fonts listed in the AcroForm's default resources. Viewers may draw any
+// text with these when regenerating field appearances, so they are never subsetted.
+fn form_resource_programs(doc: &Document) -> std::collections::HashSet<ObjectId> {
+    let fonts = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"DR").ok())
+        .and_then(|dr| resolve_object(doc, dr))
+        .and_then(|dr| dr.as_dict().ok())
+        .and_then(|dr| resource_category(doc, dr, b"Font"));
+    let mut programs = std::collections::HashSet::new();
+    for (_, font) in fonts.into_iter().flat_map(|fonts| fonts.iter()) {
+        let font = resolve_object(doc, font).and_then(|font| font.as_dict().ok());
+        let descriptor = font.and_then(|font| font_descriptor(doc, font));
+        for key in [&b"FontFile"[..], b"FontFile2", b"FontFile3"] {
+            if let Some(id) = descriptor.and_then(|d| d.get(key).and_then(Object::as_reference).ok()) {
+                programs.insert(id);
+            }
+        }
+    }
+    programs
+}
+
+// Six-letter subset tag derived from a subsetted program's bytes
+fn subset_tag(program: &[u8]) -> String {
+    md5(program).iter().take(6).map(|b| (b'A' + b % 26) as char).collect()
+}
+
+// Replace a subset tag (or add one) on a font name
+fn retag_font_name(name: &[u8], tag: &str) -> Vec<u8> {
+    let base = match String::from_utf8_lossy(name) {
+        name if is_subset_font_name(&name) => name[7..].to_string(),
+        name => name.into_owned(),
+    };
+    format!("{}+{}", tag, base).into_bytes()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UnsubsettedFont {
+    font: String,
+    reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FontConsolidation {
+    programs_subsetted: usize,
+    fonts_not_subsetted: Vec<UnsubsettedFont>,
+}
+
+// Why an embedded font isn't one subsettable_font_program can handle
+fn unsubsettable_font_reason(doc: &Document, font: &lopdf::Dictionary) -> &'static str {
+    let descriptor = font_descriptor(doc, font);
+    let has = |key: &[u8]| descriptor.is_some_and(|descriptor| descriptor.has(key));
+    if has(b"FontFile3") {
+        "CFF or OpenType program (FontFile3)"
+    } else if has(b"FontFile") {
+        "Type 1 program"
+    } else if font.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"TrueType") {
+        "simple TrueType font"
+    } else {
+        "codes are not glyph indices"
+    }
+}
+
+// Embedded fonts with the reason `reason` gives for keeping each one's program whole;
+// it returns None for fonts that were subsetted. CID fonts are reported through their
+// Type 0 font.
+fn unsubsetted_fonts(
+    doc: &Document,
+    reason: impl Fn(&lopdf::Dictionary, ObjectId) -> Option<&'static str>,
+) -> Vec<UnsubsettedFont> {
+    doc.objects
+        .values()
+        .filter_map(|object| {
+            let font = object.as_dict().ok()?;
+            let subtype = font.get(b"Subtype").and_then(Object::as_name).ok()?;
+            if font.get(b"Type").and_then(Object::as_name).ok()? != b"Font" || subtype.starts_with(b"CIDFontType") {
+                return None;
+            }
+            let descriptor = font_descriptor(doc, font)?;
+            let program = [&b"FontFile"[..], b"FontFile2", b"FontFile3"]
+                .into_iter()
+                .find_map(|key| descriptor.get(key).and_then(Object::as_reference).ok())?;
+            let name = font.get(b"BaseFont").and_then(Object::as_name).unwrap_or(b"");
+            Some(UnsubsettedFont {
+                font: String::from_utf8_lossy(name).into_owned(),
+                reason: reason(font, program)?.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Merge duplicate fonts, then cut embedded TrueType programs of Type 0 fonts down to
+// the glyphs the document's content draws, unioned over every font sharing a program.
+// Each subsetted program gets a tag derived from its bytes, so fonts that collided on
+// a tag after merging documents are told apart. Embedded fonts left whole are
+// reported with the reason.
+fn consolidate_document_fonts(doc: &mut Document) -> Result<FontConsolidation, String> {
+    merge_duplicate_objects(doc, false);
+
+    let form_programs = form_resource_programs(doc);
+    let streams = match text_content_streams(doc) {
+        Some(streams) => streams,
+        None => {
+            let fonts_not_subsetted = unsubsetted_fonts(doc, |_, _| Some("some content's resources can't be determined"));
+            return Ok(FontConsolidation { programs_subsetted: 0, fonts_not_subsetted });
+        }
+    };
+    let mut blocked = form_programs.clone();
+    let mut glyphs: HashMap<ObjectId, std::collections::BTreeSet<u32>> = HashMap::new();
+    for (content, resources) in &streams {
+        let fonts = load_fonts(doc, *resources);
+        let font_dicts = resources.and_then(|r| resource_category(doc, r, b"Font"));
+        let used = font_codes_in_content(content, &fonts)?;
+        for (name, font) in font_dicts.into_iter().flat_map(|fonts| fonts.iter()) {
+            let font = match resolve_object(doc, font).and_then(|font| font.as_dict().ok()) {
+                Some(font) => font,
+                None => continue,
+            };
+            match subsettable_font_program(doc, font) {
+                Some((program, gid_map)) => {
+                    let entry = glyphs.entry(program).or_default();
+                    for &cid in used.get(name).into_iter().flatten() {
+                        entry.insert(match &gid_map {
+                            Some(map) => map
+                                .get(cid as usize * 2..cid as usize * 2 + 2)
+                                .map_or(0, |b| u16::from_be_bytes([b[0], b[1]]) as u32),
+                            None => cid,
+                        });
+                    }
+                }
+                // A program shared with a font we can't analyze must stay whole
+                None => {
+                    let program = font_descriptor(doc, font)
+                        .and_then(|descriptor| descriptor.get(b"FontFile2").and_then(Object::as_reference).ok());
+                    blocked.extend(program);
+                }
+            }
+        }
+    }
+
+    let analyzed: std::collections::HashSet<ObjectId> = glyphs.keys().copied().collect();
+    let mut tags: HashMap<ObjectId, String> = HashMap::new();
+    for (program_id, keep) in glyphs {
+        if blocked.contains(&program_id) {
+            continue;
+        }
+        let (dict, bytes) = match doc.get_object(program_id) {
+            Ok(Object::Stream(stream)) => {
+                (stream.dict.clone(), stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()))
+            }
+            _ => continue,
+        };
+        let subset = match subset_truetype_glyphs(&bytes, &keep) {
+            Some(subset) if subset.len() < bytes.len() => subset,
+            _ => continue,
+        };
+        let tag = subset_tag(&subset);
+
+        let mut dict = dict;
+        for key in [&b"Filter"[..], b"DecodeParms", b"Length"] {
+            dict.remove(key);
+        }
+        dict.set("Length1", Object::Integer(subset.len() as i64));
+        let mut stream = lopdf::Stream::new(dict, subset);
+        stream.compress().map_err(|e| e.to_string())?;
+        doc.objects.insert(program_id, Object::Stream(stream));
+        tags.insert(program_id, tag);
+    }
+
+    // Retag the descriptors, CID fonts and Type 0 fonts built on the new programs
+    let descriptors: HashMap<ObjectId, String> = doc
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let program = object.as_dict().ok()?.get(b"FontFile2").and_then(Object::as_reference).ok()?;
+            Some((id, tags.get(&program)?.clone()))
+        })
+        .collect();
+    let descendants: HashMap<ObjectId, String> = doc
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let descriptor = object.as_dict().ok()?.get(b"FontDescriptor").and_then(Object::as_reference).ok()?;
+            Some((id, descriptors.get(&descriptor)?.clone()))
+        })
+        .collect();
+    let type0: HashMap<ObjectId, String> = doc
+        .objects
+        .iter()
+        .filter_map(|(&id, object)| {
+            let fonts = object.as_dict().ok()?.get(b"DescendantFonts").ok()?;
+            let first = resolve_object(doc, fonts)?.as_array().ok()?.first()?.as_reference().ok()?;
+            Some((id, descendants.get(&first)?.clone()))
+        })
+        .collect();
+    let renamed = [(&descriptors, &b"FontName"[..]), (&descendants, &b"BaseFont"[..]), (&type0, &b"BaseFont"[..])];
+    for (targets, key) in renamed {
+        for (&id, tag) in targets {
+            if let Ok(dict) = doc.get_dictionary_mut(id) {
+                if let Ok(name) = dict.get(key).and_then(Object::as_name) {
+                    let name = retag_font_name(name, tag);
+                    dict.set(key, Object::Name(name));
+                }
+                // The CIDSet would still list the glyphs that were removed
+                dict.remove(b"CIDSet");
+            }
+        }
+    }
+
+    merge_duplicate_objects(doc, false);
+    let fonts_not_subsetted = unsubsetted_fonts(doc, |font, program| {
+        if tags.contains_key(&program) {
+            None
+        } else if subsettable_font_program(doc, font).is_none() {
+            Some(unsubsettable_font_reason(doc, font))
+        } else if form_programs.contains(&program) {
+            Some("listed in the form's default resources")
+        } else if blocked.contains(&program) {
+            Some("program shared with a font that can't be subsetted")
+        } else if !analyzed.contains(&program) {
+            Some("not used by any content")
+        } else {
+            Some("subsetting would not make it smaller")
+        }
+    });
+    Ok(FontConsolidation { programs_subsetted: tags.len(), fonts_not_subsetted })
+}
+
+// Propose a logical reading order as a page permutation for set_page_order. Tagged
+// documents are ordered by where each page's content first appears in the structure
+// tree, with pages the tree never mentions kept at the end in their current order.
+// Untagged documents fall back to their page labels when every page has a distinct
+// numeric one, and otherwise to the current order.
+#[tauri::command]
+async fn detect_reading_order(state: State<'_, AppState>, path: String) -> Result<Vec<usize>, String> {
+    with_document(&state, &path, |doc| {
+        let pages = doc.get_pages();
+        let number_of: HashMap<ObjectId, usize> = pages.iter().map(|(&number, &id)| (id, number as usize)).collect();
+        let mut order: Vec<usize> = Vec::new();
+
+        for element in collect_struct_elements(doc) {
+            if let Some(&number) = element.page.and_then(|page| number_of.get(&page)) {
+                if !order.contains(&number) {
+                    order.push(number);
+                }
+            }
+        }
+        if !order.is_empty() {
+            let unmentioned: Vec<usize> = pages
+                .keys()
+                .map(|&number| number as usize)
+                .filter(|number| !order.contains(number))
+                .collect();
+            order.extend(unmentioned);
+            return Ok(order);
+        }
+
+        let labels: Option<Vec<(u64, usize)>> = pages
+            .keys()
+            .map(|&number| {
+                let label = page_label(doc, number as usize - 1)?;
+                Some((label.trim().parse::<u64>().ok()?, number as usize))
+            })
+            .collect();
+        if let Some(mut labels) = labels {
+            labels.sort();
+            if labels.windows(2).all(|pair| pair[0].0 != pair[1].0) {
+                return Ok(labels.into_iter().map(|(_, number)| number).collect());
+            }
+        }
+        Ok(pages.keys().map(|&number| number as usize).collect())
+    })
+}
+
+// Add white space around every page without scaling it: the MediaBox (and CropBox)
+// grows by the margins and content and annotations move in by the left and bottom
+// margins. Margins are for the page as displayed, so rotated pages get them on the
+// edges the reader sees.
+#[tauri::command]
+async fn add_margins(
+    state: State<'_, AppState>,
+    path: String,
+    top: f64,
+    right: f64,
+    bottom: f64,
+    left: f64,
+) -> Result<(), String> {
+    if [top, right, bottom, left].iter().any(|&margin| margin < 0.0) {
+        return Err("Margins must not be negative".to_string());
+    }
+    edit_document(&state, &path, |doc| {
+        for page_id in doc.get_pages().into_values() {
+            materialize_inherited_attributes(doc, page_id)?;
+            // Displayed edges in unrotated page space, as (left, bottom, right, top)
+            let (l, b, r, t) = match page_rotation(doc, page_id) {
+                90 => (top, left, bottom, right),
+                180 => (right, top, left, bottom),
+                270 => (bottom, right, top, left),
+                _ => (left, bottom, right, top),
+            };
+
+            let matrix = [1.0, 0.0, 0.0, 1.0, l, b];
+            let cm = format!("q 1 0 0 1 {:.4} {:.4} cm\n", l, b);
+            wrap_page_content(doc, page_id, cm.as_bytes(), b"\nQ\n")?;
+            transform_annotation_rects(doc, page_id, &matrix);
+
+            let page = doc.get_dictionary(page_id).map_err(|e| e.to_string())?;
+            let boxes: Vec<(&[u8], [f64; 4])> = [&b"MediaBox"[..], b"CropBox", b"BleedBox", b"TrimBox", b"ArtBox"]
+                .into_iter()
+                .filter_map(|key| Some((key, rect_from_object(doc, page.get(key).ok()?)?)))
+                .collect();
+            let page = doc.get_dictionary_mut(page_id).map_err(|e| e.to_string())?;
+            for (key, [x0, y0, x1, y1]) in boxes {
+                // The visible area grows; trim, bleed and art boxes keep framing the original page
+                let moved = if key == b"MediaBox" || key == b"CropBox" {
+                    [x0, y0, x1 + l + r, y1 + b + t]
+                } else {
+                    [x0 + l, y0 + b, x1 + l, y1 + b]
+                };
+                page.set(key, Object::Array(moved.iter().map(|&v| Object::Real(v as f32)).collect()));
+            }
+        }
+        Ok(())
+    })
+}
+
+// Inset between a widget's border and its text
+const FIELD_TEXT_PADDING: f64 = 2.0;
+
+// Terminal fields of the document's form, i.e. the fields that own widgets
+fn terminal_form_fields(doc: &Document) -> Vec<ObjectId> {
+    let fields = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| resolve_object(doc, fields))
+        .and_then(|fields| fields.as_array().ok());
+    let mut pending: Vec<ObjectId> = match fields {
+        Some(fields) => fields.iter().rev().filter_map(|f| f.as_reference().ok()).collect(),
+        None => return Vec::new(),
+    };
+    let mut visited = std::collections::HashSet::new();
+    let mut terminal = Vec::new();
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        if !field_widgets(doc, id).is_empty() {
+            terminal.push(id);
+            continue;
+        }
+        if let Ok(Object::Array(kids)) = doc.get_dictionary(id).and_then(|field| field.get(b"Kids")) {
+            pending.extend(kids.iter().rev().filter_map(|kid| kid.as_reference().ok()));
+        }
+    }
+    terminal
+}
+
+// Options of a choice field as (export value, display text) pairs
+fn choice_options(doc: &Document, field_id: ObjectId) -> Vec<(String, String)> {
+    let options = match inherited_field_entry(doc, field_id, b"Opt").and_then(|opt| resolve_object(doc, opt)) {
+        Some(Object::Array(options)) => options,
+        _ => return Vec::new(),
+    };
+    options
+        .iter()
+        .filter_map(|option| match resolve_object(doc, option)? {
+            Object::String(text, _) => {
+                let text = decode_pdf_string(text);
+                Some((text.clone(), text))
+            }
+            Object::Array(pair) => {
+                let export = pair.first()?.as_str().ok().map(decode_pdf_string)?;
+                let display = pair.get(1).and_then(|d| d.as_str().ok()).map(decode_pdf_string);
+                Some((export.clone(), display.unwrap_or(export)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Names of the states in a widget's normal appearance, or None when it has a single
+// appearance stream (or none at all)
+fn appearance_states(doc: &Document, widget_id: ObjectId) -> Option<Vec<Vec<u8>>> {
+    let appearance = doc.get_dictionary(widget_id).ok()?.get(b"AP").ok()?;
+    let normal = resolve_object(doc, appearance)?.as_dict().ok()?.get(b"N").ok()?;
+    let states = resolve_object(doc, normal)?.as_dict().ok()?;
+    Some(states.iter().map(|(name, _)| name.clone()).collect())
+}
+
+// Text a variable-text field shows in its widgets
+struct FieldText {
+    // Single line, paragraphs to wrap, or one line per list box option
+    lines: Vec<String>,
+    wrap: bool,
+    // List box options drawn highlighted
+    selected: Vec<usize>,
+    // Cell count of a comb field
+    comb: Option<usize>,
+    // 0 left, 1 centered, 2 right
+    quadding: i64,
+}
+
+// Operators drawing a field's text in Helvetica into a width x height widget. Without
+// a font size the text is sized to fit, the way viewers treat a 0 size in /DA.
+fn field_text_operations(
+    text: &FieldText,
+    font_size: Option<f64>,
+    color: &[f64],
+    width: f64,
+    height: f64,
+) -> Vec<lopdf::content::Operation> {
+    use lopdf::content::Operation;
+    let reals = |values: &[f64]| values.iter().map(|&v| Object::Real(v as f32)).collect::<Vec<_>>();
+    let inner_width = (width - 2.0 * FIELD_TEXT_PADDING).max(1.0);
+    let inner_height = (height - 2.0 * FIELD_TEXT_PADDING).max(1.0);
+
+    // Each entry is a line of WinAnsi text and its position
+    let mut placed: Vec<(Vec<u8>, f64, f64)> = Vec::new();
+    let mut highlights = Vec::new();
+    let size;
+    if let Some(cells) = text.comb.filter(|&cells| cells > 0) {
+        let cell = width / cells as f64;
+        size = font_size.unwrap_or((height * 0.7).min(cell * 1.4));
+        let baseline = (height - size * 0.7) / 2.0;
+        for (index, c) in text.lines.concat().chars().take(cells).enumerate() {
+            let glyph = encode_standard_font_text(&c.to_string());
+            let left = index as f64 * cell + (cell - helvetica_text_width(&glyph, size)) / 2.0;
+            placed.push((glyph, left, baseline));
+        }
+    } else {
+        let lines: Vec<Vec<u8>>;
+        if text.wrap {
+            let paragraphs = text.lines.join("\n");
+            let mut fitted = font_size.unwrap_or(12.0);
+            let mut wrapped = wrap_helvetica_text(&paragraphs, fitted, inner_width);
+            while font_size.is_none() && fitted > 4.0 && wrapped.len() as f64 * fitted * 1.15 > inner_height {
+                fitted -= 0.5;
+                wrapped = wrap_helvetica_text(&paragraphs, fitted, inner_width);
+            }
+            size = fitted;
+            lines = wrapped.iter().map(|line| encode_standard_font_text(line)).collect();
+        } else {
+            lines = text.lines.iter().map(|line| encode_standard_font_text(line)).collect();
+            size = match font_size {
+                Some(size) => size,
+                None if lines.len() == 1 => {
+                    let unit_width = helvetica_text_width(&lines[0], 1.0);
+                    let fit = if unit_width > 0.0 { inner_width / unit_width } else { f64::MAX };
+                    (inner_height * 0.7).min(fit).max(4.0)
+                }
+                None => 12.0,
+            };
+        }
+
+        let leading = size * 1.15;
+        for (index, line) in lines.into_iter().enumerate() {
+            // A single line is centered vertically, several run down from the top
+            let baseline = if text.lines.len() == 1 && !text.wrap {
+                (height - size * 0.7) / 2.0
+            } else {
+                height - FIELD_TEXT_PADDING - size * 0.9 - index as f64 * leading
+            };
+            if text.selected.contains(&index) {
+                highlights.push(baseline - size * 0.25);
+            }
+            let line_width = helvetica_text_width(&line, size);
+            let left = match text.quadding {
+                1 => (width - line_width) / 2.0,
+                2 => width - FIELD_TEXT_PADDING - line_width,
+                _ => FIELD_TEXT_PADDING,
+            };
+            placed.push((line, left, baseline));
+        }
+    }
+
+    let mut operations = vec![
+        Operation::new("BMC", vec![Object::Name(b"Tx".to_vec())]),
+        Operation::new("q", vec![]),
+        Operation::new(
+            "re",
+            reals(&[
+                FIELD_TEXT_PADDING / 2.0,
+                FIELD_TEXT_PADDING / 2.0,
+                width - FIELD_TEXT_PADDING,
+                height - FIELD_TEXT_PADDING,
+            ]),
+        ),
+        Operation::new("W", vec![]),
+        Operation::new("n", vec![]),
+    ];
+    if !highlights.is_empty() {
+        operations.push(Operation::new("rg", reals(&[0.6, 0.75, 0.86])));
+        for bottom in highlights {
+            operations.push(Operation::new("re", reals(&[FIELD_TEXT_PADDING, bottom, inner_width, size * 1.15])));
+        }
+        operations.push(Operation::new("f", vec![]));
+    }
+    operations.push(Operation::new("BT", vec![]));
+    operations.push(Operation::new("Tf", vec![Object::Name(b"Helv".to_vec()), Object::Real(size as f32)]));
+    operations.extend(annotation_color_operation(color, false));
+    for (line, left, baseline) in placed {
+        operations.push(Operation::new("Tm", reals(&[1.0, 0.0, 0.0, 1.0, left, baseline])));
+        operations.push(Operation::new("Tj", vec![Object::string_literal(line)]));
+    }
+    operations.extend(["ET", "Q", "EMC"].map(|operator| Operation::new(operator, vec![])));
+    operations
+}
+
+// Widget background and border from its /MK, drawn under the field's value
+fn widget_frame_operations(
+    doc: &Document,
+    widget_id: ObjectId,
+    width: f64,
+    height: f64,
+) -> Vec<lopdf::content::Operation> {
+    use lopdf::content::Operation;
+    let characteristics = doc
+        .get_dictionary(widget_id)
+        .ok()
+        .and_then(|widget| widget.get(b"MK").ok())
+        .and_then(|mk| resolve_object(doc, mk))
+        .and_then(|mk| mk.as_dict().ok());
+    let color = |key: &[u8]| -> Vec<f64> {
+        characteristics
+            .and_then(|mk| mk.get(key).ok())
+            .and_then(|values| resolve_object(doc, values))
+            .and_then(|values| values.as_array().ok())
+            .map(|values| values.iter().filter_map(|v| v.as_float().ok()).map(f64::from).collect())
+            .unwrap_or_default()
+    };
+    let frame = |inset: f64| {
+        let values = [inset, inset, width - 2.0 * inset, height - 2.0 * inset];
+        Operation::new("re", values.iter().map(|&v| Object::Real(v as f32)).collect())
+    };
+
+    let mut operations = Vec::new();
+    if let Some(fill) = annotation_color_operation(&color(b"BG"), false) {
+        operations.extend([fill, frame(0.0), Operation::new("f", vec![])]);
+    }
+    if let Some(stroke) = annotation_color_operation(&color(b"BC"), true) {
+        operations.extend([
+            stroke,
+            Operation::new("w", vec![Object::Real(1.0)]),
+            frame(0.5),
+            Operation::new("S", vec![]),
+        ]);
+    }
+    operations
+}
+
+// Quarter-turn rotation of a widget's contents from its /MK /R
+fn widget_rotation(doc: &Document, widget_id: ObjectId) -> i64 {
+    doc.get_dictionary(widget_id)
+        .ok()
+        .and_then(|widget| widget.get(b"MK").ok())
+        .and_then(|mk| resolve_object(doc, mk))
+        .and_then(|mk| mk.as_dict().ok())
+        .and_then(|mk| mk.get(b"R").and_then(Object::as_i64).ok())
+        .unwrap_or(0)
+        .rem_euclid(360)
+}
+
+// Form XObject over a widget's Rect, turned by its /MK /R rotation
+fn widget_appearance_stream(
+    doc: &Document,
+    widget_id: ObjectId,
+    width: f64,
+    height: f64,
+    operations: Vec<lopdf::content::Operation>,
+    resources: lopdf::Dictionary,
+) -> Result<lopdf::Stream, String> {
+    let rotation = widget_rotation(doc, widget_id);
+    let content = lopdf::content::Content { operations }.encode().map_err(|e| e.to_string())?;
+    let mut form = lopdf::Dictionary::new();
+    form.set("Type", Object::Name(b"XObject".to_vec()));
+    form.set("Subtype", Object::Name(b"Form".to_vec()));
+    form.set("BBox", number_array(&[0.0, 0.0, width, height]));
+    // Viewers fit the transformed box to the Rect, so the rotation needs no translation
+    let matrix = match rotation {
+        90 => Some([0.0, 1.0, -1.0, 0.0, 0.0, 0.0]),
+        180 => Some([-1.0, 0.0, 0.0, -1.0, 0.0, 0.0]),
+        270 => Some([0.0, -1.0, 1.0, 0.0, 0.0, 0.0]),
+        _ => None,
+    };
+    if let Some(matrix) = matrix {
+        form.set("Matrix", number_array(&matrix));
+    }
+    form.set("Resources", Object::Dictionary(resources));
+    Ok(lopdf::Stream::new(form, content))
+}
+
+// Redraw every field's widgets from its /V so values show the same in all viewers,
+// then clear /NeedAppearances. Text and choice fields get new Helvetica appearances,
+// check boxes and radio buttons switch /AS to the state matching /V (drawing a check
+// mark when the widget has no appearance for it). Push buttons and signatures are
+// left alone. Returns the number of widgets updated.
+#[tauri::command]
+async fn regenerate_form_appearances(
+    state: State<'_, AppState>,
+    path: String,
+    output_path: String,
+) -> Result<usize, String> {
+    use lopdf::content::Operation;
+    let mut doc = snapshot_document(&state, &path)?;
+    if !doc.catalog().map_err(|e| e.to_string())?.has(b"AcroForm") {
+        return Err("The document has no interactive form".to_string());
+    }
+    let form_appearance = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_object(&doc, form))
+        .and_then(|form| form.as_dict().ok())
+        .and_then(|form| form.get(b"DA").and_then(Object::as_str).ok())
+        .map(|da| da.to_vec());
+
+    let mut helvetica = None;
+    let mut zapf_dingbats = None;
+    let mut updated = 0;
+    for field_id in terminal_form_fields(&doc) {
+        let field_type =
+            inherited_field_entry(&doc, field_id, b"FT").and_then(|t| t.as_name().ok()).map(|t| t.to_vec());
+        let flags = inherited_field_entry(&doc, field_id, b"Ff").and_then(|f| f.as_i64().ok()).unwrap_or(0);
+        let value = inherited_field_entry(&doc, field_id, b"V").and_then(|v| resolve_object(&doc, v)).cloned();
+        let widgets = field_widgets(&doc, field_id);
+
+        if field_type.as_deref() == Some(&b"Btn"[..]) {
+            if flags & FIELD_FLAG_PUSHBUTTON != 0 {
+                continue;
+            }
+            let on_state =
+                value.as_ref().and_then(|v| v.as_name().ok()).filter(|&name| name != b"Off").map(|name| name.to_vec());
+            for widget_id in widgets {
+                let states = appearance_states(&doc, widget_id);
+                // Radio buttons share /V, so only the widget with that state turns on
+                let shown = match (&on_state, &states) {
+                    (Some(on), Some(states)) if states.contains(on) => on.clone(),
+                    (Some(on), None) => {
+                        let rect = doc
+                            .get_dictionary(widget_id)
+                            .ok()
+                            .and_then(|w| w.get(b"Rect").ok())
+                            .and_then(|r| rect_from_object(&doc, r));
+                        let [x0, y0, x1, y1] = match rect {
+                            Some(rect) => rect,
+                            None => continue,
+                        };
+                        let (width, height) = ((x1 - x0).abs(), (y1 - y0).abs());
+                        let font_id = *zapf_dingbats.get_or_insert_with(|| {
+                            let mut font = standard_font("ZapfDingbats");
+                            font.remove(b"Encoding");
+                            doc.add_object(font)
+                        });
+                        // "4" is the check mark in ZapfDingbats, 0.846 em wide
+                        let size = width.min(height) * 0.8;
+                        let mut check = widget_frame_operations(&doc, widget_id, width, height);
+                        let off = check.clone();
+                        check.extend([
+                            Operation::new("BT", vec![]),
+                            Operation::new("Tf", vec![Object::Name(b"ZaDb".to_vec()), Object::Real(size as f32)]),
+                            Operation::new("g", vec![Object::Real(0.0)]),
+                            Operation::new(
+                                "Td",
+                                vec![
+                                    Object::Real(((width - size * 0.846) / 2.0) as f32),
+                                    Object::Real(((height - size * 0.7) / 2.0) as f32),
+                                ],
+                            ),
+                            Operation::new("Tj", vec![Object::string_literal(b"4".to_vec())]),
+                            Operation::new("ET", vec![]),
+                        ]);
+                        let mut fonts = lopdf::Dictionary::new();
+                        fonts.set("ZaDb", Object::Reference(font_id));
+                        let mut resources = lopdf::Dictionary::new();
+                        resources.set("Font", Object::Dictionary(fonts));
+                        let on_stream = widget_appearance_stream(&doc, widget_id, width, height, check, resources)?;
+                        let off_stream =
+                            widget_appearance_stream(&doc, widget_id, width, height, off, lopdf::Dictionary::new())?;
+                        let mut normal = lopdf::Dictionary::new();
+                        normal.set(on.clone(), Object::Reference(doc.add_object(on_stream)));
+                        normal.set("Off", Object::Reference(doc.add_object(off_stream)));
+                        let mut ap = lopdf::Dictionary::new();
+                        ap.set("N", Object::Dictionary(normal));
+                        doc.get_dictionary_mut(widget_id).map_err(|e| e.to_string())?.set("AP", Object::Dictionary(ap));
+                        on.clone()
+                    }
+                    _ => b"Off".to_vec(),
+                };
+                doc.get_dictionary_mut(widget_id).map_err(|e| e.to_string())?.set("AS", Object::Name(shown));
+                updated += 1;
+            }
+            continue;
+        }
+
+        let value_text = |value: &Object| value.as_str().ok().map(decode_pdf_string);
+        let quadding = inherited_field_entry(&doc, field_id, b"Q").and_then(|q| q.as_i64().ok()).unwrap_or(0);
+        let text = match field_type.as_deref() {
+            Some(b"Tx") => {
+                if flags & FIELD_FLAG_FILE_SELECT != 0 {
+                    continue;
+                }
+                let mut shown = value.as_ref().and_then(value_text).unwrap_or_default();
+                if flags & FIELD_FLAG_PASSWORD != 0 {
+                    shown = "*".repeat(shown.chars().count());
+                }
+                let multiline = flags & FIELD_FLAG_MULTILINE != 0;
+                if !multiline {
+                    shown = shown.lines().collect::<Vec<_>>().join(" ");
+                }
+                let max_len = inherited_field_entry(&doc, field_id, b"MaxLen").and_then(|m| m.as_i64().ok());
+                let comb =
+                    max_len.filter(|_| flags & FIELD_FLAG_COMB != 0 && !multiline).map(|cells| cells.max(0) as usize);
+                FieldText { lines: vec![shown], wrap: multiline, selected: Vec::new(), comb, quadding }
+            }
+            Some(b"Ch") => {
+                let options = choice_options(&doc, field_id);
+                let chosen: Vec<String> = match &value {
+                    Some(Object::Array(values)) => values.iter().filter_map(value_text).collect(),
+                    Some(value) => value_text(value).into_iter().collect(),
+                    None => Vec::new(),
+                };
+                if flags & FIELD_FLAG_COMBO != 0 {
+                    // Combo boxes may hold a typed value that isn't one of the options
+                    let shown = chosen.first().map(|export| {
+                        options
+                            .iter()
+                            .find(|(option, _)| option == export)
+                            .map_or(export.clone(), |(_, display)| display.clone())
+                    });
+                    FieldText {
+                        lines: shown.into_iter().collect(),
+                        wrap: false,
+                        selected: Vec::new(),
+                        comb: None,
+                        quadding,
+                    }
+                } else {
+                    let selected = options
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (export, _))| chosen.contains(export))
+                        .map(|(index, _)| index)
+                        .collect();
+                    let lines = options.into_iter().map(|(_, display)| display).collect();
+                    FieldText { lines, wrap: false, selected, comb: None, quadding }
+                }
+            }
+            _ => continue,
+        };
+
+        let appearance = inherited_field_entry(&doc, field_id, b"DA")
+            .and_then(|da| da.as_str().ok())
+            .map(|da| da.to_vec())
+            .or_else(|| form_appearance.clone());
+        let (da_size, color) = parse_default_appearance(appearance.as_deref().unwrap_or(b""));
+        // parse_default_appearance falls back to 12; an explicit 0 means fit to the widget
+        let auto_size = appearance.as_ref().is_some_and(|da| String::from_utf8_lossy(da).contains(" 0 Tf"));
+        let font_size = if auto_size { None } else { Some(da_size) };
+
+        for widget_id in widgets {
+            let rect = doc
+                .get_dictionary(widget_id)
+                .ok()
+                .and_then(|w| w.get(b"Rect").ok())
+                .and_then(|r| rect_from_object(&doc, r));
+            let [x0, y0, x1, y1] = match rect {
+                Some(rect) => rect,
+                None => continue,
+            };
+            let (mut width, mut height) = ((x1 - x0).abs(), (y1 - y0).abs());
+            let rotated = widget_rotation(&doc, widget_id) % 180 == 90;
+            if rotated {
+                std::mem::swap(&mut width, &mut height);
+            }
+
+            let font_id = *helvetica.get_or_insert_with(|| doc.add_object(standard_font("Helvetica")));
+            let mut operations = widget_frame_operations(&doc, widget_id, width, height);
+            operations.extend(field_text_operations(&text, font_size, &color, width, height));
+            let mut fonts = lopdf::Dictionary::new();
+            fonts.set("Helv", Object::Reference(font_id));
+            let mut resources = lopdf::Dictionary::new();
+            resources.set("Font", Object::Dictionary(fonts));
+            let stream = widget_appearance_stream(&doc, widget_id, width, height, operations, resources)?;
+            let appearance_id = doc.add_object(stream);
+
+            let mut ap = lopdf::Dictionary::new();
+            ap.set("N", Object::Reference(appearance_id));
+            doc.get_dictionary_mut(widget_id).map_err(|e| e.to_string())?.set("AP", Object::Dictionary(ap));
+            updated += 1;
+        }
+    }
+
+    let form_id = acro_form_id(&mut doc)?;
+    doc.get_dictionary_mut(form_id).map_err(|e| e.to_string())?.remove(b"NeedAppearances");
+    save_document(&mut doc, &output_path)?;
+    Ok(updated)
+}
+
+// Split a batch scan at blank separator sheets: pages whose inked share (see
+// ink_coverage) is at most `threshold` end the current segment and are dropped.
+// Each non-empty segment is written to `output_dir` as <name>_part<N>.pdf.
+#[tauri::command]
+async fn split_on_blank_pages(
+    state: State<'_, AppState>,
+    path: String,
+    output_dir: String,
+    threshold: f64,
+) -> Result<Vec<String>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err("Threshold must be between 0 and 1".to_string());
+    }
+    let output_dir = std::path::PathBuf::from(output_dir);
+    if !output_dir.is_dir() {
+        return Err(format!("{} is not a directory", output_dir.display()));
+    }
+    let stem = std::path::Path::new(&path)
+        .file_stem()
+        .map_or("output".into(), |stem| stem.to_string_lossy())
+        .into_owned();
+
+    let mut doc = snapshot_document(&state, &path)?;
+    let mut segments: Vec<Vec<(usize, ObjectId)>> = Vec::new();
+    let mut current = Vec::new();
+    for (page_num, page_id) in doc.get_pages() {
+        // Pages without any drawing operators are blank without rendering them
+        let blank = !page_has_marks(&doc, page_id)?
+            || inked_fraction(&render_for_analysis(&doc, page_id, default_size)?) <= threshold;
+        if blank {
+            if !current.is_empty() {
+                segments.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push((page_num as usize, page_id));
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    if segments.is_empty() {
+        return Err("Every page is blank".to_string());
+    }
+
+    let mut outputs = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let mut part_doc = build_document_from_pages(&mut doc, segment, &BTreeMap::new())?;
+        let output_path = output_dir
+            .join(format!("{}_part{}.pdf", stem, index + 1))
+            .to_string_lossy()
+            .into_owned();
+        save_document(&mut part_doc, &output_path)?;
+        outputs.push(output_path);
+    }
+    Ok(outputs)
+}
+
+// Trimmed, non-empty tags with later case-insensitive repeats dropped
+fn clean_tags<'a>(tags: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    tags.map(str::trim)
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase()))
+        .map(str::to_string)
+        .collect()
+}
+
+// The /Info /Keywords string as a list of tags, split on commas and semicolons
+#[tauri::command]
+async fn get_tags(state: State<'_, AppState>, path: String) -> Result<Vec<String>, String> {
+    with_document(&state, &path, |doc| {
+        let keywords = info_string(doc, b"Keywords").unwrap_or_default();
+        Ok(clean_tags(keywords.split([',', ';'])))
+    })
+}
+
+// Replace the document's tags, stored comma-separated in /Info /Keywords and, when the
+// XMP packet already has them, as pdf:Keywords and the dc:subject bag
+#[tauri::command]
+async fn set_tags(state: State<'_, AppState>, path: String, tags: Vec<String>) -> Result<(), String> {
+    if let Some(tag) = tags.iter().find(|tag| tag.contains([',', ';'])) {
+        return Err(format!("Tag '{}' can't contain a comma or semicolon", tag.trim()));
+    }
+    let tags = clean_tags(tags.iter().map(String::as_str));
+    edit_document(&state, &path, |doc| {
+        let keywords = tags.join(", ");
+        if tags.is_empty() {
+            if let Ok(info_id) = doc.trailer.get(b"Info").and_then(Object::as_reference) {
+                if let Ok(info) = doc.get_dictionary_mut(info_id) {
+                    info.remove(b"Keywords");
+                }
+            }
+        } else {
+            set_info_string(doc, "Keywords", &keywords)?;
+        }
+
+        set_xmp_element(doc, "pdf:Keywords", &escape_xml(&keywords))?;
+        let items: String = tags.iter().map(|tag| format!("<rdf:li>{}</rdf:li>", escape_xml(tag))).collect();
+        set_xmp_element(doc, "dc:subject", &format!("<rdf:Bag>{}</rdf:Bag>", items))?;
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MergedPageCheck {
+    page_number: usize,
+    // 1-based index into the merge inputs
+    input: usize,
+    source_page: usize,
+    // 1.0 when both renderings are identical; None when either page could not be
+    // rendered, so the page is unverified
+    similarity: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MergeVerify {
+    passed: bool,
+    expected_page_count: usize,
+    merged_page_count: usize,
+    pages: Vec<MergedPageCheck>,
+    mismatches: Vec<String>,
+}
+
+// How closely a merged page renders like its source, or why the two can't be compared
+fn merged_page_similarity(
+    source: Result<image::RgbImage, String>,
+    merged: Result<image::RgbImage, String>,
+) -> Result<f64, String> {
+    match (source, merged) {
+        (Ok(a), Ok(b)) => Ok(render_similarity(&a, &b)),
+        (Ok(_), Err(e)) => Err(format!("merged page does not render: {}", e)),
+        (Err(e), _) => Err(format!("unverified, the source page does not render: {}", e)),
+    }
+}
+
+// Check a merge_pdfs output against its inputs: the merged file must hold every input
+// page once, in input order, each rendering like its source. `selections` optionally
+// lists, per input, the 1-based pages that went into the merge (None for all of them).
+// Meant for plain merges; separator pages and unify_size fitting show up as
+// mismatches, as do pages that can't be rendered and so can't be verified.
+#[tauri::command]
+async fn verify_merge(
+    state: State<'_, AppState>,
+    inputs: Vec<String>,
+    merged_path: String,
+    selections: Option<Vec<Option<Vec<usize>>>>,
+) -> Result<MergeVerify, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    if inputs.is_empty() {
+        return Err("No merge inputs to verify against".to_string());
+    }
+    let selections = selections.unwrap_or_else(|| vec![None; inputs.len()]);
+    if selections.len() != inputs.len() {
+        return Err(format!("Expected {} page selections, one per input, got {}", inputs.len(), selections.len()));
+    }
+    let merged = Document::load(&merged_path).map_err(|e| e.to_string())?;
+    let merged_pages: Vec<ObjectId> = merged.get_pages().into_values().collect();
+
+    let mut expected_page_count = 0;
+    let mut pages = Vec::new();
+    let mut mismatches = Vec::new();
+    for (index, (path, selection)) in inputs.iter().zip(&selections).enumerate() {
+        let input = Document::load(path).map_err(|e| e.to_string())?;
+        for (source_page, source_id) in select_pages(&input, selection)? {
+            expected_page_count += 1;
+            let page_number = expected_page_count;
+            let merged_id = match merged_pages.get(page_number - 1) {
+                Some(&id) => id,
+                None => {
+                    mismatches.push(format!(
+                        "page {}: missing (page {} of input {})",
+                        page_number,
+                        source_page,
+                        index + 1
+                    ));
+                    continue;
+                }
+            };
+
+            let source_render = render_for_analysis(&input, source_id, default_size);
+            let merged_render = render_for_analysis(&merged, merged_id, default_size);
+            let similarity = match merged_page_similarity(source_render, merged_render) {
+                Ok(similarity) => Some(similarity),
+                Err(reason) => {
+                    mismatches.push(format!(
+                        "page {}: {} (page {} of input {})",
+                        page_number,
+                        reason,
+                        source_page,
+                        index + 1
+                    ));
+                    None
+                }
+            };
+            if let Some(similarity) = similarity.filter(|&similarity| similarity < MIN_RENDER_SIMILARITY) {
+                mismatches.push(format!(
+                    "page {}: differs from page {} of input {} ({:.1}% similar)",
+                    page_number,
+                    source_page,
+                    index + 1,
+                    similarity * 100.0
+                ));
+            }
+            pages.push(MergedPageCheck { page_number, input: index + 1, source_page, similarity });
+        }
+    }
+    for page_number in expected_page_count + 1..=merged_pages.len() {
+        mismatches.push(format!("page {}: not from any input", page_number));
+    }
+
+    Ok(MergeVerify {
+        passed: mismatches.is_empty(),
+        expected_page_count,
+        merged_page_count: merged_pages.len(),
+        pages,
+        mismatches,
+    })
+}
+
+// Exact visual fingerprint of each page: the SHA-256 of its rendering at `dpi`, pixel
+// dimensions included. Unlike page_hashes, pages drawn with different operators but the
+// same result hash alike, and resource changes that alter the output don't.
+#[tauri::command]
+async fn render_hashes(state: State<'_, AppState>, path: String, dpi: u32) -> Result<Vec<String>, String> {
+    let default_size = state.settings.lock().map_err(|e| e.to_string())?.default_page_size;
+    if dpi == 0 || dpi > MAX_BATCH_IMAGE_DPI {
+        return Err(format!("DPI must be between 1 and {}", MAX_BATCH_IMAGE_DPI));
+    }
+    with_document(&state, &path, |doc| {
+        let mut hashes = Vec::new();
+        for page_id in doc.get_pages().into_values() {
+            let image = render_page(doc, page_id, dpi as f64 / 72.0, None, default_size)?;
+            let mut bytes = format!("{}x{}:", image.width(), image.height()).into_bytes();
+            bytes.extend_from_slice(image.as_raw());
+            hashes.push(hex_string(&sha256(&bytes)));
+        }
+        Ok(hashes)
+    })
+}
+
+fn main() {
+    tauri::Builder::default()
+        .manage(AppState::default())
+        .setup(|app| {
+            #[cfg(debug_assertions)]
+            {
+                let window = app.get_webview_window("main").unwrap();
+                window.open_devtools();
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             load_pdf,
+            get_page_thumbnail,
+            set_render_timeout,
+            set_default_page_size,
             save_pdf,
-            merge_pdfs
+            merge_pdfs,
+            swap_pages,
+            undo,
+            redo,
+            get_cache_stats,
+            unload_all,
+            replace_page_content,
+            get_accessibility_info,
+            set_image_alt,
+            linearize,
+            replace_text,
+            remove_all_annotations,
+            convert_to_rgb,
+            analyze_text,
+            reorder_for_simplex_duplex,
+            extract_table,
+            embed_thumbnails,
+            get_page_count,
+            add_image_watermark,
+            revert,
+            get_viewer_preferences,
+            set_viewer_preferences,
+            set_page_transition,
+            flatten_transparency,
+            rebuild_page_tree,
+            classify_pages,
+            garbage_collect,
+            get_text_layout,
+            extract_page_bytes,
+            set_display_title,
+            diff_metadata,
+            dump_object,
+            dump_trailer,
+            dump_catalog,
+            unify_page_size,
+            check_font_coverage,
+            add_cover_page,
+            get_output_intent,
+            set_output_intent,
+            set_readonly,
+            get_pdfa_info,
+            redact_text_matches,
+            set_page_mode,
+            set_page_layout,
+            get_page_layout,
+            generate_toc_page,
+            strip_metadata,
+            get_page_labels,
+            set_start_page_number,
+            detect_inverted_pages,
+            invert_pages,
+            export_annotations,
+            import_annotations,
+            rotate_pages_relative,
+            ink_coverage,
+            set_overprint_black,
+            auto_crop,
+            get_capabilities,
+            deskew,
+            set_document_language,
+            get_document_language,
+            verify_save,
+            #[cfg(feature = "bundled-fonts")]
+            embed_standard_fonts,
+            get_named_destinations,
+            set_permissions_only,
+            get_permissions,
+            regenerate_appearances,
+            dedup_resources,
+            add_printer_marks,
+            split_spreads,
+            get_document_stats,
+            clear_rotation,
+            set_page_order,
+            export_page_svg,
+            audit_security,
+            batch_watermark,
+            get_document_id,
+            regenerate_document_id,
+            verify_signatures,
+            add_signature_appearance,
+            delete_pages_matching,
+            page_hashes,
+            set_field_default_appearance,
+            get_content_stream,
+            set_display_rotation,
+            bake_rotation,
+            repair_image_colorspaces,
+            save_under_size,
+            resource_usage,
+            crop_to_aspect,
+            set_field_comb,
+            combine_side_by_side,
+            add_named_view,
+            list_named_views,
+            goto_named_view,
+            make_diff_pdf,
+            batch_to_images,
+            add_line_annotation,
+            add_shape_annotation,
+            save_incremental,
+            get_revision_count,
+            list_external_uris,
+            get_page_boxes,
+            set_user_unit,
+            flatten_annotations,
+            side_by_side_compare,
+            get_dominant_page_size,
+            set_page_opacity,
+            export_structure,
+            add_page_background,
+            split_by_size,
+            list_layers,
+            set_layer_visibility,
+            flatten_layers,
+            fix_missing_types,
+            set_document_preview,
+            set_page_durations,
+            get_page_durations,
+            start_job,
+            get_job_status,
+            normalize_origin,
+            scan_active_content,
+            detect_reading_order,
+            add_margins,
+            regenerate_form_appearances,
+            split_on_blank_pages,
+            get_tags,
+            set_tags,
+            verify_merge,
+            render_hashes
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+    use tauri::test::MockRuntime;
+
+    fn test_app() -> tauri::App<MockRuntime> {
+        let app = tauri::test::mock_app();
+        app.manage(AppState::default());
+        app
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tauri::async_runtime::block_on(future)
+    }
+
+    // Scratch file path, unique per test so tests can run in parallel
+    fn scratch_path(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("pdf-editor-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn save_fixture(mut doc: Document, name: &str) -> String {
+        let path = scratch_path(name);
+        doc.save(&path).unwrap();
+        path
+    }
+
+    fn page_widths(doc: &Document) -> Vec<f64> {
+        doc.get_pages()
+            .into_values()
+            .map(|id| page_visible_box(doc, id).map_or(0.0, |[x0, _, x1, _]| x1 - x0))
+            .collect()
+    }
+
+    #[test]
+    fn swap_pages_exchanges_first_and_last() {
+        // Pages are told apart by width: 101pt, 102pt, ... 105pt
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> = (1..=5)
+            .map(|n| {
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), (100 + n).into(), 200.into()],
+                };
+                doc.add_object(page).into()
+            })
+            .collect();
+        let pages = dictionary! { "Type" => "Pages", "Count" => 5, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "swap.pdf");
+
+        let app = test_app();
+        run(swap_pages(app.state(), path.clone(), 1, 5)).unwrap();
+        let widths = with_document(&app.state::<AppState>(), &path, |doc| Ok(page_widths(doc))).unwrap();
+        assert_eq!(widths, vec![105.0, 102.0, 103.0, 104.0, 101.0]);
+
+        // One undo step restores the original order
+        assert!(run(undo(app.state(), path.clone())).unwrap());
+        let widths = with_document(&app.state::<AppState>(), &path, |doc| Ok(page_widths(doc))).unwrap();
+        assert_eq!(widths, vec![101.0, 102.0, 103.0, 104.0, 105.0]);
+
+        assert!(run(swap_pages(app.state(), path.clone(), 1, 6)).is_err());
+        run(swap_pages(app.state(), path.clone(), 3, 3)).unwrap();
+        assert!(run(swap_pages(app.state(), path.clone(), 7, 7)).is_err());
+        // Neither the rejected swaps nor the no-op one left an undo entry
+        assert!(!run(undo(app.state(), path.clone())).unwrap());
+    }
+
+    #[test]
+    fn save_pdf_keeps_annotations_when_reordering() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let first_id = doc.new_object_id();
+        let second_id = doc.new_object_id();
+
+        let form = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 80.into(), 12.into()],
+        };
+        let appearance = lopdf::Stream::new(form, b"1 1 0 rg 0 0 80 12 re f".to_vec());
+        let appearance_id = doc.add_object(appearance);
+        let highlight_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Highlight",
+            "Rect" => vec![50.into(), 700.into(), 130.into(), 712.into()],
+            "AP" => dictionary! { "N" => appearance_id },
+        });
+        // Link on page 1 jumping to page 2
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![50.into(), 50.into(), 100.into(), 60.into()],
+            "Dest" => vec![second_id.into(), "Fit".into()],
+        });
+        for (id, annot) in [(first_id, link_id), (second_id, highlight_id)] {
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Annots" => vec![annot.into()],
+            };
+            doc.objects.insert(id, Object::Dictionary(page));
+        }
+        let kids = vec![first_id.into(), second_id.into()];
+        let pages = dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "annotated.pdf");
+
+        let app = test_app();
+        let output = scratch_path("annotated-reordered.pdf");
+        let order = vec![2, 1];
+        run(save_pdf(app.state(), path.clone(), output.clone(), order, BTreeMap::new(), Vec::new(), None, None, None))
+            .unwrap();
+
+        let saved = Document::load(&output).unwrap();
+        let pages: Vec<ObjectId> = saved.get_pages().into_values().collect();
+        let annotation = |page: ObjectId| {
+            let annots = saved.get_dictionary(page).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+            saved.get_dictionary(annots[0].as_reference().unwrap()).unwrap()
+        };
+
+        let highlight = annotation(pages[0]);
+        assert_eq!(highlight.get(b"Subtype").unwrap().as_name().unwrap(), b"Highlight");
+        let normal = highlight.get(b"AP").unwrap().as_dict().unwrap().get(b"N").unwrap().as_reference().unwrap();
+        assert!(saved.get_object(normal).unwrap().as_stream().is_ok());
+
+        // The link now points at the highlight's page, which moved to the front
+        let link = annotation(pages[1]);
+        let dest = link.get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(dest[0].as_reference().unwrap(), pages[0]);
+
+        // Deleting the target page drops the link pointing at it
+        let output = scratch_path("annotated-deleted.pdf");
+        run(save_pdf(app.state(), path, output.clone(), vec![1, 2], BTreeMap::new(), vec![2], None, None, None))
+            .unwrap();
+        let saved = Document::load(&output).unwrap();
+        let page = saved.get_dictionary(saved.page_iter().next().unwrap()).unwrap();
+        let annots = page.get(b"Annots").ok().and_then(|annots| annots.as_array().ok());
+        assert!(annots.is_none_or(|annots| annots.is_empty()));
+    }
+
+    #[test]
+    fn cache_stats_follow_loading_and_unloading() {
+        // One page carrying a 256 KiB content stream of spaces
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), vec![b' '; 256 * 1024]));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 300.into(), 300.into()],
+            "Contents" => content_id,
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "large.pdf");
+
+        let app = test_app();
+        let empty = run(get_cache_stats(app.state())).unwrap();
+        assert_eq!((empty.open_documents, empty.estimated_bytes), (0, 0));
+
+        with_document(&app.state::<AppState>(), &path, |_| Ok(())).unwrap();
+        let loaded = run(get_cache_stats(app.state())).unwrap();
+        assert_eq!(loaded.open_documents, 1);
+        assert!(loaded.estimated_bytes >= 256 * 1024);
+
+        run(unload_all(app.state())).unwrap();
+        let unloaded = run(get_cache_stats(app.state())).unwrap();
+        assert_eq!((unloaded.open_documents, unloaded.estimated_bytes), (0, 0));
+    }
+
+    #[test]
+    fn replace_page_content_swaps_text_and_keeps_resources() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let content = lopdf::Stream::new(lopdf::Dictionary::new(), b"BT /F1 12 Tf 72 720 Td (Draft) Tj ET".to_vec());
+        let content_id = doc.add_object(content);
+        let template_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "Contents" => content_id,
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![template_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "template.pdf");
+
+        let app = test_app();
+        let operators = "BT /F1 18 Tf 72 700 Td (Final copy) Tj ET".to_string();
+        run(replace_page_content(app.state(), path.clone(), 1, operators)).unwrap();
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let text = extract_page_text(doc, 1)?;
+            assert!(text.contains("Final copy") && !text.contains("Draft"), "{}", text);
+            assert!(page_resources(doc, template_id).is_some_and(|resources| resources.has(b"Font")));
+            Ok(())
+        })
+        .unwrap();
+
+        // Content that doesn't tokenize to the end is rejected before the page is touched
+        for invalid in ["BT (open", "BT ] ET", "q 1 0 0 1 0 0"] {
+            assert!(
+                run(replace_page_content(app.state(), path.clone(), 1, invalid.to_string())).is_err(),
+                "{}",
+                invalid
+            );
+        }
+        let text = with_document(&app.state::<AppState>(), &path, |doc| extract_page_text(doc, 1)).unwrap();
+        assert!(text.contains("Final copy"));
+        assert!(run(replace_page_content(app.state(), path, 2, "0 0 m".to_string())).is_err());
+    }
+
+    #[test]
+    fn accessibility_info_tells_tagged_from_untagged() {
+        let build = |tagged: bool| {
+            let mut doc = Document::with_version("1.7");
+            let pages_id = doc.new_object_id();
+            let page_id = doc.new_object_id();
+            let image = lopdf::Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => 2,
+                    "Height" => 2,
+                    "ColorSpace" => "DeviceGray",
+                    "BitsPerComponent" => 8,
+                },
+                vec![0, 255, 255, 0],
+            );
+            let image_id = doc.add_object(image);
+            let content_id = doc.add_object(lopdf::Stream::new(
+                lopdf::Dictionary::new(),
+                b"/Figure <</MCID 0>> BDC q 100 0 0 100 50 50 cm /Im0 Do Q EMC".to_vec(),
+            ));
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 200.into(), 200.into()],
+                "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+                "Contents" => content_id,
+            };
+            doc.objects.insert(page_id, Object::Dictionary(page));
+            let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+            doc.objects.insert(pages_id, Object::Dictionary(pages));
+            let mut catalog = dictionary! { "Type" => "Catalog", "Pages" => pages_id };
+            if tagged {
+                let root_id = doc.new_object_id();
+                let figure_id = doc.add_object(dictionary! {
+                    "Type" => "StructElem",
+                    "S" => "Figure",
+                    "P" => root_id,
+                    "Pg" => page_id,
+                    "K" => 0,
+                    "Alt" => Object::string_literal("A checkerboard"),
+                });
+                let root = dictionary! { "Type" => "StructTreeRoot", "K" => vec![figure_id.into()] };
+                doc.objects.insert(root_id, Object::Dictionary(root));
+                catalog.set("StructTreeRoot", root_id);
+                catalog.set("MarkInfo", dictionary! { "Marked" => true });
+                catalog.set("Lang", Object::string_literal("en-US"));
+                let info_id = doc.add_object(dictionary! { "Title" => Object::string_literal("Checkers") });
+                doc.trailer.set("Info", info_id);
+            }
+            let catalog_id = doc.add_object(catalog);
+            doc.trailer.set("Root", catalog_id);
+            doc
+        };
+
+        let app = test_app();
+        let tagged = run(get_accessibility_info(app.state(), save_fixture(build(true), "tagged.pdf"))).unwrap();
+        assert!(tagged.is_tagged && tagged.has_lang && tagged.has_title);
+        assert!(tagged.images_missing_alt.is_empty());
+
+        let untagged = run(get_accessibility_info(app.state(), save_fixture(build(false), "untagged.pdf"))).unwrap();
+        assert!(!untagged.is_tagged && !untagged.has_lang && !untagged.has_title);
+        assert_eq!(untagged.images_missing_alt, vec![1]);
+    }
+
+    #[test]
+    fn set_image_alt_clears_missing_alt_and_survives_save() {
+        // Untagged page with a red and a blue logo side by side
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let mut logos = lopdf::Dictionary::new();
+        for (name, rgb) in [("Red", vec![200, 30, 30]), ("Blue", vec![30, 30, 200])] {
+            let logo = dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 1,
+                "Height" => 1,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+            };
+            logos.set(name, doc.add_object(lopdf::Stream::new(logo, rgb)));
+        }
+        let content = b"q 40 0 0 40 20 20 cm /Red Do Q q 40 0 0 40 120 20 cm /Blue Do Q".to_vec();
+        let content_id = doc.add_object(lopdf::Stream::new(lopdf::Dictionary::new(), content));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 180.into(), 80.into()],
+            "Resources" => dictionary! { "XObject" => logos },
+            "Contents" => content_id,
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "logos.pdf");
+
+        let app = test_app();
+        assert_eq!(run(get_accessibility_info(app.state(), path.clone())).unwrap().images_missing_alt, vec![1]);
+
+        // The first call has to create a structure tree and says so
+        let warning = run(set_image_alt(app.state(), path.clone(), 1, 1, "Logo, right".to_string())).unwrap();
+        assert!(warning.is_some());
+        // Only one of the two images is described so far
+        assert_eq!(run(get_accessibility_info(app.state(), path.clone())).unwrap().images_missing_alt, vec![1]);
+        let warning = run(set_image_alt(app.state(), path.clone(), 1, 0, "Logo, left".to_string())).unwrap();
+        assert!(warning.is_none());
+        assert!(run(get_accessibility_info(app.state(), path.clone())).unwrap().images_missing_alt.is_empty());
+        assert!(run(set_image_alt(app.state(), path.clone(), 1, 2, "None".to_string())).is_err());
+
+        let output = scratch_path("logos-described.pdf");
+        run(save_pdf(app.state(), path, output.clone(), vec![1], BTreeMap::new(), Vec::new(), None, None, None))
+            .unwrap();
+        assert!(run(get_accessibility_info(app.state(), output)).unwrap().images_missing_alt.is_empty());
+    }
+
+    #[test]
+    fn linearize_writes_the_linearization_dictionary_first() {
+        // Three pages, each drawing its own numbered line of text with a shared font
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let mut kids = Vec::new();
+        for n in 1..=3 {
+            let text = format!("BT /F1 24 Tf 72 720 Td (Page {}) Tj ET", n);
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, text.into_bytes()));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Contents" => content_id,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            });
+            kids.push(page_id.into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 3, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "three-pages.pdf");
+
+        let app = test_app();
+        let output = scratch_path("three-pages-linearized.pdf");
+        run(linearize(app.state(), path.clone(), output.clone())).unwrap();
+
+        // The first object after the header is the linearization dictionary
+        let bytes = std::fs::read(&output).unwrap();
+        let first_object = bytes.windows(3).position(|window| window == b"obj").unwrap();
+        let first_end = bytes.windows(6).position(|window| window == b"endobj").unwrap();
+        assert!(bytes[..first_object].starts_with(b"%PDF-1.6"));
+        assert!(String::from_utf8_lossy(&bytes[first_object..first_end]).contains("/Linearized"));
+
+        let saved = Document::load(&output).unwrap();
+        let lin = saved.get_dictionary((1, 0)).unwrap();
+        assert_eq!(lin.get(b"N").unwrap().as_i64().unwrap(), 3);
+        assert_eq!(lin.get(b"L").unwrap().as_i64().unwrap(), bytes.len() as i64);
+        let first_page = saved.get_pages()[&1];
+        assert_eq!(lin.get(b"O").unwrap().as_i64().unwrap(), first_page.0 as i64);
+        let texts: Vec<String> = (1..=3).map(|n| saved.extract_text(&[n]).unwrap()).collect();
+        assert!(texts.iter().zip(1..=3).all(|(text, n)| text.contains(&format!("Page {}", n))));
+
+        // Renumbering would break per-object encryption keys
+        edit_document(&app.state::<AppState>(), &path, |doc| {
+            let encrypt_id = doc.add_object(dictionary! { "Filter" => "Standard", "V" => 1, "R" => 2 });
+            doc.trailer.set("Encrypt", encrypt_id);
+            Ok(())
+        })
+        .unwrap();
+        let err = run(linearize(app.state(), path, scratch_path("encrypted-linearized.pdf"))).unwrap_err();
+        assert_eq!(err, "Document is encrypted");
+    }
+
+    #[test]
+    fn replace_text_fixes_a_typo_inside_one_show_operator() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let plain_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        // Remapped codes mean the string bytes no longer spell the visible word
+        let remapped_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Times-Roman",
+            "Encoding" => dictionary! { "Differences" => vec![119.into(), "x".into()] },
+        });
+        let text = b"BT /F1 12 Tf 72 700 Td (Hello wrold) Tj ET \
+            q BT /F2 12 Tf 72 690 Td (wrold) Tj ET Q \
+            BT 72 680 Td (wrold again) Tj ET \
+            BT /F1 12 Tf 72 670 Td [(wr) 10 (old)] TJ ET";
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, text.to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => plain_id, "F2" => remapped_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "typo.pdf");
+
+        // After Q the text is back in F1, so its last line is edited too
+        let app = test_app();
+        let result = run(replace_text(app.state(), path.clone(), "wrold".into(), "world".into(), None)).unwrap();
+        assert_eq!((result.replaced, result.skipped), (2, 2));
+        let (text, shows) = with_document(&app.state::<AppState>(), &path, |doc| {
+            let page_id = doc.get_pages()[&1];
+            let content = lopdf::content::Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+            let shows: Vec<Vec<u8>> = content
+                .operations
+                .iter()
+                .filter(|operation| operation.operator == "Tj")
+                .map(|operation| operation.operands[0].as_str().unwrap().to_vec())
+                .collect();
+            Ok((doc.extract_text(&[1]).unwrap(), shows))
+        })
+        .unwrap();
+        assert!(text.contains("Hello world"));
+        assert!(text.contains("world again"));
+        assert_eq!(shows[1], b"wrold");
+
+        // The matches left are in the remapped font and split across TJ elements
+        let result = run(replace_text(app.state(), path.clone(), "wrold".into(), "world".into(), None)).unwrap();
+        assert_eq!((result.replaced, result.skipped), (0, 2));
+        let err = run(replace_text(app.state(), path, "\u{20ac}uro".into(), "Euro".into(), None)).unwrap_err();
+        assert_eq!(err, "Search text contains characters outside Latin-1");
+    }
+
+    #[test]
+    fn remove_all_annotations_filters_by_subtype_and_prunes_the_form() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.new_object_id();
+        let appearance = lopdf::Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 60.into(), 10.into()] },
+            b"1 1 0 rg 0 0 60 10 re f".to_vec(),
+        );
+        let appearance_id = doc.add_object(appearance);
+        let highlight_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Highlight",
+            "Rect" => vec![72.into(), 700.into(), 132.into(), 710.into()],
+            "AP" => dictionary! { "N" => appearance_id },
+        });
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![72.into(), 680.into(), 132.into(), 690.into()],
+            "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal("https://example.com") },
+        });
+        // A "name" field whose only widget is its kid
+        let field_id = doc.new_object_id();
+        let widget_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "Rect" => vec![72.into(), 600.into(), 272.into(), 620.into()],
+            "Parent" => field_id,
+            "P" => page_id,
+        });
+        let field =
+            dictionary! { "FT" => "Tx", "T" => Object::string_literal("name"), "Kids" => vec![widget_id.into()] };
+        doc.objects.insert(field_id, Object::Dictionary(field));
+        let page = dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Annots" => vec![highlight_id.into(), link_id.into(), widget_id.into()],
+        };
+        doc.objects.insert(page_id, Object::Dictionary(page));
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "AcroForm" => dictionary! { "Fields" => vec![field_id.into()] },
+        };
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "marked-up-form.pdf");
+
+        let app = test_app();
+        let subtypes = |doc: &Document| -> Vec<Vec<u8>> {
+            let page_id = doc.get_pages()[&1];
+            page_annotations(doc, page_id).iter().filter_map(|annot| annotation_subtype(doc, annot)).collect()
+        };
+
+        // Only highlights go, and the appearance stream nothing else uses goes with them
+        let removed = run(remove_all_annotations(app.state(), path.clone(), Some(vec!["Highlight".into()]))).unwrap();
+        assert_eq!(removed, 1);
+        let (remaining, appearance_gone) = with_document(&app.state::<AppState>(), &path, |doc| {
+            Ok((subtypes(doc), !doc.objects.contains_key(&appearance_id)))
+        })
+        .unwrap();
+        assert_eq!(remaining, vec![b"Link".to_vec(), b"Widget".to_vec()]);
+        assert!(appearance_gone);
+
+        // Without a filter, form widgets are left alone
+        assert_eq!(run(remove_all_annotations(app.state(), path.clone(), None)).unwrap(), 1);
+        let remaining = with_document(&app.state::<AppState>(), &path, |doc| Ok(subtypes(doc))).unwrap();
+        assert_eq!(remaining, vec![b"Widget".to_vec()]);
+
+        // Removing the widget empties its parent field, which leaves the inline AcroForm
+        assert_eq!(run(remove_all_annotations(app.state(), path.clone(), Some(vec!["Widget".into()]))).unwrap(), 1);
+        let (fields, field_gone) = with_document(&app.state::<AppState>(), &path, |doc| {
+            let form = doc.catalog().unwrap().get(b"AcroForm").unwrap().as_dict().unwrap();
+            Ok((form.get(b"Fields").unwrap().as_array().unwrap().len(), !doc.objects.contains_key(&field_id)))
+        })
+        .unwrap();
+        assert_eq!(fields, 0);
+        assert!(field_gone);
+    }
+
+    #[test]
+    fn convert_to_rgb_turns_cmyk_images_and_colors_into_rgb() {
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        // Two pixels: pure magenta+yellow (red) and pure black
+        let pixels = vec![0, 255, 255, 0, 0, 0, 0, 255];
+        let image = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => 2,
+            "Height" => 1,
+            "ColorSpace" => "DeviceCMYK",
+            "BitsPerComponent" => 8,
+        };
+        let image_id = doc.add_object(lopdf::Stream::new(image, pixels));
+        let profile_id = doc.add_object(lopdf::Stream::new(dictionary! { "N" => 4 }, Vec::new()));
+        // A form drawing with a named ICC-based CMYK space
+        let form = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "Resources" => dictionary! {
+                "ColorSpace" => dictionary! { "CS0" => vec!["ICCBased".into(), profile_id.into()] },
+            },
+        };
+        let form_id = doc.add_object(lopdf::Stream::new(form, b"/CS0 cs 0 1 1 0 sc 0 0 10 10 re f".to_vec()));
+        let content = b"0 0 0 1 K q 20 0 0 10 0 0 cm /Im1 Do Q /Fm1 Do".to_vec();
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Im1" => image_id, "Fm1" => form_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let output_intent =
+            dictionary! { "Type" => "OutputIntent", "S" => "GTS_PDFX", "DestOutputProfile" => profile_id };
+        let catalog =
+            dictionary! { "Type" => "Catalog", "Pages" => pages_id, "OutputIntents" => vec![output_intent.into()] };
+        let catalog_id = doc.add_object(catalog);
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "print.pdf");
+
+        let app = test_app();
+        let output = scratch_path("print-rgb.pdf");
+        run(convert_to_rgb(app.state(), path, output.clone(), None, None)).unwrap();
+
+        let saved = Document::load(&output).unwrap();
+        let image = saved.get_object(image_id).unwrap().as_stream().unwrap();
+        assert_eq!(image.dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceRGB");
+        let decoded = decode_image_xobject(&saved, image).unwrap();
+        assert_eq!(decoded.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(decoded.get_pixel(1, 0).0, [0, 0, 0]);
+        assert!(saved.catalog().unwrap().get(b"OutputIntents").is_err());
+
+        let operations = |bytes: Vec<u8>| -> Vec<(String, Vec<f32>)> {
+            let content = lopdf::content::Content::decode(&bytes).unwrap();
+            let numbers = |operands: &[Object]| operands.iter().filter_map(|o| o.as_float().ok()).collect();
+            content.operations.iter().map(|op| (op.operator.clone(), numbers(&op.operands))).collect()
+        };
+        let page = operations(saved.get_page_content(saved.get_pages()[&1]).unwrap());
+        assert_eq!(page[0], ("RG".to_string(), vec![0.0, 0.0, 0.0]));
+
+        let form = saved.get_object(form_id).unwrap().as_stream().unwrap();
+        let form_bytes = form.decompressed_content().unwrap_or_else(|_| form.content.clone());
+        let form_content = lopdf::content::Content::decode(&form_bytes).unwrap();
+        assert_eq!(form_content.operations[0].operands[0].as_name().unwrap(), b"DeviceRGB");
+        let form = operations(form_bytes);
+        assert_eq!(form[1], ("sc".to_string(), vec![1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn analyze_text_counts_words_and_reports_english() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let lines = [
+            "The quarterly report shows that sales grew steadily across every region this year,",
+            "while the costs of shipping and storage were kept lower than the team had expected.",
+        ];
+        let mut text = String::from("BT /F1 11 Tf 72 720 Td 14 TL");
+        for line in lines {
+            text.push_str(&format!(" ({}) '", line));
+        }
+        text.push_str(" ET");
+        let english_id = doc.add_object(lopdf::Stream::new(dictionary! {}, text.into_bytes()));
+        let blank_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 1 rg 72 72 100 100 re f".to_vec()));
+        let kids: Vec<Object> = [english_id, blank_id]
+            .into_iter()
+            .map(|content_id| {
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                    "Contents" => content_id,
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                };
+                doc.add_object(page).into()
+            })
+            .collect();
+        let pages = dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "report-text.pdf");
+
+        let app = test_app();
+        let stats = run(analyze_text(app.state(), path)).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].page_number, 1);
+        assert_eq!(stats[0].word_count, 28);
+        let letters: usize = lines.iter().map(|line| line.chars().filter(|c| !c.is_whitespace()).count()).sum();
+        assert_eq!(stats[0].char_count, letters);
+        let expected = if cfg!(feature = "language-detection") { Some("en".to_string()) } else { None };
+        assert_eq!(stats[0].language, expected);
+
+        // A page with only graphics has nothing to count or classify
+        assert_eq!((stats[1].char_count, stats[1].word_count, stats[1].language.clone()), (0, 0, None));
+    }
+
+    #[test]
+    fn simplex_duplex_puts_odd_pages_first_and_pads_with_a_blank() {
+        // Each page fills a rectangle as wide as its number, so every page has content
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let mut kids = Vec::new();
+        for n in 1..=5 {
+            let content = format!("0 0 {} 10 re f", n * 10).into_bytes();
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 400.into(), 600.into()],
+                "Contents" => content_id,
+            };
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 5, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "five-pages.pdf");
+
+        // Page numbers read back from the rectangle widths; 0 marks the blank padding page
+        let order = |output: &str| -> Vec<i64> {
+            let saved = Document::load(output).unwrap();
+            saved
+                .get_pages()
+                .into_values()
+                .map(|page_id| {
+                    assert_eq!(page_visible_box(&saved, page_id), Some([0.0, 0.0, 400.0, 600.0]));
+                    let content = lopdf::content::Content::decode(&saved.get_page_content(page_id).unwrap()).unwrap();
+                    content.operations.first().map_or(0, |op| op.operands[2].as_i64().unwrap() / 10)
+                })
+                .collect()
+        };
+
+        let app = test_app();
+        let output = scratch_path("five-pages-duplex.pdf");
+        run(reorder_for_simplex_duplex(app.state(), path.clone(), output.clone(), true)).unwrap();
+        assert_eq!(order(&output), vec![1, 3, 5, 0, 4, 2]);
+
+        run(reorder_for_simplex_duplex(app.state(), path, output.clone(), false)).unwrap();
+        assert_eq!(order(&output), vec![1, 3, 5, 2, 4, 0]);
+    }
+
+    #[test]
+    fn extract_table_writes_a_three_by_three_grid() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        // Ruled grid with one cell per Tj, written column by column to check the row grouping
+        let rows = [["Item", "Qty", "Price"], ["Apples", "3", "1.20"], ["Pears, ripe", "12", "0.80"]];
+        let mut content = String::from("0.5 w 70 640 m 400 640 l 70 660 m 400 660 l 70 680 m 400 680 l S ");
+        for (column, x) in [72, 200, 320].into_iter().enumerate() {
+            for (row, y) in [684, 664, 644].into_iter().enumerate() {
+                content.push_str(&format!("BT /F1 10 Tf {} {} Td ({}) Tj ET ", x, y, rows[row][column]));
+            }
+        }
+        // A footer outside the region is left out
+        content.push_str("BT /F1 8 Tf 72 100 Td (Page 1 of 1) Tj ET");
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "prices.pdf");
+
+        let app = test_app();
+        let output = scratch_path("prices.csv");
+        run(extract_table(app.state(), path, 1, Some([60.0, 600.0, 450.0, 700.0]), output.clone())).unwrap();
+
+        let csv = std::fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines, vec!["Item,Qty,Price", "Apples,3,1.20", "\"Pears, ripe\",12,0.80"]);
+    }
+
+    #[test]
+    fn load_pdf_falls_back_to_the_placeholder_for_a_page_that_renders_too_long() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let simple = b"0 0 1 rg 100 100 200 200 re f".to_vec();
+        // Tens of thousands of full-page fills in alternating colors
+        let heavy = "1 0 0 rg 0 0 612 792 re f 0 1 0 rg 0 0 612 792 re f\n".repeat(20_000).into_bytes();
+        let kids: Vec<Object> = [simple, heavy]
+            .into_iter()
+            .map(|content| {
+                let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                    "Contents" => content_id,
+                };
+                doc.add_object(page).into()
+            })
+            .collect();
+        let pages = dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "vector-art.pdf");
+
+        let app = test_app();
+        run(set_render_timeout(app.state(), 300)).unwrap();
+        let info = run(load_pdf(app.state(), path.clone())).unwrap();
+        assert_eq!(info.page_count, 2);
+        assert!(info.pages[0].thumbnail.starts_with("data:image/png;base64,"));
+        assert_eq!(info.pages[0].error, None);
+        assert!(info.pages[1].thumbnail.starts_with("data:image/svg+xml;base64,"));
+        assert_eq!(info.pages[1].error.as_deref(), Some(RENDER_TIMEOUT));
+
+        // Both results are kept in the cache once rendering finishes
+        let stats = run(get_cache_stats(app.state())).unwrap();
+        let expected: usize = info.pages.iter().map(|page| page.thumbnail.len()).sum();
+        assert_eq!(stats.thumbnail_cache_bytes, expected as u64);
+        let page = run(get_page_thumbnail(app.state(), path, 2)).unwrap();
+        assert_eq!(page.error.as_deref(), Some(RENDER_TIMEOUT));
+    }
+
+    #[test]
+    fn load_pdf_uses_embedded_page_thumbnails() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        // A 3x2 thumbnail: red, green, blue over three grays
+        let pixels = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 10, 10, 128, 128, 128, 250, 250, 250];
+        let thumb = dictionary! { "Width" => 3, "Height" => 2, "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8 };
+        let thumb_id = doc.add_object(lopdf::Stream::new(thumb, pixels));
+        let mut kids = Vec::new();
+        for thumbnail in [Some(thumb_id), None] {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 0 rg 0 0 50 50 re f".to_vec()));
+            let mut page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 300.into(), 400.into()],
+                "Contents" => content_id,
+            };
+            if let Some(thumb_id) = thumbnail {
+                page.set("Thumb", thumb_id);
+            }
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "with-thumbs.pdf");
+
+        let decode = |data_url: &str| {
+            let png =
+                general_purpose::STANDARD.decode(data_url.strip_prefix("data:image/png;base64,").unwrap()).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgb8()
+        };
+        let app = test_app();
+        let info = run(load_pdf(app.state(), path)).unwrap();
+
+        let embedded = decode(&info.pages[0].thumbnail);
+        assert_eq!(embedded.dimensions(), (3, 2));
+        assert_eq!(embedded.get_pixel(2, 0).0, [0, 0, 255]);
+        assert_eq!(embedded.get_pixel(1, 1).0, [128, 128, 128]);
+
+        // Without /Thumb the page is rendered at thumbnail size
+        let rendered = decode(&info.pages[1].thumbnail);
+        assert_eq!(rendered.height(), THUMBNAIL_SIZE as u32);
+        assert_eq!(rendered.width(), 150);
+    }
+
+    #[test]
+    fn embed_thumbnails_gives_every_page_a_thumb() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        // Page 2 already ships a 1x1 white thumbnail
+        let old_thumb =
+            dictionary! { "Width" => 1, "Height" => 1, "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8 };
+        let old_thumb_id = doc.add_object(lopdf::Stream::new(old_thumb, vec![255]));
+        let mut kids = Vec::new();
+        for (width, height) in [(612, 792), (792, 612), (300, 300)] {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"1 0 0 rg 0 0 100 100 re f".to_vec()));
+            let mut page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Contents" => content_id,
+            };
+            if width > height {
+                page.set("Thumb", old_thumb_id);
+            }
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 3, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "thumbless.pdf");
+
+        let thumb_sizes = |output: &str| -> Vec<(i64, i64)> {
+            let saved = Document::load(output).unwrap();
+            saved
+                .get_pages()
+                .into_values()
+                .map(|page_id| {
+                    let thumb = saved.get_dictionary(page_id).unwrap().get(b"Thumb").unwrap();
+                    let thumb = saved.get_object(thumb.as_reference().unwrap()).unwrap().as_stream().unwrap();
+                    let dimension = |key: &[u8]| thumb.dict.get(key).unwrap().as_i64().unwrap();
+                    (dimension(b"Width"), dimension(b"Height"))
+                })
+                .collect()
+        };
+
+        let app = test_app();
+        let output = scratch_path("thumbless-embedded.pdf");
+        run(embed_thumbnails(app.state(), path.clone(), output.clone(), 40, false)).unwrap();
+        assert_eq!(thumb_sizes(&output), vec![(31, 40), (1, 1), (40, 40)]);
+
+        // Forcing replaces the thumbnail the file already had
+        run(embed_thumbnails(app.state(), path.clone(), output.clone(), 40, true)).unwrap();
+        assert_eq!(thumb_sizes(&output), vec![(31, 40), (40, 31), (40, 40)]);
+
+        assert!(run(embed_thumbnails(app.state(), path, output, 0, false)).is_err());
+    }
+
+    #[test]
+    fn pages_without_a_media_box_follow_the_configured_default_size() {
+        // Neither the page nor the page tree has a MediaBox
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 1 rg 10 10 50 50 re f".to_vec()));
+        let page_id = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content_id });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "no-media-box.pdf");
+
+        let thumbnail_size = |page: &PdfPage| {
+            let png = page.thumbnail.strip_prefix("data:image/png;base64,").unwrap();
+            image::load_from_memory(&general_purpose::STANDARD.decode(png).unwrap()).unwrap().to_rgb8().dimensions()
+        };
+
+        let app = test_app();
+        let info = run(load_pdf(app.state(), path.clone())).unwrap();
+        assert_eq!((info.pages[0].width, info.pages[0].height), (595.0, 842.0));
+
+        run(set_default_page_size(app.state(), None, None, Some(PageSizePreset::Letter))).unwrap();
+        let info = run(load_pdf(app.state(), path.clone())).unwrap();
+        assert_eq!((info.pages[0].width, info.pages[0].height), (612.0, 792.0));
+        // Re-rendered at the new size rather than served from the cache
+        assert_eq!(thumbnail_size(&info.pages[0]), (155, 200));
+
+        run(set_default_page_size(app.state(), Some(500.0), Some(250.0), None)).unwrap();
+        let page = run(get_page_thumbnail(app.state(), path.clone(), 1)).unwrap();
+        assert_eq!((page.width, page.height), (500.0, 250.0));
+        assert_eq!(thumbnail_size(&page), (200, 100));
+
+        // Repairs write the configured size as the page's MediaBox
+        let output = scratch_path("no-media-box-rebuilt.pdf");
+        run(rebuild_page_tree(app.state(), path, output.clone())).unwrap();
+        let rebuilt = Document::load(&output).unwrap();
+        let page_id = rebuilt.get_pages()[&1];
+        assert_eq!(page_visible_box(&rebuilt, page_id), Some([0.0, 0.0, 500.0, 250.0]));
+
+        assert!(run(set_default_page_size(app.state(), Some(-1.0), Some(250.0), None)).is_err());
+    }
+
+    #[test]
+    fn get_page_count_matches_load_pdf_without_caching() {
+        // Seven pages split over two intermediate page tree nodes
+        let mut doc = Document::with_version("1.5");
+        let root_id = doc.new_object_id();
+        let mut branches = Vec::new();
+        for count in [3, 4] {
+            let branch_id = doc.new_object_id();
+            let kids: Vec<Object> = (0..count)
+                .map(|_| doc.add_object(dictionary! { "Type" => "Page", "Parent" => branch_id }).into())
+                .collect();
+            let branch = dictionary! { "Type" => "Pages", "Parent" => root_id, "Count" => count, "Kids" => kids };
+            doc.objects.insert(branch_id, Object::Dictionary(branch));
+            branches.push(branch_id.into());
+        }
+        let root = dictionary! {
+            "Type" => "Pages",
+            "Count" => 7,
+            "Kids" => branches,
+            "MediaBox" => vec![0.into(), 0.into(), 200.into(), 200.into()],
+        };
+        doc.objects.insert(root_id, Object::Dictionary(root));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => root_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc.clone(), "seven-pages.pdf");
+
+        let app = test_app();
+        assert_eq!(run(get_page_count(path.clone())).unwrap(), 7);
+        assert_eq!(run(get_cache_stats(app.state())).unwrap().open_documents, 0);
+        assert_eq!(run(load_pdf(app.state(), path)).unwrap().page_count, 7);
+
+        let encrypt_id = doc.add_object(dictionary! { "Filter" => "Standard", "V" => 2, "R" => 3, "Length" => 128 });
+        doc.trailer.set("Encrypt", encrypt_id);
+        let encrypted = save_fixture(doc, "seven-pages-encrypted.pdf");
+        assert_eq!(run(get_page_count(encrypted)).unwrap_err(), "Document is encrypted");
+    }
+
+    #[test]
+    fn add_image_watermark_embeds_image_and_opacity_state() {
+        // A landscape page carrying a text watermark already, and a portrait page
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        let mut kids = Vec::new();
+        for (width, height) in [(600, 300), (300, 500)] {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 m 10 10 l S".to_vec()));
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Contents" => content_id,
+            };
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        apply_text_watermark(&mut doc, "DRAFT", 48.0, 0.2, (612.0, 792.0)).unwrap();
+        let path = save_fixture(doc, "image-watermark.pdf");
+
+        // A 40x20 logo with a transparent right half
+        let logo = scratch_path("logo.png");
+        image::RgbaImage::from_fn(40, 20, |x, _| image::Rgba([200, 30, 30, if x < 20 { 255 } else { 0 }]))
+            .save(&logo)
+            .unwrap();
+
+        let app = test_app();
+        run(load_pdf(app.state(), path.clone())).unwrap();
+        assert!(run(add_image_watermark(app.state(), path.clone(), logo.clone(), 0.4, 0.0, None)).is_err());
+        run(add_image_watermark(app.state(), path.clone(), logo, 0.4, 0.5, Some(vec![1]))).unwrap();
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let pages = doc.get_pages();
+            let first = page_resources(doc, pages[&1]).unwrap();
+            let images = resource_category(doc, first, b"XObject").unwrap();
+            let image = doc.get_object(images.get(b"WmImg1").unwrap().as_reference().unwrap()).unwrap();
+            let image = image.as_stream().unwrap();
+            assert_eq!(image.dict.get(b"Subtype").unwrap().as_name().unwrap(), b"Image");
+            assert_eq!(image.dict.get(b"Width").unwrap().as_i64().unwrap(), 40);
+            assert!(image.dict.has(b"SMask"));
+
+            // The text watermark's state is kept next to the new one
+            let states = resource_category(doc, first, b"ExtGState").unwrap();
+            assert!(states.has(b"WmGs1"));
+            let state = doc.get_dictionary(states.get(b"WmGs2").unwrap().as_reference().unwrap()).unwrap();
+            assert!((state.get(b"ca").unwrap().as_float().unwrap() - 0.4).abs() < 1e-6);
+            assert!(resource_category(doc, first, b"Font").unwrap().has(b"WmFont1"));
+
+            // Half the page width keeps the 2:1 aspect ratio, centered on the page
+            let content = String::from_utf8(doc.get_page_content(pages[&1]).unwrap()).unwrap();
+            assert!(content.contains("/WmGs2 gs 300.0000 0 0 150.0000 150.0000 75.0000 cm /WmImg1 Do"));
+
+            let second = page_resources(doc, pages[&2]).unwrap();
+            assert!(resource_category(doc, second, b"XObject").is_none());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn revert_discards_edits_and_history() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 420.into(), 595.into()],
+            "Rotate" => 90,
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "revert.pdf");
+
+        let app = test_app();
+        let state = app.state::<AppState>();
+        run(load_pdf(app.state(), path.clone())).unwrap();
+        run(rotate_pages_relative(app.state(), path.clone(), vec![1], 90)).unwrap();
+        run(rotate_pages_relative(app.state(), path.clone(), vec![1], 90)).unwrap();
+        assert!(run(undo(app.state(), path.clone())).unwrap());
+        let rotation = |state: &AppState| with_document(state, &path, |doc| Ok(page_rotation(doc, page_id))).unwrap();
+        assert_eq!(rotation(&state), 180);
+
+        run(revert(app.state(), path.clone())).unwrap();
+        assert_eq!(rotation(&state), 90);
+        assert!(!run(undo(app.state(), path.clone())).unwrap());
+        assert!(!run(redo(app.state(), path.clone())).unwrap());
+
+        // A read-only document is refused, like any other edit
+        run(rotate_pages_relative(app.state(), path.clone(), vec![1], 90)).unwrap();
+        run(set_readonly(app.state(), path.clone(), true)).unwrap();
+        assert_eq!(run(revert(app.state(), path.clone())).unwrap_err(), READ_ONLY);
+        assert_eq!(rotation(&state), 180);
+    }
+
+    #[test]
+    fn viewer_preferences_round_trip_through_save() {
+        // The catalog already asks for a centered window and duplex printing
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 1024.into(), 768.into()],
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "ViewerPreferences" => dictionary! { "CenterWindow" => true, "Duplex" => "DuplexFlipLongEdge" },
+        });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "kiosk.pdf");
+
+        let app = test_app();
+        let prefs = run(get_viewer_preferences(app.state(), path.clone())).unwrap();
+        assert!(prefs.center_window && !prefs.fit_window && !prefs.display_doc_title && !prefs.hide_toolbar);
+        assert_eq!(prefs.duplex, Some(Duplex::DuplexFlipLongEdge));
+
+        let prefs = ViewerPreferences { fit_window: true, display_doc_title: true, ..prefs };
+        run(set_viewer_preferences(app.state(), path.clone(), prefs)).unwrap();
+        let output = scratch_path("kiosk-saved.pdf");
+        run(save_pdf(app.state(), path, output.clone(), vec![1], BTreeMap::new(), Vec::new(), None, None, None))
+            .unwrap();
+
+        let prefs = run(get_viewer_preferences(app.state(), output.clone())).unwrap();
+        assert!(prefs.fit_window && prefs.display_doc_title && prefs.center_window && !prefs.hide_menubar);
+        assert_eq!(prefs.duplex, Some(Duplex::DuplexFlipLongEdge));
+
+        let prefs = ViewerPreferences { fit_window: false, display_doc_title: false, duplex: None, ..prefs };
+        run(set_viewer_preferences(app.state(), output.clone(), prefs)).unwrap();
+        let prefs = run(get_viewer_preferences(app.state(), output)).unwrap();
+        assert!(!prefs.fit_window && !prefs.display_doc_title && prefs.center_window);
+        assert_eq!(prefs.duplex, None);
+    }
+
+    #[test]
+    fn set_page_transition_writes_trans_on_the_targeted_pages() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> = (0..3)
+            .map(|_| {
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 800.into(), 600.into()],
+                };
+                doc.add_object(page).into()
+            })
+            .collect();
+        let pages = dictionary! { "Type" => "Pages", "Count" => 3, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "slides.pdf");
+
+        let app = test_app();
+        let dissolve = Transition { style: TransitionStyle::Dissolve, duration: 1.5, display_duration: Some(4.0) };
+        run(set_page_transition(app.state(), path.clone(), Some(vec![1, 3]), dissolve)).unwrap();
+        let negative = Transition { style: TransitionStyle::Fade, duration: -1.0, display_duration: None };
+        assert!(run(set_page_transition(app.state(), path.clone(), None, negative)).is_err());
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let pages = doc.get_pages();
+            for number in [1, 3] {
+                let page = doc.get_dictionary(pages[&number]).unwrap();
+                let trans = page.get(b"Trans").unwrap().as_dict().unwrap();
+                assert_eq!(trans.get(b"Type").unwrap().as_name().unwrap(), b"Trans");
+                assert_eq!(trans.get(b"S").unwrap().as_name().unwrap(), b"Dissolve");
+                assert_eq!(trans.get(b"D").unwrap().as_float().unwrap(), 1.5);
+                assert_eq!(page.get(b"Dur").unwrap().as_float().unwrap(), 4.0);
+            }
+            let middle = doc.get_dictionary(pages[&2]).unwrap();
+            assert!(!middle.has(b"Trans") && !middle.has(b"Dur"));
+            Ok(())
+        })
+        .unwrap();
+
+        // The replace style is spelled /R in PDF
+        let replace = Transition { style: TransitionStyle::Replace, duration: 0.0, display_duration: None };
+        run(set_page_transition(app.state(), path.clone(), Some(vec![2]), replace)).unwrap();
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let page = doc.get_dictionary(doc.get_pages()[&2]).unwrap();
+            assert_eq!(page.get(b"Trans").unwrap().as_dict().unwrap().get(b"S").unwrap().as_name().unwrap(), b"R");
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn flatten_transparency_leaves_no_transparency_groups() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+
+        // Page 1: opaque text plus a soft-masked 2x2 image, inside a page-level group
+        let mask_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 2, "Height" => 2,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        };
+        let mask_id = doc.add_object(lopdf::Stream::new(mask_dict, vec![255, 128, 64, 0]));
+        let image_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 2, "Height" => 2,
+            "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8, "SMask" => mask_id,
+        };
+        let image_id = doc.add_object(lopdf::Stream::new(image_dict, [0u8, 0, 255].repeat(4)));
+        let text = b"BT /F1 18 Tf 40 700 Td (Quarterly report) Tj ET q 100 0 0 100 40 500 cm /Im1 Do Q".to_vec();
+        let text_id = doc.add_object(lopdf::Stream::new(dictionary! {}, text));
+        let opaque_page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => text_id,
+            "Group" => dictionary! { "S" => "Transparency", "CS" => "DeviceRGB" },
+            "Resources" => dictionary! {
+                "Font" => dictionary! { "F1" => font_id },
+                "XObject" => dictionary! { "Im1" => image_id },
+            },
+        });
+
+        // Page 2: a half-transparent overlay drawn through a transparency group form
+        let form_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 300.into(), 300.into()],
+            "Group" => dictionary! { "Type" => "Group", "S" => "Transparency" },
+        };
+        let form_id = doc.add_object(lopdf::Stream::new(form_dict, b"1 0 0 rg 0 0 300 300 re f".to_vec()));
+        let overlay = b"0 0 1 rg 50 50 200 200 re f /GS1 gs /Fm1 Do".to_vec();
+        let overlay_id = doc.add_object(lopdf::Stream::new(dictionary! {}, overlay));
+        let overlay_page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => overlay_id,
+            "Resources" => dictionary! {
+                "ExtGState" => dictionary! { "GS1" => dictionary! { "Type" => "ExtGState", "ca" => 0.5 } },
+                "XObject" => dictionary! { "Fm1" => form_id },
+            },
+        });
+
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Count" => 2,
+            "Kids" => vec![opaque_page.into(), overlay_page.into()],
+            "MediaBox" => vec![0.into(), 0.into(), 300.into(), 300.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "transparent.pdf");
+
+        let app = test_app();
+        let output = scratch_path("transparent-flat.pdf");
+        run(flatten_transparency(app.state(), path, output.clone(), None, None)).unwrap();
+
+        let flat = Document::load(&output).unwrap();
+        for (id, object) in &flat.objects {
+            let dict = match object {
+                Object::Dictionary(dict) => dict,
+                Object::Stream(stream) => &stream.dict,
+                _ => continue,
+            };
+            assert!(!dict.has(b"Group"), "{:?} still has a /Group", id);
+            assert!(!dict.has(b"SMask"), "{:?} still has a /SMask", id);
+        }
+
+        // The opaque page keeps its text; the overlay page is now a single image
+        let pages = flat.get_pages();
+        let first = String::from_utf8_lossy(&flat.get_page_content(pages[&1]).unwrap()).into_owned();
+        assert!(first.contains("(Quarterly report) Tj"));
+        let second = String::from_utf8_lossy(&flat.get_page_content(pages[&2]).unwrap()).into_owned();
+        assert!(!second.contains("gs") && second.contains("Do"));
+        let resources = page_resources(&flat, pages[&2]).unwrap();
+        assert!(resource_category(&flat, resources, b"ExtGState").is_none());
+        let xobjects = resource_category(&flat, resources, b"XObject").unwrap();
+        assert_eq!(xobjects.len(), 1);
+        let (_, raster) = xobjects.iter().next().unwrap();
+        assert!(is_image_xobject(flat.get_object(raster.as_reference().unwrap()).unwrap()));
+    }
+
+    // Single-page-size document with `count` empty pages
+    fn blank_pages(count: usize, width: i64, height: i64) -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> =
+            (0..count).map(|_| doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id }).into()).collect();
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Count" => count as i64,
+            "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    #[test]
+    fn merge_pdfs_reports_throttled_progress_in_order() {
+        let inputs = vec![
+            save_fixture(blank_pages(2, 612, 792), "merge-short.pdf"),
+            save_fixture(blank_pages(60, 595, 842), "merge-long.pdf"),
+            save_fixture(blank_pages(1, 842, 595), "merge-single.pdf"),
+        ];
+
+        let app = test_app();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        tauri::Listener::listen(&app, "merge_progress", move |event| {
+            let progress: MergeProgress = serde_json::from_str(event.payload()).unwrap();
+            sink.lock().unwrap().push(progress);
+        });
+
+        let output = scratch_path("merge-progress.pdf");
+        run(merge_pdfs(app.handle().clone(), inputs, output.clone(), None, None)).unwrap();
+        assert_eq!(Document::load(&output).unwrap().get_pages().len(), 63);
+
+        let events = events.lock().unwrap();
+        let steps: Vec<(usize, usize)> = events.iter().map(|p| (p.current_file, p.pages_done)).collect();
+        // One event per finished input, plus one per 25 pages of the long input
+        assert_eq!(steps, [(1, 2), (2, 27), (2, 52), (2, 62), (3, 63), (3, 63)]);
+        assert!(events.iter().all(|p| p.files_total == 3));
+        let last = events.last().unwrap();
+        assert!(last.done && events[..events.len() - 1].iter().all(|p| !p.done));
+    }
+
+    #[test]
+    fn rebuild_page_tree_fixes_count_and_recovers_orphans() {
+        // Five pages under one node that claims two, a sixth page nobody points at,
+        // and a kid that loops back to the root
+        let mut doc = Document::with_version("1.3");
+        let root_id = doc.new_object_id();
+        let page = |doc: &mut Document, width: i64| {
+            doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => root_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), 400.into()],
+            })
+        };
+        let mut kids: Vec<Object> = (1..=5).map(|n| page(&mut doc, n * 100).into()).collect();
+        kids.push(root_id.into());
+        let orphan = page(&mut doc, 600);
+        doc.objects
+            .insert(root_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Count" => 2, "Kids" => kids }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => root_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "broken-tree.pdf");
+
+        let app = test_app();
+        let output = scratch_path("broken-tree-rebuilt.pdf");
+        assert_eq!(run(rebuild_page_tree(app.state(), path, output.clone())).unwrap(), 6);
+
+        let rebuilt = Document::load(&output).unwrap();
+        assert_eq!(rebuilt.get_pages().len(), 6);
+        assert_eq!(page_widths(&rebuilt), [100.0, 200.0, 300.0, 400.0, 500.0, 600.0]);
+        let root = rebuilt.get_dictionary(rebuilt.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap());
+        assert_eq!(root.unwrap().get(b"Count").unwrap().as_i64().unwrap(), 6);
+        let orphan = rebuilt.get_dictionary(orphan).unwrap();
+        assert_eq!(orphan.get(b"Parent").unwrap().as_reference().unwrap(), root_id);
+    }
+
+    #[test]
+    fn rebuild_page_tree_balances_large_documents() {
+        let path = save_fixture(blank_pages(70, 300, 300), "flat-tree.pdf");
+        let app = test_app();
+        let output = scratch_path("flat-tree-rebuilt.pdf");
+        assert_eq!(run(rebuild_page_tree(app.state(), path, output.clone())).unwrap(), 70);
+
+        let rebuilt = Document::load(&output).unwrap();
+        assert_eq!(rebuilt.get_pages().len(), 70);
+        let root_id = rebuilt.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let root = rebuilt.get_dictionary(root_id).unwrap();
+        let counts: Vec<i64> = root
+            .get(b"Kids")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|kid| {
+                let node = rebuilt.get_dictionary(kid.as_reference().unwrap()).unwrap();
+                assert_eq!(node.get(b"Parent").unwrap().as_reference().unwrap(), root_id);
+                node.get(b"Count").unwrap().as_i64().unwrap()
+            })
+            .collect();
+        assert_eq!(counts, [32, 32, 6]);
+    }
+
+    #[test]
+    fn lower_jpeg_quality_gives_smaller_output() {
+        // A detailed 160x120 JPEG photo with a soft mask, so flattening re-encodes it
+        let photo = image::RgbImage::from_fn(160, 120, |x, y| {
+            let noise = (x * 7919 + y * 104_729) % 97;
+            image::Rgb([(x * 255 / 160) as u8, (y * 255 / 120) as u8, (noise * 2) as u8])
+        });
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 100).encode_image(&photo).unwrap();
+
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let mask_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 160, "Height" => 120,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        };
+        let mask_id = doc.add_object(lopdf::Stream::new(mask_dict, vec![200; 160 * 120]));
+        let photo_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 160, "Height" => 120,
+            "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8, "Filter" => "DCTDecode", "SMask" => mask_id,
+        };
+        let photo_id = doc.add_object(lopdf::Stream::new(photo_dict, jpeg));
+        let content_id =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"q 320 0 0 240 0 0 cm /Photo Do Q".to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 320.into(), 240.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Photo" => photo_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "photo.pdf");
+
+        let app = test_app();
+        let flatten_at = |quality: u8| {
+            let output = scratch_path(&format!("photo-q{}.pdf", quality));
+            run(flatten_transparency(app.state(), path.clone(), output.clone(), Some(quality), None)).unwrap();
+            let flat = Document::load(&output).unwrap();
+            let photo = flat.get_object(photo_id).unwrap().as_stream().unwrap();
+            assert!(is_jpeg_stream(photo) && !photo.dict.has(b"SMask"));
+            std::fs::metadata(&output).unwrap().len()
+        };
+        assert!(flatten_at(50) < flatten_at(95));
+
+        assert_eq!(clamp_jpeg_quality(None), DEFAULT_JPEG_QUALITY);
+        assert_eq!(clamp_jpeg_quality(Some(0)), 1);
+        assert_eq!(clamp_jpeg_quality(Some(250)), 100);
+    }
+
+    #[test]
+    fn classify_pages_tells_native_text_from_scans() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let font_id =
+            doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Times-Roman" });
+        let scan_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 4, "Height" => 4,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        };
+        let scan_id = doc.add_object(lopdf::Stream::new(scan_dict, vec![230; 16]));
+        let resources = dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+            "XObject" => dictionary! { "Scan" => scan_id },
+        };
+        let contents: [&[u8]; 4] = [
+            b"BT /F1 11 Tf 72 720 Td (Minutes of the meeting) Tj ET",
+            b"q 612 0 0 792 0 0 cm /Scan Do Q",
+            // A scan with an invisible OCR text layer on top
+            b"q 612 0 0 792 0 0 cm /Scan Do Q BT 3 Tr /F1 11 Tf 72 720 Td (Invoice 2291) Tj ET",
+            b"",
+        ];
+        let kids: Vec<Object> = contents
+            .iter()
+            .map(|content| {
+                let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+                let page = dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                    "Resources" => resources.clone(),
+                };
+                doc.add_object(page).into()
+            })
+            .collect();
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Count" => 4,
+            "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "scanned-and-native.pdf");
+
+        let app = test_app();
+        let classes = run(classify_pages(app.state(), path)).unwrap();
+        assert_eq!(classes, [PageClass::TextNative, PageClass::Scanned, PageClass::Mixed, PageClass::Empty]);
+    }
+
+    // Width and height from a placeholder's viewBox, plus its decoded SVG
+    fn placeholder_view_box(data_url: &str) -> (f64, f64, String) {
+        let encoded = data_url.strip_prefix("data:image/svg+xml;base64,").unwrap();
+        let svg = String::from_utf8(general_purpose::STANDARD.decode(encoded).unwrap()).unwrap();
+        let start = svg.find("viewBox=\"").unwrap() + "viewBox=\"".len();
+        let values: Vec<f64> = svg[start..].split('"').next().unwrap().split(' ').map(|v| v.parse().unwrap()).collect();
+        (values[2], values[3], svg)
+    }
+
+    #[test]
+    fn thumbnail_placeholder_follows_page_rotation() {
+        let (a4_width, a4_height) = (595.0, 842.0);
+        let (width, height, svg) =
+            placeholder_view_box(&generate_thumbnail_placeholder(7, a4_width, a4_height, 90, (612.0, 792.0)));
+        assert!(width > height, "{}x{}", width, height);
+        assert_eq!((width, height), (141.0, 100.0));
+        assert!(svg.contains(">7</text>") && svg.contains("x=\"70.5\" y=\"50\""));
+
+        let (width, height, _) =
+            placeholder_view_box(&generate_thumbnail_placeholder(1, a4_width, a4_height, 270, (612.0, 792.0)));
+        assert!(width > height);
+        for rotation in [0, 180] {
+            let (width, height, _) =
+                placeholder_view_box(&generate_thumbnail_placeholder(1, a4_width, a4_height, rotation, (612.0, 792.0)));
+            assert_eq!((width, height), (100.0, 141.0));
+        }
+
+        // A landscape page turned a quarter shows as portrait; a degenerate size uses the default
+        let (width, height, _) =
+            placeholder_view_box(&generate_thumbnail_placeholder(2, 842.0, 595.0, 90, (612.0, 792.0)));
+        assert!(width < height);
+        let (width, height, _) = placeholder_view_box(&generate_thumbnail_placeholder(3, 0.0, 0.0, 0, (792.0, 612.0)));
+        assert_eq!((width, height), (141.0, 109.0));
+    }
+
+    #[test]
+    fn garbage_collect_drops_orphans_and_renumbers() {
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        // Left behind by an earlier edit: an old content stream and a pair that only reference each other
+        doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 9 Tf (old) Tj ET".to_vec()));
+        let first = doc.new_object_id();
+        let second = doc.add_object(dictionary! { "Next" => first });
+        doc.objects.insert(first, Object::Dictionary(dictionary! { "Next" => second }));
+        doc.max_id += 40;
+
+        let content_id =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 9 Tf 20 20 Td (kept) Tj ET".to_vec()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 200.into(), 100.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        let info_id = doc.add_object(dictionary! { "Producer" => Object::string_literal("fixture") });
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+        let path = save_fixture(doc, "orphans.pdf");
+
+        let app = test_app();
+        let output = scratch_path("orphans-collected.pdf");
+        let report = run(garbage_collect(app.state(), path, output.clone())).unwrap();
+        // The loaded file's own cross-reference stream is unreachable too
+        assert_eq!((report.before, report.after, report.removed), (10, 6, 4));
+
+        let collected = Document::load(&output).unwrap();
+        let ids: Vec<u32> = collected
+            .objects
+            .iter()
+            .filter(|(_, object)| !matches!(object.type_name(), Ok("XRef")))
+            .map(|(&(number, _), _)| number)
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6]);
+        assert!(collected.objects.values().all(|object| object.as_dict().map_or(true, |dict| !dict.has(b"Next"))));
+        let page = *collected.get_pages().get(&1).unwrap();
+        let content = collected.get_page_content(page).unwrap();
+        assert_eq!(content, b"BT /F1 9 Tf 20 20 Td (kept) Tj ET");
+        let info = collected.get_dictionary(collected.trailer.get(b"Info").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(info.get(b"Producer").unwrap().as_str().unwrap(), b"fixture");
+        let fonts = resource_category(&collected, page_resources(&collected, page).unwrap(), b"Font").unwrap();
+        let font = collected.get_dictionary(fonts.get(b"F1").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(font.get(b"BaseFont").unwrap().as_name().unwrap(), b"Courier");
+    }
+
+    #[test]
+    fn get_text_layout_returns_spans_in_reading_order() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        // Written footer first, and the heading's right half before its left half
+        let content = b"BT /F1 10 Tf 1 0 0 1 50 40 Tm (Page 1) Tj ET \
+            BT /F1 20 Tf 1 0 0 1 200 250 Tm (Report) Tj -150 0 Td (Annual) Tj ET \
+            BT /F1 12 Tf 50 200 Td (First paragraph) Tj T* ET"
+            .to_vec();
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 400.into(), 300.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "layout.pdf");
+
+        let app = test_app();
+        let spans = run(get_text_layout(app.state(), path.clone(), 1)).unwrap();
+        let texts: Vec<&str> = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(texts, ["Annual", "Report", "First paragraph", "Page 1"]);
+
+        assert_eq!((spans[0].x, spans[0].y, spans[0].font_size), (50.0, 250.0, 20.0));
+        assert_eq!((spans[1].x, spans[1].y), (200.0, 250.0));
+        assert_eq!((spans[3].x, spans[3].y, spans[3].font_size), (50.0, 40.0, 10.0));
+        for span in &spans {
+            assert!(span.width > 0.0 && span.height > 0.0, "{:?}", span);
+            assert!(span.x >= 0.0 && span.x + span.width <= 400.0, "{:?}", span);
+            assert!(span.y >= 0.0 && span.y + span.height <= 300.0, "{:?}", span);
+            assert_eq!(span.font_name, "Helvetica");
+        }
+        // Without a /Widths array every glyph advances half an em
+        assert_eq!(spans[3].width, 30.0);
+
+        assert!(run(get_text_layout(app.state(), path, 2)).is_err());
+    }
+
+    #[test]
+    fn extract_page_bytes_returns_a_standalone_page() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Symbol" });
+        let mut kids = Vec::new();
+        for (label, media_box) in [("one", None), ("two", Some((842, 595))), ("three", None)] {
+            let content = format!("BT /F1 14 Tf 30 30 Td (page {}) Tj ET", label).into_bytes();
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+            let mut page = dictionary! { "Type" => "Page", "Parent" => pages_id, "Contents" => content_id };
+            if let Some((width, height)) = media_box {
+                page.set("MediaBox", vec![0.into(), 0.into(), width.into(), height.into()]);
+                page.set("Rotate", 90);
+            }
+            kids.push(doc.add_object(page).into());
+        }
+        // Size and fonts are inherited from the tree for the pages that don't set their own
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Count" => 3,
+            "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), 420.into(), 595.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "three-pages.pdf");
+
+        let app = test_app();
+        let single = |page_num: usize| {
+            let encoded = run(extract_page_bytes(app.state(), path.clone(), page_num)).unwrap();
+            Document::load_mem(&general_purpose::STANDARD.decode(encoded).unwrap()).unwrap()
+        };
+
+        let second = single(2);
+        assert_eq!(second.get_pages().len(), 1);
+        let page_id = second.get_pages()[&1];
+        assert_eq!(page_visible_box(&second, page_id), Some([0.0, 0.0, 842.0, 595.0]));
+        assert_eq!(page_rotation(&second, page_id), 90);
+        assert_eq!(second.get_page_content(page_id).unwrap(), b"BT /F1 14 Tf 30 30 Td (page two) Tj ET");
+        let fonts = resource_category(&second, page_resources(&second, page_id).unwrap(), b"Font").unwrap();
+        let font = second.get_dictionary(fonts.get(b"F1").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(font.get(b"BaseFont").unwrap().as_name().unwrap(), b"Symbol");
+
+        let third = single(3);
+        assert_eq!(page_visible_box(&third, third.get_pages()[&1]), Some([0.0, 0.0, 420.0, 595.0]));
+
+        for page_num in [0, 4] {
+            assert_eq!(run(extract_page_bytes(app.state(), path.clone(), page_num)).unwrap_err(), "PageOutOfRange");
+        }
+    }
+
+    #[test]
+    fn set_display_title_sets_info_xmp_and_viewer_preference() {
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let xmp = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description>\
+            <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">scan0042</rdf:li></rdf:Alt></dc:title>\
+            </rdf:Description></rdf:RDF></x:xmpmeta>";
+        let metadata_id =
+            doc.add_object(lopdf::Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, xmp.to_vec()));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Metadata" => metadata_id,
+            "ViewerPreferences" => dictionary! { "HideToolbar" => true },
+        });
+        let info_id = doc.add_object(dictionary! { "Title" => Object::string_literal("scan0042"), "Author" => Object::string_literal("R. Ortiz") });
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+        let path = save_fixture(doc, "titled.pdf");
+
+        let app = test_app();
+        let title = "Budget 2025 & Forecast <Draft>";
+        run(set_display_title(app.state(), path.clone(), title.to_string())).unwrap();
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let info = doc.get_dictionary(info_id).unwrap();
+            assert_eq!(decode_pdf_string(info.get(b"Title").unwrap().as_str().unwrap()), title);
+            assert_eq!(info.get(b"Author").unwrap().as_str().unwrap(), b"R. Ortiz");
+
+            let packet = doc.get_object(metadata_id).unwrap().as_stream().unwrap().content.clone();
+            let packet = String::from_utf8(packet).unwrap();
+            assert!(packet.contains("<rdf:li xml:lang=\"x-default\">Budget 2025 &amp; Forecast &lt;Draft&gt;</rdf:li>"));
+            assert!(!packet.contains("scan0042"));
+            Ok(())
+        })
+        .unwrap();
+        let prefs = run(get_viewer_preferences(app.state(), path)).unwrap();
+        assert!(prefs.display_doc_title && prefs.hide_toolbar);
+    }
+
+    #[test]
+    fn diff_metadata_reports_changed_and_missing_fields() {
+        let with_info = |info: lopdf::Dictionary, name: &str| {
+            let mut doc = Document::with_version("1.4");
+            let pages_id = doc.new_object_id();
+            let page_id = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id });
+            let pages = dictionary! {
+                "Type" => "Pages",
+                "Count" => 1,
+                "Kids" => vec![page_id.into()],
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            };
+            doc.objects.insert(pages_id, Object::Dictionary(pages));
+            let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+            let info_id = doc.add_object(info);
+            doc.trailer.set("Root", catalog_id);
+            doc.trailer.set("Info", info_id);
+            save_fixture(doc, name)
+        };
+        let original = dictionary! {
+            "Title" => Object::string_literal("Site survey"),
+            "Author" => Object::string_literal("M. Haddad"),
+            "Trapped" => "False",
+        };
+        let mut reauthored = original.clone();
+        reauthored.set("Author", Object::string_literal("J. Lindqvist"));
+        let mut retitled = original.clone();
+        retitled.remove(b"Title");
+        retitled.set("Keywords", Object::string_literal("survey, north wing"));
+
+        let path_a = with_info(original, "survey.pdf");
+        let path_b = with_info(reauthored, "survey-reauthored.pdf");
+        let path_c = with_info(retitled, "survey-retitled.pdf");
+
+        let app = test_app();
+        assert!(run(diff_metadata(app.state(), path_a.clone(), path_a.clone())).unwrap().is_empty());
+
+        let diffs = run(diff_metadata(app.state(), path_a.clone(), path_b)).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].field, "Author");
+        assert_eq!(diffs[0].value_a.as_deref(), Some("M. Haddad"));
+        assert_eq!(diffs[0].value_b.as_deref(), Some("J. Lindqvist"));
+
+        let diffs = run(diff_metadata(app.state(), path_a, path_c)).unwrap();
+        let summary: Vec<(&str, Option<&str>, Option<&str>)> =
+            diffs.iter().map(|diff| (diff.field.as_str(), diff.value_a.as_deref(), diff.value_b.as_deref())).collect();
+        assert_eq!(summary, [("Keywords", None, Some("survey, north wing")), ("Title", Some("Site survey"), None)]);
+    }
+
+    #[test]
+    fn failed_atomic_write_leaves_the_destination_untouched() {
+        let original = blank_pages(3, 612, 792);
+        let path = save_fixture(original, "only-copy.pdf");
+        let before = std::fs::read(&path).unwrap();
+        let leftovers = || {
+            let dir = std::path::Path::new(&path).parent().unwrap();
+            std::fs::read_dir(dir)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(".only-copy.pdf."))
+                .count()
+        };
+
+        // Fails after half the new file has been written
+        let result = write_atomically(&path, |file| {
+            file.write_all(&before[..before.len() / 2]).map_err(|e| e.to_string())?;
+            Err("disk full".to_string())
+        });
+        assert_eq!(result.unwrap_err(), "disk full");
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert_eq!(leftovers(), 0);
+
+        // A successful save replaces the file in one step
+        let mut replacement = blank_pages(1, 842, 595);
+        save_document(&mut replacement, &path).unwrap();
+        assert_eq!(page_widths(&Document::load(&path).unwrap()), [842.0]);
+        assert_eq!(leftovers(), 0);
+
+        assert!(write_atomically(&scratch_path("missing-dir/out.pdf"), |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn concurrent_atomic_writes_to_one_file_use_separate_temp_files() {
+        let path = scratch_path("shared-output.txt");
+        // Both writers have their temp file open before either writes or renames
+        let barrier = std::sync::Barrier::new(2);
+        let results: Vec<Result<(), String>> = std::thread::scope(|scope| {
+            let writers: Vec<_> = [b'a', b'b']
+                .into_iter()
+                .map(|fill| {
+                    let (path, barrier) = (&path, &barrier);
+                    scope.spawn(move || {
+                        write_atomically(path, |file| {
+                            barrier.wait();
+                            file.write_all(&[fill; 4096]).map_err(|e| e.to_string())
+                        })
+                    })
+                })
+                .collect();
+            writers.into_iter().map(|writer| writer.join().unwrap()).collect()
+        });
+        assert_eq!(results, [Ok(()), Ok(())]);
+
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.len(), 4096);
+        assert!(written.iter().all(|&byte| byte == written[0]));
+        let dir = std::path::Path::new(&path).parent().unwrap();
+        assert!(!std::fs::read_dir(dir).unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".shared-output.txt.")));
+    }
+
+    #[test]
+    fn dump_commands_print_structure_without_stream_data() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let mut content = lopdf::Stream::new(dictionary! {}, b"0.5 g 10 10 80 80 re f ".repeat(40));
+        content.compress().unwrap();
+        let content_id = doc.add_object(content);
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 100.into(), 100.into()],
+            "Contents" => content_id,
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "PageMode" => "UseOutlines",
+            "Lang" => Object::string_literal("de-CH"),
+        });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "dump.pdf");
+
+        let app = test_app();
+        let catalog = run(dump_catalog(app.state(), path.clone())).unwrap();
+        assert!(catalog.contains("/Type /Catalog"), "{}", catalog);
+        assert!(catalog.contains(&format!("/Pages {} 0 R", pages_id.0)));
+        assert!(catalog.contains("/PageMode /UseOutlines") && catalog.contains("/Lang (de-CH)"));
+
+        let trailer = run(dump_trailer(app.state(), path.clone())).unwrap();
+        assert!(trailer.contains(&format!("/Root {} 0 R", catalog_id.0)), "{}", trailer);
+
+        let stream = run(dump_object(app.state(), path.clone(), content_id)).unwrap();
+        assert!(stream.starts_with(&format!("{} 0 obj\n<<", content_id.0)) && stream.ends_with("endstream\nendobj"));
+        assert!(stream.contains("/Filter /FlateDecode") && stream.contains("bytes, FlateDecode)"), "{}", stream);
+        assert!(!stream.contains("re f"));
+
+        let page = run(dump_object(app.state(), path.clone(), page_id)).unwrap();
+        assert!(page.contains("  /MediaBox [0 0 100 100]\n"), "{}", page);
+
+        assert_eq!(run(dump_object(app.state(), path, (999, 0))).unwrap_err(), "Object 999 0 R not found");
+    }
+
+    // One-page form whose only text field, `name`, is also its widget
+    fn single_field_form(name: &str, value: &str, font: (&str, &str)) -> Document {
+        let mut doc = Document::with_version("1.6");
+        let pages_id = doc.new_object_id();
+        let page_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => font.1 });
+        let field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal(name),
+            "V" => Object::string_literal(value),
+            "Rect" => vec![50.into(), 700.into(), 250.into(), 720.into()],
+            "P" => page_id,
+        });
+        let page = dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Annots" => vec![field_id.into()],
+        };
+        doc.objects.insert(page_id, Object::Dictionary(page));
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let acro_form = dictionary! {
+            "Fields" => vec![field_id.into()],
+            "DR" => dictionary! { "Font" => dictionary! { font.0 => font_id } },
+            "DA" => Object::string_literal(format!("/{} 0 Tf 0 g", font.0)),
+        };
+        let catalog_id =
+            doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id, "AcroForm" => acro_form });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    #[test]
+    fn merge_pdfs_keeps_both_forms_fillable() {
+        let inputs = vec![
+            save_fixture(single_field_form("email", "a@example.org", ("Helv", "Helvetica")), "form-a.pdf"),
+            save_fixture(single_field_form("email", "b@example.org", ("Cour", "Courier")), "form-b.pdf"),
+        ];
+        let app = test_app();
+        let output = scratch_path("forms-merged.pdf");
+        run(merge_pdfs(app.handle().clone(), inputs, output.clone(), None, None)).unwrap();
+
+        let merged = Document::load(&output).unwrap();
+        let form = merged.get_dictionary(merged.catalog().unwrap().get(b"AcroForm").unwrap().as_reference().unwrap());
+        let form = form.unwrap();
+        assert_eq!(form.get(b"Fields").unwrap().as_array().unwrap().len(), 2);
+        let fonts = form.get(b"DR").unwrap().as_dict().unwrap().get(b"Font").unwrap().as_dict().unwrap();
+        assert!(fonts.has(b"Helv") && fonts.has(b"Cour"));
+
+        let pages: Vec<ObjectId> = merged.get_pages().into_values().collect();
+        for (name, value, page) in [("email", "a@example.org", pages[0]), ("email_2", "b@example.org", pages[1])] {
+            let field_id = form_field_id(&merged, name).unwrap_or_else(|| panic!("no field {}", name));
+            let field = merged.get_dictionary(field_id).unwrap();
+            assert_eq!(field.get(b"V").unwrap().as_str().unwrap(), value.as_bytes());
+            assert_eq!(field.get(b"P").unwrap().as_reference().unwrap(), page);
+            // The widget on the page is the field itself, so filling it shows on that page
+            let annots = merged.get_dictionary(page).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+            assert_eq!(annots, &vec![Object::Reference(field_id)]);
+        }
+
+        let app = test_app();
+        run(set_field_default_appearance(
+            app.state(),
+            output.clone(),
+            "email_2".to_string(),
+            "Cour".to_string(),
+            11.0,
+            [0.0, 0.0, 0.5],
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn unify_page_size_gives_every_page_the_target_size() {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 50.into()],
+        });
+        let mut kids = Vec::new();
+        // A4 portrait, A5 landscape with a link, and A5 portrait with print boxes
+        for (width, height) in [(595, 842), (595, 420), (420, 595)] {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 m 100 100 l S".to_vec()));
+            let mut page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Contents" => content_id,
+            };
+            if width > height {
+                page.set("Annots", vec![link_id.into()]);
+            } else if width == 420 {
+                page.set("TrimBox", vec![10.into(), 10.into(), 410.into(), 585.into()]);
+            }
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 3, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "mixed-sizes.pdf");
+
+        let app = test_app();
+        let boxes = |key: &'static [u8]| {
+            with_document(&app.state::<AppState>(), &path, |doc| {
+                Ok(doc
+                    .get_pages()
+                    .into_values()
+                    .map(|id| {
+                        let value = doc.get_dictionary(id).unwrap().get(key).ok()?;
+                        Some(value.as_array().unwrap().iter().map(|v| v.as_float().unwrap()).collect::<Vec<f32>>())
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .unwrap()
+        };
+
+        run(unify_page_size(app.state(), path.clone(), PageSizePreset::A4, ResizeMode::Fit, None)).unwrap();
+        let portrait = Some(vec![0.0, 0.0, 595.0, 842.0]);
+        let landscape = Some(vec![0.0, 0.0, 842.0, 595.0]);
+        let expected = vec![portrait.clone(), landscape.clone(), portrait.clone()];
+        assert_eq!(boxes(b"MediaBox"), expected);
+        assert_eq!(boxes(b"CropBox"), expected);
+        assert_eq!(boxes(b"TrimBox"), [None, None, None]);
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let pages = doc.get_pages();
+            // A5 landscape scales up by about sqrt(2) to fit A4 landscape
+            let content = String::from_utf8(doc.get_page_content(pages[&2]).unwrap()).unwrap();
+            assert!(content.starts_with("q 1.415") && content.trim_end().ends_with('Q'), "{}", content);
+            let rect = doc.get_dictionary(link_id).unwrap().get(b"Rect").unwrap().as_array().unwrap().clone();
+            assert!((rect[2].as_float().unwrap() - 141.5).abs() < 0.5, "{:?}", rect);
+            Ok(())
+        })
+        .unwrap();
+
+        run(unify_page_size(app.state(), path.clone(), PageSizePreset::Letter, ResizeMode::Stretch, Some(false)))
+            .unwrap();
+        let letter = Some(vec![0.0, 0.0, 612.0, 792.0]);
+        assert_eq!(boxes(b"MediaBox"), [letter.clone(), letter.clone(), letter]);
+    }
+
+    #[test]
+    fn check_font_coverage_flags_substituted_and_incomplete_fonts() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let arial_id = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "Arial",
+            "FirstChar" => 32, "LastChar" => 126,
+        });
+        // A subset whose program dropped "C": its width was zeroed by the subsetter
+        let program_id = doc.add_object(lopdf::Stream::new(dictionary! {}, vec![0, 1, 0, 0, 0, 0]));
+        let descriptor_id = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor", "FontName" => "KLMNOP+Garamond", "Flags" => 32, "FontFile2" => program_id,
+        });
+        let subset_id = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "KLMNOP+Garamond",
+            "FirstChar" => 65, "LastChar" => 67,
+            "Widths" => vec![600.into(), 580.into(), 0.into()],
+            "FontDescriptor" => descriptor_id,
+        });
+        let resources = dictionary! { "Font" => dictionary! { "F1" => arial_id, "F2" => subset_id } };
+        let contents: [&[u8]; 2] = [
+            b"BT /F1 12 Tf 72 700 Td (Needs a substitute) Tj /F2 12 Tf (ABC) Tj ET",
+            b"BT /F2 12 Tf 72 700 Td [(AB) -20 (BA)] TJ ET",
+        ];
+        let mut kids = Vec::new();
+        for content in contents {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+            let page = dictionary! {
+                "Type" => "Page", "Parent" => pages_id, "Contents" => content_id, "Resources" => resources.clone(),
+            };
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! {
+            "Type" => "Pages", "Count" => 2, "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "font-coverage.pdf");
+
+        let app = test_app();
+        let issues = run(check_font_coverage(app.state(), path)).unwrap();
+        let summary: Vec<(usize, &str)> = issues.iter().map(|issue| (issue.page, issue.font.as_str())).collect();
+        // Page 2 only uses glyphs the subset has, and never selects Arial
+        assert_eq!(summary, [(1, "Arial"), (1, "KLMNOP+Garamond")]);
+        assert!(issues[0].problem.contains("not embedded"));
+        assert!(issues[1].problem.ends_with("codes 0x0043"), "{}", issues[1].problem);
+    }
+
+    #[test]
+    fn add_cover_page_inserts_a_titled_page_of_the_dominant_size() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let mut kids = Vec::new();
+        for (index, (width, height)) in [(612, 792), (595, 842), (612, 792)].into_iter().enumerate() {
+            let content = format!("BT /F1 10 Tf 50 50 Td (body {}) Tj ET", index + 1).into_bytes();
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Contents" => content_id,
+            };
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! { "Type" => "Pages", "Count" => 3, "Kids" => kids };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "uncovered.pdf");
+
+        let app = test_app();
+        let state = app.state::<AppState>();
+        let title = "Field Notes".to_string();
+        run(add_cover_page(app.state(), path.clone(), title, Some("Volume II".to_string()), true)).unwrap();
+        run(add_cover_page(app.state(), path.clone(), "Appendix".to_string(), None, false)).unwrap();
+
+        with_document(&state, &path, |doc| {
+            let pages = doc.get_pages();
+            assert_eq!(pages.len(), 5);
+            let text =
+                |number: u32| String::from_utf8_lossy(&doc.get_page_content(pages[&number]).unwrap()).into_owned();
+
+            let cover = text(1);
+            assert!(cover.contains("(Field Notes) Tj") && cover.contains("(Volume II) Tj"), "{}", cover);
+            assert_eq!(page_visible_box(doc, pages[&1]), Some([0.0, 0.0, 612.0, 792.0]));
+            let fonts = resource_category(doc, page_resources(doc, pages[&1]).unwrap(), b"Font").unwrap();
+            let font = doc.get_dictionary(fonts.get(b"F1").unwrap().as_reference().unwrap()).unwrap();
+            assert_eq!(font.get(b"BaseFont").unwrap().as_name().unwrap(), b"Helvetica");
+
+            assert!(text(2).contains("(body 1)") && text(4).contains("(body 3)"));
+            assert!(text(5).contains("(Appendix) Tj"));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn output_intent_round_trips_through_save() {
+        let mut doc = blank_pages(1, 595, 842);
+        // An existing sRGB intent that the new profile replaces
+        let old_intent = doc.add_object(dictionary! {
+            "Type" => "OutputIntent",
+            "S" => "GTS_PDFA1",
+            "OutputConditionIdentifier" => Object::string_literal("sRGB"),
+        });
+        doc.catalog_mut().unwrap().set("OutputIntents", vec![old_intent.into()]);
+        let path = save_fixture(doc, "print-ready.pdf");
+
+        // Minimal ICC header: profile size, device class and a CMYK data color space
+        let mut profile = vec![0u8; 2048];
+        profile[..4].copy_from_slice(&2048u32.to_be_bytes());
+        profile[12..16].copy_from_slice(b"prtr");
+        profile[16..20].copy_from_slice(b"CMYK");
+        profile[36..40].copy_from_slice(b"acsp");
+        let icc_path = scratch_path("CoatedFOGRA39.icc");
+        std::fs::write(&icc_path, &profile).unwrap();
+
+        let app = test_app();
+        let intent = run(get_output_intent(app.state(), path.clone())).unwrap().unwrap();
+        assert_eq!((intent.subtype.as_str(), intent.profile_size), ("GTS_PDFA1", None));
+
+        run(set_output_intent(app.state(), path.clone(), icc_path.clone(), "/GTS_PDFX".to_string())).unwrap();
+        let output = scratch_path("print-ready-saved.pdf");
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            output.clone(),
+            vec![1],
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+
+        let intent = run(get_output_intent(app.state(), output)).unwrap().unwrap();
+        assert_eq!(intent.subtype, "GTS_PDFX");
+        assert_eq!(intent.output_condition_identifier.as_deref(), Some("CoatedFOGRA39"));
+        assert_eq!((intent.components, intent.profile_size), (Some(4), Some(2048)));
+
+        profile[16..20].copy_from_slice(b"Lab ");
+        std::fs::write(&icc_path, &profile).unwrap();
+        let error = run(set_output_intent(app.state(), path, icc_path, "GTS_PDFX".to_string())).unwrap_err();
+        assert_eq!(error, "Unsupported ICC color space 'Lab'");
+    }
+
+    #[test]
+    fn read_only_documents_reject_edits_until_unlocked() {
+        let path = save_fixture(blank_pages(2, 612, 792), "locked.pdf");
+        let app = test_app();
+        let state = app.state::<AppState>();
+        let rotations = |state: &AppState| {
+            with_document(state, &path, |doc| {
+                Ok(doc.get_pages().into_values().map(|id| page_rotation(doc, id)).collect::<Vec<_>>())
+            })
+            .unwrap()
+        };
+
+        run(rotate_pages_relative(app.state(), path.clone(), vec![1], 90)).unwrap();
+        run(set_readonly(app.state(), path.clone(), true)).unwrap();
+        assert_eq!(run(rotate_pages_relative(app.state(), path.clone(), vec![2], 90)).unwrap_err(), READ_ONLY);
+        let transition = Transition { style: TransitionStyle::Wipe, duration: 1.0, display_duration: None };
+        assert_eq!(run(set_page_transition(app.state(), path.clone(), None, transition)).unwrap_err(), READ_ONLY);
+        assert_eq!(run(undo(app.state(), path.clone())).unwrap_err(), READ_ONLY);
+        assert_eq!(run(redo(app.state(), path.clone())).unwrap_err(), READ_ONLY);
+        assert_eq!(rotations(&state), [90, 0]);
+
+        // Reading and saving a copy still work while locked
+        assert!(!run(get_viewer_preferences(app.state(), path.clone())).unwrap().fit_window);
+        let output = scratch_path("locked-copy.pdf");
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            output.clone(),
+            vec![1, 2],
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        let copy = Document::load(&output).unwrap();
+        assert_eq!(page_rotation(&copy, copy.get_pages()[&1]), 90);
+
+        run(set_readonly(app.state(), path.clone(), false)).unwrap();
+        run(rotate_pages_relative(app.state(), path.clone(), vec![2], 90)).unwrap();
+        assert_eq!(rotations(&state), [90, 90]);
+        assert!(run(undo(app.state(), path.clone())).unwrap());
+        assert_eq!(rotations(&state), [90, 0]);
+    }
+
+    #[test]
+    fn get_pdfa_info_reports_the_claimed_level_and_obvious_problems() {
+        // Attaches a font and the given XMP packet to a one-page document
+        let archive = |xmp: &[u8], embedded: bool, intent: bool, name: &str| {
+            let mut doc = blank_pages(1, 595, 842);
+            let mut font = dictionary! { "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "DejaVuSans" };
+            if embedded {
+                let program_id = doc.add_object(lopdf::Stream::new(dictionary! {}, vec![0, 1, 0, 0]));
+                let descriptor =
+                    dictionary! { "Type" => "FontDescriptor", "FontName" => "DejaVuSans", "FontFile2" => program_id };
+                font.set("FontDescriptor", doc.add_object(descriptor));
+            }
+            doc.add_object(font);
+            let metadata = lopdf::Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, xmp.to_vec());
+            let metadata_id = doc.add_object(metadata);
+            let catalog = doc.catalog_mut().unwrap();
+            catalog.set("Metadata", metadata_id);
+            if intent {
+                let intent = dictionary! { "Type" => "OutputIntent", "S" => "GTS_PDFA1" };
+                catalog.set("OutputIntents", vec![Object::Dictionary(intent)]);
+            }
+            save_fixture(doc, name)
+        };
+        let app = test_app();
+
+        let attributes = b"<rdf:Description rdf:about=\"\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\" \
+            pdfaid:part=\"2\" pdfaid:conformance=\"B\"/>";
+        let path = archive(attributes, true, true, "pdfa-2b.pdf");
+        let info = run(get_pdfa_info(app.state(), path)).unwrap().unwrap();
+        assert_eq!((info.part, info.conformance.as_deref(), info.level.as_str()), (2, Some("B"), "2B"));
+        assert!(info.issues.is_empty(), "{:?}", info.issues);
+
+        let elements = b"<rdf:Description><pdfaid:part>1</pdfaid:part><pdfaid:conformance>a</pdfaid:conformance></rdf:Description>";
+        let path = archive(elements, false, false, "pdfa-1a-broken.pdf");
+        let info = run(get_pdfa_info(app.state(), path)).unwrap().unwrap();
+        assert_eq!(info.level, "1A");
+        assert_eq!(info.issues, ["No output intent", "Font DejaVuSans is not embedded"]);
+
+        let path = archive(
+            b"<rdf:Description><dc:format>application/pdf</dc:format></rdf:Description>",
+            true,
+            true,
+            "plain.pdf",
+        );
+        assert!(run(get_pdfa_info(app.state(), path)).unwrap().is_none());
+    }
+
+    #[test]
+    fn redact_text_matches_removes_every_occurrence() {
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        // Uneven widths so a wrong replacement gap would move the text that follows
+        let widths: Vec<Object> = (32..=122).map(|code| Object::Integer(400 + (code % 5) * 100)).collect();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica",
+            "FirstChar" => 32, "LastChar" => 122, "Widths" => widths,
+        });
+        let form_dict = dictionary! {
+            "Type" => "XObject", "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 200.into(), 50.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F9" => font_id } },
+        };
+        let form_id =
+            doc.add_object(lopdf::Stream::new(form_dict, b"BT /F9 10 Tf 0 10 Td (stamp: Secret) Tj ET".to_vec()));
+        let resources = dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+            "XObject" => dictionary! { "Stamp" => form_id },
+        };
+        let contents: [&[u8]; 2] = [
+            // One match inside a string, one across TJ elements, one across two Tj operators
+            b"BT /F1 12 Tf 72 700 Td (The secret plan) Tj 0 -20 Td [(a sec) -15 (ret deal)] TJ \
+              0 -20 Td (top SEC) Tj (RET files) Tj ET",
+            b"q 1 0 0 1 50 600 cm /Stamp Do Q q 0 1 -1 0 300 100 cm /Stamp Do Q",
+        ];
+        let mut kids = Vec::new();
+        for content in contents {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+            let page = dictionary! {
+                "Type" => "Page", "Parent" => pages_id, "Contents" => content_id, "Resources" => resources.clone(),
+            };
+            kids.push(doc.add_object(page).into());
+        }
+        let pages = dictionary! {
+            "Type" => "Pages", "Count" => 2, "Kids" => kids,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let occurrences = |doc: &Document| {
+            (1..=2)
+                .map(|page| extract_page_text(doc, page).unwrap().to_lowercase().matches("secret").count())
+                .sum::<usize>()
+        };
+        let before = occurrences(&doc);
+        assert_eq!(before, 5);
+        let plan_x = |doc: &Document| {
+            let spans = page_text_spans(doc, doc.get_pages()[&1]).unwrap();
+            let span = spans.iter().find(|span| span.text.ends_with("plan")).unwrap();
+            span.x + span.width
+        };
+        let plan_end = plan_x(&doc);
+        let path = save_fixture(doc, "secrets.pdf");
+
+        let app = test_app();
+        let output = scratch_path("secrets-redacted.pdf");
+        assert_eq!(
+            run(redact_text_matches(app.state(), path.clone(), "secret".to_string(), false, output.clone())).unwrap(),
+            before
+        );
+
+        let redacted = Document::load(&output).unwrap();
+        assert_eq!(occurrences(&redacted), 0);
+        let first = extract_page_text(&redacted, 1).unwrap();
+        assert!(
+            first.contains("The") && first.contains("plan") && first.contains("deal") && first.contains("files"),
+            "{}",
+            first
+        );
+        assert!((plan_x(&redacted) - plan_end).abs() < 1e-3);
+
+        // One box per removed run: the Tj-spanning match needs two
+        let pages = redacted.get_pages();
+        let boxes = |page: u32| {
+            String::from_utf8_lossy(&redacted.get_page_content(pages[&page]).unwrap()).matches(" l h f").count()
+        };
+        assert_eq!((boxes(1), boxes(2)), (4, 2));
+        let form = redacted.get_object(form_id).unwrap().as_stream().unwrap();
+        let form_content = form.decompressed_content().unwrap_or_else(|_| form.content.clone());
+        assert!(!String::from_utf8_lossy(&form_content).contains("Secret"));
+
+        assert_eq!(
+            run(redact_text_matches(app.state(), path.clone(), "Secret".to_string(), true, output.clone())).unwrap(),
+            2
+        );
+    }
+
+    #[test]
+    fn redact_text_matches_fails_when_a_match_survives() {
+        // The word is split over two text objects, which the rewrite does not join
+        let mut doc = Document::with_version("1.4");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        let content = b"BT /F1 12 Tf 100 500 Td (Pass) Tj ET BT /F1 12 Tf 124 500 Td (word) Tj ET".to_vec();
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 400.into(), 600.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        let pages = dictionary! { "Type" => "Pages", "Count" => 1, "Kids" => vec![page_id.into()] };
+        doc.objects.insert(pages_id, Object::Dictionary(pages));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let path = save_fixture(doc, "split-word.pdf");
+
+        let app = test_app();
+        let output = scratch_path("split-word-redacted.pdf");
+        let error =
+            run(redact_text_matches(app.state(), path, "password".to_string(), false, output.clone())).unwrap_err();
+        assert_eq!(error, "1 match(es) on page 1 could not be removed");
+        assert!(!std::path::Path::new(&output).exists());
+    }
+
+    #[test]
+    fn set_page_mode_writes_the_catalog_entry() {
+        let path = save_fixture(blank_pages(3, 420, 595), "page-mode.pdf");
+        let app = test_app();
+        let page_mode = || {
+            with_document(&app.state::<AppState>(), &path, |doc| {
+                Ok(doc.catalog().unwrap().get(b"PageMode").and_then(Object::as_name).map(<[u8]>::to_vec).ok())
+            })
+            .unwrap()
+        };
+        assert_eq!(page_mode(), None);
+
+        run(set_page_mode(app.state(), path.clone(), PageMode::UseOutlines)).unwrap();
+        assert_eq!(page_mode().as_deref(), Some(&b"UseOutlines"[..]));
+        run(set_page_mode(app.state(), path.clone(), PageMode::FullScreen)).unwrap();
+        assert_eq!(page_mode().as_deref(), Some(&b"FullScreen"[..]));
+    }
+
+    #[test]
+    fn generate_toc_page_links_each_outline_entry() {
+        let mut doc = blank_pages(4, 612, 792);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let outlines_id = doc.new_object_id();
+        let (preface_id, chapter_id, section_id) = (doc.new_object_id(), doc.new_object_id(), doc.new_object_id());
+        let fit = |page: ObjectId| vec![Object::Reference(page), "Fit".into()];
+        let items = [
+            (
+                preface_id,
+                outlines_id,
+                dictionary! {
+                    "Title" => Object::string_literal("Preface"), "Next" => chapter_id, "Dest" => fit(pages[1]),
+                },
+            ),
+            (
+                chapter_id,
+                outlines_id,
+                dictionary! {
+                    "Title" => Object::string_literal("Chapter One"), "First" => section_id, "Last" => section_id,
+                    "A" => dictionary! { "S" => "GoTo", "D" => fit(pages[2]) },
+                },
+            ),
+            (
+                section_id,
+                chapter_id,
+                dictionary! {
+                    "Title" => Object::string_literal("Getting started"), "Dest" => fit(pages[3]),
+                },
+            ),
+        ];
+        for (id, parent, mut item) in items {
+            item.set("Parent", parent);
+            doc.objects.insert(id, Object::Dictionary(item));
+        }
+        doc.objects
+            .insert(outlines_id, Object::Dictionary(dictionary! { "First" => preface_id, "Last" => chapter_id }));
+        let labels = dictionary! {
+            "Nums" => vec![0.into(), dictionary! { "S" => "r" }.into(), 2.into(), dictionary! { "S" => "D" }.into()],
+        };
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("Outlines", outlines_id);
+        catalog.set("PageLabels", labels);
+        let path = save_fixture(doc, "toc.pdf");
+
+        let app = test_app();
+        assert_eq!(run(generate_toc_page(app.state(), path.clone(), false)).unwrap(), 1);
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let all_pages = doc.get_pages();
+            assert_eq!(all_pages.len(), 5);
+            let toc_id = all_pages[&5];
+            let text = extract_page_text(doc, 5)?;
+            for expected in ["Contents", "Preface", "ii", "Chapter One", "Getting started"] {
+                assert!(text.contains(expected), "{:?} missing from {:?}", expected, text);
+            }
+            let targets: Vec<ObjectId> = doc
+                .get_dictionary(toc_id)
+                .unwrap()
+                .get(b"Annots")
+                .and_then(Object::as_array)
+                .unwrap()
+                .iter()
+                .map(|annot| {
+                    let link = doc.get_dictionary(annot.as_reference().unwrap()).unwrap();
+                    assert_eq!(link.get(b"Subtype").and_then(Object::as_name).unwrap(), b"Link");
+                    link.get(b"Dest").and_then(Object::as_array).unwrap()[0].as_reference().unwrap()
+                })
+                .collect();
+            assert_eq!(targets, pages[1..]);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn strip_metadata_leaves_no_identifying_fields() {
+        let mut doc = blank_pages(1, 300, 300);
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Quarterly numbers"),
+            "Author" => Object::string_literal("J. Smith"),
+            "Creator" => Object::string_literal("Writer"),
+            "Producer" => Object::string_literal("pdf-editor"),
+        });
+        doc.trailer.set("Info", info_id);
+        let original_id = Object::String(vec![7; 16], lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![original_id.clone(), original_id]);
+        let xmp = |doc: &mut Document| {
+            let packet = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec();
+            doc.add_object(lopdf::Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, packet))
+        };
+        let catalog_xmp = xmp(&mut doc);
+        doc.catalog_mut().unwrap().set("Metadata", catalog_xmp);
+        let image_xmp = xmp(&mut doc);
+        let image = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 1, "Height" => 1,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8, "Metadata" => image_xmp,
+        };
+        doc.add_object(lopdf::Stream::new(image, vec![0x80]));
+        let path = save_fixture(doc, "identifying.pdf");
+        let app = test_app();
+
+        let output = scratch_path("stripped.pdf");
+        let report = run(strip_metadata(app.state(), path.clone(), output.clone(), None, None)).unwrap();
+        assert_eq!(report.info_fields, ["Title", "Author", "Creator", "Producer"]);
+        assert_eq!((report.xmp_streams, report.removed_id), (2, false));
+        let stripped = Document::load(&output).unwrap();
+        assert!(info_entries(&stripped).is_empty());
+        for field in [&b"Title"[..], b"Author", b"Subject", b"Keywords", b"Creator", b"Producer"] {
+            assert_eq!(info_string(&stripped, field), None);
+        }
+        assert!(stripped.objects.values().all(|object| match object {
+            Object::Stream(stream) =>
+                !stream.dict.has(b"Metadata")
+                    && !matches!(stream.dict.get(b"Type"), Ok(Object::Name(n)) if n == b"Metadata"),
+            Object::Dictionary(dict) => !dict.has(b"Metadata"),
+            _ => true,
+        }));
+        assert!(stripped.trailer.has(b"ID"));
+
+        let output = scratch_path("stripped-no-id.pdf");
+        let report = run(strip_metadata(app.state(), path, output.clone(), Some(true), Some(true))).unwrap();
+        assert!(report.removed_id);
+        let stripped = Document::load(&output).unwrap();
+        assert!(!stripped.trailer.has(b"ID"));
+        assert_eq!(
+            info_entries(&stripped).into_iter().collect::<Vec<_>>(),
+            [("Producer".to_string(), "pdf-editor".to_string())]
+        );
+    }
+
+    #[test]
+    fn set_start_page_number_offsets_the_labels() {
+        let path = save_fixture(blank_pages(5, 500, 700), "numbering.pdf");
+        let app = test_app();
+        assert_eq!(run(get_page_labels(app.state(), path.clone())).unwrap(), ["1", "2", "3", "4", "5"]);
+
+        run(set_start_page_number(app.state(), path.clone(), 3, 1, LabelStyle::Decimal)).unwrap();
+        assert_eq!(run(get_page_labels(app.state(), path.clone())).unwrap(), ["i", "ii", "1", "2", "3"]);
+
+        // Replaces the earlier ranges instead of adding to them
+        run(set_start_page_number(app.state(), path.clone(), 1, 25, LabelStyle::UpperAlpha)).unwrap();
+        assert_eq!(run(get_page_labels(app.state(), path.clone())).unwrap(), ["Y", "Z", "AA", "BB", "CC"]);
+
+        assert!(run(set_start_page_number(app.state(), path.clone(), 6, 1, LabelStyle::Decimal)).is_err());
+        assert!(run(set_start_page_number(app.state(), path, 2, 0, LabelStyle::Decimal)).is_err());
+    }
+
+    #[test]
+    fn save_pdf_consolidates_multi_stream_pages() {
+        let mut doc = blank_pages(3, 400, 400);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let stream =
+            |doc: &mut Document, content: &[u8]| doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+        let header = stream(&mut doc, b"0 0 1 rg");
+        let body = stream(&mut doc, b"10 10 50 50 re f");
+        let footer = stream(&mut doc, b"0 1 0 rg 100 100 20 20 re f");
+        let indirect = doc.add_object(vec![Object::Reference(header), Object::Reference(footer)]);
+        let contents =
+            [Object::Array(vec![header.into(), body.into()]), Object::Reference(indirect), Object::Reference(header)];
+        for (&page_id, contents) in pages.iter().zip(contents) {
+            doc.get_dictionary_mut(page_id).unwrap().set("Contents", contents);
+        }
+        let path = save_fixture(doc, "multi-stream.pdf");
+        let app = test_app();
+
+        let output = scratch_path("multi-stream-joined.pdf");
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            output.clone(),
+            vec![1, 2, 3],
+            BTreeMap::new(),
+            Vec::new(),
+            Some(true),
+            None,
+            None,
+        ))
+        .unwrap();
+        let saved = Document::load(&output).unwrap();
+        let joined: Vec<String> = saved
+            .get_pages()
+            .into_values()
+            .map(|page_id| {
+                let contents = saved.get_dictionary(page_id).unwrap().get(b"Contents").unwrap();
+                let stream = saved.get_object(contents.as_reference().unwrap()).unwrap().as_stream().unwrap();
+                String::from_utf8(stream.decompressed_content().unwrap_or_else(|_| stream.content.clone())).unwrap()
+            })
+            .collect();
+        assert_eq!(joined, ["0 0 1 rg\n10 10 50 50 re f\n", "0 0 1 rg\n0 1 0 rg 100 100 20 20 re f\n", "0 0 1 rg"]);
+        // The page-1 body and the indirect array are gone; the shared header stays for page 3
+        let stream_count = |doc: &Document| {
+            doc.objects.values().filter(|object| object.as_stream().is_ok_and(|s| !s.dict.has(b"Type"))).count()
+        };
+        assert_eq!(stream_count(&saved), 3);
+        assert!(saved.objects.values().all(|object| object.as_array().is_err()));
+    }
+
+    #[test]
+    fn invert_pages_fixes_a_negative_without_touching_shared_uses() {
+        let mut doc = blank_pages(3, 200, 200);
+        let gray = |doc: &mut Document, background: u8, mark: u8| {
+            // 8x8 gray image with one row of the other shade, like a line of text
+            let pixels: Vec<u8> = (0..64).map(|i| if i / 8 == 4 { mark } else { background }).collect();
+            let image = dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 8, "Height" => 8,
+                "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+            };
+            doc.add_object(lopdf::Stream::new(image, pixels))
+        };
+        let negative = gray(&mut doc, 15, 240);
+        let positive = gray(&mut doc, 240, 15);
+        let form = dictionary! {
+            "Type" => "XObject", "Subtype" => "Form",
+            "BBox" => vec![0.into(), 0.into(), 1.into(), 1.into()],
+            "Resources" => dictionary! { "XObject" => dictionary! { "Neg" => negative } },
+        };
+        let form_id = doc.add_object(lopdf::Stream::new(form, b"/Neg Do".to_vec()));
+        // Every page inherits the same resources from the page tree
+        let pages_id = doc.catalog().unwrap().get(b"Pages").and_then(Object::as_reference).unwrap();
+        let resources =
+            dictionary! { "XObject" => dictionary! { "Neg" => negative, "Pos" => positive, "Scan" => form_id } };
+        doc.get_dictionary_mut(pages_id).unwrap().set("Resources", resources);
+        for (page_id, draw) in doc.get_pages().into_values().zip(["/Neg", "/Pos", "/Scan"]) {
+            let content = doc.add_object(lopdf::Stream::new(
+                dictionary! {},
+                format!("q 200 0 0 200 0 0 cm {} Do Q", draw).into_bytes(),
+            ));
+            doc.get_dictionary_mut(page_id).unwrap().set("Contents", content);
+        }
+        let path = save_fixture(doc, "negative-scan.pdf");
+        let app = test_app();
+
+        let detected = run(detect_inverted_pages(app.state(), path.clone())).unwrap();
+        assert_eq!(detected, [1]);
+        assert_eq!(run(invert_pages(app.state(), path.clone(), detected)).unwrap(), 1);
+        assert!(run(detect_inverted_pages(app.state(), path.clone())).unwrap().is_empty());
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let dark_fraction = |page: u32| {
+                let image = render_for_analysis(doc, doc.get_pages()[&page], DEFAULT_PAGE_SIZE).unwrap();
+                image.pixels().filter(|p| luminance(p) < 96.0).count() as f64 / (image.width() * image.height()) as f64
+            };
+            assert!(dark_fraction(1) < 0.3, "{}", dark_fraction(1));
+            // The form on page 3 still draws the original negative
+            assert!(dark_fraction(3) > 0.6, "{}", dark_fraction(3));
+            assert!(doc.get_object(negative).is_ok());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn exported_annotations_import_back_unchanged() {
+        let mut doc = blank_pages(2, 612, 792);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let note = dictionary! {
+            "Type" => "Annot", "Subtype" => "Text",
+            "Rect" => vec![72.5.into(), 700.25.into(), 96.5.into(), 724.25.into()],
+            "Contents" => Object::string_literal("Check this figure"),
+            "T" => Object::string_literal("Reviewer"), "M" => Object::string_literal("D:20250721101500Z"),
+            "C" => vec![1.into(), 0.8.into(), 0.into()],
+        };
+        let highlight = dictionary! {
+            "Type" => "Annot", "Subtype" => "Highlight",
+            "Rect" => vec![100.into(), 400.into(), 250.into(), 414.into()],
+            "QuadPoints" => vec![100.into(), 414.into(), 250.into(), 414.into(), 100.into(), 400.into(), 250.into(), 400.into()],
+        };
+        let widget = dictionary! { "Type" => "Annot", "Subtype" => "Widget", "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()] };
+        let annots: Vec<Object> = [note, widget].into_iter().map(|annot| doc.add_object(annot).into()).collect();
+        doc.get_dictionary_mut(pages[0]).unwrap().set("Annots", annots);
+        let highlight_id = doc.add_object(highlight);
+        doc.get_dictionary_mut(pages[1]).unwrap().set("Annots", vec![highlight_id.into()]);
+        let path = save_fixture(doc, "review.pdf");
+        let app = test_app();
+
+        let exported = scratch_path("review-annotations.json");
+        assert_eq!(run(export_annotations(app.state(), path.clone(), exported.clone())).unwrap(), 2);
+        let original: Vec<AnnotationData> = serde_json::from_str(&std::fs::read_to_string(&exported).unwrap()).unwrap();
+        assert_eq!(original[0].rect, [72.5, 700.25, 96.5, 724.25]);
+        assert_eq!(original[1].page, 2);
+
+        edit_document(&app.state::<AppState>(), &path, |doc| {
+            for page_id in doc.get_pages().into_values() {
+                doc.get_dictionary_mut(page_id).unwrap().remove(b"Annots");
+            }
+            Ok(())
+        })
+        .unwrap();
+        let emptied = scratch_path("review-empty.json");
+        assert_eq!(run(export_annotations(app.state(), path.clone(), emptied)).unwrap(), 0);
+
+        assert_eq!(run(import_annotations(app.state(), path.clone(), exported)).unwrap(), 2);
+        let reexported = scratch_path("review-reimported.json");
+        run(export_annotations(app.state(), path, reexported.clone())).unwrap();
+        let restored: Vec<AnnotationData> =
+            serde_json::from_str(&std::fs::read_to_string(&reexported).unwrap()).unwrap();
+        assert_eq!(restored, original);
+    }
+
+    #[test]
+    fn rotate_pages_relative_adds_to_each_rotation() {
+        let mut doc = blank_pages(4, 612, 792);
+        // Page 4 inherits its rotation from the page tree
+        let pages_id = doc.catalog().unwrap().get(b"Pages").and_then(Object::as_reference).unwrap();
+        doc.get_dictionary_mut(pages_id).unwrap().set("Rotate", 180);
+        for (page_id, rotate) in doc.get_pages().into_values().zip([0, 90, -90]) {
+            doc.get_dictionary_mut(page_id).unwrap().set("Rotate", rotate);
+        }
+        let path = save_fixture(doc, "mixed-rotations.pdf");
+        let app = test_app();
+        let rotations = || {
+            with_document(&app.state::<AppState>(), &path, |doc| {
+                Ok(doc.get_pages().into_values().map(|id| page_rotation(doc, id)).collect::<Vec<_>>())
+            })
+            .unwrap()
+        };
+        assert_eq!(rotations(), [0, 90, 270, 180]);
+
+        // A page listed twice is only turned once
+        run(rotate_pages_relative(app.state(), path.clone(), vec![1, 2, 3, 4, 2], 90)).unwrap();
+        assert_eq!(rotations(), [90, 180, 0, 270]);
+        run(rotate_pages_relative(app.state(), path.clone(), vec![2, 4], -270)).unwrap();
+        assert_eq!(rotations(), [90, 270, 0, 0]);
+        assert!(run(rotate_pages_relative(app.state(), path.clone(), vec![1], 45)).is_err());
+        assert!(run(rotate_pages_relative(app.state(), path.clone(), vec![5], 90)).is_err());
+        assert_eq!(rotations(), [90, 270, 0, 0]);
+    }
+
+    #[test]
+    fn ink_coverage_ranks_dense_pages_above_sparse_ones() {
+        let mut doc = blank_pages(3, 100, 100);
+        let contents: [&[u8]; 3] =
+            [b"0 0 0 rg 10 10 5 5 re f", b"1 0 0 rg 0 0 100 90 re f", b"0 0 1 rg 0 0 100 50 re f"];
+        for (page_id, content) in doc.get_pages().into_values().zip(contents) {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+            doc.get_dictionary_mut(page_id).unwrap().set("Contents", content_id);
+        }
+        let path = save_fixture(doc, "ink.pdf");
+        let app = test_app();
+
+        let pages = run(ink_coverage(app.state(), path.clone(), None)).unwrap();
+        let coverage: Vec<f64> = pages.iter().map(|page| page.coverage).collect();
+        assert!(coverage[0] < 0.05 && coverage[1] > 0.85 && (coverage[2] - 0.5).abs() < 0.05, "{:?}", coverage);
+        assert!(pages.iter().all(|page| page.channels.is_none()));
+
+        let pages = run(ink_coverage(app.state(), path, Some(true))).unwrap();
+        let red = pages[1].channels.as_ref().unwrap();
+        assert!(red.magenta > 0.85 && red.yellow > 0.85 && red.cyan < 0.05 && red.black < 0.05);
+        let blue = pages[2].channels.as_ref().unwrap();
+        assert!(blue.cyan > 0.45 && blue.yellow < 0.05);
+    }
+
+    #[test]
+    fn set_overprint_black_marks_small_black_text() {
+        let mut doc = blank_pages(4, 612, 792);
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        let contents: [&[u8]; 4] = [
+            b"0 g BT /F1 9 Tf 72 700 Td (Footnote) Tj ET",
+            b"0 g BT /F1 24 Tf 72 700 Td (Heading) Tj ET",
+            b"q 0.5 0 0 0.5 0 0 cm 0 0 0 1 k BT /F1 20 Tf 144 1400 Td (Scaled) Tj ET Q",
+            b"1 0 0 rg BT /F1 8 Tf 72 700 Td (Red) Tj ET",
+        ];
+        for (page_id, content) in doc.get_pages().into_values().zip(contents) {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content_id);
+            page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font_id } });
+        }
+        let path = save_fixture(doc, "black-text.pdf");
+        let app = test_app();
+
+        let output = scratch_path("black-text-overprint.pdf");
+        assert_eq!(run(set_overprint_black(app.state(), path, output.clone())).unwrap(), 2);
+        let saved = Document::load(&output).unwrap();
+        for (page_num, page_id) in saved.get_pages() {
+            let content = String::from_utf8(saved.get_page_content(page_id).unwrap()).unwrap();
+            let states = page_resources(&saved, page_id)
+                .and_then(|resources| resource_category(&saved, resources, b"ExtGState"));
+            if page_num == 1 || page_num == 3 {
+                let on = saved
+                    .get_dictionary(states.unwrap().get(b"OPon1").and_then(Object::as_reference).unwrap())
+                    .unwrap();
+                assert_eq!(
+                    (on.get(b"OP").unwrap(), on.get(b"op").unwrap()),
+                    (&Object::Boolean(true), &Object::Boolean(true))
+                );
+                let show = content.find(") Tj").unwrap();
+                assert!(content[..show].contains("/OPon1 gs") && content[show..].contains("/OPoff1 gs"), "{}", content);
+            } else {
+                assert!(states.is_none() && !content.contains(" gs"), "page {}: {}", page_num, content);
+            }
+        }
+    }
+
+    #[test]
+    fn auto_crop_tightens_pages_to_their_content() {
+        let mut doc = blank_pages(2, 400, 600);
+        let page_id = doc.get_pages()[&1];
+        let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, b"0.2 g 150 250 100 80 re f".to_vec()));
+        doc.get_dictionary_mut(page_id).unwrap().set("Contents", content_id);
+        let path = save_fixture(doc, "centered.pdf");
+        let app = test_app();
+
+        run(auto_crop(app.state(), path.clone(), 10.0)).unwrap();
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let pages = doc.get_pages();
+            let crop = doc.get_dictionary(pages[&1]).unwrap().get(b"CropBox").and_then(Object::as_array).unwrap();
+            let [x0, y0, x1, y1] = <[f64; 4]>::try_from(operand_numbers(crop)).unwrap();
+            // Contains the 150,250 - 250,330 rectangle plus the margin, give or take a pixel
+            for (actual, expected) in [(x0, 140.0), (y0, 240.0), (x1, 260.0), (y1, 340.0)] {
+                assert!((actual - expected).abs() < 1.0, "{:?}", [x0, y0, x1, y1]);
+            }
+            assert!(x0 < 150.0 && y0 < 250.0 && x1 > 250.0 && y1 > 330.0);
+            // The blank page keeps its full MediaBox
+            assert!(!doc.get_dictionary(pages[&2]).unwrap().has(b"CropBox"));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn get_capabilities_matches_the_build() {
+        let capabilities = run(get_capabilities()).unwrap();
+        assert!(capabilities.can_render);
+        assert!(!capabilities.can_ocr && !capabilities.has_qr);
+        assert!(capabilities.can_encrypt);
+        assert_eq!(capabilities.language_detection, cfg!(feature = "language-detection"));
+    }
+
+    #[test]
+    fn deskew_straightens_a_tilted_scan() {
+        let mut doc = blank_pages(2, 200, 260);
+        // A 200x260 px scan whose text lines rise 2 degrees to the right
+        let slope = 2f64.to_radians().tan();
+        let mut pixels = vec![255u8; 200 * 260];
+        for line in 0..10 {
+            for x in 20..180 {
+                let top = 40.0 + line as f64 * 20.0 - x as f64 * slope;
+                for y in top.round() as usize..top.round() as usize + 3 {
+                    pixels[y * 200 + x] = 0;
+                }
+            }
+        }
+        let image = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 200, "Height" => 260,
+            "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+        };
+        let image_id = doc.add_object(lopdf::Stream::new(image, pixels));
+        let page_id = doc.get_pages()[&1];
+        let content_id =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"q 200 0 0 260 0 0 cm /Scan Do Q".to_vec()));
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Contents", content_id);
+        page.set("Resources", dictionary! { "XObject" => dictionary! { "Scan" => image_id } });
+        let path = save_fixture(doc, "skewed.pdf");
+        let app = test_app();
+
+        let corrections = run(deskew(app.state(), path.clone(), 5.0)).unwrap();
+        assert_eq!(corrections.keys().copied().collect::<Vec<_>>(), [1]);
+        assert!((corrections[&1] + 2.0).abs() < 0.3, "{:?}", corrections);
+        // Once straightened there is nothing left to correct
+        assert!(run(deskew(app.state(), path, 5.0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn document_language_round_trips() {
+        let path = save_fixture(blank_pages(1, 612, 792), "language.pdf");
+        let app = test_app();
+        assert_eq!(run(get_document_language(app.state(), path.clone())).unwrap(), None);
+
+        run(set_document_language(app.state(), path.clone(), " fr-CA ".to_string())).unwrap();
+        assert_eq!(run(get_document_language(app.state(), path.clone())).unwrap().as_deref(), Some("fr-CA"));
+        for invalid in ["f", "en_US", "en-", "toolongtag-x"] {
+            assert!(run(set_document_language(app.state(), path.clone(), invalid.to_string())).is_err(), "{}", invalid);
+        }
+        assert_eq!(run(get_document_language(app.state(), path)).unwrap().as_deref(), Some("fr-CA"));
+    }
+
+    #[test]
+    fn load_pdf_reports_rendering_warnings_per_page() {
+        let mut doc = blank_pages(3, 300, 300);
+        let font_id =
+            doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "Garamond" });
+        let contents: [&[u8]; 3] = [
+            b"BT /Body 11 Tf 20 250 Td (Not embedded) Tj ET",
+            b"0 0 1 rg 20 20 100 100 re f",
+            b"/Sh0 sh BT /Missing 10 Tf (x) Tj ET",
+        ];
+        for (page_id, content) in doc.get_pages().into_values().zip(contents) {
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.to_vec()));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content_id);
+            page.set("Resources", dictionary! { "Font" => dictionary! { "Body" => font_id } });
+        }
+        let path = save_fixture(doc, "warnings.pdf");
+        let app = test_app();
+
+        let info = run(load_pdf(app.state(), path)).unwrap();
+        let warnings: Vec<&[String]> = info.pages.iter().map(|page| page.warnings.as_slice()).collect();
+        assert_eq!(warnings[0], ["substituted font Garamond"]);
+        assert!(warnings[1].is_empty());
+        assert_eq!(warnings[2], ["unsupported shading", "missing font resource /Missing"]);
+    }
+
+    #[test]
+    fn merge_pdfs_can_unify_page_sizes() {
+        let a4 = save_fixture(blank_pages(1, 595, 842), "unify-a4.pdf");
+        let mut letter = blank_pages(2, 612, 792);
+        let landscape = letter.get_pages()[&2];
+        letter.get_dictionary_mut(landscape).unwrap().set("MediaBox", vec![0.into(), 0.into(), 792.into(), 612.into()]);
+        let letter = save_fixture(letter, "unify-letter.pdf");
+        let app = test_app();
+        let sizes = |path: &str| {
+            let doc = Document::load(path).unwrap();
+            doc.get_pages()
+                .into_values()
+                .map(|id| {
+                    let [x0, y0, x1, y1] = page_visible_box(&doc, id).unwrap();
+                    ((x1 - x0).round() as i64, (y1 - y0).round() as i64)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let output = scratch_path("unified.pdf");
+        run(merge_pdfs(
+            app.handle().clone(),
+            vec![a4.clone(), letter.clone()],
+            output.clone(),
+            Some(PageSizePreset::A4),
+            None,
+        ))
+        .unwrap();
+        assert_eq!(sizes(&output), [(595, 842); 3]);
+
+        let output = scratch_path("not-unified.pdf");
+        run(merge_pdfs(app.handle().clone(), vec![a4, letter], output.clone(), None, None)).unwrap();
+        assert_eq!(sizes(&output), [(595, 842), (612, 792), (792, 612)]);
+    }
+
+    #[test]
+    fn verify_save_checks_reordered_pages_against_their_sources() {
+        let mut doc = blank_pages(3, 300, 400);
+        let font_id = doc.add_object(standard_font("Helvetica"));
+        for (index, page_id) in doc.get_pages().into_values().enumerate() {
+            let content = format!(
+                "0 0 1 rg {} 50 60 60 re f BT /F1 14 Tf 40 300 Td (Section {}) Tj ET",
+                40 + index * 70,
+                index + 1
+            );
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content.into_bytes()));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content_id);
+            page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font_id } });
+        }
+        let path = save_fixture(doc, "verify-source.pdf");
+        let app = test_app();
+
+        let output = scratch_path("verify-reordered.pdf");
+        let order = vec![3, 1, 2];
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            output.clone(),
+            order.clone(),
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        let report = run(verify_save(app.state(), path.clone(), output.clone(), Some(order.clone()))).unwrap();
+        assert!(report.passed, "{:?}", report.regressions);
+        assert_eq!(report.pages.iter().map(|page| page.source_page).collect::<Vec<_>>(), order);
+        assert!(report.pages.iter().all(|page| page.text_match && page.dimensions_match && page.similarity > 0.999));
+
+        // Compared one to one the reordered pages no longer line up
+        let report = run(verify_save(app.state(), path.clone(), output.clone(), None)).unwrap();
+        assert!(!report.passed);
+
+        // A save that drops a page's text and shrinks another page
+        let mut broken = Document::load(&output).unwrap();
+        let pages = broken.get_pages();
+        let shapes_only = broken.add_object(lopdf::Stream::new(dictionary! {}, b"0 0 1 rg 180 50 60 60 re f".to_vec()));
+        broken.get_dictionary_mut(pages[&1]).unwrap().set("Contents", shapes_only);
+        broken.get_dictionary_mut(pages[&2]).unwrap().set("MediaBox", vec![0.into(), 0.into(), 300.into(), 300.into()]);
+        broken.save(&output).unwrap();
+        let report = run(verify_save(app.state(), path, output, Some(order))).unwrap();
+        assert!(!report.passed);
+        assert!(!report.pages[0].text_match && report.pages[0].dimensions_match);
+        assert!(!report.pages[1].dimensions_match);
+        assert!(report.pages[2].text_match && report.pages[2].dimensions_match);
+        assert!(
+            report.regressions.contains(&"page 2: size changed from 300.0x400.0 to 300.0x300.0".to_string()),
+            "{:?}",
+            report.regressions
+        );
+    }
+
+    // A minimal TrueType program mapping each character to its own triangle glyph
+    // (glyph 0 is .notdef), with the given advance widths in 1000 units per em
+    fn truetype_program(glyphs: &[(char, u16)]) -> Vec<u8> {
+        let be16 = |value: u16| value.to_be_bytes();
+        let count = glyphs.len() as u16 + 1;
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for index in 0..count {
+            loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+            let size = 100 + index * 10;
+            // One contour of three on-curve points, coordinates as 16-bit deltas
+            for value in [1, 0, 0, size, size, 2, 0] {
+                glyf.extend_from_slice(&be16(value));
+            }
+            glyf.extend_from_slice(&[1, 1, 1]);
+            for value in [0, size, 0, 0, 0, size] {
+                glyf.extend_from_slice(&be16(value));
+            }
+            glyf.resize(glyf.len().div_ceil(4) * 4, 0);
+        }
+        loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+
+        let mut head = vec![0u8; 54];
+        head[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&be16(1000));
+        head[40..44].copy_from_slice(&[0x03, 0xE8, 0x03, 0xE8]);
+        head[50..52].copy_from_slice(&be16(1));
+        let mut hhea = vec![0u8; 36];
+        hhea[..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[4..6].copy_from_slice(&be16(800));
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&be16(count));
+        let mut maxp = 0x0000_5000u32.to_be_bytes().to_vec();
+        maxp.extend_from_slice(&be16(count));
+        let mut hmtx = be16(500).to_vec();
+        hmtx.extend_from_slice(&be16(0));
+        for &(_, advance) in glyphs {
+            hmtx.extend_from_slice(&be16(advance));
+            hmtx.extend_from_slice(&be16(0));
+        }
+
+        // Format 4 cmap with one segment per character plus the closing 0xFFFF segment
+        let segments = glyphs.len() + 1;
+        let mut ends = Vec::new();
+        let mut starts = Vec::new();
+        let mut deltas = Vec::new();
+        for (index, &(c, _)) in glyphs.iter().enumerate() {
+            ends.extend_from_slice(&be16(c as u16));
+            starts.extend_from_slice(&be16(c as u16));
+            deltas.extend_from_slice(&be16((index as u16 + 1).wrapping_sub(c as u16)));
+        }
+        ends.extend_from_slice(&be16(0xFFFF));
+        starts.extend_from_slice(&be16(0xFFFF));
+        deltas.extend_from_slice(&be16(1));
+        let mut cmap = [be16(0), be16(1), be16(3), be16(1)].concat();
+        cmap.extend_from_slice(&12u32.to_be_bytes());
+        for value in [4, 16 + segments as u16 * 8, 0, segments as u16 * 2, 0, 0, 0] {
+            cmap.extend_from_slice(&be16(value));
+        }
+        cmap.extend(ends.into_iter().chain(be16(0)).chain(starts).chain(deltas));
+        cmap.resize(cmap.len() + segments * 2, 0);
+
+        let tables = [
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        let mut program = 0x0001_0000u32.to_be_bytes().to_vec();
+        for value in [tables.len() as u16, 0, 0, 0] {
+            program.extend_from_slice(&be16(value));
+        }
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, data) in &tables {
+            program.extend_from_slice(*tag);
+            program.extend_from_slice(&0u32.to_be_bytes());
+            program.extend_from_slice(&(offset as u32).to_be_bytes());
+            program.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len().div_ceil(4) * 4;
+        }
+        for (_, data) in &tables {
+            program.extend_from_slice(data);
+            program.resize(program.len().div_ceil(4) * 4, 0);
+        }
+        program
+    }
+
+    #[test]
+    fn embed_bundled_fonts_embeds_a_tagged_subset() {
+        let font_dir = std::path::PathBuf::from(scratch_path("bundled-fonts"));
+        std::fs::create_dir_all(&font_dir).unwrap();
+        let alphabet: Vec<(char, u16)> = (' '..='z').map(|c| (c, 400 + (c as u16 % 7) * 50)).collect();
+        let program = truetype_program(&alphabet);
+        std::fs::write(font_dir.join("LiberationSans-Regular.ttf"), &program).unwrap();
+
+        let mut doc = blank_pages(1, 400, 200);
+        let page_id = doc.get_pages()[&1];
+        let helvetica = doc.add_object(standard_font("Helvetica"));
+        let content_id =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 12 Tf 20 100 Td (Hello) Tj ET".to_vec()));
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Contents", content_id);
+        page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => helvetica } });
+        let text_before = extract_page_text(&doc, 1).unwrap();
+        let before = render_for_analysis(&doc, page_id, DEFAULT_PAGE_SIZE).unwrap();
+
+        assert_eq!(embed_bundled_fonts(&mut doc, &font_dir).unwrap(), 1);
+        let font = doc.get_dictionary(helvetica).unwrap();
+        let base_font = String::from_utf8(font.get(b"BaseFont").and_then(Object::as_name).unwrap().to_vec()).unwrap();
+        assert!(is_subset_font_name(&base_font) && base_font.ends_with("+Helvetica"), "{}", base_font);
+        assert_eq!(font.get(b"Subtype").and_then(Object::as_name).unwrap(), b"TrueType");
+        let descriptor =
+            doc.get_dictionary(font.get(b"FontDescriptor").and_then(Object::as_reference).unwrap()).unwrap();
+        assert_eq!(descriptor.get(b"FontName").and_then(Object::as_name).unwrap(), base_font.as_bytes());
+        let file = doc
+            .get_object(descriptor.get(b"FontFile2").and_then(Object::as_reference).unwrap())
+            .unwrap()
+            .as_stream()
+            .unwrap();
+        let embedded = file.decompressed_content().unwrap();
+        assert!(embedded.len() < program.len());
+        // Only .notdef and the glyphs of "Helo" keep their outlines
+        let glyph_count = (truetype_table(&embedded, b"loca").unwrap().len() / 4 - 1) as u32;
+        let kept: std::collections::BTreeSet<u32> =
+            [0u32].into_iter().chain("Helo".chars().map(|c| c as u32 - 31)).collect();
+        let full = std::collections::BTreeSet::from_iter(0..glyph_count);
+        assert_eq!(subset_truetype_glyphs(&program, &kept).unwrap(), embedded);
+        assert_ne!(subset_truetype_glyphs(&program, &full).unwrap(), embedded);
+
+        assert_eq!(extract_page_text(&doc, 1).unwrap(), text_before);
+        let after = render_for_analysis(&doc, page_id, DEFAULT_PAGE_SIZE).unwrap();
+        assert!(render_similarity(&before, &after) > 0.99);
+
+        // A font the form's default resources offer for field text is embedded whole
+        let mut form = single_field_form("Name", "Ada", ("Helv", "Helvetica"));
+        assert_eq!(embed_bundled_fonts(&mut form, &font_dir).unwrap(), 1);
+        let file = form.objects.values().find_map(|object| object.as_dict().ok()?.get(b"FontFile2").ok()).unwrap();
+        let file = form.get_object(file.as_reference().unwrap()).unwrap().as_stream().unwrap();
+        assert_eq!(file.decompressed_content().unwrap(), program);
+
+        // Fonts this build does not bundle are reported rather than silently skipped
+        let mut times = blank_pages(1, 100, 100);
+        times.add_object(standard_font("Times-Roman"));
+        assert!(embed_bundled_fonts(&mut times, &font_dir).unwrap_err().starts_with("Font Times-Roman is not bundled"));
+    }
+
+    #[cfg(feature = "bundled-fonts")]
+    #[test]
+    fn bundled_fonts_cover_every_standard_substitute() {
+        let font_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts");
+        assert!(font_dir.join("LICENSE").is_file(), "the font license must ship with the fonts");
+        for (base_font, file) in STANDARD_FONT_FILES {
+            let program = std::fs::read(font_dir.join(file)).unwrap_or_else(|e| panic!("{}: {}", file, e));
+            assert!(truetype_table(&program, b"cmap").is_some(), "{} ({}) is not a TrueType font", file, base_font);
+        }
+
+        let mut doc = blank_pages(1, 300, 120);
+        let page_id = doc.get_pages()[&1];
+        let (sans, mono) = (doc.add_object(standard_font("Helvetica")), doc.add_object(standard_font("Courier-Bold")));
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "Font" => dictionary! { "F1" => sans, "F2" => mono } });
+        set_page_content(
+            &mut doc,
+            page_id,
+            b"BT /F1 14 Tf 20 80 Td (Total due) Tj /F2 12 Tf 0 -30 Td (EUR 42) Tj ET".to_vec(),
+        )
+        .unwrap();
+        let text_before = extract_page_text(&doc, 1).unwrap();
+        assert_eq!(embed_bundled_fonts(&mut doc, &font_dir).unwrap(), 2);
+        for font in [sans, mono] {
+            let descriptor = font_descriptor(&doc, doc.get_dictionary(font).unwrap()).unwrap();
+            assert!(descriptor.has(b"FontFile2"));
+        }
+        assert_eq!(extract_page_text(&doc, 1).unwrap(), text_before);
+    }
+
+    #[test]
+    fn named_destinations_resolve_to_pages() {
+        let mut doc = blank_pages(3, 612, 792);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        // Name tree with a kid node, one entry wrapped in a /D dictionary
+        let leaf_id = doc.add_object(dictionary! {
+            "Names" => vec![
+                Object::string_literal("appendix"),
+                dictionary! { "D" => vec![pages[2].into(), "FitH".into(), 500.into()] }.into(),
+                Object::string_literal("intro"),
+                vec![pages[0].into(), "XYZ".into(), 72.into(), 720.into(), 0.into()].into(),
+            ],
+            "Limits" => vec![Object::string_literal("appendix"), Object::string_literal("intro")],
+        });
+        let orphan = vec![Object::Reference((999, 0)), "Fit".into()];
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("Names", dictionary! { "Dests" => dictionary! { "Kids" => vec![leaf_id.into()] } });
+        catalog.set("Dests", dictionary! { "chapter1" => vec![pages[1].into(), "Fit".into()], "gone" => orphan });
+        let path = save_fixture(doc, "named-dests.pdf");
+        let app = test_app();
+
+        let dests = run(get_named_destinations(app.state(), path)).unwrap();
+        let summary: Vec<(&str, Option<usize>, &str)> = dests
+            .iter()
+            .map(|dest| (dest.name.as_str(), dest.page_number, dest.position.as_ref().map_or("", |p| p.fit.as_str())))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("appendix", Some(3), "FitH"),
+                ("chapter1", Some(2), "Fit"),
+                ("gone", None, "Fit"),
+                ("intro", Some(1), "XYZ")
+            ]
+        );
+        let intro = dests[3].position.as_ref().unwrap();
+        assert_eq!((intro.left, intro.top, intro.zoom), (Some(72.0), Some(720.0), None));
+        assert_eq!(dests[0].position.as_ref().unwrap().top, Some(500.0));
+    }
+
+    #[test]
+    fn set_permissions_only_restricts_without_a_user_password() {
+        let mut doc = blank_pages(1, 300, 300);
+        doc.version = "1.3".to_string();
+        let page_id = doc.get_pages()[&1];
+        let font_id = doc.add_object(standard_font("Courier"));
+        let content_id =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 10 Tf 20 200 Td (Draft terms) Tj ET".to_vec()));
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Contents", content_id);
+        page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font_id } });
+        let digest = vec![0x30, 0x82, 0x5A, 0x17, 0xC3];
+        doc.add_object(dictionary! {
+            "Type" => "Sig", "Filter" => "Adobe.PPKLite",
+            "ByteRange" => vec![0.into(), 10.into(), 20.into(), 30.into()],
+            "Contents" => Object::String(digest.clone(), lopdf::StringFormat::Hexadecimal),
+            "Reason" => Object::string_literal("Approved"),
+        });
+        let path = save_fixture(doc, "restrict.pdf");
+        let app = test_app();
+        assert_eq!(
+            run(get_permissions(app.state(), path.clone())).unwrap().to_flags(),
+            Permissions::from_flags(-1).to_flags()
+        );
+
+        let output = scratch_path("restricted.pdf");
+        let perms = Permissions {
+            print: true,
+            modify: false,
+            copy: false,
+            annotate: false,
+            fill_forms: true,
+            extract_for_accessibility: true,
+            assemble: false,
+            print_high_quality: false,
+        };
+        assert!(run(set_permissions_only(app.state(), path.clone(), output.clone(), String::new(), perms)).is_err());
+        run(set_permissions_only(app.state(), path, output.clone(), "owner".to_string(), perms)).unwrap();
+
+        let raw = std::fs::read(&output).unwrap();
+        assert!(raw.starts_with(b"%PDF-1.4"));
+        let contains = |needle: &[u8]| raw.windows(needle.len()).any(|window| window == needle);
+        assert!(!contains(b"Draft terms") && !contains(b"Approved"));
+        assert!(contains(b"<30825A17C3>"), "signature digest was encrypted");
+
+        // Opens with no password and reports the restrictions
+        let restricted = scratch_path("restricted-copy.pdf");
+        std::fs::copy(&output, &restricted).unwrap();
+        assert_eq!(run(get_permissions(app.state(), restricted)).unwrap().to_flags(), perms.to_flags());
+        // lopdf's own security handler decrypts it with the empty user password
+        let mut opened = Document::load(&output).unwrap();
+        opened.decrypt("").unwrap();
+        assert!(extract_page_text(&opened, 1).unwrap().contains("Draft terms"));
+        let signature = opened.objects.values().find_map(|object| {
+            let dict = object.as_dict().ok()?;
+            (dict.get(b"Type").and_then(Object::as_name).ok()? == b"Sig").then_some(dict)
+        });
+        let signature = signature.unwrap();
+        assert_eq!(signature.get(b"Contents").and_then(Object::as_str).unwrap(), digest);
+    }
+
+    #[test]
+    fn regenerate_appearances_fills_in_missing_ap_streams() {
+        let mut doc = blank_pages(2, 612, 792);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let existing_ap = dictionary! { "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 40.into(), 40.into()] };
+        let existing_ap = doc.add_object(lopdf::Stream::new(existing_ap, b"0 g 0 0 40 40 re S".to_vec()));
+        let first_page = [
+            dictionary! {
+                "Subtype" => "Highlight", "Rect" => vec![100.into(), 600.into(), 300.into(), 620.into()],
+                "QuadPoints" => vec![100.into(), 620.into(), 300.into(), 620.into(), 100.into(), 600.into(), 300.into(), 600.into()],
+                "C" => vec![1.into(), 1.into(), 0.into()],
+            },
+            dictionary! {
+                "Subtype" => "FreeText", "Rect" => vec![100.into(), 400.into(), 300.into(), 460.into()],
+                "Contents" => Object::string_literal("Please revise this paragraph"),
+                "DA" => Object::string_literal("/Helv 11 Tf 0 0 1 rg"),
+            },
+            dictionary! {
+                "Subtype" => "Square", "Rect" => vec![50.into(), 50.into(), 90.into(), 90.into()],
+                "AP" => dictionary! { "N" => existing_ap },
+            },
+            dictionary! { "Subtype" => "Popup", "Rect" => vec![300.into(), 300.into(), 400.into(), 350.into()] },
+        ];
+        let link = dictionary! {
+            "Subtype" => "Link", "Rect" => vec![72.into(), 72.into(), 200.into(), 90.into()],
+            "Border" => vec![0.into(), 0.into(), 1.into()], "C" => vec![0.into(), 0.into(), 1.into()],
+            "Dest" => vec![pages[0].into(), "Fit".into()],
+        };
+        for (page_id, annots) in pages.iter().zip([first_page.to_vec(), vec![link]]) {
+            let annots: Vec<Object> = annots
+                .into_iter()
+                .map(|mut annot| {
+                    annot.set("Type", "Annot");
+                    doc.add_object(annot).into()
+                })
+                .collect();
+            doc.get_dictionary_mut(*page_id).unwrap().set("Annots", annots);
+        }
+        let path = save_fixture(doc, "no-appearances.pdf");
+        let app = test_app();
+
+        assert_eq!(run(regenerate_appearances(app.state(), path.clone(), Some(vec![1]))).unwrap(), 2);
+        assert_eq!(run(regenerate_appearances(app.state(), path.clone(), None)).unwrap(), 1);
+        assert_eq!(run(regenerate_appearances(app.state(), path.clone(), None)).unwrap(), 0);
+
+        with_document(&app.state::<AppState>(), &path, |doc| {
+            let mut subtypes = Vec::new();
+            for page_id in doc.get_pages().into_values() {
+                for annot in page_annotations(doc, page_id) {
+                    let annot = doc.get_dictionary(annot.as_reference().unwrap()).unwrap();
+                    let subtype = annot.get(b"Subtype").and_then(Object::as_name).unwrap();
+                    let normal = annot.get(b"AP").and_then(Object::as_dict).and_then(|ap| ap.get(b"N"));
+                    let normal = match normal.and_then(Object::as_reference) {
+                        Ok(id) => doc.get_object(id).and_then(Object::as_stream).unwrap(),
+                        Err(_) => continue,
+                    };
+                    assert!(normal.dict.has(b"BBox"));
+                    let content = normal.decompressed_content().unwrap_or_else(|_| normal.content.clone());
+                    assert!(!content.is_empty());
+                    subtypes.push((String::from_utf8_lossy(subtype).into_owned(), normal.dict.has(b"Resources")));
+                }
+            }
+            let subtypes: Vec<(&str, bool)> = subtypes.iter().map(|(s, r)| (s.as_str(), *r)).collect();
+            assert_eq!(subtypes[..2], [("Highlight", true), ("FreeText", true)]);
+            assert_eq!(subtypes[2].0, "Square");
+            assert_eq!(subtypes[3].0, "Link");
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn dedup_resources_shares_identical_logos_and_fonts() {
+        let mut doc = blank_pages(3, 200, 200);
+        let logo_pixels: Vec<u8> = (0..48u8).map(|i| i * 5).collect();
+        let program = truetype_program(&[('A', 600), ('B', 650)]);
+        for page_id in doc.get_pages().into_values() {
+            let logo = dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 4, "Height" => 4,
+                "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8,
+            };
+            let logo_id = doc.add_object(lopdf::Stream::new(logo, logo_pixels.clone()));
+            let program_id =
+                doc.add_object(lopdf::Stream::new(dictionary! { "Length1" => program.len() as i64 }, program.clone()));
+            let descriptor_id = doc.add_object(dictionary! {
+                "Type" => "FontDescriptor", "FontName" => "Sample", "Flags" => 32, "FontFile2" => program_id,
+            });
+            let font_id = doc.add_object(dictionary! {
+                "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "Sample", "FontDescriptor" => descriptor_id,
+            });
+            let content = b"q 50 0 0 50 10 140 cm /Logo Do Q BT /F1 12 Tf 10 20 Td (AB) Tj ET".to_vec();
+            let content_id = doc.add_object(lopdf::Stream::new(dictionary! {}, content));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content_id);
+            page.set(
+                "Resources",
+                dictionary! {
+                    "XObject" => dictionary! { "Logo" => logo_id },
+                    "Font" => dictionary! { "F1" => font_id },
+                },
+            );
+        }
+        // A different image with the same dictionary is not a duplicate
+        let other = dictionary! {
+            "Type" => "XObject", "Subtype" => "Image", "Width" => 4, "Height" => 4,
+            "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8,
+        };
+        let other_id = doc.add_object(lopdf::Stream::new(other, vec![9; 48]));
+        let last_page = doc.get_pages()[&3];
+        let resources =
+            doc.get_dictionary_mut(last_page).unwrap().get_mut(b"Resources").unwrap().as_dict_mut().unwrap();
+        resources.get_mut(b"XObject").unwrap().as_dict_mut().unwrap().set("Other", other_id);
+        let before: Vec<image::RgbImage> =
+            doc.get_pages().into_values().map(|id| render_for_analysis(&doc, id, DEFAULT_PAGE_SIZE).unwrap()).collect();
+        let path = save_fixture(doc, "repeated-logo.pdf");
+        let app = test_app();
+
+        let output = scratch_path("repeated-logo-dedup.pdf");
+        let report = run(dedup_resources(app.state(), path, output.clone())).unwrap();
+        // Two spare copies each of the logo, the font program, its descriptor and the font
+        assert_eq!(report.objects_removed, 8);
+        assert!(report.bytes_saved >= 2 * (logo_pixels.len() + program.len()) as u64, "{}", report.bytes_saved);
+
+        let deduped = Document::load(&output).unwrap();
+        let resource = |page_id: ObjectId, category: &[u8], name: &[u8]| {
+            let resources = page_resources(&deduped, page_id).unwrap();
+            resource_category(&deduped, resources, category).unwrap().get(name).and_then(Object::as_reference).unwrap()
+        };
+        let pages: Vec<ObjectId> = deduped.get_pages().into_values().collect();
+        let logos: std::collections::HashSet<ObjectId> =
+            pages.iter().map(|&id| resource(id, b"XObject", b"Logo")).collect();
+        let fonts: std::collections::HashSet<ObjectId> = pages.iter().map(|&id| resource(id, b"Font", b"F1")).collect();
+        assert_eq!((logos.len(), fonts.len()), (1, 1));
+        assert_ne!(resource(pages[2], b"XObject", b"Other"), *logos.iter().next().unwrap());
+        for (page_id, before) in pages.iter().zip(&before) {
+            let after = render_for_analysis(&deduped, *page_id, DEFAULT_PAGE_SIZE).unwrap();
+            assert_eq!(render_similarity(before, &after), 1.0);
+        }
+    }
+
+    #[test]
+    fn add_printer_marks_draws_in_the_bleed_margin() {
+        let mut doc = blank_pages(2, 400, 600);
+        let cropped = doc.get_pages()[&2];
+        doc.get_dictionary_mut(cropped).unwrap().set("CropBox", vec![50.into(), 50.into(), 350.into(), 550.into()]);
+        let path = save_fixture(doc, "trim.pdf");
+        let app = test_app();
+        assert!(run(add_printer_marks(app.state(), path.clone(), scratch_path("no-bleed.pdf"), 0.0, None)).is_err());
+
+        let output = scratch_path("trim-marks.pdf");
+        run(add_printer_marks(app.state(), path.clone(), output.clone(), 18.0, None)).unwrap();
+        let marked = Document::load(&output).unwrap();
+        let pages: Vec<ObjectId> = marked.get_pages().into_values().collect();
+        let page_box = |page_id: ObjectId, key: &[u8]| {
+            operand_numbers(marked.get_dictionary(page_id).unwrap().get(key).and_then(Object::as_array).unwrap())
+        };
+        assert_eq!(page_box(pages[0], b"MediaBox"), [-18.0, -18.0, 418.0, 618.0]);
+        assert_eq!(page_box(pages[0], b"TrimBox"), [0.0, 0.0, 400.0, 600.0]);
+        assert_eq!(page_box(pages[1], b"MediaBox"), [32.0, 32.0, 368.0, 568.0]);
+        assert_eq!(page_box(pages[1], b"TrimBox"), [50.0, 50.0, 350.0, 550.0]);
+        assert_eq!(page_box(pages[1], b"BleedBox"), page_box(pages[1], b"MediaBox"));
+        assert!(!marked.get_dictionary(pages[1]).unwrap().has(b"CropBox"));
+
+        // Ink only in the 18 point margin, none inside the trim box
+        let image = render_page(&marked, pages[0], 1.0, None, DEFAULT_PAGE_SIZE).unwrap();
+        let (mut margin, mut inside) = (0, 0);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel.0.iter().all(|&c| c >= PAPER_WHITE) {
+                continue;
+            }
+            if (18..418).contains(&x) && (18..618).contains(&y) {
+                inside += 1;
+            } else {
+                margin += 1;
+            }
+        }
+        assert!(margin > 0 && inside == 0, "{} {}", margin, inside);
+        let content = String::from_utf8(marked.get_page_content(pages[0]).unwrap()).unwrap();
+        assert!(content.contains("1 1 1 1 K") && !content.contains(" re f"));
+
+        run(add_printer_marks(app.state(), path, output.clone(), 18.0, Some(true))).unwrap();
+        let marked = Document::load(&output).unwrap();
+        let content = String::from_utf8(marked.get_page_content(marked.get_pages()[&1]).unwrap()).unwrap();
+        assert!(content.contains("1.00 0.00 0.00 0.00 k") && content.contains(" re f"));
+    }
+
+    #[test]
+    fn split_spreads_halves_wide_pages() {
+        let mut doc = blank_pages(3, 400, 600);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        doc.get_dictionary_mut(pages[1]).unwrap().set("MediaBox", vec![0.into(), 0.into(), 1000.into(), 700.into()]);
+        // A note near the right edge of the spread moves to the right-hand page
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Text", "P" => pages[1],
+            "Rect" => vec![900.into(), 600.into(), 920.into(), 620.into()],
+        });
+        doc.get_dictionary_mut(pages[1]).unwrap().set("Annots", vec![note.into()]);
+        let path = save_fixture(doc, "spread.pdf");
+        let app = test_app();
+
+        let output = scratch_path("spread-split.pdf");
+        assert_eq!(run(split_spreads(app.state(), path.clone(), output.clone(), 0.0)).unwrap(), 1);
+        let split = Document::load(&output).unwrap();
+        let boxes: Vec<[f64; 4]> =
+            split.get_pages().into_values().map(|id| page_visible_box(&split, id).unwrap()).collect();
+        assert_eq!(
+            boxes,
+            [[0.0, 0.0, 400.0, 600.0], [0.0, 0.0, 500.0, 700.0], [500.0, 0.0, 1000.0, 700.0], [0.0, 0.0, 400.0, 600.0]]
+        );
+        let right = split.get_pages()[&3];
+        let annots = page_annotations(&split, right);
+        assert_eq!(annots.len(), 1);
+        let note = split.get_dictionary(annots[0].as_reference().unwrap()).unwrap();
+        assert_eq!(note.get(b"P").and_then(Object::as_reference).unwrap(), right);
+        assert!(page_annotations(&split, split.get_pages()[&2]).is_empty());
+
+        // A gutter drops the middle tenth of the spread
+        assert_eq!(run(split_spreads(app.state(), path.clone(), output.clone(), 0.1)).unwrap(), 1);
+        let split = Document::load(&output).unwrap();
+        let widths: Vec<[f64; 4]> =
+            split.get_pages().into_values().map(|id| page_visible_box(&split, id).unwrap()).collect();
+        assert_eq!(widths[1..3], [[0.0, 0.0, 450.0, 700.0], [550.0, 0.0, 1000.0, 700.0]]);
+        assert!(run(split_spreads(app.state(), path, output, 0.5)).is_err());
+    }
+
+    #[test]
+    fn document_stats_counts_images_and_fonts() {
+        let mut doc = blank_pages(1, 200, 200);
+        for name in ["Helvetica", "Courier", "Times-Roman"] {
+            doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => name });
+        }
+        for side in [2, 3] {
+            let pixels = vec![0x80u8; side * side];
+            doc.add_object(lopdf::Stream::new(
+                dictionary! {
+                    "Type" => "XObject", "Subtype" => "Image", "Width" => side as i64, "Height" => side as i64,
+                    "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+                },
+                pixels,
+            ));
+        }
+        // A highly repetitive stream stored with Flate
+        let mut packed = lopdf::Stream::new(dictionary! {}, b"0 0 m 10 10 l S\n".repeat(200));
+        packed.compress().unwrap();
+        doc.add_object(packed);
+        let path = save_fixture(doc, "stats.pdf");
+        let app = test_app();
+
+        let stats = run(get_document_stats(app.state(), path)).unwrap();
+        assert_eq!((stats.images, stats.fonts), (2, 3));
+        assert!(stats.streams >= 4);
+        assert!(stats.stream_bytes_decoded >= stats.stream_bytes_encoded + 3000);
+        assert!(stats.largest.windows(2).all(|pair| pair[0].bytes >= pair[1].bytes));
+        assert!(stats.largest.iter().any(|object| object.kind == "XObject/Image"));
+    }
+
+    #[test]
+    fn clear_rotation_zeroes_selected_pages() {
+        let mut doc = blank_pages(3, 300, 400);
+        // Rotation inherited from the page tree, plus an explicit one on the last page
+        let root = doc.catalog().unwrap().get(b"Pages").and_then(Object::as_reference).unwrap();
+        doc.get_dictionary_mut(root).unwrap().set("Rotate", 90);
+        let last = doc.get_pages()[&3];
+        doc.get_dictionary_mut(last).unwrap().set("Rotate", 270);
+        let path = save_fixture(doc, "rotated.pdf");
+        let app = test_app();
+
+        run(clear_rotation(app.state(), path.clone(), Some(vec![1, 3]))).unwrap();
+        let rotations = with_document(&app.state(), &path, |doc| {
+            Ok(doc.get_pages().into_values().map(|id| page_rotation(doc, id)).collect::<Vec<_>>())
+        })
+        .unwrap();
+        assert_eq!(rotations, [0, 90, 0]);
+        let first = with_document(&app.state(), &path, |doc| {
+            Ok(doc.get_dictionary(doc.get_pages()[&1]).unwrap().get(b"Rotate").and_then(Object::as_i64).unwrap())
+        })
+        .unwrap();
+        assert_eq!(first, 0);
+
+        run(clear_rotation(app.state(), path.clone(), None)).unwrap();
+        let rotations = with_document(&app.state(), &path, |doc| {
+            Ok(doc.get_pages().into_values().map(|id| page_rotation(doc, id)).collect::<Vec<_>>())
+        })
+        .unwrap();
+        assert_eq!(rotations, [0, 0, 0]);
+        assert!(run(clear_rotation(app.state(), path, Some(vec![4]))).is_err());
+    }
+
+    #[test]
+    fn set_page_order_applies_a_permutation() {
+        let mut doc = blank_pages(3, 100, 100);
+        // Tell the pages apart by width
+        for (page_num, page_id) in doc.get_pages() {
+            let width = 100 * page_num as i64;
+            doc.get_dictionary_mut(page_id)
+                .unwrap()
+                .set("MediaBox", vec![0.into(), 0.into(), width.into(), 100.into()]);
+        }
+        let path = save_fixture(doc, "order.pdf");
+        let app = test_app();
+        let widths = |app: &tauri::App<MockRuntime>| {
+            with_document(&app.state(), &path, |doc| {
+                Ok(doc.get_pages().into_values().map(|id| page_visible_box(doc, id).unwrap()[2]).collect::<Vec<_>>())
+            })
+            .unwrap()
+        };
+
+        run(set_page_order(app.state(), path.clone(), vec![3, 1, 2])).unwrap();
+        assert_eq!(widths(&app), [300.0, 100.0, 200.0]);
+
+        // Rejected orders leave the document as it was
+        for order in [vec![3, 1, 1], vec![1, 2], vec![1, 2, 4], vec![0, 1, 2]] {
+            assert!(run(set_page_order(app.state(), path.clone(), order)).is_err());
+        }
+        assert_eq!(widths(&app), [300.0, 100.0, 200.0]);
+    }
+
+    #[test]
+    fn export_page_svg_sizes_root_to_the_page() {
+        let mut doc = blank_pages(2, 250, 400);
+        let font =
+            doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica-Bold" });
+        let image = doc.add_object(lopdf::Stream::new(
+            dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 2, "Height" => 1,
+                "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8,
+            },
+            vec![255, 0, 0, 0, 0, 255],
+        ));
+        let pages = doc.get_pages();
+        doc.get_dictionary_mut(pages[&1]).unwrap().set(
+            "Resources",
+            dictionary! { "Font" => dictionary! { "F1" => font }, "XObject" => dictionary! { "Im1" => image } },
+        );
+        let content =
+            b"0 0 1 rg 20 20 100 50 re f BT /F1 12 Tf 30 300 Td (Hello) Tj ET q 40 0 0 20 50 200 cm /Im1 Do Q".to_vec();
+        set_page_content(&mut doc, pages[&1], content).unwrap();
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("Rotate", 90);
+        let path = save_fixture(doc, "svg.pdf");
+        let app = test_app();
+
+        let output = scratch_path("page.svg");
+        run(export_page_svg(app.state(), path.clone(), 1, output.clone())).unwrap();
+        let svg = std::fs::read_to_string(&output).unwrap();
+        assert!(svg.contains("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"250\" height=\"400\""));
+        assert!(svg.contains("<path d=\""));
+        assert!(svg.contains(">Hello</text>"));
+        assert!(svg.contains("href=\"data:image/png;base64,"));
+
+        // A rotated page swaps its displayed width and height
+        run(export_page_svg(app.state(), path.clone(), 2, output.clone())).unwrap();
+        let svg = std::fs::read_to_string(&output).unwrap();
+        assert!(svg.contains("width=\"400\" height=\"250\""));
+        assert!(run(export_page_svg(app.state(), path, 3, output)).is_err());
+    }
+
+    #[test]
+    fn audit_security_flags_rc4_40() {
+        let app = test_app();
+        let plain = save_fixture(blank_pages(1, 200, 200), "audit-plain.pdf");
+        let audit = run(audit_security(app.state(), plain)).unwrap();
+        assert!(!audit.encrypted);
+        assert_eq!(audit.findings.len(), 1);
+        assert!(audit.findings[0].message.starts_with("No protection"));
+
+        // Revision 2 handler with an empty user password and printing only
+        let mut doc = blank_pages(1, 200, 200);
+        let file_id = b"0123456789abcdef".to_vec();
+        let owner_value = vec![0x5Au8; 32];
+        let flags = Permissions::from_flags(-1).to_flags() & !0x38;
+        let key = rc4_file_key(b"", &owner_value, flags, &file_id, 5, 2, true);
+        let encrypt = doc.add_object(dictionary! {
+            "Filter" => "Standard", "V" => 1, "R" => 2, "Length" => 40, "P" => flags,
+            "O" => Object::string_literal(owner_value),
+            "U" => Object::string_literal(user_password_value(&key, &file_id, 2)),
+        });
+        doc.trailer.set("Encrypt", encrypt);
+        doc.trailer.set("ID", vec![Object::string_literal(file_id.clone()), Object::string_literal(file_id)]);
+        let path = save_fixture(doc, "audit-rc4.pdf");
+
+        let audit = run(audit_security(app.state(), path)).unwrap();
+        assert!(audit.encrypted);
+        let messages: Vec<(Severity, &str)> =
+            audit.findings.iter().map(|finding| (finding.severity, finding.message.as_str())).collect();
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        assert!(messages.iter().all(|(severity, _)| *severity == Severity::High));
+        assert!(messages.iter().any(|(_, message)| message.contains("40-bit")));
+        assert!(messages.iter().any(|(_, message)| message.starts_with("Permissions are advisory only")));
+    }
+
+    #[test]
+    fn batch_watermark_writes_each_input_and_reports_failures() {
+        let inputs = vec![
+            save_fixture(blank_pages(2, 612, 792), "report-a.pdf"),
+            save_fixture(blank_pages(1, 420, 595), "report-b.pdf"),
+            scratch_path("report-missing.pdf"),
+        ];
+        let output_dir = scratch_path("watermarked");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let app = test_app();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        tauri::Listener::listen(&app, "batch_progress", move |event| {
+            let progress: BatchProgress = serde_json::from_str(event.payload()).unwrap();
+            sink.lock().unwrap().push(progress.current);
+        });
+
+        let watermark = WatermarkSpec {
+            content: WatermarkContent::Text { text: "CONFIDENTIAL".to_string(), font_size: 48.0 },
+            opacity: 0.3,
+        };
+        let results =
+            run(batch_watermark(app.handle().clone(), app.state(), inputs, output_dir.clone(), watermark)).unwrap();
+        assert_eq!(*events.lock().unwrap(), [1, 2, 3]);
+        assert_eq!(results.len(), 3);
+        for result in &results[..2] {
+            let output = result.output_path.as_ref().unwrap();
+            assert!(output.starts_with(&output_dir));
+            let doc = Document::load(output).unwrap();
+            for page_num in doc.get_pages().keys() {
+                assert!(extract_page_text(&doc, *page_num as usize).unwrap().contains("CONFIDENTIAL"));
+            }
+        }
+        assert!(results[2].output_path.is_none() && results[2].error.is_some());
+    }
+
+    #[test]
+    fn saved_documents_carry_a_two_element_id() {
+        let app = test_app();
+        let bare = save_fixture(blank_pages(2, 300, 300), "no-id.pdf");
+        let output = scratch_path("no-id-saved.pdf");
+        run(save_pdf(app.state(), bare, output.clone(), vec![2, 1], BTreeMap::new(), Vec::new(), None, None, None))
+            .unwrap();
+        let saved = Document::load(&output).unwrap();
+        let ids = saved.trailer.get(b"ID").and_then(Object::as_array).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.iter().all(|id| matches!(id, Object::String(bytes, _) if bytes.len() == 16)));
+
+        // An existing /ID survives the page tree being rebuilt
+        let mut doc = blank_pages(3, 300, 300);
+        let permanent = Object::String(b"permanent-id-001".to_vec(), lopdf::StringFormat::Hexadecimal);
+        let changing = Object::String(b"changing-id-0002".to_vec(), lopdf::StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![permanent, changing]);
+        let path = save_fixture(doc, "with-id.pdf");
+        let expected = (hex_string(b"permanent-id-001"), hex_string(b"changing-id-0002"));
+        assert_eq!(run(get_document_id(app.state(), path.clone())).unwrap().unwrap(), expected);
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            output.clone(),
+            vec![1, 3],
+            BTreeMap::new(),
+            vec![2],
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        assert_eq!(run(get_document_id(app.state(), output.clone())).unwrap().unwrap(), expected);
+
+        run(regenerate_document_id(app.state(), path.clone())).unwrap();
+        let (first, second) = run(get_document_id(app.state(), path)).unwrap().unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, expected.0);
+    }
+
+    #[test]
+    fn merge_pdfs_inserts_labeled_separators_between_inputs() {
+        let inputs = vec![
+            save_fixture(blank_pages(2, 612, 792), "sep-a.pdf"),
+            save_fixture(blank_pages(1, 842, 595), "sep-b.pdf"),
+            save_fixture(blank_pages(3, 400, 400), "sep-c.pdf"),
+        ];
+        let app = test_app();
+        let output = scratch_path("sep-merged.pdf");
+        let separator = SeparatorSpec { blank: true, label: Some("Next document".to_string()) };
+        run(merge_pdfs(app.handle().clone(), inputs, output.clone(), None, Some(separator))).unwrap();
+
+        let merged = Document::load(&output).unwrap();
+        assert_eq!(merged.get_pages().len(), 8);
+        let texts: Vec<String> = (1..=8).map(|page_num| extract_page_text(&merged, page_num).unwrap()).collect();
+        let labeled: Vec<usize> = (1..=8).filter(|&page_num| texts[page_num - 1].contains("Next document")).collect();
+        assert_eq!(labeled, [3, 5]);
+        // Each separator takes the size of the page before it
+        let widths: Vec<f64> =
+            merged.get_pages().into_values().map(|id| page_visible_box(&merged, id).unwrap()[2]).collect();
+        assert_eq!(widths, [612.0, 612.0, 612.0, 842.0, 842.0, 400.0, 400.0, 400.0]);
+    }
+
+    #[test]
+    fn verify_signatures_checks_the_byte_range_digest() {
+        let unsigned = save_fixture(blank_pages(1, 200, 200), "unsigned.pdf");
+        assert!(run(verify_signatures(unsigned)).unwrap().is_empty());
+
+        let mut doc = blank_pages(1, 200, 200);
+        let placeholder = 1_000_000_000i64;
+        let signature = doc.add_object(dictionary! {
+            "Type" => "Sig", "Filter" => "Adobe.PPKLite", "SubFilter" => "adbe.pkcs7.detached",
+            "Name" => Object::string_literal("Jane Signer"), "M" => Object::string_literal("D:20250101120000Z"),
+            "ByteRange" => vec![0.into(), placeholder.into(), placeholder.into(), placeholder.into()],
+            "Contents" => Object::String(vec![0; 80], lopdf::StringFormat::Hexadecimal),
+        });
+        let field =
+            doc.add_object(dictionary! { "FT" => "Sig", "T" => Object::string_literal("Approval"), "V" => signature });
+        let root = doc.trailer.get(b"Root").and_then(Object::as_reference).unwrap();
+        doc.get_dictionary_mut(root).unwrap().set("AcroForm", dictionary! { "Fields" => vec![field.into()] });
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+
+        // Fill in the byte range around the /Contents gap, then sign what it covers
+        let find =
+            |bytes: &[u8], needle: &[u8]| bytes.windows(needle.len()).position(|window| window == needle).unwrap();
+        let gap_start = find(&bytes, format!("<{}>", "00".repeat(80)).as_bytes());
+        let gap_end = gap_start + 162;
+        let range_text = b"1000000000 1000000000 1000000000";
+        let range_at = find(&bytes, range_text);
+        let range = format!("{} {} {}", gap_start, gap_end, bytes.len() - gap_end);
+        bytes[range_at..range_at + range_text.len()].copy_from_slice(format!("{:<32}", range).as_bytes());
+        let digest = sha256(&[&bytes[..gap_start], &bytes[gap_end..]].concat());
+        let mut cms = vec![0x06, OID_SHA256.len() as u8];
+        cms.extend_from_slice(OID_SHA256);
+        cms.extend_from_slice(&[0x06, OID_MESSAGE_DIGEST.len() as u8]);
+        cms.extend_from_slice(OID_MESSAGE_DIGEST);
+        cms.extend_from_slice(&[0x31, 0x22, 0x04, 0x20]);
+        cms.extend_from_slice(&digest);
+        cms.resize(80, 0);
+        bytes[gap_start + 1..gap_end - 1].copy_from_slice(hex_string(&cms).as_bytes());
+
+        let signed = scratch_path("signed.pdf");
+        std::fs::write(&signed, &bytes).unwrap();
+        let statuses = run(verify_signatures(signed)).unwrap();
+        assert_eq!(statuses.len(), 1);
+        let status = &statuses[0];
+        assert_eq!(status.field_name, "Approval");
+        assert_eq!(status.signer.as_deref(), Some("Jane Signer"));
+        assert_eq!(status.signed_at.as_deref(), Some("D:20250101120000Z"));
+        assert!(status.covers_whole_file && status.digest_valid, "{:?}", status);
+
+        // Changing a signed byte breaks the digest
+        let header = find(&bytes, b"%PDF-1.");
+        bytes[header + 7] ^= 1;
+        let tampered = scratch_path("signed-tampered.pdf");
+        std::fs::write(&tampered, &bytes).unwrap();
+        let statuses = run(verify_signatures(tampered)).unwrap();
+        assert!(statuses[0].covers_whole_file && !statuses[0].digest_valid);
+        assert!(statuses[0].error.is_none());
+    }
+
+    #[test]
+    fn add_signature_appearance_creates_an_unsigned_sig_widget() {
+        let path = save_fixture(blank_pages(2, 612, 792), "stamp.pdf");
+        let signature_image = scratch_path("signature.png");
+        image::RgbImage::from_fn(
+            60,
+            20,
+            |x, y| if (x + y) % 7 == 0 { image::Rgb([0, 0, 90]) } else { image::Rgb([255; 3]) },
+        )
+        .save(&signature_image)
+        .unwrap();
+        let app = test_app();
+
+        // Corners given in either order describe the same rectangle
+        let name = run(add_signature_appearance(
+            app.state(),
+            path.clone(),
+            2,
+            [400.0, 140.0, 100.0, 80.0],
+            "Jane Signer".to_string(),
+            Some("Approved".to_string()),
+            Some(signature_image),
+        ))
+        .unwrap();
+        assert_eq!(name, "Signature1");
+
+        with_document(&app.state(), &path, |doc| {
+            let page_id = doc.get_pages()[&2];
+            let annots = page_annotations(doc, page_id);
+            assert_eq!(annots.len(), 1);
+            let widget = doc.get_dictionary(annots[0].as_reference().unwrap()).unwrap();
+            assert_eq!(widget.get(b"Subtype").and_then(Object::as_name).unwrap(), b"Widget");
+            assert_eq!(widget.get(b"FT").and_then(Object::as_name).unwrap(), b"Sig");
+            assert!(!widget.has(b"V"));
+            let rect: Vec<f64> = widget
+                .get(b"Rect")
+                .and_then(Object::as_array)
+                .unwrap()
+                .iter()
+                .map(|v| v.as_float().unwrap() as f64)
+                .collect();
+            assert_eq!(rect, [100.0, 80.0, 400.0, 140.0]);
+
+            let ap = widget.get(b"AP").and_then(Object::as_dict).unwrap();
+            let stream =
+                doc.get_object(ap.get(b"N").and_then(Object::as_reference).unwrap()).unwrap().as_stream().unwrap();
+            let content = String::from_utf8_lossy(&stream.content);
+            assert!(content.contains("/Img Do"));
+            assert!(content.contains("(Signed by: Jane Signer)") && content.contains("(Reason: Approved)"));
+
+            let form = doc.catalog().unwrap().get(b"AcroForm").unwrap();
+            let form = resolve_object(doc, form).unwrap().as_dict().unwrap();
+            assert_eq!(form.get(b"SigFlags").and_then(Object::as_i64).unwrap(), 1);
+            assert_eq!(form.get(b"Fields").and_then(Object::as_array).unwrap(), &annots);
+            Ok(())
+        })
+        .unwrap();
+
+        let second = run(add_signature_appearance(
+            app.state(),
+            path.clone(),
+            1,
+            [50.0, 50.0, 200.0, 90.0],
+            "A. N. Other".to_string(),
+            None,
+            None,
+        ));
+        assert_eq!(second.unwrap(), "Signature2");
+        assert!(run(add_signature_appearance(
+            app.state(),
+            path,
+            1,
+            [50.0, 50.0, 50.0, 90.0],
+            "Nobody".to_string(),
+            None,
+            None
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn delete_pages_matching_removes_only_marked_pages() {
+        let mut doc = blank_pages(3, 612, 792);
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        let pages = doc.get_pages();
+        let texts: [&[u8]; 3] = [
+            b"BT /F1 12 Tf 72 700 Td (Quarterly summary) Tj ET",
+            // The marker split over two text spans, in different case
+            b"BT /F1 12 Tf 72 700 Td (Internal use) Tj 0 -14 Td (only) Tj ET",
+            b"BT /F1 12 Tf 72 700 Td (Appendix for internal review) Tj ET",
+        ];
+        for (page_num, content) in (1..=3).zip(texts) {
+            let page_id = pages[&page_num];
+            doc.get_dictionary_mut(page_id)
+                .unwrap()
+                .set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+            set_page_content(&mut doc, page_id, content.to_vec()).unwrap();
+        }
+        let path = save_fixture(doc, "marked.pdf");
+        let app = test_app();
+
+        let query = "INTERNAL USE ONLY".to_string();
+        assert!(run(delete_pages_matching(app.state(), path.clone(), query.clone(), true)).unwrap().is_empty());
+        assert_eq!(run(delete_pages_matching(app.state(), path.clone(), query, false)).unwrap(), [2]);
+        let remaining = with_document(&app.state(), &path, |doc| {
+            Ok((1..=doc.get_pages().len())
+                .map(|page_num| extract_page_text(doc, page_num).unwrap())
+                .collect::<Vec<_>>())
+        })
+        .unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert!(remaining[0].contains("Quarterly") && remaining[1].contains("Appendix"));
+
+        assert!(run(delete_pages_matching(app.state(), path.clone(), "a".to_string(), false)).is_err());
+        assert!(run(delete_pages_matching(app.state(), path, "  ".to_string(), false)).is_err());
+    }
+
+    #[test]
+    fn page_hashes_ignore_layout_and_track_edits() {
+        let mut doc = blank_pages(3, 500, 500);
+        let pages = doc.get_pages();
+        let contents: [&[u8]; 3] = [
+            b"BT /F1 10 Tf 50 400 Td (Same text) Tj ET",
+            b"BT /F1 10 Tf 50 400 Td (Other text) Tj ET",
+            // Same operations as page 1 with different whitespace and a comment
+            b"BT\n  /F1   10 Tf\n50 400 Td % note\n(Same text) Tj\nET\n",
+        ];
+        for (page_num, content) in (1..=3).zip(contents) {
+            // Separate but identical font objects
+            let font =
+                doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Times-Roman" });
+            let page_id = pages[&page_num];
+            doc.get_dictionary_mut(page_id)
+                .unwrap()
+                .set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+            set_page_content(&mut doc, page_id, content.to_vec()).unwrap();
+        }
+        let path = save_fixture(doc, "hashes.pdf");
+        let app = test_app();
+
+        let hashes = run(page_hashes(app.state(), path.clone())).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert!(hashes.iter().all(|hash| hash.len() == 64));
+        assert_eq!(hashes[0], hashes[2]);
+        assert_ne!(hashes[0], hashes[1]);
+
+        // Reordering and re-saving renumbers every object but keeps each page's hash
+        let resaved = scratch_path("hashes-resaved.pdf");
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            resaved.clone(),
+            vec![2, 3, 1],
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        assert_eq!(
+            run(page_hashes(app.state(), resaved)).unwrap(),
+            [hashes[1].clone(), hashes[2].clone(), hashes[0].clone()]
+        );
+
+        edit_document(&app.state(), &path, |doc| {
+            let page_id = doc.get_pages()[&3];
+            set_page_content(doc, page_id, b"BT /F1 10 Tf 50 400 Td (Same text!) Tj ET".to_vec())
+        })
+        .unwrap();
+        let edited = run(page_hashes(app.state(), path)).unwrap();
+        assert_eq!(edited[..2], hashes[..2]);
+        assert_ne!(edited[2], hashes[2]);
+    }
+
+    #[test]
+    fn field_default_appearance_drives_generated_values() {
+        let path = save_fixture(single_field_form("Company", "Acme", ("Cour", "Courier")), "da.pdf");
+        let app = test_app();
+        let set = |font: &str, size: f64, color: [f64; 3]| {
+            run(set_field_default_appearance(
+                app.state(),
+                path.clone(),
+                "Company".to_string(),
+                font.to_string(),
+                size,
+                color,
+            ))
+        };
+
+        set("Helvetica", 12.0, [0.0, 0.0, 1.0]).unwrap();
+        let field_id = with_document(&app.state(), &path, |doc| {
+            let field_id = form_field_id(doc, "Company").unwrap();
+            let field = doc.get_dictionary(field_id).unwrap();
+            assert_eq!(field.get(b"DA").and_then(Object::as_str).unwrap(), b"/Helv 12 Tf 0 0 1 rg");
+            let form =
+                resolve_object(doc, doc.catalog().unwrap().get(b"AcroForm").unwrap()).unwrap().as_dict().unwrap();
+            let fonts =
+                form.get(b"DR").and_then(Object::as_dict).unwrap().get(b"Font").and_then(Object::as_dict).unwrap();
+            let helv = doc.get_dictionary(fonts.get(b"Helv").and_then(Object::as_reference).unwrap()).unwrap();
+            assert_eq!(helv.get(b"BaseFont").and_then(Object::as_name).unwrap(), b"Helvetica");
+            assert!(fonts.has(b"Cour"));
+            Ok(field_id)
+        })
+        .unwrap();
+
+        // Fill in a value, then have its appearance generated
+        edit_document(&app.state(), &path, |doc| {
+            doc.get_dictionary_mut(field_id).unwrap().set("V", Object::string_literal("Initech"));
+            Ok(())
+        })
+        .unwrap();
+        let output = scratch_path("da-filled.pdf");
+        assert_eq!(run(regenerate_form_appearances(app.state(), path.clone(), output.clone())).unwrap(), 1);
+        let filled = Document::load(&output).unwrap();
+        let widget = filled.get_dictionary(field_id).unwrap();
+        let ap = widget.get(b"AP").and_then(Object::as_dict).unwrap().get(b"N").and_then(Object::as_reference).unwrap();
+        let stream = filled.get_object(ap).unwrap().as_stream().unwrap();
+        let content =
+            String::from_utf8_lossy(&stream.decompressed_content().unwrap_or(stream.content.clone())).into_owned();
+        assert!(content.contains("/Helv 12 Tf\n0 0 1 rg"));
+        assert!(content.contains("(Initech) Tj"));
+
+        assert!(set("Futura", 12.0, [0.0; 3]).is_err());
+        assert!(set("Helvetica", 12.0, [0.0, 2.0, 0.0]).is_err());
+        assert!(run(set_field_default_appearance(
+            app.state(),
+            path,
+            "Missing".to_string(),
+            "Cour".to_string(),
+            0.0,
+            [0.0; 3]
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn get_content_stream_joins_and_decodes_page_content() {
+        let mut doc = blank_pages(1, 300, 300);
+        let page_id = doc.get_pages()[&1];
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        // /Contents split over two streams, the second one compressed
+        let first = doc.add_object(lopdf::Stream::new(dictionary! {}, b"q 1 0 0 rg 10 10 50 50 re f Q".to_vec()));
+        let mut second = lopdf::Stream::new(dictionary! {}, b"BT /F1 14 Tf 20 200 Td (Hi there) Tj ET".to_vec());
+        second.compress().unwrap();
+        let second = doc.add_object(second);
+        doc.get_dictionary_mut(page_id).unwrap().set("Contents", vec![first.into(), second.into()]);
+        let path = save_fixture(doc, "content-stream.pdf");
+        let app = test_app();
+
+        let text = run(get_content_stream(app.state(), path.clone(), 1)).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.first(), Some(&"q"));
+        assert!(lines.contains(&"BT") && lines.contains(&"ET"));
+        assert!(lines.contains(&"/F1 14 Tf") && lines.contains(&"(Hi there) Tj"));
+        assert!(lines.iter().position(|&line| line == "Q") < lines.iter().position(|&line| line == "BT"));
+
+        // The text goes back in through replace_page_content unchanged
+        run(replace_page_content(app.state(), path.clone(), 1, text.clone())).unwrap();
+        assert_eq!(run(get_content_stream(app.state(), path.clone(), 1)).unwrap(), text);
+        assert!(run(get_content_stream(app.state(), path, 2)).is_err());
+    }
+
+    #[test]
+    fn set_display_rotation_only_changes_rotate() {
+        let mut doc = blank_pages(2, 200, 100);
+        let page_id = doc.get_pages()[&1];
+        set_page_content(&mut doc, page_id, b"0 0 1 rg 10 10 30 30 re f".to_vec()).unwrap();
+        let path = save_fixture(doc, "display-rotation.pdf");
+        let app = test_app();
+        let before = run(get_content_stream(app.state(), path.clone(), 1)).unwrap();
+
+        let rotate = |page: usize, deg: i64| run(set_display_rotation(app.state(), path.clone(), page, deg));
+        rotate(1, -90).unwrap();
+        rotate(2, 450).unwrap();
+        assert!(rotate(1, 45).is_err());
+        let (rotations, boxes) = with_document(&app.state(), &path, |doc| {
+            let pages = doc.get_pages();
+            let rotations: Vec<i32> = pages.values().map(|&id| page_rotation(doc, id)).collect();
+            let boxes: Vec<[f64; 4]> = pages.values().map(|&id| page_visible_box(doc, id).unwrap()).collect();
+            Ok((rotations, boxes))
+        })
+        .unwrap();
+        assert_eq!(rotations, [270, 90]);
+        assert_eq!(boxes, [[0.0, 0.0, 200.0, 100.0]; 2]);
+        assert_eq!(run(get_content_stream(app.state(), path, 1)).unwrap(), before);
+    }
+
+    #[test]
+    fn bake_rotation_turns_content_and_swaps_dimensions() {
+        let mut doc = blank_pages(2, 200, 100);
+        let pages = doc.get_pages();
+        set_page_content(&mut doc, pages[&1], b"1 0 0 rg 10 10 40 20 re f 0 0 1 rg 150 60 30 30 re f".to_vec())
+            .unwrap();
+        doc.get_dictionary_mut(pages[&1]).unwrap().set("Rotate", 90);
+        let path = save_fixture(doc, "bake-rotation.pdf");
+        let app = test_app();
+        let render = |app: &tauri::App<MockRuntime>| {
+            with_document(&app.state(), &path, |doc| render_for_analysis(doc, doc.get_pages()[&1], DEFAULT_PAGE_SIZE))
+                .unwrap()
+        };
+        let before = render(&app);
+
+        run(bake_rotation(app.state(), path.clone(), None)).unwrap();
+        let after = render(&app);
+        assert_eq!(before.dimensions(), after.dimensions());
+        assert!(render_similarity(&before, &after) > 0.99);
+        let (rotations, boxes) = with_document(&app.state(), &path, |doc| {
+            let pages = doc.get_pages();
+            let rotations: Vec<i32> = pages.values().map(|&id| page_rotation(doc, id)).collect();
+            let boxes: Vec<[f64; 4]> = pages.values().map(|&id| page_visible_box(doc, id).unwrap()).collect();
+            Ok((rotations, boxes))
+        })
+        .unwrap();
+        assert_eq!(rotations, [0, 0]);
+        // The unrotated second page is left as it was
+        assert_eq!(boxes, [[0.0, 0.0, 100.0, 200.0], [0.0, 0.0, 200.0, 100.0]]);
+        assert!(run(get_content_stream(app.state(), path, 1)).unwrap().starts_with("q\n0 -1 1 0 0 200 cm"));
+    }
+
+    #[test]
+    fn repair_image_colorspaces_makes_broken_images_render() {
+        let mut doc = blank_pages(1, 200, 100);
+        let page_id = doc.get_pages()[&1];
+        let image = |doc: &mut Document, color_space: Object, pixels: Vec<u8>| {
+            doc.add_object(lopdf::Stream::new(
+                dictionary! {
+                    "Type" => "XObject", "Subtype" => "Image", "Width" => 4, "Height" => 4,
+                    "BitsPerComponent" => 8, "ColorSpace" => color_space,
+                },
+                pixels,
+            ))
+        };
+        // RGB data pointing at a color space object that doesn't exist
+        let dangling = image(&mut doc, Object::Reference((900, 0)), [240, 200, 40].repeat(16));
+        // An ICC profile without /N
+        let profile = doc.add_object(lopdf::Stream::new(dictionary! {}, vec![0; 16]));
+        let icc = image(&mut doc, vec!["ICCBased".into(), profile.into()].into(), vec![210; 16]);
+        let fine = image(&mut doc, "DeviceGray".into(), vec![90; 16]);
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "XObject" => dictionary! { "A" => dangling, "B" => icc, "C" => fine } });
+        set_page_content(&mut doc, page_id, b"q 100 0 0 100 0 0 cm /A Do Q q 100 0 0 100 100 0 cm /B Do Q".to_vec())
+            .unwrap();
+        let path = save_fixture(doc, "broken-colorspace.pdf");
+        let app = test_app();
+
+        let output = scratch_path("repaired-colorspace.pdf");
+        let repaired = run(repair_image_colorspaces(app.state(), path, output.clone())).unwrap();
+        let summary: Vec<((u32, u16), &str)> =
+            repaired.iter().map(|image| (image.object_id, image.color_space.as_str())).collect();
+        assert_eq!(summary, [(dangling, "DeviceRGB"), (icc, "DeviceGray")]);
+        assert!(repaired.iter().all(|image| !image.problem.is_empty()));
+
+        let doc = Document::load(&output).unwrap();
+        let page = render_for_analysis(&doc, doc.get_pages()[&1], DEFAULT_PAGE_SIZE).unwrap();
+        let (width, height) = page.dimensions();
+        let left = page.get_pixel(width / 4, height / 2).0;
+        let right = page.get_pixel(width * 3 / 4, height / 2).0;
+        assert!(left[0] > 200 && left[1] > 160 && left[2] < 80, "{:?}", left);
+        assert!(right.iter().all(|&c| (190..=230).contains(&c)), "{:?}", right);
+    }
+
+    #[test]
+    fn save_under_size_downsamples_until_it_fits() {
+        let mut doc = blank_pages(2, 300, 300);
+        for (index, page_id) in doc.get_pages().into_values().enumerate() {
+            // Busy 600px photos drawn two inches wide, so 300 dpi on the page
+            let pixels: Vec<u8> = (0..600u32 * 600)
+                .flat_map(|i| {
+                    let (x, y) = (i % 600, i / 600);
+                    let noise = ((x * 7919 + y * 104_729 + index as u32 * 31) ^ (x * y)) % 64;
+                    [(x / 3) as u8 ^ noise as u8, (y / 3) as u8, 128 + noise as u8]
+                })
+                .collect();
+            let mut image = lopdf::Stream::new(
+                dictionary! {
+                    "Type" => "XObject", "Subtype" => "Image", "Width" => 600, "Height" => 600,
+                    "ColorSpace" => "DeviceRGB", "BitsPerComponent" => 8,
+                },
+                pixels,
+            );
+            image.compress().unwrap();
+            let image = doc.add_object(image);
+            doc.get_dictionary_mut(page_id)
+                .unwrap()
+                .set("Resources", dictionary! { "XObject" => dictionary! { "Im" => image } });
+            set_page_content(&mut doc, page_id, b"q 144 0 0 144 78 78 cm /Im Do Q".to_vec()).unwrap();
+        }
+        let path = save_fixture(doc, "photos.pdf");
+        let original = std::fs::metadata(&path).unwrap().len();
+        let app = test_app();
+        let output = scratch_path("photos-small.pdf");
+
+        // Already small enough: only lossless clean-up
+        let result = run(save_under_size(app.state(), path.clone(), output.clone(), original * 2, None)).unwrap();
+        assert_eq!((result.max_dpi, result.images_resampled), (None, 0));
+
+        let limit = original / 8;
+        let result = run(save_under_size(app.state(), path.clone(), output.clone(), limit, None)).unwrap();
+        assert!(result.bytes <= limit, "{} > {}", result.bytes, limit);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), result.bytes);
+        assert!(result.max_dpi.is_some() && result.jpeg_quality.is_some());
+        assert_eq!(result.images_resampled, 2);
+        let small = Document::load(&output).unwrap();
+        assert_eq!(small.get_pages().len(), 2);
+
+        // Refuses sizes that would need resampling below the legibility limit
+        let unreachable = scratch_path("photos-tiny.pdf");
+        assert!(run(save_under_size(app.state(), path, unreachable.clone(), 500, None)).is_err());
+        assert!(!std::path::Path::new(&unreachable).exists());
+    }
+
+    #[test]
+    fn resource_usage_maps_shared_logo_and_orphans() {
+        let mut doc = blank_pages(3, 400, 400);
+        let pages = doc.get_pages();
+        let logo = doc.add_object(lopdf::Stream::new(
+            dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 8, "Height" => 4,
+                "ColorSpace" => "DeviceGray", "BitsPerComponent" => 8,
+            },
+            vec![0; 32],
+        ));
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier" });
+        let unused = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Symbol" });
+        // Page 3 draws the logo through a form XObject
+        let footer = doc.add_object(lopdf::Stream::new(
+            dictionary! {
+                "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 400.into(), 50.into()],
+                "Resources" => dictionary! { "XObject" => dictionary! { "Logo" => logo } },
+            },
+            b"q 80 0 0 40 10 5 cm /Logo Do Q".to_vec(),
+        ));
+        let resources = [
+            dictionary! { "XObject" => dictionary! { "Logo" => logo }, "Font" => dictionary! { "F1" => font } },
+            dictionary! { "Font" => dictionary! { "F1" => font } },
+            dictionary! { "XObject" => dictionary! { "Footer" => footer } },
+        ];
+        for (page_num, resources) in (1..=3).zip(resources) {
+            doc.get_dictionary_mut(pages[&page_num]).unwrap().set("Resources", resources);
+        }
+        let path = save_fixture(doc, "usage.pdf");
+        let app = test_app();
+
+        let usage = run(resource_usage(app.state(), path)).unwrap();
+        let entry = |id: ObjectId| usage.resources.iter().find(|r| r.object_id == id).unwrap();
+        assert_eq!((entry(logo).kind.as_str(), entry(logo).description.as_str()), ("image", "8x4"));
+        assert_eq!(entry(logo).pages, [1, 3]);
+        assert_eq!((entry(font).description.as_str(), entry(font).pages.as_slice()), ("Courier", &[1, 2][..]));
+        assert_eq!(usage.orphans, [unused]);
+        assert_eq!(usage.resources.len(), 3);
+    }
+
+    #[test]
+    fn crop_to_aspect_fits_the_requested_ratio() {
+        let mut doc = blank_pages(3, 612, 792);
+        let pages = doc.get_pages();
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("MediaBox", vec![0.into(), 0.into(), 1600.into(), 900.into()]);
+        doc.get_dictionary_mut(pages[&3]).unwrap().set("Rotate", 90);
+        let path = save_fixture(doc, "aspect.pdf");
+        let app = test_app();
+
+        run(crop_to_aspect(app.state(), path.clone(), 16.0 / 9.0, Anchor::Top)).unwrap();
+        let (boxes, crop_boxes) = with_document(&app.state(), &path, |doc| {
+            let pages = doc.get_pages();
+            let boxes: Vec<[f64; 4]> = pages.values().map(|&id| page_visible_box(doc, id).unwrap()).collect();
+            let crop_boxes: Vec<bool> =
+                pages.values().map(|&id| doc.get_dictionary(id).unwrap().has(b"CropBox")).collect();
+            Ok((boxes, crop_boxes))
+        })
+        .unwrap();
+        // Portrait page: full width, cut from the top
+        let [x0, y0, x1, y1] = boxes[0];
+        assert!(((x1 - x0) / (y1 - y0) - 16.0 / 9.0).abs() < 0.001);
+        assert_eq!((x0, x1, y1), (0.0, 612.0, 792.0));
+        // Already 16:9, so left alone
+        assert_eq!(crop_boxes, [true, false, true]);
+        // The rotated page is displayed landscape, so its unrotated crop is 9:16
+        let [x0, y0, x1, y1] = boxes[2];
+        assert!(((y1 - y0) / (x1 - x0) - 16.0 / 9.0).abs() < 0.001);
+        assert_eq!(y1 - y0, 792.0);
+
+        assert!(run(crop_to_aspect(app.state(), path, 0.0, Anchor::Center)).is_err());
+    }
+
+    #[test]
+    fn set_field_comb_writes_flag_max_len_and_cells() {
+        let path = save_fixture(single_field_form("Phone", "5551234567", ("Helv", "Helvetica")), "comb.pdf");
+        let app = test_app();
+
+        run(set_field_comb(app.state(), path.clone(), "Phone".to_string(), 10)).unwrap();
+        let positions = with_document(&app.state(), &path, |doc| {
+            let field_id = form_field_id(doc, "Phone").unwrap();
+            let field = doc.get_dictionary(field_id).unwrap();
+            assert_eq!(field.get(b"Ff").and_then(Object::as_i64).unwrap() & FIELD_FLAG_COMB, FIELD_FLAG_COMB);
+            assert_eq!(field.get(b"MaxLen").and_then(Object::as_i64).unwrap(), 10);
+            let ap =
+                field.get(b"AP").and_then(Object::as_dict).unwrap().get(b"N").and_then(Object::as_reference).unwrap();
+            let content =
+                lopdf::content::Content::decode(&doc.get_object(ap).unwrap().as_stream().unwrap().content).unwrap();
+            let positions: Vec<f64> = content
+                .operations
+                .iter()
+                .filter(|operation| operation.operator == "Tm")
+                .map(|operation| operand_numbers(&operation.operands)[4])
+                .collect();
+            assert_eq!(content.operations.iter().filter(|operation| operation.operator == "Tj").count(), 10);
+            Ok(positions)
+        })
+        .unwrap();
+        // The 200pt wide field has 20pt cells, so the glyphs step evenly
+        assert!(positions.windows(2).all(|pair| (pair[1] - pair[0] - 20.0).abs() < 3.0), "{:?}", positions);
+        assert!(positions[0] > 0.0 && positions[9] < 200.0);
+
+        assert!(run(set_field_comb(app.state(), path.clone(), "Phone".to_string(), 0)).is_err());
+        assert!(run(set_field_comb(app.state(), path.clone(), "Missing".to_string(), 4)).is_err());
+        edit_document(&app.state(), &path, |doc| {
+            let field_id = form_field_id(doc, "Phone").unwrap();
+            doc.get_dictionary_mut(field_id).unwrap().set("Ff", FIELD_FLAG_MULTILINE);
+            Ok(())
+        })
+        .unwrap();
+        assert!(run(set_field_comb(app.state(), path, "Phone".to_string(), 10)).is_err());
+    }
+
+    #[test]
+    fn combine_side_by_side_puts_pairs_on_double_width_sheets() {
+        let mut doc = blank_pages(3, 595, 842);
+        let pages = doc.get_pages();
+        for (page_num, color) in [(1, "1 0 0"), (2, "0 0 1"), (3, "0 1 0")] {
+            let content = format!("{} rg 0 0 595 842 re f", color);
+            set_page_content(&mut doc, pages[&page_num], content.into_bytes()).unwrap();
+        }
+        let path = save_fixture(doc, "two-up.pdf");
+        let app = test_app();
+
+        let output = scratch_path("two-up-sheets.pdf");
+        assert_eq!(run(combine_side_by_side(app.state(), path, output.clone())).unwrap(), 2);
+        let sheets = Document::load(&output).unwrap();
+        let ids: Vec<ObjectId> = sheets.get_pages().into_values().collect();
+        let boxes: Vec<[f64; 4]> = ids.iter().map(|&id| page_visible_box(&sheets, id).unwrap()).collect();
+        assert_eq!(boxes, [[0.0, 0.0, 1190.0, 842.0], [0.0, 0.0, 595.0, 842.0]]);
+
+        // Both pages are drawn at full size, first on the left
+        let sheet = render_for_analysis(&sheets, ids[0], DEFAULT_PAGE_SIZE).unwrap();
+        let (width, height) = sheet.dimensions();
+        assert_eq!(sheet.get_pixel(width / 4, height / 2).0, [255, 0, 0]);
+        assert_eq!(sheet.get_pixel(width * 3 / 4, height / 2).0, [0, 0, 255]);
+        assert_eq!(sheet.get_pixel(2, 2).0, [255, 0, 0]);
+        assert_eq!(sheet.get_pixel(width - 3, height - 3).0, [0, 0, 255]);
+        let odd = render_for_analysis(&sheets, ids[1], DEFAULT_PAGE_SIZE).unwrap();
+        assert_eq!(odd.get_pixel(odd.width() / 2, odd.height() / 2).0, [0, 255, 0]);
+    }
+
+    #[test]
+    fn extract_page_text_rebuilds_words_from_positioned_runs() {
+        let mut doc = blank_pages(1, 612, 792);
+        let page_id = doc.get_pages()[&1];
+        // Every Courier glyph is 6pt wide at 10pt
+        let font = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Courier",
+            "FirstChar" => 32, "LastChar" => 126, "Widths" => vec![Object::Integer(600); 95],
+        });
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        let content = b"BT /F1 10 Tf
+            72 700 Td [(Justified)-400(text)-350(line)] TJ
+            0 -20 Td [(K)80(e)-30(r)15(ned)] TJ
+            0 -20 Td (ab) Tj 12 0 Td (cd) Tj 22 0 Td (ef) Tj
+        ET";
+        set_page_content(&mut doc, page_id, content.to_vec()).unwrap();
+
+        let text = extract_page_text(&doc, 1).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        // Large TJ gaps and wide run spacing are word breaks; kerning and abutting runs aren't
+        assert_eq!(lines, ["Justified text line", "Kerned", "abcd ef"]);
+    }
+
+    #[test]
+    fn named_views_round_trip_page_and_zoom() {
+        let mut doc = blank_pages(3, 612, 792);
+        let first = doc.get_pages()[&1];
+        // An old-style /Dests entry under the same name is replaced, not shadowing the view
+        doc.catalog_mut().unwrap().set("Dests", dictionary! { "summary" => vec![first.into(), "Fit".into()] });
+        let path = save_fixture(doc, "views.pdf");
+        let app = test_app();
+
+        let add = |name: &str, page_num: usize, zoom: f64| {
+            run(add_named_view(app.state(), path.clone(), name.to_string(), page_num, zoom))
+        };
+        add("summary", 3, 1.5).unwrap();
+        add("chart", 2, 0.0).unwrap();
+        assert!(add(" ", 1, 1.0).is_err());
+        assert!(add("broken", 4, 1.0).is_err());
+        assert!(add("negative", 1, -1.0).is_err());
+
+        let view = run(goto_named_view(app.state(), path.clone(), "summary".to_string())).unwrap();
+        assert_eq!(view.page_number, Some(3));
+        let position = view.position.unwrap();
+        assert_eq!(
+            (position.fit.as_str(), position.left, position.top, position.zoom),
+            ("XYZ", Some(0.0), Some(792.0), Some(1.5))
+        );
+
+        let views = run(list_named_views(app.state(), path.clone())).unwrap();
+        let names: Vec<(&str, Option<usize>)> =
+            views.iter().map(|view| (view.name.as_str(), view.page_number)).collect();
+        assert_eq!(names, [("chart", Some(2)), ("summary", Some(3))]);
+        assert!(run(goto_named_view(app.state(), path, "missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn make_diff_pdf_highlights_edited_text() {
+        let text_doc = |texts: &[&str]| {
+            let mut doc = blank_pages(texts.len(), 300, 300);
+            let font =
+                doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+            for (page_id, text) in doc.get_pages().into_values().zip(texts) {
+                doc.get_dictionary_mut(page_id)
+                    .unwrap()
+                    .set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+                let content = format!("BT /F1 24 Tf 40 200 Td ({}) Tj ET", text);
+                set_page_content(&mut doc, page_id, content.into_bytes()).unwrap();
+            }
+            doc
+        };
+        let original = save_fixture(text_doc(&["Intro", "Total: 100", "Notes"]), "diff-a.pdf");
+        let edited = save_fixture(text_doc(&["Intro", "Total: 100 000", "Notes", "Extra"]), "diff-b.pdf");
+        let shorter = save_fixture(text_doc(&["Intro", "Total: 100"]), "diff-c.pdf");
+        let app = test_app();
+
+        let output = scratch_path("diff-overlay.pdf");
+        let summary = run(make_diff_pdf(app.state(), original.clone(), edited, output.clone())).unwrap();
+        assert_eq!((summary.changed_pages, summary.added_pages), (vec![2], vec![4]));
+        assert!(summary.removed_pages.is_empty());
+
+        // The highlight covers the appended digits and nothing outside the text line
+        let diff = Document::load(&output).unwrap();
+        let page = render_page(&diff, diff.get_pages()[&2], 1.0, None, DEFAULT_PAGE_SIZE).unwrap();
+        let reddish = |x: u32, y: u32| {
+            let [r, g, b] = page.get_pixel(x, y).0;
+            r > 200 && g < 220 && b < 220 && r > g + 30
+        };
+        let marked = (40..300).flat_map(|x| (70..110).map(move |y| (x, y))).filter(|&(x, y)| reddish(x, y)).count();
+        assert!(marked > 50, "{} highlighted pixels", marked);
+        assert!(!reddish(20, 250));
+        assert!(extract_page_text(&diff, 4).unwrap().contains("Extra"));
+
+        let summary = run(make_diff_pdf(app.state(), original, shorter, output.clone())).unwrap();
+        assert!(summary.changed_pages.is_empty());
+        assert_eq!(summary.removed_pages, [3]);
+        let diff = Document::load(&output).unwrap();
+        assert!(extract_page_text(&diff, 3).unwrap().contains("Page 3 of the original was removed"));
+    }
+
+    #[test]
+    fn batch_to_images_renders_every_page_of_every_input() {
+        let output_dir = scratch_path("thumbnails");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let nested = scratch_path("thumbnails-src");
+        std::fs::create_dir_all(&nested).unwrap();
+        // Two inputs share a file name and must not overwrite each other's images
+        let twin = format!("{}/brochure.pdf", nested);
+        blank_pages(1, 144, 72).save(&twin).unwrap();
+        let inputs =
+            vec![save_fixture(blank_pages(2, 72, 144), "brochure.pdf"), twin, scratch_path("no-such-input.pdf")];
+        let app = test_app();
+        let failures = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sink = failures.clone();
+        tauri::Listener::listen(&app, "batch_file_error", move |event| {
+            let failure: BatchOutput = serde_json::from_str(event.payload()).unwrap();
+            sink.lock().unwrap().push(failure.input_path);
+        });
+
+        let outputs =
+            run(batch_to_images(app.handle().clone(), inputs.clone(), output_dir.clone(), 36, ImageFormat::Png))
+                .unwrap();
+        let names: Vec<String> = outputs
+            .iter()
+            .map(|output| std::path::Path::new(output).file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["brochure_p1.png", "brochure_p2.png", "brochure-2_p1.png"]);
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 3);
+        // 36 dpi is half of a point per pixel
+        assert_eq!(image::open(&outputs[0]).unwrap().to_rgb8().dimensions(), (36, 72));
+        assert_eq!(image::open(&outputs[2]).unwrap().to_rgb8().dimensions(), (72, 36));
+        assert_eq!(*failures.lock().unwrap(), [inputs[2].clone()]);
+
+        assert!(run(batch_to_images(app.handle().clone(), inputs, output_dir, 0, ImageFormat::Jpeg)).is_err());
+    }
+
+    #[test]
+    fn line_and_shape_annotations_get_appearances() {
+        let mut doc = blank_pages(1, 400, 400);
+        let page_id = doc.get_pages()[&1];
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Text", "Rect" => vec![10.into(), 10.into(), 30.into(), 30.into()],
+        });
+        doc.get_dictionary_mut(page_id).unwrap().set("Annots", vec![note.into()]);
+        let path = save_fixture(doc, "markup.pdf");
+        let app = test_app();
+
+        run(add_line_annotation(
+            app.state(),
+            path.clone(),
+            1,
+            [50.0, 50.0],
+            [250.0, 150.0],
+            [1.0, 0.0, 0.0],
+            2.0,
+            true,
+        ))
+        .unwrap();
+        run(add_shape_annotation(
+            app.state(),
+            path.clone(),
+            1,
+            ShapeKind::Ellipse,
+            [300.0, 300.0, 200.0, 250.0],
+            [0.0, 0.0, 1.0],
+            Some([1.0, 1.0, 0.0]),
+        ))
+        .unwrap();
+        assert!(run(add_line_annotation(
+            app.state(),
+            path.clone(),
+            1,
+            [0.0, 0.0],
+            [1.0, 1.0],
+            [2.0, 0.0, 0.0],
+            1.0,
+            false
+        ))
+        .is_err());
+        assert!(run(add_shape_annotation(
+            app.state(),
+            path.clone(),
+            1,
+            ShapeKind::Rect,
+            [5.0, 5.0, 5.0, 50.0],
+            [0.0; 3],
+            None
+        ))
+        .is_err());
+
+        with_document(&app.state(), &path, |doc| {
+            let annots = page_annotations(doc, doc.get_pages()[&1]);
+            assert_eq!(annots.len(), 3);
+            assert_eq!(annots[0].as_reference().unwrap(), note);
+            let annot = |index: usize| doc.get_dictionary(annots[index].as_reference().unwrap()).unwrap();
+            let appearance = |index: usize| {
+                let ap = annot(index).get(b"AP").and_then(Object::as_dict).unwrap();
+                let stream =
+                    doc.get_object(ap.get(b"N").and_then(Object::as_reference).unwrap()).unwrap().as_stream().unwrap();
+                let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                lopdf::content::Content::decode(&content).unwrap().operations
+            };
+            let count = |operations: &[lopdf::content::Operation], operator: &str| {
+                operations.iter().filter(|operation| operation.operator == operator).count()
+            };
+
+            let line = annot(1);
+            assert_eq!(line.get(b"Subtype").and_then(Object::as_name).unwrap(), b"Line");
+            let ending = line.get(b"LE").and_then(Object::as_array).unwrap();
+            assert_eq!(ending[1].as_name().unwrap(), b"OpenArrow");
+            let operations = appearance(1);
+            // The shaft plus both sides of the arrowhead
+            assert!(count(&operations, "l") >= 3);
+            assert!(count(&operations, "S") >= 1);
+            let rect: Vec<f64> = operand_numbers(line.get(b"Rect").and_then(Object::as_array).unwrap());
+            assert!(rect[0] < 50.0 && rect[1] < 50.0 && rect[2] > 250.0 && rect[3] > 150.0);
+
+            let ellipse = annot(2);
+            assert_eq!(ellipse.get(b"Subtype").and_then(Object::as_name).unwrap(), b"Circle");
+            assert_eq!(operand_numbers(ellipse.get(b"IC").and_then(Object::as_array).unwrap()), [1.0, 1.0, 0.0]);
+            let operations = appearance(2);
+            assert_eq!(count(&operations, "c"), 4);
+            assert!(count(&operations, "B") + count(&operations, "b") + count(&operations, "f") >= 1);
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn save_incremental_appends_a_revision_in_the_previous_xref_style() {
+        let app = test_app();
+        for (name, table) in [("incremental-stream.pdf", false), ("incremental-table.pdf", true)] {
+            let mut doc = blank_pages(2, 300, 300);
+            if table {
+                doc.reference_table.cross_reference_type = lopdf::xref::XrefType::CrossReferenceTable;
+            }
+            let info = doc.add_object(dictionary! { "Title" => Object::string_literal("Signed contract") });
+            doc.trailer.set("Info", info);
+            let path = save_fixture(doc, name);
+            let original = std::fs::read(&path).unwrap();
+            assert_eq!(run(get_revision_count(path.clone())).unwrap(), 1);
+
+            run(set_display_rotation(app.state(), path.clone(), 2, 90)).unwrap();
+            let output = scratch_path(&format!("revised-{}", name));
+            assert_eq!(run(save_incremental(app.state(), path.clone(), output.clone())).unwrap(), 1);
+
+            // The earlier revision is a byte-for-byte prefix of the new file
+            let revised = std::fs::read(&output).unwrap();
+            assert_eq!(&revised[..original.len()], &original[..]);
+            let appended = &revised[original.len()..];
+            let contains = |needle: &[u8]| appended.windows(needle.len()).any(|window| window == needle);
+            assert_eq!(contains(b"/Type /XRef"), !table, "{}", name);
+            assert_eq!(contains(b"\ntrailer\n"), table, "{}", name);
+            assert_eq!(run(get_revision_count(output.clone())).unwrap(), 2);
+
+            let reopened = Document::load(&output).unwrap();
+            let rotations: Vec<i32> =
+                reopened.get_pages().into_values().map(|id| page_rotation(&reopened, id)).collect();
+            assert_eq!(rotations, [0, 90]);
+            let title = reopened.get_dictionary(info).unwrap().get(b"Title").and_then(Object::as_str).unwrap();
+            assert_eq!(title, b"Signed contract");
+
+            // A further revision chains onto the one just written
+            run(set_display_rotation(app.state(), output.clone(), 1, 180)).unwrap();
+            let again = scratch_path(&format!("revised-again-{}", name));
+            run(save_incremental(app.state(), output, again.clone())).unwrap();
+            assert_eq!(run(get_revision_count(again.clone())).unwrap(), 3);
+            let reopened = Document::load(&again).unwrap();
+            let rotations: Vec<i32> =
+                reopened.get_pages().into_values().map(|id| page_rotation(&reopened, id)).collect();
+            assert_eq!(rotations, [180, 90]);
+        }
+    }
+
+    #[test]
+    fn list_external_uris_merges_repeated_links() {
+        let mut doc = blank_pages(3, 400, 400);
+        let pages = doc.get_pages();
+        let link = |doc: &mut Document, action: lopdf::Dictionary| {
+            doc.add_object(dictionary! {
+                "Type" => "Annot", "Subtype" => "Link", "Rect" => vec![10.into(), 10.into(), 90.into(), 30.into()],
+                "A" => action,
+            })
+        };
+        let uri = |target: &str| dictionary! { "S" => "URI", "URI" => Object::string_literal(target) };
+        let home = [link(&mut doc, uri("https://example.com/")), link(&mut doc, uri("https://example.com/"))];
+        // A relative URI resolved against /URI /Base, reached through a chained action
+        let chained = link(
+            &mut doc,
+            dictionary! {
+                "S" => "JavaScript", "JS" => Object::string_literal("void 0"), "Next" => vec![uri("docs/start").into()],
+            },
+        );
+        let internal = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link", "Rect" => vec![0.into(), 0.into(), 5.into(), 5.into()],
+            "Dest" => vec![pages[&1].into(), "Fit".into()],
+        });
+        let annots: [Vec<Object>; 3] =
+            [vec![home[0].into()], vec![internal.into(), chained.into()], vec![home[1].into()]];
+        for (page_num, annots) in (1..=3).zip(annots) {
+            doc.get_dictionary_mut(pages[&page_num]).unwrap().set("Annots", annots);
+        }
+        doc.catalog_mut().unwrap().set("URI", dictionary! { "Base" => Object::string_literal("https://example.org/") });
+        let path = save_fixture(doc, "uris.pdf");
+        let app = test_app();
+
+        let uris = run(list_external_uris(app.state(), path)).unwrap();
+        let summary: Vec<(&str, &[usize])> =
+            uris.iter().map(|entry| (entry.uri.as_str(), entry.pages.as_slice())).collect();
+        assert_eq!(summary, [("https://example.com/", &[1, 3][..]), ("https://example.org/docs/start", &[2][..])]);
+    }
+
+    #[test]
+    fn set_user_unit_scales_physical_size() {
+        let path = save_fixture(blank_pages(2, 300, 200), "user-unit.pdf");
+        let app = test_app();
+
+        run(set_user_unit(app.state(), path.clone(), 1, 10.0)).unwrap();
+        assert!(run(set_user_unit(app.state(), path.clone(), 2, 0.0)).is_err());
+        assert!(run(set_user_unit(app.state(), path.clone(), 2, -2.0)).is_err());
+
+        let boxes = run(get_page_boxes(app.state(), path.clone(), 1)).unwrap();
+        assert_eq!(boxes.user_unit, 10.0);
+        assert_eq!(boxes.media_box, Some([0.0, 0.0, 300.0, 200.0]));
+        assert_eq!(run(get_page_boxes(app.state(), path.clone(), 2)).unwrap().user_unit, 1.0);
+
+        with_document(&app.state(), &path, |doc| {
+            assert!(doc.version.as_str() >= "1.6");
+            assert_eq!(get_page_dimensions(doc, 1, DEFAULT_PAGE_SIZE)?, (3000.0, 2000.0));
+            assert_eq!(page_size_in_units(doc, 1, DEFAULT_PAGE_SIZE)?, (300.0, 200.0));
+            assert_eq!(get_page_dimensions(doc, 2, DEFAULT_PAGE_SIZE)?, (300.0, 200.0));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn flatten_annotations_draws_highlight_and_keeps_link() {
+        let mut doc = blank_pages(1, 500, 700);
+        let page = doc.get_pages()[&1];
+        let appearance = doc.add_object(lopdf::Stream::new(
+            dictionary! { "BBox" => vec![0.into(), 0.into(), 120.into(), 14.into()] },
+            b"1 1 0 rg 0 0 120 14 re f".to_vec(),
+        ));
+        let highlight = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Highlight",
+            "Rect" => vec![72.into(), 600.into(), 192.into(), 614.into()],
+            "QuadPoints" => vec![72.into(), 614.into(), 192.into(), 614.into(), 72.into(), 600.into(), 192.into(), 600.into()],
+            "AP" => dictionary! { "N" => appearance },
+        });
+        let link = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link",
+            "Rect" => vec![72.into(), 500.into(), 150.into(), 520.into()],
+            "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal("https://example.com/") },
+        });
+        doc.get_dictionary_mut(page).unwrap().set("Annots", vec![highlight.into(), link.into()]);
+        let path = save_fixture(doc, "flatten-annots.pdf");
+        let output = scratch_path("flatten-annots-out.pdf");
+        let app = test_app();
+
+        assert_eq!(run(flatten_annotations(app.state(), path, output.clone(), None, None)).unwrap(), 1);
+
+        let doc = Document::load(&output).unwrap();
+        let page = doc.get_pages()[&1];
+        let annots = page_annotations(&doc, page);
+        assert_eq!(annots.len(), 1);
+        assert_eq!(annots[0].as_reference().unwrap(), link);
+        let content = String::from_utf8_lossy(&doc.get_page_content(page).unwrap()).into_owned();
+        assert!(content.contains("72.0000 600.0000 cm /Annot"), "{content}");
+        let xobjects = page_resources(&doc, page).unwrap().get(b"XObject").unwrap().as_dict().unwrap().clone();
+        let (_, form) = xobjects.iter().next().unwrap();
+        assert_eq!(form.as_reference().unwrap(), appearance);
+    }
+
+    #[test]
+    fn side_by_side_compare_pairs_pages_and_pads_shorter_side() {
+        let fixture = |count: usize, fill: &[u8], name: &str| {
+            let mut doc = blank_pages(count, 300, 400);
+            for page_id in doc.get_pages().into_values() {
+                set_page_content(&mut doc, page_id, [fill, b" 20 20 100 100 re f"].concat()).unwrap();
+            }
+            save_fixture(doc, name)
+        };
+        let before = fixture(2, b"1 0 0 rg", "compare-old.pdf");
+        let after = fixture(1, b"0 0 1 rg", "compare-new.pdf");
+        let output = scratch_path("compare-out.pdf");
+        let app = test_app();
+
+        assert_eq!(run(side_by_side_compare(app.state(), before, after, output.clone())).unwrap(), 2);
+
+        let doc = Document::load(&output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(page_visible_box(&doc, pages[&1]), Some([0.0, 0.0, 600.0, 400.0 + COMPARE_LABEL_HEIGHT]));
+        let form_content = |page: ObjectId, name: &[u8]| {
+            let xobjects = page_resources(&doc, page)?.get(b"XObject").ok()?.as_dict().ok()?;
+            let form =
+                doc.get_object(xobjects.get(name).ok()?.as_reference().ok()?).and_then(Object::as_stream).ok()?;
+            Some(form.decompressed_content().unwrap_or_else(|_| form.content.clone()))
+        };
+        assert!(form_content(pages[&1], b"P0").unwrap().starts_with(b"1 0 0 rg"));
+        assert!(form_content(pages[&1], b"P1").unwrap().starts_with(b"0 0 1 rg"));
+        assert!(form_content(pages[&2], b"P1").is_none());
+        let labels = extract_page_text(&doc, 2).unwrap();
+        assert!(labels.contains("compare-new.pdf (no page 2)"), "{labels}");
+    }
+
+    #[test]
+    fn get_dominant_page_size_lists_odd_pages() {
+        let mut doc = blank_pages(4, 595, 842);
+        let pages = doc.get_pages();
+        // A float CropBox within a point of the inherited MediaBox still counts as A4
+        let crop = vec![Object::Real(0.2), Object::Real(0.1), Object::Real(595.4), Object::Real(842.0)];
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("CropBox", crop);
+        doc.get_dictionary_mut(pages[&4]).unwrap().set("MediaBox", vec![0.into(), 0.into(), 842.into(), 595.into()]);
+        let path = save_fixture(doc, "mixed-sizes.pdf");
+        let app = test_app();
+
+        let dominant = run(get_dominant_page_size(app.state(), path)).unwrap();
+        assert_eq!((dominant.width, dominant.height), (595.0, 842.0));
+        assert!(matches!(dominant.orientation, PageOrientation::Portrait));
+        let deviations: Vec<(usize, f64, f64)> =
+            dominant.deviations.iter().map(|page| (page.page_number, page.width, page.height)).collect();
+        assert_eq!(deviations, [(4, 842.0, 595.0)]);
+    }
+
+    #[test]
+    fn set_page_opacity_wraps_targeted_pages() {
+        let mut doc = blank_pages(3, 200, 200);
+        for page_id in doc.get_pages().into_values() {
+            set_page_content(&mut doc, page_id, b"0 0 1 rg 10 10 80 80 re f".to_vec()).unwrap();
+        }
+        let path = save_fixture(doc, "opacity.pdf");
+        let app = test_app();
+
+        run(set_page_opacity(app.state(), path.clone(), Some(vec![1, 3]), 0.35)).unwrap();
+        run(set_page_opacity(app.state(), path.clone(), Some(vec![2]), 4.0)).unwrap();
+
+        let doc = snapshot_document(&app.state(), &path).unwrap();
+        let alpha_of = |page_num: u32| {
+            let page_id = doc.get_pages()[&page_num];
+            let content = String::from_utf8_lossy(&doc.get_page_content(page_id).unwrap()).into_owned();
+            let name =
+                content.strip_prefix("q /").and_then(|rest| rest.split_once(" gs")).map(|(name, _)| name.to_string());
+            let name = name.unwrap_or_else(|| panic!("page {page_num} is not wrapped: {content}"));
+            assert!(content.trim_end().ends_with('Q'), "{content}");
+            let states = page_resources(&doc, page_id).unwrap().get(b"ExtGState").unwrap().as_dict().unwrap();
+            let state = doc.get_dictionary(states.get(name.as_bytes()).unwrap().as_reference().unwrap()).unwrap();
+            let alpha = |key: &[u8]| state.get(key).and_then(Object::as_float).unwrap();
+            assert_eq!(alpha(b"ca"), alpha(b"CA"));
+            alpha(b"ca")
+        };
+        assert_eq!(alpha_of(1), 0.35);
+        assert_eq!(alpha_of(3), 0.35);
+        assert_eq!(alpha_of(2), 1.0);
+    }
+
+    #[test]
+    fn export_structure_reports_nested_page_tree() {
+        let mut doc = Document::with_version("1.7");
+        let root = doc.new_object_id();
+        let middle = doc.new_object_id();
+        let content =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 12 Tf (secret body text) Tj ET".to_vec()));
+        let page = |doc: &mut Document, parent: ObjectId| {
+            doc.add_object(dictionary! { "Type" => "Page", "Parent" => parent, "Contents" => content })
+        };
+        let nested = [page(&mut doc, middle), page(&mut doc, middle)];
+        let last = page(&mut doc, root);
+        doc.objects.insert(
+            middle,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages", "Parent" => root, "Kids" => vec![nested[0].into(), nested[1].into()], "Count" => 2,
+                "Rotate" => 90,
+            }),
+        );
+        doc.objects.insert(
+            root,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages", "Kids" => vec![middle.into(), last.into()], "Count" => 3,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => root, "PageMode" => "UseOutlines" });
+        doc.trailer.set("Root", catalog);
+        let path = save_fixture(doc, "structure.pdf");
+        let app = test_app();
+
+        let json = run(export_structure(app.state(), path)).unwrap();
+        assert!(!json.contains("secret body text"));
+        let structure: DocumentStructure = serde_json::from_str(&json).unwrap();
+        assert_eq!(structure.page_count, 3);
+        assert_eq!(structure.tree_depth, 3);
+        assert!(!structure.has_acro_form);
+        assert_eq!(structure.catalog["PageMode"], "/UseOutlines");
+
+        let tree = structure.page_tree.unwrap();
+        assert_eq!((tree.kind.as_str(), tree.count, tree.kids.len()), ("Pages", Some(3), 2));
+        assert!(tree.attributes.contains_key("MediaBox"));
+        let middle = &tree.kids[0];
+        assert_eq!(middle.attributes["Rotate"], "90");
+        let numbers: Vec<Option<usize>> =
+            middle.kids.iter().chain([&tree.kids[1]]).map(|kid| kid.page_number).collect();
+        assert_eq!(numbers, [Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn add_page_background_draws_before_original_content() {
+        let mut doc = blank_pages(2, 200, 200);
+        let original = b"0 0 0 rg 20 20 40 40 re f".to_vec();
+        let mut original_ids = Vec::new();
+        for page_id in doc.get_pages().into_values() {
+            set_page_content(&mut doc, page_id, original.clone()).unwrap();
+            original_ids.push(page_content_refs(&doc, page_id)[0].as_reference().unwrap());
+        }
+        let path = save_fixture(doc, "background.pdf");
+        let image_path = scratch_path("background-tile.png");
+        image::RgbImage::from_pixel(100, 50, image::Rgb([240, 230, 200])).save(&image_path).unwrap();
+        let app = test_app();
+
+        let tint = BackgroundSpec::Color { color: [1.0, 0.95, 0.8] };
+        run(add_page_background(app.state(), path.clone(), Some(vec![1]), tint)).unwrap();
+        let picture = BackgroundSpec::Image { image_path };
+        run(add_page_background(app.state(), path.clone(), Some(vec![2]), picture)).unwrap();
+        let bad = BackgroundSpec::Color { color: [1.5, 0.0, 0.0] };
+        assert!(run(add_page_background(app.state(), path.clone(), None, bad)).is_err());
+
+        let doc = snapshot_document(&app.state(), &path).unwrap();
+        let pages = doc.get_pages();
+        let expected_first = [
+            "q 0.0000 0.0000 200.0000 200.0000 re 1.0000 0.9500 0.8000 rg f Q\n",
+            "q 0.0000 0.0000 200.0000 200.0000 re W n 400.0000 0 0 200.0000 -100.0000 0.0000 cm /Bg",
+        ];
+        for ((page_id, original_id), expected) in pages.values().zip(&original_ids).zip(expected_first) {
+            let refs = page_content_refs(&doc, *page_id);
+            assert_eq!(refs.len(), 2);
+            assert_eq!(refs[1].as_reference().unwrap(), *original_id);
+            assert_eq!(doc.get_object(*original_id).unwrap().as_stream().unwrap().content, original);
+            let background = doc.get_object(refs[0].as_reference().unwrap()).unwrap().as_stream().unwrap();
+            let background = String::from_utf8_lossy(&background.content).into_owned();
+            assert!(background.starts_with(expected), "{background}");
+        }
+    }
+
+    #[test]
+    fn canonical_save_is_byte_stable_across_numbering() {
+        // The same two-page document, built with objects created in different orders
+        let build = |reversed: bool| {
+            let mut doc = Document::with_version("1.5");
+            if reversed {
+                doc.add_object(dictionary! { "Unused" => true });
+            }
+            let pages_id = doc.new_object_id();
+            let font =
+                doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+            let mut kids = Vec::new();
+            let texts: [&[u8]; 2] = [b"BT /F1 12 Tf 72 700 Td (first) Tj ET", b"BT /F1 12 Tf 72 700 Td (second) Tj ET"];
+            let order: Vec<usize> = if reversed { vec![1, 0] } else { vec![0, 1] };
+            let mut ids = [(0, 0); 2];
+            for index in order {
+                let content = doc.add_object(lopdf::Stream::new(dictionary! {}, texts[index].to_vec()));
+                ids[index] = doc.add_object(dictionary! {
+                    "Type" => "Page", "Parent" => pages_id, "Contents" => content,
+                    "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+                });
+            }
+            kids.extend(ids.map(Object::from));
+            doc.objects.insert(
+                pages_id,
+                Object::Dictionary(dictionary! {
+                    "Type" => "Pages", "Kids" => kids, "Count" => 2,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                }),
+            );
+            let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+            doc.trailer.set("Root", catalog);
+            doc
+        };
+        let inputs = [save_fixture(build(false), "canonical-a.pdf"), save_fixture(build(true), "canonical-b.pdf")];
+        let app = test_app();
+
+        let save = |input: &String, name: &str, canonical: bool| {
+            let output = scratch_path(name);
+            let call = save_pdf(
+                app.state(),
+                input.clone(),
+                output.clone(),
+                vec![1, 2],
+                BTreeMap::new(),
+                Vec::new(),
+                None,
+                Some(canonical),
+                None,
+            );
+            run(call).unwrap();
+            std::fs::read(output).unwrap()
+        };
+        let canonical = [save(&inputs[0], "canonical-a-out.pdf", true), save(&inputs[1], "canonical-b-out.pdf", true)];
+        assert!(canonical[0] == canonical[1]);
+        assert!(!canonical[0].windows(7).any(|window| window == b"/ObjStm"));
+        assert!(save(&inputs[0], "plain-a-out.pdf", false) != save(&inputs[1], "plain-b-out.pdf", false));
+
+        let doc = Document::load_mem(&canonical[0]).unwrap();
+        assert!(doc.trailer.has(b"ID"));
+        assert_eq!(extract_page_text(&doc, 2).unwrap().trim(), "second");
+    }
+
+    #[test]
+    fn split_by_size_packs_pages_under_cap() {
+        let mut doc = blank_pages(5, 400, 400);
+        // Random hex digits, which Flate only halves, so each page stream stays large
+        let mut seed = 0x2545_f491_u32;
+        let mut noise = |len: usize| -> Vec<u8> {
+            let mut bytes = b"% ".to_vec();
+            bytes.extend((0..len).map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b"0123456789abcdef"[(seed >> 16) as usize % 16]
+            }));
+            bytes.extend_from_slice(b"\n0 0 1 rg 10 10 50 50 re f");
+            bytes
+        };
+        for (page_num, page_id) in doc.get_pages() {
+            let len = if page_num == 5 { 24_000 } else { 7_000 };
+            set_page_content(&mut doc, page_id, noise(len)).unwrap();
+        }
+        let path = save_fixture(doc, "large-report.pdf");
+        let output_dir = scratch_path("split-by-size");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let app = test_app();
+        let warnings = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        tauri::Listener::listen(&app, "split_warning", move |event| {
+            let warning: SplitWarning = serde_json::from_str(event.payload()).unwrap();
+            sink.lock().unwrap().push(warning.page_number);
+        });
+        assert!(run(split_by_size(app.handle().clone(), app.state(), path.clone(), 512, output_dir.clone())).is_err());
+
+        let parts = run(split_by_size(app.handle().clone(), app.state(), path, 10_000, output_dir)).unwrap();
+        let page_counts: Vec<usize> =
+            parts.iter().map(|part| Document::load(part).unwrap().get_pages().len()).collect();
+        assert_eq!(page_counts, [2, 2, 1]);
+        assert!(parts[2].ends_with("large-report_part3.pdf"));
+        for part in &parts[..2] {
+            assert!(std::fs::metadata(part).unwrap().len() < 10_000);
+        }
+        assert_eq!(*warnings.lock().unwrap(), [5]);
+    }
+
+    #[test]
+    fn list_layers_reports_default_visibility_and_pages() {
+        let mut doc = blank_pages(3, 300, 300);
+        let pages = doc.get_pages();
+        let group = |doc: &mut Document, name: &str| {
+            doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal(name) })
+        };
+        let notes = group(&mut doc, "Notes");
+        let draft = group(&mut doc, "Draft stamp");
+        // Notes via marked content on page 1, Draft via an XObject on page 2 and an annotation on page 3
+        doc.get_dictionary_mut(pages[&1]).unwrap().set(
+            "Resources",
+            dictionary! {
+                "Properties" => dictionary! { "MC0" => notes },
+            },
+        );
+        set_page_content(&mut doc, pages[&1], b"/OC /MC0 BDC 0 0 1 rg 10 10 50 50 re f EMC".to_vec()).unwrap();
+        let stamp = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 50.into(), 50.into()], "OC" => draft },
+            b"1 0 0 rg 0 0 50 50 re f".to_vec(),
+        ));
+        doc.get_dictionary_mut(pages[&2])
+            .unwrap()
+            .set("Resources", dictionary! { "XObject" => dictionary! { "Fm0" => stamp } });
+        let membership = doc.add_object(dictionary! { "Type" => "OCMD", "OCGs" => draft });
+        let annot = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Square", "Rect" => vec![0.into(), 0.into(), 20.into(), 20.into()], "OC" => membership,
+        });
+        doc.get_dictionary_mut(pages[&3]).unwrap().set("Annots", vec![annot.into()]);
+        doc.catalog_mut().unwrap().set(
+            "OCProperties",
+            dictionary! {
+                "OCGs" => vec![notes.into(), draft.into()],
+                "D" => dictionary! { "OFF" => vec![draft.into()] },
+            },
+        );
+        let path = save_fixture(doc, "layers.pdf");
+        let app = test_app();
+
+        let summary = |app: &tauri::App<MockRuntime>| {
+            run(list_layers(app.state(), path.clone()))
+                .unwrap()
+                .into_iter()
+                .map(|layer| (layer.name, layer.visible_by_default, layer.pages))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&app),
+            [("Notes".to_string(), true, vec![1]), ("Draft stamp".to_string(), false, vec![2, 3])]
+        );
+
+        run(set_layer_visibility(app.state(), path.clone(), "Draft stamp".to_string(), true)).unwrap();
+        run(set_layer_visibility(app.state(), path.clone(), "Notes".to_string(), false)).unwrap();
+        assert!(run(set_layer_visibility(app.state(), path.clone(), "Missing".to_string(), true)).is_err());
+        let visibility: Vec<bool> = summary(&app).into_iter().map(|(_, visible, _)| visible).collect();
+        assert_eq!(visibility, [false, true]);
+    }
+
+    #[test]
+    fn flatten_layers_removes_hidden_content() {
+        let mut doc = blank_pages(1, 400, 400);
+        let page = doc.get_pages()[&1];
+        let shown = doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Base map") });
+        let hidden = doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal("Redlines") });
+        let overlay = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "XObject", "Subtype" => "Form", "BBox" => vec![0.into(), 0.into(), 40.into(), 40.into()], "OC" => hidden },
+            b"0 1 0 rg 0 0 40 40 re f".to_vec(),
+        ));
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Square", "Rect" => vec![0.into(), 0.into(), 30.into(), 30.into()], "OC" => hidden,
+        });
+        let keep = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Square", "Rect" => vec![50.into(), 50.into(), 80.into(), 80.into()], "OC" => shown,
+        });
+        let page_dict = doc.get_dictionary_mut(page).unwrap();
+        page_dict.set(
+            "Resources",
+            dictionary! {
+                "Properties" => dictionary! { "L0" => shown, "L1" => hidden },
+                "XObject" => dictionary! { "Fm0" => overlay },
+            },
+        );
+        page_dict.set("Annots", vec![note.into(), keep.into()]);
+        let content = b"/OC /L0 BDC 0 0 1 rg 10 10 100 100 re f EMC\n\
+            /OC /L1 BDC /Span BMC 1 0 0 rg 200 200 50 50 re f EMC EMC\n\
+            q 1 0 0 1 300 300 cm /Fm0 Do Q\n0 g 5 5 m 20 20 l S";
+        set_page_content(&mut doc, page, content.to_vec()).unwrap();
+        // Everything is off by default except the base map
+        doc.catalog_mut().unwrap().set(
+            "OCProperties",
+            dictionary! {
+                "OCGs" => vec![shown.into(), hidden.into()],
+                "D" => dictionary! { "BaseState" => "OFF", "ON" => vec![shown.into()] },
+            },
+        );
+        let path = save_fixture(doc, "layered-plan.pdf");
+        let output = scratch_path("layered-plan-flat.pdf");
+        let app = test_app();
+
+        run(flatten_layers(app.state(), path, output.clone())).unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let page = doc.get_pages()[&1];
+        let content = String::from_utf8_lossy(&doc.get_page_content(page).unwrap()).into_owned();
+        assert!(content.contains("0 0 1 rg"), "{content}");
+        assert!(content.contains("20 20 l"), "{content}");
+        assert!(!content.contains("1 0 0 rg") && !content.contains("Fm0"), "{content}");
+        assert!(!doc.catalog().unwrap().has(b"OCProperties"));
+        assert_eq!(page_annotations(&doc, page).len(), 1);
+        assert!(!doc.objects.values().any(|object| match object {
+            Object::Dictionary(dict) => dict.has(b"OC"),
+            Object::Stream(stream) => stream.dict.has(b"OC"),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn image_codec_sets_output_filter() {
+        let mut doc = blank_pages(1, 200, 200);
+        let page = doc.get_pages()[&1];
+        let cmyk: Vec<u8> = (0..16 * 16).flat_map(|i| [(i % 256) as u8, 40, 200, 10]).collect();
+        let image = doc.add_object(lopdf::Stream::new(
+            dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 16, "Height" => 16,
+                "ColorSpace" => "DeviceCMYK", "BitsPerComponent" => 8,
+            },
+            cmyk,
+        ));
+        doc.get_dictionary_mut(page)
+            .unwrap()
+            .set("Resources", dictionary! { "XObject" => dictionary! { "Im0" => image } });
+        set_page_content(&mut doc, page, b"q 160 0 0 160 20 20 cm /Im0 Do Q".to_vec()).unwrap();
+        let path = save_fixture(doc, "cmyk-photo.pdf");
+        let app = test_app();
+
+        let filter_for = |codec: ImageCodec, name: &str| {
+            let output = scratch_path(name);
+            run(convert_to_rgb(app.state(), path.clone(), output.clone(), Some(70), Some(codec))).unwrap();
+            let doc = Document::load(&output).unwrap();
+            let stream = doc.get_object(image).unwrap().as_stream().unwrap();
+            assert_eq!(stream.dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceRGB");
+            String::from_utf8_lossy(stream.dict.get(b"Filter").unwrap().as_name().unwrap()).into_owned()
+        };
+        assert_eq!(filter_for(ImageCodec::Jpeg, "cmyk-photo-jpeg.pdf"), "DCTDecode");
+        assert_eq!(filter_for(ImageCodec::FlateLossless, "cmyk-photo-flate.pdf"), "FlateDecode");
+
+        // Page 1 has a soft-masked image, page 2 transparent fills that get rasterized
+        let mut doc = blank_pages(2, 200, 200);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let gray = |value: u8| (0..32 * 32).map(|i| value ^ (i % 7) as u8).collect::<Vec<u8>>();
+        let image_dict = |color_space: &str| {
+            dictionary! {
+                "Type" => "XObject", "Subtype" => "Image", "Width" => 32, "Height" => 32,
+                "ColorSpace" => color_space, "BitsPerComponent" => 8,
+            }
+        };
+        let mask = doc.add_object(lopdf::Stream::new(image_dict("DeviceGray"), gray(128)));
+        let mut masked = image_dict("DeviceGray");
+        masked.set("SMask", mask);
+        let masked = doc.add_object(lopdf::Stream::new(masked, gray(60)));
+        doc.get_dictionary_mut(pages[0])
+            .unwrap()
+            .set("Resources", dictionary! { "XObject" => dictionary! { "Im0" => masked } });
+        set_page_content(&mut doc, pages[0], b"q 100 0 0 100 50 50 cm /Im0 Do Q".to_vec()).unwrap();
+        doc.get_dictionary_mut(pages[1])
+            .unwrap()
+            .set("Resources", dictionary! { "ExtGState" => dictionary! { "Half" => dictionary! { "ca" => 0.5 } } });
+        set_page_content(&mut doc, pages[1], b"/Half gs 0.9 0.2 0.1 rg 30 30 140 140 re f".to_vec()).unwrap();
+        let path = save_fixture(doc, "transparent-shapes.pdf");
+
+        let flattened_filters = |codec: Option<ImageCodec>, name: &str| {
+            let output = scratch_path(name);
+            run(flatten_transparency(app.state(), path.clone(), output.clone(), Some(70), codec)).unwrap();
+            let doc = Document::load(&output).unwrap();
+            let filter = |stream: &lopdf::Stream| stream.dict.get(b"Filter").unwrap().as_name().unwrap().to_vec();
+            let image = doc.get_object(masked).unwrap().as_stream().unwrap();
+            let resources = page_resources(&doc, doc.get_pages()[&2]).unwrap();
+            let raster = resource_category(&doc, resources, b"XObject").unwrap().get(b"Raster").unwrap();
+            let raster = resolve_object(&doc, raster).unwrap().as_stream().unwrap();
+            (filter(image), filter(raster))
+        };
+        let expected = |image: &str, raster: &str| (image.as_bytes().to_vec(), raster.as_bytes().to_vec());
+        let flate = Some(ImageCodec::FlateLossless);
+        assert_eq!(flattened_filters(flate, "flat-flate.pdf"), expected("FlateDecode", "FlateDecode"));
+        assert_eq!(flattened_filters(Some(ImageCodec::Jpeg), "flat-jpeg.pdf"), expected("DCTDecode", "DCTDecode"));
+        assert_eq!(flattened_filters(None, "flat-default.pdf"), expected("FlateDecode", "DCTDecode"));
+    }
+
+    #[test]
+    fn fix_missing_types_infers_page_tree_and_resource_types() {
+        let mut doc = Document::with_version("1.4");
+        let root = doc.new_object_id();
+        let font = doc.add_object(dictionary! { "Subtype" => "Type1", "BaseFont" => "Times-Roman" });
+        let content =
+            doc.add_object(lopdf::Stream::new(dictionary! {}, b"BT /F1 14 Tf 50 700 Td (typed) Tj ET".to_vec()));
+        // One leaf is missing /Type, the other already has it
+        let untyped = doc.add_object(dictionary! {
+            "Parent" => root, "Contents" => content,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+        });
+        let typed = doc.add_object(dictionary! { "Type" => "Page", "Parent" => root });
+        doc.objects.insert(
+            root,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages", "Kids" => vec![untyped.into(), typed.into()], "Count" => 2,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+        let catalog = doc.add_object(dictionary! { "Pages" => root });
+        doc.trailer.set("Root", catalog);
+        let path = save_fixture(doc, "untyped.pdf");
+        let output = scratch_path("untyped-fixed.pdf");
+        let app = test_app();
+
+        // The catalog, the untyped page and its font
+        assert_eq!(run(fix_missing_types(app.state(), path, output.clone())).unwrap(), 3);
+
+        let doc = Document::load(&output).unwrap();
+        let type_of = |id: ObjectId| doc.get_dictionary(id).unwrap().get(b"Type").unwrap().as_name().unwrap().to_vec();
+        assert_eq!(type_of(catalog), b"Catalog");
+        assert_eq!(type_of(untyped), b"Page");
+        assert_eq!(type_of(font), b"Font");
+        assert_eq!(doc.get_pages().len(), 2);
+        assert_eq!(extract_page_text(&doc, 1).unwrap().trim(), "typed");
+    }
+
+    #[test]
+    fn set_document_preview_embeds_decodable_thumb() {
+        let mut doc = blank_pages(2, 400, 200);
+        let first = doc.get_pages()[&1];
+        set_page_content(&mut doc, first, b"0 0 1 rg 0 0 200 200 re f".to_vec()).unwrap();
+        let path = save_fixture(doc, "preview.pdf");
+        let picture = scratch_path("preview-icon.png");
+        image::RgbImage::from_pixel(50, 100, image::Rgb([10, 200, 30])).save(&picture).unwrap();
+        let app = test_app();
+
+        run(set_document_preview(app.state(), path.clone(), None)).unwrap();
+        let rendered = with_document(&app.state(), &path, |doc| Ok(embedded_thumbnail(doc, first))).unwrap().unwrap();
+        assert_eq!(rendered.dimensions(), (256, 128));
+        // The left half of the page is filled blue, the right half is blank
+        assert_eq!(rendered.get_pixel(64, 64).0, [0, 0, 255]);
+        assert_eq!(rendered.get_pixel(192, 64).0, [255, 255, 255]);
+
+        run(set_document_preview(app.state(), path.clone(), Some(picture))).unwrap();
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            path.clone(),
+            vec![1, 2],
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        let doc = Document::load(&path).unwrap();
+        let page = doc.get_pages()[&1];
+        let provided = embedded_thumbnail(&doc, page).unwrap();
+        assert_eq!(provided.dimensions(), (128, 256));
+        assert_eq!(provided.get_pixel(64, 128).0, [10, 200, 30]);
+        assert!(embedded_thumbnail(&doc, doc.get_pages()[&2]).is_none());
+    }
+
+    #[test]
+    fn page_durations_round_trip() {
+        let path = save_fixture(blank_pages(3, 640, 480), "kiosk.pdf");
+        let app = test_app();
+
+        let durations = BTreeMap::from([(1, 4.5), (3, 12.0)]);
+        run(set_page_durations(app.state(), path.clone(), durations.clone())).unwrap();
+        assert!(run(set_page_durations(app.state(), path.clone(), BTreeMap::from([(2, -1.0)]))).is_err());
+        assert!(run(set_page_durations(app.state(), path.clone(), BTreeMap::from([(9, 1.0)]))).is_err());
+
+        assert_eq!(run(get_page_durations(app.state(), path.clone())).unwrap(), durations);
+        run(save_pdf(
+            app.state(),
+            path.clone(),
+            path.clone(),
+            vec![1, 2, 3],
+            BTreeMap::new(),
+            Vec::new(),
+            None,
+            None,
+            None,
+        ))
+        .unwrap();
+        let reopened = test_app();
+        assert_eq!(run(get_page_durations(reopened.state(), path)).unwrap(), durations);
+    }
+
+    #[test]
+    fn background_job_reports_progress_until_done() {
+        // Progress advances per input file
+        let inputs: Vec<String> = (1..=4)
+            .map(|deck| {
+                let mut doc = blank_pages(2, 300, 300);
+                for page_id in doc.get_pages().into_values() {
+                    set_page_content(&mut doc, page_id, b"0.5 g 0 0 300 150 re f".to_vec()).unwrap();
+                }
+                save_fixture(doc, &format!("job-deck{deck}.pdf"))
+            })
+            .collect();
+        let output_dir = scratch_path("job-images");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let app = test_app();
+
+        let wait = |job_id: u64| {
+            let mut seen = Vec::new();
+            for _ in 0..2000 {
+                let status = run(get_job_status(app.state(), job_id)).unwrap();
+                seen.push(status.progress);
+                if !matches!(status.state, JobState::Running) {
+                    return (status, seen);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            panic!("job {job_id} did not finish");
+        };
+        let request = JobRequest::BatchToImages {
+            input_paths: inputs,
+            output_dir: output_dir.clone(),
+            dpi: 144,
+            format: ImageFormat::Png,
+        };
+        let job_id = run(start_job(app.handle().clone(), app.state(), request)).unwrap();
+        let (status, seen) = wait(job_id);
+        assert!(matches!(status.state, JobState::Completed), "{status:?}");
+        assert_eq!(status.result_path.as_deref(), Some(output_dir.as_str()));
+        assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]), "{seen:?}");
+        assert_eq!(*seen.last().unwrap(), 1.0);
+        assert!(seen.iter().any(|&progress| progress > 0.0 && progress < 1.0), "{seen:?}");
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 8);
+
+        let missing = scratch_path("job-missing.pdf");
+        let request = JobRequest::SideBySideCompare {
+            path_a: missing.clone(),
+            path_b: missing,
+            output_path: scratch_path("job-out.pdf"),
+        };
+        let failed_id = run(start_job(app.handle().clone(), app.state(), request)).unwrap();
+        assert!(failed_id > job_id);
+        let (status, _) = wait(failed_id);
+        assert!(matches!(status.state, JobState::Failed) && status.error.is_some(), "{status:?}");
+        assert!(run(get_job_status(app.state(), failed_id + 1)).is_err());
+
+        // Jobs finished longer ago than the retention period are dropped by the next start
+        let expired = std::time::Instant::now().checked_sub(JOB_RETENTION + std::time::Duration::from_secs(1));
+        if let Some(expired) = expired {
+            app.state::<AppState>().jobs.lock().unwrap().jobs.get_mut(&job_id).unwrap().finished_at = Some(expired);
+            let request =
+                JobRequest::MakeDiffPdf { path_a: String::new(), path_b: String::new(), output_path: String::new() };
+            run(start_job(app.handle().clone(), app.state(), request)).unwrap();
+            assert!(run(get_job_status(app.state(), job_id)).is_err());
+            assert!(run(get_job_status(app.state(), failed_id)).is_ok());
+        }
+    }
+
+    #[test]
+    fn jobs_can_rasterize_and_edit_the_cached_document() {
+        let mut doc = blank_pages(1, 400, 300);
+        let page_id = doc.get_pages()[&1];
+        doc.get_dictionary_mut(page_id)
+            .unwrap()
+            .set("Resources", dictionary! { "ExtGState" => dictionary! { "Glass" => dictionary! { "CA" => 0.4 } } });
+        set_page_content(&mut doc, page_id, b"/Glass gs 0 0 0.6 RG 6 w 100 80 150 120 re S".to_vec()).unwrap();
+        let path = save_fixture(doc, "job-glass.pdf");
+        let app = test_app();
+        let finish = |request: JobRequest| {
+            let job_id = run(start_job(app.handle().clone(), app.state(), request)).unwrap();
+            for _ in 0..2000 {
+                let status = run(get_job_status(app.state(), job_id)).unwrap();
+                if !matches!(status.state, JobState::Running) {
+                    return status;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            panic!("job {job_id} did not finish");
+        };
+
+        let output_path = scratch_path("job-glass-flat.pdf");
+        let request = JobRequest::FlattenTransparency {
+            path: path.clone(),
+            output_path: output_path.clone(),
+            jpeg_quality: None,
+            codec: None,
+        };
+        let status = finish(request);
+        assert!(matches!(status.state, JobState::Completed), "{status:?}");
+        assert_eq!(status.result_path.as_deref(), Some(output_path.as_str()));
+        let flat = Document::load(&output_path).unwrap();
+        let resources = page_resources(&flat, flat.get_pages()[&1]).unwrap();
+        assert!(resource_category(&flat, resources, b"XObject").unwrap().has(b"Raster"));
+
+        // An editing job changes the cached document, and the edit can be undone
+        let status = finish(JobRequest::AutoCrop { path: path.clone(), margin: 0.0 });
+        assert_eq!(status.result_path.as_deref(), Some(path.as_str()));
+        let crop_box = || {
+            with_document(&app.state::<AppState>(), &path, |doc| {
+                Ok(doc
+                    .get_dictionary(doc.get_pages()[&1])
+                    .unwrap()
+                    .get(b"CropBox")
+                    .ok()
+                    .and_then(|b| rect_from_object(doc, b)))
+            })
+            .unwrap()
+        };
+        let [x0, y0, x1, y1] = crop_box().unwrap();
+        assert!((x0 - 97.0).abs() < 2.0 && (y0 - 77.0).abs() < 2.0, "{x0} {y0}");
+        assert!((x1 - 253.0).abs() < 2.0 && (y1 - 203.0).abs() < 2.0, "{x1} {y1}");
+        assert!(run(undo(app.state(), path.clone())).unwrap());
+        assert_eq!(crop_box(), None);
+    }
+
+    #[test]
+    fn normalize_origin_zeroes_shifted_media_box() {
+        let mut doc = blank_pages(2, 595, 842);
+        let pages = doc.get_pages();
+        let shifted = pages[&1];
+        let note = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Square", "Rect" => vec![60.into(), 60.into(), 160.into(), 110.into()],
+        });
+        let page = doc.get_dictionary_mut(shifted).unwrap();
+        page.set("MediaBox", vec![10.into(), 10.into(), 605.into(), 852.into()]);
+        page.set("CropBox", vec![20.into(), 20.into(), 595.into(), 842.into()]);
+        page.set("Annots", vec![note.into()]);
+        set_page_content(&mut doc, shifted, b"0 0.5 0 rg 50 50 200 120 re f 1 0 0 rg 400 700 60 60 re f".to_vec())
+            .unwrap();
+        let path = save_fixture(doc, "shifted-origin.pdf");
+        let app = test_app();
+        let render = |app: &tauri::App<MockRuntime>| {
+            with_document(&app.state(), &path, |doc| render_for_analysis(doc, shifted, DEFAULT_PAGE_SIZE)).unwrap()
+        };
+        let before = render(&app);
+
+        assert_eq!(run(normalize_origin(app.state(), path.clone(), None)).unwrap(), 1);
+
+        let (media, crop, rect) = with_document(&app.state(), &path, |doc| {
+            let page = doc.get_dictionary(shifted).map_err(|e| e.to_string())?;
+            let rect_of =
+                |dict: &lopdf::Dictionary, key: &[u8]| dict.get(key).ok().and_then(|rect| rect_from_object(doc, rect));
+            let annot = doc.get_dictionary(note).map_err(|e| e.to_string())?;
+            Ok((rect_of(page, b"MediaBox"), rect_of(page, b"CropBox"), rect_of(annot, b"Rect")))
+        })
+        .unwrap();
+        assert_eq!(media, Some([0.0, 0.0, 595.0, 842.0]));
+        assert_eq!(crop, Some([10.0, 10.0, 585.0, 832.0]));
+        assert_eq!(rect, Some([50.0, 50.0, 150.0, 100.0]));
+        assert_eq!(render_similarity(&before, &render(&app)), 1.0);
+        assert_eq!(run(normalize_origin(app.state(), path, None)).unwrap(), 0);
+    }
+
+    #[test]
+    fn scan_active_content_locates_page_javascript() {
+        let mut doc = blank_pages(3, 300, 300);
+        let pages = doc.get_pages();
+        let script = |code: &str| dictionary! { "S" => "JavaScript", "JS" => Object::string_literal(code) };
+        let link = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link", "Rect" => vec![10.into(), 10.into(), 90.into(), 30.into()],
+            "A" => script("app.alert('hi')"),
+        });
+        // A chained submit behind a harmless named action, on a keystroke trigger
+        let submit =
+            doc.add_object(dictionary! { "S" => "SubmitForm", "F" => Object::string_literal("https://example.com/f") });
+        let field = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Widget", "FT" => "Tx", "Rect" => vec![10.into(), 50.into(), 90.into(), 70.into()],
+            "AA" => dictionary! { "K" => dictionary! { "S" => "Named", "N" => "NextPage", "Next" => submit } },
+        });
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("Annots", vec![link.into()]);
+        doc.get_dictionary_mut(pages[&3]).unwrap().set("Annots", vec![field.into()]);
+        let init = doc.add_object(script("this.print()"));
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set(
+            "Names",
+            dictionary! {
+                "JavaScript" => dictionary! { "Names" => vec![Object::string_literal("init"), init.into()] },
+            },
+        );
+        catalog.set("OpenAction", vec![pages[&1].into(), "Fit".into()]);
+        let path = save_fixture(doc, "active.pdf");
+        let app = test_app();
+
+        let report = run(scan_active_content(app.state(), path)).unwrap();
+        assert_eq!(report.javascript_names, ["init"]);
+        assert!(report.has_open_action);
+        assert!(report.embedded_files.is_empty());
+        assert_eq!(report.pages_with_active_content, [2, 3]);
+        let items: Vec<(&str, &str, Option<usize>)> =
+            report.items.iter().map(|item| (item.kind.as_str(), item.location.as_str(), item.page_number)).collect();
+        assert_eq!(
+            items,
+            [
+                ("JavaScript", "document script 'init'", None),
+                ("OpenAction", "catalog /OpenAction", None),
+                ("JavaScript", "page 2 Link annotation /A", Some(2)),
+                ("SubmitForm", "page 3 Widget annotation /AA /K", Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_pdf_consolidates_merged_fonts_and_reports_whole_ones() {
+        let program = truetype_program(&[('A', 600), ('B', 650), ('C', 700), ('D', 750)]);
+        let mut doc = blank_pages(2, 400, 300);
+        let pages = doc.get_pages();
+        // Each page carries its own copy of the same Type 0 font, as merging two files leaves it
+        let type0 = |doc: &mut Document| {
+            let file =
+                doc.add_object(lopdf::Stream::new(dictionary! { "Length1" => program.len() as i64 }, program.clone()));
+            let descriptor = doc.add_object(dictionary! {
+                "Type" => "FontDescriptor", "FontName" => "ABCDEF+Demo", "Flags" => 4, "FontFile2" => file,
+                "FontBBox" => vec![0.into(), (-200).into(), 1000.into(), 800.into()], "ItalicAngle" => 0,
+                "Ascent" => 800, "Descent" => -200, "CapHeight" => 700, "StemV" => 80,
+            });
+            let cid_font = doc.add_object(dictionary! {
+                "Type" => "Font", "Subtype" => "CIDFontType2", "BaseFont" => "ABCDEF+Demo", "FontDescriptor" => descriptor,
+                "CIDSystemInfo" => dictionary! { "Registry" => Object::string_literal("Adobe"), "Ordering" => Object::string_literal("Identity"), "Supplement" => 0 },
+                "CIDToGIDMap" => "Identity",
+            });
+            doc.add_object(dictionary! {
+                "Type" => "Font", "Subtype" => "Type0", "BaseFont" => "ABCDEF+Demo", "Encoding" => "Identity-H",
+                "DescendantFonts" => vec![cid_font.into()],
+            })
+        };
+        let fonts = [type0(&mut doc), type0(&mut doc)];
+        let simple_file = doc.add_object(lopdf::Stream::new(dictionary! {}, truetype_program(&[('x', 500)])));
+        let simple = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "TrueType", "BaseFont" => "Plain", "FirstChar" => 120, "LastChar" => 120,
+            "Widths" => vec![500.into()],
+            "FontDescriptor" => dictionary! { "Type" => "FontDescriptor", "FontName" => "Plain", "Flags" => 32, "FontFile2" => simple_file },
+        });
+        let cff_file = doc.add_object(lopdf::Stream::new(dictionary! { "Subtype" => "Type1C" }, vec![1, 0, 4, 2]));
+        let cff = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Compact",
+            "FontDescriptor" => dictionary! { "Type" => "FontDescriptor", "FontName" => "Compact", "Flags" => 32, "FontFile3" => cff_file },
+        });
+        let contents: [&[u8]; 2] = [
+            b"BT /F1 20 Tf 20 200 Td <00010002> Tj /F2 12 Tf (x) Tj ET",
+            b"BT /F1 20 Tf 20 200 Td <0003> Tj /F3 12 Tf (y) Tj ET",
+        ];
+        let extra = [simple, cff];
+        for (index, page_num) in [1, 2].into_iter().enumerate() {
+            let page = pages[&page_num];
+            let resources = dictionary! { "Font" => dictionary! { "F1" => fonts[index], format!("F{}", index + 2) => extra[index] } };
+            doc.get_dictionary_mut(page).unwrap().set("Resources", resources);
+            set_page_content(&mut doc, page, contents[index].to_vec()).unwrap();
+        }
+        let before: Vec<image::RgbImage> =
+            pages.values().map(|&page| render_for_analysis(&doc, page, DEFAULT_PAGE_SIZE).unwrap()).collect();
+        let font_count =
+            |doc: &Document| doc.objects.values().filter(|object| object.type_name().ok() == Some("Font")).count();
+        assert_eq!(font_count(&doc), 6);
+        let path = save_fixture(doc, "merged-fonts.pdf");
+        let output = scratch_path("merged-fonts-out.pdf");
+        let app = test_app();
+
+        let save = |consolidate: Option<bool>| {
+            run(save_pdf(
+                app.state(),
+                path.clone(),
+                output.clone(),
+                vec![1, 2],
+                BTreeMap::new(),
+                Vec::new(),
+                None,
+                None,
+                consolidate,
+            ))
+        };
+        assert!(save(None).unwrap().is_none());
+        let report = save(Some(true)).unwrap().unwrap();
+
+        assert_eq!(report.programs_subsetted, 1);
+        let mut skipped: Vec<(&str, &str)> =
+            report.fonts_not_subsetted.iter().map(|font| (font.font.as_str(), font.reason.as_str())).collect();
+        skipped.sort();
+        assert_eq!(skipped, [("Compact", "CFF or OpenType program (FontFile3)"), ("Plain", "simple TrueType font")]);
+
+        let doc = Document::load(&output).unwrap();
+        assert_eq!(font_count(&doc), 4);
+        let pages = doc.get_pages();
+        // The two copies of the Type 0 program became one subset
+        let programs: Vec<&lopdf::Stream> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok()?.get(b"FontFile2").ok()?.as_reference().ok())
+            .filter_map(|id| doc.get_object(id).and_then(Object::as_stream).ok())
+            .collect();
+        assert_eq!(programs.len(), 1);
+        assert!(programs[0].decompressed_content().unwrap().len() < program.len());
+        for (page, before) in pages.values().zip(&before) {
+            let after = render_for_analysis(&doc, *page, DEFAULT_PAGE_SIZE).unwrap();
+            assert!(render_similarity(before, &after) > 0.99);
+        }
+    }
+
+    #[test]
+    fn detect_reading_order_follows_structure_tags() {
+        let mut doc = blank_pages(4, 300, 400);
+        let pages = doc.get_pages();
+        let root = doc.new_object_id();
+        let element = |doc: &mut Document, role: &str, page: Option<u32>, kids: Vec<Object>| {
+            let mut element = dictionary! { "Type" => "StructElem", "S" => role, "P" => root, "K" => kids };
+            if let Some(page) = page {
+                element.set("Pg", pages[&page]);
+            }
+            doc.add_object(element)
+        };
+        // Imposed sheets: the article starts on page 3, continues on 1, then a section on 4
+        let opening = element(&mut doc, "P", Some(3), vec![0.into()]);
+        let middle = element(&mut doc, "P", Some(1), vec![0.into()]);
+        let nested = element(&mut doc, "P", None, vec![0.into()]);
+        let section = element(&mut doc, "Sect", Some(4), vec![nested.into()]);
+        let document = element(&mut doc, "Document", None, vec![opening.into(), middle.into(), section.into()]);
+        doc.objects.insert(root, Object::Dictionary(dictionary! { "Type" => "StructTreeRoot", "K" => document }));
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("StructTreeRoot", root);
+        catalog.set("MarkInfo", dictionary! { "Marked" => true });
+        let tagged = save_fixture(doc, "imposed-tagged.pdf");
+
+        // Without tags, distinct numeric page labels give the order
+        let mut untagged = blank_pages(3, 300, 400);
+        untagged.catalog_mut().unwrap().set(
+            "PageLabels",
+            dictionary! {
+                "Nums" => vec![
+                    0.into(), dictionary! { "S" => "D", "St" => 3 }.into(),
+                    1.into(), dictionary! { "S" => "D", "St" => 1 }.into(),
+                ],
+            },
+        );
+        let labelled = save_fixture(untagged, "imposed-labelled.pdf");
+        let app = test_app();
+
+        // Page 2 is never mentioned by the tags and goes last
+        assert_eq!(run(detect_reading_order(app.state(), tagged)).unwrap(), [3, 1, 4, 2]);
+        assert_eq!(run(detect_reading_order(app.state(), labelled)).unwrap(), [2, 3, 1]);
+    }
+
+    #[test]
+    fn add_margins_grows_boxes_and_shifts_content() {
+        let mut doc = blank_pages(2, 300, 400);
+        let pages = doc.get_pages();
+        let stamp = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Square", "Rect" => vec![100.into(), 100.into(), 150.into(), 120.into()],
+        });
+        doc.get_dictionary_mut(pages[&1]).unwrap().set("Annots", vec![stamp.into()]);
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("Rotate", 90);
+        for page in pages.values() {
+            set_page_content(&mut doc, *page, b"0 0 1 rg 0 0 300 400 re f".to_vec()).unwrap();
+        }
+        let path = save_fixture(doc, "binding.pdf");
+        let app = test_app();
+
+        assert!(run(add_margins(app.state(), path.clone(), 10.0, -1.0, 0.0, 0.0)).is_err());
+        run(add_margins(app.state(), path.clone(), 10.0, 20.0, 30.0, 40.0)).unwrap();
+
+        with_document(&app.state(), &path, |doc| {
+            let media_box = |page: ObjectId| {
+                rect_from_object(doc, doc.get_dictionary(page).unwrap().get(b"MediaBox").unwrap()).unwrap()
+            };
+            let content = |page: ObjectId| String::from_utf8_lossy(&doc.get_page_content(page).unwrap()).into_owned();
+            // Width grows by left + right and height by top + bottom
+            assert_eq!(media_box(pages[&1]), [0.0, 0.0, 360.0, 440.0]);
+            assert!(content(pages[&1]).starts_with("q 1 0 0 1 40.0000 30.0000 cm"), "{}", content(pages[&1]));
+            let rect = rect_from_object(doc, doc.get_dictionary(stamp).unwrap().get(b"Rect").unwrap()).unwrap();
+            assert_eq!(rect, [140.0, 130.0, 190.0, 150.0]);
+            // A quarter turn puts the reader's top margin on the unrotated left edge
+            assert_eq!(media_box(pages[&2]), [0.0, 0.0, 340.0, 460.0]);
+            assert!(content(pages[&2]).starts_with("q 1 0 0 1 10.0000 40.0000 cm"), "{}", content(pages[&2]));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn regenerate_form_appearances_draws_values_and_clears_flag() {
+        let mut doc = single_field_form("name", "Ada Lovelace", ("Helv", "Helvetica"));
+        let page = doc.get_pages()[&1];
+        let name_field = form_field_id(&doc, "name").unwrap();
+        let widget = |name: &str, extra: lopdf::Dictionary, rect: [i64; 4]| {
+            let mut field = dictionary! {
+                "Type" => "Annot", "Subtype" => "Widget", "T" => Object::string_literal(name), "P" => page,
+                "Rect" => rect.iter().map(|&v| Object::Integer(v)).collect::<Vec<_>>(),
+            };
+            for (key, value) in extra.iter() {
+                field.set(key.clone(), value.clone());
+            }
+            field
+        };
+        let agree = doc.add_object(widget("agree", dictionary! { "FT" => "Btn", "V" => "Yes" }, [50, 650, 64, 664]));
+        let options = vec![
+            vec![Object::string_literal("fr"), Object::string_literal("France")].into(),
+            vec![Object::string_literal("jp"), Object::string_literal("Japan")].into(),
+        ];
+        let country = doc.add_object(widget(
+            "country",
+            dictionary! { "FT" => "Ch", "Ff" => FIELD_FLAG_COMBO, "Opt" => options, "V" => Object::string_literal("jp") },
+            [50, 600, 200, 620],
+        ));
+        doc.get_dictionary_mut(page).unwrap().set("Annots", vec![name_field.into(), agree.into(), country.into()]);
+        let catalog = doc.catalog_mut().unwrap();
+        let form = catalog.get_mut(b"AcroForm").unwrap().as_dict_mut().unwrap();
+        form.set("Fields", vec![name_field.into(), agree.into(), country.into()]);
+        form.set("NeedAppearances", true);
+        let path = save_fixture(doc, "needs-appearances.pdf");
+        let output = scratch_path("needs-appearances-out.pdf");
+        let app = test_app();
+
+        assert_eq!(run(regenerate_form_appearances(app.state(), path, output.clone())).unwrap(), 3);
+
+        let doc = Document::load(&output).unwrap();
+        let form = resolve_object(&doc, doc.catalog().unwrap().get(b"AcroForm").unwrap()).unwrap().as_dict().unwrap();
+        assert!(!form.get(b"NeedAppearances").and_then(Object::as_bool).unwrap_or(false));
+        let normal = |widget: ObjectId| {
+            doc.get_dictionary(widget).unwrap().get(b"AP").unwrap().as_dict().unwrap().get(b"N").unwrap().clone()
+        };
+        let appearance_text = |stream: &Object| {
+            let stream = doc.get_object(stream.as_reference().unwrap()).unwrap().as_stream().unwrap();
+            String::from_utf8_lossy(&stream.decompressed_content().unwrap_or_else(|_| stream.content.clone()))
+                .into_owned()
+        };
+        assert!(appearance_text(&normal(name_field)).contains("(Ada Lovelace) Tj"));
+        assert!(appearance_text(&normal(country)).contains("(Japan) Tj"));
+        let check = doc.get_dictionary(agree).unwrap();
+        assert_eq!(check.get(b"AS").unwrap().as_name().unwrap(), b"Yes");
+        let states = normal(agree);
+        let states = states.as_dict().unwrap();
+        assert!(states.has(b"Off"));
+        assert!(appearance_text(states.get(b"Yes").unwrap()).contains("/ZaDb"));
+    }
+
+    #[test]
+    fn split_on_blank_pages_drops_separators() {
+        let mut doc = blank_pages(7, 200, 260);
+        for (page_num, page_id) in doc.get_pages() {
+            let content = match page_num {
+                3 => String::new(),
+                // A speck of scanner noise on an otherwise blank separator
+                5 => "% page 5\n0 g 100 100 1 1 re f".to_string(),
+                _ => format!("% page {page_num}\n0 g 20 20 160 {} re f", 40 + page_num * 20),
+            };
+            set_page_content(&mut doc, page_id, content.into_bytes()).unwrap();
+        }
+        let path = save_fixture(doc, "scan-stack.pdf");
+        let output_dir = scratch_path("scan-stack-parts");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let app = test_app();
+
+        assert!(run(split_on_blank_pages(app.state(), path.clone(), output_dir.clone(), 1.5)).is_err());
+        let parts = run(split_on_blank_pages(app.state(), path, output_dir, 0.01)).unwrap();
+
+        let sources: Vec<Vec<String>> = parts
+            .iter()
+            .map(|part| {
+                let doc = Document::load(part).unwrap();
+                doc.get_pages()
+                    .into_values()
+                    .map(|page| {
+                        String::from_utf8_lossy(&doc.get_page_content(page).unwrap())
+                            .lines()
+                            .next()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(sources, [vec!["% page 1", "% page 2"], vec!["% page 4"], vec!["% page 6", "% page 7"]]);
+        assert!(parts[1].ends_with("scan-stack_part2.pdf"));
+    }
+
+    #[test]
+    fn tags_round_trip_through_keywords_and_xmp() {
+        let mut doc = blank_pages(1, 200, 200);
+        let info = doc.add_object(dictionary! { "Keywords" => Object::string_literal("draft; old ,") });
+        doc.trailer.set("Info", info);
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+            <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\
+            <dc:subject><rdf:Bag><rdf:li>draft</rdf:li></rdf:Bag></dc:subject><pdf:Keywords>draft; old</pdf:Keywords>\
+            </rdf:Description></rdf:RDF></x:xmpmeta>";
+        let metadata = doc.add_object(lopdf::Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            packet.as_bytes().to_vec(),
+        ));
+        doc.catalog_mut().unwrap().set("Metadata", metadata);
+        let path = save_fixture(doc, "tagged-invoice.pdf");
+        let app = test_app();
+
+        assert_eq!(run(get_tags(app.state(), path.clone())).unwrap(), ["draft", "old"]);
+        let tags = [" invoices ", "2024", "Invoices", "R&D", ""].map(String::from).to_vec();
+        run(set_tags(app.state(), path.clone(), tags)).unwrap();
+        assert!(run(set_tags(app.state(), path.clone(), vec!["a,b".to_string()])).is_err());
+
+        assert_eq!(run(get_tags(app.state(), path.clone())).unwrap(), ["invoices", "2024", "R&D"]);
+        let packet = with_document(&app.state(), &path, |doc| {
+            let stream = doc.get_object(metadata).and_then(Object::as_stream).map_err(|e| e.to_string())?;
+            Ok(String::from_utf8_lossy(&stream.content).into_owned())
+        })
+        .unwrap();
+        assert!(packet.contains(
+            "<dc:subject><rdf:Bag><rdf:li>invoices</rdf:li><rdf:li>2024</rdf:li><rdf:li>R&amp;D</rdf:li></rdf:Bag></dc:subject>"
+        ));
+        assert!(packet.contains("<pdf:Keywords>invoices, 2024, R&amp;D</pdf:Keywords>"));
+    }
+
+    #[test]
+    fn verify_merge_checks_selected_pages_and_reports_unrenderable_ones() {
+        // Each page is a block at its own position, so any two pages render differently
+        let blocks = |xs: &[i64]| {
+            let mut doc = blank_pages(xs.len(), 240, 240);
+            let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+            for (&page, &x) in pages.iter().zip(xs) {
+                set_page_content(&mut doc, page, format!("0.1 0.2 0.7 rg {} 30 50 180 re f", x).into_bytes()).unwrap();
+            }
+            doc
+        };
+        let first = save_fixture(blocks(&[10, 90]), "verify-merge-first.pdf");
+        let second = save_fixture(blocks(&[170]), "verify-merge-second.pdf");
+        let app = test_app();
+        let inputs = vec![first.clone(), second.clone()];
+
+        let merged = scratch_path("verify-merge-merged.pdf");
+        run(merge_pdfs(app.handle().clone(), inputs.clone(), merged.clone(), None, None)).unwrap();
+        let report = run(verify_merge(app.state(), inputs.clone(), merged, None)).unwrap();
+        assert!(report.passed, "{:?}", report.mismatches);
+        assert_eq!((report.expected_page_count, report.merged_page_count), (3, 3));
+        let sources: Vec<(usize, usize)> = report.pages.iter().map(|page| (page.input, page.source_page)).collect();
+        assert_eq!(sources, [(1, 1), (1, 2), (2, 1)]);
+        assert!(report.pages.iter().all(|page| page.similarity.unwrap() > 0.99));
+
+        // Only page 2 of the first input went into this merge
+        let partial = save_fixture(blocks(&[90, 170]), "verify-merge-partial.pdf");
+        let selections = Some(vec![Some(vec![2]), None]);
+        let report = run(verify_merge(app.state(), inputs.clone(), partial.clone(), selections)).unwrap();
+        assert!(report.passed, "{:?}", report.mismatches);
+        let report = run(verify_merge(app.state(), inputs.clone(), partial.clone(), None)).unwrap();
+        assert!(!report.passed);
+        assert_eq!(report.mismatches.len(), 3);
+        assert!(report.mismatches[2].starts_with("page 3: missing"));
+        assert!(run(verify_merge(app.state(), inputs.clone(), partial, Some(vec![None]))).is_err());
+
+        let reordered = save_fixture(blocks(&[170, 10, 90]), "verify-merge-reordered.pdf");
+        let report = run(verify_merge(app.state(), inputs, reordered, None)).unwrap();
+        assert!(!report.passed);
+        assert!(report.mismatches.iter().all(|mismatch| mismatch.contains("differs from page")));
+        assert_eq!(report.mismatches.len(), 3);
+
+        // A source that can't be rendered is a mismatch, not a match
+        let image = image::RgbImage::from_pixel(8, 8, image::Rgb([255, 255, 255]));
+        let reason = merged_page_similarity(Err(RENDER_TIMEOUT.to_string()), Ok(image.clone())).unwrap_err();
+        assert!(reason.starts_with("unverified"));
+        assert!(merged_page_similarity(Ok(image.clone()), Err(RENDER_TIMEOUT.to_string())).is_err());
+        assert_eq!(merged_page_similarity(Ok(image.clone()), Ok(image)), Ok(1.0));
+    }
+
+    #[test]
+    fn page_layout_round_trips_through_the_catalog() {
+        let mut doc = blank_pages(4, 300, 400);
+        let unknown = doc.add_object(Object::Name(b"ThreeColumn".to_vec()));
+        doc.catalog_mut().unwrap().set("PageLayout", unknown);
+        let path = save_fixture(doc, "page-layout.pdf");
+        let app = test_app();
+        assert_eq!(run(get_page_layout(app.state(), path.clone())).unwrap(), None);
+
+        run(set_page_layout(app.state(), path.clone(), PageLayout::TwoColumnLeft)).unwrap();
+        assert_eq!(run(get_page_layout(app.state(), path.clone())).unwrap(), Some(PageLayout::TwoColumnLeft));
+        let written =
+            with_document(&app.state(), &path, |doc| Ok(doc.catalog().unwrap().get(b"PageLayout").unwrap().clone()))
+                .unwrap();
+        assert_eq!(written.as_name().unwrap(), b"TwoColumnLeft");
+
+        run(set_page_layout(app.state(), path.clone(), PageLayout::SinglePage)).unwrap();
+        assert_eq!(run(get_page_layout(app.state(), path)).unwrap(), Some(PageLayout::SinglePage));
+    }
+
+    #[test]
+    fn render_hashes_are_stable_and_change_with_rotation() {
+        let mut doc = blank_pages(2, 200, 200);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        set_page_content(&mut doc, pages[0], b"0.8 0.1 0.1 rg 20 20 60 30 re f".to_vec()).unwrap();
+        set_page_content(&mut doc, pages[1], b"0 0.5 0 rg 10 120 150 40 re f 0 0 0 RG 4 w 30 30 m 90 90 l S".to_vec())
+            .unwrap();
+        let original = save_fixture(doc, "render-hashes.pdf");
+        let turned = scratch_path("render-hashes-turned.pdf");
+        std::fs::copy(&original, &turned).unwrap();
+        let app = test_app();
+        run(rotate_pages_relative(app.state(), turned.clone(), vec![2], 180)).unwrap();
+
+        let hashes = run(render_hashes(app.state(), original.clone(), 36)).unwrap();
+        assert_eq!(hashes.len(), 2);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_eq!(run(render_hashes(app.state(), original.clone(), 36)).unwrap(), hashes);
+        let rotated = run(render_hashes(app.state(), turned, 36)).unwrap();
+        assert_eq!(rotated[0], hashes[0]);
+        assert_ne!(rotated[1], hashes[1]);
+        assert_ne!(run(render_hashes(app.state(), original.clone(), 48)).unwrap(), hashes);
+        assert!(run(render_hashes(app.state(), original, 0)).is_err());
+    }
+}